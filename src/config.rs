@@ -0,0 +1,296 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// User-wide configuration file, looked up in the home directory
+pub const GLOBAL_CONFIG_FILE: &str = ".gitjadeconfig";
+
+/// Settings read from an INI-style config file, e.g.
+///
+/// ```text
+/// [init]
+///     defaultBranch = main
+/// ```
+///
+/// Section and key names are case-insensitive, so the setting above is
+/// looked up as `init.defaultbranch` or `init.defaultBranch` alike.
+#[derive(Debug, Default, Clone)]
+pub struct Config {
+    /// `(section.key, value)` pairs in file order; later entries win
+    entries: Vec<(String, String)>,
+}
+
+impl Config {
+    /// Loads a config file; a missing file is an empty config
+    pub fn load(path: &Path) -> io::Result<Config> {
+        match fs::read_to_string(path) {
+            Ok(text) => Self::parse(&text),
+            Err(why) if why.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(why) => Err(why),
+        }
+    }
+
+    /// Loads the user-wide config, or an empty one when there is none
+    pub fn global() -> Config {
+        Self::global_path()
+            .and_then(|path| Self::load(&path).ok())
+            .unwrap_or_default()
+    }
+
+    /// Where the user-wide config lives, if `$HOME` is set
+    pub fn global_path() -> Option<PathBuf> {
+        std::env::var_os("HOME").map(|home| PathBuf::from(home).join(GLOBAL_CONFIG_FILE))
+    }
+
+    /// Parses the text of a config file
+    pub fn parse(text: &str) -> io::Result<Config> {
+        let mut entries = Vec::new();
+        let mut section: Option<String> = None;
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+            let invalid = || {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("bad config line {}: {line}", number + 1),
+                )
+            };
+            if let Some(header) = line.strip_prefix('[') {
+                let header = header.strip_suffix(']').ok_or_else(invalid)?;
+                section = Some(match header.split_once(' ') {
+                    // Subsection names are case-sensitive: [remote "origin"]
+                    Some((name, sub)) => {
+                        let sub = sub.trim().trim_matches('"');
+                        format!("{}.{sub}", name.to_lowercase())
+                    }
+                    None => header.trim().to_lowercase(),
+                });
+                continue;
+            }
+            let section = section.as_ref().ok_or_else(invalid)?;
+            // A key without a value is a boolean set to true
+            let (key, value) = line.split_once('=').unwrap_or((line, "true"));
+            let value = value.trim();
+            let value = value
+                .strip_prefix('"')
+                .and_then(|v| v.strip_suffix('"'))
+                .unwrap_or(value);
+            entries.push((
+                format!("{section}.{}", key.trim().to_lowercase()),
+                value.to_string(),
+            ));
+        }
+        Ok(Config { entries })
+    }
+
+    /// Writes the config back out, one `[section]` block per section in
+    /// the order they first appear
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut sections: Vec<(&str, Vec<(&str, &str)>)> = Vec::new();
+        for (name, value) in &self.entries {
+            let (section, key) = name.rsplit_once('.').unwrap_or(("", name));
+            match sections.iter_mut().find(|(known, _)| *known == section) {
+                Some((_, keys)) => keys.push((key, value)),
+                None => sections.push((section, vec![(key, value)])),
+            }
+        }
+        let mut text = String::new();
+        for (section, keys) in sections {
+            match section.split_once('.') {
+                Some((name, sub)) => text.push_str(&format!("[{name} \"{sub}\"]\n")),
+                None => text.push_str(&format!("[{section}]\n")),
+            }
+            for (key, value) in keys {
+                let value = if value.starts_with(char::is_whitespace)
+                    || value.ends_with(char::is_whitespace)
+                    || value.contains(['#', ';'])
+                {
+                    format!("\"{value}\"")
+                } else {
+                    value.to_string()
+                };
+                text.push_str(&format!("\t{key} = {value}\n"));
+            }
+        }
+        fs::write(path, text)
+    }
+
+    /// Looks up a `section.key` setting
+    pub fn get(&self, key: &str) -> Option<&str> {
+        let key = normalize_key(key);
+        self.entries
+            .iter()
+            .rev()
+            .find(|(name, _)| *name == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Looks up a boolean setting, spelled `true`/`false`, `yes`/`no`,
+    /// `on`/`off` or `1`/`0`
+    pub fn get_bool(&self, key: &str) -> Result<Option<bool>, String> {
+        let Some(value) = self.get(key) else {
+            return Ok(None);
+        };
+        match value.to_lowercase().as_str() {
+            "true" | "yes" | "on" | "1" => Ok(Some(true)),
+            "false" | "no" | "off" | "0" | "" => Ok(Some(false)),
+            _ => Err(format!("bad boolean config value '{value}' for '{key}'")),
+        }
+    }
+
+    /// Looks up an integer setting
+    pub fn get_int(&self, key: &str) -> Result<Option<i64>, String> {
+        self.get(key)
+            .map(|value| {
+                value
+                    .parse()
+                    .map_err(|_| format!("bad numeric config value '{value}' for '{key}'"))
+            })
+            .transpose()
+    }
+
+    /// Sets a `section.key` setting, replacing any earlier values
+    pub fn set<V: ToString>(&mut self, key: &str, value: V) -> Result<(), String> {
+        let (section, name) = key
+            .rsplit_once('.')
+            .ok_or_else(|| format!("key does not contain a section: {key}"))?;
+        if section.is_empty() || name.is_empty() {
+            return Err(format!("invalid key: {key}"));
+        }
+        let key = normalize_key(key);
+        self.entries.retain(|(name, _)| *name != key);
+        self.entries.push((key, value.to_string()));
+        Ok(())
+    }
+
+    /// Removes every setting of `section`, e.g. `remote.origin`,
+    /// returning whether there were any
+    pub fn remove_section(&mut self, section: &str) -> bool {
+        let section = normalize_section(section);
+        let count = self.entries.len();
+        self.entries
+            .retain(|(name, _)| name.rsplit_once('.').map(|(s, _)| s) != Some(&section));
+        self.entries.len() != count
+    }
+
+    /// Moves every setting of section `old` into section `new`
+    pub fn rename_section(&mut self, old: &str, new: &str) {
+        let (old, new) = (normalize_section(old), normalize_section(new));
+        for (name, _) in &mut self.entries {
+            if let Some((section, key)) = name.rsplit_once('.')
+                && section == old
+            {
+                *name = format!("{new}.{key}");
+            }
+        }
+    }
+
+    /// The distinct subsection names of `section` in file order, e.g. the
+    /// remotes of `[remote "origin"]` blocks
+    pub fn subsections(&self, section: &str) -> Vec<&str> {
+        let prefix = format!("{}.", section.to_lowercase());
+        let mut subsections = Vec::new();
+        for (name, _) in &self.entries {
+            if let Some((sub, _)) = name
+                .strip_prefix(&prefix)
+                .and_then(|rest| rest.rsplit_once('.'))
+                && !subsections.contains(&sub)
+            {
+                subsections.push(sub);
+            }
+        }
+        subsections
+    }
+
+    /// Every `(section.key, value)` pair in file order
+    pub fn entries(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    /// Appends the settings of `other`, which then take precedence
+    pub fn extend(&mut self, other: Config) {
+        self.entries.extend(other.entries);
+    }
+}
+
+/// Lowercases the section name, keeping any subsection as is
+fn normalize_section(section: &str) -> String {
+    match section.split_once('.') {
+        Some((name, sub)) => format!("{}.{sub}", name.to_lowercase()),
+        None => section.to_lowercase(),
+    }
+}
+
+/// Lowercases the section and key name, keeping any subsection as is
+fn normalize_key(key: &str) -> String {
+    let (section, rest) = key.split_once('.').unwrap_or((key, ""));
+    match rest.rsplit_once('.') {
+        Some((sub, name)) => format!("{}.{sub}.{}", section.to_lowercase(), name.to_lowercase()),
+        None => format!("{}.{}", section.to_lowercase(), rest.to_lowercase()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_sections_and_keys() {
+        let config = Config::parse(
+            "# comment\n[init]\n\tdefaultBranch = main\n[remote \"Origin\"]\nurl = \"/tmp/x\"\n\
+             [core]\nbare\n[init]\ndefaultbranch = trunk\n",
+        )
+        .unwrap();
+        assert_eq!(config.get("init.defaultBranch"), Some("trunk"));
+        assert_eq!(config.get("remote.Origin.url"), Some("/tmp/x"));
+        assert_eq!(config.get("remote.origin.url"), None);
+        assert_eq!(config.get("core.bare"), Some("true"));
+        assert_eq!(config.get("user.name"), None);
+
+        assert!(Config::parse("key = outside a section").is_err());
+        assert!(Config::load(Path::new("/nonexistent/config")).is_ok());
+    }
+
+    #[test]
+    fn set_and_save_round_trip() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("config");
+        let mut config = Config::parse("[core]\n\tbare = no\n[user]\n\tname = Old\n").unwrap();
+        config.set("user.name", "Alice Liddell").unwrap();
+        config.set("remote.Origin.url", "/tmp/x # y").unwrap();
+        config.set("core.depth", 3).unwrap();
+        assert!(config.set("nosection", "x").is_err());
+        config.save(&path).unwrap();
+
+        let config = Config::load(&path).unwrap();
+        assert_eq!(config.get("user.name"), Some("Alice Liddell"));
+        assert_eq!(config.get("remote.Origin.url"), Some("/tmp/x # y"));
+        assert_eq!(config.get_bool("core.bare"), Ok(Some(false)));
+        assert_eq!(config.get_int("core.depth"), Ok(Some(3)));
+        assert!(config.get_int("user.name").is_err());
+        assert_eq!(config.entries().count(), 4);
+    }
+
+    #[test]
+    fn rename_and_remove_sections() {
+        let mut config = Config::parse(
+            "[remote \"origin\"]\n\turl = /a\n[remote \"up\"]\n\turl = /b\n\
+             [Remote \"origin\"]\n\tfetch = x\n",
+        )
+        .unwrap();
+        assert_eq!(config.subsections("remote"), ["origin", "up"]);
+
+        config.rename_section("remote.origin", "remote.mirror");
+        assert_eq!(config.get("remote.mirror.url"), Some("/a"));
+        assert_eq!(config.get("remote.mirror.fetch"), Some("x"));
+        assert_eq!(config.subsections("remote"), ["mirror", "up"]);
+
+        assert!(config.remove_section("Remote.mirror"));
+        assert!(!config.remove_section("remote.mirror"));
+        assert_eq!(config.subsections("remote"), ["up"]);
+    }
+}
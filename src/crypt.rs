@@ -0,0 +1,140 @@
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use sha1::{Digest, Sha1};
+
+/// Environment variable holding the repository key as 64 hex digits
+pub const KEY_ENV: &str = "RUST_GIT_KEY";
+
+/// Marker file in the objects directory of an encrypted repository.
+/// It names the cipher and a fingerprint of the key, never the key itself.
+pub const ENCRYPTION_FILE: &str = "encryption";
+
+const CIPHER_NAME: &str = "aes-256-gcm";
+
+/// Prefix of every encrypted object file
+const MAGIC: &[u8] = b"ENC1";
+const NONCE_LEN: usize = 12;
+
+/// Encrypts and decrypts object payloads with the repository key
+#[derive(Clone)]
+pub struct ObjectCipher {
+    cipher: Aes256Gcm,
+    key: [u8; 32],
+}
+
+impl ObjectCipher {
+    pub fn new(key: [u8; 32]) -> Self {
+        ObjectCipher {
+            cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key)),
+            key,
+        }
+    }
+
+    /// Reads the key from `RUST_GIT_KEY`
+    pub fn from_env() -> Result<Self, String> {
+        let hex_key = std::env::var(KEY_ENV)
+            .map_err(|_| format!("repository is encrypted; set {KEY_ENV} to its key"))?;
+        let key = hex::decode(hex_key.trim())
+            .ok()
+            .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+            .ok_or_else(|| format!("{KEY_ENV} must be 64 hex digits"))?;
+        Ok(Self::new(key))
+    }
+
+    /// Content of the marker file for this key
+    pub fn marker(&self) -> String {
+        format!("{CIPHER_NAME} {}\n", self.fingerprint())
+    }
+
+    /// Checks that this key is the one named by a marker file
+    pub fn check_marker(&self, marker: &str) -> Result<(), String> {
+        match marker.trim().split_once(' ') {
+            Some((CIPHER_NAME, fingerprint)) if fingerprint == self.fingerprint() => Ok(()),
+            Some((CIPHER_NAME, _)) => Err(format!("{KEY_ENV} is not the key of this repository")),
+            _ => Err(format!("unsupported object encryption '{}'", marker.trim())),
+        }
+    }
+
+    /// Encrypts a serialized object stored under `encoded_sha`.
+    ///
+    /// The nonce is derived from the key and the plaintext SHA1, so a nonce
+    /// is only ever reused for identical plaintext, which yields identical
+    /// ciphertext and reveals nothing the object's name does not. The name
+    /// is authenticated along with the ciphertext, so the file cannot be
+    /// passed off as another object.
+    pub fn encrypt(&self, encoded_sha: &str, plaintext: &[u8]) -> Vec<u8> {
+        let mut hasher = Sha1::new();
+        hasher.update(self.key);
+        hasher.update(encoded_sha.as_bytes());
+        let nonce = &hasher.finalize()[..NONCE_LEN];
+        let ciphertext = self
+            .cipher
+            .encrypt(
+                Nonce::from_slice(nonce),
+                Payload {
+                    msg: plaintext,
+                    aad: encoded_sha.as_bytes(),
+                },
+            )
+            .expect("AES-GCM encryption cannot fail for in-memory buffers");
+
+        let mut data = MAGIC.to_vec();
+        data.extend_from_slice(nonce);
+        data.extend(ciphertext);
+        data
+    }
+
+    /// Decrypts an object file written by `encrypt` for `encoded_sha`
+    pub fn decrypt(&self, encoded_sha: &str, data: &[u8]) -> Result<Vec<u8>, String> {
+        if !is_encrypted(data) || data.len() < MAGIC.len() + NONCE_LEN {
+            return Err("object is not encrypted".to_string());
+        }
+        let (nonce, ciphertext) = data[MAGIC.len()..].split_at(NONCE_LEN);
+        self.cipher
+            .decrypt(
+                Nonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: encoded_sha.as_bytes(),
+                },
+            )
+            .map_err(|_| {
+                format!("cannot decrypt object {encoded_sha}: wrong key, corrupt data or not this object")
+            })
+    }
+
+    fn fingerprint(&self) -> String {
+        let mut hasher = Sha1::new();
+        hasher.update(b"rust-git key fingerprint\0");
+        hasher.update(self.key);
+        hex::encode(hasher.finalize())
+    }
+}
+
+/// Whether an object file holds encrypted data
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip_and_wrong_key() {
+        let cipher = ObjectCipher::new([7; 32]);
+        let sealed = cipher.encrypt("ab", b"blob 5\0hello");
+        assert!(is_encrypted(&sealed));
+        assert!(!sealed.windows(5).any(|w| w == b"hello"));
+        assert_eq!(cipher.decrypt("ab", &sealed).unwrap(), b"blob 5\0hello");
+        // The file of one object does not pass for another
+        assert!(cipher.decrypt("cd", &sealed).is_err());
+        // Same plaintext gives the same file
+        assert_eq!(sealed, cipher.encrypt("ab", b"blob 5\0hello"));
+
+        let other = ObjectCipher::new([8; 32]);
+        assert!(other.decrypt("ab", &sealed).is_err());
+        assert!(other.check_marker(&cipher.marker()).is_err());
+        assert!(cipher.check_marker(&cipher.marker()).is_ok());
+    }
+}
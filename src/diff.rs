@@ -0,0 +1,299 @@
+use std::ops::Range;
+
+/// A run of elements that differ between two sequences: `old` was replaced
+/// by `new`. One of the ranges is empty for a pure insertion or deletion,
+/// and then starts where the elements go or were.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hunk {
+    pub old: Range<usize>,
+    pub new: Range<usize>,
+}
+
+/// Diffs two sequences with the algorithm of Myers, which finds an edit
+/// script of the fewest insertions and deletions in `O((N+M)D)` time and
+/// linear space.
+///
+/// # Returns
+/// The hunks that differ, in order. Two hunks are never adjacent: the
+/// elements between them are the same on both sides.
+pub fn diff_slices<T: PartialEq>(old: &[T], new: &[T]) -> Vec<Hunk> {
+    let size = old.len() + new.len() + 2;
+    let mut diff = Myers {
+        old,
+        new,
+        forward: vec![0; 2 * size],
+        backward: vec![0; 2 * size],
+        offset: size as isize,
+        hunks: Vec::new(),
+    };
+    diff.conquer(0..old.len(), 0..new.len());
+    diff.hunks
+}
+
+/// Diffs two texts line by line; lines keep their terminator, so that a
+/// last line without one differs from the same line with one
+pub fn diff_lines(old: &str, new: &str) -> Vec<Hunk> {
+    diff_slices(&lines(old), &lines(new))
+}
+
+/// Diffs two byte strings byte by byte
+pub fn diff_bytes(old: &[u8], new: &[u8]) -> Vec<Hunk> {
+    diff_slices(old, new)
+}
+
+/// The lines of a text as [`diff_lines`] compares them
+pub fn lines(text: &str) -> Vec<&str> {
+    text.split_inclusive('\n').collect()
+}
+
+/// [`similarity`] of two texts by lines, e.g. to tell whether a file was
+/// renamed
+pub fn line_similarity(old: &str, new: &str) -> f32 {
+    let (old, new) = (lines(old), lines(new));
+    similarity(&diff_slices(&old, &new), old.len(), new.len())
+}
+
+/// The pairs of old and new indices of the elements `hunks` leave alone,
+/// in order, for sequences of `old_len` and `new_len` elements
+pub fn unchanged(
+    hunks: &[Hunk],
+    old_len: usize,
+    new_len: usize,
+) -> impl Iterator<Item = (usize, usize)> + '_ {
+    let ends = hunks
+        .iter()
+        .map(|hunk| (hunk.old.start, hunk.new.start))
+        .chain([(old_len, new_len)]);
+    let starts = [(0, 0)]
+        .into_iter()
+        .chain(hunks.iter().map(|hunk| (hunk.old.end, hunk.new.end)));
+    starts
+        .zip(ends)
+        .flat_map(|((old_start, new_start), (old_end, _))| {
+            (old_start..old_end).map(move |old| (old, new_start + old - old_start))
+        })
+}
+
+/// How alike two sequences are, from 0 for nothing in common to 1 for
+/// equal ones: twice the unchanged elements over all elements
+pub fn similarity(hunks: &[Hunk], old_len: usize, new_len: usize) -> f32 {
+    if old_len + new_len == 0 {
+        return 1.0;
+    }
+    let changed: usize = hunks.iter().map(|hunk| hunk.old.len()).sum();
+    2.0 * (old_len - changed) as f32 / (old_len + new_len) as f32
+}
+
+/// State of [`diff_slices`]. `forward` and `backward` hold the furthest
+/// reaching x of each diagonal k, at index `k + offset`, for the searches
+/// from either end of the ranges being compared.
+struct Myers<'a, T> {
+    old: &'a [T],
+    new: &'a [T],
+    forward: Vec<usize>,
+    backward: Vec<usize>,
+    offset: isize,
+    hunks: Vec<Hunk>,
+}
+
+impl<T: PartialEq> Myers<'_, T> {
+    /// Diffs `old[old]` against `new[new]` by splitting both at the middle
+    /// of an optimal edit script until one side is empty
+    fn conquer(&mut self, mut old: Range<usize>, mut new: Range<usize>) {
+        while !old.is_empty() && !new.is_empty() && self.old[old.start] == self.new[new.start] {
+            old.start += 1;
+            new.start += 1;
+        }
+        while !old.is_empty() && !new.is_empty() && self.old[old.end - 1] == self.new[new.end - 1] {
+            old.end -= 1;
+            new.end -= 1;
+        }
+        if old.is_empty() || new.is_empty() {
+            self.push(old, new);
+            return;
+        }
+        let (x, y) = self.middle_snake(old.clone(), new.clone());
+        self.conquer(old.start..x, new.start..y);
+        self.conquer(x..old.end, y..new.end);
+    }
+
+    /// Finds where an optimal edit script of `old[old]` into `new[new]`
+    /// crosses its middle, searching from both ends until the paths meet
+    fn middle_snake(&mut self, old: Range<usize>, new: Range<usize>) -> (usize, usize) {
+        let n = old.len() as isize;
+        let m = new.len() as isize;
+        let delta = n - m;
+        let odd = delta & 1 == 1;
+        let offset = self.offset;
+        let at = |k: isize| (k + offset) as usize;
+        self.forward[at(1)] = 0;
+        self.backward[at(1)] = 0;
+        let d_max = (n + m + 1) / 2;
+        for d in 0..=d_max {
+            for k in (-d..=d).rev().step_by(2) {
+                let mut x =
+                    if k == -d || (k != d && self.forward[at(k - 1)] < self.forward[at(k + 1)]) {
+                        self.forward[at(k + 1)]
+                    } else {
+                        self.forward[at(k - 1)] + 1
+                    };
+                let mut y = (x as isize - k) as usize;
+                let (x0, y0) = (x, y);
+                while (x as isize) < n
+                    && (y as isize) < m
+                    && self.old[old.start + x] == self.new[new.start + y]
+                {
+                    x += 1;
+                    y += 1;
+                }
+                self.forward[at(k)] = x;
+                if odd
+                    && (k - delta).abs() < d
+                    && (self.forward[at(k)] + self.backward[at(delta - k)]) as isize >= n
+                {
+                    return (old.start + x0, new.start + y0);
+                }
+            }
+            for k in (-d..=d).rev().step_by(2) {
+                let mut x =
+                    if k == -d || (k != d && self.backward[at(k - 1)] < self.backward[at(k + 1)]) {
+                        self.backward[at(k + 1)]
+                    } else {
+                        self.backward[at(k - 1)] + 1
+                    };
+                let mut y = (x as isize - k) as usize;
+                while (x as isize) < n
+                    && (y as isize) < m
+                    && self.old[old.end - 1 - x] == self.new[new.end - 1 - y]
+                {
+                    x += 1;
+                    y += 1;
+                }
+                self.backward[at(k)] = x;
+                if !odd
+                    && (k - delta).abs() <= d
+                    && (self.backward[at(k)] + self.forward[at(delta - k)]) as isize >= n
+                {
+                    return (old.end - x, new.end - y);
+                }
+            }
+        }
+        unreachable!("the searches from both ends always meet")
+    }
+
+    /// Records that `old` was replaced by `new`, joining the last hunk if
+    /// it ends where this one starts
+    fn push(&mut self, old: Range<usize>, new: Range<usize>) {
+        if old.is_empty() && new.is_empty() {
+            return;
+        }
+        if let Some(last) = self.hunks.last_mut()
+            && last.old.end == old.start
+            && last.new.end == new.start
+        {
+            last.old.end = old.end;
+            last.new.end = new.end;
+            return;
+        }
+        self.hunks.push(Hunk { old, new });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Applies `hunks` to `old`, taking the replacements from `new`
+    fn patch<T: Clone>(old: &[T], new: &[T], hunks: &[Hunk]) -> Vec<T> {
+        let mut out = Vec::new();
+        let mut at = 0;
+        for hunk in hunks {
+            out.extend_from_slice(&old[at..hunk.old.start]);
+            out.extend_from_slice(&new[hunk.new.clone()]);
+            at = hunk.old.end;
+        }
+        out.extend_from_slice(&old[at..]);
+        out
+    }
+
+    /// The number of inserted and deleted elements
+    fn cost(hunks: &[Hunk]) -> usize {
+        hunks
+            .iter()
+            .map(|hunk| hunk.old.len() + hunk.new.len())
+            .sum()
+    }
+
+    #[test]
+    fn finds_the_shortest_edit_script() {
+        // The example of Myers' paper takes 5 edits
+        let hunks = diff_bytes(b"ABCABBA", b"CBABAC");
+        assert_eq!(patch(b"ABCABBA", b"CBABAC", &hunks), b"CBABAC");
+        assert_eq!(cost(&hunks), 5);
+
+        assert_eq!(diff_bytes(b"same", b"same"), []);
+        assert_eq!(
+            diff_bytes(b"", b"new"),
+            [Hunk {
+                old: 0..0,
+                new: 0..3
+            }]
+        );
+        assert_eq!(
+            diff_bytes(b"abcd", b"axcd"),
+            [Hunk {
+                old: 1..2,
+                new: 1..2
+            }]
+        );
+    }
+
+    #[test]
+    fn random_sequences_patch_back() {
+        let mut seed: u64 = 7;
+        let mut next = || {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+            (seed >> 33) as usize
+        };
+        for _ in 0..500 {
+            let old: Vec<usize> = (0..next() % 20).map(|_| next() % 4).collect();
+            let new: Vec<usize> = (0..next() % 20).map(|_| next() % 4).collect();
+            let hunks = diff_slices(&old, &new);
+            assert_eq!(patch(&old, &new, &hunks), new);
+            // As short as the longest common subsequence allows
+            let common = unchanged(&hunks, old.len(), new.len()).count();
+            assert_eq!(cost(&hunks), old.len() + new.len() - 2 * common);
+            assert_eq!(common, lcs_len(&old, &new));
+        }
+    }
+
+    fn lcs_len(old: &[usize], new: &[usize]) -> usize {
+        let mut table = vec![vec![0; new.len() + 1]; old.len() + 1];
+        for i in 0..old.len() {
+            for j in 0..new.len() {
+                table[i + 1][j + 1] = if old[i] == new[j] {
+                    table[i][j] + 1
+                } else {
+                    table[i][j + 1].max(table[i + 1][j])
+                };
+            }
+        }
+        table[old.len()][new.len()]
+    }
+
+    #[test]
+    fn lines_keep_their_terminator() {
+        let old = "one\ntwo\nthree";
+        let new = "one\n2\nthree\n";
+        let hunks = diff_lines(old, new);
+        assert_eq!(
+            hunks,
+            [Hunk {
+                old: 1..3,
+                new: 1..3
+            }]
+        );
+        assert_eq!(unchanged(&hunks, 3, 3).collect::<Vec<_>>(), [(0, 0)]);
+        assert_eq!(similarity(&hunks, 3, 3), 1.0 / 3.0);
+    }
+}
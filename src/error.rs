@@ -0,0 +1,78 @@
+use std::io;
+use std::path::PathBuf;
+use thiserror::Error;
+
+/// Errors reported by repository operations
+#[derive(Debug, Error)]
+pub enum Error {
+    /// Reading or writing a file failed
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    /// The directory holds no repository
+    #[error("{} isn't a vaild git dir", .0.display())]
+    NotARepository(PathBuf),
+    /// A revision names no commit
+    #[error("bad revision '{0}'")]
+    BadRevision(String),
+    /// No branch has the given name
+    #[error("no such branch '{0}'")]
+    NoSuchBranch(String),
+    /// A branch with the given name exists already
+    #[error("a branch named '{0}' already exists")]
+    BranchExists(String),
+    /// A pathspec matches no file
+    #[error("pathspec '{0}' did not match any files")]
+    PathspecNoMatch(String),
+    /// The index or working tree holds changes the operation would lose
+    #[error("you have uncommitted changes; commit or stash them first")]
+    UncommittedChanges,
+    /// Switching commits would overwrite local changes to these files
+    #[error(
+        "Your local changes to the following files would be overwritten by checkout:\n\t{}\n\
+         Please commit your changes or stash them before you switch branches.",
+        .0.join("\n\t")
+    )]
+    LocalChangesOverwritten(Vec<String>),
+    /// An untracked file is where the operation would write a tracked one
+    #[error("untracked working tree file '{0}' would be overwritten")]
+    UntrackedInTheWay(String),
+    /// The index still holds conflicts to resolve
+    #[error(
+        "Committing is not possible because you have unmerged files:\n\t{}\n\
+         Fix them up in the work tree, and then use 'rust-git add <file>' as appropriate.",
+        .0.join("\n\t")
+    )]
+    UnmergedFiles(Vec<String>),
+    /// Nothing is staged that differs from HEAD
+    #[error("nothing to commit")]
+    NothingToCommit,
+    /// `user.name` or `user.email` is not configured
+    #[error(
+        "Author identity unknown\n\n\
+         *** Please tell me who you are.\n\n\
+         Run\n\n  \
+         rust-git config --global --set user.email \"you@example.com\"\n  \
+         rust-git config --global --set user.name \"Your Name\"\n\n\
+         to set your account's default identity.\n\
+         Omit --global to set the identity only in this repository."
+    )]
+    IdentityUnknown,
+    /// A hook refused to let the operation go ahead
+    #[error("{hook} hook rejected the operation: {reason}")]
+    HookRejected { hook: &'static str, reason: String },
+    /// Any other failure, described by its message
+    #[error("{0}")]
+    Other(String),
+}
+
+impl From<String> for Error {
+    fn from(why: String) -> Self {
+        Error::Other(why)
+    }
+}
+
+impl From<&str> for Error {
+    fn from(why: &str) -> Self {
+        Error::Other(why.to_string())
+    }
+}
@@ -0,0 +1,608 @@
+use flate2::Compression;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use memchr::memchr;
+use sha1::{Digest, Sha1};
+use walkdir::WalkDir;
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Object types found in a git repository
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GitKind {
+    Commit,
+    Tree,
+    Blob,
+    Tag,
+}
+
+impl GitKind {
+    /// The type name used in object headers
+    pub fn name(&self) -> &'static str {
+        match self {
+            GitKind::Commit => "commit",
+            GitKind::Tree => "tree",
+            GitKind::Blob => "blob",
+            GitKind::Tag => "tag",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Result<GitKind, String> {
+        match name {
+            "commit" => Ok(GitKind::Commit),
+            "tree" => Ok(GitKind::Tree),
+            "blob" => Ok(GitKind::Blob),
+            "tag" => Ok(GitKind::Tag),
+            _ => Err(format!("unknown object type '{name}'")),
+        }
+    }
+}
+
+/// Where HEAD of a git repository points
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GitHead {
+    /// Full ref name, e.g. `refs/heads/main`
+    Symbolic(String),
+    /// Hex object id
+    Detached(String),
+}
+
+/// Read-only view of a repository in git's own on-disk format:
+/// zlib-compressed loose objects, pack files and loose/packed refs.
+pub struct GitSource {
+    git_dir: PathBuf,
+    packs: PackStore,
+}
+
+/// The pack files of an object directory, `objects/pack/pack-*.pack`
+#[derive(Default)]
+pub struct PackStore {
+    packs: Vec<Pack>,
+}
+
+/// A pack file loaded together with its index
+struct Pack {
+    data: Vec<u8>,
+    /// Hex object id to offset of the object in `data`
+    offsets: HashMap<String, usize>,
+}
+
+impl GitSource {
+    /// Opens the git repository at `path`, which may be a working tree
+    /// containing `.git` or a bare repository
+    pub fn open(path: &Path) -> Result<GitSource, String> {
+        let dot_git = path.join(".git");
+        let git_dir = if dot_git.is_dir() {
+            dot_git
+        } else {
+            path.to_path_buf()
+        };
+        if !git_dir.join("HEAD").is_file() || !git_dir.join("objects").is_dir() {
+            return Err(format!("{} is not a git repository", path.display()));
+        }
+
+        let packs = PackStore::open(&git_dir.join("objects"))?;
+        Ok(GitSource { git_dir, packs })
+    }
+
+    /// Reads an object by hex id, resolving pack deltas
+    ///
+    /// # Returns
+    /// The object type and its content without the `"{type} {size}\0"` header
+    pub fn read(&self, sha: &str) -> Result<(GitKind, Vec<u8>), String> {
+        let loose = self.git_dir.join("objects").join(&sha[..2]).join(&sha[2..]);
+        if loose.is_file() {
+            let compressed = fs::read(&loose).map_err(|why| why.to_string())?;
+            let data = inflate(&compressed)?;
+            let nul = memchr(0, &data).ok_or_else(|| format!("corrupt object {sha}"))?;
+            let header = String::from_utf8_lossy(&data[..nul]);
+            let (kind, _) = header
+                .split_once(' ')
+                .ok_or_else(|| format!("corrupt object header in {sha}"))?;
+            return Ok((GitKind::from_name(kind)?, data[nul + 1..].to_vec()));
+        }
+        self.packs
+            .read(sha)
+            .unwrap_or_else(|| Err(format!("object {sha} not found")))
+    }
+
+    /// Reads HEAD
+    pub fn head(&self) -> Result<GitHead, String> {
+        let content =
+            fs::read_to_string(self.git_dir.join("HEAD")).map_err(|why| why.to_string())?;
+        let content = content.trim();
+        match content.strip_prefix("ref: ") {
+            Some(name) => Ok(GitHead::Symbolic(name.to_string())),
+            None => Ok(GitHead::Detached(content.to_string())),
+        }
+    }
+
+    /// Lists all refs with the object ids they point at, sorted by name.
+    /// Loose refs take precedence over `packed-refs`.
+    pub fn refs(&self) -> Result<Vec<(String, String)>, String> {
+        let mut refs = HashMap::new();
+        if let Ok(packed) = fs::read_to_string(self.git_dir.join("packed-refs")) {
+            for line in packed.lines() {
+                // Skip the header and peeled tag lines
+                if line.starts_with('#') || line.starts_with('^') {
+                    continue;
+                }
+                if let Some((sha, name)) = line.split_once(' ') {
+                    refs.insert(name.to_string(), sha.to_string());
+                }
+            }
+        }
+        for entry in WalkDir::new(self.git_dir.join("refs"))
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_file())
+        {
+            let name = entry.path().strip_prefix(&self.git_dir).unwrap();
+            let name = name.to_string_lossy().replace('\\', "/");
+            let content = fs::read_to_string(entry.path()).map_err(|why| why.to_string())?;
+            let content = content.trim();
+            // Symbolic refs such as refs/remotes/origin/HEAD are not followed
+            if !content.starts_with("ref: ") {
+                refs.insert(name, content.to_string());
+            }
+        }
+        let mut refs: Vec<_> = refs.into_iter().collect();
+        refs.sort();
+        Ok(refs)
+    }
+}
+
+impl PackStore {
+    /// Loads every pack in `objects_dir`; a missing pack directory holds
+    /// no packs
+    pub fn open(objects_dir: &Path) -> Result<PackStore, String> {
+        let mut packs = Vec::new();
+        let pack_dir = objects_dir.join("pack");
+        if pack_dir.is_dir() {
+            for entry in fs::read_dir(&pack_dir).map_err(|why| why.to_string())? {
+                let idx_path = entry.map_err(|why| why.to_string())?.path();
+                if idx_path.extension().is_some_and(|ext| ext == "idx") {
+                    packs.push(Pack::load(&idx_path)?);
+                }
+            }
+        }
+        Ok(PackStore { packs })
+    }
+
+    /// The hex ids of the objects the packs hold, in no particular order;
+    /// an object in several packs is listed for each
+    pub fn objects(&self) -> impl Iterator<Item = &str> {
+        self.packs
+            .iter()
+            .flat_map(|pack| pack.offsets.keys().map(String::as_str))
+    }
+
+    /// Reads an object by hex id, resolving deltas against objects in the
+    /// packs. Returns `None` if no pack holds the object.
+    pub fn read(&self, sha: &str) -> Option<Result<(GitKind, Vec<u8>), String>> {
+        let (pack, &offset) = self
+            .packs
+            .iter()
+            .find_map(|pack| Some((pack, pack.offsets.get(sha)?)))?;
+        Some(self.read_packed(pack, offset))
+    }
+
+    fn read_packed(&self, pack: &Pack, offset: usize) -> Result<(GitKind, Vec<u8>), String> {
+        let data = &pack.data;
+        let byte = |pos: usize| {
+            data.get(pos)
+                .copied()
+                .ok_or_else(|| "truncated pack file".to_string())
+        };
+
+        let mut pos = offset;
+        let mut c = byte(pos)?;
+        pos += 1;
+        let type_id = (c >> 4) & 7;
+        while c & 0x80 != 0 {
+            // The size is not needed: inflating stops at the end of the stream
+            c = byte(pos)?;
+            pos += 1;
+        }
+
+        match type_id {
+            1..=4 => {
+                let kind = [GitKind::Commit, GitKind::Tree, GitKind::Blob, GitKind::Tag]
+                    [type_id as usize - 1];
+                Ok((kind, inflate(&data[pos..])?))
+            }
+            // OFS_DELTA: base is earlier in the same pack
+            6 => {
+                c = byte(pos)?;
+                pos += 1;
+                let mut distance = (c & 0x7f) as usize;
+                while c & 0x80 != 0 {
+                    c = byte(pos)?;
+                    pos += 1;
+                    distance = ((distance + 1) << 7) | (c & 0x7f) as usize;
+                }
+                let base_offset = offset
+                    .checked_sub(distance)
+                    .ok_or_else(|| "invalid delta base offset".to_string())?;
+                let (kind, base) = self.read_packed(pack, base_offset)?;
+                Ok((kind, apply_delta(&base, &inflate(&data[pos..])?)?))
+            }
+            // REF_DELTA: base is named by its object id
+            7 => {
+                let base_sha = hex::encode(
+                    data.get(pos..pos + 20)
+                        .ok_or_else(|| "truncated pack file".to_string())?,
+                );
+                let (kind, base) = self
+                    .read(&base_sha)
+                    .unwrap_or_else(|| Err(format!("delta base {base_sha} not found")))?;
+                Ok((kind, apply_delta(&base, &inflate(&data[pos + 20..])?)?))
+            }
+            _ => Err(format!("unknown pack object type {type_id}")),
+        }
+    }
+}
+
+impl Pack {
+    /// Loads `pack-*.idx` and the matching `.pack`
+    fn load(idx_path: &Path) -> Result<Pack, String> {
+        let idx = fs::read(idx_path).map_err(|why| why.to_string())?;
+        let data = fs::read(idx_path.with_extension("pack")).map_err(|why| why.to_string())?;
+        if !data.starts_with(b"PACK") {
+            return Err(format!("{} is not a pack file", idx_path.display()));
+        }
+        let offsets = parse_pack_index(&idx)?;
+        Ok(Pack { data, offsets })
+    }
+}
+
+/// Writes a version 2 pack file holding `objects` whole, without deltas.
+/// The pack ends with the SHA1 of everything before it.
+pub fn write_pack(objects: &[(GitKind, Vec<u8>)]) -> Vec<u8> {
+    let mut pack = b"PACK".to_vec();
+    pack.extend_from_slice(&2u32.to_be_bytes());
+    pack.extend_from_slice(&(objects.len() as u32).to_be_bytes());
+    for (kind, content) in objects {
+        let type_id = match kind {
+            GitKind::Commit => 1,
+            GitKind::Tree => 2,
+            GitKind::Blob => 3,
+            GitKind::Tag => 4,
+        };
+        // Type and size: 4 bits of the size in the first byte, then 7 per byte
+        let mut size = content.len();
+        let mut c = (type_id << 4) | (size & 0x0f) as u8;
+        size >>= 4;
+        while size != 0 {
+            pack.push(c | 0x80);
+            c = (size & 0x7f) as u8;
+            size >>= 7;
+        }
+        pack.push(c);
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        // Writing to a Vec cannot fail
+        encoder.write_all(content).unwrap();
+        pack.extend_from_slice(&encoder.finish().unwrap());
+    }
+    let checksum = Sha1::digest(&pack);
+    pack.extend_from_slice(&checksum);
+    pack
+}
+
+/// Reads every object of a pack file in order, without an index, and
+/// resolves the deltas. Bases of `REF_DELTA` objects that are not in the
+/// pack, as in thin packs, are looked up with `external`.
+///
+/// # Returns
+/// The hex id, type and content of every object
+pub fn parse_pack<F>(data: &[u8], external: F) -> Result<Vec<(String, GitKind, Vec<u8>)>, String>
+where
+    F: Fn(&str) -> Option<(GitKind, Vec<u8>)>,
+{
+    let truncated = || "truncated pack file".to_string();
+    if data.len() < 32 || !data.starts_with(b"PACK") {
+        return Err("not a pack file".to_string());
+    }
+    let (body, checksum) = data.split_at(data.len() - 20);
+    if Sha1::digest(body).as_slice() != checksum {
+        return Err("pack checksum mismatch".to_string());
+    }
+    let version = u32::from_be_bytes(body[4..8].try_into().unwrap());
+    if version != 2 && version != 3 {
+        return Err(format!("unsupported pack version {version}"));
+    }
+    let count = u32::from_be_bytes(body[8..12].try_into().unwrap()) as usize;
+
+    let mut objects: Vec<(String, GitKind, Vec<u8>)> = Vec::with_capacity(count);
+    // Pack offset and hex id to position in `objects`
+    let mut by_offset: HashMap<usize, usize> = HashMap::new();
+    let mut by_sha: HashMap<String, usize> = HashMap::new();
+    let mut pos = 12;
+    for _ in 0..count {
+        let offset = pos;
+        let byte = |pos: usize| body.get(pos).copied().ok_or_else(truncated);
+        let mut c = byte(pos)?;
+        pos += 1;
+        let type_id = (c >> 4) & 7;
+        let mut size = (c & 0x0f) as usize;
+        let mut shift = 4;
+        while c & 0x80 != 0 {
+            c = byte(pos)?;
+            pos += 1;
+            size |= ((c & 0x7f) as usize) << shift;
+            shift += 7;
+        }
+
+        let base = match type_id {
+            6 => {
+                c = byte(pos)?;
+                pos += 1;
+                let mut distance = (c & 0x7f) as usize;
+                while c & 0x80 != 0 {
+                    c = byte(pos)?;
+                    pos += 1;
+                    distance = ((distance + 1) << 7) | (c & 0x7f) as usize;
+                }
+                let base = offset
+                    .checked_sub(distance)
+                    .and_then(|base| by_offset.get(&base))
+                    .ok_or_else(|| "invalid delta base offset".to_string())?;
+                let (_, kind, content) = &objects[*base];
+                Some((*kind, content.clone()))
+            }
+            7 => {
+                let base_sha = hex::encode(body.get(pos..pos + 20).ok_or_else(truncated)?);
+                pos += 20;
+                let base = match by_sha.get(&base_sha) {
+                    Some(&index) => {
+                        let (_, kind, content) = &objects[index];
+                        Some((*kind, content.clone()))
+                    }
+                    None => external(&base_sha),
+                };
+                Some(base.ok_or_else(|| format!("delta base {base_sha} not found"))?)
+            }
+            _ => None,
+        };
+
+        let mut decoder = ZlibDecoder::new(body.get(pos..).ok_or_else(truncated)?);
+        let mut inflated = Vec::new();
+        decoder
+            .read_to_end(&mut inflated)
+            .map_err(|why| format!("corrupt compressed data: {why}"))?;
+        pos += decoder.total_in() as usize;
+        if inflated.len() != size {
+            return Err(format!("corrupt pack object at offset {offset}"));
+        }
+        let (kind, content) = match (type_id, base) {
+            (1..=4, _) => (
+                [GitKind::Commit, GitKind::Tree, GitKind::Blob, GitKind::Tag][type_id as usize - 1],
+                inflated,
+            ),
+            (_, Some((kind, base))) => (kind, apply_delta(&base, &inflated)?),
+            _ => return Err(format!("unknown pack object type {type_id}")),
+        };
+
+        let mut hasher = Sha1::new();
+        hasher.update(format!("{} {}\0", kind.name(), content.len()));
+        hasher.update(&content);
+        let sha = hex::encode(hasher.finalize());
+        by_offset.insert(offset, objects.len());
+        by_sha.insert(sha.clone(), objects.len());
+        objects.push((sha, kind, content));
+    }
+    if pos != body.len() {
+        return Err("trailing data in pack file".to_string());
+    }
+    Ok(objects)
+}
+
+/// Parses a pack index (version 1 or 2) into object id -> pack offset
+fn parse_pack_index(idx: &[u8]) -> Result<HashMap<String, usize>, String> {
+    let truncated = || "truncated pack index".to_string();
+    let u32_at = |pos: usize| -> Result<usize, String> {
+        let bytes = idx.get(pos..pos + 4).ok_or_else(truncated)?;
+        Ok(u32::from_be_bytes(bytes.try_into().unwrap()) as usize)
+    };
+
+    let mut offsets = HashMap::new();
+    if idx.starts_with(b"\xfftOc") {
+        if u32_at(4)? != 2 {
+            return Err("unsupported pack index version".to_string());
+        }
+        let count = u32_at(8 + 255 * 4)?;
+        let shas = 8 + 256 * 4;
+        let small_offsets = shas + count * 20 + count * 4;
+        let large_offsets = small_offsets + count * 4;
+        for i in 0..count {
+            let sha = idx
+                .get(shas + i * 20..shas + (i + 1) * 20)
+                .ok_or_else(truncated)?;
+            let mut offset = u32_at(small_offsets + i * 4)?;
+            if offset & 0x8000_0000 != 0 {
+                let pos = large_offsets + (offset & 0x7fff_ffff) * 8;
+                let bytes = idx.get(pos..pos + 8).ok_or_else(truncated)?;
+                offset = u64::from_be_bytes(bytes.try_into().unwrap()) as usize;
+            }
+            offsets.insert(hex::encode(sha), offset);
+        }
+    } else {
+        let count = u32_at(255 * 4)?;
+        let entries = 256 * 4;
+        for i in 0..count {
+            let pos = entries + i * 24;
+            let sha = idx.get(pos + 4..pos + 24).ok_or_else(truncated)?;
+            offsets.insert(hex::encode(sha), u32_at(pos)?);
+        }
+    }
+    Ok(offsets)
+}
+
+/// Decompresses a zlib stream, ignoring anything after its end
+fn inflate(data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    ZlibDecoder::new(data)
+        .read_to_end(&mut out)
+        .map_err(|why| format!("corrupt compressed data: {why}"))?;
+    Ok(out)
+}
+
+/// Rebuilds an object from its base and a git delta
+fn apply_delta(base: &[u8], delta: &[u8]) -> Result<Vec<u8>, String> {
+    let corrupt = || "corrupt delta".to_string();
+    let mut pos = 0;
+    let varint = |pos: &mut usize| -> Result<usize, String> {
+        let mut value = 0;
+        let mut shift = 0;
+        loop {
+            let c = *delta.get(*pos).ok_or_else(corrupt)?;
+            *pos += 1;
+            value |= ((c & 0x7f) as usize) << shift;
+            shift += 7;
+            if c & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+    };
+    let base_size = varint(&mut pos)?;
+    let result_size = varint(&mut pos)?;
+    if base_size != base.len() {
+        return Err(corrupt());
+    }
+
+    let mut result = Vec::with_capacity(result_size);
+    while pos < delta.len() {
+        let op = delta[pos];
+        pos += 1;
+        if op & 0x80 != 0 {
+            // Copy a range of the base
+            let mut offset = 0;
+            let mut size = 0;
+            for i in 0..4 {
+                if op & (1 << i) != 0 {
+                    offset |= (*delta.get(pos).ok_or_else(corrupt)? as usize) << (8 * i);
+                    pos += 1;
+                }
+            }
+            for i in 0..3 {
+                if op & (0x10 << i) != 0 {
+                    size |= (*delta.get(pos).ok_or_else(corrupt)? as usize) << (8 * i);
+                    pos += 1;
+                }
+            }
+            if size == 0 {
+                size = 0x10000;
+            }
+            result.extend_from_slice(base.get(offset..offset + size).ok_or_else(corrupt)?);
+        } else if op != 0 {
+            // Insert literal bytes
+            let size = op as usize;
+            result.extend_from_slice(delta.get(pos..pos + size).ok_or_else(corrupt)?);
+            pos += size;
+        } else {
+            return Err(corrupt());
+        }
+    }
+    if result.len() != result_size {
+        return Err(corrupt());
+    }
+    Ok(result)
+}
+
+/// Writes `objects` to `objects_dir/pack` as a pack with a version 2
+/// index, the way `git gc` leaves them
+#[cfg(test)]
+pub(crate) fn write_test_pack(objects_dir: &Path, objects: &[(GitKind, Vec<u8>)]) {
+    let pack = write_pack(objects);
+    // Each object starts where a pack of the ones before it would end
+    let mut entries: Vec<(Vec<u8>, u32)> = (0..objects.len())
+        .map(|i| {
+            let (kind, content) = &objects[i];
+            let mut hasher = Sha1::new();
+            hasher.update(format!("{} {}\0", kind.name(), content.len()));
+            hasher.update(content);
+            let offset = write_pack(&objects[..i]).len() - 20;
+            (hasher.finalize().to_vec(), offset as u32)
+        })
+        .collect();
+    entries.sort();
+    let mut idx = b"\xfftOc\0\0\0\x02".to_vec();
+    for first in 0..=255u8 {
+        let count = entries.iter().filter(|(sha, _)| sha[0] <= first).count();
+        idx.extend((count as u32).to_be_bytes());
+    }
+    for (sha, _) in &entries {
+        idx.extend(sha);
+    }
+    idx.extend(vec![0; entries.len() * 4]);
+    for (_, offset) in &entries {
+        idx.extend(offset.to_be_bytes());
+    }
+    let pack_dir = objects_dir.join("pack");
+    fs::create_dir_all(&pack_dir).unwrap();
+    fs::write(pack_dir.join("pack-test.pack"), pack).unwrap();
+    fs::write(pack_dir.join("pack-test.idx"), idx).unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn applies_copy_and_insert_ops() {
+        let base = b"hello world";
+        // base size 11, result size 12, copy 6 bytes from 0, insert "there!"
+        let delta = [11, 12, 0x90, 6, 6, b't', b'h', b'e', b'r', b'e', b'!'];
+        assert_eq!(apply_delta(base, &delta).unwrap(), b"hello there!");
+        assert!(apply_delta(b"short", &delta).is_err());
+    }
+
+    #[test]
+    fn parses_v2_index() {
+        let mut idx = b"\xfftOc\0\0\0\x02".to_vec();
+        // Fanout: one object whose id starts with 0xab
+        for i in 0..256u32 {
+            idx.extend_from_slice(&(if i >= 0xab { 1u32 } else { 0 }).to_be_bytes());
+        }
+        idx.extend_from_slice(&[0xab; 20]);
+        idx.extend_from_slice(&[0; 4]); // crc
+        idx.extend_from_slice(&12u32.to_be_bytes());
+
+        let offsets = parse_pack_index(&idx).unwrap();
+        assert_eq!(offsets.get(&"ab".repeat(20)), Some(&12));
+    }
+
+    #[test]
+    fn written_packs_parse_back() {
+        let objects = vec![
+            (GitKind::Blob, b"hello world".to_vec()),
+            (GitKind::Blob, vec![b'x'; 300]),
+        ];
+        let pack = write_pack(&objects);
+        let parsed = parse_pack(&pack, |_| None).unwrap();
+        // The id of the blob "hello world", as `git hash-object` gives it
+        assert_eq!(parsed[0].0, "95d09f2b10159347eece71399a7e2e907ea3df4f");
+        assert_eq!(parsed[1].2, objects[1].1);
+
+        // A REF_DELTA against the first object is resolved too
+        let mut thin = pack[..pack.len() - 20].to_vec();
+        thin[11] = 3;
+        thin.push(0x70 | 11);
+        thin.extend_from_slice(&hex::decode(&parsed[0].0).unwrap());
+        let delta = [11, 12, 0x90, 6, 6, b't', b'h', b'e', b'r', b'e', b'!'];
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&delta).unwrap();
+        thin.extend_from_slice(&encoder.finish().unwrap());
+        let checksum = Sha1::digest(&thin);
+        thin.extend_from_slice(&checksum);
+        let parsed = parse_pack(&thin, |_| None).unwrap();
+        assert_eq!(parsed[2].2, b"hello there!");
+
+        let mut corrupt = pack.clone();
+        corrupt[20] ^= 1;
+        assert!(parse_pack(&corrupt, |_| None).is_err());
+    }
+}
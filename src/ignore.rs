@@ -0,0 +1,294 @@
+use regex::Regex;
+use std::fs;
+use std::path::Path;
+use walkdir::WalkDir;
+
+/// Per-directory file listing paths that should stay untracked
+pub const IGNORE_FILE: &str = ".gitignore";
+
+/// One pattern line of an ignore file
+#[derive(Debug)]
+struct Rule {
+    /// Directory holding the ignore file, relative to the worktree root;
+    /// empty for the top level
+    base: String,
+    regex: Regex,
+    /// `!pattern`: re-includes what an earlier pattern excluded
+    negated: bool,
+    /// `pattern/`: only matches directories
+    dir_only: bool,
+    /// Patterns without a slash match the file name at any depth below
+    /// `base`; the others match the whole path relative to `base`
+    basename_only: bool,
+    /// Line number in the ignore file, counting from 1
+    line: usize,
+    /// The pattern as written
+    pattern: String,
+}
+
+/// The pattern deciding whether a path is ignored, as `check-ignore -v`
+/// reports it
+#[derive(Debug, Clone, PartialEq)]
+pub struct IgnoreMatch {
+    /// The ignore file, relative to the worktree root
+    pub source: String,
+    /// Line number of the pattern in `source`, counting from 1
+    pub line: usize,
+    /// The pattern as written, with its `!` if it re-includes the path
+    pub pattern: String,
+}
+
+impl IgnoreMatch {
+    /// Whether the pattern ignores the path rather than re-including it
+    pub fn is_ignored(&self) -> bool {
+        !self.pattern.starts_with('!')
+    }
+}
+
+impl std::fmt::Display for IgnoreMatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}:{}", self.source, self.line, self.pattern)
+    }
+}
+
+/// The patterns of every `.gitignore` in a worktree, e.g.
+///
+/// ```text
+/// target/
+/// *.log
+/// !keep.log
+/// docs/**/*.tmp
+/// ```
+///
+/// As in git, the last matching pattern decides, patterns from deeper
+/// directories take precedence, and a file inside an ignored directory is
+/// ignored no matter what.
+#[derive(Debug, Default)]
+pub struct IgnoreRules {
+    rules: Vec<Rule>,
+}
+
+impl IgnoreRules {
+    /// Reads every `.gitignore` below `root`, skipping the directory
+    /// `skip` (the git dir) and directories that are ignored themselves
+    pub fn load(root: &Path, skip: &Path) -> IgnoreRules {
+        let mut rules = IgnoreRules::default();
+        let mut walker = WalkDir::new(root).into_iter();
+        while let Some(Ok(entry)) = walker.next() {
+            if !entry.file_type().is_dir() {
+                continue;
+            }
+            let dir = relative_path(root, entry.path());
+            if entry.path() == skip || (!dir.is_empty() && rules.is_ignored(&dir, true)) {
+                walker.skip_current_dir();
+                continue;
+            }
+            if let Ok(text) = fs::read_to_string(entry.path().join(IGNORE_FILE)) {
+                rules.add_patterns(&dir, &text);
+            }
+        }
+        // Parents are visited before their children, so deeper patterns
+        // come later and win
+        rules
+    }
+
+    /// Adds the patterns of an ignore file found in `base`
+    pub fn add_patterns(&mut self, base: &str, text: &str) {
+        for (number, line) in text.lines().enumerate() {
+            if let Some(rule) = Rule::parse(base, line, number + 1) {
+                self.rules.push(rule);
+            }
+        }
+    }
+
+    /// Whether `path`, relative to the worktree root, is ignored, either by
+    /// a pattern of its own or because a parent directory is
+    pub fn is_ignored(&self, path: &str, is_dir: bool) -> bool {
+        self.deciding_rule(path, is_dir)
+            .is_some_and(|rule| !rule.negated)
+    }
+
+    /// The pattern deciding whether `path` is ignored, see
+    /// [`Self::is_ignored`], or `None` if no pattern matches it
+    pub fn explain(&self, path: &str, is_dir: bool) -> Option<IgnoreMatch> {
+        let rule = self.deciding_rule(path, is_dir)?;
+        let source = match rule.base.as_str() {
+            "" => IGNORE_FILE.to_string(),
+            base => format!("{base}/{IGNORE_FILE}"),
+        };
+        Some(IgnoreMatch {
+            source,
+            line: rule.line,
+            pattern: rule.pattern.clone(),
+        })
+    }
+
+    fn deciding_rule(&self, path: &str, is_dir: bool) -> Option<&Rule> {
+        let mut end = 0;
+        while let Some(slash) = path[end..].find('/') {
+            end += slash;
+            if let Some(rule) = self.matching_rule(&path[..end], true)
+                && !rule.negated
+            {
+                return Some(rule);
+            }
+            end += 1;
+        }
+        self.matching_rule(path, is_dir)
+    }
+
+    /// The last pattern matching `path` itself, ignoring its parents
+    fn matching_rule(&self, path: &str, is_dir: bool) -> Option<&Rule> {
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| rule.matches(path, is_dir))
+    }
+}
+
+impl Rule {
+    fn parse(base: &str, line: &str, number: usize) -> Option<Rule> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negated, pattern) = match line.strip_prefix('!') {
+            Some(pattern) => (true, pattern),
+            None => (false, line.strip_prefix('\\').unwrap_or(line)),
+        };
+        let (dir_only, pattern) = match pattern.strip_suffix('/') {
+            Some(pattern) => (true, pattern),
+            None => (false, pattern),
+        };
+        if pattern.is_empty() {
+            return None;
+        }
+        let basename_only = !pattern.contains('/');
+        let pattern = pattern.strip_prefix('/').unwrap_or(pattern);
+        let regex = Regex::new(&format!("^{}$", glob_to_regex(pattern))).ok()?;
+        Some(Rule {
+            base: base.to_string(),
+            regex,
+            negated,
+            dir_only,
+            basename_only,
+            line: number,
+            pattern: line.to_string(),
+        })
+    }
+
+    fn matches(&self, path: &str, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        let relative = if self.base.is_empty() {
+            path
+        } else {
+            match path
+                .strip_prefix(&self.base)
+                .and_then(|p| p.strip_prefix('/'))
+            {
+                Some(relative) => relative,
+                None => return false,
+            }
+        };
+        if self.basename_only {
+            let name = relative.rsplit('/').next().unwrap_or(relative);
+            self.regex.is_match(name)
+        } else {
+            self.regex.is_match(relative)
+        }
+    }
+}
+
+/// Translates a gitignore glob into a regular expression: `*` and `?` stay
+/// within one path component, `**` spans any number of them
+pub(crate) fn glob_to_regex(pattern: &str) -> String {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut regex = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '*' if chars.get(i + 1) == Some(&'*') => {
+                let at_start = i == 0 || chars[i - 1] == '/';
+                if at_start && chars.get(i + 2) == Some(&'/') {
+                    // `**/`: zero or more leading directories
+                    regex.push_str("(?:.*/)?");
+                    i += 3;
+                } else {
+                    regex.push_str(".*");
+                    i += 2;
+                }
+            }
+            '*' => {
+                regex.push_str("[^/]*");
+                i += 1;
+            }
+            '?' => {
+                regex.push_str("[^/]");
+                i += 1;
+            }
+            '[' => match chars[i + 1..].iter().position(|&c| c == ']') {
+                Some(len) => {
+                    let class: String = chars[i + 1..i + 1 + len].iter().collect();
+                    let class = match class.strip_prefix('!') {
+                        Some(rest) => format!("^{rest}"),
+                        None => class,
+                    };
+                    regex.push('[');
+                    regex.push_str(&class.replace('\\', "\\\\"));
+                    regex.push(']');
+                    i += len + 2;
+                }
+                None => {
+                    regex.push_str("\\[");
+                    i += 1;
+                }
+            },
+            '\\' if i + 1 < chars.len() => {
+                regex.push_str(&regex::escape(&chars[i + 1].to_string()));
+                i += 2;
+            }
+            c => {
+                regex.push_str(&regex::escape(&c.to_string()));
+                i += 1;
+            }
+        }
+    }
+    regex
+}
+
+fn relative_path(root: &Path, path: &Path) -> String {
+    path.strip_prefix(root)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .replace('\\', "/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patterns_follow_gitignore_rules() {
+        let mut rules = IgnoreRules::default();
+        rules.add_patterns("", "# build output\ntarget/\n*.log\n!keep.log\n/root.txt\n");
+        rules.add_patterns("docs", "**/*.tmp\nbuild/**\n");
+        rules.add_patterns("docs/sub", "!*.log\n");
+
+        assert!(rules.is_ignored("target", true));
+        assert!(!rules.is_ignored("target", false));
+        assert!(rules.is_ignored("target/debug/app", false));
+        assert!(rules.is_ignored("src/debug.log", false));
+        assert!(!rules.is_ignored("src/keep.log", false));
+        assert!(rules.is_ignored("root.txt", false));
+        assert!(!rules.is_ignored("src/root.txt", false));
+
+        assert!(rules.is_ignored("docs/a/b/c.tmp", false));
+        assert!(rules.is_ignored("docs/c.tmp", false));
+        assert!(!rules.is_ignored("c.tmp", false));
+        assert!(rules.is_ignored("docs/build/out.html", false));
+        assert!(!rules.is_ignored("docs/sub/x.log", false));
+        assert!(rules.is_ignored("docs/other/x.log", false));
+    }
+}
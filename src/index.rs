@@ -1,14 +1,139 @@
 use std::collections::BTreeMap;
 use std::fmt;
+use std::fs::{self, Metadata};
+use std::ops::{Deref, DerefMut};
 use std::path::{Component, Path};
+use std::time::UNIX_EPOCH;
+
+use sha1::{Digest, Sha1};
 
 use crate::EncodedSha;
+use crate::lock::{self, LockFile};
+use crate::object::{Blob, FileMode, Object};
+use crate::repo::ConflictKind;
+
+/// Signature at the start of a Git index file ("dircache")
+const INDEX_SIGNATURE: &[u8; 4] = b"DIRC";
+/// Index format version written by `Index::save`
+const INDEX_VERSION: u32 = 2;
+
+/// File system data cached for a tracked file, used to tell that a file
+/// is unchanged without reading it.
+///
+/// These are the stat fields of a Git index entry. Git truncates device,
+/// inode and owner numbers to 32 bits, and so does `from_metadata`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FileStat {
+    pub ctime_secs: i64,
+    pub ctime_nanos: u32,
+    pub mtime_secs: i64,
+    pub mtime_nanos: u32,
+    pub dev: u32,
+    pub ino: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub size: u64,
+}
+
+impl FileStat {
+    pub fn from_metadata(metadata: &Metadata) -> FileStat {
+        let (mtime_secs, mtime_nanos) = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map(|since| (since.as_secs() as i64, since.subsec_nanos()))
+            .unwrap_or_default();
+        let stat = FileStat {
+            mtime_secs,
+            mtime_nanos,
+            size: metadata.len(),
+            ..FileStat::default()
+        };
+        #[cfg(unix)]
+        let stat = {
+            use std::os::unix::fs::MetadataExt;
+            FileStat {
+                ctime_secs: metadata.ctime(),
+                ctime_nanos: metadata.ctime_nsec() as u32,
+                dev: metadata.dev() as u32,
+                ino: metadata.ino() as u32,
+                uid: metadata.uid(),
+                gid: metadata.gid(),
+                ..stat
+            }
+        };
+        stat
+    }
+}
+
+/// The versions of a path a three-way merge left unmerged, index stages
+/// 1 (base), 2 (ours) and 3 (theirs). A side without the file has none.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ConflictStages {
+    pub base: Option<EncodedSha>,
+    pub ours: Option<EncodedSha>,
+    pub theirs: Option<EncodedSha>,
+    /// The file mode the stages are kept with
+    pub mode: FileMode,
+}
+
+impl ConflictStages {
+    /// How the versions conflict, e.g. both sides added the file
+    pub fn kind(&self) -> ConflictKind {
+        match (&self.base, &self.ours, &self.theirs) {
+            (_, None, _) => ConflictKind::DeletedByUs,
+            (_, _, None) => ConflictKind::DeletedByThem,
+            (None, _, _) => ConflictKind::AddAdd,
+            _ => ConflictKind::Content,
+        }
+    }
+
+    /// The stages with their number, skipping absent sides
+    fn entries(&self) -> impl Iterator<Item = (u16, &EncodedSha)> {
+        [(1, &self.base), (2, &self.ours), (3, &self.theirs)]
+            .into_iter()
+            .filter_map(|(stage, sha)| sha.as_ref().map(|sha| (stage, sha)))
+    }
+}
+
+/// An index loaded under its lock, `index.lock`, see [`Index::lock`].
+/// Dropping it without committing leaves the index file untouched.
+#[derive(Debug)]
+pub struct LockedIndex {
+    index: Index,
+    lock: LockFile,
+}
+
+impl LockedIndex {
+    /// Writes the changed index through the lock, replacing the index file
+    pub fn commit(mut self) -> Result<(), String> {
+        let content = self.index.encode()?;
+        self.lock.write_all(&content).map_err(|e| e.to_string())?;
+        self.lock.commit().map_err(|e| e.to_string())
+    }
+}
+
+impl Deref for LockedIndex {
+    type Target = Index;
+
+    fn deref(&self) -> &Index {
+        &self.index
+    }
+}
+
+impl DerefMut for LockedIndex {
+    fn deref_mut(&mut self) -> &mut Index {
+        &mut self.index
+    }
+}
 
 /// Represents a node in the file tree (either a directory or a file)
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct TreeNode {
     children: BTreeMap<String, TreeNode>,
     sha1: Option<EncodedSha>,
+    mode: FileMode,
+    stat: Option<FileStat>,
 }
 
 impl TreeNode {
@@ -17,24 +142,25 @@ impl TreeNode {
         TreeNode {
             children: BTreeMap::new(),
             sha1: None,
+            mode: FileMode::Directory,
+            stat: None,
         }
     }
 
     /// Create a new file node with SHA1
-    fn new_file(sha1: EncodedSha) -> Self {
+    fn new_file(sha1: EncodedSha, mode: FileMode) -> Self {
         TreeNode {
             children: BTreeMap::new(),
             sha1: Some(sha1),
+            mode,
+            stat: None,
         }
     }
     pub fn get_children(&self) -> &BTreeMap<String, TreeNode> {
         &self.children
     }
     pub fn is_file(&self) -> bool {
-        match self.sha1 {
-            None => false,
-            Some(_) => true,
-        }
+        self.sha1.is_some()
     }
     pub fn is_dir(&self) -> bool {
         !self.is_file()
@@ -42,13 +168,20 @@ impl TreeNode {
     pub fn get_sha1(&self) -> Option<&EncodedSha> {
         self.sha1.as_ref()
     }
+    pub fn get_mode(&self) -> FileMode {
+        self.mode
+    }
 }
 
 /// Represents a hierarchical index of tracked files
-#[derive(Debug)]
+///
+/// A path left unmerged has no regular entry in the tree; its stages are
+/// kept aside until the path is added or removed again.
+#[derive(Debug, Clone)]
 pub struct Index {
     root: TreeNode,
     size: u64,
+    conflicts: BTreeMap<String, ConflictStages>,
 }
 
 impl Index {
@@ -57,6 +190,7 @@ impl Index {
         Index {
             root: TreeNode::new_directory(),
             size: 0,
+            conflicts: BTreeMap::new(),
         }
     }
 
@@ -64,9 +198,23 @@ impl Index {
         &self.root
     }
 
-    /// Add/update a file entry with normalized path
+    /// Add/update a regular file entry with normalized path.
+    /// Any cached stat data of the entry is dropped.
     pub fn update_entry<P: AsRef<Path>>(&mut self, file_path: P, sha1: EncodedSha) {
+        self.update_entry_with_mode(file_path, sha1, FileMode::Regular);
+    }
+
+    /// Add/update a file entry with the given file mode.
+    /// Any cached stat data of the entry is dropped, and a conflict at the
+    /// path is resolved.
+    pub fn update_entry_with_mode<P: AsRef<Path>>(
+        &mut self,
+        file_path: P,
+        sha1: EncodedSha,
+        mode: FileMode,
+    ) {
         let normalized_path = Self::normalize_path(file_path);
+        self.conflicts.remove(&normalized_path);
         let file_path = Path::new(&normalized_path);
         let components = Self::split_path(file_path);
         if components.is_empty() {
@@ -82,18 +230,19 @@ impl Index {
         }
 
         let file_name = components.last().unwrap();
-        match current
+        if current
             .children
-            .insert(file_name.clone(), TreeNode::new_file(sha1))
+            .insert(file_name.clone(), TreeNode::new_file(sha1, mode))
+            .is_none()
         {
-            None => self.size += 1,
-            Some(_) => {}
+            self.size += 1;
         }
     }
 
-    /// Remove a file entry by path
+    /// Remove a file entry by path, resolving a conflict at the path
     pub fn remove_entry<P: AsRef<Path>>(&mut self, file_path: P) -> Option<EncodedSha> {
         let normalized_path = Self::normalize_path(file_path);
+        self.conflicts.remove(&normalized_path);
         let file_path = Path::new(&normalized_path);
         let components = Self::split_path(file_path);
         if components.is_empty() {
@@ -117,8 +266,117 @@ impl Index {
             })
     }
 
+    /// Remove a file entry, or every entry below a directory, by path.
+    /// An empty path removes everything. Directories left empty are removed
+    /// as well, and so are the conflicts of the removed paths.
+    ///
+    /// # Returns
+    /// The paths of the removed file entries, sorted
+    pub fn remove_subtree<P: AsRef<Path>>(&mut self, path: P) -> Vec<String> {
+        let normalized_path = Self::normalize_path(path);
+        let components = Self::split_path(Path::new(&normalized_path));
+        let prefix = format!("{normalized_path}/");
+        let unmerged: Vec<String> = self
+            .conflicts
+            .keys()
+            .filter(|path| {
+                normalized_path.is_empty() || **path == normalized_path || path.starts_with(&prefix)
+            })
+            .cloned()
+            .collect();
+        for path in &unmerged {
+            self.conflicts.remove(path);
+        }
+        let removed = match components.split_last() {
+            Some((last, parents)) => Self::take_node(&mut self.root, parents, last),
+            None => Some(std::mem::replace(&mut self.root, TreeNode::new_directory())),
+        };
+        let mut entries = Vec::new();
+        match removed {
+            Some(TreeNode {
+                sha1: Some(sha1), ..
+            }) => entries.push((components.join("/"), sha1)),
+            Some(removed) => Self::traverse_tree(&removed, &mut components.clone(), &mut entries),
+            None => {}
+        }
+        self.size -= entries.len() as u64;
+        let mut paths: Vec<String> = entries.into_iter().map(|(path, _)| path).collect();
+        if !unmerged.is_empty() {
+            paths.extend(unmerged);
+            paths.sort();
+        }
+        paths
+    }
+
+    /// Detaches the node `name` below the directory at `parents`, pruning
+    /// the directories that become empty on the way back up
+    fn take_node(node: &mut TreeNode, parents: &[String], name: &str) -> Option<TreeNode> {
+        let Some((first, rest)) = parents.split_first() else {
+            return node.children.remove(name);
+        };
+        let child = node.children.get_mut(first)?;
+        let removed = Self::take_node(child, rest, name);
+        if child.is_dir() && child.children.is_empty() {
+            node.children.remove(first);
+        }
+        removed
+    }
+
+    /// Marks `file_path` as unmerged with the given stages, replacing its
+    /// regular entry
+    pub fn add_conflict<P: AsRef<Path>>(&mut self, file_path: P, stages: ConflictStages) {
+        let normalized_path = Self::normalize_path(file_path);
+        self.remove_entry(&normalized_path);
+        self.conflicts.insert(normalized_path, stages);
+    }
+
+    /// The stages of an unmerged path
+    pub fn get_conflict<P: AsRef<Path>>(&self, file_path: P) -> Option<&ConflictStages> {
+        self.conflicts.get(&Self::normalize_path(file_path))
+    }
+
+    /// The unmerged paths with their stages, sorted by path
+    pub fn conflicts(&self) -> &BTreeMap<String, ConflictStages> {
+        &self.conflicts
+    }
+
     /// Get SHA1 by file path
     pub fn get_sha1<P: AsRef<Path>>(&self, file_path: P) -> Option<&EncodedSha> {
+        self.get_file(file_path).and_then(|node| node.sha1.as_ref())
+    }
+
+    /// Get the file mode of a file entry
+    pub fn get_mode<P: AsRef<Path>>(&self, file_path: P) -> Option<FileMode> {
+        self.get_file(file_path)
+            .filter(|node| node.is_file())
+            .map(|node| node.mode)
+    }
+
+    /// Get the cached stat data of a file entry
+    pub fn get_stat<P: AsRef<Path>>(&self, file_path: P) -> Option<FileStat> {
+        self.get_file(file_path).and_then(|node| node.stat)
+    }
+
+    /// Caches stat data for an existing file entry, leaving its SHA1 alone.
+    /// Returns false if there is no such entry.
+    pub fn set_stat<P: AsRef<Path>>(&mut self, file_path: P, stat: FileStat) -> bool {
+        let normalized_path = Self::normalize_path(file_path);
+        let components = Self::split_path(Path::new(&normalized_path));
+        let mut current = &mut self.root;
+        for component in &components {
+            match current.children.get_mut(component) {
+                Some(node) => current = node,
+                None => return false,
+            }
+        }
+        if !current.is_file() {
+            return false;
+        }
+        current.stat = Some(stat);
+        true
+    }
+
+    fn get_file<P: AsRef<Path>>(&self, file_path: P) -> Option<&TreeNode> {
         let normalized_path = Self::normalize_path(file_path);
         let file_path = Path::new(&normalized_path);
         let components = Self::split_path(file_path);
@@ -134,19 +392,25 @@ impl Index {
             }
         }
 
-        current
-            .children
-            .get(components.last().unwrap())
-            .and_then(|node| node.sha1.as_ref())
+        current.children.get(components.last().unwrap())
     }
 
     /// Load index from file
+    ///
+    /// Reads Git's binary index format (versions 2 and 3). Index files
+    /// written before that format was adopted, with one
+    /// `<path> <sha> [<mtime secs> <mtime nanos> <size>]` line per entry,
+    /// are still understood.
     pub fn load(index_path: &Path) -> Result<Self, String> {
         if !index_path.exists() {
             return Err(format!("Index file not found: {}", index_path.display()));
         }
 
-        let content = std::fs::read_to_string(index_path).map_err(|e| e.to_string())?;
+        let content = std::fs::read(index_path).map_err(|e| e.to_string())?;
+        if content.starts_with(INDEX_SIGNATURE) {
+            return Self::parse_binary(&content);
+        }
+        let content = String::from_utf8(content).map_err(|_| "Invalid index format")?;
 
         let mut index = Index::new();
         for line in content.lines() {
@@ -154,22 +418,163 @@ impl Index {
             if parts.len() != 2 {
                 return Err("Invalid index format".into());
             }
-            index.update_entry(parts[0], EncodedSha(parts[1].to_string()));
+            // `<sha>`, optionally followed by `<mtime secs> <mtime nanos> <size>`
+            let mut fields = parts[1].split(' ');
+            let sha = fields.next().unwrap_or_default();
+            index.update_entry(parts[0], EncodedSha(sha.to_string()));
+            let stat: Vec<&str> = fields.collect();
+            if let [secs, nanos, size] = stat[..] {
+                let stat = FileStat {
+                    mtime_secs: secs.parse().map_err(|_| "Invalid index format")?,
+                    mtime_nanos: nanos.parse().map_err(|_| "Invalid index format")?,
+                    size: size.parse().map_err(|_| "Invalid index format")?,
+                    ..FileStat::default()
+                };
+                index.set_stat(parts[0], stat);
+            } else if !stat.is_empty() {
+                return Err("Invalid index format".into());
+            }
         }
 
         Ok(index)
     }
 
-    /// Save index to file
+    /// Parses an index in Git's binary format: a `DIRC` header, the
+    /// entries sorted by path, optional extensions and a SHA1 checksum
+    fn parse_binary(content: &[u8]) -> Result<Self, String> {
+        if content.len() < 32 {
+            return Err("Invalid index format: file too short".into());
+        }
+        let (body, checksum) = content.split_at(content.len() - 20);
+        if Sha1::digest(body).as_slice() != checksum {
+            return Err("Invalid index format: checksum mismatch".into());
+        }
+
+        let mut reader = IndexReader { data: body, pos: 4 };
+        let version = reader.u32()?;
+        if version != 2 && version != 3 {
+            return Err(format!("Unsupported index version {version}"));
+        }
+        let count = reader.u32()?;
+
+        let mut index = Index::new();
+        for _ in 0..count {
+            let start = reader.pos;
+            let ctime_secs = reader.u32()?;
+            let ctime_nanos = reader.u32()?;
+            let mtime_secs = reader.u32()?;
+            let mtime_nanos = reader.u32()?;
+            let dev = reader.u32()?;
+            let ino = reader.u32()?;
+            let mode = reader.u32()?;
+            let uid = reader.u32()?;
+            let gid = reader.u32()?;
+            let size = reader.u32()?;
+            let sha1 = EncodedSha(hex::encode(reader.take(20)?));
+            let flags = reader.u16()?;
+            let stage = (flags >> 12) & 0x3;
+            // Version 3 entries may carry a second flags word
+            if flags & 0x4000 != 0 {
+                reader.u16()?;
+            }
+            let name_len = reader.data[reader.pos..]
+                .iter()
+                .position(|&byte| byte == 0)
+                .ok_or("Invalid index format: unterminated path")?;
+            let path = std::str::from_utf8(reader.take(name_len)?)
+                .map_err(|_| "Invalid index format: path is not UTF-8")?
+                .to_string();
+            // Entries are NUL-padded to a multiple of eight bytes
+            let entry_len = reader.pos - start;
+            reader.take(8 - entry_len % 8)?;
+
+            let mode = FileMode::parse(&format!("{mode:o}"))
+                .filter(|mode| *mode != FileMode::Directory)
+                .ok_or_else(|| format!("Unsupported mode {mode:o} for {path}"))?;
+            if stage != 0 {
+                let stages = index.conflicts.entry(path).or_default();
+                stages.mode = mode;
+                match stage {
+                    1 => stages.base = Some(sha1),
+                    2 => stages.ours = Some(sha1),
+                    _ => stages.theirs = Some(sha1),
+                }
+                continue;
+            }
+            index.update_entry_with_mode(&path, sha1, mode);
+            let stat = FileStat {
+                ctime_secs: ctime_secs.into(),
+                ctime_nanos,
+                mtime_secs: mtime_secs.into(),
+                mtime_nanos,
+                dev,
+                ino,
+                uid,
+                gid,
+                size: size.into(),
+            };
+            // Entries written without stat data have it zeroed
+            if stat != FileStat::default() {
+                index.set_stat(&path, stat);
+            }
+        }
+        // Extensions such as the cache tree are not needed and are dropped
+        Ok(index)
+    }
+
+    /// Save index to file in Git's binary index format, version 2
     pub fn save(&self, index_path: &Path) -> Result<(), String> {
-        let entries = self.collect_entries();
-        let content = entries
-            .into_iter()
-            .map(|(path, sha1)| format!("{} {}", path, sha1.0))
-            .collect::<Vec<_>>()
-            .join("\n");
+        lock::write_locked(index_path, self.encode()?).map_err(|e| e.to_string())
+    }
 
-        std::fs::write(index_path, content).map_err(|e| e.to_string())
+    /// Takes the lock of the index at `index_path`, then loads it, so that
+    /// nobody else changes it until the result is committed or dropped. A
+    /// missing index loads as empty.
+    pub fn lock(index_path: &Path) -> Result<LockedIndex, String> {
+        let lock = LockFile::acquire(index_path).map_err(|e| e.to_string())?;
+        let index = if index_path.exists() {
+            Index::load(index_path)?
+        } else {
+            Index::new()
+        };
+        Ok(LockedIndex { index, lock })
+    }
+
+    /// Encodes the index in Git's binary index format, version 2
+    fn encode(&self) -> Result<Vec<u8>, String> {
+        let entries = self.collect_stage_entries();
+        let mut content = INDEX_SIGNATURE.to_vec();
+        content.extend(INDEX_VERSION.to_be_bytes());
+        content.extend((entries.len() as u32).to_be_bytes());
+        for (path, mode, stage, sha1) in entries {
+            let stat = self.get_stat(&path).unwrap_or_default();
+            let mode = u32::from_str_radix(mode.as_str(), 8).unwrap();
+            let sha1 =
+                hex::decode(&sha1.0).map_err(|_| format!("Invalid SHA1 {} for {path}", sha1.0))?;
+            let start = content.len();
+            for field in [
+                stat.ctime_secs as u32,
+                stat.ctime_nanos,
+                stat.mtime_secs as u32,
+                stat.mtime_nanos,
+                stat.dev,
+                stat.ino,
+                mode,
+                stat.uid,
+                stat.gid,
+                stat.size as u32,
+            ] {
+                content.extend(field.to_be_bytes());
+            }
+            content.extend(sha1);
+            content.extend((stage << 12 | path.len().min(0xfff) as u16).to_be_bytes());
+            content.extend(path.as_bytes());
+            let entry_len = content.len() - start;
+            content.resize(content.len() + 8 - entry_len % 8, 0);
+        }
+        let checksum = Sha1::digest(&content);
+        content.extend(checksum);
+        Ok(content)
     }
 
     /// Collect all entries as (path, SHA1) pairs
@@ -179,6 +584,81 @@ impl Index {
         entries
     }
 
+    /// Collect all entries as (path, file mode, stage, SHA1), with the
+    /// stages 1 to 3 of unmerged paths and stage 0 for the others
+    ///
+    /// Entries are in the order Git keeps them: by their full path, so
+    /// "a.txt" precedes "a/b", and the stages of a path by number.
+    pub fn collect_stage_entries(&self) -> Vec<(String, FileMode, u16, EncodedSha)> {
+        let mut entries: Vec<_> = self
+            .collect_entries_with_mode()
+            .into_iter()
+            .map(|(path, mode, sha1)| (path, mode, 0, sha1))
+            .collect();
+        for (path, stages) in &self.conflicts {
+            for (stage, sha1) in stages.entries() {
+                entries.push((path.clone(), stages.mode, stage, sha1.clone()));
+            }
+        }
+        entries.sort_by(|(a, _, a_stage, _), (b, _, b_stage, _)| {
+            a.as_bytes().cmp(b.as_bytes()).then(a_stage.cmp(b_stage))
+        });
+        entries
+    }
+
+    /// Collect all entries as (path, file mode, SHA1), sorted by path
+    pub fn collect_entries_with_mode(&self) -> Vec<(String, FileMode, EncodedSha)> {
+        self.collect_entries()
+            .into_iter()
+            .map(|(path, sha1)| {
+                let mode = self.get_mode(&path).unwrap_or_default();
+                (path, mode, sha1)
+            })
+            .collect()
+    }
+
+    /// Paths of the entries with no file left under `worktree`, sorted.
+    /// A submodule counts as deleted when its directory is gone.
+    pub fn deleted_entries(&self, worktree: &Path) -> Vec<String> {
+        self.collect_entries_with_mode()
+            .into_iter()
+            .filter(|(path, mode, _)| {
+                let file = worktree.join(path);
+                match mode {
+                    FileMode::Gitlink => !file.is_dir(),
+                    FileMode::Symlink => fs::symlink_metadata(&file).is_err(),
+                    _ => !file.is_file(),
+                }
+            })
+            .map(|(path, _, _)| path)
+            .collect()
+    }
+
+    /// Paths of the entries whose file under `worktree` no longer has the
+    /// staged content, deleted files included, sorted. Files whose cached
+    /// stat data still matches are not read. Submodules change by commits
+    /// the index cannot see and are left out.
+    pub fn modified_entries(&self, worktree: &Path) -> Result<Vec<String>, String> {
+        let mut modified = Vec::new();
+        for (path, mode, sha1) in self.collect_entries_with_mode() {
+            if mode == FileMode::Gitlink {
+                continue;
+            }
+            let file = worktree.join(&path);
+            let Ok(metadata) = fs::metadata(&file) else {
+                modified.push(path);
+                continue;
+            };
+            if self.get_stat(&path) == Some(FileStat::from_metadata(&metadata)) {
+                continue;
+            }
+            if !metadata.is_file() || Blob::new(&file)?.encoded_sha1() != sha1.0 {
+                modified.push(path);
+            }
+        }
+        Ok(modified)
+    }
+
     /// Recursive tree traversal to collect entries
     fn traverse_tree(
         node: &TreeNode,
@@ -209,14 +689,12 @@ impl Index {
         let normalized_path = Path::new(&path_str);
 
         for component in normalized_path.components() {
-            match component {
-                Component::Normal(s) => {
-                    if !normalized.is_empty() {
-                        normalized.push('/');
-                    }
-                    normalized.push_str(s.to_str().unwrap());
+            // Ignore special components such as root directory
+            if let Component::Normal(s) = component {
+                if !normalized.is_empty() {
+                    normalized.push('/');
                 }
-                _ => {} // Ignore special components such as root directory
+                normalized.push_str(s.to_str().unwrap());
             }
         }
 
@@ -232,9 +710,7 @@ impl Index {
                     components.push(name.to_string_lossy().into_owned());
                 }
                 Component::ParentDir => {
-                    if !components.is_empty() {
-                        components.pop();
-                    }
+                    components.pop();
                 }
                 Component::CurDir => {}
                 _ => {} // 其他组件（如根目录）在相对路径中忽略
@@ -245,6 +721,31 @@ impl Index {
     }
 }
 
+/// Cursor over the big-endian fields of a binary index
+struct IndexReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> IndexReader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + len)
+            .ok_or("Invalid index format: truncated entry")?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        Ok(u16::from_be_bytes(self.take(2)?.try_into().unwrap()))
+    }
+}
+
 impl fmt::Display for Index {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let entries = self.collect_entries();
@@ -325,6 +826,32 @@ mod tests {
                 .as_ref()
         );
     }
+
+    #[test]
+    fn test_remove_subtree() {
+        let mut index = Index::new();
+        let sha = EncodedSha::from_str("abcd1234abcd1234abcd1234abcd1234abcd1234").unwrap();
+        for path in [
+            "src/main.rs",
+            "src/repo/log.rs",
+            "src/repo/diff.rs",
+            "README.md",
+        ] {
+            index.update_entry(path, sha.clone());
+        }
+
+        assert_eq!(
+            index.remove_subtree("src/repo"),
+            vec!["src/repo/diff.rs", "src/repo/log.rs"]
+        );
+        assert!(index.get_sha1("src/main.rs").is_some());
+        assert_eq!(index.remove_subtree("src/main.rs"), vec!["src/main.rs"]);
+        // The emptied directory goes too
+        assert!(!index.get_root().get_children().contains_key("src"));
+        assert!(index.remove_subtree("src").is_empty());
+        assert_eq!(index.remove_subtree(""), vec!["README.md"]);
+        assert!(index.collect_entries().is_empty());
+    }
     use std::{io::Write, str::FromStr};
     use tempfile::NamedTempFile;
 
@@ -373,21 +900,123 @@ mod tests {
     fn test_save_normal_entries() {
         let mut index = Index::new();
         index.update_entry(
-            "a.txt".to_string(),
+            "a.txt",
             EncodedSha::from_str("abcde12345abcde12345abcde12345abcde12345").unwrap(),
         );
         index.update_entry(
-            "b/c.txt".to_string(),
+            "b/c.txt",
             EncodedSha::from_str("0123456789012345678901234567890123456789").unwrap(),
         );
 
         let file = NamedTempFile::new().unwrap();
         index.save(file.path()).unwrap();
 
-        let content = std::fs::read_to_string(file.path()).unwrap();
-        println!("{}", content);
-        assert!(content.contains("a.txt abcde12345abcde12345abcde12345abcde12345"));
-        assert!(content.contains("b/c.txt 0123456789012345678901234567890123456789"));
+        let content = std::fs::read(file.path()).unwrap();
+        assert!(content.starts_with(b"DIRC\0\0\0\x02\0\0\0\x02"));
+        let loaded = Index::load(file.path()).unwrap();
+        assert_eq!(loaded.collect_entries(), index.collect_entries());
+    }
+
+    #[test]
+    fn binary_index_keeps_modes_and_git_order() {
+        let mut index = Index::new();
+        let sha = EncodedSha::from_str("abcde12345abcde12345abcde12345abcde12345").unwrap();
+        index.update_entry("a/b", sha.clone());
+        index.update_entry_with_mode("a.txt", sha.clone(), FileMode::Executable);
+        let file = NamedTempFile::new().unwrap();
+        index.save(file.path()).unwrap();
+
+        // "a.txt" sorts before "a/b" by full path
+        let content = std::fs::read(file.path()).unwrap();
+        assert_eq!(&content[12 + 62..12 + 62 + 5], b"a.txt");
+        let loaded = Index::load(file.path()).unwrap();
+        assert_eq!(loaded.get_mode("a.txt"), Some(FileMode::Executable));
+        assert_eq!(loaded.get_mode("a/b"), Some(FileMode::Regular));
+
+        // A corrupted index fails its checksum
+        let mut corrupt = content.clone();
+        corrupt[20] ^= 1;
+        std::fs::write(file.path(), corrupt).unwrap();
+        assert!(Index::load(file.path()).is_err());
+    }
+
+    #[test]
+    fn stat_data_round_trips_and_is_dropped_on_update() {
+        let mut index = Index::new();
+        let sha = EncodedSha::from_str("abcde12345abcde12345abcde12345abcde12345").unwrap();
+        index.update_entry("a.txt", sha.clone());
+        index.update_entry("b.txt", sha.clone());
+        let stat = FileStat {
+            mtime_secs: 1700000000,
+            mtime_nanos: 42,
+            size: 5,
+            ..FileStat::default()
+        };
+        assert!(index.set_stat("a.txt", stat));
+        assert!(!index.set_stat("missing.txt", stat));
+
+        let file = NamedTempFile::new().unwrap();
+        index.save(file.path()).unwrap();
+        let mut loaded = Index::load(file.path()).unwrap();
+        assert_eq!(loaded.get_stat("a.txt"), Some(stat));
+        assert_eq!(loaded.get_stat("b.txt"), None);
+        assert_eq!(loaded.get_sha1("a.txt"), Some(&sha));
+
+        loaded.update_entry("a.txt", sha);
+        assert_eq!(loaded.get_stat("a.txt"), None);
+    }
+
+    #[test]
+    fn conflict_stages_round_trip_and_resolve_on_update() {
+        let sha = |c: char| EncodedSha(c.to_string().repeat(40));
+        let mut index = Index::new();
+        index.update_entry("a.txt", sha('a'));
+        index.update_entry("b.txt", sha('b'));
+        let stages = ConflictStages {
+            base: None,
+            ours: Some(sha('c')),
+            theirs: Some(sha('d')),
+            mode: FileMode::Executable,
+        };
+        index.add_conflict("a.txt", stages.clone());
+        assert_eq!(index.get_sha1("a.txt"), None);
+        assert_eq!(stages.kind(), ConflictKind::AddAdd);
+
+        let file = NamedTempFile::new().unwrap();
+        index.save(file.path()).unwrap();
+        // Stage 2 of "a.txt" comes first, flagged in bits 12-13
+        let content = std::fs::read(file.path()).unwrap();
+        assert_eq!(&content[12 + 60..12 + 62], &[0x20, 5]);
+        let mut loaded = Index::load(file.path()).unwrap();
+        assert_eq!(loaded.get_conflict("a.txt"), Some(&stages));
+        assert_eq!(loaded.get_sha1("b.txt"), Some(&sha('b')));
+
+        loaded.update_entry("a.txt", sha('e'));
+        assert!(loaded.conflicts().is_empty());
+        assert_eq!(loaded.get_sha1("a.txt"), Some(&sha('e')));
+    }
+
+    #[test]
+    fn locked_index_keeps_others_out_until_committed() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("index");
+        let sha = EncodedSha("a".repeat(40));
+
+        let mut locked = Index::lock(&path).unwrap();
+        assert!(locked.collect_entries().is_empty());
+        locked.update_entry("a.txt", sha.clone());
+        // Nobody else can write the index meanwhile
+        assert!(Index::new().save(&path).is_err());
+        assert!(!path.exists());
+        locked.commit().unwrap();
+
+        assert_eq!(Index::load(&path).unwrap().get_sha1("a.txt"), Some(&sha));
+        assert!(!temp_dir.path().join("index.lock").exists());
+        // Dropping the lock leaves the index as it was
+        let mut locked = Index::lock(&path).unwrap();
+        locked.remove_entry("a.txt");
+        drop(locked);
+        assert_eq!(Index::load(&path).unwrap().get_sha1("a.txt"), Some(&sha));
     }
 
     /// Test saving empty index
@@ -397,8 +1026,38 @@ mod tests {
         let file = NamedTempFile::new().unwrap();
 
         index.save(file.path()).unwrap();
-        let content = std::fs::read_to_string(file.path()).unwrap();
-        assert!(content.is_empty());
+        // A header and checksum only
+        assert_eq!(std::fs::read(file.path()).unwrap().len(), 32);
+        assert!(
+            Index::load(file.path())
+                .unwrap()
+                .collect_entries()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn worktree_queries_find_modified_and_deleted_entries() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut index = Index::new();
+        for (name, content) in [("kept.txt", "same\n"), ("edited.txt", "old\n")] {
+            std::fs::write(dir.path().join(name), content).unwrap();
+            let blob = Blob::new(dir.path().join(name)).unwrap();
+            index.update_entry(name, EncodedSha(blob.encoded_sha1()));
+        }
+        index.update_entry_with_mode(
+            "gone/file",
+            EncodedSha("a".repeat(40)),
+            FileMode::Executable,
+        );
+        std::fs::write(dir.path().join("edited.txt"), "new\n").unwrap();
+
+        assert_eq!(index.collect_entries_with_mode()[1].1, FileMode::Executable);
+        assert_eq!(index.deleted_entries(dir.path()), ["gone/file"]);
+        assert_eq!(
+            index.modified_entries(dir.path()).unwrap(),
+            ["edited.txt", "gone/file"]
+        );
     }
 }
 #[cfg(test)]
@@ -1,19 +1,29 @@
 use std::str::FromStr;
 
+pub use config::Config;
+pub use crypt::ObjectCipher;
+pub use error::Error;
+pub use ignore::IgnoreMatch;
+pub use object::{Author, Commit, FileMode, ObjectType};
 pub use repo::Repository;
+mod config;
+mod crypt;
+pub mod diff;
+mod error;
+mod git_source;
+mod ignore;
 mod index;
+mod lock;
 mod object;
+mod patch;
 pub mod repo;
-#[derive(Debug, Clone, PartialEq, Eq)]
-struct EncodedSha(String);
-impl EncodedSha {
-    fn from_string(string: String) -> EncodedSha {
-        EncodedSha(string)
-    }
-}
+mod smart_http;
+/// Hex-encoded SHA1 object id, e.g. `a906cb2a4a904a152e80877d4088654daad0c859`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct EncodedSha(String);
 impl AsRef<EncodedSha> for EncodedSha {
     fn as_ref(&self) -> &EncodedSha {
-        &self
+        self
     }
 }
 impl FromStr for EncodedSha {
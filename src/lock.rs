@@ -0,0 +1,295 @@
+use chrono::{DateTime, Local};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Suffix appended to a file name to form its lock file, e.g. `HEAD.lock`
+pub const LOCK_SUFFIX: &str = ".lock";
+
+/// How long to keep retrying when another process holds the lock
+const LOCK_TIMEOUT: Duration = Duration::from_millis(1000);
+
+/// First retry delay; doubled after every failed attempt up to `MAX_BACKOFF`
+const INITIAL_BACKOFF: Duration = Duration::from_millis(1);
+const MAX_BACKOFF: Duration = Duration::from_millis(100);
+
+/// An exclusive lock on a file, held through `<file>.lock`.
+///
+/// While held, the lock file records its owner (see `LockOwner`) so a lock
+/// left behind by a crashed process can be told apart from a live one.
+/// It doubles as the staging area for the new content: `commit` replaces
+/// the owner record with the data and renames the file over the target, so
+/// readers only ever see the old or the new file. Dropping an uncommitted
+/// lock removes the lock file and leaves the target untouched.
+#[derive(Debug)]
+pub struct LockFile {
+    path: PathBuf,
+    lock_path: PathBuf,
+    file: Option<File>,
+    content: Vec<u8>,
+}
+
+impl LockFile {
+    /// Takes the lock for `path`, retrying with exponential backoff while
+    /// another process holds it
+    ///
+    /// # Returns
+    /// - `Ok(LockFile)`: The lock is held until committed or dropped
+    /// - `Err(io::Error)`: The lock could not be taken before the timeout
+    pub fn acquire(path: &Path) -> io::Result<LockFile> {
+        let mut lock_name = path.file_name().unwrap_or_default().to_os_string();
+        lock_name.push(LOCK_SUFFIX);
+        let lock_path = path.with_file_name(lock_name);
+
+        let start = Instant::now();
+        let mut backoff = INITIAL_BACKOFF;
+        loop {
+            match OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&lock_path)
+            {
+                Ok(file) => {
+                    let mut lock = LockFile {
+                        path: path.to_path_buf(),
+                        lock_path,
+                        file: Some(file),
+                        content: Vec::new(),
+                    };
+                    // Dropping `lock` on failure removes the half-written file
+                    let owner = LockOwner::current().to_string();
+                    lock.file.as_mut().unwrap().write_all(owner.as_bytes())?;
+                    return Ok(lock);
+                }
+                Err(why) if why.kind() == io::ErrorKind::AlreadyExists => {
+                    if start.elapsed() >= LOCK_TIMEOUT {
+                        return Err(io::Error::new(
+                            io::ErrorKind::WouldBlock,
+                            held_lock_message(&lock_path),
+                        ));
+                    }
+                    thread::sleep(backoff);
+                    backoff = std::cmp::min(backoff * 2, MAX_BACKOFF);
+                }
+                Err(why) => return Err(why),
+            }
+        }
+    }
+
+    /// Appends data to the new content of the locked file
+    pub fn write_all(&mut self, data: &[u8]) -> io::Result<()> {
+        self.content.extend_from_slice(data);
+        Ok(())
+    }
+
+    /// Flushes the new content to disk and atomically replaces the target
+    pub fn commit(mut self) -> io::Result<()> {
+        let mut file = self.file.take().unwrap();
+        file.set_len(0)?;
+        file.rewind()?;
+        file.write_all(&self.content)?;
+        file.sync_all()?;
+        drop(file);
+        fs::rename(&self.lock_path, &self.path)?;
+        // Nothing left for `drop` to clean up
+        self.lock_path = PathBuf::new();
+        Ok(())
+    }
+}
+
+impl Drop for LockFile {
+    fn drop(&mut self) {
+        if !self.lock_path.as_os_str().is_empty() {
+            let _ = fs::remove_file(&self.lock_path);
+        }
+    }
+}
+
+/// The process holding a lock, as recorded in the lock file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockOwner {
+    pub pid: u32,
+    /// Seconds since the Unix epoch at which the lock was taken
+    pub timestamp: u64,
+}
+
+impl LockOwner {
+    fn current() -> LockOwner {
+        LockOwner {
+            pid: std::process::id(),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Reads the owner record of a lock file
+    ///
+    /// # Returns
+    /// - `Some(LockOwner)`: The lock is held by this process
+    /// - `None`: The file is gone or holds no owner record, e.g. because it
+    ///   was written by an older version or is being committed
+    pub fn read(lock_path: &Path) -> Option<LockOwner> {
+        let content = fs::read_to_string(lock_path).ok()?;
+        let record = content.strip_prefix("pid ")?;
+        let (pid, timestamp) = record.trim_end().split_once(" time ")?;
+        Some(LockOwner {
+            pid: pid.parse().ok()?,
+            timestamp: timestamp.parse().ok()?,
+        })
+    }
+
+    /// Whether the owning process is still running on this machine
+    pub fn is_alive(&self) -> bool {
+        if self.pid == std::process::id() {
+            return true;
+        }
+        let proc_dir = Path::new("/proc");
+        if proc_dir.is_dir() {
+            return proc_dir.join(self.pid.to_string()).exists();
+        }
+        // Without procfs, ask the system; assume alive if we cannot tell
+        std::process::Command::new("kill")
+            .args(["-0", &self.pid.to_string()])
+            .stderr(std::process::Stdio::null())
+            .status()
+            .map(|status| status.success())
+            .unwrap_or(true)
+    }
+
+    /// When the lock was taken, in local time
+    pub fn taken_at(&self) -> String {
+        DateTime::from_timestamp(self.timestamp as i64, 0)
+            .map(|time| {
+                time.with_timezone(&Local)
+                    .format("%Y-%m-%d %H:%M:%S")
+                    .to_string()
+            })
+            .unwrap_or_else(|| self.timestamp.to_string())
+    }
+}
+
+impl std::fmt::Display for LockOwner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "pid {} time {}", self.pid, self.timestamp)
+    }
+}
+
+/// Explains who holds the lock at `lock_path` and what to do about it
+fn held_lock_message(lock_path: &Path) -> String {
+    let prefix = format!("Unable to create '{}': File exists.", lock_path.display());
+    match LockOwner::read(lock_path) {
+        Some(owner) if owner.is_alive() => format!(
+            "{prefix} It is held by process {} since {}",
+            owner.pid,
+            owner.taken_at()
+        ),
+        Some(owner) => format!(
+            "{prefix} It was left by process {}, which is no longer running; \
+             run 'repair --stale-locks' to remove it",
+            owner.pid
+        ),
+        None => format!("{prefix} Another process seems to be running in this repository"),
+    }
+}
+
+/// Replaces the content of `path` under its lock, so concurrent writers are
+/// serialized and readers never observe a partially written file
+pub fn write_locked<C: AsRef<[u8]>>(path: &Path, content: C) -> io::Result<()> {
+    let mut lock = LockFile::acquire(path)?;
+    lock.write_all(content.as_ref())?;
+    lock.commit()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn commit_replaces_target() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("HEAD");
+        fs::write(&path, "old").unwrap();
+
+        let mut lock = LockFile::acquire(&path).unwrap();
+        lock.write_all(b"new").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "old");
+        lock.commit().unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        assert!(!temp_dir.path().join("HEAD.lock").exists());
+    }
+
+    #[test]
+    fn held_lock_blocks_and_drop_releases() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("master");
+
+        let lock = LockFile::acquire(&path).unwrap();
+        let err = write_locked(&path, "sha").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+        assert!(err.to_string().contains("held by process"));
+        assert!(!path.exists());
+
+        drop(lock);
+        assert!(!temp_dir.path().join("master.lock").exists());
+        write_locked(&path, "sha").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "sha");
+    }
+
+    #[test]
+    fn concurrent_writers_are_serialized() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("counter");
+        fs::write(&path, "0").unwrap();
+
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let path = path.clone();
+                thread::spawn(move || {
+                    for _ in 0..10 {
+                        let mut lock = LockFile::acquire(&path).unwrap();
+                        let value: u32 = fs::read_to_string(&path).unwrap().parse().unwrap();
+                        lock.write_all((value + 1).to_string().as_bytes()).unwrap();
+                        lock.commit().unwrap();
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(fs::read_to_string(&path).unwrap(), "40");
+    }
+
+    #[test]
+    fn lock_file_records_its_owner() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("index");
+        let lock_path = temp_dir.path().join("index.lock");
+
+        let mut lock = LockFile::acquire(&path).unwrap();
+        let owner = LockOwner::read(&lock_path).unwrap();
+        assert_eq!(owner.pid, std::process::id());
+        assert!(owner.is_alive());
+
+        // The owner record never leaks into the committed content
+        lock.write_all(b"entries").unwrap();
+        lock.commit().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "entries");
+
+        let dead = LockOwner {
+            pid: u32::MAX,
+            timestamp: 0,
+        };
+        fs::write(&lock_path, dead.to_string()).unwrap();
+        assert_eq!(LockOwner::read(&lock_path), Some(dead.clone()));
+        assert!(!dead.is_alive());
+        let err = LockFile::acquire(&path).unwrap_err();
+        assert!(err.to_string().contains("no longer running"));
+    }
+}
@@ -1,6 +1,15 @@
 use clap::{Parser, Subcommand};
-use rust_git::Repository;
-use std::{env::current_dir, path::{Path, PathBuf}};
+use rust_git::repo::{
+    ApplyOptions, CatFileMode, CommitOptions, CopyDetection, DiffAlgorithm, DiffOptions,
+    FastForward, GrepOptions, LogOptions, LsFilesOptions, LsTreeOptions, PrettyFormat, ResetMode,
+    SignatureStatus, Snapshot, hash_blob, merge_text, parse_expire,
+};
+use rust_git::{Config, Error, ObjectCipher, Repository};
+use std::{
+    env::current_dir,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+};
 
 #[derive(Parser)]
 #[command(name = "rust-git")]
@@ -16,9 +25,25 @@ struct Args {
 enum Command {
     /// Commit changes to repository
     Commit {
-        /// Commit message
-        #[clap(short = 'm', long = "message", required = true)]
-        message: String,
+        /// Commit message; may be left out when concluding a conflicted revert
+        #[clap(short = 'm', long = "message")]
+        message: Option<String>,
+
+        /// Replace the last commit instead of adding a new one
+        #[clap(long = "amend")]
+        amend: bool,
+
+        /// Sign the commit, with the key and format of user.signingKey and gpg.format
+        #[clap(short = 'S', long = "gpg-sign", conflicts_with = "no_gpg_sign")]
+        gpg_sign: bool,
+
+        /// Do not sign the commit, even with commit.gpgSign set
+        #[clap(long = "no-gpg-sign")]
+        no_gpg_sign: bool,
+
+        /// Only commit staged changes to these paths
+        #[clap(last = true)]
+        paths: Vec<String>,
     },
 
     /// Add files to staging area
@@ -28,9 +53,28 @@ enum Command {
         paths: Vec<String>,
     },
     /// Initialize a new repository
-    Init,
+    Init {
+        /// Encrypt objects at rest with the key in RUST_GIT_KEY
+        #[clap(long = "encrypt")]
+        encrypt: bool,
+
+        /// Name of the first branch, instead of init.defaultBranch
+        #[clap(short = 'b', long = "initial-branch", value_name = "NAME")]
+        initial_branch: Option<String>,
+    },
+    /// Clone a local repository into a new directory
+    Clone {
+        /// Path of the repository to clone
+        source: String,
+        /// Directory to clone into, named after the source by default
+        directory: Option<String>,
+    },
     /// Print the status
-    Status,
+    Status {
+        /// Limit the output to these paths
+        #[clap(last = true)]
+        paths: Vec<String>,
+    },
     /// Manage branches
     Branch {
         /// Name of the branch
@@ -43,99 +87,1936 @@ enum Command {
     },
     /// Switch branches or restore working tree files
     Checkout {
-        /// Target branch/commit to checkout
-        #[clap(value_name = "TARGET", required = true)]
-        target: String,
+        /// Target branch/commit to checkout, or the commit to restore paths from
+        #[clap(value_name = "TARGET", required_unless_present = "paths")]
+        target: Option<String>,
+
+        /// Commit to restore paths from, instead of the index
+        #[clap(long = "source")]
+        source: Option<String>,
+
+        /// Restore these paths without switching branches
+        #[clap(last = true)]
+        paths: Vec<String>,
 
         /// Create a branch
         #[clap(short = 'b')]
         create: bool,
+
+        /// Stash local changes before switching and reapply them afterwards
+        #[clap(long = "autostash")]
+        autostash: bool,
+    },
+    /// Restore working tree files or index entries
+    Restore {
+        /// Paths to restore, relative to the repository root
+        #[clap(value_name = "PATH", required = true)]
+        paths: Vec<String>,
+
+        /// Commit to restore from; defaults to HEAD for --staged and to
+        /// the index otherwise
+        #[clap(short = 's', long = "source")]
+        source: Option<String>,
+
+        /// Restore the index
+        #[clap(short = 'S', long = "staged")]
+        staged: bool,
+
+        /// Restore the working tree; the default without --staged
+        #[clap(short = 'W', long = "worktree")]
+        worktree: bool,
+    },
+    /// Switch branches
+    Switch {
+        /// Branch to switch to, or the start point of the branch to create
+        #[clap(value_name = "BRANCH", required_unless_present = "create")]
+        target: Option<String>,
+
+        /// Create a branch with this name and switch to it
+        #[clap(short = 'c', long = "create", value_name = "NEW_BRANCH")]
+        create: Option<String>,
+
+        /// Detach HEAD at the given commit
+        #[clap(long = "detach", conflicts_with = "create")]
+        detach: bool,
     },
     /// Merge another branch into current branch
     Merge {
         /// Branch name to merge
-        #[clap(value_name = "BRANCH", required = true)]
-        branch: String,
+        #[clap(value_name = "BRANCH", required_unless_present = "abort")]
+        branch: Option<String>,
+
+        /// Stash local changes before merging and reapply them afterwards
+        #[clap(long = "autostash")]
+        autostash: bool,
+
+        /// Refuse to merge unless the branch can be fast-forwarded
+        #[clap(long = "ff-only", conflicts_with = "no_ff")]
+        ff_only: bool,
+
+        /// Create a merge commit even when a fast-forward is possible
+        #[clap(long = "no-ff")]
+        no_ff: bool,
+
+        /// Give up a merge stopped at conflicts
+        #[clap(
+            long = "abort",
+            conflicts_with_all = ["branch", "autostash", "ff_only", "no_ff"]
+        )]
+        abort: bool,
+    },
+    /// Apply the changes introduced by existing commits
+    CherryPick {
+        /// Commits or `A..B` ranges to pick
+        #[clap(value_name = "COMMIT", required_unless_present_any = ["cont", "abort"])]
+        commits: Vec<String>,
+
+        /// Continue after resolving the conflicts of a stopped pick
+        #[clap(long = "continue", conflicts_with_all = ["commits", "abort"])]
+        cont: bool,
+
+        /// Give up and return to where the cherry-pick started
+        #[clap(long = "abort", conflicts_with = "commits")]
+        abort: bool,
+    },
+    /// Create a commit undoing the changes of an earlier one
+    Revert {
+        /// Commit to revert
+        #[clap(value_name = "COMMIT", required = true)]
+        commit: String,
+    },
+    /// Merge two branches without touching the index or working tree
+    MergeTree {
+        /// Our side of the merge
+        #[clap(value_name = "BRANCH1", required = true)]
+        ours: String,
+
+        /// Their side of the merge
+        #[clap(value_name = "BRANCH2", required = true)]
+        theirs: String,
+
+        /// Use this commit as the merge base instead of the common ancestor
+        #[clap(long = "merge-base", value_name = "COMMIT")]
+        merge_base: Option<String>,
+    },
+    /// Three-way merge a file, writing the result into the current version
+    MergeFile {
+        /// Our version, which receives the result
+        #[clap(value_name = "CURRENT", required = true)]
+        current: PathBuf,
+
+        /// The version both sides started from
+        #[clap(value_name = "BASE", required = true)]
+        base: PathBuf,
+
+        /// Their version
+        #[clap(value_name = "OTHER", required = true)]
+        other: PathBuf,
+
+        /// Print the result instead of overwriting the current file
+        #[clap(short = 'p', long = "stdout")]
+        stdout: bool,
+
+        /// Labels for the current, base and other versions in conflict
+        /// markers; the file names by default
+        #[clap(short = 'L', value_name = "LABEL", num_args = 1)]
+        labels: Vec<String>,
+    },
+    /// Resolve conflicts with an external merge tool
+    Mergetool {
+        /// Tool to run instead of the one set in merge.tool
+        #[clap(short = 't', long = "tool", value_name = "TOOL")]
+        tool: Option<String>,
+
+        /// Only resolve these paths
+        #[clap(value_name = "PATH")]
+        paths: Vec<String>,
+    },
+    /// Reuse recorded resolutions of conflicts; records the resolutions of
+    /// the current conflicts without a subcommand
+    Rerere {
+        #[clap(subcommand)]
+        action: Option<RerereCommand>,
     },
     /// Remove a file
     Rm {
         /// Paths to files/directories to remove
         #[clap(required = true)]
         paths: Vec<String>,
-    }
+        /// Only remove from the index, keeping the working tree files
+        #[clap(long = "cached")]
+        cached: bool,
+        /// Remove directories and the tracked files below them
+        #[clap(short = 'r')]
+        recursive: bool,
+    },
+    /// Move or rename a tracked file or directory
+    Mv {
+        /// File or directory to move
+        #[clap(value_name = "SOURCE", required = true)]
+        src: String,
+
+        /// New path, or an existing directory to move into
+        #[clap(value_name = "DESTINATION", required = true)]
+        dst: String,
+    },
+    /// Show information about files in the index and the working tree
+    LsFiles {
+        /// Show the mode, SHA1 and stage of index entries
+        #[clap(short = 's', long = "stage")]
+        stage: bool,
+
+        /// Show untracked files that are not ignored
+        #[clap(short = 'o', long = "others")]
+        others: bool,
+
+        /// Show tracked files that were modified or deleted
+        #[clap(short = 'm', long = "modified")]
+        modified: bool,
+
+        /// Show tracked files that were deleted
+        #[clap(short = 'd', long = "deleted")]
+        deleted: bool,
+    },
+    /// Print the type, size or content of an object
+    CatFile {
+        /// Object to read: a SHA1, a revision or a tag
+        #[clap(value_name = "OBJECT", required = true)]
+        object: String,
+
+        /// Print the object type
+        #[clap(short = 't', conflicts_with_all = ["size", "pretty"])]
+        object_type: bool,
+
+        /// Print the size of the content
+        #[clap(short = 's', conflicts_with = "pretty")]
+        size: bool,
+
+        /// Pretty-print the content
+        #[clap(short = 'p', required_unless_present_any = ["object_type", "size"])]
+        pretty: bool,
+    },
+    /// Print the commit SHA1 that revisions such as `HEAD~2` or `@{-1}` name
+    RevParse {
+        /// Revisions to resolve; a range prints the commits it includes and
+        /// then those it excludes as `^<sha>`
+        #[clap(value_name = "REV", required = true)]
+        revs: Vec<String>,
+    },
+    /// List commits reachable from some revisions but not others
+    RevList {
+        /// Revisions to walk from; `^<rev>` excludes, `<a>..<b>` is
+        /// `^<a> <b>` and `<a>...<b>` what either reaches but not both
+        #[clap(value_name = "REV", required = true)]
+        revs: Vec<String>,
+
+        /// Only print how many commits there are
+        #[clap(long = "count")]
+        count: bool,
+
+        /// Stop after this many commits
+        #[clap(short = 'n', long = "max-count", value_name = "N")]
+        max_count: Option<usize>,
+    },
+    /// Update the value a ref holds, checking its old value first
+    UpdateRef {
+        /// The ref to update: HEAD or a full name such as refs/heads/main
+        #[clap(value_name = "REF")]
+        name: String,
+
+        /// The new value, or with -d the value the ref must hold
+        #[clap(value_name = "NEWVALUE", required_unless_present = "delete")]
+        new_value: Option<String>,
+
+        /// The value the ref must hold for the update to happen; all zeros
+        /// if it must not exist yet
+        #[clap(value_name = "OLDVALUE")]
+        old_value: Option<String>,
+
+        /// Delete the ref instead
+        #[clap(short = 'd')]
+        delete: bool,
+    },
+    /// Read or point a symbolic ref such as HEAD
+    SymbolicRef {
+        /// The symbolic ref: HEAD or a full name such as refs/remotes/origin/HEAD
+        #[clap(value_name = "NAME")]
+        name: String,
+
+        /// The ref to point it at, e.g. refs/heads/main; prints where it
+        /// points when omitted
+        #[clap(value_name = "REF")]
+        target: Option<String>,
+
+        /// Reason recorded in the reflog
+        #[clap(short = 'm', value_name = "REASON")]
+        reason: Option<String>,
+
+        /// Print the target without its refs/heads/ or refs/remotes/ prefix
+        #[clap(long = "short")]
+        short: bool,
+    },
+    /// Show whether paths are ignored, and by which pattern
+    CheckIgnore {
+        /// Paths relative to the top of the working tree
+        #[clap(value_name = "PATH", required = true)]
+        paths: Vec<String>,
+
+        /// Print the ignore file, line and pattern that matched, including
+        /// `!` patterns that re-include a path
+        #[clap(short = 'v', long = "verbose")]
+        verbose: bool,
+    },
+    /// Apply a unified diff to the working tree or the index
+    Apply {
+        /// The patch file; standard input if omitted
+        #[clap(value_name = "PATCH")]
+        patch: Option<PathBuf>,
+
+        /// Apply to the index instead of the working tree
+        #[clap(long = "cached")]
+        cached: bool,
+
+        /// Undo the patch instead
+        #[clap(short = 'R', long = "reverse")]
+        reverse: bool,
+
+        /// Only check that the patch applies, changing nothing
+        #[clap(long = "check")]
+        check: bool,
+    },
+    /// Write commits as patch mails, one file per commit
+    FormatPatch {
+        /// `<since>..<until>`, or `<since>` for the commits HEAD has on top of it
+        #[clap(value_name = "RANGE")]
+        range: String,
+
+        /// Directory to write the patch files to
+        #[clap(short = 'o', long = "output-directory", value_name = "DIR")]
+        output_dir: Option<PathBuf>,
+
+        /// Print the mails instead of writing files
+        #[clap(long = "stdout")]
+        stdout: bool,
+    },
+    /// Apply patch mails from mailboxes and commit them
+    Am {
+        /// Mailboxes or patch files; standard input if none are given
+        #[clap(value_name = "MBOX", conflicts_with_all = ["cont", "abort"])]
+        mailboxes: Vec<PathBuf>,
+
+        /// Commit the patch applied by hand and apply the remaining ones
+        #[clap(long = "continue", conflicts_with = "abort")]
+        cont: bool,
+
+        /// Give up and return to where the session started
+        #[clap(long = "abort")]
+        abort: bool,
+    },
+    /// Compute the SHA1 of file contents as a blob
+    HashObject {
+        /// Files to hash
+        #[clap(value_name = "FILE", required_unless_present = "stdin")]
+        files: Vec<PathBuf>,
+
+        /// Read the content from standard input instead
+        #[clap(long = "stdin")]
+        stdin: bool,
+
+        /// Store the blob in the object database
+        #[clap(short = 'w')]
+        write: bool,
+    },
+    /// List the entries of a tree
+    LsTree {
+        /// Commit or tree to list
+        #[clap(value_name = "TREE-ISH", required = true)]
+        tree_ish: String,
+
+        /// Only list this entry, or the content of a directory given with a
+        /// trailing slash
+        #[clap(value_name = "PATH")]
+        path: Option<String>,
+
+        /// List the files of subtrees recursively
+        #[clap(short = 'r')]
+        recursive: bool,
+
+        /// Only print the paths
+        #[clap(long = "name-only")]
+        name_only: bool,
+    },
+    /// Print lines matching a pattern
+    Grep {
+        /// Regular expression to search for
+        #[clap(value_name = "PATTERN", required = true)]
+        pattern: String,
+
+        /// Search the tree of this commit instead of the working tree
+        #[clap(value_name = "REV", conflicts_with = "cached")]
+        rev: Option<String>,
+
+        /// Search the staged content instead of the working tree
+        #[clap(long = "cached")]
+        cached: bool,
+
+        /// Prefix matching lines with their line number
+        #[clap(short = 'n', long = "line-number")]
+        line_number: bool,
+
+        /// Ignore case differences
+        #[clap(short = 'i', long = "ignore-case")]
+        ignore_case: bool,
+
+        /// Limit the search to these paths
+        #[clap(last = true)]
+        paths: Vec<String>,
+    },
+    /// Show changes between the working tree, the index and commits
+    Diff {
+        /// Show how changed lines are spread over directories
+        #[clap(long = "dirstat")]
+        dirstat: bool,
+
+        /// Compare the index instead of the working tree
+        #[clap(long = "cached")]
+        cached: bool,
+
+        /// Only print the changed paths, each with a status letter
+        #[clap(long = "name-status", conflicts_with = "dirstat")]
+        name_status: bool,
+
+        /// Report added files copied or renamed from files changed by the
+        /// same change
+        #[clap(short = 'C', long = "find-copies")]
+        find_copies: bool,
+
+        /// Look for the source of copies among all files, changed or not
+        #[clap(long = "find-copies-harder")]
+        find_copies_harder: bool,
+
+        /// How to match up lines: myers, patience or histogram; defaults
+        /// to diff.algorithm
+        #[clap(long = "diff-algorithm", value_name = "ALGORITHM")]
+        diff_algorithm: Option<String>,
+
+        /// Commits to compare: none for the index, one to compare it
+        /// with the working tree or index, two or a range `<a>..<b>` to
+        /// compare them; `<a>...<b>` compares `<b>` with the merge base
+        #[clap(value_name = "COMMIT", num_args = 0..=2)]
+        commits: Vec<String>,
+
+        /// Limit the comparison to these paths
+        #[clap(last = true)]
+        paths: Vec<String>,
+    },
+    /// Show changes in an external diff tool, one file at a time
+    Difftool {
+        /// Tool to run instead of the one set in diff.tool
+        #[clap(short = 't', long = "tool", value_name = "TOOL")]
+        tool: Option<String>,
+
+        /// Compare the index instead of the working tree
+        #[clap(long = "cached")]
+        cached: bool,
+
+        /// Commits to compare, as for diff
+        #[clap(value_name = "COMMIT", num_args = 0..=2)]
+        commits: Vec<String>,
+
+        /// Limit the comparison to these paths
+        #[clap(last = true)]
+        paths: Vec<String>,
+    },
+    /// Update cached data in the index
+    UpdateIndex {
+        /// Re-stat tracked files and report those that need updating
+        #[clap(long = "refresh", required = true)]
+        refresh: bool,
+    },
+    /// Write a snapshot of refs and objects to an archive
+    Backup {
+        /// Archive file to create
+        #[clap(value_name = "FILE", required = true)]
+        file: String,
+    },
+    /// Recreate a repository in the current directory from a backup
+    RestoreBackup {
+        /// Archive written by `backup`
+        #[clap(value_name = "FILE", required = true)]
+        file: String,
+    },
+    /// Import the history of an existing git repository
+    ImportGit {
+        /// Path to the git repository (working tree or bare)
+        #[clap(value_name = "PATH", required = true)]
+        path: String,
+    },
+    /// Verify the objects and their connectivity
+    Fsck {
+        /// List every unreachable object, not only the dangling ones
+        #[clap(long = "unreachable")]
+        unreachable: bool,
+    },
+    /// Pack refs and delete old unreachable objects
+    Gc,
+    /// Delete unreachable loose objects
+    Prune {
+        /// Only list the objects that would be deleted
+        #[clap(short = 'n', long = "dry-run")]
+        dry_run: bool,
+
+        /// Only delete objects older than this, e.g. `2.weeks.ago`
+        #[clap(long = "expire", value_name = "TIME", default_value = "now")]
+        expire: String,
+    },
+    /// Move corrupt objects aside, restoring them from packs, and move refs
+    /// left without a commit back along their reflog
+    RepairObjects,
+    /// Move loose refs into the packed-refs file
+    PackRefs,
+    /// Clean up state left behind by interrupted operations
+    #[clap(group(clap::ArgGroup::new("task").required(true).multiple(true)))]
+    Repair {
+        /// Remove lock files whose owning process is no longer running
+        #[clap(long = "stale-locks", group = "task")]
+        stale_locks: bool,
+        /// Deflate loose objects written before objects were compressed
+        #[clap(long = "compress-objects", group = "task")]
+        compress_objects: bool,
+    },
+    /// Revert the last operation recorded in the reflog
+    Undo {
+        /// Only show which refs would move
+        #[clap(long = "dry-run")]
+        dry_run: bool,
+    },
+    /// Download the branches of a remote into its remote-tracking refs
+    Fetch {
+        /// Remote to fetch from
+        #[clap(default_value = "origin")]
+        remote: String,
+    },
+    /// Fetch the upstream of the current branch and merge it
+    Pull {
+        /// Stash local changes before merging and reapply them afterwards
+        #[clap(long = "autostash")]
+        autostash: bool,
+    },
+    /// Update remote branches along with the objects they need
+    Push {
+        /// Remote to push to
+        #[clap(default_value = "origin")]
+        remote: String,
+        /// Branches to push as [+]<src>[:<dst>]; defaults to the current one
+        refspecs: Vec<String>,
+    },
+    /// Manage the repositories whose branches are tracked
+    Remote {
+        /// Show the URL after each remote name
+        #[clap(short = 'v', long = "verbose")]
+        verbose: bool,
+        #[clap(subcommand)]
+        action: Option<RemoteCommand>,
+    },
+    /// Set local changes aside and restore them later
+    Stash {
+        #[clap(subcommand)]
+        action: Option<StashCommand>,
+    },
+    /// Move the current branch to another commit
+    Reset {
+        /// Branch or commit to reset to; defaults to HEAD
+        #[clap(value_name = "COMMIT")]
+        target: Option<String>,
+
+        /// Leave the index and working tree alone
+        #[clap(long = "soft", conflicts_with_all = ["mixed", "hard"])]
+        soft: bool,
+
+        /// Reset the index but not the working tree (the default)
+        #[clap(long = "mixed", conflicts_with = "hard")]
+        mixed: bool,
+
+        /// Reset the index and the working tree, discarding local changes
+        #[clap(long = "hard")]
+        hard: bool,
+    },
+    /// Remove untracked files from the working tree
+    Clean {
+        /// Only show what would be removed
+        #[clap(short = 'n', long = "dry-run")]
+        dry_run: bool,
+
+        /// Actually remove the files
+        #[clap(short = 'f', long = "force")]
+        force: bool,
+
+        /// Remove untracked directories too
+        #[clap(short = 'd')]
+        directories: bool,
+
+        /// Remove ignored files too
+        #[clap(short = 'x')]
+        remove_ignored: bool,
+    },
+    /// Find the best common ancestor of two commits
+    MergeBase {
+        #[clap(value_name = "COMMIT", required = true)]
+        lhs: String,
+
+        #[clap(value_name = "COMMIT", required = true)]
+        rhs: String,
+
+        /// Print all best common ancestors
+        #[clap(long = "all")]
+        all: bool,
+    },
+    /// Show the commit that last changed each line of a file
+    Blame {
+        /// File to blame, relative to the repository root
+        #[clap(value_name = "FILE", required = true)]
+        path: String,
+    },
+    /// Binary-search the commit that introduced a bug
+    Bisect {
+        #[clap(subcommand)]
+        action: BisectCommand,
+    },
+    /// Write the files of a commit or tree to a tar, tar.gz or zip archive
+    Archive {
+        /// Commit or tree to archive
+        #[clap(value_name = "TREE-ISH", default_value = "HEAD")]
+        tree_ish: String,
+
+        /// Archive to write; `.zip`, `.tar.gz` and `.tgz` pick the format
+        #[clap(short = 'o', long = "output", value_name = "FILE", required = true)]
+        output: PathBuf,
+
+        /// Prepend this to every path, e.g. `project/`
+        #[clap(long = "prefix", value_name = "PREFIX")]
+        prefix: Option<String>,
+    },
+    /// Manage repositories nested in the working tree
+    Submodule {
+        #[clap(subcommand)]
+        action: SubmoduleCommand,
+    },
+    /// Move history between repositories offline as a single file
+    Bundle {
+        #[clap(subcommand)]
+        action: BundleCommand,
+    },
+    /// Create, list or delete tags
+    Tag {
+        /// Tag to create or delete; lists the tags when left out
+        #[clap(value_name = "NAME")]
+        name: Option<String>,
+
+        /// Commit to tag; defaults to HEAD
+        #[clap(value_name = "COMMIT")]
+        commit: Option<String>,
+
+        /// Make an annotated tag with this message
+        #[clap(short = 'm', long = "message")]
+        message: Option<String>,
+
+        /// Delete the tag
+        #[clap(short = 'd', long = "delete", requires = "name")]
+        delete: bool,
+    },
+    /// Name HEAD after the nearest tag it descends from
+    Describe {
+        /// Consider lightweight tags too
+        #[clap(long = "tags")]
+        tags: bool,
+
+        /// Append `-dirty` if there are uncommitted changes
+        #[clap(long = "dirty")]
+        dirty: bool,
+    },
+    /// Show a commit with its patch, a file of a commit, or a tree
+    Show {
+        /// Commit, `<commit>:<path>`, or the SHA1 of a tree or blob
+        #[clap(value_name = "OBJECT", default_value = "HEAD")]
+        object: String,
+
+        /// Print commits as their abbreviated SHA1 and subject
+        #[clap(long = "oneline", conflicts_with = "format")]
+        oneline: bool,
+
+        /// Print commits as oneline, medium, or a line of placeholders like
+        /// "%h %an %s"
+        #[clap(long = "format", alias = "pretty", value_name = "FORMAT")]
+        format: Option<String>,
+    },
+    /// Show commit history
+    Log {
+        /// Commit to start from, defaults to HEAD, or a range such as
+        /// `<a>..<b>` or `<a>...<b>`
+        #[clap(value_name = "REV")]
+        rev: Option<String>,
+
+        /// Check the signatures of signed commits
+        #[clap(long = "show-signature")]
+        show_signature: bool,
+
+        /// Only list commits whose author matches this regex
+        #[clap(long = "author", value_name = "PATTERN")]
+        author: Option<String>,
+
+        /// Only list commits whose message matches this regex
+        #[clap(long = "grep", value_name = "PATTERN")]
+        grep: Option<String>,
+
+        /// Only list commits more recent than a date like 2024-01-31 or
+        /// "2 weeks ago"
+        #[clap(long = "since", alias = "after", value_name = "DATE")]
+        since: Option<String>,
+
+        /// Only list commits older than a date
+        #[clap(long = "until", alias = "before", value_name = "DATE")]
+        until: Option<String>,
+
+        /// Follow the history of a single file across renames
+        #[clap(long = "follow")]
+        follow: bool,
+
+        /// Only list commits changing how often this string occurs in a file
+        #[clap(short = 'S', value_name = "STRING", conflicts_with = "pickaxe_regex")]
+        pickaxe: Option<String>,
+
+        /// Only list commits adding or removing lines matching this regex
+        #[clap(short = 'G', value_name = "REGEX")]
+        pickaxe_regex: Option<String>,
+
+        /// Print commits as their abbreviated SHA1 and subject
+        #[clap(long = "oneline", conflicts_with = "format")]
+        oneline: bool,
+
+        /// Print commits as oneline, medium, or a line of placeholders like
+        /// "%h %an %s"
+        #[clap(long = "format", alias = "pretty", value_name = "FORMAT")]
+        format: Option<String>,
+
+        /// Only list commits changing these paths
+        #[clap(last = true)]
+        paths: Vec<String>,
+    },
+    /// Check the signatures of commits
+    VerifyCommit {
+        /// Commits to check
+        #[clap(value_name = "COMMIT", required = true)]
+        commits: Vec<String>,
+    },
+    /// Check the signatures of annotated tags
+    VerifyTag {
+        /// Tags to check
+        #[clap(value_name = "TAG", required = true)]
+        tags: Vec<String>,
+    },
+    /// Summarize history by author
+    Shortlog {
+        /// Commit to start from; defaults to HEAD
+        #[clap(value_name = "REV")]
+        rev: Option<String>,
+
+        /// Only print the number of commits of every author
+        #[clap(short = 's', long = "summary")]
+        summary: bool,
+
+        /// Sort authors by their number of commits
+        #[clap(short = 'n', long = "numbered")]
+        numbered: bool,
+    },
+    /// Show where a ref has pointed, newest first
+    Reflog {
+        /// Ref whose log to show, e.g. a branch; defaults to HEAD
+        #[clap(value_name = "REF")]
+        name: Option<String>,
+    },
+    /// Find commits not yet applied upstream
+    Cherry {
+        /// Upstream branch to search for equivalent commits
+        #[clap(value_name = "UPSTREAM", required = true)]
+        upstream: String,
+
+        /// Working branch; defaults to HEAD
+        #[clap(value_name = "HEAD")]
+        head: Option<String>,
+
+        /// Show the commit subjects next to the SHA1s
+        #[clap(short = 'v')]
+        verbose: bool,
+    },
+    /// Get and set repository or global options
+    Config {
+        /// Use the user-wide config file instead of the repository's
+        #[clap(long = "global")]
+        global: bool,
+
+        /// Print the value of a setting
+        #[clap(long = "get", value_name = "KEY", conflicts_with_all = ["set", "list"])]
+        get: Option<String>,
+
+        /// Store a setting
+        #[clap(long = "set", num_args = 2, value_names = ["KEY", "VALUE"], conflicts_with = "list")]
+        set: Option<Vec<String>>,
+
+        /// List every setting as key=value
+        #[clap(short = 'l', long = "list")]
+        list: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum RemoteCommand {
+    /// Add a remote
+    Add { name: String, url: String },
+    /// Remove a remote and its remote-tracking refs
+    #[clap(alias = "rm")]
+    Remove { name: String },
+    /// Rename a remote
+    Rename { old: String, new: String },
+}
+
+#[derive(Debug, Subcommand)]
+enum StashCommand {
+    /// Stash the changes of the index and tracked files (the default)
+    Push {
+        /// Describe the stash
+        #[clap(short = 'm', long = "message")]
+        message: Option<String>,
+    },
+    /// List the stashes, most recent first
+    List,
+    /// Apply a stash and keep it
+    Apply {
+        /// Stash to apply, e.g. stash@{1}; defaults to the most recent
+        #[clap(value_name = "STASH")]
+        stash: Option<String>,
+    },
+    /// Apply a stash and drop it
+    Pop {
+        #[clap(value_name = "STASH")]
+        stash: Option<String>,
+    },
+    /// Delete a stash
+    Drop {
+        #[clap(value_name = "STASH")]
+        stash: Option<String>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum RerereCommand {
+    /// List the conflicted paths whose resolution will be recorded
+    Status,
+    /// Forget the recorded resolution of a conflicted path
+    Forget {
+        #[clap(value_name = "PATH", required = true)]
+        path: String,
+    },
+    /// Drop the conflicts that were not resolved yet
+    Clear,
+}
+
+#[derive(Debug, Subcommand)]
+enum BisectCommand {
+    /// Start bisecting, optionally with a bad commit and good ones
+    Start {
+        #[clap(value_name = "BAD")]
+        bad: Option<String>,
+        #[clap(value_name = "GOOD")]
+        good: Vec<String>,
+    },
+    /// Mark a commit, HEAD by default, as not having the bug
+    Good {
+        #[clap(value_name = "COMMIT")]
+        rev: Option<String>,
+    },
+    /// Mark a commit, HEAD by default, as having the bug
+    Bad {
+        #[clap(value_name = "COMMIT")]
+        rev: Option<String>,
+    },
+    /// Stop bisecting and return to where it started
+    Reset,
+    /// Bisect automatically with a command that exits 0 on good commits
+    Run {
+        #[clap(
+            value_name = "CMD",
+            required = true,
+            trailing_var_arg = true,
+            allow_hyphen_values = true
+        )]
+        cmd: Vec<String>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum SubmoduleCommand {
+    /// Clone a repository into the working tree and record it as a submodule
+    Add {
+        #[clap(value_name = "URL", required = true)]
+        url: String,
+        #[clap(value_name = "PATH")]
+        path: Option<String>,
+    },
+    /// Register the submodules of .gitmodules in the repository config
+    Init,
+    /// Clone and check out the submodules at the commits the index records
+    Update {
+        /// Initialize the submodules first
+        #[clap(long = "init")]
+        init: bool,
+    },
+    /// Show the commit every submodule is at
+    Status,
+}
+
+#[derive(Debug, Subcommand)]
+enum BundleCommand {
+    /// Write branches, or `A..B` ranges of them, to a bundle file
+    Create {
+        #[clap(value_name = "FILE", required = true)]
+        file: PathBuf,
+        /// Refs to include; HEAD and every branch by default
+        #[clap(value_name = "REV")]
+        revs: Vec<String>,
+    },
+    /// Check a bundle is intact and its prerequisites are present
+    Verify {
+        #[clap(value_name = "FILE", required = true)]
+        file: PathBuf,
+    },
+    /// Store the objects of a bundle and list the refs it carries
+    Unbundle {
+        #[clap(value_name = "FILE", required = true)]
+        file: PathBuf,
+    },
 }
 
 fn find_repo_dir() -> PathBuf {
-    let repo_dir = current_dir().unwrap();
-    repo_dir
+    current_dir().unwrap()
 }
 fn open_repo(repo_dir: &Path) -> Repository {
-    let repo = match Repository::open(&repo_dir) {
+    match Repository::open(repo_dir) {
         Ok(repo) => repo,
         Err(why) => {
             println!("{why}");
             std::process::exit(-1);
         }
-    };
-    repo
+    }
 }
 
 fn main() {
     let args = Args::parse();
+    if let Err(why) = run(args.command) {
+        println!("fatal: {why}");
+        std::process::exit(1);
+    }
+}
 
-    match args.command {
-        Command::Commit { message } => {
+fn run(command: Command) -> Result<(), Error> {
+    match command {
+        Command::Commit {
+            message,
+            amend,
+            gpg_sign,
+            no_gpg_sign,
+            paths,
+        } => {
             let repo_dir = find_repo_dir();
             let repo = open_repo(&repo_dir);
-            repo.commit(message);
+            let options = CommitOptions {
+                amend,
+                sign: match (gpg_sign, no_gpg_sign) {
+                    (true, _) => Some(true),
+                    (_, true) => Some(false),
+                    _ => None,
+                },
+            };
+            let commit_sha = repo.commit_with(message.as_deref(), &paths, options)?;
+            eprintln!("{commit_sha}");
         }
         Command::Add { paths } => {
             let repo_dir = find_repo_dir();
             let repo = open_repo(&repo_dir);
-            repo.add(&paths);
+            repo.add(&paths)?;
         }
-        Command::Rm { paths } => {
+        Command::Rm {
+            paths,
+            cached,
+            recursive,
+        } => {
             let repo_dir = find_repo_dir();
             let repo = open_repo(&repo_dir);
-            repo.rm(&paths);
+            repo.rm(&paths, cached, recursive)?;
         }
-        Command::Init => {
-            let current_dir = current_dir().unwrap();
-            let _ = match Repository::init(&current_dir) {
-                Ok(repo) => repo,
-                Err(why) => {
+        Command::Mv { src, dst } => {
+            let repo_dir = find_repo_dir();
+            let repo = open_repo(&repo_dir);
+            repo.mv(&src, &dst)?;
+        }
+        Command::Init {
+            encrypt,
+            initial_branch,
+        } => {
+            // Check the key before anything is created
+            let cipher = encrypt.then(|| {
+                ObjectCipher::from_env().unwrap_or_else(|why| {
                     println!("{why}");
                     std::process::exit(-1);
+                })
+            });
+            let current_dir = current_dir().unwrap();
+            let initial_branch = initial_branch.unwrap_or_else(Repository::default_branch_name);
+            let mut repo = Repository::init_with_branch(&current_dir, &initial_branch)?;
+            if let Some(cipher) = cipher {
+                repo.enable_encryption(cipher)?;
+            }
+        }
+        Command::Clone { source, directory } => {
+            let directory = directory.unwrap_or_else(|| {
+                let source = Path::new(source.trim_end_matches('/'));
+                let source = match source.file_name() {
+                    Some(name) if name == ".git" => source.parent().unwrap_or(source),
+                    _ => source,
+                };
+                let name = source
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                match name.strip_suffix(".bundle") {
+                    Some(stem) if !stem.is_empty() => stem.to_string(),
+                    _ => name,
                 }
-            };
+            });
+            println!("Cloning into '{directory}'...");
+            Repository::clone(Path::new(&source), &current_dir()?.join(directory))?;
         }
         Command::Branch { name, delete } => {
             let repo_dir = find_repo_dir();
             let repo = open_repo(&repo_dir);
             if delete {
-                repo.rm_branch(name);
+                repo.rm_branch(name)?;
             } else {
-                repo.branch(name);
+                repo.branch(name)?;
             }
         }
-        Command::Checkout { target , create} => {
+        Command::Checkout {
+            target,
+            source,
+            paths,
+            create,
+            autostash,
+        } => {
             let repo_dir = find_repo_dir();
             let repo = open_repo(&repo_dir);
-            if create {
-                repo.branch(&target);
+            if !paths.is_empty() {
+                repo.checkout_paths(&paths, source.or(target).as_deref())?;
+            } else if let Some(target) = target {
+                if create {
+                    repo.branch(&target)?;
+                }
+                repo.checkout(&target, autostash)?;
+            }
+        }
+        Command::Restore {
+            paths,
+            source,
+            staged,
+            worktree,
+        } => {
+            let repo_dir = find_repo_dir();
+            let repo = open_repo(&repo_dir);
+            repo.restore(&paths, source.as_deref(), staged, worktree)?;
+        }
+        Command::Switch {
+            target,
+            create,
+            detach,
+        } => {
+            let repo_dir = find_repo_dir();
+            let repo = open_repo(&repo_dir);
+            match (create, target) {
+                (Some(name), start_point) => repo.switch_create(&name, start_point.as_deref())?,
+                (None, Some(target)) => repo.switch(&target, detach)?,
+                (None, None) => unreachable!("clap requires a target without -c"),
+            }
+        }
+        Command::Merge {
+            branch,
+            autostash,
+            ff_only,
+            no_ff,
+            abort,
+        } => {
+            let repo_dir = find_repo_dir();
+            let repo = open_repo(&repo_dir);
+            let fast_forward = if ff_only {
+                FastForward::Only
+            } else if no_ff {
+                FastForward::Never
+            } else {
+                FastForward::Allow
+            };
+            match (branch, abort) {
+                (_, true) => repo.merge_abort()?,
+                (Some(branch), false) => {
+                    if !repo.merge(&branch, autostash, fast_forward)? {
+                        std::process::exit(1);
+                    }
+                }
+                (None, false) => unreachable!("clap requires a branch without --abort"),
+            }
+        }
+        Command::CherryPick {
+            commits,
+            cont,
+            abort,
+        } => {
+            let repo_dir = find_repo_dir();
+            let repo = open_repo(&repo_dir);
+            let done = if abort {
+                repo.cherry_pick_abort()?;
+                true
+            } else if cont {
+                repo.cherry_pick_continue()?
+            } else {
+                repo.cherry_pick(&commits)?
+            };
+            if !done {
+                std::process::exit(1);
+            }
+        }
+        Command::Revert { commit } => {
+            let repo_dir = find_repo_dir();
+            let repo = open_repo(&repo_dir);
+            match repo.revert(&commit)? {
+                Some(commit_sha) => eprintln!("{commit_sha}"),
+                None => std::process::exit(1),
+            }
+        }
+        Command::MergeTree {
+            ours,
+            theirs,
+            merge_base,
+        } => {
+            let repo_dir = find_repo_dir();
+            let repo = open_repo(&repo_dir);
+            if !repo.merge_tree(&ours, &theirs, merge_base.as_deref())? {
+                std::process::exit(1);
+            }
+        }
+        Command::MergeFile {
+            current,
+            base,
+            other,
+            stdout,
+            labels,
+        } => {
+            let label = |at: usize, file: &Path| {
+                labels
+                    .get(at)
+                    .cloned()
+                    .unwrap_or_else(|| file.display().to_string())
+            };
+            let merge = merge_text(
+                &std::fs::read_to_string(&base)?,
+                &std::fs::read_to_string(&current)?,
+                &std::fs::read_to_string(&other)?,
+                &label(0, &current),
+                &label(2, &other),
+            );
+            if stdout {
+                print!("{}", merge.content);
+            } else {
+                std::fs::write(&current, &merge.content)?;
+            }
+            // Like Git, the exit status counts the conflicts
+            if !merge.is_clean() {
+                std::process::exit(merge.conflicts.len().min(127) as i32);
+            }
+        }
+        Command::Mergetool { tool, paths } => {
+            let repo_dir = find_repo_dir();
+            let repo = open_repo(&repo_dir);
+            if !repo.mergetool(tool.as_deref(), &paths)?.is_empty() {
+                std::process::exit(1);
+            }
+        }
+        Command::Rerere { action } => {
+            let repo_dir = find_repo_dir();
+            let repo = open_repo(&repo_dir);
+            match action {
+                None => repo.rerere()?,
+                Some(RerereCommand::Status) => {
+                    for path in repo.rerere_status()? {
+                        println!("{path}");
+                    }
+                }
+                Some(RerereCommand::Forget { path }) => repo.rerere_forget(&path)?,
+                Some(RerereCommand::Clear) => repo.rerere_clear()?,
+            }
+        }
+        Command::Status { paths } => {
+            let repo_dir = find_repo_dir();
+            let repo = open_repo(&repo_dir);
+            repo.status(&paths)?;
+        }
+        Command::LsFiles {
+            stage,
+            others,
+            modified,
+            deleted,
+        } => {
+            let repo_dir = find_repo_dir();
+            let repo = open_repo(&repo_dir);
+            let options = LsFilesOptions {
+                stage,
+                others,
+                modified,
+                deleted,
+            };
+            for line in repo.ls_files(options)? {
+                println!("{line}");
+            }
+        }
+        Command::CatFile {
+            object,
+            object_type,
+            size,
+            pretty: _,
+        } => {
+            let repo_dir = find_repo_dir();
+            let repo = open_repo(&repo_dir);
+            let mode = if object_type {
+                CatFileMode::Type
+            } else if size {
+                CatFileMode::Size
+            } else {
+                CatFileMode::Pretty
+            };
+            io::stdout().write_all(&repo.cat_file(&object, mode)?)?;
+        }
+        Command::RevParse { revs } => {
+            let repo_dir = find_repo_dir();
+            let repo = open_repo(&repo_dir);
+            for rev in &revs {
+                let (include, exclude) = repo.parse_rev_range(&[rev])?;
+                for sha in include {
+                    println!("{sha}");
+                }
+                for sha in exclude {
+                    println!("^{sha}");
+                }
+            }
+        }
+        Command::RevList {
+            revs,
+            count,
+            max_count,
+        } => {
+            let repo_dir = find_repo_dir();
+            let repo = open_repo(&repo_dir);
+            let commits = repo.rev_list(&revs, max_count)?;
+            if count {
+                println!("{}", commits.len());
+            } else {
+                for sha in commits {
+                    println!("{sha}");
+                }
+            }
+        }
+        Command::UpdateRef {
+            name,
+            new_value,
+            old_value,
+            delete,
+        } => {
+            let repo_dir = find_repo_dir();
+            let repo = open_repo(&repo_dir);
+            match (delete, new_value) {
+                // `-d <ref> [<oldvalue>]` takes the old value first
+                (true, old_value) => repo.delete_ref(&name, old_value.as_deref())?,
+                (false, Some(new_value)) => {
+                    repo.update_ref(&name, &new_value, old_value.as_deref())?
+                }
+                (false, None) => unreachable!("clap requires a new value"),
+            }
+        }
+        Command::SymbolicRef {
+            name,
+            target,
+            reason,
+            short,
+        } => {
+            let repo_dir = find_repo_dir();
+            let repo = open_repo(&repo_dir);
+            match target {
+                Some(target) => repo.set_symbolic_ref(&name, &target, reason.as_deref())?,
+                None => {
+                    let target = repo.symbolic_ref(&name)?;
+                    let short_name = ["refs/heads/", "refs/remotes/", "refs/tags/"]
+                        .iter()
+                        .find_map(|prefix| target.strip_prefix(prefix))
+                        .filter(|_| short);
+                    println!("{}", short_name.unwrap_or(&target));
+                }
+            }
+        }
+        Command::CheckIgnore { paths, verbose } => {
+            let repo_dir = find_repo_dir();
+            let repo = open_repo(&repo_dir);
+            let matches = repo.check_ignore(&paths)?;
+            let mut any_ignored = false;
+            for (path, pattern) in matches {
+                any_ignored |= pattern.is_ignored();
+                if verbose {
+                    println!("{pattern}\t{path}");
+                } else if pattern.is_ignored() {
+                    println!("{path}");
+                }
+            }
+            if !any_ignored {
+                std::process::exit(1);
+            }
+        }
+        Command::Apply {
+            patch,
+            cached,
+            reverse,
+            check,
+        } => {
+            let repo_dir = find_repo_dir();
+            let repo = open_repo(&repo_dir);
+            let patch = match patch {
+                Some(path) => std::fs::read_to_string(path)?,
+                None => {
+                    let mut patch = String::new();
+                    io::stdin().read_to_string(&mut patch)?;
+                    patch
+                }
+            };
+            let options = ApplyOptions {
+                cached,
+                reverse,
+                check,
+            };
+            repo.apply(&patch, options)?;
+        }
+        Command::FormatPatch {
+            range,
+            output_dir,
+            stdout,
+        } => {
+            let repo_dir = find_repo_dir();
+            let repo = open_repo(&repo_dir);
+            if stdout {
+                for (_, patch) in repo.format_patch_output(&range)? {
+                    print!("{patch}");
+                }
+            } else {
+                let output_dir = output_dir.unwrap_or_else(|| PathBuf::from("."));
+                for path in repo.format_patch(&range, &output_dir)? {
+                    println!("{}", path.display());
+                }
+            }
+        }
+        Command::Am {
+            mailboxes,
+            cont,
+            abort,
+        } => {
+            let repo_dir = find_repo_dir();
+            let repo = open_repo(&repo_dir);
+            let done = if abort {
+                repo.am_abort()?;
+                true
+            } else if cont {
+                repo.am_continue()?
+            } else if mailboxes.is_empty() {
+                let mut mailbox = String::new();
+                io::stdin().read_to_string(&mut mailbox)?;
+                repo.am(&[mailbox])?
+            } else {
+                let mailboxes = mailboxes
+                    .iter()
+                    .map(std::fs::read_to_string)
+                    .collect::<Result<Vec<_>, _>>()?;
+                repo.am(&mailboxes)?
+            };
+            if !done {
+                std::process::exit(1);
+            }
+        }
+        Command::HashObject {
+            files,
+            stdin,
+            write,
+        } => {
+            let mut contents = Vec::new();
+            if stdin {
+                let mut data = Vec::new();
+                io::stdin().read_to_end(&mut data)?;
+                contents.push(data);
+            }
+            for file in &files {
+                contents.push(std::fs::read(file)?);
+            }
+            // Hashing alone works outside of a repository
+            let repo = write.then(|| open_repo(&find_repo_dir()));
+            for data in &contents {
+                let sha = match &repo {
+                    Some(repo) => repo.hash_object(data, true)?,
+                    None => hash_blob(data),
+                };
+                println!("{sha}");
+            }
+        }
+        Command::LsTree {
+            tree_ish,
+            path,
+            recursive,
+            name_only,
+        } => {
+            let repo_dir = find_repo_dir();
+            let repo = open_repo(&repo_dir);
+            let options = LsTreeOptions {
+                recursive,
+                name_only,
+            };
+            for line in repo.ls_tree(&tree_ish, path.as_deref(), options)? {
+                println!("{line}");
+            }
+        }
+        Command::Grep {
+            pattern,
+            rev,
+            cached,
+            line_number,
+            ignore_case,
+            paths,
+        } => {
+            let repo_dir = find_repo_dir();
+            let repo = open_repo(&repo_dir);
+            let source = match &rev {
+                Some(rev) => Snapshot::Commit(rev),
+                None if cached => Snapshot::Index,
+                None => Snapshot::Worktree,
+            };
+            let options = GrepOptions {
+                line_number,
+                ignore_case,
+            };
+            if !repo.grep(&pattern, source, options, &paths)? {
+                std::process::exit(1);
+            }
+        }
+        Command::Diff {
+            dirstat,
+            cached,
+            name_status,
+            find_copies,
+            find_copies_harder,
+            diff_algorithm,
+            commits,
+            paths,
+        } => {
+            let repo_dir = find_repo_dir();
+            let repo = open_repo(&repo_dir);
+            let range = diff_range(&repo, &commits)?;
+            let (old, new) = diff_snapshots(&range, &commits, cached);
+            let options = DiffOptions {
+                copies: match (find_copies, find_copies_harder) {
+                    (_, true) => CopyDetection::All,
+                    (true, false) => CopyDetection::Changed,
+                    (false, false) => CopyDetection::Off,
+                },
+                algorithm: diff_algorithm
+                    .as_deref()
+                    .map(str::parse::<DiffAlgorithm>)
+                    .transpose()?,
+            };
+            if dirstat {
+                repo.diff_dirstat(old, new, &paths)?;
+            } else if name_status {
+                repo.diff_name_status(old, new, &paths, &options)?;
+            } else {
+                repo.diff_with(old, new, &paths, &options)?;
+            }
+        }
+        Command::Difftool {
+            tool,
+            cached,
+            commits,
+            paths,
+        } => {
+            let repo_dir = find_repo_dir();
+            let repo = open_repo(&repo_dir);
+            let range = diff_range(&repo, &commits)?;
+            let (old, new) = diff_snapshots(&range, &commits, cached);
+            if !repo.difftool(old, new, tool.as_deref(), &paths)?.is_empty() {
+                std::process::exit(1);
+            }
+        }
+        Command::UpdateIndex { refresh } => {
+            let repo_dir = find_repo_dir();
+            let repo = open_repo(&repo_dir);
+            if refresh {
+                let needs_update = repo.refresh_index()?;
+                for path in &needs_update {
+                    println!("{path}: needs update");
+                }
+                if !needs_update.is_empty() {
+                    std::process::exit(1);
+                }
+            }
+        }
+        Command::Backup { file } => {
+            let repo_dir = find_repo_dir();
+            let repo = open_repo(&repo_dir);
+            repo.backup(&file)?;
+        }
+        Command::RestoreBackup { file } => {
+            let current_dir = current_dir().unwrap();
+            Repository::restore_backup(Path::new(&file), &current_dir)?;
+            println!("Restored repository from {file}");
+        }
+        Command::ImportGit { path } => {
+            let repo_dir = find_repo_dir();
+            let repo = open_repo(&repo_dir);
+            repo.import_git(&path)?;
+        }
+        Command::PackRefs => {
+            let repo_dir = find_repo_dir();
+            let repo = open_repo(&repo_dir);
+            repo.pack_refs()?;
+        }
+        Command::Fsck { unreachable } => {
+            let repo_dir = find_repo_dir();
+            let repo = open_repo(&repo_dir);
+            if !repo.fsck(unreachable)? {
+                std::process::exit(1);
+            }
+        }
+        Command::Gc => {
+            let repo_dir = find_repo_dir();
+            let repo = open_repo(&repo_dir);
+            repo.gc()?;
+        }
+        Command::Prune { dry_run, expire } => {
+            let repo_dir = find_repo_dir();
+            let repo = open_repo(&repo_dir);
+            for sha in repo.prune(parse_expire(&expire)?, dry_run)? {
+                println!("{sha}");
+            }
+        }
+        Command::RepairObjects => {
+            let repo_dir = find_repo_dir();
+            let repo = open_repo(&repo_dir);
+            if !repo.repair_objects()? {
+                std::process::exit(1);
+            }
+        }
+        Command::Repair {
+            stale_locks,
+            compress_objects,
+        } => {
+            let repo_dir = find_repo_dir();
+            let repo = open_repo(&repo_dir);
+            if stale_locks {
+                repo.repair_stale_locks()?;
+            }
+            if compress_objects {
+                repo.repair_compress_objects()?;
+            }
+        }
+        Command::Undo { dry_run } => {
+            let repo_dir = find_repo_dir();
+            let repo = open_repo(&repo_dir);
+            repo.undo(dry_run)?;
+        }
+        Command::Fetch { remote } => {
+            let repo_dir = find_repo_dir();
+            let repo = open_repo(&repo_dir);
+            repo.fetch(&remote)?;
+        }
+        Command::Pull { autostash } => {
+            let repo_dir = find_repo_dir();
+            let repo = open_repo(&repo_dir);
+            if !repo.pull(autostash)? {
+                std::process::exit(1);
+            }
+        }
+        Command::Push { remote, refspecs } => {
+            let repo_dir = find_repo_dir();
+            let repo = open_repo(&repo_dir);
+            if !repo.push(&remote, &refspecs)? {
+                std::process::exit(1);
+            }
+        }
+        Command::Remote { verbose, action } => {
+            let repo_dir = find_repo_dir();
+            let repo = open_repo(&repo_dir);
+            match action {
+                Some(RemoteCommand::Add { name, url }) => repo.remote_add(&name, &url)?,
+                Some(RemoteCommand::Remove { name }) => repo.remote_remove(&name)?,
+                Some(RemoteCommand::Rename { old, new }) => repo.remote_rename(&old, &new)?,
+                None => {
+                    for (name, url) in repo.remotes()? {
+                        if verbose {
+                            println!("{name}\t{url} (fetch)");
+                            println!("{name}\t{url} (push)");
+                        } else {
+                            println!("{name}");
+                        }
+                    }
+                }
+            }
+        }
+        Command::Stash { action } => {
+            let repo_dir = find_repo_dir();
+            let repo = open_repo(&repo_dir);
+            match action.unwrap_or(StashCommand::Push { message: None }) {
+                StashCommand::Push { message } => repo.stash_push(message.as_deref()),
+                StashCommand::List => repo.stash_list(),
+                StashCommand::Apply { stash } => repo.stash_apply(stash.as_deref(), false),
+                StashCommand::Pop { stash } => repo.stash_apply(stash.as_deref(), true),
+                StashCommand::Drop { stash } => repo.stash_drop(stash.as_deref()),
+            }?;
+        }
+        Command::Reset {
+            target,
+            soft,
+            mixed: _,
+            hard,
+        } => {
+            let repo_dir = find_repo_dir();
+            let repo = open_repo(&repo_dir);
+            let mode = if soft {
+                ResetMode::Soft
+            } else if hard {
+                ResetMode::Hard
+            } else {
+                ResetMode::Mixed
+            };
+            repo.reset(target.as_deref(), mode)?;
+        }
+        Command::Clean {
+            dry_run,
+            force,
+            directories,
+            remove_ignored,
+        } => {
+            let repo_dir = find_repo_dir();
+            let repo = open_repo(&repo_dir);
+            repo.clean(dry_run, force, directories, remove_ignored)?;
+        }
+        Command::MergeBase { lhs, rhs, all } => {
+            let repo_dir = find_repo_dir();
+            let repo = open_repo(&repo_dir);
+            if !repo.merge_base(&lhs, &rhs, all)? {
+                std::process::exit(1);
             }
-            repo.checkout(&target);
-
         }
-        Command::Merge { branch } => {
+        Command::Blame { path } => {
             let repo_dir = find_repo_dir();
             let repo = open_repo(&repo_dir);
-            repo.merge(&branch); 
+            repo.print_blame(&path)?;
         }
-        Command::Status => {
+        Command::Bisect { action } => {
             let repo_dir = find_repo_dir();
             let repo = open_repo(&repo_dir);
-            repo.status();
+            match action {
+                BisectCommand::Start { bad, good } => {
+                    repo.bisect_start(bad.as_deref(), &good)?;
+                }
+                BisectCommand::Good { rev } => {
+                    repo.bisect_good(rev.as_deref())?;
+                }
+                BisectCommand::Bad { rev } => {
+                    repo.bisect_bad(rev.as_deref())?;
+                }
+                BisectCommand::Reset => repo.bisect_reset()?,
+                BisectCommand::Run { cmd } => {
+                    repo.bisect_run(&cmd)?;
+                }
+            }
+        }
+        Command::Archive {
+            tree_ish,
+            output,
+            prefix,
+        } => {
+            let repo_dir = find_repo_dir();
+            let repo = open_repo(&repo_dir);
+            repo.archive(&tree_ish, &output, prefix.as_deref())?;
+        }
+        Command::Submodule { action } => {
+            let repo_dir = find_repo_dir();
+            let repo = open_repo(&repo_dir);
+            match action {
+                SubmoduleCommand::Add { url, path } => {
+                    repo.submodule_add(&url, path.as_deref())?;
+                }
+                SubmoduleCommand::Init => {
+                    repo.submodule_init()?;
+                }
+                SubmoduleCommand::Update { init } => {
+                    if init {
+                        repo.submodule_init()?;
+                    }
+                    repo.submodule_update()?;
+                }
+                SubmoduleCommand::Status => repo.submodule_status()?,
+            }
+        }
+        Command::Bundle { action } => {
+            let repo_dir = find_repo_dir();
+            let repo = open_repo(&repo_dir);
+            match action {
+                BundleCommand::Create { file, revs } => {
+                    repo.bundle_create(&file, &revs)?;
+                }
+                BundleCommand::Verify { file } => {
+                    let bundle = repo.bundle_verify(&file)?;
+                    println!("The bundle contains {} refs:", bundle.refs.len());
+                    for (name, sha) in &bundle.refs {
+                        println!("{sha} {name}");
+                    }
+                    if !bundle.prerequisites.is_empty() {
+                        println!("The bundle requires these commits:");
+                        for (sha, subject) in &bundle.prerequisites {
+                            println!("{sha} {subject}");
+                        }
+                    }
+                    println!("{} is okay", file.display());
+                }
+                BundleCommand::Unbundle { file } => {
+                    for (name, sha) in repo.bundle_unbundle(&file)?.refs {
+                        println!("{sha} {name}");
+                    }
+                }
+            }
+        }
+        Command::Tag {
+            name,
+            commit,
+            message,
+            delete,
+        } => {
+            let repo_dir = find_repo_dir();
+            let repo = open_repo(&repo_dir);
+            match name {
+                Some(name) if delete => {
+                    repo.tag_delete(&name)?;
+                    println!("Deleted tag '{name}'");
+                }
+                Some(name) => {
+                    repo.tag_create(&name, commit.as_deref(), message.as_deref())?;
+                }
+                None => {
+                    for name in repo.list_tags()?.keys() {
+                        println!("{name}");
+                    }
+                }
+            }
+        }
+        Command::Describe { tags, dirty } => {
+            let repo_dir = find_repo_dir();
+            let repo = open_repo(&repo_dir);
+            println!("{}", repo.describe(tags, dirty)?);
+        }
+        Command::Show {
+            object,
+            oneline,
+            format,
+        } => {
+            let repo_dir = find_repo_dir();
+            let repo = open_repo(&repo_dir);
+            repo.show(&object, &pretty_format(oneline, format)?)?;
+        }
+        Command::Log {
+            rev,
+            show_signature,
+            author,
+            grep,
+            since,
+            until,
+            follow,
+            pickaxe,
+            pickaxe_regex,
+            oneline,
+            format,
+            paths,
+        } => {
+            let repo_dir = find_repo_dir();
+            let repo = open_repo(&repo_dir);
+            let options = LogOptions {
+                format: pretty_format(oneline, format)?,
+                show_signature,
+                author,
+                grep,
+                since: since.as_deref().map(parse_expire).transpose()?,
+                until: until.as_deref().map(parse_expire).transpose()?,
+                paths,
+                follow,
+                pickaxe,
+                pickaxe_regex,
+            };
+            repo.log(rev.as_deref(), options)?;
+        }
+        Command::VerifyCommit { commits } => {
+            let repo_dir = find_repo_dir();
+            let repo = open_repo(&repo_dir);
+            let statuses = commits.iter().map(|commit| repo.verify_commit(commit));
+            if !report_signatures(statuses)? {
+                std::process::exit(1);
+            }
+        }
+        Command::VerifyTag { tags } => {
+            let repo_dir = find_repo_dir();
+            let repo = open_repo(&repo_dir);
+            let statuses = tags.iter().map(|tag| repo.verify_tag(tag));
+            if !report_signatures(statuses)? {
+                std::process::exit(1);
+            }
+        }
+        Command::Shortlog {
+            rev,
+            summary,
+            numbered,
+        } => {
+            let repo_dir = find_repo_dir();
+            let repo = open_repo(&repo_dir);
+            repo.shortlog(rev.as_deref(), summary, numbered)?;
+        }
+        Command::Reflog { name } => {
+            let repo_dir = find_repo_dir();
+            let repo = open_repo(&repo_dir);
+            repo.print_reflog(name.as_deref())?;
+        }
+        Command::Cherry {
+            upstream,
+            head,
+            verbose,
+        } => {
+            let repo_dir = find_repo_dir();
+            let repo = open_repo(&repo_dir);
+            repo.cherry(&upstream, head.as_deref(), verbose)?;
+        }
+        Command::Config {
+            global,
+            get,
+            set,
+            list,
+        } => config(global, get, set, list)?,
+    }
+    Ok(())
+}
+
+/// Runs the `config` command against the repository's config, which also
+/// sees the global settings when reading, or only the global one
+fn config(
+    global: bool,
+    get: Option<String>,
+    set: Option<Vec<String>>,
+    list: bool,
+) -> Result<(), Error> {
+    let repo = (!global).then(|| open_repo(&find_repo_dir()));
+    let path = match &repo {
+        Some(repo) => repo.config_path(),
+        None => Config::global_path().ok_or("$HOME is not set")?,
+    };
+    if let Some(set) = set {
+        let mut config = Config::load(&path)?;
+        config.set(&set[0], &set[1])?;
+        config.save(&path)?;
+        return Ok(());
+    }
+    let config = match &repo {
+        Some(repo) => repo.config()?,
+        None => Config::load(&path)?,
+    };
+    if let Some(key) = get {
+        match config.get(&key) {
+            Some(value) => println!("{value}"),
+            None => std::process::exit(1),
+        }
+    } else if list {
+        for (key, value) in config.entries() {
+            println!("{key}={value}");
+        }
+    } else {
+        return Err("one of --get, --set or --list is required".into());
+    }
+    Ok(())
+}
+
+/// The format `--oneline` or `--format` ask for
+/// The commits a single `<a>..<b>` or `<a>...<b>` argument of `diff`
+/// compares
+fn diff_range(repo: &Repository, commits: &[String]) -> Result<Option<(String, String)>, Error> {
+    Ok(match commits {
+        [rev] => repo
+            .diff_range(rev)?
+            .map(|(old, new)| (old.to_string(), new.to_string())),
+        _ => None,
+    })
+}
+
+/// The snapshots `diff` compares for its commit arguments, given the
+/// commits of a range argument as [`diff_range`] found them
+fn diff_snapshots<'a>(
+    range: &'a Option<(String, String)>,
+    commits: &'a [String],
+    cached: bool,
+) -> (Snapshot<'a>, Snapshot<'a>) {
+    let worktree_or_index = if cached {
+        Snapshot::Index
+    } else {
+        Snapshot::Worktree
+    };
+    match (range, commits) {
+        (Some((old, new)), _) => (Snapshot::Commit(old), Snapshot::Commit(new)),
+        (None, []) if cached => (Snapshot::Commit("HEAD"), Snapshot::Index),
+        (None, []) => (Snapshot::Index, Snapshot::Worktree),
+        (None, [commit]) => (Snapshot::Commit(commit), worktree_or_index),
+        (None, [old, new, ..]) => (Snapshot::Commit(old), Snapshot::Commit(new)),
+    }
+}
+
+fn pretty_format(oneline: bool, format: Option<String>) -> Result<PrettyFormat, Error> {
+    match format {
+        _ if oneline => Ok(PrettyFormat::Oneline),
+        Some(format) => Ok(format.parse::<PrettyFormat>()?),
+        None => Ok(PrettyFormat::Medium),
+    }
+}
+
+/// Prints the outcome of `verify-commit` or `verify-tag` for every object
+/// to stderr, like git
+///
+/// # Returns
+/// Whether every signature is good
+fn report_signatures(
+    statuses: impl Iterator<Item = Result<Option<SignatureStatus>, Error>>,
+) -> Result<bool, Error> {
+    let mut all_good = true;
+    for status in statuses {
+        match status? {
+            Some(status) => {
+                eprintln!("{status}");
+                all_good &= matches!(status, SignatureStatus::Good(_));
+            }
+            None => {
+                eprintln!("error: no signature found");
+                all_good = false;
+            }
         }
     }
+    Ok(all_good)
 }
@@ -1,5 +1,7 @@
 use super::EncodedSha;
-use hex;
+use crate::crypt::{self, ObjectCipher};
+use crate::git_source::PackStore;
+use flate2::{Compression, read::ZlibDecoder, write::ZlibEncoder};
 use memchr::memchr;
 use sha1::{Digest, Sha1};
 use std::{
@@ -7,6 +9,10 @@ use std::{
     fs::{self, File},
     io::{Read, Write},
     path::{Path, PathBuf},
+    sync::{
+        OnceLock,
+        atomic::{AtomicUsize, Ordering},
+    },
 };
 
 // Object type enumeration
@@ -15,14 +21,16 @@ pub enum ObjectType {
     Blob,
     Tree,
     Commit,
+    Tag,
 }
 
-impl ToString for ObjectType {
-    fn to_string(&self) -> String {
+impl std::fmt::Display for ObjectType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            ObjectType::Blob => "blob".to_string(),
-            ObjectType::Commit => "commit".to_string(),
-            ObjectType::Tree => "tree".to_string(),
+            ObjectType::Blob => write!(f, "blob"),
+            ObjectType::Commit => write!(f, "commit"),
+            ObjectType::Tree => write!(f, "tree"),
+            ObjectType::Tag => write!(f, "tag"),
         }
     }
 }
@@ -65,6 +73,7 @@ pub fn determine_object_type(data: &[u8]) -> Result<ObjectType, String> {
         "blob" => Ok(ObjectType::Blob),
         "tree" => Ok(ObjectType::Tree),
         "commit" => Ok(ObjectType::Commit),
+        "tag" => Ok(ObjectType::Tag),
         _ => Err(format!("Unknown object type: {}", type_str)),
     }
 }
@@ -170,17 +179,101 @@ impl Blob {
     }
 }
 
+/// File mode of a tree entry, as Git records it
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum FileMode {
+    #[default]
+    Regular,
+    Executable,
+    Symlink,
+    Directory,
+    /// A submodule: the entry names a commit of another repository
+    Gitlink,
+}
+
+impl FileMode {
+    /// The octal mode written into tree objects
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FileMode::Regular => "100644",
+            FileMode::Executable => "100755",
+            FileMode::Symlink => "120000",
+            FileMode::Directory => "40000",
+            FileMode::Gitlink => "160000",
+        }
+    }
+
+    /// Parses a tree entry mode. Old Git versions also wrote group-writable
+    /// files as `100664`.
+    pub fn parse(mode: &str) -> Option<FileMode> {
+        match mode {
+            "100644" | "100664" => Some(FileMode::Regular),
+            "100755" => Some(FileMode::Executable),
+            "120000" => Some(FileMode::Symlink),
+            "40000" => Some(FileMode::Directory),
+            "160000" => Some(FileMode::Gitlink),
+            _ => None,
+        }
+    }
+
+    /// The mode Git records for a file with `metadata`: executable if any
+    /// execute bit is set, regular otherwise
+    pub fn from_metadata(metadata: &fs::Metadata) -> FileMode {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            if metadata.permissions().mode() & 0o111 != 0 {
+                return FileMode::Executable;
+            }
+        }
+        #[cfg(not(unix))]
+        let _ = metadata;
+        FileMode::Regular
+    }
+
+    /// The mode an entry of `object_type` gets when none is given
+    pub fn default_for(object_type: ObjectType) -> FileMode {
+        match object_type {
+            ObjectType::Tree => FileMode::Directory,
+            _ => FileMode::Regular,
+        }
+    }
+
+    /// Type of the object an entry with this mode points at
+    pub fn object_type(&self) -> ObjectType {
+        match self {
+            FileMode::Directory => ObjectType::Tree,
+            FileMode::Gitlink => ObjectType::Commit,
+            _ => ObjectType::Blob,
+        }
+    }
+}
+
 /// Tree entry structure containing metadata
 #[derive(Debug)]
 pub struct TreeEntry {
     pub object_type: ObjectType,
+    pub mode: FileMode,
     pub sha1: EncodedSha,
     pub name: String,
 }
+
+impl TreeEntry {
+    /// Git orders tree entries by name, comparing directories as if their
+    /// name ended in a slash
+    fn sort_key(&self) -> Vec<u8> {
+        let mut key = self.name.as_bytes().to_vec();
+        if self.object_type == ObjectType::Tree {
+            key.push(b'/');
+        }
+        key
+    }
+}
 impl Tree {
-    pub fn get_entries(&self) -> impl Iterator<Item = (&String, &TreeEntry)> {
+    pub fn get_entries(&self) -> impl DoubleEndedIterator<Item = (&String, &TreeEntry)> {
         self.entries.iter()
     }
+    #[allow(dead_code)]
     pub fn get_object_type<S: AsRef<str>>(&self, filename: S) -> Option<ObjectType> {
         let filename = filename.as_ref();
         let entry = match self.entries.get(filename) {
@@ -189,9 +282,16 @@ impl Tree {
             }
             Some(entry) => entry,
         };
-        Some(entry.object_type.clone())
+        Some(entry.object_type)
+    }
+
+    /// File mode of the entry `filename`
+    #[allow(dead_code)]
+    pub fn get_mode<S: AsRef<str>>(&self, filename: S) -> Option<FileMode> {
+        self.entries.get(filename.as_ref()).map(|entry| entry.mode)
     }
 
+    #[allow(dead_code)]
     pub fn get_encoded_sha<S: AsRef<str>>(&self, filename: S) -> Option<EncodedSha> {
         let filename = filename.as_ref();
         let entry = match self.entries.get(filename) {
@@ -203,13 +303,14 @@ impl Tree {
         Some(entry.sha1.clone())
     }
     /// Deserialize a Tree from a byte vector following Git's tree format
+    ///
+    /// Trees written before the binary format was adopted hold one
+    /// "{type} {sha} {name}\n" line per entry and are still understood.
     pub fn deserialize(data: &[u8]) -> Result<Self, Box<dyn std::error::Error>> {
-        let input = std::str::from_utf8(data)?;
-
         // Split header and entries
-        let (header, entries_str) = input
-            .split_once('\0')
-            .ok_or("Invalid format: missing null separator")?;
+        let nul = memchr(0, data).ok_or("Invalid format: missing null separator")?;
+        let header = std::str::from_utf8(&data[..nul])?;
+        let contents = &data[nul + 1..];
 
         // Validate header format "tree {size}"
         let (prefix, size_str) = header.split_once(' ').ok_or("Invalid header format")?;
@@ -219,15 +320,62 @@ impl Tree {
 
         // Parse expected content size
         let expected_size = size_str.parse::<usize>()?;
-        if entries_str.len() != expected_size {
+        if contents.len() != expected_size {
             return Err(format!(
                 "Size mismatch: expected {}, actual {}",
                 expected_size,
-                entries_str.len()
+                contents.len()
             )
             .into());
         }
 
+        // Binary entries start with their octal mode
+        let entries = match contents.first() {
+            Some(byte) if byte.is_ascii_digit() => Self::parse_binary_entries(contents)?,
+            _ => Self::parse_text_entries(std::str::from_utf8(contents)?)?,
+        };
+        Ok(Tree { entries })
+    }
+
+    /// Parses entries in Git's "{mode} {name}\0{20-byte sha}" format
+    fn parse_binary_entries(
+        mut rest: &[u8],
+    ) -> Result<BTreeMap<String, TreeEntry>, Box<dyn std::error::Error>> {
+        let mut entries = BTreeMap::new();
+        while !rest.is_empty() {
+            let space = memchr(b' ', rest).ok_or("Missing filename")?;
+            let nul = memchr(0, rest).ok_or("Missing SHA hash")?;
+            if nul < space {
+                return Err("Missing filename".into());
+            }
+            let mode = std::str::from_utf8(&rest[..space])?;
+            let mode = FileMode::parse(mode).ok_or_else(|| format!("Invalid mode: {}", mode))?;
+            let name = std::str::from_utf8(&rest[space + 1..nul])?.to_string();
+            let sha = rest.get(nul + 1..nul + 21).ok_or("Truncated SHA hash")?;
+            rest = &rest[nul + 21..];
+
+            // Validate unique filenames
+            if entries.contains_key(&name) {
+                return Err(format!("Duplicate entry: {}", name).into());
+            }
+
+            entries.insert(
+                name.clone(),
+                TreeEntry {
+                    object_type: mode.object_type(),
+                    mode,
+                    sha1: EncodedSha(hex::encode(sha)),
+                    name,
+                },
+            );
+        }
+        Ok(entries)
+    }
+
+    /// Parses entries in the legacy "{type} {sha} {name}\n" format
+    fn parse_text_entries(
+        entries_str: &str,
+    ) -> Result<BTreeMap<String, TreeEntry>, Box<dyn std::error::Error>> {
         let mut entries = BTreeMap::new();
 
         // Parse each entry
@@ -257,13 +405,14 @@ impl Tree {
                 name.clone(),
                 TreeEntry {
                     object_type,
+                    mode: FileMode::default_for(object_type),
                     sha1,
                     name,
                 },
             );
         }
 
-        Ok(Tree { entries })
+        Ok(entries)
     }
     /// Create a new empty tree
     pub fn new() -> Self {
@@ -272,13 +421,20 @@ impl Tree {
         }
     }
 
-    /// Add an entry to the tree with automatic sorting
+    /// Add an entry to the tree with automatic sorting. Blobs are recorded
+    /// as regular files.
     pub fn add_entry(&mut self, object_type: ObjectType, sha1: &EncodedSha, name: &String) {
+        self.add_entry_with_mode(FileMode::default_for(object_type), sha1, name);
+    }
+
+    /// Add an entry with an explicit file mode, such as an executable
+    pub fn add_entry_with_mode(&mut self, mode: FileMode, sha1: &EncodedSha, name: &String) {
         // Use BTreeMap to maintain sorted order by filename
         self.entries.insert(
             name.to_string(),
             TreeEntry {
-                object_type: object_type.clone(),
+                object_type: mode.object_type(),
+                mode,
                 sha1: sha1.clone(),
                 name: name.clone(),
             },
@@ -294,26 +450,77 @@ pub struct Tree {
 // Database structure
 pub struct ObjectDB {
     path: PathBuf,
+    /// Store consulted when an object is missing from `path`, so a
+    /// quarantine can see the objects of the repository it belongs to
+    alternate: Option<PathBuf>,
+    /// Encrypts object files at rest when the repository has a key
+    cipher: Option<ObjectCipher>,
+    /// Pack files Git wrote, loaded when an object is first missing
+    packs: OnceLock<Result<PackStore, String>>,
+}
+
+/// Temporary object store for incoming objects.
+///
+/// Objects written to a quarantine are invisible to the main store until
+/// `migrate` moves them over; dropping the quarantine discards them. This
+/// keeps half-received object sets out of the repository.
+pub struct Quarantine {
+    db: ObjectDB,
+    target: PathBuf,
+    migrated: bool,
+}
+
+/// An object that is already serialized
+struct Serialized(Vec<u8>);
+
+impl Object for Serialized {
+    fn serialize(&self) -> Vec<u8> {
+        self.0.clone()
+    }
+}
+
+/// Makes temporary file names unique within this process
+static TEMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+fn unique_suffix() -> String {
+    let count = TEMP_COUNTER.fetch_add(1, Ordering::Relaxed);
+    format!("{}_{}", std::process::id(), count)
+}
+
+/// Whether loose object data starts with a zlib header. Objects written
+/// before they were deflated start with their type name instead, which never
+/// forms a valid header.
+fn is_zlib(data: &[u8]) -> bool {
+    match data {
+        [cmf, flg, ..] => cmf & 0x0f == 8 && (u16::from(*cmf) << 8 | u16::from(*flg)) % 31 == 0,
+        _ => false,
+    }
+}
+
+/// Inflates loose object data, passing legacy uncompressed objects through
+fn inflate_object(data: Vec<u8>) -> std::io::Result<Vec<u8>> {
+    if !is_zlib(&data) {
+        return Ok(data);
+    }
+    let mut inflated = Vec::new();
+    ZlibDecoder::new(data.as_slice()).read_to_end(&mut inflated)?;
+    Ok(inflated)
 }
 
 impl Object for Tree {
-    /// Serialize tree following "tree {size}\0{entries}" format
-    /// Entry format: "{type} {sha} {name}\n"
+    /// Serialize tree following Git's "tree {size}\0{entries}" format
+    /// Entry format: "{mode} {name}\0{20-byte sha}", so trees hash like Git's
     fn serialize(&self) -> Vec<u8> {
-        // Generate entry lines with sorted order
-        let contents: Vec<u8> = self
-            .entries
-            .values()
-            .flat_map(|entry| {
-                format!(
-                    "{} {} {}\n",
-                    entry.object_type.to_string(),
-                    entry.sha1.0,
-                    entry.name
-                )
-                .into_bytes()
-            })
-            .collect();
+        let mut entries: Vec<&TreeEntry> = self.entries.values().collect();
+        entries.sort_by_cached_key(|entry| entry.sort_key());
+        let mut contents = Vec::new();
+        for entry in entries {
+            contents.extend(entry.mode.as_str().as_bytes());
+            contents.push(b' ');
+            contents.extend(entry.name.as_bytes());
+            contents.push(0);
+            contents.extend(hex::decode(&entry.sha1.0).expect("tree entry SHA1 is hex"));
+        }
 
         // Build header with size and null separator
         let header = format!("tree {}\0", contents.len());
@@ -342,6 +549,15 @@ impl Author {
             timestamp,
         }
     }
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+    pub fn get_email(&self) -> &str {
+        &self.email
+    }
+    pub fn get_timestamp(&self) -> DateTime<FixedOffset> {
+        self.timestamp
+    }
 }
 
 impl Display for Author {
@@ -360,11 +576,12 @@ impl Display for Author {
 /// Git commit object structure
 #[derive(Debug)]
 pub struct Commit {
-    tree_sha: EncodedSha,     // SHA1 of the top-level tree object
-    parents: Vec<EncodedSha>, // List of parent commit SHA1s
-    author: Author,           // Author information
-    committer: Author,        // Committer information
-    message: String,          // Commit message
+    tree_sha: EncodedSha,      // SHA1 of the top-level tree object
+    parents: Vec<EncodedSha>,  // List of parent commit SHA1s
+    author: Author,            // Author information
+    committer: Author,         // Committer information
+    signature: Option<String>, // Detached signature of the rest, the `gpgsig` header
+    message: String,           // Commit message
 }
 
 impl Commit {
@@ -376,10 +593,11 @@ impl Commit {
         message: &str,
     ) -> Self {
         Self {
-            tree_sha: tree_sha,
+            tree_sha,
             parents,
             author,
             committer,
+            signature: None,
             message: message.to_string(),
         }
     }
@@ -389,6 +607,24 @@ impl Commit {
     pub fn get_tree_sha(&self) -> EncodedSha {
         self.tree_sha.clone()
     }
+    pub fn get_message(&self) -> &str {
+        &self.message
+    }
+    pub fn get_author(&self) -> &Author {
+        &self.author
+    }
+    pub fn get_committer(&self) -> &Author {
+        &self.committer
+    }
+    /// The armored signature of the commit, without its trailing newline
+    pub fn get_signature(&self) -> Option<&str> {
+        self.signature.as_deref()
+    }
+    /// Signs the commit with `signature`, made over the commit as written
+    /// without one
+    pub fn set_signature(&mut self, signature: &str) {
+        self.signature = Some(signature.trim_end().to_string());
+    }
 }
 
 impl Display for Commit {
@@ -405,6 +641,14 @@ impl Display for Commit {
         writeln!(f, "author {}", self.author)?;
         writeln!(f, "committer {}", self.committer)?;
 
+        // The signature continues on lines starting with a space
+        if let Some(signature) = &self.signature {
+            for (i, line) in signature.lines().enumerate() {
+                let key = if i == 0 { "gpgsig" } else { "" };
+                writeln!(f, "{key} {line}")?;
+            }
+        }
+
         // Empty line to separate header and message
         writeln!(f)?;
 
@@ -486,28 +730,41 @@ fn parse_header(header: &str) -> Result<(&str, usize), String> {
 /// Helper to parse commit content
 fn parse_commit_content(content: &[u8]) -> Result<Commit, String> {
     let content_str = std::str::from_utf8(content).map_err(|e| e.to_string())?;
-    let mut lines = content_str.lines();
+    let lines = content_str.lines();
 
     let mut tree_sha = None;
     let mut parents = Vec::new();
     let mut author = None;
     let mut committer = None;
+    let mut signature: Option<String> = None;
+    let mut in_signature = false;
     let mut message = String::new();
     let mut in_message = false;
 
     // Parse header lines
-    while let Some(line) = lines.next() {
-        if line.is_empty() {
+    for line in lines {
+        if line.is_empty() && !in_message {
             in_message = true;
             continue;
         }
 
+        // The message keeps its blank lines between paragraphs
         if in_message {
             message.push_str(line);
             message.push('\n');
             continue;
         }
 
+        if in_signature
+            && let Some(signature) = &mut signature
+            && let Some(continuation) = line.strip_prefix(' ')
+        {
+            signature.push('\n');
+            signature.push_str(continuation);
+            continue;
+        }
+        in_signature = false;
+
         if let Some(sha) = line.strip_prefix("tree ") {
             tree_sha = Some(sha.to_string());
         } else if let Some(parent_sha) = line.strip_prefix("parent ") {
@@ -516,6 +773,9 @@ fn parse_commit_content(content: &[u8]) -> Result<Commit, String> {
             author = Some(parse_author(auth_info)?);
         } else if let Some(committer_info) = line.strip_prefix("committer ") {
             committer = Some(parse_author(committer_info)?);
+        } else if let Some(first) = line.strip_prefix("gpgsig ") {
+            signature = Some(first.to_string());
+            in_signature = true;
         } else {
             return Err(format!("Unexpected line: {}", line));
         }
@@ -535,6 +795,7 @@ fn parse_commit_content(content: &[u8]) -> Result<Commit, String> {
         parents,
         author,
         committer,
+        signature,
         message,
     })
 }
@@ -558,6 +819,125 @@ fn parse_author(s: &str) -> Result<Author, String> {
 
     Ok(Author::new(name, email, dt))
 }
+
+/// Git annotated tag object: a named pointer to another object, usually a
+/// commit, with a tagger and a message
+#[derive(Debug)]
+pub struct Tag {
+    object: EncodedSha,      // SHA1 of the tagged object
+    object_type: ObjectType, // Type of the tagged object
+    name: String,            // Tag name, without refs/tags/
+    tagger: Author,          // Who made the tag and when
+    message: String,         // Tag message
+}
+
+impl Tag {
+    pub fn new(
+        object: EncodedSha,
+        object_type: ObjectType,
+        name: &str,
+        tagger: Author,
+        message: &str,
+    ) -> Self {
+        Self {
+            object,
+            object_type,
+            name: name.to_string(),
+            tagger,
+            message: message.to_string(),
+        }
+    }
+    pub fn get_object(&self) -> &EncodedSha {
+        &self.object
+    }
+    pub fn get_object_type(&self) -> ObjectType {
+        self.object_type
+    }
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+    pub fn get_tagger(&self) -> &Author {
+        &self.tagger
+    }
+    pub fn get_message(&self) -> &str {
+        &self.message
+    }
+
+    /// Deserialize raw object data in "tag {size}\0{content}" format into
+    /// a Tag instance
+    pub fn deserialize(data: &[u8]) -> Result<Self, String> {
+        let null_pos = memchr(0, data).ok_or("Missing null byte separator")?;
+        let header = std::str::from_utf8(&data[..null_pos]).map_err(|e| e.to_string())?;
+        let content = &data[null_pos + 1..];
+        let (obj_type, obj_size) = parse_header(header)?;
+        if obj_type != "tag" {
+            return Err(format!("Expected tag object, got {}", obj_type));
+        }
+        if content.len() != obj_size {
+            return Err(format!(
+                "Size mismatch: header {} vs actual {}",
+                obj_size,
+                content.len()
+            ));
+        }
+
+        let content = std::str::from_utf8(content).map_err(|e| e.to_string())?;
+        let (headers, message) = content.split_once("\n\n").unwrap_or((content, ""));
+        let mut object = None;
+        let mut object_type = None;
+        let mut name = None;
+        let mut tagger = None;
+        for line in headers.lines() {
+            if let Some(sha) = line.strip_prefix("object ") {
+                object = Some(EncodedSha(sha.to_string()));
+            } else if let Some(kind) = line.strip_prefix("type ") {
+                object_type = Some(match kind {
+                    "blob" => ObjectType::Blob,
+                    "tree" => ObjectType::Tree,
+                    "commit" => ObjectType::Commit,
+                    "tag" => ObjectType::Tag,
+                    _ => return Err(format!("Unknown tagged object type: {}", kind)),
+                });
+            } else if let Some(tag_name) = line.strip_prefix("tag ") {
+                name = Some(tag_name.to_string());
+            } else if let Some(tagger_info) = line.strip_prefix("tagger ") {
+                tagger = Some(parse_author(tagger_info)?);
+            } else {
+                return Err(format!("Unexpected line: {}", line));
+            }
+        }
+
+        Ok(Tag {
+            object: object.ok_or("Missing tagged object")?,
+            object_type: object_type.ok_or("Missing tagged object type")?,
+            name: name.ok_or("Missing tag name")?,
+            tagger: tagger.ok_or("Missing tagger")?,
+            message: message.trim_end().to_string(),
+        })
+    }
+}
+
+impl Display for Tag {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "object {}", self.object)?;
+        writeln!(f, "type {}", self.object_type)?;
+        writeln!(f, "tag {}", self.name)?;
+        writeln!(f, "tagger {}", self.tagger)?;
+        writeln!(f)?;
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Object for Tag {
+    /// Serialize tag object following Git's object format:
+    /// "tag {content_length}\0{header}{message}"
+    fn serialize(&self) -> Vec<u8> {
+        let content = self.to_string();
+        let mut bytes = format!("tag {}\0", content.len()).into_bytes();
+        bytes.extend_from_slice(content.as_bytes());
+        bytes
+    }
+}
 impl ObjectDB {
     /// Create new object database
     pub fn new(path: &Path) -> Result<ObjectDB, &str> {
@@ -565,10 +945,26 @@ impl ObjectDB {
             return Err("Objects dir not exists!");
         }
         let path_buf = path.to_path_buf();
-        Ok(ObjectDB { path: path_buf })
+        Ok(ObjectDB {
+            path: path_buf,
+            alternate: None,
+            cipher: None,
+            packs: OnceLock::new(),
+        })
+    }
+
+    /// Encrypt objects written from now on and decrypt objects on retrieval.
+    /// SHA1s keep being computed over the plaintext.
+    pub fn set_cipher(&mut self, cipher: ObjectCipher) {
+        self.cipher = Some(cipher);
     }
 
     /// Store object in database
+    ///
+    /// Like Git, the serialized object is zlib-deflated before it is
+    /// written (and encrypted after that). The object is written to a
+    /// temporary file first and renamed into place, so concurrent readers
+    /// never see a partially written object.
     pub fn store(&self, obj: &impl Object) -> std::io::Result<EncodedSha> {
         // Generate SHA1 hash
         let encoded_sha = obj.encoded_sha1();
@@ -584,13 +980,43 @@ impl ObjectDB {
             fs::create_dir_all(&obj_dir)?;
 
             // Write data
-            let mut file = File::create(&obj_path)?;
-            file.write_all(&obj.serialize())?;
+            let written = self.write_object_file(&encoded_sha, &obj.serialize(), &obj_path);
+            // Another process may have stored the same object meanwhile
+            if let Err(why) = written
+                && !obj_path.exists()
+            {
+                return Err(why);
+            }
         }
 
         Ok(EncodedSha(encoded_sha))
     }
 
+    /// Deflates and encrypts `data` and atomically replaces `obj_path` with it
+    fn write_object_file(
+        &self,
+        encoded_sha: &str,
+        data: &[u8],
+        obj_path: &Path,
+    ) -> std::io::Result<()> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data)?;
+        let mut data = encoder.finish()?;
+        if let Some(cipher) = &self.cipher {
+            data = cipher.encrypt(encoded_sha, &data);
+        }
+        let temp_path = self.path.join(format!("tmp_obj_{}", unique_suffix()));
+        let mut file = File::create_new(&temp_path)?;
+        let written = file
+            .write_all(&data)
+            .and_then(|_| file.sync_all())
+            .and_then(|_| fs::rename(&temp_path, obj_path));
+        if written.is_err() {
+            let _ = fs::remove_file(&temp_path);
+        }
+        written
+    }
+
     /// Retrieve object from database
     pub fn retrieve<E: AsRef<EncodedSha>>(&self, encoded_sha: E) -> std::io::Result<Vec<u8>> {
         // Validate SHA format
@@ -604,14 +1030,182 @@ impl ObjectDB {
 
         // Parse path
         let (dir_part, file_part) = encoded_sha.split_at(2);
-        let obj_path = self.path.join(dir_part).join(file_part);
+        let mut obj_path = self.path.join(dir_part).join(file_part);
+        if let Some(alternate) = &self.alternate
+            && !obj_path.exists()
+        {
+            obj_path = alternate.join(dir_part).join(file_part);
+        }
+        if !obj_path.exists()
+            && let Some(data) = self.retrieve_packed(encoded_sha)?
+        {
+            return Ok(data);
+        }
 
         // Read file
         let mut file = File::open(obj_path)?;
         let mut contents = Vec::new();
         file.read_to_end(&mut contents)?;
 
-        Ok(contents)
+        inflate_object(self.decrypt_object_file(encoded_sha, contents)?)
+    }
+
+    /// Reads an object from the pack files Git keeps in `objects/pack`,
+    /// e.g. after `git gc`, in the serialized form of a loose object
+    fn retrieve_packed(&self, encoded_sha: &str) -> std::io::Result<Option<Vec<u8>>> {
+        let invalid =
+            |why: &String| std::io::Error::new(std::io::ErrorKind::InvalidData, why.clone());
+        let Some(packed) = self.packs()?.read(encoded_sha) else {
+            return Ok(None);
+        };
+        let (kind, content) = packed.map_err(|why| invalid(&why))?;
+        let mut data = format!("{} {}\0", kind.name(), content.len()).into_bytes();
+        data.extend(content);
+        Ok(Some(data))
+    }
+
+    /// The pack files of the store, loaded on first use
+    fn packs(&self) -> std::io::Result<&PackStore> {
+        self.packs
+            .get_or_init(|| PackStore::open(self.alternate.as_ref().unwrap_or(&self.path)))
+            .as_ref()
+            .map_err(|why| std::io::Error::new(std::io::ErrorKind::InvalidData, why.clone()))
+    }
+
+    /// Lists every object the pack files of the store hold, sorted
+    pub fn packed_objects(&self) -> std::io::Result<Vec<EncodedSha>> {
+        let mut objects: Vec<EncodedSha> = self
+            .packs()?
+            .objects()
+            .map(|sha| EncodedSha(sha.to_string()))
+            .collect();
+        objects.sort_by(|a, b| a.0.cmp(&b.0));
+        objects.dedup();
+        Ok(objects)
+    }
+
+    /// Stores an object given in its serialized form, such as one read
+    /// from a pack, as a loose object
+    pub fn store_serialized(&self, data: Vec<u8>) -> std::io::Result<EncodedSha> {
+        self.store(&Serialized(data))
+    }
+
+    /// Decrypts the contents of the file of `encoded_sha` if the store is
+    /// encrypted
+    fn decrypt_object_file(
+        &self,
+        encoded_sha: &str,
+        contents: Vec<u8>,
+    ) -> std::io::Result<Vec<u8>> {
+        match &self.cipher {
+            Some(cipher) => cipher
+                .decrypt(encoded_sha, &contents)
+                .map_err(|why| std::io::Error::new(std::io::ErrorKind::InvalidData, why)),
+            None if crypt::is_encrypted(&contents) => Err(std::io::Error::new(
+                std::io::ErrorKind::PermissionDenied,
+                format!("object is encrypted; set {} to read it", crypt::KEY_ENV),
+            )),
+            None => Ok(contents),
+        }
+    }
+
+    /// Rewrites the loose objects written before objects were deflated,
+    /// returning how many were converted
+    pub fn compress_legacy_objects(&self) -> std::io::Result<usize> {
+        let mut converted = 0;
+        for encoded_sha in self.loose_objects()? {
+            let obj_path = self.object_path(&encoded_sha);
+            let contents = self.decrypt_object_file(&encoded_sha.0, fs::read(&obj_path)?)?;
+            if is_zlib(&contents) {
+                continue;
+            }
+            self.write_object_file(&encoded_sha.0, &contents, &obj_path)?;
+            converted += 1;
+        }
+        Ok(converted)
+    }
+
+    /// Path of the loose object file for `encoded_sha`
+    pub fn object_path(&self, encoded_sha: &EncodedSha) -> PathBuf {
+        let (dir_part, file_part) = encoded_sha.0.split_at(2);
+        self.path.join(dir_part).join(file_part)
+    }
+
+    /// Lists every loose object in the store, skipping quarantines and
+    /// temporary files
+    pub fn loose_objects(&self) -> std::io::Result<Vec<EncodedSha>> {
+        let is_hex = |name: &str, len: usize| {
+            name.len() == len && name.chars().all(|c| c.is_ascii_hexdigit())
+        };
+        let mut objects = Vec::new();
+        for dir in fs::read_dir(&self.path)? {
+            let dir = dir?;
+            let dir_name = dir.file_name().to_string_lossy().to_string();
+            if !is_hex(&dir_name, 2) || !dir.file_type()?.is_dir() {
+                continue;
+            }
+            for object in fs::read_dir(dir.path())? {
+                let file_name = object?.file_name().to_string_lossy().to_string();
+                if is_hex(&file_name, 38) {
+                    objects.push(EncodedSha(format!("{dir_name}{file_name}")));
+                }
+            }
+        }
+        objects.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(objects)
+    }
+
+    /// Creates an empty quarantine below this store's directory
+    pub fn quarantine(&self) -> std::io::Result<Quarantine> {
+        let path = self.path.join(format!("incoming-{}", unique_suffix()));
+        fs::create_dir(&path)?;
+        Ok(Quarantine {
+            db: ObjectDB {
+                path,
+                alternate: Some(self.path.clone()),
+                cipher: self.cipher.clone(),
+                packs: OnceLock::new(),
+            },
+            target: self.path.clone(),
+            migrated: false,
+        })
+    }
+}
+
+impl Quarantine {
+    /// Object database that writes into the quarantine and reads through
+    /// to the main store
+    pub fn db(&self) -> &ObjectDB {
+        &self.db
+    }
+
+    /// Moves every quarantined object into the main store
+    pub fn migrate(mut self) -> std::io::Result<()> {
+        for dir in fs::read_dir(&self.db.path)? {
+            let dir = dir?;
+            if !dir.file_type()?.is_dir() {
+                continue;
+            }
+            let target_dir = self.target.join(dir.file_name());
+            fs::create_dir_all(&target_dir)?;
+            for object in fs::read_dir(dir.path())? {
+                let object = object?;
+                let target_path = target_dir.join(object.file_name());
+                if !target_path.exists() {
+                    fs::rename(object.path(), target_path)?;
+                }
+            }
+        }
+        self.migrated = true;
+        fs::remove_dir_all(&self.db.path)
+    }
+}
+
+impl Drop for Quarantine {
+    fn drop(&mut self) {
+        if !self.migrated {
+            let _ = fs::remove_dir_all(&self.db.path);
+        }
     }
 }
 
@@ -670,38 +1264,31 @@ mod tree_tests {
         let mut tree = Tree::new();
         let entry1 = TreeEntry {
             object_type: ObjectType::Blob,
-            sha1: EncodedSha {
-                0: "a906cb2a4a904a152e80877d4088654daad0c859".to_string(),
-            },
+            mode: FileMode::Regular,
+            sha1: EncodedSha("a906cb2a4a904a152e80877d4088654daad0c859".to_string()),
             name: "README".into(),
         };
         let entry2 = TreeEntry {
             object_type: ObjectType::Tree,
-            sha1: EncodedSha {
-                0: "99f1a6d12cb4b6f19c8655fca46c3ecf317074e0".to_string(),
-            },
+            mode: FileMode::Directory,
+            sha1: EncodedSha("99f1a6d12cb4b6f19c8655fca46c3ecf317074e0".to_string()),
             name: "lib".into(),
         };
         // Add test entries
-        tree.add_entry(entry1.object_type.clone(), &entry1.sha1, &entry1.name);
+        tree.add_entry(entry1.object_type, &entry1.sha1, &entry1.name);
 
-        tree.add_entry(entry2.object_type.clone(), &entry2.sha1, &entry2.name);
+        tree.add_entry(entry2.object_type, &entry2.sha1, &entry2.name);
 
         // Verify serialization format
         let data = tree.serialize();
-        let expected_content = format!(
-            "{} {} {}\n{} {} {}\n",
-            entry1.object_type.to_string(),
-            entry1.sha1.0,
-            entry1.name,
-            entry2.object_type.to_string(),
-            entry2.sha1.0,
-            entry2.name
-        );
+        let mut expected_content = b"100644 README\0".to_vec();
+        expected_content.extend(hex::decode(&entry1.sha1.0).unwrap());
+        expected_content.extend(b"40000 lib\0");
+        expected_content.extend(hex::decode(&entry2.sha1.0).unwrap());
         let expected_header = format!("tree {}\0", expected_content.len());
 
         assert!(data.starts_with(expected_header.as_bytes()));
-        assert!(data.ends_with(expected_content.as_bytes()));
+        assert!(data.ends_with(&expected_content));
         let deserialized_tree = Tree::deserialize(&data).unwrap();
         assert_eq!(
             deserialized_tree.get_object_type(&entry1.name).unwrap(),
@@ -720,6 +1307,41 @@ mod tree_tests {
             entry2.sha1
         );
     }
+    #[test]
+    fn tree_hashes_match_git() {
+        let blob = Blob {
+            data: b"hello".to_vec(),
+        };
+        let blob_sha = EncodedSha(blob.encoded_sha1());
+        assert_eq!(blob_sha.0, "b6fc4c620b67d95f953a5c1c1230aaab5db5a1b0");
+        assert_eq!(
+            Tree::new().encoded_sha1(),
+            "4b825dc642cb6eb9a060e54bf8d69288fbee4904"
+        );
+
+        let mut sub = Tree::new();
+        sub.add_entry(ObjectType::Blob, &blob_sha, &"x".to_string());
+        let sub_sha = EncodedSha(sub.encoded_sha1());
+        assert_eq!(sub_sha.0, "2e4ba6a73c4476b0d49115fe8c7d60c2840e08ff");
+
+        // The directory "a" sorts after "a.txt", as if it were "a/"
+        let mut tree = Tree::new();
+        tree.add_entry(ObjectType::Tree, &sub_sha, &"a".to_string());
+        tree.add_entry(ObjectType::Blob, &blob_sha, &"a.txt".to_string());
+        tree.add_entry_with_mode(FileMode::Executable, &blob_sha, &"run".to_string());
+        tree.add_entry_with_mode(FileMode::Symlink, &blob_sha, &"link".to_string());
+        assert_eq!(
+            tree.encoded_sha1(),
+            "5bdac351ee236d27d2ece146a48efb0bb5c46d35"
+        );
+
+        let parsed = Tree::deserialize(&tree.serialize()).unwrap();
+        assert_eq!(parsed.get_mode("run"), Some(FileMode::Executable));
+        assert_eq!(parsed.get_mode("link"), Some(FileMode::Symlink));
+        assert_eq!(parsed.get_object_type("a"), Some(ObjectType::Tree));
+        assert_eq!(parsed.get_encoded_sha("a.txt"), Some(blob_sha));
+    }
+
     #[test]
     fn test_filename_with_spaces() {
         let data = b"tree 61\0blob 0000000000000000000000000000000000000000 file with space";
@@ -790,8 +1412,6 @@ mod tree_tests {
 }
 #[cfg(test)]
 mod tests {
-    use std::str::FromStr;
-
     use super::*;
     use tempfile::TempDir;
 
@@ -836,14 +1456,107 @@ mod tests {
         let sha2 = db.store(&obj).unwrap();
 
         assert_eq!(sha1, sha2);
+        // No temporary files are left behind
+        let entries: Vec<_> = fs::read_dir(temp_dir.path()).unwrap().collect();
+        assert_eq!(entries.len(), 1);
+    }
+
+    #[test]
+    fn objects_are_deflated_and_legacy_objects_readable() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = ObjectDB::new(temp_dir.path()).unwrap();
+        let obj = TestObject(b"blob 5\0hello".to_vec());
+        let sha = db.store(&obj).unwrap();
+        let mut inflated = Vec::new();
+        ZlibDecoder::new(File::open(db.object_path(&sha)).unwrap())
+            .read_to_end(&mut inflated)
+            .unwrap();
+        assert_eq!(inflated, obj.serialize());
+        assert_eq!(db.retrieve(&sha).unwrap(), obj.serialize());
+
+        // Objects written before deflating still read back and convert
+        let legacy = TestObject(b"blob 6\0legacy".to_vec());
+        let legacy_sha = EncodedSha(legacy.encoded_sha1());
+        let legacy_path = db.object_path(&legacy_sha);
+        fs::create_dir_all(legacy_path.parent().unwrap()).unwrap();
+        fs::write(&legacy_path, legacy.serialize()).unwrap();
+        assert_eq!(db.retrieve(&legacy_sha).unwrap(), legacy.serialize());
+        assert_eq!(db.compress_legacy_objects().unwrap(), 1);
+        assert!(is_zlib(&fs::read(&legacy_path).unwrap()));
+        assert_eq!(db.retrieve(&legacy_sha).unwrap(), legacy.serialize());
+        assert_eq!(db.compress_legacy_objects().unwrap(), 0);
+    }
+
+    #[test]
+    fn reads_objects_git_has_packed() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = ObjectDB::new(temp_dir.path()).unwrap();
+        let blob = Blob {
+            data: b"packed".to_vec(),
+        };
+        let sha = blob.encoded_sha1();
+
+        // A pack holding the blob undeltified, and its version 2 index
+        let mut pack = b"PACK\0\0\0\x02\0\0\0\x01".to_vec();
+        pack.push(0x30 | blob.data.len() as u8);
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&blob.data).unwrap();
+        pack.extend(encoder.finish().unwrap());
+        let mut idx = b"\xfftOc\0\0\0\x02".to_vec();
+        let first = u32::from_str_radix(&sha[..2], 16).unwrap();
+        for i in 0..256u32 {
+            idx.extend((if i >= first { 1u32 } else { 0 }).to_be_bytes());
+        }
+        idx.extend(hex::decode(&sha).unwrap());
+        idx.extend([0; 4]);
+        idx.extend(12u32.to_be_bytes());
+        let pack_dir = temp_dir.path().join("pack");
+        fs::create_dir(&pack_dir).unwrap();
+        fs::write(pack_dir.join("pack-1.pack"), pack).unwrap();
+        fs::write(pack_dir.join("pack-1.idx"), idx).unwrap();
+
+        let sha = EncodedSha(sha);
+        assert_eq!(db.retrieve(&sha).unwrap(), blob.serialize());
+        assert!(db.retrieve(EncodedSha("ab".repeat(20))).is_err());
+    }
+
+    #[test]
+    fn quarantine_migrates_or_discards() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = ObjectDB::new(temp_dir.path()).unwrap();
+        let existing = db.store(&TestObject(b"existing".to_vec())).unwrap();
+
+        let quarantine = db.quarantine().unwrap();
+        let incoming = quarantine
+            .db()
+            .store(&TestObject(b"incoming".to_vec()))
+            .unwrap();
+        assert!(quarantine.db().retrieve(&existing).is_ok());
+        assert!(db.retrieve(&incoming).is_err());
+        drop(quarantine);
+        assert!(db.retrieve(&incoming).is_err());
+
+        let quarantine = db.quarantine().unwrap();
+        let incoming = quarantine
+            .db()
+            .store(&TestObject(b"incoming".to_vec()))
+            .unwrap();
+        quarantine.migrate().unwrap();
+        assert!(db.retrieve(&incoming).is_ok());
+        let entries: Vec<_> = fs::read_dir(temp_dir.path()).unwrap().collect();
+        assert_eq!(entries.len(), 2);
     }
+
     #[test]
     fn determine_type_works() {
         let blob_data = b"blob 12\0hello world";
         assert_eq!(determine_object_type(blob_data), Ok(ObjectType::Blob));
 
-        let invalid_data = b"tag 5\0data";
-        assert!(matches!(determine_object_type(invalid_data), Err(_)));
+        let tag_data = b"tag 5\0data";
+        assert_eq!(determine_object_type(tag_data), Ok(ObjectType::Tag));
+
+        let invalid_data = b"note 5\0data";
+        assert!(determine_object_type(invalid_data).is_err());
     }
     #[test]
     fn test_serialize_empty_blob() {
@@ -960,6 +1673,33 @@ Add new functionality"#;
         assert_eq!(commit.to_string(), expected);
     }
 
+    #[test]
+    fn signed_commit_round_trips() {
+        let author = create_sample_author();
+        let mut commit = Commit::new(
+            EncodedSha::from_str("b45ef6fec89518d314f546fd3b302bf7a11b0d18").unwrap(),
+            vec![],
+            author.clone(),
+            author,
+            "Signed commit",
+        );
+        commit.set_signature("-----BEGIN PGP SIGNATURE-----\n\niQE\n-----END PGP SIGNATURE-----\n");
+
+        let expected = r#"tree b45ef6fec89518d314f546fd3b302bf7a11b0d18
+author Alice <alice@example.com> 1689820200 +0800
+committer Alice <alice@example.com> 1689820200 +0800
+gpgsig -----BEGIN PGP SIGNATURE-----
+ 
+ iQE
+ -----END PGP SIGNATURE-----
+
+Signed commit"#;
+        assert_eq!(commit.to_string(), expected);
+        let parsed = Commit::deserialize(&commit.serialize()).unwrap();
+        assert_eq!(parsed.serialize(), commit.serialize());
+        assert_eq!(parsed.get_message(), "Signed commit");
+    }
+
     #[test]
     fn test_author_formatting() {
         let timestamp = FixedOffset::east_opt(-5 * 3600)
@@ -0,0 +1,485 @@
+use chrono::{DateTime, FixedOffset};
+
+/// How many context lines a hunk may lose at either end when its context
+/// no longer matches exactly, like the default fuzz factor of `patch`
+pub const MAX_FUZZ: usize = 2;
+
+/// What a line of a hunk does
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineKind {
+    /// Unchanged, only there to locate the hunk
+    Context,
+    Removed,
+    Added,
+}
+
+/// One line of a hunk. The text keeps its line terminator, unless the
+/// patch marks it as the last line of a file without newline.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HunkLine {
+    pub kind: LineKind,
+    pub text: String,
+}
+
+/// A run of changes with the context around them, as introduced by
+/// `@@ -<old_start>,<len> +<new_start>,<len> @@`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hunk {
+    /// First line the hunk covers in the old file, counting from 1; for a
+    /// hunk without old lines, the line it goes after
+    pub old_start: usize,
+    /// Same as `old_start`, in the new file
+    pub new_start: usize,
+    pub lines: Vec<HunkLine>,
+}
+
+/// The changes of a unified diff to one file
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FilePatch {
+    /// Path before the change, without its `a/` prefix; `None` if the
+    /// patch creates the file
+    pub old_path: Option<String>,
+    /// Path after the change, without its `b/` prefix; `None` if the patch
+    /// deletes the file
+    pub new_path: Option<String>,
+    pub hunks: Vec<Hunk>,
+}
+
+/// Parses the unified diffs in `text`, file by file. Anything outside of
+/// them, such as the headers and message of a `format-patch` mail, is
+/// skipped.
+pub fn parse_patch(text: &str) -> Result<Vec<FilePatch>, String> {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut patches = Vec::new();
+    let mut current: Option<FilePatch> = None;
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i];
+        if let Some(paths) = line.strip_prefix("diff --git ") {
+            patches.extend(current.take());
+            let (old, new) =
+                parse_git_paths(paths).ok_or_else(|| format!("corrupt patch at line {}", i + 1))?;
+            current = Some(FilePatch {
+                old_path: Some(old),
+                new_path: Some(new),
+                hunks: Vec::new(),
+            });
+        } else if line.starts_with("Binary files ") || line == "GIT binary patch" {
+            return Err("cannot apply binary patch".to_string());
+        } else if let Some(patch) = current.as_mut().filter(|patch| patch.hunks.is_empty())
+            && (line.starts_with("new file") || line.starts_with("deleted file"))
+        {
+            match line.starts_with("new file") {
+                true => patch.old_path = None,
+                false => patch.new_path = None,
+            }
+        } else if let Some(old) = line.strip_prefix("--- ")
+            && let Some(new) = lines.get(i + 1).and_then(|next| next.strip_prefix("+++ "))
+        {
+            // A plain diff starts with the file names
+            let mut patch = match current.take() {
+                Some(patch) if patch.hunks.is_empty() => patch,
+                finished => {
+                    patches.extend(finished);
+                    FilePatch::default()
+                }
+            };
+            patch.old_path = header_path(old, "a/");
+            patch.new_path = header_path(new, "b/");
+            current = Some(patch);
+            i += 1;
+        } else if line.starts_with("@@ ") {
+            let patch = current
+                .as_mut()
+                .ok_or_else(|| format!("hunk without file header at line {}", i + 1))?;
+            let (hunk, used) = parse_hunk(&lines[i..])
+                .ok_or_else(|| format!("corrupt patch at line {}", i + 1))?;
+            patch.hunks.push(hunk);
+            i += used;
+            continue;
+        }
+        // Mode and index lines, mail headers and messages change nothing
+        i += 1;
+    }
+    patches.extend(current);
+    match patches.is_empty() {
+        true => Err("no valid patches in input".to_string()),
+        false => Ok(patches),
+    }
+}
+
+/// A patch sent by mail, as `format-patch` writes it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatchMail {
+    pub author_name: String,
+    pub author_email: String,
+    /// When the change was authored, if the mail says
+    pub date: Option<DateTime<FixedOffset>>,
+    /// The subject without its `[PATCH]` prefix, then the body
+    pub message: String,
+    /// Everything after the message: the diffstat and the diffs
+    pub patch: String,
+}
+
+/// Splits an mbox into its mails, each starting with a `From ` line. Text
+/// without such a line is a single mail.
+pub fn split_mbox(text: &str) -> Vec<String> {
+    let mut mails: Vec<String> = Vec::new();
+    let mut after_blank = true;
+    for line in text.split_inclusive('\n') {
+        if (line.starts_with("From ") && after_blank) || mails.is_empty() {
+            mails.push(String::new());
+        }
+        mails.last_mut().unwrap().push_str(line);
+        after_blank = line.trim_end().is_empty();
+    }
+    mails
+}
+
+/// Parses the headers and message of a patch mail
+pub fn parse_mail(text: &str) -> Result<PatchMail, String> {
+    let mut lines = text.lines().peekable();
+    lines.next_if(|line| line.starts_with("From "));
+    let mut headers: Vec<(String, String)> = Vec::new();
+    for line in lines.by_ref() {
+        if line.is_empty() {
+            break;
+        }
+        match (line.starts_with([' ', '\t']), headers.last_mut()) {
+            // Long headers continue on indented lines
+            (true, Some((_, value))) => {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+            _ => {
+                let (name, value) = line.split_once(':').unwrap_or((line, ""));
+                headers.push((name.to_ascii_lowercase(), value.trim().to_string()));
+            }
+        }
+    }
+    let header = |name: &str| {
+        headers
+            .iter()
+            .find(|(header, _)| header == name)
+            .map(|(_, value)| value.as_str())
+    };
+    let (author_name, author_email) = header("from")
+        .and_then(|from| {
+            let (name, email) = from.split_once('<')?;
+            let email = email.strip_suffix('>')?;
+            Some((name.trim().trim_matches('"').to_string(), email.to_string()))
+        })
+        .ok_or("patch does not have a valid author")?;
+    let mut subject = header("subject").unwrap_or_default();
+    while subject.starts_with('[')
+        && let Some(end) = subject.find(']')
+    {
+        subject = subject[end + 1..].trim_start();
+    }
+
+    // The message ends where the diffstat or the first diff starts
+    let mut body = Vec::new();
+    let mut patch = String::new();
+    while let Some(line) = lines.next() {
+        if line == "---" || line.starts_with("diff --git ") {
+            patch = std::iter::once(line)
+                .chain(lines)
+                .map(|line| format!("{line}\n"))
+                .collect();
+            break;
+        }
+        body.push(line);
+    }
+    let body = body.join("\n");
+    let message = match body.trim() {
+        "" => subject.to_string(),
+        body => format!("{subject}\n\n{body}"),
+    };
+    Ok(PatchMail {
+        author_name,
+        author_email,
+        date: header("date").and_then(|date| DateTime::parse_from_rfc2822(date).ok()),
+        message,
+        patch,
+    })
+}
+
+/// Splits the `a/<old> b/<new>` of a `diff --git` line
+fn parse_git_paths(paths: &str) -> Option<(String, String)> {
+    let (old, new) = paths.strip_prefix("a/")?.split_once(" b/")?;
+    Some((old.to_string(), new.to_string()))
+}
+
+/// The path of a `---` or `+++` line, `None` for `/dev/null`
+fn header_path(header: &str, prefix: &str) -> Option<String> {
+    // Plain diffs may follow the name with a timestamp
+    let path = header.split('\t').next().unwrap_or(header).trim_end();
+    (path != "/dev/null").then(|| path.strip_prefix(prefix).unwrap_or(path).to_string())
+}
+
+/// Parses the hunk starting at `lines[0]`
+///
+/// # Returns
+/// The hunk and how many lines it spans
+fn parse_hunk(lines: &[&str]) -> Option<(Hunk, usize)> {
+    let ranges = lines[0].strip_prefix("@@ -")?.split(" @@").next()?;
+    let (old, new) = ranges.split_once(" +")?;
+    let parse_range = |range: &str| -> Option<(usize, usize)> {
+        match range.split_once(',') {
+            Some((start, len)) => Some((start.parse().ok()?, len.parse().ok()?)),
+            None => Some((range.parse().ok()?, 1)),
+        }
+    };
+    let (old_start, mut old_left) = parse_range(old)?;
+    let (new_start, mut new_left) = parse_range(new)?;
+    let mut hunk = Hunk {
+        old_start,
+        new_start,
+        lines: Vec::new(),
+    };
+    let mut used = 1;
+    while old_left > 0 || new_left > 0 {
+        let line = lines.get(used)?;
+        used += 1;
+        let (kind, text) = match line.chars().next() {
+            Some(' ') => (LineKind::Context, &line[1..]),
+            // Editors tend to strip the space of empty context lines
+            None => (LineKind::Context, ""),
+            Some('-') => (LineKind::Removed, &line[1..]),
+            Some('+') => (LineKind::Added, &line[1..]),
+            Some('\\') => {
+                strip_newline(&mut hunk);
+                continue;
+            }
+            Some(_) => return None,
+        };
+        match kind {
+            LineKind::Context if old_left > 0 && new_left > 0 => {
+                old_left -= 1;
+                new_left -= 1;
+            }
+            LineKind::Removed if old_left > 0 => old_left -= 1,
+            LineKind::Added if new_left > 0 => new_left -= 1,
+            _ => return None,
+        }
+        hunk.lines.push(HunkLine {
+            kind,
+            text: format!("{text}\n"),
+        });
+    }
+    // The last line may lack its newline too
+    if lines.get(used).is_some_and(|line| line.starts_with('\\')) {
+        strip_newline(&mut hunk);
+        used += 1;
+    }
+    Some((hunk, used))
+}
+
+/// Applies `\ No newline at end of file` to the line before it
+fn strip_newline(hunk: &mut Hunk) {
+    if let Some(line) = hunk.lines.last_mut() {
+        line.text.pop();
+    }
+}
+
+impl Hunk {
+    /// The lines the hunk expects in the old file
+    fn old_lines(&self) -> impl Iterator<Item = &HunkLine> {
+        self.lines
+            .iter()
+            .filter(|line| line.kind != LineKind::Added)
+    }
+}
+
+impl FilePatch {
+    /// The path the patch is about, the old one unless it creates the file
+    pub fn path(&self) -> &str {
+        self.old_path
+            .as_deref()
+            .or(self.new_path.as_deref())
+            .unwrap_or_default()
+    }
+
+    /// The patch undoing this one
+    pub fn reversed(&self) -> FilePatch {
+        let hunks = self
+            .hunks
+            .iter()
+            .map(|hunk| Hunk {
+                old_start: hunk.new_start,
+                new_start: hunk.old_start,
+                lines: hunk
+                    .lines
+                    .iter()
+                    .map(|line| HunkLine {
+                        kind: match line.kind {
+                            LineKind::Context => LineKind::Context,
+                            LineKind::Removed => LineKind::Added,
+                            LineKind::Added => LineKind::Removed,
+                        },
+                        text: line.text.clone(),
+                    })
+                    .collect(),
+            })
+            .collect();
+        FilePatch {
+            old_path: self.new_path.clone(),
+            new_path: self.old_path.clone(),
+            hunks,
+        }
+    }
+
+    /// Applies the hunks to `content`, the old version of the file. A hunk
+    /// whose context is not at the line it names is looked for at the
+    /// nearest line where it is, and failing that with up to [`MAX_FUZZ`]
+    /// context lines dropped at either end.
+    pub fn apply_to(&self, content: &str) -> Result<String, String> {
+        let lines: Vec<&str> = content.split_inclusive('\n').collect();
+        let mut out = String::new();
+        let mut next = 0;
+        // How far from their stated lines earlier hunks applied
+        let mut offset = 0;
+        for hunk in &self.hunks {
+            let (at, leading, trailing) = find_hunk(&lines, hunk, next, offset)
+                .ok_or_else(|| format!("patch failed: {}:{}", self.path(), hunk.old_start))?;
+            let kept = &hunk.lines[leading..hunk.lines.len() - trailing];
+            out.extend(lines[next..at].iter().copied());
+            let mut line = at;
+            for hunk_line in kept {
+                match hunk_line.kind {
+                    LineKind::Context => {
+                        out.push_str(lines[line]);
+                        line += 1;
+                    }
+                    LineKind::Removed => line += 1,
+                    LineKind::Added => out.push_str(&hunk_line.text),
+                }
+            }
+            offset = at as isize - expected_line(hunk, leading) as isize;
+            next = line;
+        }
+        out.extend(lines[next..].iter().copied());
+        Ok(out)
+    }
+}
+
+/// Where the old lines of `hunk` start in `lines`, at or after `next`,
+/// along with how many context lines had to be dropped at its start and
+/// end for it to match
+fn find_hunk(
+    lines: &[&str],
+    hunk: &Hunk,
+    next: usize,
+    offset: isize,
+) -> Option<(usize, usize, usize)> {
+    let is_context = |line: &&HunkLine| line.kind == LineKind::Context;
+    let leading_context = hunk.lines.iter().take_while(is_context).count();
+    let trailing_context = hunk.lines.iter().rev().take_while(is_context).count();
+    for fuzz in 0..=MAX_FUZZ {
+        let leading = fuzz.min(leading_context);
+        let trailing = fuzz.min(trailing_context);
+        if fuzz > 0 && leading + trailing == 0 {
+            break;
+        }
+        // Dropping context only makes sense while changes remain
+        if leading + trailing >= hunk.lines.len() {
+            break;
+        }
+        let kept = Hunk {
+            lines: hunk.lines[leading..hunk.lines.len() - trailing].to_vec(),
+            ..hunk.clone()
+        };
+        let old: Vec<&str> = kept.old_lines().map(|line| line.text.as_str()).collect();
+        let Some(last) = lines
+            .len()
+            .checked_sub(old.len())
+            .filter(|&last| last >= next)
+        else {
+            continue;
+        };
+        let expected = (expected_line(hunk, leading) as isize + offset).clamp(0, last as isize);
+        let expected = (expected as usize).max(next);
+        // Try the expected line first, then further and further away
+        let candidates = (0..=last - next).flat_map(|distance| {
+            let after = expected + distance;
+            let before = expected.checked_sub(distance).filter(|_| distance > 0);
+            [Some(after), before]
+        });
+        for at in candidates.flatten() {
+            if (next..=last).contains(&at) && lines[at..at + old.len()] == old[..] {
+                return Some((at, leading, trailing));
+            }
+        }
+    }
+    None
+}
+
+/// The 0-based line where `hunk` says its old lines start, once `leading`
+/// context lines are dropped
+fn expected_line(hunk: &Hunk, leading: usize) -> usize {
+    let has_old_lines = hunk.old_lines().next().is_some();
+    match has_old_lines {
+        true => hunk.old_start.saturating_sub(1) + leading,
+        // A pure addition names the line it goes after
+        false => hunk.old_start,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patches_apply_with_offset_fuzz_and_in_reverse() {
+        let patch = [
+            "From 1234 Mon Sep 17 00:00:00 2001",
+            "Subject: [PATCH] change",
+            "",
+            "---",
+            "diff --git a/f.txt b/f.txt",
+            "index 1111111..2222222",
+            "--- a/f.txt",
+            "+++ b/f.txt",
+            "@@ -2,4 +2,4 @@ header",
+            " b",
+            " c",
+            "-d",
+            "+D",
+            " e",
+            "diff --git a/new.txt b/new.txt",
+            "new file",
+            "--- /dev/null",
+            "+++ b/new.txt",
+            "@@ -0,0 +1 @@",
+            "+no newline",
+            "\\ No newline at end of file",
+        ]
+        .map(|line| format!("{line}\n"))
+        .concat();
+        let files = parse_patch(&patch).unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].path(), "f.txt");
+        assert_eq!(files[1].old_path, None);
+        assert_eq!(files[1].hunks[0].lines[0].text, "no newline");
+        assert_eq!(files[1].apply_to("").unwrap(), "no newline");
+
+        let original = "a\nb\nc\nd\ne\nf\n";
+        let changed = "a\nb\nc\nD\ne\nf\n";
+        assert_eq!(files[0].apply_to(original).unwrap(), changed);
+        assert_eq!(files[0].reversed().apply_to(changed).unwrap(), original);
+        // Lines added above move the hunk down
+        assert_eq!(
+            files[0].apply_to(&format!("x\ny\n{original}")).unwrap(),
+            format!("x\ny\n{changed}")
+        );
+        // A changed context line at the edge of the hunk is tolerated
+        assert_eq!(
+            files[0].apply_to("a\nB\nc\nd\ne\nf\n").unwrap(),
+            "a\nB\nc\nD\ne\nf\n"
+        );
+        assert!(files[0].apply_to("a\nb\nc\nX\ne\nf\n").is_err());
+        assert!(files[0].apply_to(changed).is_err());
+        assert!(parse_patch("@@ -1 +1 @@\n-a\n+b\n").is_err());
+        assert!(parse_patch("just text\n").is_err());
+    }
+}
@@ -4,132 +4,142 @@ use crate::object::{Author, Commit};
 use walkdir::WalkDir;
 
 use super::EncodedSha;
-use super::index::{Index, TreeNode};
-use super::object::{Blob, ObjectDB, ObjectType, Tree};
+use super::Error;
+use super::config::Config;
+use super::crypt::{ENCRYPTION_FILE, ObjectCipher};
+use super::ignore::IgnoreRules;
+use super::index::{FileStat, Index, LockedIndex, TreeNode};
+use super::lock;
+use super::object::{Blob, FileMode, Object, ObjectDB, ObjectType, Tree};
+use operation::STATE_HEADS;
+use pathspec::{glob_pathspec, is_glob, matches_pathspec, normalize_pathspecs};
 use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
-use std::{env, fs, io, path};
+use std::{fs, io, path};
 const OBJECTS_DIR: &str = "objects";
 const REFS_DIR: &str = "refs";
 const HEADS_DIR: &str = "heads";
+const REMOTES_DIR: &str = "remotes";
 const MASTER_BRANCH_NAME: &str = "master";
 const HEAD_FILE: &str = "HEAD";
 const GIT_DIR: &str = ".git";
 const INDEX_FILE: &str = "index";
+const CONFIG_FILE: &str = "config";
+#[cfg(test)]
 const AUTHOR_NAME: &str = "Alice";
+#[cfg(test)]
 const AUTHOR_EMAIL: &str = "alice@wonderland.edu";
+/// Shortest abbreviated SHA1 accepted as a revision
+const MIN_ABBREV_LEN: usize = 4;
+
+mod am;
+mod apply;
+mod archive;
+mod autostash;
+mod backup;
+mod bisect;
+mod blame;
+mod bundle;
+mod cat_file;
+mod check_ignore;
+mod cherry;
+mod cherry_pick;
+mod clean;
+mod clone;
+mod delta;
+mod describe;
+mod diff;
+mod difftool;
+mod events;
+mod fetch;
+mod format_patch;
+mod fsck;
+mod gc;
+mod grep;
+mod hash_object;
+mod histogram;
+mod history;
+mod hooks;
+mod import;
+mod log;
+mod ls_files;
+mod ls_tree;
+mod merge;
+mod merge_file;
+mod merge_tree;
+mod mergetool;
+mod operation;
+mod packed_refs;
+mod pathspec;
+mod pretty;
+mod push;
+mod reachable;
+mod reflog;
+mod remote;
+mod repair;
+mod rerere;
+mod reset;
+mod restore;
+mod rev_list;
+mod rev_parse;
+mod revert;
+mod revwalk;
+mod shortlog;
+mod show;
+mod sign;
+mod stash;
+mod status;
+mod submodule;
+mod switch;
+mod symbolic_ref;
+mod tag;
+mod tree_walk;
+mod undo;
+mod update_ref;
+
+pub use apply::ApplyOptions;
+pub use archive::ArchiveFormat;
+pub use bisect::BisectStep;
+pub use blame::BlameLine;
+pub use bundle::Bundle;
+pub use cat_file::CatFileMode;
+pub use delta::{DeltaStatus, Diff, DiffFile, DiffHunk, DiffLine, FileDelta, LineOrigin};
+pub use diff::{
+    CopyDetection, DIFF_CONTEXT, DIRSTAT_THRESHOLD, DiffAlgorithm, DiffOptions, Snapshot,
+};
+pub use events::{EXTERNAL_REASON, RefEvent, RefWatcher};
+pub use fsck::FsckReport;
+pub use gc::parse_expire;
+pub use grep::GrepOptions;
+pub use hash_object::hash_blob;
+pub use history::FileRevision;
+pub use hooks::{CheckoutInfo, CommitInfo};
+pub use log::LogOptions;
+pub use ls_files::LsFilesOptions;
+pub use ls_tree::LsTreeOptions;
+pub use merge::FastForward;
+pub use merge_file::{FileMerge, merge_text};
+pub use merge_tree::{ConflictKind, MergeConflict, TreeMerge};
+pub use operation::{Operation, OperationState};
+pub use pretty::PrettyFormat;
+pub use reflog::{LOGS_DIR, ReflogEntry};
+pub use reset::ResetMode;
+pub use revwalk::{RevSort, RevWalk};
+pub use sign::SignatureStatus;
+pub use stash::STASH_REF;
+pub use status::{ChangeKind, StatusEntry, StatusReport};
+pub use submodule::Submodule;
+pub use tree_walk::{TreeWalkEntry, TreeWalkStep};
 
-mod line_diff {
-    pub fn line_diff(a: &str, b: &str) -> Vec<bool> {
-        let a_lines: Vec<&str> = a.split('\n').collect();
-        let b_lines: Vec<&str> = b.split('\n').collect();
-        let max_len = std::cmp::max(a_lines.len(), b_lines.len());
-        (0..max_len)
-            .map(|i| a_lines.get(i) == b_lines.get(i))
-            .collect()
-    }
-    pub fn group_ranges(bools: &[bool]) -> Vec<(usize, usize, bool)> {
-        let mut result = Vec::new();
-        if bools.is_empty() {
-            return result;
-        }
-
-        let mut current_start = 0;
-        let mut current_value = bools[0];
-
-        for (i, &value) in bools.iter().enumerate().skip(1) {
-            if value != current_value {
-                result.push((current_start, i - 1, current_value));
-                current_start = i;
-                current_value = value;
-            }
-        }
-
-        result.push((current_start, bools.len() - 1, current_value));
-        result
-    }
-    #[cfg(test)]
-    mod tests {
-        use super::*;
-
-        #[test]
-        fn test_line_diff_basic() {
-            let a = "hello\nworld\n2023";
-            let b = "hello\nworld\n2023";
-            assert_eq!(line_diff(a, b), vec![true, true, true]);
-
-            let a = "Rust\nis\nfast";
-            let b = "Rust\nis\nslow";
-            assert_eq!(line_diff(a, b), vec![true, true, false]);
-
-            let a = "line1\nline2";
-            let b = "line1";
-            assert_eq!(line_diff(a, b), vec![true, false]);
-        }
-
-        #[test]
-        fn test_line_diff_edge_cases() {
-            assert_eq!(line_diff("", ""), vec![true] as Vec<bool>);
-            assert_eq!(line_diff("text", ""), vec![false]);
-            assert_eq!(line_diff("", "text"), vec![false]);
-
-            let a = "first\n\nthird";
-            let b = "first\nsecond\nthird";
-            assert_eq!(line_diff(a, b), vec![true, false, true]);
-        }
-
-        #[test]
-        fn test_group_ranges_normal() {
-            let bools = vec![true, true, false, false, true];
-            assert_eq!(
-                group_ranges(&bools),
-                vec![(0,1,true), (2,3,false), (4,4,true)]
-            );
-
-            let all_true = vec![true; 5];
-            assert_eq!(
-                group_ranges(&all_true),
-                vec![(0,4,true)]
-            );
-        }
-
-        #[test]
-        fn test_group_ranges_special() {
-            assert_eq!(group_ranges(&[true]), vec![(0,0,true)]);
-            assert_eq!(group_ranges(&[false]), vec![(0,0,false)]);
-
-            let empty: Vec<bool> = Vec::new();
-            assert!(group_ranges(&empty).is_empty());
-
-            let alternating = vec![true, false, true, false];
-            assert_eq!(
-                group_ranges(&alternating),
-                vec![(0,0,true), (1,1,false), (2,2,true), (3,3,false)]
-            );
-        }
-
-        #[test]
-        fn test_integration() {
-            let a = "A\nB\nC\nD";
-            let b = "A\nX\nC\nY";
-            let diff = line_diff(a, b);
-            assert_eq!(diff, vec![true, false, true, false]);
-            
-            let groups = group_ranges(&diff);
-            assert_eq!(
-                groups,
-                vec![(0,0,true), (1,1,false), (2,2,true), (3,3,false)]
-            );
-        }
-    }
-}
 pub struct Repository {
-    dir: PathBuf,      // Path to the repository directory.
-    git_dir: PathBuf,  // Path to the git directory ({dir}/{GIT_DIR}).
+    dir: PathBuf,     // Path to the repository directory.
+    git_dir: PathBuf, // Path to the git directory ({dir}/{GIT_DIR}).
     obj_db: ObjectDB,
+    hooks: hooks::Hooks,
+    ref_subscribers: Vec<std::sync::mpsc::Sender<RefEvent>>,
 }
 /// Represents the difference status between two index entries
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -172,13 +182,71 @@ impl Repository {
 
         true
     }
-    pub fn init(dir: &Path) -> Result<Repository, String> {
+    /// Initializes a repository whose first branch is named by the
+    /// `init.defaultBranch` setting of the global config, or `master`
+    pub fn init(dir: &Path) -> Result<Repository, Error> {
+        Self::init_with_branch(dir, &Self::default_branch_name())
+    }
+
+    /// Name for the first branch of a new repository
+    pub fn default_branch_name() -> String {
+        Config::global()
+            .get("init.defaultBranch")
+            .unwrap_or(MASTER_BRANCH_NAME)
+            .to_string()
+    }
+
+    /// The settings of the user-wide config, overridden by those of the
+    /// repository's own `config` file
+    pub fn config(&self) -> Result<Config, Error> {
+        let mut config = Config::global();
+        config.extend(Config::load(&self.config_path())?);
+        Ok(config)
+    }
+
+    /// Path of the repository's own config file
+    pub fn config_path(&self) -> PathBuf {
+        self.git_dir.join(CONFIG_FILE)
+    }
+
+    /// Stores a `section.key` setting in the repository's config file
+    pub fn set_config<V: ToString>(&self, key: &str, value: V) -> Result<(), Error> {
+        let path = self.config_path();
+        let mut config = Config::load(&path)?;
+        config.set(key, value)?;
+        Ok(config.save(&path)?)
+    }
+
+    /// The `(name, email)` recorded as author and committer of new commits:
+    /// `user.name` and `user.email` from the config, overridden by the
+    /// `GIT_AUTHOR_NAME` and `GIT_AUTHOR_EMAIL` environment variables
+    pub(super) fn identity(&self) -> Result<(String, String), Error> {
+        let config = self.config()?;
+        let lookup = |var: &str, key: &str| {
+            std::env::var(var)
+                .ok()
+                .or_else(|| config.get(key).map(str::to_string))
+                .filter(|value| !value.trim().is_empty())
+        };
+        match (
+            lookup("GIT_AUTHOR_NAME", "user.name"),
+            lookup("GIT_AUTHOR_EMAIL", "user.email"),
+        ) {
+            (Some(name), Some(email)) => Ok((name, email)),
+            _ => Err(Error::IdentityUnknown),
+        }
+    }
+
+    /// Initializes a repository in `dir` with HEAD on the unborn branch
+    /// `initial_branch`
+    pub fn init_with_branch(dir: &Path, initial_branch: &str) -> Result<Repository, Error> {
+        check_branch_name(initial_branch)?;
         if !dir.exists() {
-            return Err("Specified init dir don't exists".to_owned());
+            return Err("Specified init dir don't exists".into());
         }
         let git_dir = dir.join(GIT_DIR);
         if git_dir.exists() {
-            return Err("git directory already exists".to_owned());
+            return Err("git directory already exists".into());
         }
         // Create .git directory
         fs::create_dir(&git_dir).map_err(|_| "Failed to create git directory")?;
@@ -198,48 +266,68 @@ impl Repository {
         // Create HEAD file and write initial content
         let head_path = git_dir.join(HEAD_FILE);
         // e.g: refs/heads/master
-        let head = Head::Symbolic(Path::new(REFS_DIR).join(HEADS_DIR).join(MASTER_BRANCH_NAME));
-        head.save(&head_path).map_err(|why| why.to_string())?;
+        let head = Head::Symbolic(Path::new(REFS_DIR).join(HEADS_DIR).join(initial_branch));
+        head.save(&head_path)?;
 
         let obj_db = match ObjectDB::new(&objects_dir) {
             Ok(obj_db) => obj_db,
             Err(_) => {
-                return Err("Failed to create object db".to_owned());
+                return Err("Failed to create object db".into());
             }
         };
         let repo = Repository {
             dir: dir.to_path_buf(),
-            git_dir: git_dir,
-            obj_db: obj_db,
+            git_dir,
+            obj_db,
+            hooks: Default::default(),
+            ref_subscribers: Vec::new(),
         };
-        repo.branch("master");
         Ok(repo)
     }
     /// Open a repository based on the repository dir
     /// The git dir should be {dir}/{GIT_DIR}
-    pub fn open(dir: &Path) -> Result<Repository, String> {
-        let dir = path::absolute(dir).map_err(|_| "Failed to get dir abs path")?;
+    pub fn open(dir: &Path) -> Result<Repository, Error> {
+        let dir = path::absolute(dir)?;
         let git_dir = dir.join(GIT_DIR);
         if !Repository::is_vaild_git_dir(&git_dir) {
-            return Err(format!(
-                "{} isn't a vaild git dir",
-                git_dir.to_str().unwrap()
-            ));
+            return Err(Error::NotARepository(git_dir));
         }
         let objects_dir = git_dir.join(OBJECTS_DIR);
-        let obj_db = match ObjectDB::new(&objects_dir) {
+        let mut obj_db = match ObjectDB::new(&objects_dir) {
             Ok(obj_db) => obj_db,
             Err(_) => {
-                return Err("Failed to create object db".to_string());
+                return Err("Failed to create object db".into());
             }
         };
+        // Encrypted repositories cannot be read without their key
+        if let Ok(marker) = fs::read_to_string(objects_dir.join(ENCRYPTION_FILE)) {
+            let cipher = ObjectCipher::from_env()?;
+            cipher.check_marker(&marker)?;
+            obj_db.set_cipher(cipher);
+        }
         Ok(Repository {
             dir: dir.to_path_buf(),
-            git_dir: git_dir,
-            obj_db: obj_db,
+            git_dir,
+            obj_db,
+            hooks: Default::default(),
+            ref_subscribers: Vec::new(),
         })
     }
 
+    /// Turns on encryption at rest for the object store.
+    /// Only allowed while the store is still empty, so that no object is
+    /// left in plaintext.
+    pub fn enable_encryption(&mut self, cipher: ObjectCipher) -> Result<(), Error> {
+        let objects = self.obj_db.loose_objects()?;
+        if !objects.is_empty() {
+            return Err("encryption can only be enabled on an empty repository".into());
+        }
+        let marker = self.git_dir.join(OBJECTS_DIR).join(ENCRYPTION_FILE);
+        fs::write(marker, cipher.marker())?;
+        self.obj_db.set_cipher(cipher);
+        Ok(())
+    }
+
     /// Validates if a file path meets repository requirements
     ///
     /// # Conditions
@@ -259,7 +347,7 @@ impl Repository {
         if abs_path.starts_with(&self.git_dir) {
             return false;
         }
-        return true;
+        true
     }
     /// Converts an absolute path to repository-relative format
     ///
@@ -290,6 +378,14 @@ impl Repository {
     ///    - Existing file: Create/store blob + update entry
     ///    - Missing file: Remove existing entry
     fn update_index(&self, file_path: &Path) -> Result<(), String> {
+        let mut index = self.lock_index()?;
+        self.stage_file(&mut index, file_path)?;
+        index.commit()
+    }
+
+    /// Stages the current state of `file_path` in `index`, see
+    /// [`Self::update_index`]
+    fn stage_file(&self, index: &mut Index, file_path: &Path) -> Result<(), String> {
         if !self.is_file_path_vaild(file_path) {
             return Err(format!(
                 "File path {} invaild!",
@@ -297,7 +393,7 @@ impl Repository {
             ));
         }
         let entry_file_path = self
-            .turn_relative_path_to_repo_dir(&file_path)?
+            .turn_relative_path_to_repo_dir(file_path)?
             .to_str()
             .unwrap()
             .to_string();
@@ -306,15 +402,16 @@ impl Repository {
             return Err(format!("{} isn't a file", file_path.to_str().unwrap()));
         }
 
-        let index_path = self.git_dir.join(INDEX_FILE);
-        if !index_path.is_file() {
-            let _ = fs::File::create_new(&index_path).map_err(|err| err.to_string());
-        }
-        let mut index = Index::load(&index_path)?;
         if file_path.exists() {
-            let blob = Blob::new(&file_path)?;
+            let metadata = fs::metadata(file_path).map_err(|why| why.to_string())?;
+            let blob = Blob::new(file_path)?;
             let sha1 = self.obj_db.store(&blob).map_err(|why| why.to_string())?;
-            index.update_entry(&entry_file_path, sha1);
+            index.update_entry_with_mode(
+                &entry_file_path,
+                sha1,
+                FileMode::from_metadata(&metadata),
+            );
+            index.set_stat(&entry_file_path, FileStat::from_metadata(&metadata));
         } else {
             if index.get_sha1(&entry_file_path).is_some() {
                 // delete the entry from index
@@ -326,9 +423,50 @@ impl Repository {
                 ));
             }
         }
-        index.save(&index_path)?;
         Ok(())
     }
+    /// Re-stats every tracked file and caches the stat data of those whose
+    /// content still matches the index, without changing what is staged.
+    ///
+    /// # Returns
+    /// - `Ok(Vec<String>)`: Tracked files that were modified or deleted
+    ///   since they were staged, sorted by path
+    /// - `Err(Error)`: The index could not be read or written
+    pub fn refresh_index(&self) -> Result<Vec<String>, Error> {
+        let index_path = self.get_index_path();
+        let mut index = Index::load(&index_path)?;
+        let mut needs_update = Vec::new();
+        let mut changed = false;
+        for (path, sha) in index.collect_entries() {
+            let file = self.dir.join(&path);
+            if index.get_mode(&path) == Some(FileMode::Gitlink) {
+                // Submodules have no stat data; they only change by commits
+                if self.submodule_head(&path).is_some_and(|head| head != sha) {
+                    needs_update.push(path);
+                }
+                continue;
+            }
+            let Ok(metadata) = fs::metadata(&file) else {
+                needs_update.push(path);
+                continue;
+            };
+            let stat = FileStat::from_metadata(&metadata);
+            if index.get_stat(&path) == Some(stat) {
+                continue;
+            }
+            if metadata.is_file() && Blob::new(&file)?.encoded_sha1() == sha.0 {
+                index.set_stat(&path, stat);
+                changed = true;
+            } else {
+                needs_update.push(path);
+            }
+        }
+        if changed {
+            index.save(&index_path)?;
+        }
+        Ok(needs_update)
+    }
+
     /// Converts the index into tree objects and stores them in the object database,
     /// returning the SHA1 hash of the root tree.
     ///
@@ -346,11 +484,51 @@ impl Repository {
         let root = index.get_root();
         self.write_tree_impl(root)
     }
+    /// Writes a tree with the index content of paths matching `pathspecs`
+    /// and the HEAD content of all other paths
+    fn write_partial_tree(&self, pathspecs: &[String]) -> Result<EncodedSha, String> {
+        let index = Index::load(&self.get_index_path())?;
+        let head_index = match self.get_current_commit() {
+            Some(sha) => self.read_tree(&self.read_commit(&sha)?.get_tree_sha())?,
+            None => Index::new(),
+        };
+        let head_entries = head_index.collect_entries();
+        let staged_entries = index.collect_entries();
+        for spec in pathspecs {
+            let spec = std::slice::from_ref(spec);
+            if !head_entries
+                .iter()
+                .chain(&staged_entries)
+                .any(|(path, _)| matches_pathspec(path, spec))
+            {
+                return Err(format!(
+                    "pathspec '{}' did not match any file(s) known to git",
+                    spec[0]
+                ));
+            }
+        }
+        let mut tree_index = Index::new();
+        let unmatched = head_entries
+            .into_iter()
+            .filter(|(path, _)| !matches_pathspec(path, pathspecs));
+        let matched = staged_entries
+            .into_iter()
+            .filter(|(path, _)| matches_pathspec(path, pathspecs));
+        for (path, sha) in unmatched {
+            let mode = head_index.get_mode(&path).unwrap_or_default();
+            tree_index.update_entry_with_mode(&path, sha, mode);
+        }
+        for (path, sha) in matched {
+            let mode = index.get_mode(&path).unwrap_or_default();
+            tree_index.update_entry_with_mode(&path, sha, mode);
+        }
+        self.write_tree_impl(tree_index.get_root())
+    }
     fn write_tree_impl(&self, node: &TreeNode) -> Result<EncodedSha, String> {
         let mut tree = Tree::new();
         for (name, child) in node.get_children() {
             if child.is_file() {
-                tree.add_entry(ObjectType::Blob, &child.get_sha1().unwrap(), &name);
+                tree.add_entry_with_mode(child.get_mode(), child.get_sha1().unwrap(), name);
             } else {
                 let subdir_tree_sha1 = self.write_tree_impl(child).unwrap();
                 tree.add_entry(ObjectType::Tree, &subdir_tree_sha1, name);
@@ -368,19 +546,15 @@ impl Repository {
     /// # Returns
     /// Result containing the populated Index or error string
     fn read_tree(&self, tree_root: &EncodedSha) -> Result<Index, String> {
-        // Initialize empty index
         let mut index = Index::new();
-
-        // Recursively collect all file paths and their corresponding SHA1 hashes
-        let (path_vec, sha_vec) = self.collect_tree_files(&tree_root)?;
-        debug_assert_eq!(path_vec.len(), sha_vec.len());
-
-        // Populate index with collected entries
-        let mut i = 0;
-        for sha in sha_vec.into_iter() {
-            index.update_entry(&path_vec[i], sha);
-            i += 1;
-        }
+        self.walk_tree(tree_root, |entry| {
+            // Submodules are recorded like files, by the commit they are at
+            if entry.object_type != ObjectType::Tree {
+                index.update_entry_with_mode(&entry.path, entry.sha.clone(), entry.mode);
+            }
+            TreeWalkStep::Continue
+        })
+        .map_err(|why| why.to_string())?;
         Ok(index)
     }
 
@@ -420,15 +594,11 @@ impl Repository {
     ///
     /// # Arguments
     /// * `index` - Target index to check out
-    fn checkout_index(&self, index: &Index) {
-        let head = self.get_head().unwrap();
-
+    fn checkout_index(&self, index: &Index) -> Result<(), Error> {
         // Build index from current commit's tree
-        let current_commit_index = match head {
-            Head::Symbolic(path_buf) => {
-                self.read_branch_to_index(path_buf.file_name().unwrap().to_str().unwrap())
-            },
-            Head::Detached(encoded_sha) => todo!(),
+        let current_commit_index = match self.get_current_commit() {
+            Some(sha) => self.read_tree(&self.read_commit(&sha)?.get_tree_sha())?,
+            None => Index::new(),
         };
 
         // Calculate differences between current state and target index
@@ -436,21 +606,31 @@ impl Repository {
 
         // Prevent overwriting untracked files
         for (file, status) in diff.iter() {
-            if let IndexDiffType::RightOnly = status {
-                let path = self.dir.join(file);
-                if path.exists() {
-                    println!(
-                        "There is an untracked file in the way; delete it, or add and commit it first."
-                    );
-                    std::process::exit(1);
-                }
+            if let IndexDiffType::RightOnly = status
+                && self.dir.join(file).exists()
+            {
+                return Err(Error::UntrackedInTheWay(file.clone()));
             }
         }
 
         // Apply changes to working directory
-        for (file, status) in diff.iter() {
-            let path = self.dir.join(file);
+        Ok(self.update_worktree(&current_commit_index, index)?)
+    }
+
+    /// Rewrites the working tree files that differ between two snapshots,
+    /// assuming the working tree currently matches `from`
+    ///
+    /// # Arguments
+    /// * `from` - Snapshot the working tree currently holds
+    /// * `to` - Snapshot to write out
+    fn update_worktree(&self, from: &Index, to: &Index) -> Result<(), String> {
+        for (file, status) in self.diff_index(from, to) {
+            let path = self.dir.join(&file);
             match status {
+                IndexDiffType::LeftOnly if from.get_mode(&file) == Some(FileMode::Gitlink) => {
+                    // A submodule that is checked out is left in place
+                    let _ = fs::remove_dir(&path);
+                }
                 IndexDiffType::LeftOnly => {
                     // Remove deleted files
                     if let Err(why) = fs::remove_file(&path) {
@@ -461,593 +641,371 @@ impl Repository {
                         let _ = fs::remove_dir(dir);
                     }
                 }
+                IndexDiffType::RightOnly | IndexDiffType::Modified
+                    if to.get_mode(&file) == Some(FileMode::Gitlink) =>
+                {
+                    // Submodules get an empty directory until they are
+                    // cloned by `submodule update`
+                    if from
+                        .get_mode(&file)
+                        .is_some_and(|mode| mode != FileMode::Gitlink)
+                    {
+                        let _ = fs::remove_file(&path);
+                    }
+                    fs::create_dir_all(&path).map_err(|why| why.to_string())?;
+                }
                 IndexDiffType::RightOnly | IndexDiffType::Modified => {
                     // Write new/changed files
-                    if let Some(sha) = index.get_sha1(file) {
-                        let blob_data = self.obj_db.retrieve(sha).unwrap_or_else(|why| {
-                            println!("{}", why.to_string());
-                            std::process::exit(1);
-                        });
-                        let blob = Blob::deserialize(&blob_data).unwrap_or_else(|why| {
-                            println!("{}", why.to_string());
-                            std::process::exit(1);
-                        });
+                    if let Some(sha) = to.get_sha1(&file) {
+                        let blob_data = self.obj_db.retrieve(sha).map_err(|why| why.to_string())?;
+                        let blob = Blob::deserialize(&blob_data)?;
                         // Ensure parent directories exist
-                        if let Some(dir) = path.parent() {
-                            if !dir.is_dir() {
-                                if let Err(why) = fs::create_dir_all(dir) {
-                                    println!("{}", why.to_string());
-                                    std::process::exit(1);
-                                }
-                            }
+                        if let Some(dir) = path.parent()
+                            && !dir.is_dir()
+                        {
+                            fs::create_dir_all(dir).map_err(|why| why.to_string())?;
                         }
                         // Write file contents
-                        let mut file = File::create(path).unwrap_or_else(|why| {
-                            println!("{}", why.to_string());
-                            std::process::exit(1);
-                        });
-                        file.write_all(&blob.data).unwrap_or_else(|why| {
-                            println!("{}", why.to_string());
-                            std::process::exit(1);
-                        })
+                        let mut file = File::create(path).map_err(|why| why.to_string())?;
+                        file.write_all(&blob.data).map_err(|why| why.to_string())?;
                     }
                 }
                 IndexDiffType::Unmodified => (),
             }
         }
+        Ok(())
     }
-    pub fn status(&self) {
-        let head = self.get_head().unwrap_or_else(|| {
-            println!("Failed to fetch head");
-            std::process::exit(1);
-        });
-        let commit_sha = match head {
-            Head::Symbolic(path_buf) => {
-                let branch_name = path_buf
-                    .file_name()
-                    .unwrap_or_else(|| {
-                        println!("Failed to get branch name");
-                        std::process::exit(1);
-                    })
-                    .to_str()
-                    .unwrap_or_else(|| {
-                        println!("Failed to ture to str");
-                        std::process::exit(1);
-                    });
-                let branch =
-                    Branch::load(&self.git_dir.join(REFS_DIR).join(HEADS_DIR), branch_name)
-                        .unwrap_or_else(|| {
-                            println!("Failed to load branch");
-                            std::process::exit(1);
-                        });
-                branch.commit_sha
-            }
-            Head::Detached(commit_sha) => {
-                println!("HEAD detached at {commit_sha}");
-                Some(commit_sha)
-            }
-        };
-        if commit_sha.is_none() {
-            println!("No commits yet");
-            std::process::exit(0);
-        }
-        let commit_sha = commit_sha.unwrap();
-        let current_commit_data = self.obj_db.retrieve(&commit_sha).unwrap_or_else(|why| {
-            println!("commit {commit_sha} doesn't exist: {why}");
-            std::process::exit(1);
-        });
-        let current_commit = Commit::deserialize(&current_commit_data).unwrap_or_else(|why| {
-            println!("{why}");
-            std::process::exit(1);
-        });
-        let index = Index::load(&self.git_dir.join(INDEX_FILE)).unwrap_or_else(|why| {
-            println!("cannot find index: {why}");
-            std::process::exit(1);
-        });
-        // Build index from current commit's tree
-        let current_commit_index = self
-            .read_tree(&current_commit.get_tree_sha())
-            .unwrap_or_else(|why| {
-                println!("{}", why.to_string());
-                std::process::exit(1);
-            });
-
-        // Calculate differences between current state and target index
-        let diff = self.diff_index(&current_commit_index, &index);
-        for (name, status) in diff {
-            match status {
-                IndexDiffType::LeftOnly => {
-                    println!("Deleted: {name}");
-                }
-                IndexDiffType::RightOnly => {
-                    println!("New: {name}");
-                }
-                IndexDiffType::Modified => {
-                    println!("Modified: {name}");
-                }
-                IndexDiffType::Unmodified => (),
-            }
-        }
-    }
-
-    fn load_commit(&self, encoded_sha: &EncodedSha) -> Commit {
-        let data = self.obj_db.retrieve(encoded_sha).unwrap();
-        let commit = Commit::deserialize(&data).unwrap();
-        commit
+    /// Reads and parses the commit object `encoded_sha`
+    fn read_commit(&self, encoded_sha: &EncodedSha) -> Result<Commit, String> {
+        let data = self
+            .obj_db
+            .retrieve(encoded_sha)
+            .map_err(|why| format!("cannot read object {encoded_sha}: {why}"))?;
+        Commit::deserialize(&data)
     }
 
     fn get_index_path(&self) -> PathBuf {
         self.git_dir.join(INDEX_FILE)
     }
 
-    pub fn merge(&self, branch_name: &str) {
-        let current_commit_sha = self.get_current_commit().unwrap();
-        let mut index = Index::load(&self.get_index_path()).unwrap();
-        let current_commit_data = self.obj_db.retrieve(&current_commit_sha).unwrap();
-        let current_commit = Commit::deserialize(&current_commit_data).unwrap();
-        let current_commit_index = self.read_tree(&current_commit.get_tree_sha()).unwrap();
-        let diff = self.diff_index(&current_commit_index, &index);
-        for (_, status) in diff {
-            if let IndexDiffType::Unmodified = status {
-            } else {
-                println!("You have uncommitted changes.");
-                std::process::exit(1);
-            }
-        }
-        let branch = match Branch::load(&self.git_dir.join(REFS_DIR).join(HEADS_DIR), branch_name) {
-            Some(branch) => branch,
-            None => {
-                println!("A branch with that name does not exist.");
-                std::process::exit(1);
-            }
-        };
-        if branch.commit_sha.is_none() {
-            println!("There is no commit in branch {}", &branch.name);
-            std::process::exit(1);
-        }
-        let branch_commit_sha = branch.commit_sha.unwrap();
-        if branch_commit_sha == current_commit_sha {
-            println!("Cannot merge a branch with itself.");
-            std::process::exit(1);
-        }
-        let lca = match self.find_lca(&current_commit_sha, &branch_commit_sha) {
-            Some(encoded_sha) => encoded_sha,
-            None => {
-                println!(
-                    "Cannot find lca of {} and {}",
-                    &current_commit_sha, &branch_commit_sha
-                );
-                std::process::exit(1);
-            }
-        };
-        if lca.eq(&current_commit_sha) {
-            self.fast_forward(branch_name);
-            return;
-        }
-        if lca.eq(&branch_commit_sha) {
-            return;
+    /// Loads the index; nothing has been staged yet if there is none
+    fn load_index_or_empty(&self) -> Result<Index, String> {
+        let index_path = self.get_index_path();
+        if index_path.exists() {
+            Index::load(&index_path)
+        } else {
+            Ok(Index::new())
         }
+    }
 
-        let branch_commit = self.load_commit(&branch_commit_sha);
-        let lca_commit = self.load_commit(&lca);
-
-        let branch_index = self.read_tree(&branch_commit.get_tree_sha()).unwrap();
-        let lca_index = self.read_tree(&lca_commit.get_tree_sha()).unwrap();
-
-        let diff_lca_cur = self.diff_index(&lca_index, &current_commit_index);
-        let diff_lca_branch = self.diff_index(&lca_index, &branch_index);
-        let mut has_conflict = false;
-
-        // Collect all unique files from both diffs
-        let all_files: HashSet<_> = diff_lca_cur
-            .keys()
-            .chain(diff_lca_branch.keys())
-            .cloned()
-            .collect();
-
-        // Merge logic for each file
-        for file_path in all_files {
-            let cur_status = diff_lca_cur.get(&file_path);
-            let branch_status = diff_lca_branch.get(&file_path);
-
-            if cur_status.is_some() && branch_status.is_some() {
-                let cur_status = cur_status.unwrap();
-                let branch_status = branch_status.unwrap();
-                match (cur_status, branch_status) {
-                    // Both modified differently - Conflict
-                    // 8 Any files modified in different ways in the current and given branches are in conflict.
-                    // 8.1 the contents of both are changed and different from other.
-                    // 8.3 the file was absent at the split point and has different
-                    // contents in the given and current branches.
-                    (IndexDiffType::Modified, IndexDiffType::Modified)
-                    | (IndexDiffType::RightOnly, IndexDiffType::RightOnly) => {
-                        let cur_sha = current_commit_index.get_sha1(&file_path).unwrap();
-                        let branch_sha = branch_index.get_sha1(&file_path).unwrap();
-                        // 3. Any files that have been modified in both the current and given branch in the same way
-                        // are left unchanged by the merge.
-                        // 3.1. Both files now have the same content
-                        if cur_sha != branch_sha {
-                            self.handle_conflict(
-                                Path::new(&file_path),
-                                cur_sha,
-                                branch_sha,
-                                &mut index,
-                            );
-                            has_conflict = true;
-                        }
-                    }
-
-                    // Current deleted, branch modified (or vice versa) - Conflict
-                    // 8.2 the contents of one are changed and the other file is deleted
-                    (IndexDiffType::LeftOnly, IndexDiffType::Modified)
-                    | (IndexDiffType::Modified, IndexDiffType::LeftOnly) => {
-                        let (blob_sha, is_cur_content) = if let IndexDiffType::LeftOnly = cur_status
-                        {
-                            (branch_index.get_sha1(&file_path).unwrap(), false)
-                        } else {
-                            (current_commit_index.get_sha1(&file_path).unwrap(), true)
-                        };
-                        self.handle_deletion_conflict(
-                            Path::new(&file_path),
-                            blob_sha,
-                            is_cur_content,
-                            &mut index,
-                        );
-                        has_conflict = true;
-                    }
-
-                    // 1. Any files that have been modified in the given branch since the split point,
-                    // but not modified in the current branch since the split point should be changed to their versions in the given branch
-                    (IndexDiffType::Unmodified, IndexDiffType::Modified) => {
-                        let sha = branch_index.get_sha1(&file_path).unwrap();
-                        index.update_entry(&file_path, sha.clone());
-                    }
-
-                    // 2. Any files that have been modified in the current branch but not in the given branch
-                    // since the split point should stay as they are.
-                    (IndexDiffType::Modified, IndexDiffType::Unmodified) => (),
-
-                    // 3.2 Both files were both removed are left unchanged by the merge.
-                    (IndexDiffType::LeftOnly, IndexDiffType::LeftOnly) => (),
-
-                    // 6. Any files present at the split point, unmodified in the current branch,
-                    // and absent in the given branch should be removed (and untracked).
-                    (IndexDiffType::Unmodified, IndexDiffType::LeftOnly) => {
-                        index.remove_entry(&file_path);
-                    }
-
-                    // 7. Any files present at the split point, unmodified in the given branch,
-                    // and absent in the current branch should remain absent.
-                    (IndexDiffType::LeftOnly, IndexDiffType::Unmodified) => (),
+    /// Takes the lock of the index and loads it, for changes that must not
+    /// interleave with another process's
+    fn lock_index(&self) -> Result<LockedIndex, String> {
+        Index::lock(&self.get_index_path())
+    }
 
-                    // Other cases
-                    _ => (),
-                }
+    /// Loads a blob as text; a missing blob reads as empty content
+    fn blob_text(&self, sha: Option<&EncodedSha>) -> Result<String, String> {
+        match sha {
+            None => Ok(String::new()),
+            Some(sha) => {
+                let data = self.obj_db.retrieve(sha).map_err(|why| why.to_string())?;
+                let blob = Blob::deserialize(&data)?;
+                Ok(String::from_utf8_lossy(&blob.data).into_owned())
             }
-            if branch_status.is_none() {
-                let cur_status = cur_status.unwrap();
-                match cur_status {
-                    // 4. Any files that were not present at the split point and are present only in the current branch
-                    // should remain as they are.
-                    IndexDiffType::RightOnly => (),
-                    _ => unreachable!(),
-                }
-            }
-            if cur_status.is_none() {
-                let branch_status = branch_status.unwrap();
-                // 5. Any files that were not present at the split point
-                // and are present only in the given branch should be checked out and staged.
-                match branch_status {
-                    IndexDiffType::RightOnly => {
-                        let sha = branch_index.get_sha1(&file_path).unwrap();
-                        index.update_entry(file_path, sha.clone());
-                    }
-                    _ => unreachable!(),
-                }
+        }
+    }
+    /// The content of a blob; a missing blob reads as empty
+    fn blob_bytes(&self, sha: Option<&EncodedSha>) -> Result<Vec<u8>, String> {
+        match sha {
+            Some(sha) => {
+                let data = self.obj_db.retrieve(sha).map_err(|why| why.to_string())?;
+                Ok(Blob::deserialize(&data)?.data)
             }
+            None => Ok(Vec::new()),
         }
-
-        // Write merged index and create commit
-        if let Err(why) = index.save(&self.get_index_path()) {
-            println!("{why}");
-            std::process::exit(1);
+    }
+    /// Prints the best common ancestor of two commits, or all of them with
+    /// `all`, like `git merge-base`.
+    ///
+    /// # Returns
+    /// Whether the commits have a common ancestor at all
+    pub fn merge_base(&self, lhs: &str, rhs: &str, all: bool) -> Result<bool, Error> {
+        let bases = self.find_lcas(&self.resolve_commit(lhs)?, &self.resolve_commit(rhs)?);
+        let count = if all { bases.len() } else { 1.min(bases.len()) };
+        for base in &bases[..count] {
+            println!("{base}");
         }
-        // Update work dir
-        self.checkout_index(&index);
-        let tree_sha = self.write_tree().unwrap();
-        let parents = vec![current_commit_sha, branch_commit_sha.clone()];
-        let commit_sha = self
-            .commit_tree(
-                tree_sha,
-                parents,
-                &format!("Merge {}", branch_name),
-                AUTHOR_NAME,
-                AUTHOR_EMAIL,
-            )
-            .unwrap();
-        self.update_head(&commit_sha);
-        //if has_conflict {
-            //println!("Encountered a merge conflict.");
-        //}
+        Ok(!bases.is_empty())
     }
 
-    fn load_blob(&self, encoded_sha: &EncodedSha) -> Blob {
-        let blob_data = self.obj_db.retrieve(encoded_sha).unwrap();
-        let blob = Blob::deserialize(&blob_data).unwrap();
-        blob
+    /// Finds a lowest common ancestor of two commits, i.e. a common ancestor
+    /// that is not an ancestor of another common ancestor. When there are
+    /// several, as in criss-cross histories, the most recent one is returned.
+    pub fn find_lca(&self, lhs: &EncodedSha, rhs: &EncodedSha) -> Option<EncodedSha> {
+        self.find_lcas(lhs, rhs).into_iter().next()
     }
 
-    fn handle_conflict_text(
+    /// Finds every lowest common ancestor of two commits, following all
+    /// parents of merges. Sorted by committer date, most recent first.
+    pub fn find_lcas(&self, lhs: &EncodedSha, rhs: &EncodedSha) -> Vec<EncodedSha> {
+        let lhs_ancestors: HashSet<EncodedSha> = self.reachable_from([lhs.clone()]).collect();
+        let common: Vec<EncodedSha> = self
+            .reachable_from([rhs.clone()])
+            .filter(|sha| lhs_ancestors.contains(sha))
+            .collect();
+        // Whatever is reachable from a common ancestor's parents is an
+        // ancestor of that one, so it cannot be a lowest one
+        let parents = common
+            .iter()
+            .filter_map(|sha| self.read_commit(sha).ok())
+            .flat_map(|commit| commit.get_parents().clone());
+        let redundant: HashSet<EncodedSha> = self.reachable_from(parents).collect();
+        let mut bases: Vec<(i64, EncodedSha)> = common
+            .into_iter()
+            .filter(|sha| !redundant.contains(sha))
+            .map(|sha| {
+                let time = self
+                    .read_commit(&sha)
+                    .map(|commit| commit.get_committer().get_timestamp().timestamp())
+                    .unwrap_or_default();
+                (time, sha)
+            })
+            .collect();
+        bases.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.0.cmp(&b.1.0)));
+        bases.into_iter().map(|(_, sha)| sha).collect()
+    }
+    /// Restores files in the working tree from the index, or from the tree
+    /// of `source` when given, leaving HEAD, the index and the files not
+    /// matched by `paths` alone
+    pub fn checkout_paths<S: AsRef<str>>(
         &self,
-        path: &Path,
-        cur_content: String,
-        branch_content: String,
-        index: &mut Index,
-    ) {
-        let a_lines: Vec<&str> = cur_content.split('\n').collect();
-        let diff = line_diff::line_diff(&cur_content, &branch_content);
-        let mut merged_content = String::new();
-        let get_conflict_text = |cur_text:&str, branch_text:&str| {
-            format!("<<<<<<< HEAD\n{}=======\n{}>>>>>>>", cur_text, branch_text)
-        };
-        merged_content += &get_conflict_text(&cur_content, &branch_content);
-
-        /* Example:
-        Merge conflict in test.txt: 1
-        Merge conflict in test.txt: [3, 5]
-        Merge conflict in test.txt: [7, 9]  */
-        for group in line_diff::group_ranges(&diff) {
-            if group.2 == true {
-                continue;
-            }
-            let start = group.0 + 1;
-            let end = group.1 + 1;
-            if start > a_lines.len() {
-                continue;
+        paths: &[S],
+        source: Option<&str>,
+    ) -> Result<(), Error> {
+        let index = match source {
+            Some(source) => {
+                let commit = self.read_commit(&self.resolve_commit(source)?)?;
+                self.read_tree(&commit.get_tree_sha())?
             }
-            let end = std::cmp::min(end, a_lines.len());
-            print!("Merge conflict in {}: ", path.file_name().unwrap().to_str().unwrap());
-            if start == end {
-                println!("{}", start);
-            } else {
-                println!("[{}, {}]", start, end);
+            None => self.load_index_or_empty()?,
+        };
+        for spec in normalize_pathspecs(paths) {
+            let matched = self.checkout_index_paths(&index, std::slice::from_ref(&spec))?;
+            if matched == 0 {
+                return Err(Error::PathspecNoMatch(spec));
             }
         }
-        let blob = Blob {
-            data: merged_content.into(),
-        };
-        let blob_sha = self.obj_db.store(&blob).unwrap();
-        index.update_entry(path, blob_sha);
+        Ok(())
     }
 
-    // Helper to handle content conflicts
-    fn handle_conflict(
-        &self,
-        path: &Path,
-        cur_blob_sha: &EncodedSha,
-        branch_blob_sha: &EncodedSha,
-        index: &mut Index,
-    ) {
-        let cur_content = String::from_utf8(self.load_blob(cur_blob_sha).data).unwrap();
-        let branch_content = String::from_utf8(self.load_blob(branch_blob_sha).data).unwrap();
-        self.handle_conflict_text(path, cur_content, branch_content, index);
-    }
-
-    // Helper to handle deletion conflicts
-    fn handle_deletion_conflict(
-        &self,
-        path: &Path,
-        blob_sha: &EncodedSha,
-        is_cur_content: bool,
-        index: &mut Index,
-    ) {
-        let content = String::from_utf8(self.load_blob(blob_sha).data).unwrap();
-        let (cur_content, branch_content) = if is_cur_content {
-            (content, String::new())
-        } else {
-            (String::new(), content)
-        };
-        self.handle_conflict_text(path, cur_content, branch_content, index);
-    }
-    fn fast_forward(&self, target_branch_name: &str) {
-        let head = self.get_head().unwrap();
-        let target_branch = self.load_branch(target_branch_name).unwrap();
-        let branch_dir = self.get_branch_dir();
-        self.checkout(target_branch_name);
-        let head = match head {
-            Head::Symbolic(p) => {
-                let current_branch = Branch::load(
-                    &self.get_branch_dir(),
-                    p.file_name().unwrap().to_str().unwrap(),
-                )
-                .unwrap();
-                let current_branch = Branch {
-                    commit_sha: target_branch.commit_sha,
-                    ..current_branch
-                };
-                if let Err(why) = current_branch.save(&branch_dir) {
-                    println!("Failed to save current branch: {why}");
-                    std::process::exit(1);
-                }
-                Head::Symbolic(p)
-            }
-            Head::Detached(_) => Head::Detached(target_branch.commit_sha.unwrap()),
-        };
-        head.save(&self.git_dir.join(HEAD_FILE)).unwrap();
-    }
-    fn find_lca(&self, lhs: &EncodedSha, rhs: &EncodedSha) -> Option<EncodedSha> {
-        let get_first_parent = |commit: &Commit| match commit.get_parents().first() {
-            Some(encoded_sha) => Some(encoded_sha.clone()),
-            None => None,
-        };
-        // Mark ancestors of lhs
-        let mut ancestors = HashSet::new();
-        let mut current = lhs.0.clone();
-        loop {
-            // Load commit data
-            let commit_data = self
-                .obj_db
-                .retrieve(EncodedSha::from_string(current.clone()))
-                .unwrap();
-            let commit = Commit::deserialize(&commit_data).unwrap();
-            ancestors.insert(current);
-            if commit.get_parents().is_empty() {
-                break;
+    /// Like [`Self::checkout_index`], but only writes out the files of
+    /// `index` matching `pathspecs`, whatever the working tree holds
+    ///
+    /// # Returns
+    /// The number of files written
+    fn checkout_index_paths(&self, index: &Index, pathspecs: &[String]) -> Result<usize, String> {
+        let mut written = 0;
+        for (file, sha) in index.collect_entries() {
+            if !matches_pathspec(&file, pathspecs) {
+                continue;
             }
-            current = match get_first_parent(&commit) {
-                Some(encoded_sha) => encoded_sha.0.clone(),
-                None => break, // Handle missing parent (e.g., invalid commit)
-            };
-        }
-        // Traverse rhs ancestors to find LCA
-        let mut current_rhs = rhs.0.clone();
-        loop {
-            // Check if current rhs commit is in lhs' ancestors
-            if ancestors.contains(&current_rhs) {
-                return Some(EncodedSha::from_string(current_rhs));
+            let blob_data = self.obj_db.retrieve(&sha).map_err(|why| why.to_string())?;
+            let blob = Blob::deserialize(&blob_data)?;
+            let path = self.dir.join(&file);
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir).map_err(|why| why.to_string())?;
             }
-
-            // Move to parent commit
-            let commit_data = self
-                .obj_db
-                .retrieve(EncodedSha::from_string(current_rhs.clone()))
-                .unwrap();
-            let commit = Commit::deserialize(&commit_data).unwrap();
-
-            match get_first_parent(&commit) {
-                Some(parent_sha) => current_rhs = parent_sha.0,
-                None => {
-                    break;
-                } // Reached root commit
-            };
+            fs::write(&path, &blob.data).map_err(|why| why.to_string())?;
+            written += 1;
         }
-        None
+        Ok(written)
     }
+
     fn load_branch(&self, branch_name: &str) -> Option<Branch> {
-        // Load branch metadata
-        let branch = Branch::load(&self.git_dir.join(REFS_DIR).join(HEADS_DIR), branch_name);
-        branch
+        // Load branch metadata, falling back to packed-refs
+        Branch::load(&self.git_dir.join(REFS_DIR).join(HEADS_DIR), branch_name).or_else(|| {
+            let commit_sha = self.packed_ref(&format!("{REFS_DIR}/{HEADS_DIR}/{branch_name}"))?;
+            Some(Branch {
+                name: branch_name.to_string(),
+                commit_sha: Some(commit_sha),
+            })
+        })
     }
 
-    fn read_branch_to_index(&self, branch_name: &str) -> Index {
-        let branch = match self.load_branch(branch_name) {
-            Some(b) => b,
-            None => {
-                println!("No such branch exists.");
-                std::process::exit(1);
+    /// Every branch under refs/heads or in packed-refs, sorted by name
+    fn list_branches(&self) -> Vec<Branch> {
+        let branch_dir = self.get_branch_dir();
+        let mut branches: Vec<Branch> = WalkDir::new(&branch_dir)
+            .sort_by_file_name()
+            .into_iter()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_type().is_file())
+            .map(|entry| {
+                let name = entry.path().strip_prefix(&branch_dir).unwrap();
+                name.to_string_lossy().replace('\\', "/")
+            })
+            .filter(|name| !name.ends_with(lock::LOCK_SUFFIX))
+            .filter_map(|name| Branch::load(&branch_dir, &name))
+            .collect();
+        // Loose refs take precedence over packed ones
+        for packed in self.packed_branches() {
+            if !branches.iter().any(|branch| branch.name == packed.name) {
+                branches.push(packed);
             }
-        };
-        let commit_sha = branch.commit_sha;
-        if commit_sha.is_some() {
-            let commit_sha = commit_sha.unwrap();
-
-            // Load commit data
-            let commit_data = self.obj_db.retrieve(commit_sha).unwrap();
-            let commit = Commit::deserialize(&commit_data).unwrap();
-
-            // Build index from commit's tree
-            let tree_sha = commit.get_tree_sha();
-            let index = self.read_tree(&tree_sha).unwrap_or_else(|why| {
-                println!("{why}");
-                std::process::exit(1);
-            });
-            return index;
-        } else {
-            // empty branch
-            // remove all files checked by current index
-            let index = Index::new();
-            return index;
         }
-
+        branches.sort_by(|a, b| a.name.cmp(&b.name));
+        branches
     }
 
-    /// Checks out a branch by updating HEAD and working directory
+    /// Checks out a branch by updating HEAD and working directory.
+    /// A target that is not a branch but names a branch of exactly one
+    /// remote creates a local branch tracking it. Any other target is
+    /// resolved as a commit SHA1, possibly abbreviated, or a remote-tracking
+    /// ref like `origin/master`, and checked out as a detached HEAD.
+    /// Local changes are kept, or refused when they would be overwritten.
     ///
     /// # Arguments
-    /// * `branch_name` - Name of the branch to check out
-    pub fn checkout(&self, branch_name: &str) {
-        let branch = match self.load_branch(branch_name) {
-            Some(b) => b,
-            None => {
-                println!("No such branch exists.");
-                std::process::exit(1);
-            }
-        };
-        if let Some(head) = self.get_head() {
-            if let Head::Symbolic(current_branch_path) = head {
-                if current_branch_path.file_name().unwrap().to_str().unwrap() == &branch.name {
-                    //println!("No need to checkout current branch");
-                    std::process::exit(0);
+    /// * `branch_name` - Name of the branch or commit to check out
+    /// * `autostash` - Stash uncommitted changes before switching and
+    ///   reapply them on top of the checked out branch
+    pub fn checkout(&self, branch_name: &str, autostash: bool) -> Result<(), Error> {
+        // `-` and `@{-n}` go back to the branch checked out before
+        if let Some(previous) = self.previous_checkout(branch_name)? {
+            return self.checkout(&previous, autostash);
+        }
+        let (head, new_commit) = match self.load_branch(branch_name) {
+            Some(branch) => {
+                if self.get_head().and_then(|head| head.branch_name()) == Some(branch.name.clone())
+                {
+                    // No need to checkout current branch
+                    return Ok(());
                 }
+                let head = Head::Symbolic(Path::new(REFS_DIR).join(HEADS_DIR).join(&branch.name));
+                (head, branch.commit_sha)
             }
-        }
-
-        let head = Head::Symbolic(Path::new(REFS_DIR).join(HEADS_DIR).join(branch.name));
+            None => match self.branch_from_remote(branch_name)? {
+                // A branch only the remotes have is created to track it
+                Some(branch) => {
+                    let head =
+                        Head::Symbolic(Path::new(REFS_DIR).join(HEADS_DIR).join(&branch.name));
+                    (head, branch.commit_sha)
+                }
+                None => match self.resolve_commit(branch_name) {
+                    Ok(sha) => (Head::Detached(sha.clone()), Some(sha)),
+                    Err(_) => return Err(Error::BadRevision(branch_name.to_string())),
+                },
+            },
+        };
 
-        let index = self.read_branch_to_index(branch_name);
-        // Update working directory
-        self.checkout_index(&index);
-
-        // Save index state
-        index
-            .save(&self.git_dir.join(INDEX_FILE))
-            .unwrap_or_else(|why| {
-                println!("{why}");
-                std::process::exit(1);
-            });
-        head.save(&self.git_dir.join(HEAD_FILE)).unwrap();
+        self.move_head(head, new_commit, branch_name, autostash)
     }
 
-    /// Recursively collects all file entries from a tree object
-    ///
-    /// # Arguments
-    /// * `tree_sha` - SHA1 hash of the tree object to process
+    /// Points HEAD at `head`, the branch or commit the user named `target`,
+    /// and checks out `new_commit`, the commit it points at.
     ///
-    /// # Returns
-    /// Tuple containing:
-    /// - Vector of relative file paths
-    /// - Vector of corresponding SHA1 hashes
-    fn collect_tree_files(
+    /// Only files that differ between the old and the new commit are
+    /// touched; staged and unstaged changes to other files are carried
+    /// over. Local changes to the files that differ are refused with
+    /// [`Error::LocalChangesOverwritten`] unless `autostash` stashes them.
+    fn move_head(
         &self,
-        tree_sha: &EncodedSha,
-    ) -> Result<(Vec<PathBuf>, Vec<EncodedSha>), String> {
-        // Retrieve and deserialize tree object
-        let tree_data = self
-            .obj_db
-            .retrieve(tree_sha)
-            .map_err(|why| why.to_string())?;
-        let tree = Tree::deserialize(&tree_data).map_err(|why| why.to_string())?;
-
-        let mut path_vec: Vec<PathBuf> = Vec::new();
-        let mut sha_vec: Vec<EncodedSha> = Vec::new();
-
-        // Process each entry in the tree
-        for (name, entry) in tree.get_entries() {
-            match entry.object_type {
-                ObjectType::Blob => {
-                    // Add file entry directly
-                    path_vec.push(PathBuf::from_str(name).map_err(|why| why.to_string())?);
-                    sha_vec.push(entry.sha1.clone());
-                }
-                ObjectType::Tree => {
-                    // Recursively process subtree
-                    let (sub_tree_path_vec, sub_tree_sha_vec) =
-                        self.collect_tree_files(&entry.sha1)?;
-                    // Merge subtree results with current paths
-                    for sha in sub_tree_sha_vec {
-                        sha_vec.push(sha);
-                    }
-                    for path in sub_tree_path_vec {
-                        path_vec.push(Path::new(name).join(path));
-                    }
+        head: Head,
+        new_commit: Option<EncodedSha>,
+        branch_name: &str,
+        autostash: bool,
+    ) -> Result<(), Error> {
+        let old_commit = self.get_current_commit();
+        let info = CheckoutInfo {
+            branch: branch_name,
+            old: old_commit.as_ref(),
+            new: new_commit.as_ref(),
+        };
+        self.run_pre_checkout(&info)
+            .map_err(|reason| Error::HookRejected {
+                hook: "pre-checkout",
+                reason: reason.to_string(),
+            })?;
+
+        let stash = if autostash && self.get_current_commit().is_some() {
+            self.create_autostash()?
+        } else {
+            None
+        };
+
+        let old_index = match &old_commit {
+            Some(sha) => self.read_tree(&self.read_commit(sha)?.get_tree_sha())?,
+            None => Index::new(),
+        };
+        let new_index = match &new_commit {
+            Some(sha) => self.read_tree(&self.read_commit(sha)?.get_tree_sha())?,
+            None => Index::new(),
+        };
+        let changed: Vec<String> = self
+            .diff_index(&old_index, &new_index)
+            .into_iter()
+            .filter(|(_, status)| *status != IndexDiffType::Unmodified)
+            .map(|(path, _)| path)
+            .collect();
+        let mut index = self.load_index_or_empty()?;
+        let worktree = self.snapshot_files(Snapshot::Worktree)?;
+        let mut overwritten: Vec<String> = changed
+            .iter()
+            .filter(|path| {
+                index.get_sha1(path) != old_index.get_sha1(path)
+                    || index.get_sha1(path) != worktree.entries.get(*path)
+            })
+            .cloned()
+            .collect();
+        if !overwritten.is_empty() {
+            overwritten.sort();
+            if let Some(stash) = stash {
+                self.apply_autostash(&stash)?;
+            }
+            return Err(Error::LocalChangesOverwritten(overwritten));
+        }
+
+        // Update working directory
+        self.checkout_index(&new_index)?;
+
+        // The files that differ take the new version; other entries keep
+        // whatever is staged for them
+        for path in &changed {
+            match new_index.get_sha1(path) {
+                Some(sha) => {
+                    let mode = new_index.get_mode(path).unwrap_or_default();
+                    index.update_entry_with_mode(path, sha.clone(), mode);
                 }
-                ObjectType::Commit => {
-                    return Err(format!("Commit type should not appear in a tree"));
+                None => {
+                    index.remove_entry(path);
                 }
             }
         }
-        Ok((path_vec, sha_vec))
+        index.save(&self.git_dir.join(INDEX_FILE))?;
+        let from = match self.get_head().and_then(|head| head.branch_name()) {
+            Some(branch) => branch,
+            None => old_commit
+                .as_ref()
+                .map(|sha| sha.to_string())
+                .unwrap_or_default(),
+        };
+        self.save_head(
+            &head,
+            &format!("checkout: moving from {from} to {branch_name}"),
+        )?;
+        if let Head::Detached(sha) = &head {
+            let commit = self.read_commit(sha)?;
+            let subject = commit.get_message().lines().next().unwrap_or_default();
+            println!("HEAD is now at {} {subject}", &sha.0[..7]);
+        }
+
+        if let Some(stash) = stash {
+            self.apply_autostash(&stash)?;
+        }
+        self.run_post_checkout(&info);
+        Ok(())
     }
+
     /// Creates a commit object from a tree SHA and parent commits,
     /// then stores it in the object database.
     ///
@@ -1055,8 +1013,8 @@ impl Repository {
     /// * `tree_sha` - SHA1 hash of the tree object representing the snapshot
     /// * `parents` - List of parent commit SHA1s (empty for initial commit)
     /// * `message` - Commit message
-    /// * `author_name` - Config user.name for author
-    /// * `author_email` - Config user.email for author
+    /// * `author_name` - Name of the author and committer
+    /// * `author_email` - Email of the author and committer
     ///
     /// # Returns
     /// SHA1 hash of the created commit object
@@ -1067,6 +1025,32 @@ impl Repository {
         message: &str,
         author_name: &str,
         author_email: &str,
+    ) -> Result<EncodedSha, String> {
+        self.commit_tree_with_author(
+            tree_sha,
+            parents,
+            message,
+            None,
+            author_name,
+            author_email,
+            false,
+        )
+    }
+
+    /// Like [`Self::commit_tree`], but keeps `author` when given, as when
+    /// replaying someone else's commit, and records the current identity
+    /// only as the committer. With `sign` the commit carries a signature of
+    /// the committer.
+    #[allow(clippy::too_many_arguments)]
+    fn commit_tree_with_author(
+        &self,
+        tree_sha: EncodedSha,
+        parents: Vec<EncodedSha>,
+        message: &str,
+        author: Option<&Author>,
+        committer_name: &str,
+        committer_email: &str,
+        sign: bool,
     ) -> Result<EncodedSha, String> {
         // Generate timestamp with current time and local offset
         let now = Utc::now();
@@ -1074,229 +1058,613 @@ impl Repository {
         let timestamp = now.with_timezone(&offset);
 
         // Create author/committer (usually same unless amended)
-        let author = Author::new(author_name, author_email, timestamp);
-        let committer = author.clone();
+        let committer = Author::new(committer_name, committer_email, timestamp);
+        let author = author.cloned().unwrap_or_else(|| committer.clone());
 
         // Build commit object
-        let commit = Commit::new(tree_sha, parents, author, committer, message);
+        let mut commit = Commit::new(tree_sha, parents, author, committer, message);
+        if sign {
+            let signature = self
+                .sign_payload(
+                    &commit.to_string(),
+                    &format!("{committer_name} <{committer_email}>"),
+                )
+                .map_err(|why| why.to_string())?;
+            commit.set_signature(&signature);
+        }
 
         // Store in object database and return SHA1
-        Ok(self.obj_db.store(&commit).map_err(|e| e.to_string())?)
+        self.obj_db.store(&commit).map_err(|e| e.to_string())
     }
 
     /// Attempts to load and return the HEAD reference from the .git directory.
     /// Returns `Some(Head)` if successfully loaded, or `None` on error.
     fn get_head(&self) -> Option<Head> {
         let head_path = self.git_dir.join(HEAD_FILE);
-        let head = Head::load(&head_path).ok();
-        head
+
+        Head::load(&head_path).ok()
     }
 
     /// Resolves and returns the SHA1 hash of the current commit.
     /// - For symbolic references (branches): Follows the branch pointer
     /// - For detached HEAD states: Directly returns the commit SHA1
+    ///
     /// Panics if HEAD cannot be resolved or branch data is corrupted.
     /// If there is no commit found (e.g: just after git init), None is returned.
     fn get_current_commit(&self) -> Option<EncodedSha> {
         let head = self.get_head().unwrap();
         match head {
             Head::Symbolic(path_buf) => {
-                let branch_path = self.git_dir.join(path_buf);
+                let branch_path = self.git_dir.join(&path_buf);
                 let branch_result = Branch::load(
-                    &branch_path.parent().unwrap(),
+                    branch_path.parent().unwrap(),
                     branch_path.file_name().unwrap().to_str().unwrap(),
                 );
                 match branch_result {
                     Some(branch) => branch.commit_sha,
-                    None => None,
+                    None => self.packed_ref(&path_buf.to_string_lossy().replace('\\', "/")),
                 }
             }
             Head::Detached(encoded_sha) => Some(encoded_sha),
         }
     }
 
+    /// Resolves a name to a commit SHA1, without the `~` and `^` suffixes
+    /// [`Self::resolve_commit`] takes.
+    /// Accepts `HEAD`, a branch name, a tag, `<ref>@{n}` or a commit SHA1,
+    /// either in full or abbreviated to a unique prefix of at least 4
+    /// characters.
+    fn resolve_name(&self, rev: &str) -> Result<EncodedSha, String> {
+        if let Some(sha) = self.resolve_reflog_selector(rev) {
+            return sha;
+        }
+        if rev == HEAD_FILE {
+            return self
+                .get_current_commit()
+                .ok_or_else(|| "HEAD does not point to a commit yet".to_string());
+        }
+        if STATE_HEADS.contains(&rev) {
+            return self
+                .state_head(rev)
+                .ok_or_else(|| format!("{rev} does not name a commit"));
+        }
+        if let Some(branch) = self.load_branch(rev) {
+            return branch
+                .commit_sha
+                .ok_or_else(|| format!("There is no commit in branch {rev}"));
+        }
+        if let Some(sha) = self.load_remote_ref(rev) {
+            return Ok(sha);
+        }
+        if let Some(sha) = self.load_tag(rev) {
+            return match self.peel_tag(&sha) {
+                (sha, ObjectType::Commit) => Ok(sha),
+                _ => Err(format!("{rev} is not a commit")),
+            };
+        }
+        if rev.starts_with(&format!("{REFS_DIR}/"))
+            && let Some(sha) = self.read_ref(rev)
+        {
+            return match self.peel_tag(&sha) {
+                (sha, ObjectType::Commit) => Ok(sha),
+                _ => Err(format!("{rev} is not a commit")),
+            };
+        }
+        if let Ok(sha) = EncodedSha::from_str(rev) {
+            let data = self
+                .obj_db
+                .retrieve(&sha)
+                .map_err(|_| format!("bad revision '{rev}'"))?;
+            Commit::deserialize(&data).map_err(|_| format!("{rev} is not a commit"))?;
+            return Ok(sha);
+        }
+        if (MIN_ABBREV_LEN..40).contains(&rev.len()) && rev.chars().all(|c| c.is_ascii_hexdigit()) {
+            let prefix = rev.to_ascii_lowercase();
+            let objects = self.obj_db.loose_objects().map_err(|why| why.to_string())?;
+            let mut commits = objects.into_iter().filter(|sha| {
+                sha.0.starts_with(&prefix)
+                    && self
+                        .obj_db
+                        .retrieve(sha)
+                        .is_ok_and(|data| Commit::deserialize(&data).is_ok())
+            });
+            return match (commits.next(), commits.next()) {
+                (Some(sha), None) => Ok(sha),
+                (Some(_), Some(_)) => Err(format!("short SHA1 {rev} is ambiguous")),
+                (None, _) => Err(format!("bad revision '{rev}'")),
+            };
+        }
+        Err(format!("bad revision '{rev}'"))
+    }
+
     fn get_branch_dir(&self) -> PathBuf {
         self.git_dir.join(REFS_DIR).join(HEADS_DIR)
     }
 
+    fn get_remotes_dir(&self) -> PathBuf {
+        self.git_dir.join(REFS_DIR).join(REMOTES_DIR)
+    }
+
+    /// Reads a remote-tracking ref given as `<remote>/<branch>` or
+    /// `refs/remotes/<remote>/<branch>`
+    fn load_remote_ref(&self, name: &str) -> Option<EncodedSha> {
+        let prefix = format!("{REFS_DIR}/{REMOTES_DIR}/");
+        let name = name.strip_prefix(&prefix).unwrap_or(name);
+        self.read_ref(&format!("{prefix}{name}"))
+    }
+
     /// Creates a new branch pointing to the current commit.
     /// - Checks for existing branch name conflicts
-    /// - Exits process if branch already exists
+    /// - Fails if branch already exists
     /// - Saves new branch reference in .git/refs/heads/
-    pub fn branch<S: AsRef<str>>(&self, name: S) {
-        let branch_dir = self.get_branch_dir();
-        match Branch::load(&branch_dir, name.as_ref()) {
-            Some(_) => {
-                println!("A branch with that name already exists.");
-                std::process::exit(0);
-            }
-            None => {}
+    pub fn branch<S: AsRef<str>>(&self, name: S) -> Result<(), Error> {
+        if self.load_branch(name.as_ref()).is_some() {
+            return Err(Error::BranchExists(name.as_ref().to_string()));
         };
         let current_commit = self.get_current_commit();
         let branch = Branch {
             name: name.as_ref().to_string(),
             commit_sha: current_commit,
         };
-        branch.save(&branch_dir).unwrap();
+        Ok(self.save_branch(&branch, "branch: Created from HEAD")?)
     }
 
     /// Deletes an existing branch.
     /// - Prevents deletion of currently checked-out branch
-    /// - Exits process if attempting to delete active branch
+    /// - Fails if attempting to delete active branch
     /// - Removes branch reference from .git/refs/heads/
-    pub fn rm_branch<S: AsRef<str>>(&self, name: S) {
-        let head = self.get_head().unwrap();
-        match head {
-            Head::Symbolic(path_buf) => {
-                if path_buf.file_name().unwrap().to_str().unwrap() == name.as_ref() {
-                    println!("Cannot delete the currently active branch.");
-                    std::process::exit(0);
-                }
-            }
-            Head::Detached(_) => (),
+    pub fn rm_branch<S: AsRef<str>>(&self, name: S) -> Result<(), Error> {
+        if self
+            .get_head()
+            .and_then(|head| head.branch_name())
+            .as_deref()
+            == Some(name.as_ref())
+        {
+            return Err("Cannot delete the currently active branch.".into());
         }
-        let branch_dir = self.git_dir.join(REFS_DIR).join(HEADS_DIR);
-        Branch::remove(&branch_dir, name.as_ref()).unwrap()
+        if self.load_branch(name.as_ref()).is_none() {
+            return Err(Error::NoSuchBranch(name.as_ref().to_string()));
+        }
+        Ok(self.remove_branch(name.as_ref(), "branch: deleted")?)
     }
 
     /// Stages file changes to the index (staging area).
     /// Accepts a list of file paths and updates their entries in the index.
-    pub fn add<S: AsRef<str>>(&self, files: &Vec<S>) {
-        let add_single_file = |p: &Path| {
-            self.update_index(p).unwrap_or_else(|why| {
-                println!("{why}");
-                std::process::exit(1);
-            })
+    /// Directories are added recursively and glob patterns like
+    /// `src/**/*.rs` stage every matching file; both skip ignored files.
+    pub fn add<S: AsRef<str>>(&self, files: &Vec<S>) -> Result<(), Error> {
+        let ignore = self.ignore_rules();
+        let mut index = self.lock_index()?;
+        let is_ignored = |p: &Path, is_dir: bool| match self.turn_relative_path_to_repo_dir(p) {
+            Ok(relative) => {
+                let relative = relative.to_string_lossy().replace('\\', "/");
+                !relative.is_empty() && ignore.is_ignored(&relative, is_dir)
+            }
+            Err(_) => false,
         };
         for file in files {
             let file_path = Path::new(file.as_ref());
-            if file_path.is_dir() {
-                for entry in WalkDir::new(file_path)
+            if !file_path.exists() && is_glob(file.as_ref()) {
+                let spec = self.turn_relative_path_to_repo_dir(file_path)?;
+                let matches = self.expand_glob(&spec.to_string_lossy(), &index, &ignore)?;
+                if matches.is_empty() {
+                    return Err(Error::PathspecNoMatch(file.as_ref().to_string()));
+                }
+                for path in matches {
+                    self.stage_file(&mut index, &self.dir.join(path))?;
+                }
+            } else if file_path.is_dir() {
+                // Ignored files are skipped, ignored directories not entered
+                let mut entries = WalkDir::new(file_path)
                     .into_iter()
-                    .filter_map(|e| e.ok())
-                    .filter(|f| f.file_type().is_file())
-                    .filter(|f| self.is_file_path_vaild(f.path()))
-                {
-                    add_single_file(entry.path());
+                    .filter_entry(|f| !is_ignored(f.path(), f.file_type().is_dir()));
+                while let Some(entry) = entries.next() {
+                    let Ok(entry) = entry else {
+                        continue;
+                    };
+                    if entry.file_type().is_dir() && self.is_submodule_dir(entry.path()) {
+                        // Nested repositories are staged by their HEAD
+                        entries.skip_current_dir();
+                        self.stage_gitlink(&mut index, entry.path())?;
+                    } else if entry.file_type().is_file() && self.is_file_path_vaild(entry.path()) {
+                        self.stage_file(&mut index, entry.path())?;
+                    }
                 }
             } else {
-                add_single_file(file_path);
+                // Named files are refused too, unless they are tracked already
+                if is_ignored(file_path, false)
+                    && let Ok(relative) = self.turn_relative_path_to_repo_dir(file_path)
+                    && index.get_sha1(&relative).is_none()
+                {
+                    return Err(format!(
+                        "The following paths are ignored by one of your .gitignore files:\n{}",
+                        file.as_ref()
+                    )
+                    .into());
+                }
+                self.stage_file(&mut index, file_path)?;
             }
         }
+        Ok(index.commit()?)
     }
 
-    pub fn rm<S: AsRef<str>>(&self, files: &Vec<S>) {
-        let add_single_file = |p: &Path| {
-            self.update_index(p).unwrap_or_else(|why| {
-                println!("{why}");
-                std::process::exit(1);
-            })
+    /// Lists the repository-relative paths matching a glob pathspec: files in
+    /// the working tree that are not ignored, plus tracked files that were
+    /// deleted so their removal gets staged
+    fn expand_glob(
+        &self,
+        spec: &str,
+        index: &Index,
+        ignore: &IgnoreRules,
+    ) -> Result<Vec<String>, String> {
+        let glob = glob_pathspec(spec)?;
+        let relative = |path: &Path| {
+            path.strip_prefix(&self.dir)
+                .unwrap_or(path)
+                .to_string_lossy()
+                .replace('\\', "/")
         };
-        let rm_single_file = |p: &Path| {
-            let index= Index::load(&self.get_index_path()).unwrap();
-            if index.get_sha1(p).is_none() {
-                println!("fatal: pathspec '{}' did not match any files", p.to_str().unwrap());
-                std::process::exit(1);
+        let walker = WalkDir::new(&self.dir)
+            .sort_by_file_name()
+            .into_iter()
+            .filter_entry(|entry| {
+                let name = relative(entry.path());
+                entry.path() != self.git_dir
+                    && (name.is_empty() || !ignore.is_ignored(&name, entry.file_type().is_dir()))
+            });
+        let mut matches = Vec::new();
+        for entry in walker {
+            let entry = entry.map_err(|why| why.to_string())?;
+            let name = relative(entry.path());
+            if entry.file_type().is_file() && glob.is_match(&name) {
+                matches.push(name);
             }
-            if let Err(why) = fs::remove_file(p) {
-                println!("fatal: {}", why);
-                std::process::exit(1);
+        }
+        for (path, _) in index.collect_entries() {
+            if glob.is_match(&path) && !self.dir.join(&path).exists() {
+                matches.push(path);
             }
-            add_single_file(p);
-        };
+        }
+        matches.sort();
+        Ok(matches)
+    }
+
+    /// Removes files from the index, and from the working tree unless
+    /// `cached`. Directories are only removed, along with every tracked
+    /// file below them, when `recursive`.
+    ///
+    /// Every path is checked before anything is removed.
+    pub fn rm<S: AsRef<str>>(
+        &self,
+        files: &Vec<S>,
+        cached: bool,
+        recursive: bool,
+    ) -> Result<(), Error> {
+        let mut index = self.lock_index()?;
+        let mut removed = Vec::new();
         for file in files {
-            let file_path = Path::new(file.as_ref());
-            if file_path.is_dir() {
-                for entry in WalkDir::new(file_path)
-                    .into_iter()
-                    .filter_map(|e| e.ok())
-                    .filter(|f| f.file_type().is_file())
-                    .filter(|f| self.is_file_path_vaild(f.path()))
-                {
-                    rm_single_file(entry.path());
-                }
-            } else {
-                rm_single_file(file_path);
+            let relative = self.turn_relative_path_to_repo_dir(Path::new(file.as_ref()))?;
+            let relative = relative.to_string_lossy().replace('\\', "/");
+            let is_tracked_file =
+                index.get_sha1(&relative).is_some() || index.get_conflict(&relative).is_some();
+            let entries = index.remove_subtree(&relative);
+            if entries.is_empty() {
+                return Err(Error::PathspecNoMatch(file.as_ref().to_string()));
+            }
+            if !is_tracked_file && !recursive {
+                return Err(
+                    format!("not removing '{}' recursively without -r", file.as_ref()).into(),
+                );
+            }
+            removed.extend(entries);
+        }
+
+        for name in &removed {
+            println!("rm '{name}'");
+            if cached {
+                continue;
+            }
+            let path = self.dir.join(name);
+            match fs::remove_file(&path) {
+                Ok(()) => {}
+                Err(why) if why.kind() == std::io::ErrorKind::NotFound => {}
+                Err(why) => return Err(format!("cannot remove '{name}': {why}").into()),
             }
+            let mut dir = path.parent();
+            while let Some(parent) = dir
+                && parent != self.dir
+                && fs::remove_dir(parent).is_ok()
+            {
+                dir = parent.parent();
+            }
+        }
+        Ok(index.commit()?)
+    }
+
+    /// Moves or renames a tracked file or directory in the working tree and
+    /// moves its index entries along, keeping their content and mode.
+    /// Moving onto an existing directory moves `src` into it.
+    ///
+    /// Everything is checked before anything is moved.
+    pub fn mv(&self, src: &str, dst: &str) -> Result<(), Error> {
+        let mut index = self.load_index_or_empty()?;
+        let relative = |path: &Path| -> Result<String, Error> {
+            let relative = self.turn_relative_path_to_repo_dir(path)?;
+            Ok(relative.to_string_lossy().replace('\\', "/"))
+        };
+        let src_path = Path::new(src);
+        let src_name = relative(src_path)?;
+        let mut dst_path = PathBuf::from(dst);
+        if dst_path.is_dir()
+            && let Some(file_name) = src_path.file_name()
+        {
+            dst_path.push(file_name);
         }
+        let dst_name = relative(&dst_path)?;
+
+        // The tracked files to move, as (old path, new path)
+        let moves: Vec<(String, String)> = match index.get_sha1(&src_name) {
+            Some(_) => vec![(src_name.clone(), dst_name.clone())],
+            None => index
+                .collect_entries()
+                .into_iter()
+                .filter_map(|(path, _)| {
+                    let rest = path.strip_prefix(&format!("{src_name}/"))?.to_string();
+                    Some((path, format!("{dst_name}/{rest}")))
+                })
+                .collect(),
+        };
+        if src_name.is_empty() || moves.is_empty() {
+            return Err(format!("not under version control, source={src}").into());
+        }
+        if !src_path.exists() {
+            return Err(format!("bad source, source={src}").into());
+        }
+        if dst_path.exists() {
+            return Err(format!("destination exists, destination={dst}").into());
+        }
+        if dst_name == src_name || dst_name.starts_with(&format!("{src_name}/")) {
+            return Err(format!("cannot move directory over itself, source={src}").into());
+        }
+
+        if let Some(parent) = dst_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::rename(src_path, &dst_path)?;
+        for (old, new) in moves {
+            let mode = index.get_mode(&old).unwrap_or_default();
+            let sha = index.remove_entry(&old).unwrap();
+            index.update_entry_with_mode(&new, sha, mode);
+        }
+        Ok(index.save(&self.get_index_path())?)
     }
 
     /// Creates a new commit with staged changes.
     /// - Validates non-empty commit message
     /// - Records parent commit, tree state, and author information
     /// - Updates HEAD reference (branch pointer or detached commit)
-    /// Exits process if no changes detected or message is empty.
-    pub fn commit<S: AsRef<str>>(&self, message: S) {
-        // Convert the message to a string reference
-        let message = message.as_ref();
+    ///
+    /// Fails if no changes detected or message is empty.
+    ///
+    /// # Returns
+    /// SHA1 of the new commit
+    pub fn commit<S: AsRef<str>>(&self, message: S) -> Result<EncodedSha, Error> {
+        self.commit_paths(message, &[] as &[&str])
+    }
+
+    /// Like `commit`, but only commits the staged changes of paths matching
+    /// `paths`. The tree takes the index content for those paths and HEAD
+    /// content for everything else; other staged changes stay staged.
+    pub fn commit_paths<S: AsRef<str>, P: AsRef<str>>(
+        &self,
+        message: S,
+        paths: &[P],
+    ) -> Result<EncodedSha, Error> {
+        self.commit_with(Some(message.as_ref()), paths, CommitOptions::default())
+    }
+
+    /// Replaces the HEAD commit with one holding the staged changes, with the
+    /// same parents and `message`, or the old message if none is given.
+    /// `paths` limits the staged changes taken as in `commit_paths`.
+    pub fn commit_amend<P: AsRef<str>>(
+        &self,
+        message: Option<&str>,
+        paths: &[P],
+    ) -> Result<EncodedSha, Error> {
+        let options = CommitOptions {
+            amend: true,
+            ..CommitOptions::default()
+        };
+        self.commit_with(message, paths, options)
+    }
+
+    /// Commits the staged changes to paths matching `paths`, or all of
+    /// them, as `commit_paths` and `commit_amend` do, with more `options`
+    pub fn commit_with<P: AsRef<str>>(
+        &self,
+        message: Option<&str>,
+        paths: &[P],
+        options: CommitOptions,
+    ) -> Result<EncodedSha, Error> {
+        let amend = options.amend;
+        let pathspecs = normalize_pathspecs(paths);
+        // A merge stopped at conflicts is concluded as a whole
+        let merge_head = self.merge_head();
+        if merge_head.is_some() && amend {
+            return Err("You are in the middle of a merge -- cannot amend.".into());
+        }
+        if merge_head.is_some() && !pathspecs.is_empty() {
+            return Err("cannot do a partial commit during a merge.".into());
+        }
+
+        // The commit being amended, if any
+        let amended = if amend {
+            let sha = self
+                .get_current_commit()
+                .ok_or("You have nothing to amend.")?;
+            Some(self.read_commit(&sha)?)
+        } else {
+            None
+        };
+        // A conflicted revert or merge leaves its message for the commit
+        // concluding it
+        let pending = self.pending_message();
+        let message = message
+            .filter(|message| !message.is_empty())
+            .or(amended.as_ref().map(|commit| commit.get_message()))
+            .or(pending.as_deref())
+            .unwrap_or_default();
 
         // Validate commit message is not empty
-        if message.len() == 0 {
-            println!("Please enter a commit message.");
-            std::process::exit(0);
+        if message.is_empty() {
+            return Err("Please enter a commit message.".into());
+        }
+
+        let unmerged: Vec<String> = self
+            .load_index_or_empty()?
+            .conflicts()
+            .keys()
+            .cloned()
+            .collect();
+        if !unmerged.is_empty() {
+            return Err(Error::UnmergedFiles(unmerged));
         }
 
         // Generate tree object from current index
-        let tree = self.write_tree().unwrap();
+        let tree = if pathspecs.is_empty() {
+            self.write_tree()?
+        } else {
+            self.write_partial_tree(&pathspecs)?
+        };
 
-        // Hardcoded author information (would normally be configurable)
-        let author_name = AUTHOR_NAME;
-        let author_email = AUTHOR_EMAIL;
+        let (author_name, author_email) = self.identity()?;
 
         // Get parent commit if exists
         let parent = self.get_current_commit();
 
-        // Create commit object, handling parent commit logic
-        let commit_sha = match parent {
-            Some(parent_sha) => {
+        // Collect parent commit, handling parent commit logic
+        let parents = match (&amended, parent.clone()) {
+            // The amended commit is replaced, so its parents are reused
+            (Some(amended), _) => amended.get_parents().clone(),
+            (None, Some(parent_sha)) => {
                 // Retrieve parent commit data from object database
-                let parent_commit_data = self.obj_db.retrieve(&parent_sha).unwrap();
-                let parent_commit = Commit::deserialize(&parent_commit_data).unwrap();
-
-                // Prevent empty commits by comparing tree hashes
-                if tree == parent_commit.get_tree_sha() {
-                    println!("No changes added to the commit.");
-                    std::process::exit(0);
-                } else {
-                    // Create commit with parent reference
-                    self.commit_tree(tree, vec![parent_sha], message, author_name, author_email)
-                        .unwrap()
+                let parent_commit = self.read_commit(&parent_sha)?;
+
+                // Prevent empty commits by comparing tree hashes; a merge
+                // may keep the tree of HEAD
+                if tree == parent_commit.get_tree_sha() && merge_head.is_none() {
+                    return Err(Error::NothingToCommit);
                 }
+                vec![parent_sha].into_iter().chain(merge_head).collect()
             }
             // Initial commit (no parent)
-            None => self
-                .commit_tree(tree, vec![], message, author_name, author_email)
-                .unwrap(),
+            (None, None) => vec![],
         };
-        self.update_head(&commit_sha);
-        eprintln!("{}", &commit_sha.0);
-    }
-    fn update_head(&self, commit_sha: &EncodedSha) {
-        // Update HEAD reference
-        let head = self.get_head().unwrap();
-        let new_head = match &head {
-            // Handle branch reference (symbolic HEAD)
-            Head::Symbolic(path) => {
-                // Create branch object with new commit
-                let branch = Branch {
-                    name: path.file_name().unwrap().to_string_lossy().to_string(),
-                    commit_sha: Some(commit_sha.clone()),
-                };
-
-                // Save updated branch reference
-                branch
-                    .save(&self.git_dir.join(path.parent().unwrap()))
-                    .unwrap();
-                head
-            }
-            // Handle detached HEAD state
-            Head::Detached(_) => Head::Detached(commit_sha.clone()),
+
+        let info = CommitInfo {
+            tree: &tree,
+            parents: &parents,
+            message,
         };
-        // Persist HEAD state to file
-        new_head.save(&self.git_dir.join(HEAD_FILE)).unwrap();
+        self.run_pre_commit(&info)
+            .map_err(|reason| Error::HookRejected {
+                hook: "pre-commit",
+                reason: reason.to_string(),
+            })?;
+
+        let reason = if amend {
+            format!("commit (amend): {message}")
+        } else if parents.len() > 1 {
+            format!("commit (merge): {message}")
+        } else if parents.is_empty() {
+            format!("commit (initial): {message}")
+        } else {
+            format!("commit: {message}")
+        };
+        // Concluding a conflicted cherry-pick keeps the original author
+        let picked = match (amend, self.picked_commit()) {
+            (false, Some(sha)) => Some(self.read_commit(&sha)?),
+            _ => None,
+        };
+        let commit_sha = self.commit_tree_with_author(
+            tree,
+            parents,
+            message,
+            picked.as_ref().map(|commit| commit.get_author()),
+            &author_name,
+            &author_email,
+            self.gpg_sign(options.sign)?,
+        )?;
+        self.update_head(parent.as_ref(), &commit_sha, &reason)?;
+        // The conflicts just resolved are remembered for next time
+        self.rerere()?;
+        self.clear_pick_state()?;
+        self.run_post_commit(&commit_sha);
+        Ok(commit_sha)
+    }
+    /// Moves the current branch, or a detached HEAD, from `old` to
+    /// `commit_sha`. Fails if it no longer points at `old`, i.e. another
+    /// update came in since it was read.
+    fn update_head(
+        &self,
+        old: Option<&EncodedSha>,
+        commit_sha: &EncodedSha,
+        reason: &str,
+    ) -> Result<(), Error> {
+        let head = self.lock_head_ref()?;
+        head.check(old)?;
+        Ok(head.commit(Some(commit_sha), reason)?)
     }
 }
 
+/// Options of [`Repository::commit_with`]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CommitOptions {
+    /// Replace the HEAD commit, like `--amend`
+    pub amend: bool,
+    /// Sign the commit, like `-S`, or not, like `--no-gpg-sign`; by default
+    /// as `commit.gpgSign` says
+    pub sign: Option<bool>,
+}
+
+/// Rejects names that cannot be stored as a file under refs/heads
+fn check_branch_name(name: &str) -> Result<(), String> {
+    let invalid = name.is_empty()
+        || name.starts_with('-')
+        || name.starts_with('/')
+        || name.ends_with('/')
+        || name.ends_with(lock::LOCK_SUFFIX)
+        || name.contains("..")
+        || name.contains("//")
+        || name
+            .chars()
+            .any(|c| c.is_whitespace() || c.is_control() || c == '\\');
+    if invalid {
+        return Err(format!("'{name}' is not a valid branch name"));
+    }
+    Ok(())
+}
+
+/// Initializes a repository whose config holds the test identity, so
+/// commits can be made in it
+#[cfg(test)]
+fn init_test_repo(dir: &Path) -> Repository {
+    let repo = Repository::init(dir).unwrap();
+    repo.set_config("user.name", AUTHOR_NAME).unwrap();
+    repo.set_config("user.email", AUTHOR_EMAIL).unwrap();
+    repo
+}
+
+/// Writes `content` to the file `name` of the working tree, stages it and
+/// commits it on HEAD as "change <name>"
+#[cfg(test)]
+pub(crate) fn commit_file(repo: &Repository, name: &str, content: &str) -> EncodedSha {
+    let file = repo.dir.join(name);
+    fs::write(&file, content).unwrap();
+    repo.add(&vec![file.to_str().unwrap()]).unwrap();
+    repo.commit(format!("change {name}")).unwrap()
+}
+
 #[derive(Debug)]
 struct Branch {
     name: String,
@@ -1304,21 +1672,6 @@ struct Branch {
 }
 
 impl Branch {
-    /// save branch to base_path/\<branch name\>
-    /// e.g: refs/heads/master
-    pub fn save(&self, base_path: &Path) -> io::Result<()> {
-        let file_path = base_path.join(&self.name);
-        if let Some(parent) = file_path.parent() {
-            fs::create_dir_all(parent)?;
-        }
-        if let Some(encoded_sha) = &self.commit_sha {
-            fs::write(file_path, encoded_sha.to_string())?;
-        } else {
-            fs::write(file_path, "No commit")?;
-        }
-        Ok(())
-    }
-
     /// load branch from base_path/name
     pub fn load(base_path: &Path, name: &str) -> Option<Branch> {
         let file_path = base_path.join(name);
@@ -1338,19 +1691,36 @@ impl Branch {
     /// # Returns
     /// * `io::Result<()>` - Success if file is deleted, error if deletion fails
     pub fn remove(base_path: &Path, name: &str) -> io::Result<()> {
-        let file_path = base_path.join(&name);
+        let file_path = base_path.join(name);
         fs::remove_file(file_path)
     }
 }
 
+/// The content of HEAD, or of any other ref that may be symbolic, such as
+/// refs/remotes/origin/HEAD
+#[derive(Debug, PartialEq)]
 enum Head {
     /// Symbolic reference, e.g., refs/heads/master
     Symbolic(PathBuf),
-    /// Detached HEAD state, directly pointing to a commit
+    /// Detached HEAD state, or a ref directly pointing to an object
     Detached(EncodedSha),
 }
 
 impl Head {
+    /// The branch a symbolic HEAD is on, which may contain slashes, such
+    /// as `release/v1`
+    fn branch_name(&self) -> Option<String> {
+        match self {
+            Head::Symbolic(path) => Some(
+                path.strip_prefix(Path::new(REFS_DIR).join(HEADS_DIR))
+                    .unwrap_or(path)
+                    .to_string_lossy()
+                    .replace('\\', "/"),
+            ),
+            Head::Detached(_) => None,
+        }
+    }
+
     /// Saves the HEAD to the specified path
     pub fn save(&self, path: &Path) -> io::Result<()> {
         // Ensure parent directories exist
@@ -1358,13 +1728,15 @@ impl Head {
             fs::create_dir_all(parent)?;
         }
 
-        // Generate content based on state
-        let content = match self {
+        lock::write_locked(path, self.content())
+    }
+
+    /// The content of a HEAD file pointing where this one does
+    fn content(&self) -> String {
+        match self {
             Head::Symbolic(ref_path) => format!("ref: {}\n", ref_path.display()),
             Head::Detached(sha) => sha.0.clone(),
-        };
-
-        fs::write(path, content)
+        }
     }
 
     /// Loads HEAD from the specified path
@@ -1399,6 +1771,55 @@ mod tests {
         assert!(Repository::is_vaild_git_dir(&repo.git_dir));
     }
     #[test]
+    fn init_with_initial_branch() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = Repository::init_with_branch(temp_dir.path(), "main").unwrap();
+        assert!(
+            matches!(repo.get_head(), Some(Head::Symbolic(p)) if p == Path::new("refs/heads/main"))
+        );
+        assert!(repo.load_branch("master").is_none());
+        // Like Git, an unborn branch has no ref file
+        assert!(repo.load_branch("main").is_none());
+
+        repo.set_config("user.name", AUTHOR_NAME).unwrap();
+        repo.set_config("user.email", AUTHOR_EMAIL).unwrap();
+        let file = repo.dir.join("a.txt");
+        fs::write(&file, "a").unwrap();
+        repo.add(&vec![file.to_str().unwrap()]).unwrap();
+        repo.commit("first").unwrap();
+        assert_eq!(
+            repo.load_branch("main").unwrap().commit_sha,
+            repo.get_current_commit()
+        );
+
+        let other = TempDir::new().unwrap();
+        assert!(Repository::init_with_branch(other.path(), "bad name").is_err());
+        assert!(!other.path().join(GIT_DIR).exists());
+    }
+    #[test]
+    fn commits_on_slashed_branches() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = Repository::init_with_branch(temp_dir.path(), "release/v1").unwrap();
+        repo.set_config("user.name", AUTHOR_NAME).unwrap();
+        repo.set_config("user.email", AUTHOR_EMAIL).unwrap();
+        let first = commit_file(&repo, "a.txt", "one\n");
+        assert_eq!(
+            repo.load_branch("release/v1").unwrap().commit_sha,
+            Some(first)
+        );
+        assert!(repo.load_branch("v1").is_none());
+
+        repo.branch("feature/x").unwrap();
+        repo.checkout("feature/x", false).unwrap();
+        let second = commit_file(&repo, "a.txt", "two\n");
+        assert_eq!(
+            repo.load_branch("feature/x").unwrap().commit_sha,
+            Some(second)
+        );
+        assert!(repo.rm_branch("feature/x").is_err());
+        assert!(repo.load_branch("x").is_none());
+    }
+    #[test]
     fn is_vaild_git_dir_works() {
         // Since this project itself is managed by git
         assert!(Repository::is_vaild_git_dir(Path::new(".git")));
@@ -1417,10 +1838,380 @@ mod tests {
         full_path
     }
 
+    #[test]
+    fn refresh_index_caches_stat_data() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        let a = create_file(&repo, "a.txt", "a");
+        let b = create_file(&repo, "b.txt", "b");
+        repo.update_index(&a).unwrap();
+        repo.update_index(&b).unwrap();
+        assert!(
+            Index::load(&repo.get_index_path())
+                .unwrap()
+                .get_stat("a.txt")
+                .is_some()
+        );
+
+        // Rewrite a.txt with the same content and change b.txt
+        fs::write(&a, "a").unwrap();
+        let mut index = Index::load(&repo.get_index_path()).unwrap();
+        let sha = index.get_sha1("a.txt").unwrap().clone();
+        index.update_entry("a.txt", sha.clone());
+        index.save(&repo.get_index_path()).unwrap();
+        fs::write(&b, "changed").unwrap();
+
+        assert_eq!(repo.refresh_index().unwrap(), vec!["b.txt".to_string()]);
+        let index = Index::load(&repo.get_index_path()).unwrap();
+        assert!(index.get_stat("a.txt").is_some());
+        assert_eq!(index.get_sha1("a.txt"), Some(&sha));
+
+        fs::remove_file(&a).unwrap();
+        assert_eq!(repo.refresh_index().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn lca_of_criss_cross_merges() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        let tree = repo.write_tree_impl(Index::new().get_root()).unwrap();
+        let commit = |parents: &[&EncodedSha], message: &str| {
+            let parents = parents.iter().map(|&p| p.clone()).collect();
+            repo.commit_tree(tree.clone(), parents, message, AUTHOR_NAME, AUTHOR_EMAIL)
+                .unwrap()
+        };
+        let root = commit(&[], "root");
+        let a1 = commit(&[&root], "a1");
+        let b1 = commit(&[&root], "b1");
+        let a2 = commit(&[&a1, &b1], "a2");
+        let b2 = commit(&[&b1, &a1], "b2");
+        let other = commit(&[], "other");
+
+        assert_eq!(repo.find_lca(&a1, &b1), Some(root.clone()));
+        assert_eq!(repo.find_lca(&a1, &a2), Some(a1.clone()));
+        let mut bases = repo.find_lcas(&a2, &b2);
+        bases.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut expected = vec![a1, b1];
+        expected.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(bases, expected);
+        assert_eq!(repo.find_lca(&a2, &other), None);
+    }
+
+    #[test]
+    fn commit_paths_leaves_other_changes_staged() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        fs::create_dir(repo.dir.join("src")).unwrap();
+        let a = create_file(&repo, "src/a.txt", "a");
+        let b = create_file(&repo, "b.txt", "b");
+        repo.update_index(&a).unwrap();
+        repo.update_index(&b).unwrap();
+        repo.commit("first").unwrap();
+        let first = repo.get_current_commit().unwrap();
+
+        fs::write(&a, "changed a").unwrap();
+        fs::write(&b, "changed b").unwrap();
+        repo.update_index(&a).unwrap();
+        repo.update_index(&b).unwrap();
+        repo.commit_paths("only src", &["src/"]).unwrap();
+
+        let commit = repo
+            .read_commit(&repo.get_current_commit().unwrap())
+            .unwrap();
+        assert_eq!(commit.get_parents(), &vec![first.clone()]);
+        let tree = repo.read_tree(&commit.get_tree_sha()).unwrap();
+        let first_tree = repo
+            .read_tree(&repo.read_commit(&first).unwrap().get_tree_sha())
+            .unwrap();
+        let index = Index::load(&repo.get_index_path()).unwrap();
+        assert_eq!(tree.get_sha1("src/a.txt"), index.get_sha1("src/a.txt"));
+        assert_eq!(tree.get_sha1("b.txt"), first_tree.get_sha1("b.txt"));
+        assert_ne!(index.get_sha1("b.txt"), first_tree.get_sha1("b.txt"));
+    }
+
+    #[test]
+    fn checkout_abbreviated_sha_detaches_head() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        let a = create_file(&repo, "a.txt", "one");
+        repo.update_index(&a).unwrap();
+        repo.commit("first").unwrap();
+        let first = repo.get_current_commit().unwrap();
+        fs::write(&a, "two").unwrap();
+        let b = create_file(&repo, "b.txt", "b");
+        repo.update_index(&a).unwrap();
+        repo.update_index(&b).unwrap();
+        repo.commit("second").unwrap();
+
+        repo.checkout(&first.0[..7], false).unwrap();
+        assert!(matches!(repo.get_head(), Some(Head::Detached(sha)) if sha == first));
+        assert_eq!(fs::read_to_string(&a).unwrap(), "one");
+        assert!(!b.exists());
+
+        // Leaving the detached HEAD starts from its tree
+        repo.checkout("master", false).unwrap();
+        assert_eq!(fs::read_to_string(&a).unwrap(), "two");
+        assert!(b.exists());
+    }
+
+    #[test]
+    fn local_config_overrides_global() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        assert_eq!(repo.config().unwrap().get("core.editor"), None);
+        repo.set_config("core.editor", "vi").unwrap();
+        repo.set_config("core.autocrlf", false).unwrap();
+        let config = repo.config().unwrap();
+        assert_eq!(config.get("core.editor"), Some("vi"));
+        assert_eq!(config.get_bool("core.autocrlf"), Ok(Some(false)));
+        assert!(repo.set_config("editor", "vi").is_err());
+
+        repo.set_config("user.name", "Bob").unwrap();
+        repo.set_config("user.email", "bob@example.com").unwrap();
+        if std::env::var_os("GIT_AUTHOR_NAME").is_none()
+            && std::env::var_os("GIT_AUTHOR_EMAIL").is_none()
+        {
+            assert_eq!(
+                repo.identity().unwrap(),
+                ("Bob".to_string(), "bob@example.com".to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn failures_are_reported_as_errors() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        let a = create_file(&repo, "a.txt", "a");
+        repo.update_index(&a).unwrap();
+        repo.commit("first").unwrap();
+
+        assert!(matches!(repo.commit("again"), Err(Error::NothingToCommit)));
+        assert!(matches!(repo.branch("master"), Err(Error::BranchExists(_))));
+        assert!(matches!(
+            repo.checkout("nowhere", false),
+            Err(Error::BadRevision(_))
+        ));
+        assert!(matches!(
+            Repository::open(&temp_dir.path().join("missing")),
+            Err(Error::NotARepository(_))
+        ));
+    }
+
+    #[test]
+    fn commit_amend_replaces_head() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        let a = create_file(&repo, "a.txt", "a");
+        repo.update_index(&a).unwrap();
+        repo.commit("first").unwrap();
+        let first = repo.get_current_commit().unwrap();
+        let b = create_file(&repo, "b.txt", "b");
+        repo.update_index(&b).unwrap();
+        repo.commit("second").unwrap();
+
+        let c = create_file(&repo, "c.txt", "c");
+        repo.update_index(&c).unwrap();
+        repo.commit_amend(None, &[] as &[&str]).unwrap();
+        let amended = repo
+            .read_commit(&repo.get_current_commit().unwrap())
+            .unwrap();
+        assert_eq!(amended.get_parents(), &vec![first.clone()]);
+        assert_eq!(amended.get_message(), "second");
+        let tree = repo.read_tree(&amended.get_tree_sha()).unwrap();
+        assert!(tree.get_sha1("b.txt").is_some() && tree.get_sha1("c.txt").is_some());
+
+        // Only the message changes
+        repo.commit_amend(Some("second, reworded"), &[] as &[&str])
+            .unwrap();
+        let reworded = repo
+            .read_commit(&repo.get_current_commit().unwrap())
+            .unwrap();
+        assert_eq!(reworded.get_parents(), &vec![first]);
+        assert_eq!(reworded.get_tree_sha(), amended.get_tree_sha());
+        assert_eq!(reworded.get_message(), "second, reworded");
+    }
+
+    #[test]
+    fn checkout_paths_from_index_and_commit() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        fs::create_dir(repo.dir.join("src")).unwrap();
+        let a = create_file(&repo, "src/a.txt", "one");
+        let b = create_file(&repo, "b.txt", "b");
+        repo.update_index(&a).unwrap();
+        repo.update_index(&b).unwrap();
+        repo.commit("first").unwrap();
+        let first = repo.get_current_commit().unwrap();
+
+        fs::write(&a, "staged").unwrap();
+        repo.update_index(&a).unwrap();
+        fs::write(&a, "unstaged").unwrap();
+        fs::write(&b, "local").unwrap();
+        repo.checkout_paths(&["src"], None).unwrap();
+        assert_eq!(fs::read_to_string(&a).unwrap(), "staged");
+        assert_eq!(fs::read_to_string(&b).unwrap(), "local");
+
+        fs::remove_file(&a).unwrap();
+        repo.checkout_paths(&["src/a.txt"], Some(&first.0)).unwrap();
+        assert_eq!(fs::read_to_string(&a).unwrap(), "one");
+        // The index and HEAD stay where they were
+        let index = Index::load(&repo.get_index_path()).unwrap();
+        assert_ne!(
+            index.get_sha1("src/a.txt"),
+            repo.commit_index(Some(&first))
+                .unwrap()
+                .get_sha1("src/a.txt")
+        );
+        assert_eq!(repo.get_current_commit(), Some(first));
+    }
+
+    #[test]
+    fn add_glob_pathspec() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        fs::create_dir_all(repo.dir.join("src/repo")).unwrap();
+        fs::create_dir_all(repo.dir.join("target")).unwrap();
+        create_file(&repo, ".gitignore", "target/\n");
+        create_file(&repo, "src/main.rs", "main");
+        create_file(&repo, "src/repo/log.rs", "log");
+        create_file(&repo, "src/notes.txt", "notes");
+        create_file(&repo, "target/build.rs", "build");
+        let glob = repo.dir.join("src/**/*.rs");
+        repo.add(&vec![glob.to_str().unwrap()]).unwrap();
+
+        let paths: Vec<String> = Index::load(&repo.get_index_path())
+            .unwrap()
+            .collect_entries()
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect();
+        assert_eq!(paths, vec!["src/main.rs", "src/repo/log.rs"]);
+    }
+
+    #[test]
+    fn add_dot_stages_the_working_tree() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        fs::create_dir_all(repo.dir.join("src")).unwrap();
+        create_file(&repo, "a.txt", "a");
+        create_file(&repo, "src/b.txt", "b");
+        let cwd = std::env::current_dir().unwrap();
+        std::env::set_current_dir(&repo.dir).unwrap();
+        let result = repo.add(&vec!["."]);
+        std::env::set_current_dir(cwd).unwrap();
+        result.unwrap();
+
+        let paths: Vec<String> = Index::load(&repo.get_index_path())
+            .unwrap()
+            .collect_entries()
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect();
+        assert_eq!(paths, vec!["a.txt", "src/b.txt"]);
+    }
+
+    #[test]
+    fn rm_cached_and_recursive() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        fs::create_dir_all(repo.dir.join("src/repo")).unwrap();
+        let a = create_file(&repo, "src/a.txt", "a");
+        let b = create_file(&repo, "src/repo/b.txt", "b");
+        let c = create_file(&repo, "c.txt", "c");
+        repo.add(&vec![repo.dir.to_str().unwrap()]).unwrap();
+        let index = || Index::load(&repo.get_index_path()).unwrap();
+
+        repo.rm(&vec![c.to_str().unwrap()], true, false).unwrap();
+        assert!(index().get_sha1("c.txt").is_none());
+        assert!(c.exists());
+
+        repo.rm(&vec![repo.dir.join("src").to_str().unwrap()], false, true)
+            .unwrap();
+        assert!(index().collect_entries().is_empty());
+        assert!(!a.exists() && !b.exists());
+        assert!(!repo.dir.join("src").exists());
+    }
+
+    #[test]
+    fn mv_renames_files_and_directories() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        fs::create_dir_all(repo.dir.join("src/repo")).unwrap();
+        create_file(&repo, "src/a.txt", "a");
+        create_file(&repo, "src/repo/b.txt", "b");
+        let c = create_file(&repo, "c.txt", "c");
+        repo.add(&vec![repo.dir.to_str().unwrap()]).unwrap();
+        let index = || Index::load(&repo.get_index_path()).unwrap();
+        let c_sha = index().get_sha1("c.txt").unwrap().clone();
+        let path = |name: &str| repo.dir.join(name).to_str().unwrap().to_string();
+
+        repo.mv(&path("c.txt"), &path("d.txt")).unwrap();
+        assert!(!c.exists());
+        assert_eq!(fs::read_to_string(repo.dir.join("d.txt")).unwrap(), "c");
+        assert!(index().get_sha1("c.txt").is_none());
+        assert_eq!(index().get_sha1("d.txt"), Some(&c_sha));
+
+        // Moving onto a directory moves into it
+        repo.mv(&path("d.txt"), &path("src")).unwrap();
+        assert!(index().get_sha1("src/d.txt").is_some());
+
+        repo.mv(&path("src"), &path("lib")).unwrap();
+        let paths: Vec<String> = index()
+            .collect_entries()
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect();
+        assert_eq!(paths, ["lib/a.txt", "lib/d.txt", "lib/repo/b.txt"]);
+        assert!(repo.dir.join("lib/repo/b.txt").exists());
+
+        create_file(&repo, "e.txt", "e");
+        assert!(repo.mv(&path("e.txt"), &path("f.txt")).is_err());
+        assert!(repo.mv(&path("lib/a.txt"), &path("e.txt")).is_err());
+        assert!(repo.mv(&path("lib"), &path("lib/repo/x")).is_err());
+    }
+
+    #[test]
+    fn encrypted_objects_need_the_key() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut repo = init_test_repo(temp_dir.path());
+        repo.enable_encryption(ObjectCipher::new([1; 32])).unwrap();
+        let file_path = create_file(&repo, "secret.txt", "plaintext secret");
+        repo.update_index(&file_path).unwrap();
+
+        let index = Index::load(&repo.get_index_path()).unwrap();
+        let sha = index.get_sha1("secret.txt").unwrap();
+        let raw = fs::read(repo.obj_db.object_path(sha)).unwrap();
+        assert!(!String::from_utf8_lossy(&raw).contains("plaintext secret"));
+        assert_eq!(
+            repo.blob_text(Some(sha)).unwrap(),
+            "plaintext secret".to_string()
+        );
+
+        // Another object's file put in its place is refused
+        let other = repo
+            .obj_db
+            .store(&Blob {
+                data: b"other".to_vec(),
+            })
+            .unwrap();
+        fs::copy(
+            repo.obj_db.object_path(&other),
+            repo.obj_db.object_path(sha),
+        )
+        .unwrap();
+        assert!(repo.obj_db.retrieve(sha).is_err());
+
+        // The store is no longer empty
+        assert!(repo.enable_encryption(ObjectCipher::new([1; 32])).is_err());
+        // Opening without RUST_GIT_KEY is refused
+        assert!(Repository::open(temp_dir.path()).is_err());
+    }
+
     #[test]
     fn test_update_index_add_new_file() {
         let temp_dir = TempDir::new().unwrap();
-        let repo = Repository::init(temp_dir.path()).unwrap();
+        let repo = init_test_repo(temp_dir.path());
         let file_path = create_file(&repo, "test.txt", "content");
 
         // First update (add)
@@ -1432,7 +2223,7 @@ mod tests {
     #[test]
     fn test_update_index_update_existing_file() {
         let temp_dir = TempDir::new().unwrap();
-        let repo = Repository::init(temp_dir.path()).unwrap();
+        let repo = init_test_repo(temp_dir.path());
         let file_path = create_file(&repo, "update.txt", "v1");
 
         // First add
@@ -1452,7 +2243,7 @@ mod tests {
     #[test]
     fn test_update_index_remove_deleted_file() {
         let temp_dir = TempDir::new().unwrap();
-        let repo = Repository::init(temp_dir.path()).unwrap();
+        let repo = init_test_repo(temp_dir.path());
         let file_path = create_file(&repo, "to_delete.txt", "content");
 
         // Add to index
@@ -1469,7 +2260,7 @@ mod tests {
     #[test]
     fn test_update_index_reject_unknown_file() {
         let temp_dir = TempDir::new().unwrap();
-        let repo = Repository::init(temp_dir.path()).unwrap();
+        let repo = init_test_repo(temp_dir.path());
         let bad_path = temp_dir.path().join("ghost.txt");
 
         let result = repo.update_index(&bad_path);
@@ -1480,7 +2271,7 @@ mod tests {
     #[test]
     fn test_update_index_security_checks() {
         let temp_dir = TempDir::new().unwrap();
-        let repo = Repository::init(temp_dir.path()).unwrap();
+        let repo = init_test_repo(temp_dir.path());
 
         // Test outside repo path
         let external_path = temp_dir.path().parent().unwrap().join("external.txt");
@@ -1496,7 +2287,7 @@ mod tests {
     #[test]
     fn test_update_index_directory_rejection() {
         let temp_dir = TempDir::new().unwrap();
-        let repo = Repository::init(temp_dir.path()).unwrap();
+        let repo = init_test_repo(temp_dir.path());
         let dir_path = repo.dir.join("subdir");
         fs::create_dir(&dir_path).unwrap();
 
@@ -1513,8 +2304,7 @@ mod function_tests {
 
     fn create_test_repo() -> Repository {
         let dir = tempdir().unwrap();
-        let repo = Repository::init(dir.path()).unwrap();
-        repo
+        Repository::init(dir.path()).unwrap()
     }
 
     #[test]
@@ -1598,7 +2388,8 @@ mod branch_tests {
     fn test_save_and_load_branch() {
         // Create a temporary directory
         let temp_dir = TempDir::new().unwrap();
-        let base_path = temp_dir.path();
+        let repo = init_test_repo(temp_dir.path());
+        let base_path = &repo.get_branch_dir();
 
         // Construct a test branch
         let branch = Branch {
@@ -1607,7 +2398,7 @@ mod branch_tests {
         };
 
         // Test saving the branch
-        branch.save(base_path).unwrap();
+        repo.save_branch(&branch, "test").unwrap();
 
         // Verify the file exists and its content is correct
         let file_path = base_path.join("test-branch");
@@ -1630,18 +2421,15 @@ mod branch_tests {
     fn test_save_creates_parent_directories() {
         // Test the automatic directory creation logic
         let temp_dir = TempDir::new().unwrap();
-        let base_path = temp_dir.path().join("sub/dir");
-
-        let branch = Branch {
-            name: "deep-branch".to_string(),
-            commit_sha: Some(EncodedSha("b".repeat(40))),
-        };
+        let repo = init_test_repo(temp_dir.path());
+        let sha = EncodedSha("b".repeat(40));
 
         // Save to a multi-level directory
-        branch.save(&base_path).unwrap();
+        repo.write_ref("refs/heads/sub/dir/deep-branch", Some(&sha), None, "test")
+            .unwrap();
 
         // Verify the file path
-        let file_path = base_path.join("deep-branch");
+        let file_path = repo.git_dir.join("refs/heads/sub/dir/deep-branch");
         assert!(file_path.exists());
     }
 
@@ -1748,7 +2536,7 @@ mod head_tests {
 
         // Test loading
         let loaded = Head::load(&head_path).unwrap();
-        assert!(matches!(loaded, Head::Symbolic(p) if p == PathBuf::from("refs/heads/master")));
+        assert!(matches!(loaded, Head::Symbolic(p) if p == Path::new("refs/heads/master")));
     }
 
     #[test]
@@ -0,0 +1,243 @@
+use chrono::{FixedOffset, Utc};
+
+use super::{ApplyOptions, Repository, ResetMode};
+use crate::object::Author;
+use crate::patch::{PatchMail, parse_mail, split_mbox};
+use crate::{EncodedSha, Error};
+use std::fs;
+use std::path::PathBuf;
+
+/// Directory holding the state of `am`: `head`, where HEAD was before it,
+/// and the mails still to apply, numbered from `0001`
+pub(super) const AM_DIR: &str = "rebase-apply";
+/// File inside [`AM_DIR`]
+const ORIG_HEAD_FILE: &str = "head";
+
+impl Repository {
+    /// Applies the patch mails of `mailboxes`, mbox contents such as
+    /// `format-patch` writes, and commits each one with the author, date
+    /// and message of its mail, like `git am`.
+    ///
+    /// When a patch does not apply, `am` stops with nothing changed; apply
+    /// it by hand, `add` the result and call [`Self::am_continue`], or give
+    /// up with [`Self::am_abort`].
+    ///
+    /// # Returns
+    /// - `Ok(true)`: Every patch was applied
+    /// - `Ok(false)`: `am` stopped at a patch that does not apply
+    pub fn am<S: AsRef<str>>(&self, mailboxes: &[S]) -> Result<bool, Error> {
+        let am_dir = self.git_dir.join(AM_DIR);
+        self.check_no_operation_in_progress()?;
+        let (head_sha, head_index) = self.clean_head()?;
+        if !self.worktree_is_clean(&head_index)? {
+            return Err(Error::UncommittedChanges);
+        }
+        self.identity()?;
+        let mails: Vec<String> = mailboxes
+            .iter()
+            .flat_map(|mailbox| split_mbox(mailbox.as_ref()))
+            .collect();
+        // Reject malformed mails before any is applied
+        for mail in &mails {
+            parse_mail(mail)?;
+        }
+        if mails.is_empty() {
+            return Err("patch is empty".into());
+        }
+
+        self.save_orig_head()?;
+        fs::create_dir_all(&am_dir)?;
+        fs::write(am_dir.join(ORIG_HEAD_FILE), format!("{head_sha}\n"))?;
+        for (number, mail) in mails.iter().enumerate() {
+            fs::write(am_dir.join(format!("{:04}", number + 1)), mail)?;
+        }
+        self.run_am()
+    }
+
+    /// Commits the patch `am` stopped at, applied by hand and added to the
+    /// index, then applies the remaining mails
+    pub fn am_continue(&self) -> Result<bool, Error> {
+        let queue = self.am_queue().map_err(|_| "no am session in progress")?;
+        if let Some(stopped) = queue.first() {
+            let mail = parse_mail(&fs::read_to_string(stopped)?)?;
+            let head_sha = self
+                .get_current_commit()
+                .ok_or("HEAD does not point to a commit yet")?;
+            let tree = self.write_tree()?;
+            if tree == self.read_commit(&head_sha)?.get_tree_sha() {
+                return Err("no changes - did you forget to use 'add'?".into());
+            }
+            self.commit_mail(&mail, tree, head_sha)?;
+            fs::remove_file(stopped)?;
+        }
+        self.run_am()
+    }
+
+    /// Abandons the am session, resetting HEAD, the index and the working
+    /// tree to where they were before it started
+    pub fn am_abort(&self) -> Result<(), Error> {
+        let am_dir = self.git_dir.join(AM_DIR);
+        let head = fs::read_to_string(am_dir.join(ORIG_HEAD_FILE))
+            .map_err(|_| "no am session in progress")?;
+        self.reset(Some(head.trim()), ResetMode::Hard)?;
+        Ok(fs::remove_dir_all(am_dir)?)
+    }
+
+    /// Applies the queued mails one after another until none is left or
+    /// one does not apply
+    fn run_am(&self) -> Result<bool, Error> {
+        for path in self.am_queue()? {
+            let mail = parse_mail(&fs::read_to_string(&path)?)?;
+            let subject = mail.message.lines().next().unwrap_or_default();
+            println!("Applying: {subject}");
+            if let Err(why) = self.apply_mail(&mail) {
+                println!("error: {why}");
+                println!("Patch failed at {}", path.file_name().unwrap().display());
+                println!(
+                    "hint: apply the patch by hand, mark the corrected paths with\n\
+                     hint: 'rust-git add <paths>' and run 'rust-git am --continue'\n\
+                     hint: or give up with 'rust-git am --abort'"
+                );
+                return Ok(false);
+            }
+            fs::remove_file(path)?;
+        }
+        fs::remove_dir_all(self.git_dir.join(AM_DIR))?;
+        Ok(true)
+    }
+
+    /// Applies the patch of `mail` to the index and the working tree and
+    /// commits it, or changes nothing if it does not apply
+    fn apply_mail(&self, mail: &PatchMail) -> Result<(), Error> {
+        let head_sha = self
+            .get_current_commit()
+            .ok_or("HEAD does not point to a commit yet")?;
+        let index = ApplyOptions {
+            cached: true,
+            ..Default::default()
+        };
+        let check = |options| ApplyOptions {
+            check: true,
+            ..options
+        };
+        self.apply(&mail.patch, check(index))?;
+        self.apply(&mail.patch, check(ApplyOptions::default()))?;
+        self.apply(&mail.patch, index)?;
+        self.apply(&mail.patch, ApplyOptions::default())?;
+        self.commit_mail(mail, self.write_tree()?, head_sha)?;
+        Ok(())
+    }
+
+    /// Commits `tree` on top of `head_sha` with the author and message of
+    /// `mail`
+    fn commit_mail(
+        &self,
+        mail: &PatchMail,
+        tree: EncodedSha,
+        head_sha: EncodedSha,
+    ) -> Result<EncodedSha, Error> {
+        let offset = FixedOffset::east_opt(8 * 3600).unwrap();
+        let date = mail
+            .date
+            .unwrap_or_else(|| Utc::now().with_timezone(&offset));
+        let author = Author::new(&mail.author_name, &mail.author_email, date);
+        let (name, email) = self.identity()?;
+        let commit_sha = self.commit_tree_with_author(
+            tree,
+            vec![head_sha.clone()],
+            &mail.message,
+            Some(&author),
+            &name,
+            &email,
+            self.gpg_sign(None)?,
+        )?;
+        let subject = mail.message.lines().next().unwrap_or_default();
+        self.update_head(Some(&head_sha), &commit_sha, &format!("am: {subject}"))?;
+        Ok(commit_sha)
+    }
+
+    /// The mails still to apply, in order
+    fn am_queue(&self) -> Result<Vec<PathBuf>, Error> {
+        let mut queue: Vec<PathBuf> = fs::read_dir(self.git_dir.join(AM_DIR))?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.file_name().is_some_and(|name| name != ORIG_HEAD_FILE))
+            .collect();
+        queue.sort();
+        Ok(queue)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::init_test_repo;
+    use tempfile::TempDir;
+
+    #[test]
+    fn am_commits_mails_and_resumes_after_failures() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        let file = repo.dir.join("a.txt");
+        let commit_file = |name: &str, content: &str, message: &str| {
+            let file = repo.dir.join(name);
+            fs::write(&file, content).unwrap();
+            repo.add(&vec![file.to_str().unwrap()]).unwrap();
+            repo.commit(message).unwrap()
+        };
+        let base = commit_file("a.txt", "1\n2\n3\n", "base");
+        repo.set_config("user.name", "Sender").unwrap();
+        commit_file("a.txt", "1\ntwo\n3\n", "Spell out two\n\nDigits are hard.");
+        commit_file("b.txt", "b\n", "Add b");
+        let sent = repo.get_current_commit().unwrap();
+        let mbox: String = repo
+            .format_patch_output(&base.0)
+            .unwrap()
+            .into_iter()
+            .map(|(_, mail)| mail)
+            .collect();
+
+        // Replaying the mails recreates the commits
+        repo.set_config("user.name", "Receiver").unwrap();
+        repo.reset(Some(&base.0), ResetMode::Hard).unwrap();
+        assert!(repo.am(&[&mbox]).unwrap());
+        let head = repo.get_current_commit().unwrap();
+        let applied = repo.read_commit(&head).unwrap();
+        let original = repo.read_commit(&sent).unwrap();
+        assert_eq!(applied.get_tree_sha(), original.get_tree_sha());
+        assert_eq!(
+            applied.get_author().to_string(),
+            original.get_author().to_string()
+        );
+        assert_eq!(applied.get_committer().get_name(), "Receiver");
+        let first = repo.read_commit(&applied.get_parents()[0]).unwrap();
+        assert_eq!(first.get_message(), "Spell out two\n\nDigits are hard.");
+        assert!(!repo.git_dir.join(AM_DIR).exists());
+
+        // A patch that does not apply stops the session
+        repo.reset(Some(&base.0), ResetMode::Hard).unwrap();
+        let moved = commit_file("a.txt", "1\nTWO\n3\n", "Shout two");
+        assert!(!repo.am(&[&mbox]).unwrap());
+        assert_eq!(repo.get_current_commit(), Some(moved.clone()));
+        assert!(repo.am(&[&mbox]).is_err());
+        assert!(repo.am_continue().is_err());
+        fs::write(&file, "1\ntwo\n3\n").unwrap();
+        repo.add(&vec![file.to_str().unwrap()]).unwrap();
+        assert!(repo.am_continue().unwrap());
+        assert!(repo.dir.join("b.txt").exists());
+        let head = repo.get_current_commit().unwrap();
+        let resolved = repo.read_commit(&head).unwrap().get_parents()[0].clone();
+        assert_eq!(
+            repo.read_commit(&resolved).unwrap().get_message(),
+            "Spell out two\n\nDigits are hard."
+        );
+
+        // Aborting returns to where the session started
+        repo.reset(Some(&moved.0), ResetMode::Hard).unwrap();
+        assert!(!repo.am(&[&mbox]).unwrap());
+        repo.am_abort().unwrap();
+        assert_eq!(repo.get_current_commit(), Some(moved));
+        assert!(!repo.git_dir.join(AM_DIR).exists());
+        assert!(repo.am(&["not a mail"]).is_err());
+    }
+}
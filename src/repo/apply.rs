@@ -0,0 +1,180 @@
+use super::Repository;
+use crate::Error;
+use crate::object::{Blob, FileMode};
+use crate::patch::{FilePatch, parse_patch};
+use std::collections::BTreeMap;
+use std::fs;
+
+/// Options of [`Repository::apply`]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ApplyOptions {
+    /// Apply to the index instead of the working tree, like `--cached`
+    pub cached: bool,
+    /// Undo the patch instead, like `--reverse`
+    pub reverse: bool,
+    /// Only check that the patch applies, like `--check`
+    pub check: bool,
+}
+
+impl Repository {
+    /// Applies a unified diff, as `diff` or `format-patch` write it, to the
+    /// working tree, like `git apply`. Hunks are looked for near the lines
+    /// they name and may apply with some of their context changed, see
+    /// [`FilePatch::apply_to`]. Nothing is written unless every file of
+    /// the patch applies.
+    ///
+    /// # Returns
+    /// The paths the patch changes, created or deletes
+    pub fn apply(&self, patch: &str, options: ApplyOptions) -> Result<Vec<String>, Error> {
+        let mut files = parse_patch(patch)?;
+        if options.reverse {
+            files = files.iter().map(FilePatch::reversed).collect();
+        }
+        let mut index = self.load_index_or_empty()?;
+        // New content by path, `None` for deleted files, so a file patched
+        // twice sees the first result
+        let mut results: BTreeMap<String, Option<String>> = BTreeMap::new();
+        for file in &files {
+            let old = match &file.old_path {
+                Some(path) => match results.get(path) {
+                    Some(content) => content.clone(),
+                    None => self.read_to_patch(path, options.cached)?,
+                }
+                .ok_or_else(|| format!("{path}: does not exist"))?,
+                None => String::new(),
+            };
+            let new = file.apply_to(&old)?;
+            match (&file.old_path, &file.new_path) {
+                (None, Some(path)) => {
+                    let exists = match results.get(path) {
+                        Some(content) => content.is_some(),
+                        None => self.read_to_patch(path, options.cached)?.is_some(),
+                    };
+                    if exists {
+                        return Err(format!("{path}: already exists").into());
+                    }
+                }
+                (Some(path), None) if !new.is_empty() => {
+                    return Err(format!("{path}: removal patch leaves file contents").into());
+                }
+                _ => {}
+            }
+            if let Some(old_path) = &file.old_path
+                && file.new_path.as_ref() != Some(old_path)
+            {
+                results.insert(old_path.clone(), None);
+            }
+            if let Some(new_path) = &file.new_path {
+                results.insert(new_path.clone(), Some(new));
+            }
+        }
+        if options.check {
+            return Ok(results.into_keys().collect());
+        }
+
+        for (path, content) in &results {
+            match (content, options.cached) {
+                (Some(content), true) => {
+                    let sha = self.obj_db.store(&Blob {
+                        data: content.as_bytes().to_vec(),
+                    })?;
+                    let mode = index.get_mode(path).unwrap_or(FileMode::Regular);
+                    index.update_entry_with_mode(path, sha, mode);
+                }
+                (None, true) => {
+                    index.remove_entry(path);
+                }
+                (Some(content), false) => {
+                    let file = self.dir.join(path);
+                    if let Some(parent) = file.parent() {
+                        fs::create_dir_all(parent)?;
+                    }
+                    fs::write(file, content)?;
+                }
+                (None, false) => fs::remove_file(self.dir.join(path))?,
+            }
+        }
+        if options.cached {
+            index.save(&self.get_index_path())?;
+        }
+        Ok(results.into_keys().collect())
+    }
+
+    /// Reads the file at `path` from the index or the working tree, `None`
+    /// if it is not there
+    fn read_to_patch(&self, path: &str, cached: bool) -> Result<Option<String>, Error> {
+        let data = match cached {
+            true => {
+                let index = self.load_index_or_empty()?;
+                let Some(sha) = index.get_sha1(path) else {
+                    return Ok(None);
+                };
+                Blob::deserialize(&self.obj_db.retrieve(sha)?)?.data
+            }
+            false => match fs::read(self.dir.join(path)) {
+                Ok(data) => data,
+                Err(why) if why.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+                Err(why) => return Err(why.into()),
+            },
+        };
+        String::from_utf8(data)
+            .map(Some)
+            .map_err(|_| format!("{path}: cannot patch a binary file").into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::{Snapshot, init_test_repo};
+    use tempfile::TempDir;
+
+    #[test]
+    fn apply_patches_worktree_or_index() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        let file = repo.dir.join("a.txt");
+        fs::write(&file, "one\ntwo\nthree\n").unwrap();
+        repo.add(&vec![file.to_str().unwrap()]).unwrap();
+        repo.commit("first").unwrap();
+        fs::write(&file, "one\n2\nthree\n").unwrap();
+        let new_file = repo.dir.join("b.txt");
+        fs::write(&new_file, "new\n").unwrap();
+        repo.add(&vec![new_file.to_str().unwrap()]).unwrap();
+        let patch = repo
+            .unified_diff(Snapshot::Commit("HEAD"), Snapshot::Worktree, &[] as &[&str])
+            .unwrap();
+
+        // Undo the changes in the working tree, then redo them
+        let reverse = ApplyOptions {
+            reverse: true,
+            ..Default::default()
+        };
+        assert_eq!(repo.apply(&patch, reverse).unwrap(), ["a.txt", "b.txt"]);
+        assert_eq!(fs::read_to_string(&file).unwrap(), "one\ntwo\nthree\n");
+        assert!(!new_file.exists());
+        assert!(repo.apply(&patch, reverse).is_err());
+        let check = ApplyOptions {
+            check: true,
+            ..Default::default()
+        };
+        repo.apply(&patch, check).unwrap();
+        assert!(!new_file.exists());
+        repo.apply(&patch, ApplyOptions::default()).unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "one\n2\nthree\n");
+
+        // With --cached only the index changes
+        repo.rm(&vec![new_file.to_str().unwrap()], true, false)
+            .unwrap();
+        let cached = ApplyOptions {
+            cached: true,
+            ..Default::default()
+        };
+        repo.apply(&patch, cached).unwrap();
+        let staged = repo
+            .unified_diff(Snapshot::Commit("HEAD"), Snapshot::Index, &[] as &[&str])
+            .unwrap();
+        assert_eq!(staged, patch);
+        assert!(repo.apply(&patch, cached).is_err());
+    }
+}
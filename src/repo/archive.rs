@@ -0,0 +1,263 @@
+use chrono::{DateTime, Datelike, FixedOffset, Timelike, Utc};
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use super::Repository;
+use crate::index::Index;
+use crate::object::FileMode;
+use crate::{EncodedSha, Error};
+use std::fs::File;
+use std::io::{BufWriter, Seek, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+/// Container written by `archive`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Tar,
+    /// Gzipped tar
+    TarGz,
+    Zip,
+}
+
+impl ArchiveFormat {
+    /// Picks the format from the extension of `path`: `.zip`, `.tar.gz`
+    /// or `.tgz`, and plain tar otherwise
+    pub fn from_path(path: &Path) -> ArchiveFormat {
+        let name = path.to_string_lossy();
+        if name.ends_with(".zip") {
+            ArchiveFormat::Zip
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            ArchiveFormat::TarGz
+        } else {
+            ArchiveFormat::Tar
+        }
+    }
+}
+
+impl Repository {
+    /// Writes the files of `tree_ish` to the archive `output`, like
+    /// `git archive -o`, in the format its extension names.
+    ///
+    /// # Returns
+    /// The number of files archived
+    pub fn archive(
+        &self,
+        tree_ish: &str,
+        output: &Path,
+        prefix: Option<&str>,
+    ) -> Result<usize, Error> {
+        let file = BufWriter::new(File::create(output)?);
+        self.archive_to(tree_ish, ArchiveFormat::from_path(output), prefix, file)
+    }
+
+    /// Writes the files of `tree_ish` into an archive, without a checkout.
+    /// Blobs are read one at a time, so only one file is held in memory.
+    ///
+    /// # Arguments
+    /// * `tree_ish` - A commit, whose committer date the files get, or the
+    ///   SHA1 of a tree, whose files are dated now
+    /// * `prefix` - Prepended to every path, e.g. `project-1.0/`
+    ///
+    /// # Returns
+    /// The number of files archived
+    pub fn archive_to<W: Write + Seek>(
+        &self,
+        tree_ish: &str,
+        format: ArchiveFormat,
+        prefix: Option<&str>,
+        out: W,
+    ) -> Result<usize, Error> {
+        let (tree, date) = match self.resolve_commit(tree_ish) {
+            Ok(sha) => {
+                let commit = self.read_commit(&sha)?;
+                let date = commit.get_committer().get_timestamp();
+                (self.read_tree(&commit.get_tree_sha())?, date)
+            }
+            Err(_) => {
+                let sha = EncodedSha::from_str(tree_ish)
+                    .map_err(|_| Error::BadRevision(tree_ish.to_string()))?;
+                let tree = self
+                    .read_tree(&sha)
+                    .map_err(|_| Error::BadRevision(tree_ish.to_string()))?;
+                (tree, Utc::now().fixed_offset())
+            }
+        };
+        let prefix = prefix.unwrap_or_default();
+        match format {
+            ArchiveFormat::Tar => {
+                self.write_tar(&tree, prefix, date, out)?;
+            }
+            ArchiveFormat::TarGz => {
+                let gz = self.write_tar(
+                    &tree,
+                    prefix,
+                    date,
+                    GzEncoder::new(out, Compression::default()),
+                )?;
+                gz.finish()?;
+            }
+            ArchiveFormat::Zip => self.write_zip(&tree, prefix, date, out)?,
+        }
+        Ok(archived_files(&tree).len())
+    }
+
+    fn write_tar<W: Write>(
+        &self,
+        tree: &Index,
+        prefix: &str,
+        date: DateTime<FixedOffset>,
+        out: W,
+    ) -> Result<W, Error> {
+        let mut builder = tar::Builder::new(out);
+        for (path, sha) in archived_files(tree) {
+            let data = self.blob_content(&sha)?;
+            let name = format!("{prefix}{path}");
+            let mut header = tar::Header::new_gnu();
+            header.set_mtime(date.timestamp().max(0) as u64);
+            match tree.get_mode(&path).unwrap_or_default() {
+                FileMode::Symlink => {
+                    header.set_entry_type(tar::EntryType::Symlink);
+                    header.set_mode(0o777);
+                    header.set_size(0);
+                    let target = String::from_utf8_lossy(&data).to_string();
+                    builder.append_link(&mut header, name, target)?;
+                }
+                mode => {
+                    header.set_mode(unix_mode(mode));
+                    header.set_size(data.len() as u64);
+                    builder.append_data(&mut header, name, data.as_slice())?;
+                }
+            }
+        }
+        Ok(builder.into_inner()?)
+    }
+
+    fn write_zip<W: Write + Seek>(
+        &self,
+        tree: &Index,
+        prefix: &str,
+        date: DateTime<FixedOffset>,
+        out: W,
+    ) -> Result<(), Error> {
+        let zip_error = |why: zip::result::ZipError| Error::Other(why.to_string());
+        // Zip times have no zone and cannot predate 1980
+        let time = zip::DateTime::from_date_and_time(
+            date.year().clamp(1980, 2107) as u16,
+            date.month() as u8,
+            date.day() as u8,
+            date.hour() as u8,
+            date.minute() as u8,
+            date.second() as u8,
+        )
+        .unwrap_or_default();
+        let mut zip = ZipWriter::new(out);
+        for (path, sha) in archived_files(tree) {
+            let data = self.blob_content(&sha)?;
+            let name = format!("{prefix}{path}");
+            let mode = tree.get_mode(&path).unwrap_or_default();
+            let options = SimpleFileOptions::default()
+                .compression_method(CompressionMethod::Deflated)
+                .last_modified_time(time)
+                .unix_permissions(unix_mode(mode));
+            if mode == FileMode::Symlink {
+                let target = String::from_utf8_lossy(&data).to_string();
+                zip.add_symlink(name, target, options).map_err(zip_error)?;
+            } else {
+                zip.start_file(name, options).map_err(zip_error)?;
+                zip.write_all(&data)?;
+            }
+        }
+        zip.finish().map_err(zip_error)?;
+        Ok(())
+    }
+}
+
+/// The files of `tree`; submodules are left out, as their content lives
+/// in another repository
+fn archived_files(tree: &Index) -> Vec<(String, EncodedSha)> {
+    tree.collect_entries()
+        .into_iter()
+        .filter(|(path, _)| tree.get_mode(path) != Some(FileMode::Gitlink))
+        .collect()
+}
+
+fn unix_mode(mode: FileMode) -> u32 {
+    match mode {
+        FileMode::Executable => 0o755,
+        FileMode::Symlink => 0o777,
+        _ => 0o644,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::init_test_repo;
+    use flate2::read::GzDecoder;
+    use std::fs;
+    use std::io::{Cursor, Read};
+    use tempfile::TempDir;
+
+    #[test]
+    fn archive_writes_tar_tgz_and_zip() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        fs::create_dir(repo.dir.join("src")).unwrap();
+        let files = [repo.dir.join("a.txt"), repo.dir.join("src/lib.rs")];
+        fs::write(&files[0], "one\n").unwrap();
+        fs::write(&files[1], "fn main() {}\n").unwrap();
+        let paths: Vec<&str> = files.iter().map(|f| f.to_str().unwrap()).collect();
+        repo.add(&paths).unwrap();
+        let commit = repo.commit("first").unwrap();
+        let expected = [
+            ("v1/a.txt".to_string(), "one\n".to_string()),
+            ("v1/src/lib.rs".to_string(), "fn main() {}\n".to_string()),
+        ];
+
+        let archive = |format| {
+            let mut out = Cursor::new(Vec::new());
+            let count = repo
+                .archive_to("HEAD", format, Some("v1/"), &mut out)
+                .unwrap();
+            assert_eq!(count, 2);
+            out.into_inner()
+        };
+        let read_tar = |data: &mut dyn Read| {
+            let mut files = Vec::new();
+            for entry in tar::Archive::new(data).entries().unwrap() {
+                let mut entry = entry.unwrap();
+                let path = entry.path().unwrap().to_string_lossy().to_string();
+                let mut content = String::new();
+                entry.read_to_string(&mut content).unwrap();
+                files.push((path, content));
+            }
+            files
+        };
+        assert_eq!(
+            read_tar(&mut archive(ArchiveFormat::Tar).as_slice()),
+            expected
+        );
+        let tgz = archive(ArchiveFormat::TarGz);
+        assert_eq!(read_tar(&mut GzDecoder::new(tgz.as_slice())), expected);
+
+        let mut zip = zip::ZipArchive::new(Cursor::new(archive(ArchiveFormat::Zip))).unwrap();
+        let mut content = String::new();
+        zip.by_name("v1/src/lib.rs")
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(content, expected[1].1);
+        assert_eq!(zip.len(), 2);
+
+        // A tree can be archived by its SHA1, and the extension picks the format
+        let tree = repo.read_commit(&commit).unwrap().get_tree_sha();
+        let output = temp_dir.path().join("out.tar.gz");
+        assert_eq!(repo.archive(&tree.0, &output, None).unwrap(), 2);
+        let tgz = fs::read(&output).unwrap();
+        assert_eq!(read_tar(&mut GzDecoder::new(tgz.as_slice()))[0].0, "a.txt");
+        assert!(repo.archive("missing", &output, None).is_err());
+    }
+}
@@ -0,0 +1,231 @@
+use super::{IndexDiffType, Repository};
+use crate::index::Index;
+use crate::object::Blob;
+use crate::{EncodedSha, Error};
+use std::fs;
+
+/// Records the pending autostash so the changes can be recovered
+/// if the operation in between is interrupted
+const AUTOSTASH_FILE: &str = "AUTOSTASH";
+/// Labels the stashed side of conflicts when a stash is applied
+const STASH_LABEL: &str = "Stashed changes";
+
+impl Repository {
+    /// Saves the tracked changes of the index and the working tree in a stash
+    /// commit and resets both to HEAD, recording the stash in `AUTOSTASH` so
+    /// it can be recovered if the operation in between is interrupted.
+    ///
+    /// # Returns
+    /// - `Ok(None)`: Nothing to stash
+    /// - `Ok(Some(sha))`: SHA1 of the working tree commit
+    pub(super) fn create_autostash(&self) -> Result<Option<EncodedSha>, Error> {
+        let Some(stash) = self.write_stash(Some("autostash"))? else {
+            return Ok(None);
+        };
+        fs::write(self.git_dir.join(AUTOSTASH_FILE), format!("{stash}\n"))?;
+        println!("Created autostash: {stash}");
+        Ok(Some(stash))
+    }
+
+    /// Reapplies a stash made by [`Self::create_autostash`] on top of the
+    /// current HEAD, which is expected to match the index and working tree.
+    ///
+    /// On conflicts the working tree receives conflict markers, the index is
+    /// left at HEAD and the stash commit is kept so nothing is lost.
+    pub(super) fn apply_autostash(&self, stash: &EncodedSha) -> Result<(), Error> {
+        let conflicts = self.merge_stash(stash)?;
+        if conflicts.is_empty() {
+            let _ = fs::remove_file(self.git_dir.join(AUTOSTASH_FILE));
+            println!("Applied autostash.");
+            return Ok(());
+        }
+        println!("Applying autostash resulted in conflicts.");
+        println!("Your changes are safe in the stash commit {stash}.");
+        Ok(())
+    }
+
+    /// Saves the tracked changes of the index and the working tree in a stash
+    /// commit, then resets both to HEAD.
+    ///
+    /// Like `git stash`, two commits are written on top of HEAD: one holding
+    /// the index and one holding the working tree, whose parents are HEAD and
+    /// the index commit. The latter is titled `On <branch>: <message>`, or
+    /// `WIP on <branch>: <HEAD>` without a message.
+    ///
+    /// # Returns
+    /// - `Ok(None)`: Nothing to stash
+    /// - `Ok(Some(sha))`: SHA1 of the working tree commit
+    pub(super) fn write_stash(&self, message: Option<&str>) -> Result<Option<EncodedSha>, Error> {
+        let head_sha = self
+            .get_current_commit()
+            .ok_or("cannot stash without a commit")?;
+        let (author_name, author_email) = self.identity()?;
+        let head_commit = self.read_commit(&head_sha)?;
+        let head_index = self.read_tree(&head_commit.get_tree_sha())?;
+        let index_path = self.get_index_path();
+        let index = if index_path.is_file() {
+            Index::load(&index_path)?
+        } else {
+            Index::new()
+        };
+        let worktree = self.worktree_snapshot(&index)?;
+
+        let is_clean = |lhs: &Index, rhs: &Index| {
+            self.diff_index(lhs, rhs)
+                .values()
+                .all(|status| *status == IndexDiffType::Unmodified)
+        };
+        if is_clean(&head_index, &index) && is_clean(&index, &worktree) {
+            return Ok(None);
+        }
+
+        let branch = self
+            .get_head()
+            .and_then(|head| head.branch_name())
+            .unwrap_or_else(|| "(no branch)".to_string());
+        let subject = head_commit.get_message().lines().next().unwrap_or_default();
+        let head_summary = format!("{} {subject}", &head_sha.0[..7]);
+        let index_tree = self.write_tree_impl(index.get_root())?;
+        let index_commit = self.commit_tree(
+            index_tree,
+            vec![head_sha.clone()],
+            &format!("index on {branch}: {head_summary}"),
+            &author_name,
+            &author_email,
+        )?;
+        let message = match message {
+            Some(message) => format!("On {branch}: {message}"),
+            None => format!("WIP on {branch}: {head_summary}"),
+        };
+        let worktree_tree = self.write_tree_impl(worktree.get_root())?;
+        let stash = self.commit_tree(
+            worktree_tree,
+            vec![head_sha, index_commit],
+            &message,
+            &author_name,
+            &author_email,
+        )?;
+
+        self.update_worktree(&worktree, &head_index)?;
+        head_index.save(&index_path)?;
+        Ok(Some(stash))
+    }
+
+    /// Merges a stash commit written by [`Self::write_stash`] into the
+    /// working tree and index, which are expected to match HEAD.
+    ///
+    /// # Returns
+    /// - `Ok(conflicts)`: Paths that received conflict markers; the index is
+    ///   only updated when there are none
+    /// - `Err(Error)`: The stash or HEAD could not be read
+    pub(super) fn merge_stash(&self, stash: &EncodedSha) -> Result<Vec<String>, Error> {
+        let stash_commit = self.read_commit(stash)?;
+        let [base_sha, index_sha] = stash_commit.get_parents().as_slice() else {
+            return Err(format!("{stash} is not a stash commit").into());
+        };
+        let base = self.read_tree(&self.read_commit(base_sha)?.get_tree_sha())?;
+        let stashed_index = self.read_tree(&self.read_commit(index_sha)?.get_tree_sha())?;
+        let stashed_worktree = self.read_tree(&stash_commit.get_tree_sha())?;
+
+        let head_sha = self
+            .get_current_commit()
+            .ok_or("HEAD does not point to a commit")?;
+        let head_index = self.read_tree(&self.read_commit(&head_sha)?.get_tree_sha())?;
+
+        let (worktree, worktree_conflicts) =
+            self.merge_indexes(&base, &head_index, &stashed_worktree, STASH_LABEL)?;
+        let (index, index_conflicts) =
+            self.merge_indexes(&base, &head_index, &stashed_index, STASH_LABEL)?;
+        self.update_worktree(&head_index, &worktree)?;
+
+        if worktree_conflicts.is_empty() && index_conflicts.is_empty() {
+            index.save(&self.get_index_path())?;
+            return Ok(Vec::new());
+        }
+        let mut conflicts: Vec<String> = worktree_conflicts
+            .into_iter()
+            .chain(index_conflicts)
+            .map(|conflict| conflict.path)
+            .collect();
+        conflicts.sort();
+        conflicts.dedup();
+        for path in &conflicts {
+            println!("CONFLICT (content): Merge conflict in {path}");
+        }
+        Ok(conflicts)
+    }
+
+    /// Hashes the working tree copy of every file tracked by `index` into the
+    /// object database. Files missing from the working tree are left out.
+    fn worktree_snapshot(&self, index: &Index) -> Result<Index, String> {
+        let mut worktree = Index::new();
+        for (path, _) in index.collect_entries() {
+            let file = self.dir.join(&path);
+            if !file.is_file() {
+                continue;
+            }
+            let blob = Blob::new(&file)?;
+            let sha = self.obj_db.store(&blob).map_err(|why| why.to_string())?;
+            worktree.update_entry(&path, sha);
+        }
+        Ok(worktree)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::{commit_file, init_test_repo};
+    use tempfile::TempDir;
+
+    #[test]
+    fn stash_round_trip() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        commit_file(&repo, "a.txt", "a\n");
+        commit_file(&repo, "b.txt", "b\n");
+        assert_eq!(repo.create_autostash().unwrap(), None);
+
+        // One staged and one unstaged change
+        fs::write(repo.dir.join("a.txt"), "staged\n").unwrap();
+        repo.update_index(&repo.dir.join("a.txt")).unwrap();
+        fs::write(repo.dir.join("b.txt"), "unstaged\n").unwrap();
+
+        let stash = repo.create_autostash().unwrap().unwrap();
+        assert_eq!(fs::read_to_string(repo.dir.join("a.txt")).unwrap(), "a\n");
+        assert_eq!(fs::read_to_string(repo.dir.join("b.txt")).unwrap(), "b\n");
+        assert!(repo.git_dir.join(AUTOSTASH_FILE).exists());
+
+        // Meanwhile HEAD moves on with an unrelated change
+        commit_file(&repo, "c.txt", "c\n");
+        repo.apply_autostash(&stash).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(repo.dir.join("a.txt")).unwrap(),
+            "staged\n"
+        );
+        assert_eq!(
+            fs::read_to_string(repo.dir.join("b.txt")).unwrap(),
+            "unstaged\n"
+        );
+        let index = Index::load(&repo.get_index_path()).unwrap();
+        assert!(index.get_sha1("c.txt").is_some());
+        assert!(!repo.git_dir.join(AUTOSTASH_FILE).exists());
+    }
+
+    #[test]
+    fn conflicting_stash_is_kept() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        commit_file(&repo, "a.txt", "a\n");
+
+        fs::write(repo.dir.join("a.txt"), "local\n").unwrap();
+        let stash = repo.create_autostash().unwrap().unwrap();
+        commit_file(&repo, "a.txt", "upstream\n");
+        repo.apply_autostash(&stash).unwrap();
+
+        let content = fs::read_to_string(repo.dir.join("a.txt")).unwrap();
+        assert!(content.starts_with("<<<<<<< HEAD\nupstream\n"));
+        assert!(repo.git_dir.join(AUTOSTASH_FILE).exists());
+    }
+}
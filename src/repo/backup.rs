@@ -0,0 +1,258 @@
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use walkdir::WalkDir;
+
+use super::packed_refs::PACKED_REFS_FILE;
+use super::reflog::LOGS_DIR;
+use super::{CONFIG_FILE, GIT_DIR, HEAD_FILE, OBJECTS_DIR, REFS_DIR, Repository};
+use crate::Error;
+use crate::crypt::ENCRYPTION_FILE;
+use crate::index::Index;
+use crate::lock::{LOCK_SUFFIX, LockFile};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+impl Repository {
+    /// Writes a gzipped tar snapshot of HEAD, all refs and their reflogs,
+    /// the config and every loose and packed object to `file`.
+    ///
+    /// HEAD and the refs are locked while the snapshot is taken, so no
+    /// ref can move and every ref in the archive points at objects that
+    /// are in it too.
+    pub fn backup(&self, file: &str) -> Result<(), Error> {
+        let objects = self.write_backup(Path::new(file))?;
+        println!("Backed up {objects} object(s) to {file}");
+        Ok(())
+    }
+
+    /// Recreates a repository in `dir` from an archive made by `backup`,
+    /// verifies every object and checks out HEAD.
+    ///
+    /// # Returns
+    /// - `Ok(Repository)`: The restored repository
+    /// - `Err(Error)`: The archive is unusable; nothing is left behind in `dir`
+    pub fn restore_backup(archive: &Path, dir: &Path) -> Result<Repository, Error> {
+        let git_dir = dir.join(GIT_DIR);
+        if git_dir.exists() {
+            return Err("git directory already exists".into());
+        }
+        let result = Self::unpack_backup(archive, dir);
+        if result.is_err() {
+            let _ = fs::remove_dir_all(&git_dir);
+        }
+        result
+    }
+
+    fn unpack_backup(archive: &Path, dir: &Path) -> Result<Repository, Error> {
+        let git_dir = dir.join(GIT_DIR);
+        let file = File::open(archive).map_err(|why| why.to_string())?;
+        tar::Archive::new(GzDecoder::new(file))
+            .unpack(&git_dir)
+            .map_err(|why| format!("cannot unpack {}: {why}", archive.display()))?;
+        if !Repository::is_vaild_git_dir(&git_dir) {
+            return Err(format!("{} is not a repository backup", archive.display()).into());
+        }
+        let repo = Repository::open(dir)?;
+
+        let mut objects = repo.obj_db.loose_objects().map_err(|why| why.to_string())?;
+        objects.extend(
+            repo.obj_db
+                .packed_objects()
+                .map_err(|why| why.to_string())?,
+        );
+        for sha in &objects {
+            repo.verify_object(sha)
+                .map_err(|why| format!("corrupt object {sha} in backup: {why}"))?;
+        }
+
+        if let Some(commit_sha) = repo.get_current_commit() {
+            let index = repo.read_tree(&repo.read_commit(&commit_sha)?.get_tree_sha())?;
+            for (path, _) in index.collect_entries() {
+                if repo.dir.join(&path).exists() {
+                    return Err(Error::UntrackedInTheWay(path));
+                }
+            }
+            repo.update_worktree(&Index::new(), &index)?;
+            index.save(&repo.get_index_path())?;
+        }
+        Ok(repo)
+    }
+
+    /// Writes the archive, returning the number of objects it holds
+    fn write_backup(&self, path: &Path) -> Result<usize, String> {
+        // Lock HEAD and every ref in a fixed order so concurrent backups
+        // cannot deadlock each other
+        let mut refs = vec![PathBuf::from(HEAD_FILE)];
+        for entry in WalkDir::new(self.git_dir.join(REFS_DIR))
+            .sort_by_file_name()
+            .into_iter()
+            .filter_map(|e| e.ok())
+        {
+            let name = entry.file_name().to_string_lossy();
+            if entry.file_type().is_file() && !name.ends_with(LOCK_SUFFIX) {
+                refs.push(entry.path().strip_prefix(&self.git_dir).unwrap().into());
+            }
+        }
+        let mut locks = Vec::new();
+        for name in &refs {
+            let lock =
+                LockFile::acquire(&self.git_dir.join(name)).map_err(|why| why.to_string())?;
+            locks.push(lock);
+        }
+
+        let file = File::create(path).map_err(|why| why.to_string())?;
+        let mut builder = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+        let mut add = |name: &Path| -> Result<(), String> {
+            builder
+                .append_path_with_name(self.git_dir.join(name), name)
+                .map_err(|why| format!("cannot archive {}: {why}", name.display()))
+        };
+        for name in &refs {
+            add(name)?;
+        }
+        // Without its marker an encrypted store would not ask for the key
+        let marker = Path::new(OBJECTS_DIR).join(ENCRYPTION_FILE);
+        for file in [Path::new(CONFIG_FILE), Path::new(PACKED_REFS_FILE), &marker] {
+            if self.git_dir.join(file).is_file() {
+                add(file)?;
+            }
+        }
+        // The reflogs and the packs `git gc` leaves are copied whole
+        for dir in [PathBuf::from(LOGS_DIR), Path::new(OBJECTS_DIR).join("pack")] {
+            for entry in WalkDir::new(self.git_dir.join(dir))
+                .sort_by_file_name()
+                .into_iter()
+                .filter_map(|e| e.ok())
+                .filter(|e| e.file_type().is_file())
+            {
+                add(entry.path().strip_prefix(&self.git_dir).unwrap())?;
+            }
+        }
+        let objects = self.obj_db.loose_objects().map_err(|why| why.to_string())?;
+        for sha in &objects {
+            let object_path = self.obj_db.object_path(sha);
+            add(object_path.strip_prefix(&self.git_dir).unwrap())?;
+        }
+        let packed = self
+            .obj_db
+            .packed_objects()
+            .map_err(|why| why.to_string())?;
+        builder
+            .into_inner()
+            .and_then(|encoder| encoder.finish())
+            .and_then(|file| file.sync_all())
+            .map_err(|why| why.to_string())?;
+        drop(locks);
+        Ok(objects.len() + packed.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypt::{KEY_ENV, ObjectCipher};
+    use crate::git_source::{GitKind, write_test_pack};
+    use crate::repo::{commit_file, init_test_repo};
+    use tempfile::TempDir;
+
+    #[test]
+    fn backup_round_trip() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        let file = repo.dir.join("a.txt");
+        fs::write(&file, "hello").unwrap();
+        repo.add(&vec![file.to_str().unwrap()]).unwrap();
+        repo.commit("first").unwrap();
+        repo.branch("topic").unwrap();
+
+        let archive = TempDir::new().unwrap();
+        let archive = archive.path().join("backup.tar.gz");
+        repo.write_backup(&archive).unwrap();
+        // Locks are released afterwards
+        assert!(!repo.git_dir.join("HEAD.lock").exists());
+
+        let restore_dir = TempDir::new().unwrap();
+        let restored = Repository::restore_backup(&archive, restore_dir.path()).unwrap();
+        assert_eq!(restored.get_current_commit(), repo.get_current_commit());
+        assert!(restored.load_branch("topic").is_some());
+        assert_eq!(
+            fs::read_to_string(restore_dir.path().join("a.txt")).unwrap(),
+            "hello"
+        );
+
+        // Restoring over an existing repository is refused
+        assert!(Repository::restore_backup(&archive, restore_dir.path()).is_err());
+    }
+
+    #[test]
+    fn encrypted_backup_round_trip() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut repo = init_test_repo(temp_dir.path());
+        // Not the key other tests encrypt with, so their repositories
+        // still refuse to open
+        let key = [2; 32];
+        // SAFETY: no test reads the environment variable concurrently
+        // expecting it to be unset
+        unsafe { std::env::set_var(KEY_ENV, hex::encode(key)) };
+        repo.enable_encryption(ObjectCipher::new(key)).unwrap();
+        commit_file(&repo, "a.txt", "secret\n");
+
+        let archive = TempDir::new().unwrap();
+        let archive = archive.path().join("backup.tar.gz");
+        repo.write_backup(&archive).unwrap();
+        let restore_dir = TempDir::new().unwrap();
+        let restored = Repository::restore_backup(&archive, restore_dir.path()).unwrap();
+        assert!(
+            restored
+                .git_dir
+                .join(OBJECTS_DIR)
+                .join(ENCRYPTION_FILE)
+                .is_file()
+        );
+        assert_eq!(restored.get_current_commit(), repo.get_current_commit());
+        assert_eq!(
+            fs::read_to_string(restore_dir.path().join("a.txt")).unwrap(),
+            "secret\n"
+        );
+    }
+
+    #[test]
+    fn packed_backup_round_trip() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        let first = commit_file(&repo, "a.txt", "one\n");
+        commit_file(&repo, "a.txt", "two\n");
+
+        // Pack the first commit away, as `git gc` would
+        let data = repo.obj_db.retrieve(&first).unwrap();
+        let content = data[data.iter().position(|&b| b == 0).unwrap() + 1..].to_vec();
+        write_test_pack(
+            &repo.git_dir.join(OBJECTS_DIR),
+            &[(GitKind::Commit, content)],
+        );
+        fs::remove_file(repo.obj_db.object_path(&first)).unwrap();
+
+        let archive = TempDir::new().unwrap();
+        let archive = archive.path().join("backup.tar.gz");
+        repo.write_backup(&archive).unwrap();
+        let restore_dir = TempDir::new().unwrap();
+        let restored = Repository::restore_backup(&archive, restore_dir.path()).unwrap();
+        assert!(restored.fsck_report().unwrap().is_ok());
+        assert!(restored.verify_object(&first).is_ok());
+        let reflog = restored.reflog("refs/heads/master").unwrap();
+        assert_eq!(reflog.len(), 2);
+        assert_eq!(reflog, repo.reflog("refs/heads/master").unwrap());
+    }
+
+    #[test]
+    fn corrupt_backup_leaves_nothing_behind() {
+        let archive_dir = TempDir::new().unwrap();
+        let archive = archive_dir.path().join("backup.tar.gz");
+        fs::write(&archive, "not an archive").unwrap();
+
+        let restore_dir = TempDir::new().unwrap();
+        assert!(Repository::restore_backup(&archive, restore_dir.path()).is_err());
+        assert!(!restore_dir.path().join(GIT_DIR).exists());
+    }
+}
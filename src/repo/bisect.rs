@@ -0,0 +1,308 @@
+use super::log::format_commit_header;
+use super::{HEAD_FILE, HEADS_DIR, Head, REFS_DIR, Repository};
+use crate::{EncodedSha, Error};
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process;
+use std::str::FromStr;
+
+/// Branch or commit HEAD was at when the bisection started
+const BISECT_START: &str = "BISECT_START";
+/// The newest commit known to be bad
+const BISECT_BAD: &str = "BISECT_BAD";
+/// The commits known to be good, one per line
+const BISECT_GOOD: &str = "BISECT_GOOD";
+
+/// Where a bisection stands after a commit was marked
+#[derive(Debug, PartialEq)]
+pub enum BisectStep {
+    /// A bad and at least one good commit have yet to be marked
+    NeedMarks,
+    /// `commit` is checked out to be tested next; at most `remaining`
+    /// revisions are left to test after it
+    Testing {
+        commit: EncodedSha,
+        remaining: usize,
+    },
+    /// The first bad commit
+    Found(EncodedSha),
+}
+
+impl Repository {
+    /// Starts a binary search for the commit that introduced a bug, like
+    /// `git bisect start`. Commits between the good and the bad ones are
+    /// checked out one after another, halving the range with every mark
+    /// given by [`Self::bisect_good`] or [`Self::bisect_bad`], until the
+    /// first bad commit is found. [`Self::bisect_reset`] ends the search.
+    ///
+    /// # Arguments
+    /// * `bad` - A commit that has the bug, if already known
+    /// * `good` - Commits that do not have it, if already known
+    pub fn bisect_start<S: AsRef<str>>(
+        &self,
+        bad: Option<&str>,
+        good: &[S],
+    ) -> Result<BisectStep, Error> {
+        if self.bisect_start_point().is_some() {
+            return Err("bisect already in progress; use 'rust-git bisect reset' first".into());
+        }
+        let head_sha = self.resolve_commit(HEAD_FILE)?;
+        let bad = bad.map(|rev| self.resolve_commit(rev)).transpose()?;
+        let good = good
+            .iter()
+            .map(|rev| self.resolve_commit(rev.as_ref()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let start = match self.get_head() {
+            Some(Head::Symbolic(path)) => path
+                .strip_prefix(Path::new(REFS_DIR).join(HEADS_DIR))
+                .unwrap_or(&path)
+                .to_string_lossy()
+                .replace('\\', "/"),
+            _ => head_sha.to_string(),
+        };
+        fs::write(self.git_dir.join(BISECT_START), format!("{start}\n"))?;
+        if let Some(bad) = bad {
+            fs::write(self.git_dir.join(BISECT_BAD), format!("{bad}\n"))?;
+        }
+        let good: String = good.iter().map(|sha| format!("{sha}\n")).collect();
+        fs::write(self.git_dir.join(BISECT_GOOD), good)?;
+        // Marks that cannot be bisected do not leave a bisection behind
+        self.bisect_next().inspect_err(|_| {
+            let _ = self.clear_bisect_state();
+        })
+    }
+
+    /// Marks `rev`, HEAD by default, as not having the bug and checks out
+    /// the next commit to test
+    pub fn bisect_good(&self, rev: Option<&str>) -> Result<BisectStep, Error> {
+        let sha = self.bisect_mark_target(rev)?;
+        let mut good = fs::read_to_string(self.git_dir.join(BISECT_GOOD)).unwrap_or_default();
+        good.push_str(&format!("{sha}\n"));
+        fs::write(self.git_dir.join(BISECT_GOOD), good)?;
+        self.bisect_next()
+    }
+
+    /// Marks `rev`, HEAD by default, as having the bug and checks out the
+    /// next commit to test
+    pub fn bisect_bad(&self, rev: Option<&str>) -> Result<BisectStep, Error> {
+        let sha = self.bisect_mark_target(rev)?;
+        fs::write(self.git_dir.join(BISECT_BAD), format!("{sha}\n"))?;
+        self.bisect_next()
+    }
+
+    /// Ends the bisection, checking out the branch or commit HEAD was at
+    /// when it started
+    pub fn bisect_reset(&self) -> Result<(), Error> {
+        let start = self.bisect_start_point().ok_or("We are not bisecting.")?;
+        self.checkout(&start, false)?;
+        Ok(self.clear_bisect_state()?)
+    }
+
+    /// Bisects automatically by running `cmd` in the working tree on every
+    /// commit to test, like `git bisect run`. Exit code 0 marks the commit
+    /// good and 1 to 127 bad; skipping with 125 is not supported. Any other
+    /// outcome stops the bisection.
+    ///
+    /// # Returns
+    /// The first bad commit
+    pub fn bisect_run<S: AsRef<str>>(&self, cmd: &[S]) -> Result<EncodedSha, Error> {
+        let (program, args) = cmd.split_first().ok_or("bisect run needs a command")?;
+        self.bisect_run_with(|repo| {
+            let status = process::Command::new(program.as_ref())
+                .args(args.iter().map(AsRef::as_ref))
+                .current_dir(&repo.dir)
+                .status()?;
+            match status.code() {
+                Some(0) => Ok(true),
+                Some(125) => Err("bisect run cannot skip commits".into()),
+                Some(1..128) => Ok(false),
+                _ => Err(format!("bisect run failed: {status}").into()),
+            }
+        })
+    }
+
+    /// Bisects automatically, asking `is_good` about every commit checked
+    /// out to be tested
+    ///
+    /// # Returns
+    /// The first bad commit
+    pub fn bisect_run_with<F>(&self, mut is_good: F) -> Result<EncodedSha, Error>
+    where
+        F: FnMut(&Repository) -> Result<bool, Error>,
+    {
+        if self.bisect_start_point().is_none() {
+            return Err("We are not bisecting.".into());
+        }
+        let mut step = self.bisect_next()?;
+        loop {
+            step = match step {
+                BisectStep::NeedMarks => {
+                    return Err("bisect run needs a good and a bad commit to be marked".into());
+                }
+                BisectStep::Found(sha) => return Ok(sha),
+                BisectStep::Testing { .. } => match is_good(self)? {
+                    true => self.bisect_good(None)?,
+                    false => self.bisect_bad(None)?,
+                },
+            };
+        }
+    }
+
+    /// The branch or commit the bisection in progress started from
+    pub(super) fn bisect_start_point(&self) -> Option<String> {
+        let start = fs::read_to_string(self.git_dir.join(BISECT_START)).ok()?;
+        Some(start.trim().to_string())
+    }
+
+    fn clear_bisect_state(&self) -> io::Result<()> {
+        for file in [BISECT_START, BISECT_BAD, BISECT_GOOD] {
+            match fs::remove_file(self.git_dir.join(file)) {
+                Err(why) if why.kind() != io::ErrorKind::NotFound => return Err(why),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn bisect_mark_target(&self, rev: Option<&str>) -> Result<EncodedSha, Error> {
+        if self.bisect_start_point().is_none() {
+            return Err("You need to start by \"rust-git bisect start\"".into());
+        }
+        Ok(self.resolve_commit(rev.unwrap_or(HEAD_FILE))?)
+    }
+
+    /// Narrows the range down to the commits reachable from the bad one but
+    /// from none of the good ones, and checks out the commit splitting it
+    /// most evenly
+    fn bisect_next(&self) -> Result<BisectStep, Error> {
+        let bad = fs::read_to_string(self.git_dir.join(BISECT_BAD))
+            .ok()
+            .and_then(|bad| EncodedSha::from_str(bad.trim()).ok());
+        let good: Vec<EncodedSha> = fs::read_to_string(self.git_dir.join(BISECT_GOOD))
+            .unwrap_or_default()
+            .lines()
+            .filter_map(|line| EncodedSha::from_str(line).ok())
+            .collect();
+        let Some(bad) = bad.filter(|_| !good.is_empty()) else {
+            println!("status: waiting for both good and bad commits");
+            return Ok(BisectStep::NeedMarks);
+        };
+
+        let excluded: HashSet<EncodedSha> = self.reachable_from(good).collect();
+        if excluded.contains(&bad) {
+            return Err(format!("the bad commit {bad} is an ancestor of a good commit").into());
+        }
+        let candidates: Vec<EncodedSha> = self
+            .reachable_from([bad.clone()])
+            .filter(|sha| !excluded.contains(sha))
+            .collect();
+        if candidates.len() == 1 {
+            println!("{bad} is the first bad commit");
+            print!("{}", format_commit_header(&bad, &self.read_commit(&bad)?));
+            return Ok(BisectStep::Found(bad));
+        }
+
+        // Testing a commit settles either the candidates it reaches, or all
+        // the others; the best one leaves the fewest in the worst case
+        let set: HashSet<&EncodedSha> = candidates.iter().collect();
+        let total = candidates.len();
+        let mut best: Option<(&EncodedSha, usize)> = None;
+        for sha in &candidates {
+            let reached = self.count_reachable_within(sha, &set)?;
+            if best.is_none_or(|(_, most)| reached.min(total - reached) > most.min(total - most)) {
+                best = Some((sha, reached));
+            }
+        }
+        let (commit, reached) = best.unwrap();
+        let remaining = (reached - 1).max(total - reached - 1);
+        let steps = usize::BITS - remaining.leading_zeros();
+        println!(
+            "Bisecting: {remaining} revisions left to test after this (roughly {steps} steps)"
+        );
+        let commit = commit.clone();
+        self.move_head(
+            Head::Detached(commit.clone()),
+            Some(commit.clone()),
+            &commit.0,
+            false,
+        )?;
+        Ok(BisectStep::Testing { commit, remaining })
+    }
+
+    /// Counts the commits of `set` reachable from `tip`, itself included,
+    /// without leaving `set`
+    fn count_reachable_within(
+        &self,
+        tip: &EncodedSha,
+        set: &HashSet<&EncodedSha>,
+    ) -> Result<usize, Error> {
+        let mut seen = HashSet::from([tip.clone()]);
+        let mut pending = vec![tip.clone()];
+        while let Some(sha) = pending.pop() {
+            for parent in self.read_commit(&sha)?.get_parents() {
+                if set.contains(parent) && seen.insert(parent.clone()) {
+                    pending.push(parent.clone());
+                }
+            }
+        }
+        Ok(seen.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::init_test_repo;
+    use tempfile::TempDir;
+
+    #[test]
+    fn bisect_finds_the_first_bad_commit() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        let file = repo.dir.join("a.txt");
+        let commits: Vec<EncodedSha> = (0..9)
+            .map(|i| {
+                let content = if i < 6 { "fine\n" } else { "bug\n" };
+                fs::write(&file, format!("{content}{i}\n")).unwrap();
+                repo.add(&vec![file.to_str().unwrap()]).unwrap();
+                repo.commit(format!("commit {i}")).unwrap()
+            })
+            .collect();
+        assert!(repo.bisect_good(None).is_err());
+
+        // Marking by hand checks out the commits in between
+        assert_eq!(
+            repo.bisect_start::<&str>(None, &[]).unwrap(),
+            BisectStep::NeedMarks
+        );
+        assert!(repo.bisect_start::<&str>(None, &[]).is_err());
+        repo.bisect_bad(None).unwrap();
+        let step = repo.bisect_good(Some(&commits[0].0)).unwrap();
+        let BisectStep::Testing { commit, remaining } = step else {
+            panic!("expected a commit to test, got {step:?}");
+        };
+        assert_eq!(remaining, 3);
+        assert_eq!(repo.get_head(), Some(Head::Detached(commit.clone())));
+        assert!(commits[1..8].contains(&commit));
+
+        let is_good =
+            |repo: &Repository| Ok(fs::read_to_string(repo.dir.join("a.txt"))?.starts_with("fine"));
+        assert_eq!(repo.bisect_run_with(is_good).unwrap(), commits[6]);
+
+        // Resetting returns to the branch bisecting started from
+        repo.bisect_reset().unwrap();
+        assert_eq!(repo.get_current_commit(), Some(commits[8].clone()));
+        assert!(matches!(repo.get_head(), Some(Head::Symbolic(_))));
+        assert!(repo.bisect_reset().is_err());
+
+        // A bad commit older than a good one cannot be bisected
+        assert!(
+            repo.bisect_start(Some(&commits[2].0), &[commits[4].0.as_str()])
+                .is_err()
+        );
+        assert!(repo.bisect_start_point().is_none());
+    }
+}
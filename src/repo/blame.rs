@@ -0,0 +1,162 @@
+use super::Repository;
+use super::pathspec::normalize_pathspecs;
+use crate::diff;
+use crate::object::Author;
+use crate::{EncodedSha, Error};
+
+/// The commit that introduced one line of a file, as found by `blame`
+#[derive(Debug, Clone)]
+pub struct BlameLine {
+    pub commit_sha: EncodedSha,
+    pub author: Author,
+    /// Path of the file in that commit, which differs from the blamed path
+    /// when the line predates a rename
+    pub path: String,
+    /// 1-based line number in the file at HEAD
+    pub line_no: usize,
+    pub content: String,
+}
+
+impl Repository {
+    /// Attributes every line of `path` at HEAD to the commit that
+    /// introduced it.
+    ///
+    /// The versions `file_history` finds are compared newest first; a
+    /// line is blamed on the newest commit whose older neighbour does not
+    /// have it. Renames are followed.
+    ///
+    /// # Returns
+    /// - `Ok(Vec<BlameLine>)`: The origin of each line, in file order
+    /// - `Err(Error)`: `path` does not exist at HEAD
+    pub fn blame(&self, path: &str) -> Result<Vec<BlameLine>, Error> {
+        let path = normalize_pathspecs(&[path]).remove(0);
+        let mut history = self.file_history(&path, true);
+        let newest = history
+            .next()
+            .filter(|revision| revision.blob.is_some())
+            .ok_or_else(|| format!("no such path '{path}' in HEAD"))?;
+
+        let text = self.blob_text(newest.blob.as_ref())?;
+        let lines: Vec<String> = text.lines().map(str::to_string).collect();
+        // The lines not yet attributed, as (line at HEAD, line in `current`)
+        let mut unblamed: Vec<(usize, usize)> = (0..lines.len()).map(|i| (i, i)).collect();
+        let mut blamed: Vec<Option<BlameLine>> = vec![None; lines.len()];
+        let mut current = newest;
+        let mut current_text = text;
+
+        while !unblamed.is_empty() {
+            let older = history.next();
+            let older_text = match &older {
+                Some(revision) => self.blob_text(revision.blob.as_ref())?,
+                None => String::new(),
+            };
+            // Where each line of `current` sits in the older version
+            let old_len = diff::lines(&older_text).len();
+            let new_len = diff::lines(&current_text).len();
+            let mut moved_to = vec![None; new_len];
+            let hunks = diff::diff_lines(&older_text, &current_text);
+            for (old, new) in diff::unchanged(&hunks, old_len, new_len) {
+                moved_to[new] = Some(old);
+            }
+            unblamed.retain_mut(|(line, at)| match moved_to[*at] {
+                Some(old) => {
+                    *at = old;
+                    true
+                }
+                None => {
+                    blamed[*line] = Some(BlameLine {
+                        commit_sha: current.commit_sha.clone(),
+                        author: current.commit.get_author().clone(),
+                        path: current.path.clone(),
+                        line_no: *line + 1,
+                        content: lines[*line].clone(),
+                    });
+                    false
+                }
+            });
+            match older {
+                Some(revision) => {
+                    current = revision;
+                    current_text = older_text;
+                }
+                None => break,
+            }
+        }
+        Ok(blamed.into_iter().flatten().collect())
+    }
+
+    /// Prints `path` at HEAD with the abbreviated commit, author and date
+    /// that introduced each line, like `git blame`
+    pub fn print_blame(&self, path: &str) -> Result<(), Error> {
+        let lines = self.blame(path)?;
+        let name_width = lines
+            .iter()
+            .map(|line| line.author.get_name().chars().count())
+            .max()
+            .unwrap_or_default();
+        let number_width = lines.len().to_string().len();
+        for line in &lines {
+            println!(
+                "{} ({:<name_width$} {} {:>number_width$}) {}",
+                &line.commit_sha.0[..8],
+                line.author.get_name(),
+                line.author.get_timestamp().format("%Y-%m-%d %H:%M:%S %z"),
+                line.line_no,
+                line.content
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::Index;
+    use crate::repo::init_test_repo;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn blame_attributes_lines_to_the_commits_adding_them() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        let file = repo.dir.join("a.txt");
+        let commit = |content: &str, message: &str| {
+            fs::write(&file, content).unwrap();
+            repo.add(&vec![file.to_str().unwrap()]).unwrap();
+            repo.commit(message).unwrap()
+        };
+        let first = commit("one\ntwo\nthree\n", "first");
+        let second = commit("one\nTWO\nthree\nfour\n", "second");
+
+        // Rename the file while adding a line
+        let mut index = Index::load(&repo.get_index_path()).unwrap();
+        index.remove_entry("a.txt");
+        index.save(&repo.get_index_path()).unwrap();
+        fs::remove_file(&file).unwrap();
+        let file = repo.dir.join("b.txt");
+        fs::write(&file, "zero\none\nTWO\nthree\nfour\n").unwrap();
+        repo.add(&vec![file.to_str().unwrap()]).unwrap();
+        let third = repo.commit("third").unwrap();
+
+        let lines = repo.blame("b.txt").unwrap();
+        let origins: Vec<(&EncodedSha, &str)> = lines
+            .iter()
+            .map(|line| (&line.commit_sha, line.content.as_str()))
+            .collect();
+        assert_eq!(
+            origins,
+            [
+                (&third, "zero"),
+                (&first, "one"),
+                (&second, "TWO"),
+                (&first, "three"),
+                (&second, "four"),
+            ]
+        );
+        assert_eq!(lines[1].path, "a.txt");
+        assert_eq!(lines[4].line_no, 5);
+        assert!(repo.blame("a.txt").is_err());
+    }
+}
@@ -0,0 +1,310 @@
+use super::{Branch, HEAD_FILE, HEADS_DIR, REFS_DIR, REMOTES_DIR, Repository};
+use crate::git_source::{self, GitKind};
+use crate::{EncodedSha, Error};
+use memchr::memchr;
+use std::collections::HashSet;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use std::str::FromStr;
+
+/// First line of every bundle file
+const BUNDLE_SIGNATURE: &str = "# v2 git bundle\n";
+
+/// A bundle file as written by `git bundle create`: the refs it carries,
+/// the commits a repository must already have to take them, and a pack of
+/// the objects in between
+#[derive(Debug)]
+pub struct Bundle {
+    /// Commits the pack leaves out, with their subject lines
+    pub prerequisites: Vec<(EncodedSha, String)>,
+    /// Full ref names and the commits they point at
+    pub refs: Vec<(String, EncodedSha)>,
+    pack: Vec<u8>,
+}
+
+impl Bundle {
+    /// Reads the bundle at `path`; the pack is only checked when unpacked
+    pub fn read(path: &Path) -> Result<Bundle, Error> {
+        let data = fs::read(path)?;
+        let not_a_bundle = || format!("'{}' does not look like a v2 bundle file", path.display());
+        let mut rest = data
+            .strip_prefix(BUNDLE_SIGNATURE.as_bytes())
+            .ok_or_else(not_a_bundle)?;
+        let mut bundle = Bundle {
+            prerequisites: Vec::new(),
+            refs: Vec::new(),
+            pack: Vec::new(),
+        };
+        // Header lines end with an empty line
+        loop {
+            let end = memchr(b'\n', rest).ok_or_else(not_a_bundle)?;
+            let line = std::str::from_utf8(&rest[..end]).map_err(|_| not_a_bundle())?;
+            rest = &rest[end + 1..];
+            if line.is_empty() {
+                break;
+            }
+            if let Some(prerequisite) = line.strip_prefix('-') {
+                let (sha, subject) = prerequisite.split_once(' ').unwrap_or((prerequisite, ""));
+                let sha = EncodedSha::from_str(sha).map_err(|_| not_a_bundle())?;
+                bundle.prerequisites.push((sha, subject.to_string()));
+            } else {
+                let (sha, name) = line.split_once(' ').ok_or_else(not_a_bundle)?;
+                let sha = EncodedSha::from_str(sha).map_err(|_| not_a_bundle())?;
+                bundle.refs.push((name.to_string(), sha));
+            }
+        }
+        bundle.pack = rest.to_vec();
+        Ok(bundle)
+    }
+
+    /// Whether `path` is a file starting like a bundle
+    pub fn is_bundle(path: &Path) -> bool {
+        let mut signature = [0; BUNDLE_SIGNATURE.len()];
+        path.is_file()
+            && fs::File::open(path).is_ok_and(|mut file| file.read_exact(&mut signature).is_ok())
+            && signature == BUNDLE_SIGNATURE.as_bytes()
+    }
+
+    /// The `refs/heads/*` refs of the bundle as branches
+    pub(super) fn branches(&self) -> Vec<Branch> {
+        let prefix = format!("{REFS_DIR}/{HEADS_DIR}/");
+        self.refs
+            .iter()
+            .filter_map(|(name, sha)| {
+                Some(Branch {
+                    name: name.strip_prefix(&prefix)?.to_string(),
+                    commit_sha: Some(sha.clone()),
+                })
+            })
+            .collect()
+    }
+}
+
+impl Repository {
+    /// Writes the history `revs` lead to into the single file `file`, like
+    /// `git bundle create`, so it can be carried to another repository and
+    /// cloned, fetched or unbundled there.
+    ///
+    /// # Arguments
+    /// * `revs` - Branches, remote-tracking refs or HEAD to include. An
+    ///   `A..B` range or `^A` leaves out what `A` reaches, making `A` a
+    ///   prerequisite the receiving repository must have. Without any,
+    ///   HEAD and every branch are included.
+    ///
+    /// # Returns
+    /// The number of objects written
+    pub fn bundle_create<S: AsRef<str>>(&self, file: &Path, revs: &[S]) -> Result<usize, Error> {
+        let mut refs: Vec<(String, EncodedSha)> = Vec::new();
+        let mut prerequisites = Vec::new();
+        let mut add_ref = |repo: &Repository, rev: &str| -> Result<(), Error> {
+            let name = repo
+                .bundle_ref_name(rev)
+                .ok_or_else(|| format!("'{rev}' is not a branch, remote-tracking ref or HEAD"))?;
+            let sha = repo.resolve_commit(rev)?;
+            if !refs.iter().any(|(known, _)| *known == name) {
+                refs.push((name, sha));
+            }
+            Ok(())
+        };
+        if revs.is_empty() {
+            add_ref(self, HEAD_FILE)?;
+            for branch in self.list_branches() {
+                add_ref(self, &branch.name)?;
+            }
+        }
+        for rev in revs {
+            let rev = rev.as_ref();
+            if let Some(excluded) = rev.strip_prefix('^') {
+                prerequisites.push(self.resolve_commit(excluded)?);
+            } else if let Some((from, to)) = rev.split_once("..") {
+                let or_head = |side: &str| match side {
+                    "" => HEAD_FILE.to_string(),
+                    side => side.to_string(),
+                };
+                prerequisites.push(self.resolve_commit(&or_head(from))?);
+                add_ref(self, &or_head(to))?;
+            } else {
+                add_ref(self, rev)?;
+            }
+        }
+        if refs.is_empty() {
+            return Err("Refusing to create empty bundle.".into());
+        }
+
+        let objects = self.pack_objects(
+            refs.iter().map(|(_, sha)| sha.clone()),
+            prerequisites.clone(),
+        )?;
+        if objects.is_empty() {
+            return Err("Refusing to create empty bundle.".into());
+        }
+
+        let mut header = BUNDLE_SIGNATURE.to_string();
+        for sha in &prerequisites {
+            let commit = self.read_commit(sha)?;
+            let subject = commit.get_message().lines().next().unwrap_or_default();
+            header.push_str(&format!("-{sha} {subject}\n"));
+        }
+        for (name, sha) in &refs {
+            header.push_str(&format!("{sha} {name}\n"));
+        }
+        header.push('\n');
+        let mut data = header.into_bytes();
+        data.extend_from_slice(&git_source::write_pack(&objects));
+        fs::write(file, data)?;
+        Ok(objects.len())
+    }
+
+    /// Checks that the bundle `file` is intact and that this repository has
+    /// its prerequisites, like `git bundle verify`
+    pub fn bundle_verify(&self, file: &Path) -> Result<Bundle, Error> {
+        let bundle = Bundle::read(file)?;
+        self.unpack_bundle(&bundle)?;
+        Ok(bundle)
+    }
+
+    /// Stores the objects of the bundle `file` without updating any ref,
+    /// like `git bundle unbundle`
+    ///
+    /// # Returns
+    /// The bundle, whose refs tell what was brought in
+    pub fn bundle_unbundle(&self, file: &Path) -> Result<Bundle, Error> {
+        let bundle = Bundle::read(file)?;
+        self.store_bundle(&bundle)?;
+        Ok(bundle)
+    }
+
+    /// Stores the objects of `bundle`, returning how many it holds. They
+    /// are written to a quarantine first and only join the store once all
+    /// of them are written.
+    pub(super) fn store_bundle(&self, bundle: &Bundle) -> Result<usize, Error> {
+        let objects = self.unpack_bundle(bundle)?;
+        let quarantine = self.obj_db.quarantine()?;
+        for (_, kind, content) in &objects {
+            let mut data = format!("{} {}\0", kind.name(), content.len()).into_bytes();
+            data.extend_from_slice(content);
+            quarantine.db().store_serialized(data)?;
+        }
+        quarantine.migrate()?;
+        Ok(objects.len())
+    }
+
+    /// Parses the pack of `bundle` after checking the prerequisites are
+    /// present, and checks every ref points at an object of the pack or of
+    /// this repository
+    fn unpack_bundle(&self, bundle: &Bundle) -> Result<Vec<(String, GitKind, Vec<u8>)>, Error> {
+        let missing: Vec<String> = bundle
+            .prerequisites
+            .iter()
+            .filter(|(sha, _)| self.read_commit(sha).is_err())
+            .map(|(sha, subject)| format!("{sha} {subject}"))
+            .collect();
+        if !missing.is_empty() {
+            return Err(format!(
+                "Repository lacks these prerequisite commits:\n{}",
+                missing.join("\n")
+            )
+            .into());
+        }
+
+        let objects = git_source::parse_pack(&bundle.pack, |sha| {
+            let sha = EncodedSha::from_str(sha).ok()?;
+            let data = self.obj_db.retrieve(&sha).ok()?;
+            let nul = memchr(0, &data)?;
+            let header = String::from_utf8_lossy(&data[..nul]);
+            let kind = GitKind::from_name(header.split(' ').next()?).ok()?;
+            Some((kind, data[nul + 1..].to_vec()))
+        })?;
+        let packed: HashSet<&str> = objects.iter().map(|(sha, _, _)| sha.as_str()).collect();
+        for (name, sha) in &bundle.refs {
+            if !packed.contains(sha.0.as_str()) && self.obj_db.retrieve(sha).is_err() {
+                return Err(format!("the bundle lacks {sha}, which {name} points at").into());
+            }
+        }
+        Ok(objects)
+    }
+
+    /// The full ref name a bundle records `rev` under
+    fn bundle_ref_name(&self, rev: &str) -> Option<String> {
+        if rev == HEAD_FILE {
+            Some(HEAD_FILE.to_string())
+        } else if self.load_branch(rev).is_some() {
+            Some(format!("{REFS_DIR}/{HEADS_DIR}/{rev}"))
+        } else if self.load_remote_ref(rev).is_some() {
+            let prefix = format!("{REFS_DIR}/{REMOTES_DIR}/");
+            Some(format!(
+                "{prefix}{}",
+                rev.strip_prefix(&prefix).unwrap_or(rev)
+            ))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::init_test_repo;
+    use tempfile::TempDir;
+
+    #[test]
+    fn bundles_carry_history_to_clones_and_fetches() {
+        let src_dir = TempDir::new().unwrap();
+        let src = init_test_repo(src_dir.path());
+        let file = src.dir.join("a.txt");
+        let commit_file = |content: &str| {
+            fs::write(&file, content).unwrap();
+            src.add(&vec![file.to_str().unwrap()]).unwrap();
+            src.commit(content).unwrap()
+        };
+        let first = commit_file("one\n");
+        let second = commit_file("two\n");
+
+        let out_dir = TempDir::new().unwrap();
+        let bundle_path = out_dir.path().join("repo.bundle");
+        assert_eq!(src.bundle_create::<&str>(&bundle_path, &[]).unwrap(), 6);
+        let bundle = src.bundle_verify(&bundle_path).unwrap();
+        assert_eq!(
+            bundle.refs,
+            [
+                ("HEAD".to_string(), second.clone()),
+                ("refs/heads/master".to_string(), second.clone())
+            ]
+        );
+        assert!(Bundle::is_bundle(&bundle_path));
+        assert!(!Bundle::is_bundle(&file));
+
+        // Cloning from a bundle checks out the branch its HEAD is on
+        let clone_path = out_dir.path().join("clone");
+        let clone = Repository::clone(&bundle_path, &clone_path).unwrap();
+        assert_eq!(clone.get_current_commit(), Some(second.clone()));
+        assert_eq!(
+            fs::read_to_string(clone_path.join("a.txt")).unwrap(),
+            "two\n"
+        );
+        assert!(clone.fsck_report().unwrap().is_ok());
+
+        // An incremental bundle needs its prerequisite, and fetching it
+        // moves the remote-tracking branch
+        let third = commit_file("three\n");
+        let range = format!("{second}..master");
+        assert_eq!(src.bundle_create(&bundle_path, &[range]).unwrap(), 3);
+        let bundle = Bundle::read(&bundle_path).unwrap();
+        assert_eq!(bundle.prerequisites[0].0, second);
+        let empty_dir = TempDir::new().unwrap();
+        let empty = init_test_repo(empty_dir.path());
+        assert!(empty.bundle_verify(&bundle_path).is_err());
+        clone.fetch("origin").unwrap();
+        assert_eq!(clone.resolve_commit("origin/master"), Ok(third));
+        assert!(clone.read_commit(&first).is_ok());
+
+        // A damaged pack is rejected
+        let mut data = fs::read(&bundle_path).unwrap();
+        let last = data.len() - 1;
+        data[last] ^= 1;
+        fs::write(&bundle_path, data).unwrap();
+        assert!(clone.bundle_unbundle(&bundle_path).is_err());
+    }
+}
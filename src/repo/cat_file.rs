@@ -0,0 +1,111 @@
+use memchr::memchr;
+
+use super::Repository;
+use super::ls_tree::format_tree_entry;
+use crate::object::{ObjectType, determine_object_type};
+use crate::{EncodedSha, Error};
+use std::str::FromStr;
+
+/// What [`Repository::cat_file`] prints about an object
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CatFileMode {
+    /// The object type, like `-t`
+    Type,
+    /// The size of the content in bytes, like `-s`
+    Size,
+    /// The content, with trees listed like `ls-tree`, like `-p`
+    Pretty,
+}
+
+impl Repository {
+    /// Reads an object from the object database and renders it like
+    /// `git cat-file`
+    ///
+    /// # Arguments
+    /// * `object` - A tag, which names the tag object itself, a revision,
+    ///   or the full SHA1 of any object
+    pub fn cat_file(&self, object: &str, mode: CatFileMode) -> Result<Vec<u8>, Error> {
+        let sha = match self.load_tag(object) {
+            Some(sha) => sha,
+            None => match self.resolve_commit(object) {
+                Ok(sha) => sha,
+                Err(_) => EncodedSha::from_str(object)
+                    .map_err(|_| Error::BadRevision(object.to_string()))?,
+            },
+        };
+        let data = self
+            .obj_db
+            .retrieve(&sha)
+            .map_err(|_| Error::BadRevision(object.to_string()))?;
+        let object_type = determine_object_type(&data)?;
+        let nul = memchr(0, &data).ok_or_else(|| format!("corrupt object {sha}"))?;
+        let content = &data[nul + 1..];
+        Ok(match mode {
+            CatFileMode::Type => format!("{object_type}\n").into_bytes(),
+            CatFileMode::Size => format!("{}\n", content.len()).into_bytes(),
+            CatFileMode::Pretty => match object_type {
+                ObjectType::Tree => self
+                    .read_tree_object(&sha)?
+                    .get_entries()
+                    .map(|(name, entry)| format_tree_entry(entry.mode, &entry.sha1, name) + "\n")
+                    .collect::<String>()
+                    .into_bytes(),
+                // Commit and tag messages are stored without a final newline
+                ObjectType::Commit | ObjectType::Tag => {
+                    let mut out = content.to_vec();
+                    out.push(b'\n');
+                    out
+                }
+                ObjectType::Blob => content.to_vec(),
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::init_test_repo;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn cat_file_prints_type_size_and_content() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        let file = repo.dir.join("a.txt");
+        fs::write(&file, "hello\n").unwrap();
+        repo.add(&vec![file.to_str().unwrap()]).unwrap();
+        let commit = repo.commit("first").unwrap();
+        let tree = repo.read_commit(&commit).unwrap().get_tree_sha();
+        let blob = repo
+            .read_tree(&tree)
+            .unwrap()
+            .get_sha1("a.txt")
+            .unwrap()
+            .clone();
+        let cat =
+            |object: &str, mode| String::from_utf8(repo.cat_file(object, mode).unwrap()).unwrap();
+
+        assert_eq!(cat(&blob.0, CatFileMode::Type), "blob\n");
+        assert_eq!(cat(&blob.0, CatFileMode::Size), "6\n");
+        assert_eq!(cat(&blob.0, CatFileMode::Pretty), "hello\n");
+        assert_eq!(cat(&tree.0, CatFileMode::Type), "tree\n");
+        assert_eq!(
+            cat(&tree.0, CatFileMode::Pretty),
+            format!("100644 blob {blob}\ta.txt\n")
+        );
+        assert_eq!(cat("HEAD", CatFileMode::Type), "commit\n");
+        let pretty = cat("HEAD", CatFileMode::Pretty);
+        assert!(pretty.starts_with(&format!("tree {tree}\nauthor Alice <")));
+        assert!(pretty.ends_with("\n\nfirst\n"));
+
+        repo.tag_create("v1", None, Some("one")).unwrap();
+        assert_eq!(cat("v1", CatFileMode::Type), "tag\n");
+        assert!(
+            cat("v1", CatFileMode::Pretty).starts_with(&format!("object {commit}\ntype commit\n"))
+        );
+        assert!(repo.cat_file("missing", CatFileMode::Type).is_err());
+        assert!(repo.cat_file(&"0".repeat(40), CatFileMode::Type).is_err());
+    }
+}
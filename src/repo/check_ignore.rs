@@ -0,0 +1,79 @@
+use super::Repository;
+use super::pathspec::normalize_pathspecs;
+use crate::Error;
+use crate::ignore::IgnoreMatch;
+
+impl Repository {
+    /// Explains which of `paths`, relative to the top of the working tree,
+    /// the `.gitignore` patterns apply to, like `git check-ignore -v`.
+    /// Tracked files are never ignored, so they are left out.
+    ///
+    /// # Returns
+    /// Every path a pattern matches, with the last matching pattern. A
+    /// pattern starting with `!` re-includes the path: see
+    /// [`IgnoreMatch::is_ignored`].
+    pub fn check_ignore<S: AsRef<str>>(
+        &self,
+        paths: &[S],
+    ) -> Result<Vec<(String, IgnoreMatch)>, Error> {
+        let index = self.load_index_or_empty()?;
+        let rules = self.ignore_rules();
+        let mut matches = Vec::new();
+        for path in normalize_pathspecs(paths) {
+            if index.get_sha1(&path).is_some() {
+                continue;
+            }
+            let is_dir = self.dir.join(&path).is_dir();
+            if let Some(pattern) = rules.explain(&path, is_dir) {
+                matches.push((path, pattern));
+            }
+        }
+        Ok(matches)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::repo::init_test_repo;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn check_ignore_names_the_deciding_pattern() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        let tracked = repo.dir.join("tracked.log");
+        fs::write(&tracked, "tracked before it was ignored\n").unwrap();
+        repo.add(&vec![tracked.to_str().unwrap()]).unwrap();
+        fs::create_dir_all(repo.dir.join("docs/build")).unwrap();
+        fs::write(repo.dir.join(".gitignore"), "# logs\n*.log\n!keep.log\n").unwrap();
+        fs::write(repo.dir.join("docs/.gitignore"), "build/\n").unwrap();
+
+        let paths = [
+            "debug.log",
+            "./src/keep.log",
+            "docs/build/out.html",
+            "docs/build",
+            "tracked.log",
+            "main.rs",
+        ];
+        let explained: Vec<String> = repo
+            .check_ignore(&paths)
+            .unwrap()
+            .into_iter()
+            .map(|(path, pattern)| format!("{pattern}\t{path}"))
+            .collect();
+        assert_eq!(
+            explained,
+            [
+                ".gitignore:2:*.log\tdebug.log",
+                ".gitignore:3:!keep.log\tsrc/keep.log",
+                "docs/.gitignore:1:build/\tdocs/build/out.html",
+                "docs/.gitignore:1:build/\tdocs/build",
+            ]
+        );
+        let matches = repo.check_ignore(&["src/keep.log", "a.log"]).unwrap();
+        assert!(!matches[0].1.is_ignored());
+        assert!(matches[1].1.is_ignored());
+    }
+}
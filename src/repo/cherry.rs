@@ -0,0 +1,146 @@
+use sha1::{Digest, Sha1};
+
+use super::{IndexDiffType, Repository};
+use crate::diff;
+use crate::index::Index;
+use crate::{EncodedSha, Error};
+use std::collections::HashSet;
+
+impl Repository {
+    /// Lists the commits of `head` (default `HEAD`) that are not in `upstream`,
+    /// oldest first, marking each with `-` when an equivalent change already
+    /// exists upstream and `+` otherwise.
+    ///
+    /// # Arguments
+    /// * `upstream` - Branch or commit the changes would be applied to
+    /// * `head` - Branch or commit holding the local changes
+    /// * `verbose` - Also print the subject line of each commit
+    pub fn cherry(&self, upstream: &str, head: Option<&str>, verbose: bool) -> Result<(), Error> {
+        let marks = self.cherry_marks(upstream, head.unwrap_or(super::HEAD_FILE))?;
+        for (sha, applied) in marks {
+            let sign = if applied { '-' } else { '+' };
+            if verbose {
+                let commit = self.read_commit(&sha)?;
+                let subject = commit.get_message().lines().next().unwrap_or("");
+                println!("{sign} {sha} {subject}");
+            } else {
+                println!("{sign} {sha}");
+            }
+        }
+        Ok(())
+    }
+
+    /// Computes the `cherry` marks: every commit reachable from `head` but not
+    /// from `upstream`, oldest first, paired with whether its patch-id matches
+    /// a commit reachable from `upstream` but not from `head`.
+    fn cherry_marks(&self, upstream: &str, head: &str) -> Result<Vec<(EncodedSha, bool)>, String> {
+        let upstream_sha = self.resolve_commit(upstream)?;
+        let head_sha = self.resolve_commit(head)?;
+
+        let upstream_commits: HashSet<EncodedSha> =
+            self.reachable_from([upstream_sha.clone()]).collect();
+        let head_commits: HashSet<EncodedSha> = self.reachable_from([head_sha.clone()]).collect();
+
+        let mut upstream_ids = HashSet::new();
+        for sha in self.reachable_from([upstream_sha]) {
+            if head_commits.contains(&sha) {
+                continue;
+            }
+            if let Some(id) = self.patch_id(&sha)? {
+                upstream_ids.insert(id);
+            }
+        }
+
+        let mut local: Vec<EncodedSha> = self
+            .reachable_from([head_sha])
+            .filter(|sha| !upstream_commits.contains(sha))
+            .collect();
+        local.reverse();
+
+        let mut marks = Vec::new();
+        for sha in local {
+            // Merge commits have no single patch and are skipped like git does
+            let Some(id) = self.patch_id(&sha)? else {
+                continue;
+            };
+            marks.push((sha, upstream_ids.contains(&id)));
+        }
+        Ok(marks)
+    }
+
+    /// Computes a stable identifier for the change a commit introduces relative
+    /// to its parent. Only the changed paths and the added/removed lines (with
+    /// whitespace stripped) contribute, so the same change applied on top of a
+    /// different base yields the same id.
+    ///
+    /// # Returns
+    /// - `Ok(None)` for merge commits
+    /// - `Ok(Some(id))` otherwise
+    fn patch_id(&self, commit_sha: &EncodedSha) -> Result<Option<EncodedSha>, String> {
+        let commit = self.read_commit(commit_sha)?;
+        let parent_index = match commit.get_parents().as_slice() {
+            [] => Index::new(),
+            [parent] => {
+                let parent = self.read_commit(parent)?;
+                self.read_tree(&parent.get_tree_sha())?
+            }
+            _ => return Ok(None),
+        };
+        let commit_index = self.read_tree(&commit.get_tree_sha())?;
+
+        let mut changed: Vec<(String, IndexDiffType)> = self
+            .diff_index(&parent_index, &commit_index)
+            .into_iter()
+            .filter(|(_, status)| *status != IndexDiffType::Unmodified)
+            .collect();
+        changed.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let mut hasher = Sha1::new();
+        for (path, _) in changed {
+            let old = self.blob_text(parent_index.get_sha1(&path))?;
+            let new = self.blob_text(commit_index.get_sha1(&path))?;
+            hasher.update(path.as_bytes());
+            hasher.update(b"\0");
+            let (old_lines, new_lines) = (diff::lines(&old), diff::lines(&new));
+            let changes = diff::diff_slices(&old_lines, &new_lines)
+                .into_iter()
+                .flat_map(|hunk| {
+                    let removed = old_lines[hunk.old].iter().map(|line| (b'-', *line));
+                    removed.chain(new_lines[hunk.new].iter().map(|line| (b'+', *line)))
+                });
+            for (sign, line) in changes {
+                let line: String = line.chars().filter(|c| !c.is_whitespace()).collect();
+                hasher.update([sign]);
+                hasher.update(line.as_bytes());
+                hasher.update(b"\n");
+            }
+        }
+        Ok(Some(EncodedSha(hex::encode(hasher.finalize()))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::repo::{commit_file, init_test_repo};
+    use tempfile::TempDir;
+
+    #[test]
+    fn marks_commits_already_upstream() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        commit_file(&repo, "base.txt", "base\n");
+        repo.branch("topic").unwrap();
+
+        // Upstream applies the same change to a.txt after an unrelated commit
+        commit_file(&repo, "other.txt", "other\n");
+        let up2 = commit_file(&repo, "a.txt", "shared change\n");
+
+        // Local history: same a.txt change plus a local-only change
+        repo.checkout("topic", false).unwrap();
+        let local1 = commit_file(&repo, "a.txt", "shared change\n");
+        let local2 = commit_file(&repo, "b.txt", "local only\n");
+
+        let marks = repo.cherry_marks(&up2.0, &local2.0).unwrap();
+        assert_eq!(marks, vec![(local1, true), (local2, false)]);
+    }
+}
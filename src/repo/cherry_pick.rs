@@ -0,0 +1,277 @@
+use super::revert::MERGE_MSG;
+use super::{Repository, ResetMode};
+use crate::{EncodedSha, Error};
+use std::fs;
+use std::io;
+use std::str::FromStr;
+
+/// Names the commit being picked while its conflicts are resolved
+pub(super) const CHERRY_PICK_HEAD: &str = "CHERRY_PICK_HEAD";
+/// Directory holding the state of a cherry-pick sequence: `head`, where
+/// HEAD was before it, and `todo`, the commits still to pick
+pub(super) const SEQUENCER_DIR: &str = "sequencer";
+/// Files inside [`SEQUENCER_DIR`]
+const ORIG_HEAD_FILE: &str = "head";
+const TODO_FILE: &str = "todo";
+
+impl Repository {
+    /// Replays the changes the commits `revs` introduced onto HEAD, like
+    /// `git cherry-pick`. Each rev is a commit or a range `A..B` of the
+    /// commits reachable from `B` but not from `A`, picked oldest first.
+    ///
+    /// The new commits keep the author of the picked ones, with the current
+    /// identity as committer. On conflicts the sequence stops with markers
+    /// in the conflicted files; resolve them, `add` them and call
+    /// [`Self::cherry_pick_continue`], or give up with
+    /// [`Self::cherry_pick_abort`].
+    ///
+    /// # Returns
+    /// - `Ok(true)`: Every commit was picked
+    /// - `Ok(false)`: The sequence stopped at conflicts
+    pub fn cherry_pick<S: AsRef<str>>(&self, revs: &[S]) -> Result<bool, Error> {
+        self.check_no_operation_in_progress()?;
+        let (head_sha, _) = self.clean_head()?;
+        self.identity()?;
+        let mut todo = Vec::new();
+        for rev in revs {
+            todo.extend(self.resolve_pick_range(rev.as_ref())?);
+        }
+        if todo.is_empty() {
+            return Err("empty commit set passed".into());
+        }
+
+        let sequencer = self.git_dir.join(SEQUENCER_DIR);
+        fs::create_dir_all(&sequencer)?;
+        fs::write(sequencer.join(ORIG_HEAD_FILE), format!("{head_sha}\n"))?;
+        self.save_todo(&todo)?;
+        self.run_sequencer()
+    }
+
+    /// Commits the resolved conflicts of the stopped cherry-pick and picks
+    /// the remaining commits of the sequence
+    pub fn cherry_pick_continue(&self) -> Result<bool, Error> {
+        let sequencer = self.git_dir.join(SEQUENCER_DIR);
+        if !sequencer.exists() {
+            return Err("no cherry-pick in progress".into());
+        }
+        // The stopped pick may have been concluded with `commit` already
+        if let Some(picked) = self.picked_commit() {
+            let head_sha = self
+                .get_current_commit()
+                .ok_or("HEAD does not point to a commit yet")?;
+            // A resolution that drops the whole change leaves nothing to commit
+            let tree = self.write_tree()?;
+            if tree != self.read_commit(&head_sha)?.get_tree_sha() {
+                let message = self.pending_message().unwrap_or_default();
+                self.commit_picked(&picked, tree, head_sha, &message)?;
+            }
+            self.clear_pick_state()?;
+        }
+        self.run_sequencer()
+    }
+
+    /// Abandons the cherry-pick sequence, resetting HEAD, the index and the
+    /// working tree to where they were before it started
+    pub fn cherry_pick_abort(&self) -> Result<(), Error> {
+        let head = fs::read_to_string(self.git_dir.join(SEQUENCER_DIR).join(ORIG_HEAD_FILE))
+            .map_err(|_| "no cherry-pick in progress")?;
+        self.reset(Some(head.trim()), ResetMode::Hard)
+    }
+
+    /// The commit a stopped cherry-pick was replaying
+    pub(super) fn picked_commit(&self) -> Option<EncodedSha> {
+        self.state_head(CHERRY_PICK_HEAD)
+    }
+
+    /// Forgets any revert or cherry-pick in progress, including the commits
+    /// a cherry-pick sequence still had to pick
+    pub(super) fn clear_sequencer(&self) -> io::Result<()> {
+        self.clear_pick_state()?;
+        match fs::remove_dir_all(self.git_dir.join(SEQUENCER_DIR)) {
+            Err(why) if why.kind() != io::ErrorKind::NotFound => Err(why),
+            _ => Ok(()),
+        }
+    }
+
+    /// Resolves a commit or a range such as `A..B` to the commits to pick,
+    /// oldest first. A missing side of a range defaults to HEAD.
+    fn resolve_pick_range(&self, rev: &str) -> Result<Vec<EncodedSha>, Error> {
+        if !rev.contains("..") {
+            return Ok(vec![self.resolve_commit(rev)?]);
+        }
+        let (include, exclude) = self.parse_rev_range(&[rev])?;
+        let mut commits: Vec<EncodedSha> = self
+            .walk_range(include, exclude)
+            .map(|(sha, _)| sha)
+            .collect();
+        commits.reverse();
+        Ok(commits)
+    }
+
+    /// Picks the commits in the todo list one after another until it is
+    /// empty or a pick stops at conflicts
+    fn run_sequencer(&self) -> Result<bool, Error> {
+        let mut todo = self.load_todo()?;
+        while !todo.is_empty() {
+            let sha = todo.remove(0);
+            self.save_todo(&todo)?;
+            if !self.pick(&sha)? {
+                return Ok(false);
+            }
+        }
+        self.clear_sequencer()?;
+        Ok(true)
+    }
+
+    /// Replays the change of `sha` onto HEAD and commits it
+    ///
+    /// # Returns
+    /// Whether the change applied without conflicts
+    fn pick(&self, sha: &EncodedSha) -> Result<bool, Error> {
+        let (head_sha, head_index) = self.clean_head()?;
+        let commit = self.read_commit(sha)?;
+        let parent_index = self.parent_index(sha, &commit)?;
+        let commit_index = self.read_tree(&commit.get_tree_sha())?;
+        let subject = commit.get_message().lines().next().unwrap_or_default();
+        let short = &sha.0[..7];
+
+        let label = format!("{short} ({subject})");
+        if !self.apply_change(&head_index, &parent_index, &commit_index, &label)? {
+            fs::write(self.git_dir.join(CHERRY_PICK_HEAD), format!("{sha}\n"))?;
+            fs::write(
+                self.git_dir.join(MERGE_MSG),
+                format!("{}\n", commit.get_message()),
+            )?;
+            println!("error: could not apply {short}... {subject}");
+            println!(
+                "hint: after resolving the conflicts, mark the corrected paths\n\
+                 hint: with 'rust-git add <paths>' and run 'rust-git cherry-pick --continue'\n\
+                 hint: or give up with 'rust-git cherry-pick --abort'"
+            );
+            return Ok(false);
+        }
+
+        let tree = self.write_tree()?;
+        if tree == self.read_commit(&head_sha)?.get_tree_sha() {
+            println!("skipping {short}... {subject}: its change is already in HEAD");
+            return Ok(true);
+        }
+        let commit_sha = self.commit_picked(sha, tree, head_sha, commit.get_message())?;
+        println!("[{}] {subject}", &commit_sha.0[..7]);
+        Ok(true)
+    }
+
+    /// Commits `tree` on top of `head_sha` as the replayed `picked` commit
+    fn commit_picked(
+        &self,
+        picked: &EncodedSha,
+        tree: EncodedSha,
+        head_sha: EncodedSha,
+        message: &str,
+    ) -> Result<EncodedSha, Error> {
+        let original = self.read_commit(picked)?;
+        let (name, email) = self.identity()?;
+        let commit_sha = self.commit_tree_with_author(
+            tree,
+            vec![head_sha.clone()],
+            message,
+            Some(original.get_author()),
+            &name,
+            &email,
+            self.gpg_sign(None)?,
+        )?;
+        let subject = message.lines().next().unwrap_or_default();
+        self.update_head(
+            Some(&head_sha),
+            &commit_sha,
+            &format!("cherry-pick: {subject}"),
+        )?;
+        Ok(commit_sha)
+    }
+
+    fn load_todo(&self) -> Result<Vec<EncodedSha>, Error> {
+        let todo = fs::read_to_string(self.git_dir.join(SEQUENCER_DIR).join(TODO_FILE))?;
+        todo.lines()
+            .map(|line| {
+                let sha = line.split(' ').nth(1).unwrap_or_default();
+                EncodedSha::from_str(sha).map_err(|_| format!("corrupt todo line: {line}").into())
+            })
+            .collect()
+    }
+
+    fn save_todo(&self, todo: &[EncodedSha]) -> Result<(), Error> {
+        let mut content = String::new();
+        for sha in todo {
+            let subject = self
+                .read_commit(sha)?
+                .get_message()
+                .lines()
+                .next()
+                .unwrap_or_default()
+                .to_string();
+            content.push_str(&format!("pick {sha} {subject}\n"));
+        }
+        fs::write(self.git_dir.join(SEQUENCER_DIR).join(TODO_FILE), content)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::{commit_file, init_test_repo};
+    use tempfile::TempDir;
+
+    #[test]
+    fn cherry_pick_replays_ranges_and_resumes_after_conflicts() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        let base = commit_file(&repo, "a.txt", "1\n2\n3\n");
+        repo.branch("topic").unwrap();
+        repo.checkout("topic", false).unwrap();
+        let b = commit_file(&repo, "b.txt", "b\n");
+        commit_file(&repo, "c.txt", "c\n");
+        let conflicting = commit_file(&repo, "a.txt", "1\ntwo\n3\n");
+        let d = commit_file(&repo, "d.txt", "d\n");
+        repo.checkout("master", false).unwrap();
+        commit_file(&repo, "a.txt", "1\nTWO\n3\n");
+
+        // Picked commits keep their author but get a new committer
+        repo.set_config("user.name", "Picker").unwrap();
+        assert!(repo.cherry_pick(&[format!("{base}..{b}")]).unwrap());
+        let head = repo.get_current_commit().unwrap();
+        let picked = repo.read_commit(&head).unwrap();
+        assert_eq!(picked.get_message(), "change b.txt");
+        assert_ne!(picked.get_author().get_name(), "Picker");
+        assert_eq!(picked.get_committer().get_name(), "Picker");
+
+        // The range stops at the conflicting commit
+        assert!(!repo.cherry_pick(&[format!("{b}..topic")]).unwrap());
+        assert!(repo.dir.join("c.txt").exists());
+        assert_eq!(repo.picked_commit(), Some(conflicting.clone()));
+        assert!(repo.cherry_pick(&[d.0.as_str()]).is_err());
+
+        let file = repo.dir.join("a.txt");
+        fs::write(&file, "1\nboth\n3\n").unwrap();
+        repo.add(&vec![file.to_str().unwrap()]).unwrap();
+        assert!(repo.cherry_pick_continue().unwrap());
+        assert!(repo.dir.join("d.txt").exists());
+        let head = repo.get_current_commit().unwrap();
+        let resolved = repo
+            .read_commit(&repo.read_commit(&head).unwrap().get_parents()[0])
+            .unwrap();
+        assert_eq!(resolved.get_message(), "change a.txt");
+        assert!(!repo.git_dir.join(SEQUENCER_DIR).exists());
+
+        // Aborting returns to where the sequence started
+        let start = repo.get_current_commit().unwrap();
+        repo.checkout("topic", false).unwrap();
+        commit_file(&repo, "a.txt", "1\nother\n3\n");
+        repo.checkout("master", false).unwrap();
+        assert!(!repo.cherry_pick(&["topic"]).unwrap());
+        repo.cherry_pick_abort().unwrap();
+        assert_eq!(repo.get_current_commit(), Some(start));
+        assert_eq!(fs::read_to_string(&file).unwrap(), "1\nboth\n3\n");
+        assert!(repo.picked_commit().is_none());
+    }
+}
@@ -0,0 +1,177 @@
+use walkdir::WalkDir;
+
+use super::Repository;
+use crate::Error;
+use crate::ignore::IgnoreRules;
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+impl Repository {
+    /// Removes untracked files from the working tree. Ignored files are kept
+    /// unless `remove_ignored`, and untracked directories, those holding no
+    /// tracked file, are left alone unless `directories`.
+    ///
+    /// # Arguments
+    /// * `dry_run` - Only print what would be removed
+    /// * `force` - Required to actually remove anything
+    /// * `directories` - Remove untracked directories too, like `clean -d`
+    /// * `remove_ignored` - Remove ignored files too, like `clean -x`
+    pub fn clean(
+        &self,
+        dry_run: bool,
+        force: bool,
+        directories: bool,
+        remove_ignored: bool,
+    ) -> Result<(), Error> {
+        if !dry_run && !force {
+            return Err("refusing to clean without -f or -n".into());
+        }
+        let mut tracked_dirs = HashSet::new();
+        for (path, _) in self.load_index_or_empty()?.collect_entries() {
+            let mut dir = Path::new(&path).parent();
+            while let Some(parent) = dir
+                && parent != Path::new("")
+            {
+                tracked_dirs.insert(parent.to_path_buf());
+                dir = parent.parent();
+            }
+        }
+
+        let mut reported_dirs = HashSet::new();
+        for name in self.untracked_files(remove_ignored)? {
+            // The outermost untracked directory holding the file, if any.
+            // Such a directory is reported once in place of its files.
+            let untracked_dir = Path::new(&name)
+                .ancestors()
+                .skip(1)
+                .filter(|dir| *dir != Path::new("") && !tracked_dirs.contains(*dir))
+                .last();
+            let report = match untracked_dir {
+                None => Some(name.clone()),
+                Some(_) if !directories => continue,
+                Some(dir) => {
+                    let dir = format!("{}/", dir.to_string_lossy());
+                    reported_dirs.insert(dir.clone()).then_some(dir)
+                }
+            };
+            if dry_run {
+                if let Some(report) = report {
+                    println!("Would remove {report}");
+                }
+                continue;
+            }
+            if let Err(why) = fs::remove_file(self.dir.join(&name)) {
+                println!("warning: failed to remove {name}: {why}");
+                continue;
+            }
+            if let Some(report) = report {
+                println!("Removing {report}");
+            }
+            // Prune the untracked directories emptied by this
+            let mut dir = Path::new(&name).parent();
+            while let Some(parent) = dir
+                && parent != Path::new("")
+                && !tracked_dirs.contains(parent)
+                && fs::remove_dir(self.dir.join(parent)).is_ok()
+            {
+                dir = parent.parent();
+            }
+        }
+        Ok(())
+    }
+
+    /// The ignore patterns of every `.gitignore` in the working tree
+    pub(super) fn ignore_rules(&self) -> IgnoreRules {
+        IgnoreRules::load(&self.dir, &self.git_dir)
+    }
+
+    /// Lists the files in the working tree that the index does not track,
+    /// sorted by path. Ignored files and the contents of ignored
+    /// directories are left out unless `include_ignored`.
+    pub(super) fn untracked_files(&self, include_ignored: bool) -> Result<Vec<String>, String> {
+        let index = self.load_index_or_empty()?;
+        let ignore = if include_ignored {
+            IgnoreRules::default()
+        } else {
+            self.ignore_rules()
+        };
+        let relative = |path: &Path| {
+            path.strip_prefix(&self.dir)
+                .unwrap_or(path)
+                .to_string_lossy()
+                .replace('\\', "/")
+        };
+        let mut untracked = Vec::new();
+        let walker = WalkDir::new(&self.dir)
+            .sort_by_file_name()
+            .into_iter()
+            .filter_entry(|entry| {
+                let name = relative(entry.path());
+                let is_dir = entry.file_type().is_dir();
+                // Nested repositories, submodules or not, keep their own files
+                entry.path() != self.git_dir
+                    && !(is_dir && self.is_submodule_dir(entry.path()))
+                    && (name.is_empty() || !ignore.is_ignored(&name, is_dir))
+            });
+        for entry in walker {
+            let entry = entry.map_err(|why| why.to_string())?;
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            let name = relative(entry.path());
+            if index.get_sha1(&name).is_none() && index.get_conflict(&name).is_none() {
+                untracked.push(name);
+            }
+        }
+        Ok(untracked)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::Index;
+    use crate::repo::init_test_repo;
+    use tempfile::TempDir;
+
+    #[test]
+    fn untracked_files_skip_ignored_paths() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        let write = |path: &str| {
+            let file = repo.dir.join(path);
+            fs::create_dir_all(file.parent().unwrap()).unwrap();
+            fs::write(&file, path).unwrap();
+        };
+        fs::write(repo.dir.join(".gitignore"), "target/\n*.log\n").unwrap();
+        write("src/lib.rs");
+        write("src/debug.log");
+        write("target/out");
+        write("notes.txt");
+        repo.add(&vec![repo.dir.to_str().unwrap()]).unwrap();
+        let index = Index::load(&repo.get_index_path()).unwrap();
+        assert!(index.get_sha1("src/lib.rs").is_some());
+        assert!(index.get_sha1("src/debug.log").is_none());
+        assert!(index.get_sha1("target/out").is_none());
+
+        write("src/new.rs");
+        write("src/new.log");
+        assert_eq!(repo.untracked_files(false).unwrap(), vec!["src/new.rs"]);
+        assert_eq!(
+            repo.untracked_files(true).unwrap(),
+            vec!["src/debug.log", "src/new.log", "src/new.rs", "target/out"]
+        );
+
+        repo.clean(false, true, false, false).unwrap();
+        assert!(!repo.dir.join("src/new.rs").exists());
+        assert!(repo.dir.join("src/new.log").exists());
+        // Untracked directories need -d
+        repo.clean(false, true, false, true).unwrap();
+        assert!(!repo.dir.join("src/new.log").exists());
+        assert!(repo.dir.join("target/out").exists());
+        repo.clean(false, true, true, true).unwrap();
+        assert!(!repo.dir.join("target").exists());
+        assert!(repo.dir.join("src/lib.rs").exists());
+    }
+}
@@ -0,0 +1,179 @@
+use super::{
+    Branch, Bundle, GIT_DIR, HEAD_FILE, HEADS_DIR, Head, OBJECTS_DIR, REFS_DIR, Repository,
+};
+use crate::Error;
+use crate::crypt::ENCRYPTION_FILE;
+use crate::index::Index;
+use std::fs;
+use std::path::{self, Path};
+
+/// Name of the remote a clone records its source as
+const ORIGIN: &str = "origin";
+
+impl Repository {
+    /// Creates a repository in `dst` from the local repository at `src`.
+    ///
+    /// Every branch and the objects it needs are copied, `src` is recorded as the `origin`
+    /// remote with its branches as remote-tracking refs, and the branch HEAD
+    /// of `src` is on is checked out into the working tree and the index. `dst` is created if needed and must be
+    /// empty otherwise. `src` may also be a bundle file, which must not
+    /// have prerequisites.
+    ///
+    /// # Returns
+    /// - `Ok(Repository)`: The new repository
+    /// - `Err(Error)`: `src` is no repository or `dst` is not empty; nothing
+    ///   is left behind in `dst`
+    pub fn clone(src: &Path, dst: &Path) -> Result<Repository, Error> {
+        let source = match Bundle::is_bundle(src) {
+            true => None,
+            false => Some(Repository::open(src)?),
+        };
+        if dst.exists() && fs::read_dir(dst)?.next().is_some() {
+            return Err(format!(
+                "destination path '{}' already exists and is not an empty directory",
+                dst.display()
+            )
+            .into());
+        }
+        fs::create_dir_all(dst)?;
+        let result = match &source {
+            Some(source) => Self::clone_from(source, dst),
+            None => Self::clone_bundle(src, dst),
+        };
+        if result.is_err() {
+            let _ = fs::remove_dir_all(dst.join(GIT_DIR));
+        }
+        result
+    }
+
+    fn clone_from(source: &Repository, dst: &Path) -> Result<Repository, Error> {
+        let head = source.get_head().ok_or("Failed to fetch head")?;
+        Self::init_with_branch(dst, &Self::initial_branch(&head))?;
+
+        // An encrypted store stays readable with the same key, since the
+        // object files are copied as they are
+        let marker = Path::new(OBJECTS_DIR).join(ENCRYPTION_FILE);
+        if source.git_dir.join(&marker).is_file() {
+            fs::copy(
+                source.git_dir.join(&marker),
+                dst.join(GIT_DIR).join(&marker),
+            )?;
+        }
+        let repo = Repository::open(dst)?;
+        let branches = source.list_branches();
+        let tips = branches
+            .iter()
+            .filter_map(|branch| branch.commit_sha.clone())
+            .chain(source.get_current_commit());
+        Self::copy_missing_objects(source, &repo, tips)?;
+        repo.finish_clone(&path::absolute(&source.dir)?, branches, head)?;
+        Ok(repo)
+    }
+
+    /// Clones from a bundle file; HEAD goes to the branch the HEAD of the
+    /// bundle points at, or the first branch if it has no HEAD
+    fn clone_bundle(src: &Path, dst: &Path) -> Result<Repository, Error> {
+        let bundle = Bundle::read(src)?;
+        let branches = bundle.branches();
+        let head_sha = bundle
+            .refs
+            .iter()
+            .find(|(name, _)| name == HEAD_FILE)
+            .map(|(_, sha)| sha.clone());
+        let on_branch = branches
+            .iter()
+            .find(|branch| head_sha.is_none() || branch.commit_sha == head_sha);
+        let head = match (on_branch, head_sha) {
+            (Some(branch), _) => {
+                Head::Symbolic(Path::new(REFS_DIR).join(HEADS_DIR).join(&branch.name))
+            }
+            (None, Some(sha)) => Head::Detached(sha),
+            (None, None) => return Err("the bundle holds no branch to clone".into()),
+        };
+
+        let repo = Self::init_with_branch(dst, &Self::initial_branch(&head))?;
+        repo.store_bundle(&bundle)?;
+        repo.finish_clone(&path::absolute(src)?, branches, head)?;
+        Ok(repo)
+    }
+
+    /// The branch a clone whose source has `head` starts on
+    fn initial_branch(head: &Head) -> String {
+        let heads = Path::new(REFS_DIR).join(HEADS_DIR);
+        match head {
+            Head::Symbolic(path) => path
+                .strip_prefix(&heads)
+                .unwrap_or(path)
+                .to_string_lossy()
+                .to_string(),
+            Head::Detached(_) => Self::default_branch_name(),
+        }
+    }
+
+    /// Records the branches of the source at `url` both as branches and as
+    /// remote-tracking refs of `origin`, points HEAD like `head` and checks
+    /// it out
+    fn finish_clone(&self, url: &Path, branches: Vec<Branch>, head: Head) -> Result<(), Error> {
+        let reason = format!("clone: from {}", url.display());
+        for branch in branches {
+            self.save_branch(&branch, &reason)?;
+            self.save_remote_ref(ORIGIN, &branch, &reason)?;
+        }
+        self.remote_add(ORIGIN, &url.to_string_lossy())?;
+        match head {
+            Head::Symbolic(_) => self.set_upstream(&Self::initial_branch(&head), ORIGIN)?,
+            Head::Detached(sha) => self.save_head(&Head::Detached(sha), &reason)?,
+        }
+
+        if let Some(commit_sha) = self.get_current_commit() {
+            let index = self.read_tree(&self.read_commit(&commit_sha)?.get_tree_sha())?;
+            self.update_worktree(&Index::new(), &index)?;
+            index.save(&self.get_index_path())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::init_test_repo;
+    use tempfile::TempDir;
+
+    #[test]
+    fn clone_copies_history_and_checks_out_head() {
+        let src_dir = TempDir::new().unwrap();
+        let src = init_test_repo(src_dir.path());
+        let file = src.dir.join("a.txt");
+        fs::write(&file, "hello").unwrap();
+        src.add(&vec![file.to_str().unwrap()]).unwrap();
+        let first = src.commit("first").unwrap();
+        src.branch("topic").unwrap();
+
+        let dst_dir = TempDir::new().unwrap();
+        let dst = dst_dir.path().join("copy");
+        let repo = Repository::clone(src_dir.path(), &dst).unwrap();
+        assert_eq!(repo.get_current_commit(), Some(first.clone()));
+        assert_eq!(
+            repo.load_branch("topic").unwrap().commit_sha,
+            Some(first.clone())
+        );
+        assert_eq!(repo.resolve_commit("origin/topic"), Ok(first));
+        assert_eq!(fs::read_to_string(dst.join("a.txt")).unwrap(), "hello");
+        assert!(
+            Index::load(&repo.get_index_path())
+                .unwrap()
+                .get_sha1("a.txt")
+                .is_some()
+        );
+        let url = path::absolute(src_dir.path()).unwrap();
+        assert_eq!(
+            repo.config().unwrap().get("remote.origin.url"),
+            Some(url.to_str().unwrap())
+        );
+
+        // The destination must be empty
+        assert!(Repository::clone(src_dir.path(), &dst).is_err());
+        assert!(dst.join("a.txt").exists());
+    }
+}
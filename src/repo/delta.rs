@@ -0,0 +1,210 @@
+use crate::EncodedSha;
+use std::fmt;
+use std::ops::Range;
+
+/// The changes between two snapshots, file by file in path order, as
+/// [`super::Repository::diff_tree_to_tree`] finds them. Displays as the
+/// unified diff of `git diff`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Diff {
+    pub deltas: Vec<FileDelta>,
+}
+
+impl Diff {
+    /// The changed files, in path order
+    pub fn iter(&self) -> std::slice::Iter<'_, FileDelta> {
+        self.deltas.iter()
+    }
+
+    /// The number of changed files
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    /// Whether no file changed
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+}
+
+impl IntoIterator for Diff {
+    type Item = FileDelta;
+    type IntoIter = std::vec::IntoIter<FileDelta>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.deltas.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Diff {
+    type Item = &'a FileDelta;
+    type IntoIter = std::slice::Iter<'a, FileDelta>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.deltas.iter()
+    }
+}
+
+impl fmt::Display for Diff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.deltas
+            .iter()
+            .try_for_each(|delta| write!(f, "{delta}"))
+    }
+}
+
+/// How a file changed between the sides of a [`Diff`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeltaStatus {
+    Added,
+    Deleted,
+    Modified,
+    /// The file took the place of a deleted one with a share of
+    /// `similarity` percent of its lines
+    Renamed {
+        similarity: u8,
+    },
+    /// The file was copied from another one with a share of `similarity`
+    /// percent of its lines
+    Copied {
+        similarity: u8,
+    },
+}
+
+/// One side of a [`FileDelta`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiffFile {
+    /// The path on this side; the source of a copy or rename on the old
+    /// side
+    pub path: String,
+    /// The content; `None` if the file is absent on this side
+    pub sha: Option<EncodedSha>,
+}
+
+/// A file that differs between the sides of a [`Diff`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileDelta {
+    pub old: DiffFile,
+    pub new: DiffFile,
+    pub status: DeltaStatus,
+    /// Whether a side contains NUL bytes; binary files have no hunks
+    pub binary: bool,
+    /// The changed lines with their context, in order
+    pub hunks: Vec<DiffHunk>,
+}
+
+impl fmt::Display for FileDelta {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (old_path, path) = (&self.old.path, &self.new.path);
+        writeln!(f, "diff --git a/{old_path} b/{path}")?;
+        match self.status {
+            DeltaStatus::Renamed { similarity } | DeltaStatus::Copied { similarity } => {
+                let how = match self.status {
+                    DeltaStatus::Renamed { .. } => "rename",
+                    _ => "copy",
+                };
+                write!(
+                    f,
+                    "similarity index {similarity}%\n{how} from {old_path}\n{how} to {path}\n"
+                )?;
+                // An exact copy has nothing more to show
+                if self.old.sha == self.new.sha {
+                    return Ok(());
+                }
+            }
+            DeltaStatus::Added => writeln!(f, "new file")?,
+            DeltaStatus::Deleted => writeln!(f, "deleted file")?,
+            DeltaStatus::Modified => (),
+        }
+        let short = |sha: &Option<EncodedSha>| match sha {
+            Some(sha) => sha.0[..7].to_string(),
+            None => "0000000".to_string(),
+        };
+        writeln!(
+            f,
+            "index {}..{}",
+            short(&self.old.sha),
+            short(&self.new.sha)
+        )?;
+        let old_name = match self.old.sha {
+            Some(_) => format!("a/{old_path}"),
+            None => "/dev/null".to_string(),
+        };
+        let new_name = match self.new.sha {
+            Some(_) => format!("b/{path}"),
+            None => "/dev/null".to_string(),
+        };
+        if self.binary {
+            return writeln!(f, "Binary files {old_name} and {new_name} differ");
+        }
+        if !self.hunks.is_empty() {
+            write!(f, "--- {old_name}\n+++ {new_name}\n")?;
+        }
+        self.hunks.iter().try_for_each(|hunk| write!(f, "{hunk}"))
+    }
+}
+
+/// A run of changed lines with the unchanged lines around them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiffHunk {
+    /// Indices of the old lines the hunk covers, from 0
+    pub old: Range<usize>,
+    /// Indices of the new lines the hunk covers, from 0
+    pub new: Range<usize>,
+    pub lines: Vec<DiffLine>,
+}
+
+impl fmt::Display for DiffHunk {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Lines count from 1; an empty range is given by the line before
+        let range = |range: &Range<usize>| match range.len() {
+            0 => format!("{},0", range.start),
+            1 => format!("{}", range.start + 1),
+            len => format!("{},{len}", range.start + 1),
+        };
+        writeln!(f, "@@ -{} +{} @@", range(&self.old), range(&self.new))?;
+        self.lines.iter().try_for_each(|line| write!(f, "{line}"))
+    }
+}
+
+/// Whether a line of a [`DiffHunk`] is on either side or only on one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineOrigin {
+    Context,
+    Addition,
+    Deletion,
+}
+
+impl LineOrigin {
+    /// The character the unified format prefixes the line with
+    pub fn sign(&self) -> char {
+        match self {
+            LineOrigin::Context => ' ',
+            LineOrigin::Addition => '+',
+            LineOrigin::Deletion => '-',
+        }
+    }
+}
+
+/// A line of a [`DiffHunk`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiffLine {
+    pub origin: LineOrigin,
+    /// The text of the line, with its terminator unless it is the last
+    /// line of a file without one
+    pub content: String,
+    /// The line number on the old side, from 1; `None` for an addition
+    pub old_lineno: Option<usize>,
+    /// The line number on the new side, from 1; `None` for a deletion
+    pub new_lineno: Option<usize>,
+}
+
+impl fmt::Display for DiffLine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", self.origin.sign(), self.content)?;
+        if !self.content.ends_with('\n') {
+            write!(f, "\n\\ No newline at end of file\n")?;
+        }
+        Ok(())
+    }
+}
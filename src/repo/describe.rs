@@ -0,0 +1,116 @@
+use std::cmp::Reverse;
+use std::collections::HashSet;
+
+use super::Repository;
+use crate::object::ObjectType;
+use crate::{EncodedSha, Error};
+
+/// Length of the abbreviated SHA1 in a description, as Git prints it by
+/// default
+const DESCRIBE_ABBREV_LEN: usize = 7;
+
+impl Repository {
+    /// Names HEAD after the nearest tag it descends from, like
+    /// `git describe`: the tag itself when HEAD is tagged, and
+    /// `<tag>-<n>-g<short sha>` otherwise, where `n` counts the commits
+    /// reachable from HEAD but not from the tag. Ties go to the tag on the
+    /// newest commit.
+    ///
+    /// # Arguments
+    /// * `all_tags` - Consider lightweight tags too, not only annotated ones
+    /// * `dirty` - Append `-dirty` when the index or the tracked files
+    ///   differ from HEAD
+    pub fn describe(&self, all_tags: bool, dirty: bool) -> Result<String, Error> {
+        let head = self
+            .get_current_commit()
+            .ok_or("cannot describe: HEAD does not point to a commit yet")?;
+        let history: HashSet<EncodedSha> = self.reachable_from([head.clone()]).collect();
+
+        let mut best = None;
+        for (name, sha) in self.list_tags()? {
+            if !all_tags && self.read_tag(&sha).is_none() {
+                continue;
+            }
+            let (commit, ObjectType::Commit) = self.peel_tag(&sha) else {
+                continue;
+            };
+            if !history.contains(&commit) {
+                continue;
+            }
+            // The tagged history lies within HEAD's, so the rest is the distance
+            let distance = history.len() - self.reachable_from([commit.clone()]).count();
+            let date = self.read_commit(&commit)?.get_committer().get_timestamp();
+            let key = (distance, Reverse(date));
+            if best.as_ref().is_none_or(|(best_key, _)| key < *best_key) {
+                best = Some((key, name));
+            }
+        }
+        let Some(((distance, _), name)) = best else {
+            return Err(if all_tags {
+                format!("No tags can describe '{head}'.")
+            } else {
+                format!(
+                    "No annotated tags can describe '{head}'.\n\
+                     However, there were unannotated tags: try --tags."
+                )
+            }
+            .into());
+        };
+
+        let mut description = match distance {
+            0 => name,
+            _ => format!("{name}-{distance}-g{}", &head.0[..DESCRIBE_ABBREV_LEN]),
+        };
+        if dirty && !self.worktree_is_clean(&self.commit_index(Some(&head))?)? {
+            description.push_str("-dirty");
+        }
+        Ok(description)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::init_test_repo;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn describe_names_head_after_the_nearest_tag() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        let file = repo.dir.join("a.txt");
+        let commit_file = |content: &str| {
+            fs::write(&file, content).unwrap();
+            repo.add(&vec![file.to_str().unwrap()]).unwrap();
+            repo.commit(content).unwrap()
+        };
+        commit_file("one\n");
+        assert!(repo.describe(true, false).is_err());
+        repo.tag_create("v1.0", None, Some("Release 1.0")).unwrap();
+        assert_eq!(repo.describe(false, false).unwrap(), "v1.0");
+
+        commit_file("two\n");
+        repo.tag_create("snapshot", None, None).unwrap();
+        let head = commit_file("three\n");
+        let short = &head.0[..DESCRIBE_ABBREV_LEN];
+        assert_eq!(
+            repo.describe(false, false).unwrap(),
+            format!("v1.0-2-g{short}")
+        );
+        assert_eq!(
+            repo.describe(true, false).unwrap(),
+            format!("snapshot-1-g{short}")
+        );
+
+        fs::write(&file, "changed\n").unwrap();
+        assert_eq!(
+            repo.describe(true, true).unwrap(),
+            format!("snapshot-1-g{short}-dirty")
+        );
+        assert_eq!(
+            repo.describe(true, false).unwrap(),
+            format!("snapshot-1-g{short}")
+        );
+    }
+}
@@ -0,0 +1,1023 @@
+use similar::{Algorithm, ChangeTag, DiffOp, capture_diff_slices, group_diff_ops};
+
+use super::Repository;
+use super::delta::{DeltaStatus, Diff, DiffFile, DiffHunk, DiffLine, FileDelta, LineOrigin};
+use super::histogram::histogram_diff;
+use super::history::RENAME_SIMILARITY;
+use super::pathspec::{matches_pathspec, normalize_pathspecs};
+use super::rev_list::or_head;
+use crate::diff::{self as engine, line_similarity};
+use crate::index::{FileStat, Index};
+use crate::object::{Blob, Commit, FileMode, Object};
+use crate::{EncodedSha, Error};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::str::FromStr;
+
+/// Directories with a smaller share of the changed lines are folded into
+/// their parent, as `git diff --dirstat` does by default
+pub const DIRSTAT_THRESHOLD: f64 = 3.0;
+
+/// Lines of unchanged context around each hunk of `diff` output
+pub const DIFF_CONTEXT: usize = 3;
+
+/// One side of a comparison
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Snapshot<'a> {
+    /// Files in the working tree, limited to those tracked by the index
+    Worktree,
+    /// The staging area
+    Index,
+    /// The tree of a commit, given as `HEAD`, a branch or a commit SHA1
+    Commit(&'a str),
+}
+
+/// Which files of the old side added files are compared with to find
+/// where they were copied from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CopyDetection {
+    /// Added files are reported as new
+    #[default]
+    Off,
+    /// Files changed or deleted in the same comparison, like `-C`. An
+    /// added file similar to a deleted one is reported as a rename.
+    Changed,
+    /// Every file of the old side, like `--find-copies-harder`
+    All,
+}
+
+/// How the lines of two versions of a file are matched up
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DiffAlgorithm {
+    /// The shortest edit script, as Myers finds it
+    #[default]
+    Myers,
+    /// Lines occurring once on both sides are matched first, which keeps
+    /// reordered code in readable hunks
+    Patience,
+    /// Like patience, but the rarest lines are matched first even if they
+    /// are not unique, which is faster on large files
+    Histogram,
+}
+
+impl FromStr for DiffAlgorithm {
+    type Err = String;
+
+    /// Parses the names `--diff-algorithm` and `diff.algorithm` take
+    fn from_str(name: &str) -> Result<Self, String> {
+        match name {
+            "myers" | "default" => Ok(DiffAlgorithm::Myers),
+            "patience" => Ok(DiffAlgorithm::Patience),
+            "histogram" => Ok(DiffAlgorithm::Histogram),
+            name => Err(format!("unknown diff algorithm '{name}'")),
+        }
+    }
+}
+
+/// Options of [`Repository::diff_with`]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DiffOptions {
+    /// Report added files as copies or renames of similar files, like
+    /// `-C`
+    pub copies: CopyDetection,
+    /// How to match up lines, like `--diff-algorithm`; defaults to
+    /// `diff.algorithm`
+    pub algorithm: Option<DiffAlgorithm>,
+}
+
+/// A path whose content differs between two snapshots
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct FilePair {
+    pub path: String,
+    /// Content on the old side; `None` if the file was added
+    pub old: Option<EncodedSha>,
+    /// Content on the new side; `None` if the file was deleted
+    pub new: Option<EncodedSha>,
+    /// The file of the old side that `path` was copied or renamed from,
+    /// whose content `old` then is
+    pub source: Option<CopySource>,
+}
+
+/// Where a file was copied or renamed from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct CopySource {
+    pub path: String,
+    /// Share of the lines both files have, in percent
+    pub similarity: u8,
+    /// Whether the source file no longer exists
+    pub renamed: bool,
+}
+
+impl FilePair {
+    /// The status letter of `diff --name-status`, with the similarity of
+    /// copies and renames, like `M` or `C075`
+    fn status(&self) -> String {
+        match (&self.source, &self.old, &self.new) {
+            (Some(source), ..) if source.renamed => format!("R{:03}", source.similarity),
+            (Some(source), ..) => format!("C{:03}", source.similarity),
+            (None, None, _) => "A".to_string(),
+            (None, _, None) => "D".to_string(),
+            (None, Some(_), Some(_)) => "M".to_string(),
+        }
+    }
+}
+
+/// Files of a snapshot, with the content of each still to be read
+pub(super) struct SnapshotFiles {
+    pub entries: BTreeMap<String, EncodedSha>,
+    /// Paths of the entries that are submodules, recorded by commit
+    gitlinks: BTreeSet<String>,
+    /// Whether content is read from the working tree instead of the
+    /// object database
+    pub worktree: bool,
+}
+
+impl SnapshotFiles {
+    /// The files of a tree or the staging area
+    fn from_index(index: &Index) -> SnapshotFiles {
+        let entries: BTreeMap<String, EncodedSha> = index.collect_entries().into_iter().collect();
+        let gitlinks = entries
+            .keys()
+            .filter(|path| index.get_mode(path) == Some(FileMode::Gitlink))
+            .cloned()
+            .collect();
+        SnapshotFiles {
+            entries,
+            gitlinks,
+            worktree: false,
+        }
+    }
+}
+
+impl Repository {
+    /// Prints the changes between two snapshots as a unified diff, limited
+    /// to the paths matching `paths` if any are given.
+    pub fn diff<S: AsRef<str>>(
+        &self,
+        old: Snapshot,
+        new: Snapshot,
+        paths: &[S],
+    ) -> Result<(), Error> {
+        self.diff_with(old, new, paths, &DiffOptions::default())
+    }
+
+    /// [`Self::diff`] with options, such as the detection of copies
+    pub fn diff_with<S: AsRef<str>>(
+        &self,
+        old: Snapshot,
+        new: Snapshot,
+        paths: &[S],
+        options: &DiffOptions,
+    ) -> Result<(), Error> {
+        print!("{}", self.unified_diff_with(old, new, paths, options)?);
+        Ok(())
+    }
+
+    /// Prints the changed files between two snapshots with a status letter
+    /// each, like `git diff --name-status`: `A`dded, `D`eleted, `M`odified,
+    /// or `R` for renamed and `C` for copied followed by the similarity in
+    /// percent and the source path
+    pub fn diff_name_status<S: AsRef<str>>(
+        &self,
+        old: Snapshot,
+        new: Snapshot,
+        paths: &[S],
+        options: &DiffOptions,
+    ) -> Result<(), Error> {
+        let pathspecs = normalize_pathspecs(paths);
+        let old = self.snapshot_files(old)?;
+        let new = self.snapshot_files(new)?;
+        for pair in self.diff_pairs(&old, &new, options)? {
+            if !matches_pathspec(&pair.path, &pathspecs) {
+                continue;
+            }
+            match &pair.source {
+                Some(source) => println!("{}\t{}\t{}", pair.status(), source.path, pair.path),
+                None => println!("{}\t{}", pair.status(), pair.path),
+            }
+        }
+        Ok(())
+    }
+
+    /// The commits `diff <a>..<b>` and `diff <a>...<b>` compare: `a` and
+    /// `b` for two dots; for three dots the merge base of `a` and `b`, and
+    /// `b`, to show what `b` changed since the two forked. An empty side
+    /// means HEAD.
+    ///
+    /// # Returns
+    /// `None` if `rev` is not a range
+    pub fn diff_range(&self, rev: &str) -> Result<Option<(EncodedSha, EncodedSha)>, Error> {
+        if let Some((lhs, rhs)) = rev.split_once("...") {
+            let lhs = self.resolve(or_head(lhs))?;
+            let rhs = self.resolve(or_head(rhs))?;
+            let base = self
+                .find_lca(&lhs, &rhs)
+                .ok_or_else(|| format!("{rev}: no merge base"))?;
+            Ok(Some((base, rhs)))
+        } else if let Some((from, to)) = rev.split_once("..") {
+            Ok(Some((
+                self.resolve(or_head(from))?,
+                self.resolve(or_head(to))?,
+            )))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Renders the changes between two snapshots in the unified format of
+    /// `git diff`, file by file in path order. Files containing NUL bytes
+    /// are reported as binary instead of being compared line by line.
+    pub fn unified_diff<S: AsRef<str>>(
+        &self,
+        old: Snapshot,
+        new: Snapshot,
+        paths: &[S],
+    ) -> Result<String, Error> {
+        self.unified_diff_with(old, new, paths, &DiffOptions::default())
+    }
+
+    /// [`Self::unified_diff`] with options, such as the detection of
+    /// copies
+    pub fn unified_diff_with<S: AsRef<str>>(
+        &self,
+        old: Snapshot,
+        new: Snapshot,
+        paths: &[S],
+        options: &DiffOptions,
+    ) -> Result<String, Error> {
+        let pathspecs = normalize_pathspecs(paths);
+        let old = self.snapshot_files(old)?;
+        let new = self.snapshot_files(new)?;
+        Ok(self.render_unified_diff(&old, &new, &pathspecs, options)?)
+    }
+
+    /// Renders the patch a commit introduced relative to its first parent,
+    /// or relative to an empty tree for a root commit
+    pub(super) fn commit_patch(&self, commit: &Commit) -> Result<String, String> {
+        let (old, new) = self.commit_sides(commit)?;
+        self.render_unified_diff(&old, &new, &[], &DiffOptions::default())
+    }
+
+    /// Counts the lines a commit inserted and deleted in every file it
+    /// changed, relative to its first parent, sorted by path
+    pub(super) fn commit_diffstat(
+        &self,
+        commit: &Commit,
+    ) -> Result<Vec<(String, usize, usize)>, String> {
+        let (old, new) = self.commit_sides(commit)?;
+        let mut stats = Vec::new();
+        for pair in diff_files(&old, &new) {
+            let old_text = self.snapshot_text(&old, &pair.path, pair.old.as_ref())?;
+            let new_text = self.snapshot_text(&new, &pair.path, pair.new.as_ref())?;
+            let hunks = engine::diff_lines(&old_text, &new_text);
+            stats.push((
+                pair.path,
+                hunks.iter().map(|hunk| hunk.new.len()).sum(),
+                hunks.iter().map(|hunk| hunk.old.len()).sum(),
+            ));
+        }
+        Ok(stats)
+    }
+
+    /// The files of a commit's first parent, none for a root commit, and
+    /// of the commit itself
+    pub(super) fn commit_sides(
+        &self,
+        commit: &Commit,
+    ) -> Result<(SnapshotFiles, SnapshotFiles), String> {
+        let tree_files = |tree: &EncodedSha| -> Result<SnapshotFiles, String> {
+            Ok(SnapshotFiles::from_index(&self.read_tree(tree)?))
+        };
+        let old = match commit.get_parents().first() {
+            Some(parent) => tree_files(&self.read_commit(parent)?.get_tree_sha())?,
+            None => SnapshotFiles::from_index(&Index::new()),
+        };
+        Ok((old, tree_files(&commit.get_tree_sha())?))
+    }
+
+    /// The changes between two trees, e.g. of two commits, file by file;
+    /// `None` stands for the empty tree
+    pub fn diff_tree_to_tree(
+        &self,
+        old: Option<&EncodedSha>,
+        new: Option<&EncodedSha>,
+        options: &DiffOptions,
+    ) -> Result<Diff, Error> {
+        let tree_files = |tree: Option<&EncodedSha>| -> Result<SnapshotFiles, String> {
+            match tree {
+                Some(tree) => Ok(SnapshotFiles::from_index(&self.read_tree(tree)?)),
+                None => Ok(SnapshotFiles::from_index(&Index::new())),
+            }
+        };
+        let (old, new) = (tree_files(old)?, tree_files(new)?);
+        Ok(self.snapshot_diff(&old, &new, &[], options)?)
+    }
+
+    /// The changes in the working tree that are not staged, file by file
+    pub fn diff_index_to_workdir(&self, options: &DiffOptions) -> Result<Diff, Error> {
+        let old = self.snapshot_files(Snapshot::Index)?;
+        let new = self.snapshot_files(Snapshot::Worktree)?;
+        Ok(self.snapshot_diff(&old, &new, &[], options)?)
+    }
+
+    fn render_unified_diff(
+        &self,
+        old: &SnapshotFiles,
+        new: &SnapshotFiles,
+        pathspecs: &[String],
+        options: &DiffOptions,
+    ) -> Result<String, String> {
+        Ok(self
+            .snapshot_diff(old, new, pathspecs, options)?
+            .to_string())
+    }
+
+    /// The changes between two snapshots of the files matching
+    /// `pathspecs`, with their lines compared unless they are binary
+    fn snapshot_diff(
+        &self,
+        old: &SnapshotFiles,
+        new: &SnapshotFiles,
+        pathspecs: &[String],
+        options: &DiffOptions,
+    ) -> Result<Diff, String> {
+        let algorithm = match options.algorithm {
+            Some(algorithm) => algorithm,
+            None => self.diff_algorithm().map_err(|why| why.to_string())?,
+        };
+        let mut deltas = Vec::new();
+        for pair in self.diff_pairs(old, new, options)? {
+            if !matches_pathspec(&pair.path, pathspecs) {
+                continue;
+            }
+            let old_path = pair
+                .source
+                .as_ref()
+                .map_or(&pair.path, |source| &source.path);
+            let status = match (&pair.source, &pair.old, &pair.new) {
+                (Some(source), ..) if source.renamed => DeltaStatus::Renamed {
+                    similarity: source.similarity,
+                },
+                (Some(source), ..) => DeltaStatus::Copied {
+                    similarity: source.similarity,
+                },
+                (None, None, _) => DeltaStatus::Added,
+                (None, _, None) => DeltaStatus::Deleted,
+                (None, Some(_), Some(_)) => DeltaStatus::Modified,
+            };
+            let mut delta = FileDelta {
+                old: DiffFile {
+                    path: old_path.clone(),
+                    sha: pair.old.clone(),
+                },
+                new: DiffFile {
+                    path: pair.path.clone(),
+                    sha: pair.new.clone(),
+                },
+                status,
+                binary: false,
+                hunks: Vec::new(),
+            };
+            // An exact copy has no lines to compare
+            if pair.old != pair.new {
+                let old_text = self.snapshot_text(old, old_path, pair.old.as_ref())?;
+                let new_text = self.snapshot_text(new, &pair.path, pair.new.as_ref())?;
+                if old_text.contains('\0') || new_text.contains('\0') {
+                    delta.binary = true;
+                } else {
+                    let old_lines: Vec<&str> = old_text.split_inclusive('\n').collect();
+                    let new_lines: Vec<&str> = new_text.split_inclusive('\n').collect();
+                    delta.hunks = diff_hunks(&old_lines, &new_lines, algorithm);
+                }
+            }
+            deltas.push(delta);
+        }
+        Ok(Diff { deltas })
+    }
+
+    /// The algorithm `diff.algorithm` chooses, Myers by default
+    fn diff_algorithm(&self) -> Result<DiffAlgorithm, Error> {
+        match self.config()?.get("diff.algorithm") {
+            Some(name) => Ok(name.parse()?),
+            None => Ok(DiffAlgorithm::Myers),
+        }
+    }
+
+    /// The files that differ between two snapshots, with added files
+    /// paired up with the files they were copied or renamed from as
+    /// `options` asks
+    fn diff_pairs(
+        &self,
+        old: &SnapshotFiles,
+        new: &SnapshotFiles,
+        options: &DiffOptions,
+    ) -> Result<Vec<FilePair>, String> {
+        let mut pairs = diff_files(old, new);
+        if options.copies == CopyDetection::Off {
+            return Ok(pairs);
+        }
+        // Candidate sources, with whether they were deleted
+        let mut sources: Vec<(String, EncodedSha, bool)> = pairs
+            .iter()
+            .filter_map(|pair| Some((pair.path.clone(), pair.old.clone()?, pair.new.is_none())))
+            .collect();
+        if options.copies == CopyDetection::All {
+            sources.extend(
+                old.entries
+                    .iter()
+                    .filter(|(path, sha)| new.entries.get(*path) == Some(*sha))
+                    .map(|(path, sha)| (path.clone(), sha.clone(), false)),
+            );
+        }
+        let mut renamed = BTreeSet::new();
+        for pair in pairs.iter_mut().filter(|pair| pair.old.is_none()) {
+            let Some(sha) = &pair.new else {
+                continue;
+            };
+            let best = match sources.iter().find(|(_, source, _)| source == sha) {
+                Some((path, _, deleted)) => Some((1.0, path, deleted)),
+                None => {
+                    let text = self.snapshot_text(new, &pair.path, Some(sha))?;
+                    let mut best: Option<(f32, &String, &bool)> = None;
+                    for (path, source, deleted) in &sources {
+                        let old_text = self.snapshot_text(old, path, Some(source))?;
+                        let ratio = line_similarity(&old_text, &text);
+                        if ratio >= RENAME_SIMILARITY
+                            && best.as_ref().is_none_or(|(best, ..)| ratio > *best)
+                        {
+                            best = Some((ratio, path, deleted));
+                        }
+                    }
+                    best
+                }
+            };
+            let Some((ratio, path, deleted)) = best else {
+                continue;
+            };
+            // The first file taking the place of a deleted one is a rename,
+            // any other a copy
+            let renamed_now = *deleted && renamed.insert(path.clone());
+            pair.old = old.entries.get(path).cloned();
+            pair.source = Some(CopySource {
+                path: path.clone(),
+                similarity: (ratio * 100.0).floor() as u8,
+                renamed: renamed_now,
+            });
+        }
+        pairs.retain(|pair| pair.new.is_some() || !renamed.contains(&pair.path));
+        Ok(pairs)
+    }
+
+    /// Shows how the changed lines between two snapshots are spread over
+    /// directories, in the spirit of `diff --dirstat=lines`.
+    pub fn diff_dirstat<S: AsRef<str>>(
+        &self,
+        old: Snapshot,
+        new: Snapshot,
+        paths: &[S],
+    ) -> Result<(), Error> {
+        for (dir, percent) in self.dirstat(old, new, paths, DIRSTAT_THRESHOLD)? {
+            println!("{percent:6.1}% {dir}");
+        }
+        Ok(())
+    }
+
+    /// Aggregates the lines added and removed between two snapshots per
+    /// directory. Only paths matching `paths` count, if any are given.
+    ///
+    /// Changes count towards the directory holding the file. A directory
+    /// is reported when its share, including sub directories that were not
+    /// reported themselves, reaches `threshold` percent; otherwise its
+    /// changes are added to its parent. The top level is reported as `./`.
+    ///
+    /// # Returns
+    /// - `Ok(Vec<(String, f64)>)`: Directories with a trailing `/` and
+    ///   their percentage of all changed lines, sorted by path
+    /// - `Err(Error)`: A snapshot or blob could not be read
+    pub fn dirstat<S: AsRef<str>>(
+        &self,
+        old: Snapshot,
+        new: Snapshot,
+        paths: &[S],
+        threshold: f64,
+    ) -> Result<Vec<(String, f64)>, Error> {
+        let pathspecs = normalize_pathspecs(paths);
+        let old = self.snapshot_files(old)?;
+        let new = self.snapshot_files(new)?;
+        let mut changes: BTreeMap<String, usize> = BTreeMap::new();
+        for pair in diff_files(&old, &new) {
+            if !matches_pathspec(&pair.path, &pathspecs) {
+                continue;
+            }
+            let old_text = self.snapshot_text(&old, &pair.path, pair.old.as_ref())?;
+            let new_text = self.snapshot_text(&new, &pair.path, pair.new.as_ref())?;
+            let changed: usize = engine::diff_lines(&old_text, &new_text)
+                .iter()
+                .map(|hunk| hunk.old.len() + hunk.new.len())
+                .sum();
+            let dir = match pair.path.rfind('/') {
+                Some(end) => pair.path[..=end].to_string(),
+                None => String::new(),
+            };
+            *changes.entry(dir).or_default() += changed;
+        }
+        let total: usize = changes.values().sum();
+        if total == 0 {
+            return Ok(Vec::new());
+        }
+
+        // Visit the deepest directories first so that what they do not
+        // report is passed on to their parents
+        let mut dirs: BTreeSet<String> = BTreeSet::new();
+        for dir in changes.keys() {
+            let mut dir = dir.as_str();
+            loop {
+                dirs.insert(dir.to_string());
+                match dir[..dir.len().saturating_sub(1)].rfind('/') {
+                    Some(end) => dir = &dir[..=end],
+                    None if dir.is_empty() => break,
+                    None => dir = "",
+                }
+            }
+        }
+        let mut dirs: Vec<String> = dirs.into_iter().collect();
+        dirs.sort_by_key(|dir| std::cmp::Reverse(dir.matches('/').count()));
+
+        let mut reported = Vec::new();
+        for dir in dirs {
+            let lines = changes.remove(&dir).unwrap_or_default();
+            let percent = lines as f64 * 100.0 / total as f64;
+            if lines > 0 && (percent >= threshold || dir.is_empty()) {
+                let name = if dir.is_empty() {
+                    "./".to_string()
+                } else {
+                    dir
+                };
+                reported.push((name, percent));
+            } else if !dir.is_empty() {
+                let parent = match dir[..dir.len() - 1].rfind('/') {
+                    Some(end) => dir[..=end].to_string(),
+                    None => String::new(),
+                };
+                *changes.entry(parent).or_default() += lines;
+            }
+        }
+        reported.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(reported)
+    }
+
+    /// Lists the files of a snapshot with the SHA1 of their content
+    pub(super) fn snapshot_files(&self, snapshot: Snapshot) -> Result<SnapshotFiles, String> {
+        let index = Index::load(&self.get_index_path())?;
+        match snapshot {
+            Snapshot::Index => Ok(SnapshotFiles::from_index(&index)),
+            Snapshot::Commit(rev) => {
+                let commit = self.read_commit(&self.resolve_commit(rev)?)?;
+                let tree = self.read_tree(&commit.get_tree_sha())?;
+                Ok(SnapshotFiles::from_index(&tree))
+            }
+            Snapshot::Worktree => {
+                let mut entries = BTreeMap::new();
+                let mut gitlinks = BTreeSet::new();
+                for (path, _) in index.collect_entries() {
+                    // A submodule is at the commit checked out in it
+                    if index.get_mode(&path) == Some(FileMode::Gitlink) {
+                        if let Some(head) = self.submodule_head(&path) {
+                            entries.insert(path.clone(), head);
+                        }
+                        gitlinks.insert(path);
+                        continue;
+                    }
+                    let file = self.dir.join(&path);
+                    // Deleted files are simply absent from this side
+                    let Ok(metadata) = fs::metadata(&file) else {
+                        continue;
+                    };
+                    if !metadata.is_file() {
+                        continue;
+                    }
+                    // Files whose stat data is unchanged need not be read
+                    let sha = if index.get_stat(&path) == Some(FileStat::from_metadata(&metadata)) {
+                        index.get_sha1(&path).unwrap().clone()
+                    } else {
+                        EncodedSha(Blob::new(&file)?.encoded_sha1())
+                    };
+                    entries.insert(path, sha);
+                }
+                Ok(SnapshotFiles {
+                    entries,
+                    gitlinks,
+                    worktree: true,
+                })
+            }
+        }
+    }
+
+    /// Reads a file of a snapshot as text; an absent file reads as empty.
+    /// A submodule reads as the commit it is at, as `git diff` shows it.
+    pub(super) fn snapshot_text(
+        &self,
+        files: &SnapshotFiles,
+        path: &str,
+        sha: Option<&EncodedSha>,
+    ) -> Result<String, String> {
+        let data = self.snapshot_bytes(files, path, sha)?;
+        Ok(String::from_utf8_lossy(&data).into_owned())
+    }
+
+    /// [`Self::snapshot_text`] without decoding the content
+    pub(super) fn snapshot_bytes(
+        &self,
+        files: &SnapshotFiles,
+        path: &str,
+        sha: Option<&EncodedSha>,
+    ) -> Result<Vec<u8>, String> {
+        if files.gitlinks.contains(path) {
+            return Ok(sha
+                .map(|sha| format!("Subproject commit {sha}\n").into_bytes())
+                .unwrap_or_default());
+        }
+        if files.worktree && sha.is_some() {
+            return fs::read(self.dir.join(path)).map_err(|why| why.to_string());
+        }
+        self.blob_bytes(sha)
+    }
+}
+
+/// Diffs two versions of a file by lines with `algorithm` and groups the
+/// changes into hunks with [`DIFF_CONTEXT`] lines of context. The lines
+/// keep their line terminator.
+fn diff_hunks(old: &[&str], new: &[&str], algorithm: DiffAlgorithm) -> Vec<DiffHunk> {
+    let ops: Vec<DiffOp> = match algorithm {
+        DiffAlgorithm::Myers => hunk_ops(&engine::diff_slices(old, new), old.len(), new.len()),
+        DiffAlgorithm::Patience => capture_diff_slices(Algorithm::Patience, old, new),
+        DiffAlgorithm::Histogram => histogram_diff(old, new),
+    };
+    group_diff_ops(ops, DIFF_CONTEXT)
+        .into_iter()
+        .map(|group| {
+            let (first, last) = (group[0], group[group.len() - 1]);
+            let lines = group
+                .iter()
+                .flat_map(|op| op.iter_changes(old, new))
+                .map(|change| DiffLine {
+                    origin: match change.tag() {
+                        ChangeTag::Equal => LineOrigin::Context,
+                        ChangeTag::Delete => LineOrigin::Deletion,
+                        ChangeTag::Insert => LineOrigin::Addition,
+                    },
+                    content: change.value().to_string(),
+                    old_lineno: change.old_index().map(|index| index + 1),
+                    new_lineno: change.new_index().map(|index| index + 1),
+                })
+                .collect();
+            DiffHunk {
+                old: first.old_range().start..last.old_range().end,
+                new: first.new_range().start..last.new_range().end,
+                lines,
+            }
+        })
+        .collect()
+}
+
+/// The operations turning `old_len` elements into `new_len` ones by
+/// `hunks`, for the diff renderer of `similar`
+pub(super) fn hunk_ops(hunks: &[engine::Hunk], old_len: usize, new_len: usize) -> Vec<DiffOp> {
+    let mut ops = Vec::new();
+    let (mut old_at, mut new_at) = (0, 0);
+    for hunk in hunks {
+        if hunk.old.start > old_at {
+            ops.push(DiffOp::Equal {
+                old_index: old_at,
+                new_index: new_at,
+                len: hunk.old.start - old_at,
+            });
+        }
+        ops.push(match (hunk.old.is_empty(), hunk.new.is_empty()) {
+            (true, _) => DiffOp::Insert {
+                old_index: hunk.old.start,
+                new_index: hunk.new.start,
+                new_len: hunk.new.len(),
+            },
+            (_, true) => DiffOp::Delete {
+                old_index: hunk.old.start,
+                old_len: hunk.old.len(),
+                new_index: hunk.new.start,
+            },
+            _ => DiffOp::Replace {
+                old_index: hunk.old.start,
+                old_len: hunk.old.len(),
+                new_index: hunk.new.start,
+                new_len: hunk.new.len(),
+            },
+        });
+        (old_at, new_at) = (hunk.old.end, hunk.new.end);
+    }
+    if old_len > old_at {
+        ops.push(DiffOp::Equal {
+            old_index: old_at,
+            new_index: new_at,
+            len: old_len - old_at,
+        });
+    }
+    debug_assert_eq!(new_len - new_at, old_len - old_at);
+    ops
+}
+
+/// Pairs up the paths whose content differs between two snapshots,
+/// sorted by path
+pub(super) fn diff_files(old: &SnapshotFiles, new: &SnapshotFiles) -> Vec<FilePair> {
+    let paths: BTreeSet<&String> = old.entries.keys().chain(new.entries.keys()).collect();
+    paths
+        .into_iter()
+        .filter_map(|path| {
+            let old_sha = old.entries.get(path);
+            let new_sha = new.entries.get(path);
+            (old_sha != new_sha).then(|| FilePair {
+                path: path.clone(),
+                old: old_sha.cloned(),
+                new: new_sha.cloned(),
+                source: None,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::init_test_repo;
+    use tempfile::TempDir;
+
+    #[test]
+    fn unified_diff_of_worktree_and_index() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        let file = repo.dir.join("a.txt");
+        fs::write(&file, "one\ntwo\nthree\n").unwrap();
+        repo.add(&vec![file.to_str().unwrap()]).unwrap();
+        repo.commit("first").unwrap();
+
+        fs::write(&file, "one\n2\nthree\n").unwrap();
+        let none: [&str; 0] = [];
+        let diff = repo
+            .unified_diff(Snapshot::Index, Snapshot::Worktree, &none)
+            .unwrap();
+        let index = Index::load(&repo.get_index_path()).unwrap();
+        let old = &index.get_sha1("a.txt").unwrap().0[..7];
+        let new = &Blob::new(&file).unwrap().encoded_sha1()[..7];
+        assert_eq!(
+            diff,
+            format!(
+                "diff --git a/a.txt b/a.txt\nindex {old}..{new}\n--- a/a.txt\n+++ b/a.txt\n\
+                 @@ -1,3 +1,3 @@\n one\n-two\n+2\n three\n"
+            )
+        );
+
+        // Staging it moves the change between HEAD and the index
+        repo.add(&vec![file.to_str().unwrap()]).unwrap();
+        assert!(
+            repo.unified_diff(Snapshot::Index, Snapshot::Worktree, &none)
+                .unwrap()
+                .is_empty()
+        );
+        let cached = repo
+            .unified_diff(Snapshot::Commit("HEAD"), Snapshot::Index, &none)
+            .unwrap();
+        assert!(cached.contains("-two\n+2\n"));
+    }
+
+    #[test]
+    fn structured_diff_lists_hunks_and_lines() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        let path = |name: &str| repo.dir.join(name).to_str().unwrap().to_string();
+        fs::write(path("a.txt"), "one\ntwo\nthree\n").unwrap();
+        repo.add(&vec![path("a.txt")]).unwrap();
+        let first = repo.commit("first").unwrap();
+        let tree = repo.read_commit(&first).unwrap().get_tree_sha();
+        let options = DiffOptions::default();
+
+        let diff = repo.diff_tree_to_tree(None, Some(&tree), &options).unwrap();
+        assert_eq!(diff.len(), 1);
+        assert_eq!(diff.deltas[0].status, DeltaStatus::Added);
+        assert_eq!(diff.deltas[0].hunks[0].lines.len(), 3);
+        assert!(repo.diff_index_to_workdir(&options).unwrap().is_empty());
+
+        fs::write(path("a.txt"), "one\n2\nthree").unwrap();
+        let diff = repo.diff_index_to_workdir(&options).unwrap();
+        let delta = diff.iter().next().unwrap();
+        assert_eq!(
+            (delta.old.path.as_str(), delta.new.path.as_str()),
+            ("a.txt", "a.txt")
+        );
+        assert_eq!(delta.status, DeltaStatus::Modified);
+        let hunk = &delta.hunks[0];
+        assert_eq!((hunk.old.clone(), hunk.new.clone()), (0..3, 0..3));
+        let line = |origin, content: &str, old_lineno, new_lineno| DiffLine {
+            origin,
+            content: content.to_string(),
+            old_lineno,
+            new_lineno,
+        };
+        assert_eq!(
+            hunk.lines,
+            [
+                line(LineOrigin::Context, "one\n", Some(1), Some(1)),
+                line(LineOrigin::Deletion, "two\n", Some(2), None),
+                line(LineOrigin::Deletion, "three\n", Some(3), None),
+                line(LineOrigin::Addition, "2\n", None, Some(2)),
+                line(LineOrigin::Addition, "three", None, Some(3)),
+            ]
+        );
+        // The diff displays as the text `diff` prints
+        let none: [&str; 0] = [];
+        assert_eq!(
+            diff.to_string(),
+            repo.unified_diff(Snapshot::Index, Snapshot::Worktree, &none)
+                .unwrap()
+        );
+        assert!(
+            diff.to_string()
+                .ends_with("+three\n\\ No newline at end of file\n")
+        );
+    }
+
+    #[test]
+    fn copies_and_renames_are_detected() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        let path = |name: &str| repo.dir.join(name).to_str().unwrap().to_string();
+        fs::write(path("a.txt"), "1\n2\n3\n4\n").unwrap();
+        fs::write(path("b.txt"), "b\n").unwrap();
+        repo.add(&vec![path("a.txt"), path("b.txt")]).unwrap();
+        repo.commit("first").unwrap();
+        fs::write(path("c.txt"), "1\n2\n3\nfour\n").unwrap();
+        repo.add(&vec![path("c.txt")]).unwrap();
+        repo.mv(&path("b.txt"), &path("d.txt")).unwrap();
+
+        let statuses = |copies: CopyDetection| -> Vec<(String, String)> {
+            let old = repo.snapshot_files(Snapshot::Commit("HEAD")).unwrap();
+            let new = repo.snapshot_files(Snapshot::Index).unwrap();
+            let options = DiffOptions {
+                copies,
+                ..DiffOptions::default()
+            };
+            repo.diff_pairs(&old, &new, &options)
+                .unwrap()
+                .into_iter()
+                .map(|pair| (pair.status(), pair.path))
+                .collect()
+        };
+        let status = |status: &str, path: &str| (status.to_string(), path.to_string());
+        assert_eq!(
+            statuses(CopyDetection::Off),
+            [
+                status("D", "b.txt"),
+                status("A", "c.txt"),
+                status("A", "d.txt")
+            ]
+        );
+        // The unchanged a.txt is only a source when looking harder
+        assert_eq!(
+            statuses(CopyDetection::Changed),
+            [status("A", "c.txt"), status("R100", "d.txt")]
+        );
+        assert_eq!(
+            statuses(CopyDetection::All),
+            [status("C075", "c.txt"), status("R100", "d.txt")]
+        );
+
+        let options = DiffOptions {
+            copies: CopyDetection::All,
+            ..DiffOptions::default()
+        };
+        let diff = repo
+            .unified_diff_with(
+                Snapshot::Commit("HEAD"),
+                Snapshot::Index,
+                &[] as &[&str],
+                &options,
+            )
+            .unwrap();
+        assert!(diff.starts_with(
+            "diff --git a/a.txt b/c.txt\nsimilarity index 75%\ncopy from a.txt\ncopy to c.txt\n"
+        ));
+        assert!(diff.contains("-4\n+four\n"));
+        assert!(diff.ends_with(
+            "diff --git a/b.txt b/d.txt\nsimilarity index 100%\nrename from b.txt\nrename to d.txt\n"
+        ));
+    }
+
+    #[test]
+    fn algorithm_comes_from_options_or_config() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        let file = repo.dir.join("a.txt");
+        fs::write(&file, "a\na\n}\n").unwrap();
+        repo.add(&vec![file.to_str().unwrap()]).unwrap();
+        repo.commit("first").unwrap();
+        fs::write(&file, "}\na\nd\n").unwrap();
+        let diff = |algorithm: Option<DiffAlgorithm>| {
+            let options = DiffOptions {
+                algorithm,
+                ..DiffOptions::default()
+            };
+            repo.unified_diff_with(
+                Snapshot::Index,
+                Snapshot::Worktree,
+                &[] as &[&str],
+                &options,
+            )
+        };
+
+        let myers = "@@ -1,3 +1,3 @@\n+}\n a\n-a\n-}\n+d\n";
+        // The unique `}` anchors the patience and histogram diffs
+        let patience = "@@ -1,3 +1,3 @@\n-a\n-a\n }\n+a\n+d\n";
+        assert!(diff(None).unwrap().ends_with(myers));
+        assert!(
+            diff(Some(DiffAlgorithm::Patience))
+                .unwrap()
+                .ends_with(patience)
+        );
+        assert!(
+            diff(Some(DiffAlgorithm::Histogram))
+                .unwrap()
+                .ends_with(patience)
+        );
+        repo.set_config("diff.algorithm", "patience").unwrap();
+        assert!(diff(None).unwrap().ends_with(patience));
+        assert!(diff(Some(DiffAlgorithm::Myers)).unwrap().ends_with(myers));
+        repo.set_config("diff.algorithm", "fancy").unwrap();
+        assert!(diff(None).is_err());
+    }
+
+    #[test]
+    fn dirstat_folds_small_directories() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        let write = |path: &str, lines: usize| {
+            let file = repo.dir.join(path);
+            fs::create_dir_all(file.parent().unwrap()).unwrap();
+            fs::write(&file, "line\n".repeat(lines)).unwrap();
+            file
+        };
+        let files = [
+            write("README", 1),
+            write("src/lib.rs", 1),
+            write("src/repo/a.rs", 1),
+            write("docs/guide/intro.md", 1),
+        ];
+        let paths: Vec<&str> = files.iter().map(|f| f.to_str().unwrap()).collect();
+        repo.add(&paths).unwrap();
+        repo.commit("first").unwrap();
+
+        // 60 lines in src/repo, 30 in src, 8 at the top, 2 in docs/guide
+        write("src/repo/a.rs", 61);
+        write("src/lib.rs", 31);
+        write("README", 9);
+        write("docs/guide/intro.md", 3);
+
+        let none: [&str; 0] = [];
+        let stats = repo
+            .dirstat(
+                Snapshot::Commit("HEAD"),
+                Snapshot::Worktree,
+                &none,
+                DIRSTAT_THRESHOLD,
+            )
+            .unwrap();
+        let stats: Vec<(&str, i64)> = stats
+            .iter()
+            .map(|(dir, percent)| (dir.as_str(), percent.round() as i64))
+            .collect();
+        // docs/guide/ is below the threshold, so it is folded into the top level
+        assert_eq!(stats, vec![("./", 10), ("src/", 30), ("src/repo/", 60)]);
+
+        // Nothing is staged yet
+        let staged = repo
+            .dirstat(
+                Snapshot::Commit("HEAD"),
+                Snapshot::Index,
+                &none,
+                DIRSTAT_THRESHOLD,
+            )
+            .unwrap();
+        assert!(staged.is_empty());
+
+        // Only changes below src/ count
+        let limited = repo
+            .dirstat(
+                Snapshot::Commit("HEAD"),
+                Snapshot::Worktree,
+                &["src"],
+                DIRSTAT_THRESHOLD,
+            )
+            .unwrap();
+        let limited: Vec<(&str, i64)> = limited
+            .iter()
+            .map(|(dir, percent)| (dir.as_str(), percent.round() as i64))
+            .collect();
+        assert_eq!(limited, vec![("src/", 33), ("src/repo/", 67)]);
+    }
+}
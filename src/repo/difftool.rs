@@ -0,0 +1,172 @@
+use super::Repository;
+use super::diff::{Snapshot, SnapshotFiles, diff_files};
+use super::mergetool::shell;
+use super::pathspec::{matches_pathspec, normalize_pathspecs};
+use crate::Error;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+
+/// Command lines of the diff tools known without `difftool.<tool>.cmd`
+const BUILTIN_TOOLS: &[(&str, &str)] = &[
+    ("meld", r#"meld "$LOCAL" "$REMOTE""#),
+    ("vimdiff", r#"vimdiff -d "$LOCAL" "$REMOTE""#),
+    ("kdiff3", r#"kdiff3 "$LOCAL" "$REMOTE""#),
+    ("opendiff", r#"opendiff "$LOCAL" "$REMOTE""#),
+];
+
+impl Repository {
+    /// Runs a diff tool on every file that differs between two snapshots,
+    /// limited to the paths matching `paths` if any are given, like
+    /// `git difftool`. The tool's command line finds the old version in
+    /// `$LOCAL` and the new one in `$REMOTE`, both as files, and the path
+    /// compared in `$MERGED`. Versions not in the working tree are written
+    /// to a temporary directory in the git dir first; a missing version is
+    /// an empty file.
+    ///
+    /// # Arguments
+    /// * `tool` - Tool to run; defaults to `diff.tool`. Its command line
+    ///   is `difftool.<tool>.cmd` unless it is one of the built-in tools.
+    ///
+    /// # Returns
+    /// The paths the tool failed on
+    pub fn difftool<S: AsRef<str>>(
+        &self,
+        old: Snapshot,
+        new: Snapshot,
+        tool: Option<&str>,
+        paths: &[S],
+    ) -> Result<Vec<String>, Error> {
+        let pathspecs = normalize_pathspecs(paths);
+        let config = self.config()?;
+        let tool = tool
+            .or(config.get("diff.tool"))
+            .ok_or("No diff tool configured; set diff.tool or use --tool")?;
+        let cmd = config
+            .get(&format!("difftool.{tool}.cmd"))
+            .or_else(|| {
+                BUILTIN_TOOLS
+                    .iter()
+                    .find(|(name, _)| *name == tool)
+                    .map(|(_, cmd)| *cmd)
+            })
+            .ok_or_else(|| format!("unknown diff tool '{tool}'"))?
+            .to_string();
+
+        let old = self.snapshot_files(old)?;
+        let new = self.snapshot_files(new)?;
+        let pairs: Vec<_> = diff_files(&old, &new)
+            .into_iter()
+            .filter(|pair| matches_pathspec(&pair.path, &pathspecs))
+            .collect();
+        let temp_dir = self.git_dir.join(format!("difftool-{}", process::id()));
+        let mut failed = Vec::new();
+        for (n, pair) in pairs.iter().enumerate() {
+            println!("Viewing ({}/{}): '{}'", n + 1, pairs.len(), pair.path);
+            let result = (|| -> Result<process::ExitStatus, Error> {
+                let local = self.side_file(&old, &pair.path, &pair.old, &temp_dir, "old")?;
+                let remote = self.side_file(&new, &pair.path, &pair.new, &temp_dir, "new")?;
+                let status = shell(&cmd)
+                    .current_dir(&self.dir)
+                    .env("LOCAL", local)
+                    .env("REMOTE", remote)
+                    .env("MERGED", &pair.path)
+                    .env("BASE", &pair.path)
+                    .stdin(process::Stdio::inherit())
+                    .status()
+                    .map_err(|why| format!("cannot run diff tool '{tool}': {why}"))?;
+                Ok(status)
+            })();
+            let _ = fs::remove_dir_all(&temp_dir);
+            if !result?.success() {
+                failed.push(pair.path.clone());
+            }
+        }
+        Ok(failed)
+    }
+
+    /// The file holding one side of a compared path: the file itself in the
+    /// working tree, or else a copy written under `temp_dir/side`
+    fn side_file(
+        &self,
+        files: &SnapshotFiles,
+        path: &str,
+        sha: &Option<crate::EncodedSha>,
+        temp_dir: &std::path::Path,
+        side: &str,
+    ) -> Result<PathBuf, Error> {
+        if files.worktree && sha.is_some() {
+            return Ok(self.dir.join(path));
+        }
+        let file = temp_dir.join(side).join(path);
+        if let Some(parent) = file.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&file, self.snapshot_bytes(files, path, sha.as_ref())?)?;
+        Ok(file)
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::repo::init_test_repo;
+    use tempfile::TempDir;
+
+    #[test]
+    fn difftool_runs_the_tool_on_each_changed_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        let path = |name: &str| repo.dir.join(name).to_str().unwrap().to_string();
+        fs::write(path("a.txt"), "old a\n").unwrap();
+        fs::write(path("b.txt"), "b\n").unwrap();
+        repo.add(&vec![path("a.txt"), path("b.txt")]).unwrap();
+        repo.commit("first").unwrap();
+        fs::write(path("a.txt"), "new a\n").unwrap();
+        fs::write(path("b.txt"), "new b\n").unwrap();
+        let none: [&str; 0] = [];
+
+        assert!(
+            repo.difftool(Snapshot::Index, Snapshot::Worktree, None, &none)
+                .is_err()
+        );
+        // The tool sees the old version in a temporary file and the new
+        // one in the working tree
+        let log = repo.git_dir.join("tool.log");
+        repo.set_config("diff.tool", "log").unwrap();
+        repo.set_config(
+            "difftool.log.cmd",
+            format!(
+                r#"cat "$LOCAL" >> {0}; echo "$REMOTE $MERGED" >> {0}"#,
+                log.display()
+            ),
+        )
+        .unwrap();
+        let failed = repo
+            .difftool(Snapshot::Index, Snapshot::Worktree, None, &["a.txt"])
+            .unwrap();
+        assert!(failed.is_empty());
+        assert_eq!(
+            fs::read_to_string(&log).unwrap(),
+            format!("old a\n{} a.txt\n", path("a.txt"))
+        );
+        assert!(
+            !repo
+                .git_dir
+                .join(format!("difftool-{}", process::id()))
+                .exists()
+        );
+
+        repo.set_config("difftool.fail.cmd", "false").unwrap();
+        assert_eq!(
+            repo.difftool(
+                Snapshot::Commit("HEAD"),
+                Snapshot::Worktree,
+                Some("fail"),
+                &none
+            )
+            .unwrap(),
+            ["a.txt", "b.txt"]
+        );
+    }
+}
@@ -0,0 +1,506 @@
+use super::packed_refs::read_packed_refs;
+use super::tag::TAGS_DIR;
+use super::{Branch, HEAD_FILE, HEADS_DIR, Head, REFS_DIR, REMOTES_DIR, Repository};
+use crate::EncodedSha;
+use crate::lock::{LOCK_SUFFIX, LockFile};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use walkdir::WalkDir;
+
+/// Reason given to changes picked up by `watch_refs`
+pub const EXTERNAL_REASON: &str = "external change";
+
+/// An update of a reference, e.g. `refs/heads/master` or `HEAD`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RefEvent {
+    pub name: String,
+    /// Commit the ref pointed at before; `None` if it did not exist or was unborn
+    pub old: Option<EncodedSha>,
+    /// Commit the ref points at now; `None` if it was deleted or is unborn
+    pub new: Option<EncodedSha>,
+    /// What moved the ref, e.g. `commit: <message>`
+    pub reason: String,
+}
+
+/// A ref locked from reading its value until the new one is written, so
+/// no other update can slip in between, like
+/// [`LockedIndex`](crate::index::LockedIndex) for the index. Dropping it
+/// leaves the ref as it was.
+pub(super) struct LockedRef<'r> {
+    repo: &'r Repository,
+    name: String,
+    lock: LockFile,
+    old: Option<EncodedSha>,
+}
+
+impl LockedRef<'_> {
+    /// The commit the ref held when it was locked
+    pub(super) fn old(&self) -> Option<&EncodedSha> {
+        self.old.as_ref()
+    }
+
+    /// Fails unless the ref held `expected` when it was locked, `None`
+    /// meaning that it did not exist
+    pub(super) fn check(&self, expected: Option<&EncodedSha>) -> io::Result<()> {
+        check_expected(&self.name, self.old(), Some(expected))
+    }
+
+    /// Points the ref at `new`, which is `None` for an unborn branch, and
+    /// releases it.
+    ///
+    /// Moves of HEAD, branches and remote-tracking branches are logged in
+    /// their reflog, and in HEAD's when the branch is checked out. Every
+    /// update is reported to the subscribers.
+    pub(super) fn commit(mut self, new: Option<&EncodedSha>, reason: &str) -> io::Result<()> {
+        match new {
+            Some(sha) => self.lock.write_all(sha.0.as_bytes())?,
+            None => self.lock.write_all(b"No commit")?,
+        }
+        self.lock.commit()?;
+        let (repo, name, old) = (self.repo, self.name, self.old);
+        if old.as_ref() != new {
+            if name == HEAD_FILE {
+                repo.append_reflog(HEAD_FILE, old.as_ref(), new, reason)?;
+            } else if is_logged(&name) {
+                repo.append_reflog(&name, old.as_ref(), new, reason)?;
+                // Moving the checked out branch moves HEAD as well
+                if repo.head_ref_name().as_deref() == Some(name.as_str()) {
+                    repo.append_reflog(HEAD_FILE, old.as_ref(), new, reason)?;
+                }
+            }
+        }
+        repo.emit_ref_event(RefEvent {
+            name,
+            old,
+            new: new.cloned(),
+            reason: reason.to_string(),
+        });
+        Ok(())
+    }
+}
+
+/// Background poller started by `watch_refs`. Stops when dropped.
+pub struct RefWatcher {
+    events: Receiver<RefEvent>,
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl RefWatcher {
+    /// Changes seen so far; iterating blocks until the next one
+    pub fn events(&self) -> &Receiver<RefEvent> {
+        &self.events
+    }
+}
+
+impl Drop for RefWatcher {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Repository {
+    /// Subscribes to the ref updates this `Repository` performs. Every
+    /// branch and HEAD change made through it afterwards is sent on the
+    /// returned channel.
+    pub fn subscribe_refs(&mut self) -> Receiver<RefEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.ref_subscribers.push(sender);
+        receiver
+    }
+
+    /// Polls the refs directory and HEAD every `interval` and reports each
+    /// change as a `RefEvent` with reason `external change`. This catches
+    /// updates made by other processes, and by this one too.
+    pub fn watch_refs(&self, interval: Duration) -> RefWatcher {
+        let (sender, events) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let git_dir = self.git_dir.clone();
+        let stopped = stop.clone();
+        // Changes count from the moment of the call, not from thread start
+        let mut seen = snapshot_refs(&git_dir);
+        let handle = thread::spawn(move || {
+            while !stopped.load(Ordering::Relaxed) {
+                thread::sleep(interval);
+                let current = snapshot_refs(&git_dir);
+                for event in diff_snapshots(&seen, &current) {
+                    if sender.send(event).is_err() {
+                        return;
+                    }
+                }
+                seen = current;
+            }
+        });
+        RefWatcher {
+            events,
+            stop,
+            handle: Some(handle),
+        }
+    }
+
+    fn emit_ref_event(&self, event: RefEvent) {
+        for subscriber in &self.ref_subscribers {
+            // A dropped receiver only means nobody listens anymore
+            let _ = subscriber.send(event.clone());
+        }
+    }
+
+    /// Locks the ref `name`, a full name below refs/ or `HEAD`, and reads
+    /// the commit it holds
+    pub(super) fn lock_ref(&self, name: &str) -> io::Result<LockedRef<'_>> {
+        let path = self.git_dir.join(name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let lock = LockFile::acquire(&path)?;
+        let old = match name {
+            HEAD_FILE => self.get_current_commit(),
+            name => self.read_ref(name),
+        };
+        Ok(LockedRef {
+            repo: self,
+            name: name.to_string(),
+            lock,
+            old,
+        })
+    }
+
+    /// Locks the branch HEAD is on, or HEAD itself when it is detached
+    pub(super) fn lock_head_ref(&self) -> io::Result<LockedRef<'_>> {
+        self.lock_ref(
+            &self
+                .head_ref_name()
+                .unwrap_or_else(|| HEAD_FILE.to_string()),
+        )
+    }
+
+    /// Points the ref `name`, a full name below refs/, at `new`, which is
+    /// `None` for an unborn branch, as [`LockedRef::commit`] does. With
+    /// `expected` the update only happens if the ref still holds that
+    /// value, `Some(None)` meaning that it must not exist yet.
+    pub(super) fn write_ref(
+        &self,
+        name: &str,
+        new: Option<&EncodedSha>,
+        expected: Option<Option<&EncodedSha>>,
+        reason: &str,
+    ) -> io::Result<()> {
+        let locked = self.lock_ref(name)?;
+        if let Some(expected) = expected {
+            locked.check(expected)?;
+        }
+        locked.commit(new, reason)
+    }
+
+    /// Deletes the ref `name`, a full name below refs/, whether loose or
+    /// packed, checking `expected` like [`Self::write_ref`]. The reflog is
+    /// kept so a deleted branch can be restored.
+    pub(super) fn remove_ref(
+        &self,
+        name: &str,
+        expected: Option<&EncodedSha>,
+        reason: &str,
+    ) -> io::Result<()> {
+        let path = self.git_dir.join(name);
+        // A packed ref may have no directory left to hold its lock
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let lock = LockFile::acquire(&path)?;
+        let old = self.read_ref(name);
+        check_expected(name, old.as_ref(), expected.map(Some))?;
+        let packed =
+            self.update_packed_refs(|packed| (packed != name).then(|| packed.to_string()))?;
+        match Branch::remove(&self.git_dir, name) {
+            // A packed ref need not have a loose file
+            Err(why) if why.kind() == io::ErrorKind::NotFound && packed => {}
+            result => result?,
+        }
+        drop(lock);
+        if is_logged(name) {
+            self.append_reflog(name, old.as_ref(), None, reason)?;
+        }
+        self.emit_ref_event(RefEvent {
+            name: name.to_string(),
+            old,
+            new: None,
+            reason: reason.to_string(),
+        });
+        Ok(())
+    }
+
+    /// The commit or object the ref `name`, a full name below refs/,
+    /// holds, loose or packed. Symbolic refs are followed.
+    pub(super) fn read_ref(&self, name: &str) -> Option<EncodedSha> {
+        let name = self.follow_symbolic_ref(name)?;
+        match Head::load(&self.git_dir.join(&name)) {
+            Ok(Head::Detached(sha)) => Some(sha),
+            Err(why) if why.kind() == io::ErrorKind::NotFound => self.packed_ref(&name),
+            // Unborn branches hold no commit
+            _ => None,
+        }
+    }
+
+    /// Saves a branch under refs/heads, logs the move in its reflog and
+    /// reports the update
+    pub(super) fn save_branch(&self, branch: &Branch, reason: &str) -> io::Result<()> {
+        let name = branch_ref_name(&branch.name);
+        self.write_ref(&name, branch.commit_sha.as_ref(), None, reason)
+    }
+
+    /// Saves the remote-tracking ref `refs/remotes/<remote>/<branch>`, logs
+    /// the move in its reflog and reports the update
+    pub(super) fn save_remote_ref(
+        &self,
+        remote: &str,
+        branch: &Branch,
+        reason: &str,
+    ) -> io::Result<()> {
+        let name = format!("{REFS_DIR}/{REMOTES_DIR}/{remote}/{}", branch.name);
+        self.write_ref(&name, branch.commit_sha.as_ref(), None, reason)
+    }
+
+    /// Deletes a branch, logging and reporting the deletion. The reflog is
+    /// kept so the branch can be restored.
+    pub(super) fn remove_branch(&self, name: &str, reason: &str) -> io::Result<()> {
+        self.remove_ref(&branch_ref_name(name), None, reason)
+    }
+
+    /// Saves the tag `refs/tags/<name>` and reports it. Tags are not
+    /// expected to move, so they keep no reflog.
+    pub(super) fn save_tag(&self, name: &str, sha: &EncodedSha) -> io::Result<()> {
+        let name = format!("{REFS_DIR}/{TAGS_DIR}/{name}");
+        self.write_ref(&name, Some(sha), None, "tag")
+    }
+
+    /// Deletes the tag `refs/tags/<name>`, loose or packed, and reports it
+    pub(super) fn remove_tag(&self, name: &str) -> io::Result<()> {
+        self.remove_ref(
+            &format!("{REFS_DIR}/{TAGS_DIR}/{name}"),
+            None,
+            "tag: deleted",
+        )
+    }
+
+    /// Makes `name` a symbolic ref pointing to the ref `target` and reports
+    /// it. Moving HEAD is logged in its reflog.
+    pub(super) fn save_symbolic_ref(
+        &self,
+        name: &str,
+        target: &str,
+        reason: &str,
+    ) -> io::Result<()> {
+        let head = Head::Symbolic(PathBuf::from(target));
+        if name == HEAD_FILE {
+            return self.save_head(&head, reason);
+        }
+        let old = self.read_ref(name);
+        head.save(&self.git_dir.join(name))?;
+        self.emit_ref_event(RefEvent {
+            name: name.to_string(),
+            old,
+            new: self.read_ref(name),
+            reason: reason.to_string(),
+        });
+        Ok(())
+    }
+
+    /// Points HEAD elsewhere, logging and reporting the change when HEAD
+    /// moved. HEAD stays locked from reading where it was to writing it.
+    pub(super) fn save_head(&self, head: &Head, reason: &str) -> io::Result<()> {
+        let head_path = self.git_dir.join(HEAD_FILE);
+        let mut lock = LockFile::acquire(&head_path)?;
+        let old_content = fs::read_to_string(&head_path).unwrap_or_default();
+        let old = self.get_current_commit();
+        let content = head.content();
+        lock.write_all(content.as_bytes())?;
+        lock.commit()?;
+        if content != old_content {
+            let new = self.get_current_commit();
+            self.append_reflog(HEAD_FILE, old.as_ref(), new.as_ref(), reason)?;
+            self.emit_ref_event(RefEvent {
+                name: HEAD_FILE.to_string(),
+                old,
+                new,
+                reason: reason.to_string(),
+            });
+        }
+        Ok(())
+    }
+}
+
+pub(super) fn branch_ref_name(name: &str) -> String {
+    format!("{REFS_DIR}/{HEADS_DIR}/{name}")
+}
+
+/// Whether updates of the ref `name` go to its reflog: those of branches
+/// and remote-tracking branches do
+fn is_logged(name: &str) -> bool {
+    [HEADS_DIR, REMOTES_DIR]
+        .iter()
+        .any(|dir| name.starts_with(&format!("{REFS_DIR}/{dir}/")))
+}
+
+/// Fails unless the ref `name` holds `old` as `expected`, if given
+fn check_expected(
+    name: &str,
+    old: Option<&EncodedSha>,
+    expected: Option<Option<&EncodedSha>>,
+) -> io::Result<()> {
+    match expected {
+        Some(expected) if expected != old => Err(io::Error::other(match (expected, old) {
+            (Some(expected), Some(old)) => {
+                format!("cannot lock ref '{name}': is at {old} but expected {expected}")
+            }
+            (Some(_), None) => format!("cannot lock ref '{name}': unable to resolve reference"),
+            (None, _) => format!("cannot lock ref '{name}': reference already exists"),
+        })),
+        _ => Ok(()),
+    }
+}
+
+/// Reads HEAD and every ref below refs/, resolving each to its commit
+pub(super) fn snapshot_refs(git_dir: &Path) -> BTreeMap<String, Option<EncodedSha>> {
+    let read_sha = |path: &Path| {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|content| EncodedSha::from_str(content.trim()).ok())
+    };
+    let mut refs = BTreeMap::new();
+    for entry in WalkDir::new(git_dir.join(REFS_DIR))
+        .into_iter()
+        .filter_map(|e| e.ok())
+    {
+        let name = entry.file_name().to_string_lossy();
+        if entry.file_type().is_file() && !name.ends_with(LOCK_SUFFIX) {
+            let ref_name = entry.path().strip_prefix(git_dir).unwrap();
+            refs.insert(
+                ref_name.to_string_lossy().replace('\\', "/"),
+                read_sha(entry.path()),
+            );
+        }
+    }
+    for (name, sha) in read_packed_refs(git_dir) {
+        refs.entry(name).or_insert(Some(sha));
+    }
+    let head = fs::read_to_string(git_dir.join(HEAD_FILE)).unwrap_or_default();
+    let head = match head.trim().strip_prefix("ref: ") {
+        Some(target) => read_sha(&git_dir.join(PathBuf::from(target)))
+            .or_else(|| read_packed_refs(git_dir).remove(target)),
+        None => EncodedSha::from_str(head.trim()).ok(),
+    };
+    refs.insert(HEAD_FILE.to_string(), head);
+    refs
+}
+
+fn diff_snapshots(
+    old: &BTreeMap<String, Option<EncodedSha>>,
+    new: &BTreeMap<String, Option<EncodedSha>>,
+) -> Vec<RefEvent> {
+    let mut names: Vec<&String> = old.keys().chain(new.keys()).collect();
+    names.sort();
+    names.dedup();
+    names
+        .into_iter()
+        .filter(|name| old.get(*name) != new.get(*name))
+        .map(|name| RefEvent {
+            name: name.clone(),
+            old: old.get(name).cloned().flatten(),
+            new: new.get(name).cloned().flatten(),
+            reason: EXTERNAL_REASON.to_string(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::{commit_file, init_test_repo};
+    use tempfile::TempDir;
+
+    #[test]
+    fn library_updates_are_reported() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut repo = init_test_repo(temp_dir.path());
+        let events = repo.subscribe_refs();
+
+        let file = repo.dir.join("a.txt");
+        fs::write(&file, "a").unwrap();
+        repo.add(&vec![file.to_str().unwrap()]).unwrap();
+        repo.commit("first").unwrap();
+        let first = repo.get_current_commit();
+        repo.branch("topic").unwrap();
+        repo.checkout("topic", false).unwrap();
+        repo.rm_branch("master").unwrap();
+
+        let events: Vec<_> = events.try_iter().collect();
+        let summary: Vec<_> = events
+            .iter()
+            .map(|e| (e.name.as_str(), e.old.is_some(), e.new.is_some()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("refs/heads/master", false, true),
+                ("refs/heads/topic", false, true),
+                ("HEAD", true, true),
+                ("refs/heads/master", true, false),
+            ]
+        );
+        assert_eq!(events[0].reason, "commit (initial): first");
+        assert_eq!(events[0].new, first);
+        assert_eq!(events[2].reason, "checkout: moving from master to topic");
+    }
+
+    #[test]
+    fn locked_refs_keep_other_updates_out() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        let first = commit_file(&repo, "a.txt", "one\n");
+        let second = commit_file(&repo, "a.txt", "two\n");
+
+        let locked = repo.lock_head_ref().unwrap();
+        assert_eq!(locked.old(), Some(&second));
+        let moved = Branch {
+            name: "master".to_string(),
+            commit_sha: Some(first.clone()),
+        };
+        assert!(repo.save_branch(&moved, "test").is_err());
+        drop(locked);
+        assert_eq!(repo.get_current_commit(), Some(second.clone()));
+
+        // An update computed from a HEAD that has moved since is refused
+        assert!(repo.update_head(Some(&first), &first, "test").is_err());
+        repo.update_head(Some(&second), &first, "test").unwrap();
+        assert_eq!(repo.get_current_commit(), Some(first));
+    }
+
+    #[test]
+    fn watcher_sees_external_changes() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        let watcher = repo.watch_refs(Duration::from_millis(10));
+
+        let sha = "0123456789abcdef0123456789abcdef01234567";
+        fs::write(repo.get_branch_dir().join("other"), sha).unwrap();
+        let event = watcher
+            .events()
+            .recv_timeout(Duration::from_secs(5))
+            .unwrap();
+        assert_eq!(event.name, "refs/heads/other");
+        assert_eq!(event.old, None);
+        assert_eq!(event.new.unwrap().0, sha);
+        assert_eq!(event.reason, EXTERNAL_REASON);
+    }
+}
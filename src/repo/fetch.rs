@@ -0,0 +1,158 @@
+use super::{Bundle, FastForward, HEADS_DIR, REFS_DIR, REMOTES_DIR, Repository};
+use crate::{EncodedSha, Error};
+use std::path::Path;
+
+impl Repository {
+    /// Copies the branches of `remote` into its remote-tracking refs
+    /// `refs/remotes/<remote>/<branch>` together with the objects they
+    /// need, and prints one line per ref that moved. The remote may also
+    /// be a bundle file, whose objects are all stored.
+    pub fn fetch(&self, remote: &str) -> Result<(), Error> {
+        let url = self.remote_url(remote)?;
+        let (branches, source) = if Bundle::is_bundle(Path::new(&url)) {
+            let bundle = Bundle::read(Path::new(&url))?;
+            self.store_bundle(&bundle)?;
+            (bundle.branches(), None)
+        } else {
+            let (source, _) = self.open_remote(remote)?;
+            (source.list_branches(), Some(source))
+        };
+        let reason = format!("fetch {remote}");
+        let mut updated = Vec::new();
+        for branch in branches {
+            let Some(new) = &branch.commit_sha else {
+                continue;
+            };
+            let tracking = format!("{remote}/{}", branch.name);
+            let old = self.load_remote_ref(&tracking);
+            if old.as_ref() == Some(new) {
+                continue;
+            }
+            if let Some(source) = &source {
+                Self::copy_missing_objects(source, self, [new.clone()])?;
+            }
+            let short = |sha: &EncodedSha| sha.0[..7].to_string();
+            let line = match &old {
+                None => format!(" * {:<17} {} -> {tracking}", "[new branch]", branch.name),
+                Some(old) if self.is_ancestor(old, new)? => format!(
+                    "   {:<17} {} -> {tracking}",
+                    format!("{}..{}", short(old), short(new)),
+                    branch.name
+                ),
+                Some(old) => format!(
+                    " + {:<17} {} -> {tracking} (forced update)",
+                    format!("{}...{}", short(old), short(new)),
+                    branch.name
+                ),
+            };
+            // A concurrent fetch may have moved the ref meanwhile
+            let name = format!("{REFS_DIR}/{REMOTES_DIR}/{tracking}");
+            self.write_ref(&name, Some(new), Some(old.as_ref()), &reason)?;
+            updated.push(line);
+        }
+        if !updated.is_empty() {
+            println!("From {url}");
+            for line in updated {
+                println!("{line}");
+            }
+        }
+        Ok(())
+    }
+
+    /// Fetches the upstream of the current branch, given by the
+    /// `branch.<name>.remote` and `branch.<name>.merge` settings, and merges
+    /// it into the current branch, fast-forwarding when possible
+    ///
+    /// # Arguments
+    /// * `autostash` - Stash uncommitted changes before merging and reapply
+    ///   them afterwards instead of refusing to merge
+    ///
+    /// # Returns
+    /// Whether the merge completed, see [`Self::merge`]
+    pub fn pull(&self, autostash: bool) -> Result<bool, Error> {
+        let heads_prefix = format!("{REFS_DIR}/{HEADS_DIR}/");
+        let branch = self
+            .head_ref_name()
+            .and_then(|name| name.strip_prefix(&heads_prefix).map(str::to_string))
+            .ok_or("You are not currently on a branch.")?;
+        let config = self.config()?;
+        let remote = config.get(&format!("branch.{branch}.remote"));
+        let merge = config.get(&format!("branch.{branch}.merge"));
+        let (Some(remote), Some(merge)) = (remote, merge) else {
+            return Err(format!(
+                "There is no tracking information for the current branch.\n\
+                 Set it with:\n\n    \
+                 rust-git config --set branch.{branch}.remote <remote>\n    \
+                 rust-git config --set branch.{branch}.merge refs/heads/<branch>"
+            )
+            .into());
+        };
+        self.fetch(remote)?;
+        let merge = merge.strip_prefix(&heads_prefix).unwrap_or(merge);
+        let tracking = format!("{remote}/{merge}");
+        if self.load_remote_ref(&tracking).is_none() {
+            return Err(format!("no such ref was fetched: {merge}").into());
+        }
+        self.merge(&tracking, autostash, FastForward::Allow)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::{commit_file, init_test_repo};
+    use tempfile::TempDir;
+
+    #[test]
+    fn pull_fast_forwards_and_merges() {
+        let origin_dir = TempDir::new().unwrap();
+        let origin = init_test_repo(origin_dir.path());
+        commit_file(&origin, "a.txt", "a");
+        let clone_dir = TempDir::new().unwrap();
+        let repo = Repository::clone(origin_dir.path(), clone_dir.path()).unwrap();
+        repo.set_config("user.name", "Bob").unwrap();
+        repo.set_config("user.email", "bob@example.com").unwrap();
+
+        commit_file(&origin, "b.txt", "b");
+        origin.branch("topic").unwrap();
+        repo.pull(false).unwrap();
+        assert_eq!(repo.get_current_commit(), origin.get_current_commit());
+        assert!(clone_dir.path().join("b.txt").exists());
+        assert!(repo.load_remote_ref("origin/topic").is_some());
+
+        commit_file(&origin, "c.txt", "c");
+        commit_file(&repo, "d.txt", "d");
+        repo.pull(false).unwrap();
+        let head = repo
+            .read_commit(&repo.get_current_commit().unwrap())
+            .unwrap();
+        assert_eq!(head.get_parents().len(), 2);
+        assert!(clone_dir.path().join("c.txt").exists());
+
+        repo.set_config("branch.master.merge", "refs/heads/none")
+            .unwrap();
+        assert!(repo.pull(false).is_err());
+    }
+
+    #[test]
+    fn failed_fetch_stores_nothing() {
+        let origin_dir = TempDir::new().unwrap();
+        let origin = init_test_repo(origin_dir.path());
+        commit_file(&origin, "a.txt", "a");
+        let clone_dir = TempDir::new().unwrap();
+        let repo = Repository::clone(origin_dir.path(), clone_dir.path()).unwrap();
+
+        // The new commit comes across before its missing blob is noticed
+        let commit = commit_file(&origin, "b.txt", "b");
+        let tree = origin.read_tree(&origin.read_commit(&commit).unwrap().get_tree_sha());
+        let blob = tree.unwrap().get_sha1("b.txt").unwrap().clone();
+        std::fs::remove_file(origin.obj_db.object_path(&blob)).unwrap();
+        assert!(repo.fetch("origin").is_err());
+        assert!(!repo.obj_db.object_path(&commit).exists());
+        assert_eq!(repo.obj_db.loose_objects().unwrap().len(), 3);
+        assert!(
+            repo.load_remote_ref("origin/master")
+                .is_some_and(|sha| sha != commit)
+        );
+    }
+}
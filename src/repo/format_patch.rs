@@ -0,0 +1,218 @@
+use super::Repository;
+use crate::object::Commit;
+use crate::{EncodedSha, Error};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The longest subject kept in the name of a patch file
+const PATCH_NAME_MAX_LEN: usize = 52;
+
+/// The widest `+`/`-` bar of the diffstat
+const STAT_BAR_WIDTH: usize = 40;
+
+impl Repository {
+    /// Writes every commit of `range` to `output_dir` as a patch mail, like
+    /// `git format-patch`, see [`Self::format_patch_output`]
+    ///
+    /// # Returns
+    /// The paths of the patch files, in the order they apply
+    pub fn format_patch(&self, range: &str, output_dir: &Path) -> Result<Vec<PathBuf>, Error> {
+        fs::create_dir_all(output_dir)?;
+        let mut paths = Vec::new();
+        for (name, patch) in self.format_patch_output(range)? {
+            let path = output_dir.join(name);
+            fs::write(&path, patch)?;
+            paths.push(path);
+        }
+        Ok(paths)
+    }
+
+    /// Renders the commits of `range` as patch mails in the mbox format,
+    /// oldest first: the author, date and subject as mail headers, then
+    /// the rest of the message, a diffstat and the patch against the
+    /// first parent. Merges are left out.
+    ///
+    /// # Arguments
+    /// * `range` - `<since>..<until>`, or `<since>` for the commits HEAD has
+    ///   on top of it
+    ///
+    /// # Returns
+    /// The file name and the mail of every patch, named after its number
+    /// and subject like `0001-Fix-the-parser.patch`
+    pub fn format_patch_output(&self, range: &str) -> Result<Vec<(String, String)>, Error> {
+        let range = match range.contains("..") {
+            true => range.to_string(),
+            false => format!("{range}.."),
+        };
+        let mut commits: Vec<(EncodedSha, Commit)> = Vec::new();
+        for sha in self.rev_list(&[range], None)? {
+            let commit = self.read_commit(&sha)?;
+            if commit.get_parents().len() <= 1 {
+                commits.push((sha, commit));
+            }
+        }
+        commits.reverse();
+
+        let total = commits.len();
+        let mut patches = Vec::new();
+        for (number, (sha, commit)) in commits.iter().enumerate() {
+            let number = number + 1;
+            let subject = commit.get_message().lines().next().unwrap_or_default();
+            let name = format!("{number:04}-{}.patch", patch_file_slug(subject));
+            let prefix = match total {
+                1 => "[PATCH]".to_string(),
+                total => format!("[PATCH {number}/{total}]"),
+            };
+            patches.push((name, self.format_patch_mail(sha, commit, &prefix)?));
+        }
+        Ok(patches)
+    }
+
+    fn format_patch_mail(
+        &self,
+        sha: &EncodedSha,
+        commit: &Commit,
+        prefix: &str,
+    ) -> Result<String, Error> {
+        let author = commit.get_author();
+        let mut message = commit.get_message().lines();
+        let subject = message.next().unwrap_or_default();
+        let body: Vec<&str> = message.skip_while(|line| line.is_empty()).collect();
+
+        // The date is a fixed marker telling mbox readers a mail starts
+        let mut out = format!("From {sha} Mon Sep 17 00:00:00 2001\n");
+        out.push_str(&format!(
+            "From: {} <{}>\n",
+            author.get_name(),
+            author.get_email()
+        ));
+        out.push_str(&format!("Date: {}\n", author.get_timestamp().to_rfc2822()));
+        out.push_str(&format!("Subject: {prefix} {subject}\n\n"));
+        if !body.is_empty() {
+            out.push_str(&body.join("\n"));
+            out.push_str("\n\n");
+        }
+        out.push_str("---\n");
+        out.push_str(&format_diffstat(&self.commit_diffstat(commit)?));
+        out.push('\n');
+        out.push_str(&self.commit_patch(commit)?);
+        out.push_str(&format!("-- \n{}\n\n", env!("CARGO_PKG_VERSION")));
+        Ok(out)
+    }
+}
+
+/// Turns a subject into the part of a patch file name after its number:
+/// runs of anything but letters, digits, `.` and `_` become one `-`
+fn patch_file_slug(subject: &str) -> String {
+    let mut slug = String::new();
+    for c in subject.chars() {
+        if c.is_ascii_alphanumeric() || c == '.' || c == '_' {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug: String = slug.chars().take(PATCH_NAME_MAX_LEN).collect();
+    slug.trim_end_matches(['-', '.']).to_string()
+}
+
+/// Renders the inserted and deleted lines of every file like
+/// `git diff --stat`, followed by the totals
+fn format_diffstat(stats: &[(String, usize, usize)]) -> String {
+    let name_width = stats.iter().map(|(path, _, _)| path.len()).max();
+    let most = stats.iter().map(|(_, ins, del)| ins + del).max();
+    let count_width = most.unwrap_or_default().to_string().len();
+    let scale = |lines: usize| match most {
+        Some(most) if most > STAT_BAR_WIDTH => (lines * STAT_BAR_WIDTH).div_ceil(most),
+        _ => lines,
+    };
+    let mut out = String::new();
+    for (path, insertions, deletions) in stats {
+        out.push_str(&format!(
+            " {path:<name_width$} | {:>count_width$} {}{}\n",
+            insertions + deletions,
+            "+".repeat(scale(*insertions)),
+            "-".repeat(scale(*deletions)),
+            name_width = name_width.unwrap_or_default(),
+        ));
+    }
+    let plural = |n: usize, word: &str| match n {
+        1 => format!("{n} {word}"),
+        n => format!("{n} {word}s"),
+    };
+    let insertions: usize = stats.iter().map(|(_, ins, _)| ins).sum();
+    let deletions: usize = stats.iter().map(|(_, _, del)| del).sum();
+    out.push_str(&format!(" {} changed", plural(stats.len(), "file")));
+    if insertions > 0 || deletions == 0 {
+        out.push_str(&format!(", {}(+)", plural(insertions, "insertion")));
+    }
+    if deletions > 0 || insertions == 0 {
+        out.push_str(&format!(", {}(-)", plural(deletions, "deletion")));
+    }
+    out.push('\n');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::{ApplyOptions, init_test_repo};
+    use tempfile::TempDir;
+
+    #[test]
+    fn format_patch_writes_mails_that_apply() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        let file = repo.dir.join("a.txt");
+        let commit_file = |content: &str, message: &str| {
+            fs::write(&file, content).unwrap();
+            repo.add(&vec![file.to_str().unwrap()]).unwrap();
+            repo.commit(message).unwrap()
+        };
+        commit_file("one\ntwo\n", "first");
+        commit_file("one\n2\n", "Change the second line\n\nIt reads better.");
+        commit_file("one\n2\nthree\n", "Add: a third line!");
+
+        let patches = repo.format_patch_output("HEAD~2").unwrap();
+        let names: Vec<&str> = patches.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "0001-Change-the-second-line.patch",
+                "0002-Add-a-third-line.patch"
+            ]
+        );
+        let mail = &patches[0].1;
+        assert!(mail.contains("\nFrom: Alice <"));
+        assert!(mail.contains(
+            "\nSubject: [PATCH 1/2] Change the second line\n\nIt reads better.\n\n---\n"
+        ));
+        assert!(mail.contains("\n a.txt | 2 +-\n 1 file changed, 1 insertion(+), 1 deletion(-)\n"));
+        assert!(mail.contains("\n-two\n+2\n"));
+        let single = repo.format_patch_output("HEAD~1..HEAD").unwrap();
+        assert!(
+            single[0]
+                .1
+                .contains("Subject: [PATCH] Add: a third line!\n")
+        );
+
+        // The mails undo and redo the commits
+        let out_dir = temp_dir.path().join("patches");
+        let paths = repo.format_patch("HEAD~2", &out_dir).unwrap();
+        let reverse = ApplyOptions {
+            reverse: true,
+            ..Default::default()
+        };
+        for path in paths.iter().rev() {
+            repo.apply(&fs::read_to_string(path).unwrap(), reverse)
+                .unwrap();
+        }
+        assert_eq!(fs::read_to_string(&file).unwrap(), "one\ntwo\n");
+        for path in &paths {
+            repo.apply(&fs::read_to_string(path).unwrap(), ApplyOptions::default())
+                .unwrap();
+        }
+        assert_eq!(fs::read_to_string(&file).unwrap(), "one\n2\nthree\n");
+        assert!(repo.format_patch_output("HEAD").unwrap().is_empty());
+    }
+}
@@ -0,0 +1,319 @@
+use walkdir::WalkDir;
+
+use super::cherry_pick::CHERRY_PICK_HEAD;
+use super::merge::MERGE_HEAD;
+use super::packed_refs::read_packed_refs;
+use super::reflog::LOGS_DIR;
+use super::revert::REVERT_HEAD;
+use super::tag::TAGS_DIR;
+use super::{HEAD_FILE, REFS_DIR, Repository};
+use crate::lock::LOCK_SUFFIX;
+use crate::object::{Commit, FileMode, ObjectType, Tag, Tree, determine_object_type};
+use crate::{EncodedSha, Error};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::str::FromStr;
+
+/// What `fsck` found wrong with, or left over in, the object store
+#[derive(Debug, Default)]
+pub struct FsckReport {
+    /// Objects that do not hash to their name, do not parse, or are not of
+    /// the type pointing at them expects, with the reason
+    pub corrupt: Vec<(EncodedSha, String)>,
+    /// Objects that are pointed at but not in the store, with their
+    /// expected type and what points at them
+    pub missing: Vec<(EncodedSha, ObjectType, String)>,
+    /// Objects no ref, reflog or index entry reaches
+    pub unreachable: Vec<(EncodedSha, ObjectType)>,
+    /// The unreachable objects no other unreachable object points at
+    /// either, i.e. the tips of lost history
+    pub dangling: Vec<(EncodedSha, ObjectType)>,
+}
+
+impl FsckReport {
+    /// Whether the store is intact; unreachable objects are harmless
+    pub fn is_ok(&self) -> bool {
+        self.corrupt.is_empty() && self.missing.is_empty()
+    }
+}
+
+impl Repository {
+    /// Prints the result of [`Self::fsck_report`] like `git fsck`. With
+    /// `unreachable`, every unreachable object is listed instead of only
+    /// the dangling ones.
+    ///
+    /// # Returns
+    /// Whether no object is corrupt or missing
+    pub fn fsck(&self, unreachable: bool) -> Result<bool, Error> {
+        let report = self.fsck_report()?;
+        for (sha, why) in &report.corrupt {
+            println!("error: corrupt object {sha}: {why}");
+        }
+        for (sha, object_type, from) in &report.missing {
+            println!("missing {object_type} {sha} (pointed at by {from})");
+        }
+        let (label, lost) = match unreachable {
+            true => ("unreachable", &report.unreachable),
+            false => ("dangling", &report.dangling),
+        };
+        for (sha, object_type) in lost {
+            println!("{label} {object_type} {sha}");
+        }
+        Ok(report.is_ok())
+    }
+
+    /// Checks the integrity of the object store: every object must hash to
+    /// its name and parse as its type, and everything reachable from HEAD,
+    /// the refs, the reflogs, the index and a stopped cherry-pick or revert
+    /// must be present. Objects nothing reaches are reported as well.
+    pub fn fsck_report(&self) -> Result<FsckReport, Error> {
+        let mut report = FsckReport::default();
+        // Type and outgoing links of every sound object
+        let mut objects: HashMap<EncodedSha, (ObjectType, Vec<(EncodedSha, ObjectType)>)> =
+            HashMap::new();
+        // Objects `gc` has packed count as present like loose ones
+        let mut stored = self.obj_db.loose_objects()?;
+        stored.extend(self.obj_db.packed_objects()?);
+        stored.sort_by(|a, b| a.0.cmp(&b.0));
+        stored.dedup();
+        for sha in &stored {
+            match self.object_links(sha) {
+                Ok(object) => {
+                    objects.insert(sha.clone(), object);
+                }
+                Err(why) => report.corrupt.push((sha.clone(), why)),
+            }
+        }
+
+        let mut reachable = HashSet::new();
+        let mut pending = self.fsck_roots()?;
+        while let Some((sha, expected, from)) = pending.pop() {
+            if !reachable.insert(sha.clone()) {
+                continue;
+            }
+            match objects.get(&sha) {
+                Some((actual, _)) if *actual != expected => report.corrupt.push((
+                    sha,
+                    format!("{from} expects a {expected}, found a {actual}"),
+                )),
+                Some((_, links)) => {
+                    for (link, link_type) in links {
+                        pending.push((link.clone(), *link_type, format!("{expected} {sha}")));
+                    }
+                }
+                // Already reported as corrupt
+                None if report.corrupt.iter().any(|(corrupt, _)| *corrupt == sha) => {}
+                None => report.missing.push((sha, expected, from)),
+            }
+        }
+
+        let unreachable: Vec<&EncodedSha> = stored
+            .iter()
+            .filter(|sha| objects.contains_key(*sha) && !reachable.contains(*sha))
+            .collect();
+        let referenced: HashSet<&EncodedSha> = unreachable
+            .iter()
+            .flat_map(|sha| objects[*sha].1.iter().map(|(link, _)| link))
+            .collect();
+        for sha in unreachable {
+            let object_type = objects[sha].0;
+            if !referenced.contains(sha) {
+                report.dangling.push((sha.clone(), object_type));
+            }
+            report.unreachable.push((sha.clone(), object_type));
+        }
+        Ok(report)
+    }
+
+    /// Verifies an object and lists the objects it points at with their
+    /// expected types
+    fn object_links(
+        &self,
+        sha: &EncodedSha,
+    ) -> Result<(ObjectType, Vec<(EncodedSha, ObjectType)>), String> {
+        self.verify_object(sha)?;
+        let data = self.obj_db.retrieve(sha).map_err(|why| why.to_string())?;
+        let object_type = determine_object_type(&data)?;
+        let links = match object_type {
+            ObjectType::Blob => Vec::new(),
+            ObjectType::Tree => Tree::deserialize(&data)
+                .map_err(|why| why.to_string())?
+                .get_entries()
+                // Submodule commits belong to other repositories
+                .filter(|(_, entry)| entry.mode != FileMode::Gitlink)
+                .map(|(_, entry)| (entry.sha1.clone(), entry.object_type))
+                .collect(),
+            ObjectType::Commit => {
+                let commit = Commit::deserialize(&data)?;
+                let mut links = vec![(commit.get_tree_sha(), ObjectType::Tree)];
+                links.extend(
+                    commit
+                        .get_parents()
+                        .iter()
+                        .map(|parent| (parent.clone(), ObjectType::Commit)),
+                );
+                links
+            }
+            ObjectType::Tag => {
+                let tag = Tag::deserialize(&data)?;
+                vec![(tag.get_object().clone(), tag.get_object_type())]
+            }
+        };
+        Ok((object_type, links))
+    }
+
+    /// Everything `fsck` starts walking from, with its expected type and a
+    /// description of where it was found
+    fn fsck_roots(&self) -> Result<Vec<(EncodedSha, ObjectType, String)>, Error> {
+        let mut roots = Vec::new();
+        let mut commit = |sha: EncodedSha, from: String| {
+            // Annotated tags point at a tag object rather than a commit
+            let object_type = match self.read_tag(&sha) {
+                Some(_) if from.starts_with(&format!("{REFS_DIR}/{TAGS_DIR}/")) => ObjectType::Tag,
+                _ => ObjectType::Commit,
+            };
+            roots.push((sha, object_type, from));
+        };
+        if let Some(sha) = self.get_current_commit() {
+            commit(sha, HEAD_FILE.to_string());
+        }
+        for file in [CHERRY_PICK_HEAD, MERGE_HEAD, REVERT_HEAD] {
+            if let Ok(content) = fs::read_to_string(self.git_dir.join(file))
+                && let Ok(sha) = EncodedSha::from_str(content.trim())
+            {
+                commit(sha, file.to_string());
+            }
+        }
+        for entry in WalkDir::new(self.git_dir.join(REFS_DIR)).sort_by_file_name() {
+            let entry = entry.map_err(|why| why.to_string())?;
+            let name = entry.path().strip_prefix(&self.git_dir).unwrap();
+            let name = name.to_string_lossy().replace('\\', "/");
+            if !entry.file_type().is_file() || name.ends_with(LOCK_SUFFIX) {
+                continue;
+            }
+            if let Ok(sha) = EncodedSha::from_str(fs::read_to_string(entry.path())?.trim()) {
+                commit(sha, name);
+            }
+        }
+        for (name, sha) in read_packed_refs(&self.git_dir) {
+            commit(sha, name);
+        }
+        let logs_dir = self.git_dir.join(LOGS_DIR);
+        for entry in WalkDir::new(&logs_dir).sort_by_file_name() {
+            let entry = entry.map_err(|why| why.to_string())?;
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            let name = entry.path().strip_prefix(&logs_dir).unwrap();
+            let name = name.to_string_lossy().replace('\\', "/");
+            for log_entry in self.reflog(&name)? {
+                for sha in [log_entry.old, log_entry.new].into_iter().flatten() {
+                    commit(sha, format!("reflog of {name}"));
+                }
+            }
+        }
+        let index = self.load_index_or_empty()?;
+        for (path, sha) in index.collect_entries() {
+            if index.get_mode(&path) != Some(FileMode::Gitlink) {
+                roots.push((sha, ObjectType::Blob, format!("index entry {path}")));
+            }
+        }
+        Ok(roots)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git_source::{GitKind, write_test_pack};
+    use crate::object::Blob;
+    use crate::repo::{OBJECTS_DIR, ResetMode, commit_file, init_test_repo};
+    use std::time::SystemTime;
+    use tempfile::TempDir;
+
+    #[test]
+    fn fsck_reports_corrupt_missing_and_dangling_objects() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        let file = repo.dir.join("a.txt");
+        let commit_file = |content: &str| {
+            fs::write(&file, content).unwrap();
+            repo.add(&vec![file.to_str().unwrap()]).unwrap();
+            repo.commit(content).unwrap()
+        };
+        let commit = commit_file("hello\n");
+        assert!(repo.fsck_report().unwrap().is_ok());
+
+        // A blob nothing points at dangles
+        let lost = repo
+            .obj_db
+            .store(&Blob {
+                data: b"lost\n".to_vec(),
+            })
+            .unwrap();
+        let report = repo.fsck_report().unwrap();
+        assert!(report.is_ok());
+        assert_eq!(report.dangling, [(lost.clone(), ObjectType::Blob)]);
+
+        // Commits only the reflog remembers are still reachable
+        commit_file("reset away\n");
+        repo.reset(Some(&commit.0), ResetMode::Hard).unwrap();
+        assert_eq!(repo.fsck_report().unwrap().dangling.len(), 1);
+
+        // A tampered blob is corrupt, and a deleted tree is missing
+        let tree = repo.read_commit(&commit).unwrap().get_tree_sha();
+        let blob = repo
+            .read_tree(&tree)
+            .unwrap()
+            .get_sha1("a.txt")
+            .unwrap()
+            .clone();
+        fs::write(repo.obj_db.object_path(&blob), "blob 3\0bad").unwrap();
+        fs::remove_file(repo.obj_db.object_path(&tree)).unwrap();
+        let report = repo.fsck_report().unwrap();
+        assert!(!report.is_ok());
+        assert_eq!(report.corrupt.len(), 1);
+        assert_eq!(report.corrupt[0].0, blob);
+        assert_eq!(
+            report.missing,
+            [(tree, ObjectType::Tree, format!("commit {commit}"))]
+        );
+    }
+
+    #[test]
+    fn packed_objects_are_present() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        commit_file(&repo, "a.txt", "one\n");
+        commit_file(&repo, "a.txt", "two\n");
+        let lost = repo
+            .obj_db
+            .store(&Blob {
+                data: b"lost\n".to_vec(),
+            })
+            .unwrap();
+
+        // Move every object into a pack, as `git gc` does
+        let loose = repo.obj_db.loose_objects().unwrap();
+        let packed: Vec<(GitKind, Vec<u8>)> = loose
+            .iter()
+            .map(|sha| {
+                let data = repo.obj_db.retrieve(sha).unwrap();
+                let nul = data.iter().position(|&b| b == 0).unwrap();
+                let kind = std::str::from_utf8(&data[..nul]).unwrap();
+                let kind = GitKind::from_name(kind.split(' ').next().unwrap()).unwrap();
+                (kind, data[nul + 1..].to_vec())
+            })
+            .collect();
+        write_test_pack(&repo.git_dir.join(OBJECTS_DIR), &packed);
+        for sha in &loose {
+            fs::remove_file(repo.obj_db.object_path(sha)).unwrap();
+        }
+
+        let report = repo.fsck_report().unwrap();
+        assert!(report.is_ok(), "{report:?}");
+        assert_eq!(report.dangling, [(lost, ObjectType::Blob)]);
+        // Prune has no loose file to delete for packed objects
+        assert!(repo.prune(SystemTime::now(), false).unwrap().is_empty());
+    }
+}
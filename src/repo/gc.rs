@@ -0,0 +1,192 @@
+use chrono::{Local, NaiveDate};
+
+use super::{OBJECTS_DIR, Repository};
+use crate::{EncodedSha, Error};
+use std::fs;
+use std::io;
+use std::time::{Duration, SystemTime};
+
+/// How long `gc` keeps unreachable objects unless `gc.pruneExpire` says
+/// otherwise, since a command running meanwhile may be about to point at
+/// them
+const GC_PRUNE_EXPIRE: &str = "2.weeks.ago";
+
+impl Repository {
+    /// Cleans up the repository, like `git gc`: deletes unreachable loose
+    /// objects older than `gc.pruneExpire`, two weeks by default, and packs
+    /// the loose refs. Objects stay loose, as this tree cannot write packs.
+    pub fn gc(&self) -> Result<(), Error> {
+        let config = self.config()?;
+        let expire = parse_expire(config.get("gc.pruneExpire").unwrap_or(GC_PRUNE_EXPIRE))?;
+        let pruned = self.prune(expire, false)?;
+        let refs = self.pack_refs()?;
+        println!(
+            "packed {refs} refs, pruned {} unreachable objects",
+            pruned.len()
+        );
+        Ok(())
+    }
+
+    /// Deletes the loose objects that are unreachable and were last
+    /// modified before `expire`, then removes the object directories left
+    /// empty, like `git prune`.
+    ///
+    /// Reachability is taken from [`Self::fsck_report`], so objects the
+    /// reflogs or the index still need are kept. Nothing is deleted while
+    /// objects are corrupt or missing, since the unreachable ones may be
+    /// needed to repair the history.
+    ///
+    /// # Arguments
+    /// * `expire` - Only objects older than this are deleted; see
+    ///   [`parse_expire`]
+    /// * `dry_run` - Only report what would be deleted
+    ///
+    /// # Returns
+    /// The objects deleted, or that would be with `dry_run`
+    pub fn prune(&self, expire: SystemTime, dry_run: bool) -> Result<Vec<EncodedSha>, Error> {
+        let report = self.fsck_report()?;
+        if !report.is_ok() {
+            return Err("the object store is damaged; run 'rust-git fsck' first".into());
+        }
+        let mut pruned = Vec::new();
+        for (sha, _) in &report.unreachable {
+            let path = self.obj_db.object_path(sha);
+            // Packed objects have no loose file to delete
+            let Ok(metadata) = fs::metadata(&path) else {
+                continue;
+            };
+            if metadata.modified()? < expire {
+                if !dry_run {
+                    fs::remove_file(&path)?;
+                }
+                pruned.push(sha.clone());
+            }
+        }
+        if !dry_run {
+            self.remove_empty_object_dirs()?;
+        }
+        Ok(pruned)
+    }
+
+    /// Removes the fan-out directories under `objects/` that hold no
+    /// object anymore, returning how many were removed
+    fn remove_empty_object_dirs(&self) -> io::Result<usize> {
+        let mut removed = 0;
+        for dir in fs::read_dir(self.git_dir.join(OBJECTS_DIR))? {
+            let dir = dir?;
+            let name = dir.file_name().to_string_lossy().to_string();
+            let is_fan_out = name.len() == 2 && name.chars().all(|c| c.is_ascii_hexdigit());
+            // Removing a directory fails unless it is empty
+            if is_fan_out && dir.file_type()?.is_dir() && fs::remove_dir(dir.path()).is_ok() {
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+}
+
+/// Parses an expiry time for [`Repository::prune`]: `now`, `never`, a
+/// relative time like `2.weeks.ago` or `3 days ago`, or a date like
+/// `2024-01-31`
+pub fn parse_expire(spec: &str) -> Result<SystemTime, Error> {
+    let invalid = || Error::Other(format!("invalid expiry time '{spec}'"));
+    match spec {
+        "now" => return Ok(SystemTime::now()),
+        "never" => return Ok(SystemTime::UNIX_EPOCH),
+        _ => {}
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(spec, "%Y-%m-%d") {
+        let midnight = date
+            .and_hms_opt(0, 0, 0)
+            .and_then(|time| time.and_local_timezone(Local).earliest())
+            .ok_or_else(invalid)?;
+        return Ok(midnight.into());
+    }
+    let parts: Vec<&str> = spec.split(['.', ' ']).collect();
+    let [count, unit, "ago"] = parts[..] else {
+        return Err(invalid());
+    };
+    let count: u64 = count.parse().map_err(|_| invalid())?;
+    let seconds = match unit.strip_suffix('s').unwrap_or(unit) {
+        "second" => 1,
+        "minute" => 60,
+        "hour" => 60 * 60,
+        "day" => 24 * 60 * 60,
+        "week" => 7 * 24 * 60 * 60,
+        "month" => 30 * 24 * 60 * 60,
+        "year" => 365 * 24 * 60 * 60,
+        _ => return Err(invalid()),
+    };
+    SystemTime::now()
+        .checked_sub(Duration::from_secs(count.saturating_mul(seconds)))
+        .ok_or_else(invalid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::Blob;
+    use crate::repo::init_test_repo;
+    use tempfile::TempDir;
+
+    #[test]
+    fn gc_and_prune_delete_old_unreachable_objects() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        let file = repo.dir.join("a.txt");
+        fs::write(&file, "kept\n").unwrap();
+        repo.add(&vec![file.to_str().unwrap()]).unwrap();
+        repo.commit("first").unwrap();
+        let store = |data: &[u8]| {
+            repo.obj_db
+                .store(&Blob {
+                    data: data.to_vec(),
+                })
+                .unwrap()
+        };
+        let old = store(b"old\n");
+        let young = store(b"young\n");
+        let month_ago = SystemTime::now() - Duration::from_secs(30 * 24 * 60 * 60);
+        fs::File::options()
+            .write(true)
+            .open(repo.obj_db.object_path(&old))
+            .unwrap()
+            .set_modified(month_ago)
+            .unwrap();
+
+        repo.gc().unwrap();
+        assert!(!repo.obj_db.object_path(&old).exists());
+        assert!(repo.obj_db.object_path(&young).exists());
+        assert!(repo.fsck_report().unwrap().is_ok());
+        assert!(!repo.get_branch_dir().join("master").exists());
+        assert_eq!(
+            repo.get_current_commit(),
+            repo.resolve_commit("master").ok()
+        );
+        for dir in fs::read_dir(repo.git_dir.join(OBJECTS_DIR)).unwrap() {
+            let dir = dir.unwrap().path();
+            if dir.is_dir() {
+                assert!(fs::read_dir(&dir).unwrap().next().is_some());
+            }
+        }
+
+        // Dry runs only report
+        let now = parse_expire("now").unwrap();
+        assert_eq!(repo.prune(now, true).unwrap(), vec![young.clone()]);
+        assert!(repo.obj_db.object_path(&young).exists());
+        assert!(
+            repo.prune(parse_expire("1.day.ago").unwrap(), false)
+                .unwrap()
+                .is_empty()
+        );
+        assert!(parse_expire("2 weeks ago").unwrap() < parse_expire("1.week.ago").unwrap());
+        assert!(parse_expire("2024-01-31").is_ok());
+        assert!(parse_expire("soon").is_err());
+
+        // A damaged store is left alone
+        fs::remove_file(repo.obj_db.object_path(&repo.get_current_commit().unwrap())).unwrap();
+        assert!(repo.gc().is_err());
+        assert!(repo.prune(now, false).is_err());
+        assert!(repo.obj_db.object_path(&young).exists());
+    }
+}
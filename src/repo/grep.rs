@@ -0,0 +1,244 @@
+use memchr::memchr;
+use regex::{Regex, RegexBuilder};
+
+use super::pathspec::{matches_pathspec, may_contain_matches, normalize_pathspecs};
+use super::{Repository, Snapshot, TreeWalkStep};
+use crate::Error;
+use crate::object::{Blob, Commit, ObjectType};
+use std::fs;
+
+/// Options of [`Repository::grep`]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GrepOptions {
+    /// Prefix matching lines with their 1-based line number, like `-n`
+    pub line_number: bool,
+    /// Match regardless of case, like `-i`
+    pub ignore_case: bool,
+}
+
+impl Repository {
+    /// Searches tracked files for lines matching a regular expression.
+    ///
+    /// # Arguments
+    /// * `pattern` - Regular expression to search for
+    /// * `source` - What to search: the working tree copy of the files the
+    ///   index tracks, the staged blobs, or the blobs of a commit's tree;
+    ///   matches in a commit are prefixed with `<rev>:` like `git grep` does
+    /// * `options` - How matches are found and printed
+    /// * `paths` - Optional pathspecs limiting the search to files or directories
+    ///
+    /// # Returns
+    /// - `Ok(bool)`: Whether at least one line matched
+    /// - `Err(Error)`: The pattern is invalid or a file cannot be read
+    pub fn grep<S: AsRef<str>>(
+        &self,
+        pattern: &str,
+        source: Snapshot,
+        options: GrepOptions,
+        paths: &[S],
+    ) -> Result<bool, Error> {
+        let regex = RegexBuilder::new(pattern)
+            .case_insensitive(options.ignore_case)
+            .build()
+            .map_err(|why| Error::Other(format!("invalid pattern: {why}")))?;
+        let pathspecs = normalize_pathspecs(paths);
+
+        let mut found = false;
+        let mut report = |name: &str, data: &[u8]| {
+            let lines = grep_buffer(&regex, name, data, options.line_number);
+            found |= !lines.is_empty();
+            for line in lines {
+                println!("{line}");
+            }
+        };
+        match source {
+            Snapshot::Worktree => self.grep_worktree(&pathspecs, &mut report)?,
+            Snapshot::Index => self.grep_index(&pathspecs, &mut report)?,
+            Snapshot::Commit(rev) => self.grep_revision(rev, &pathspecs, &mut report)?,
+        }
+        Ok(found)
+    }
+
+    /// Greps the working tree copy of every file tracked by the index
+    fn grep_worktree(
+        &self,
+        pathspecs: &[String],
+        report: &mut dyn FnMut(&str, &[u8]),
+    ) -> Result<(), String> {
+        let index = self.load_index_or_empty()?;
+        for (path, _) in index.collect_entries() {
+            if !matches_pathspec(&path, pathspecs) {
+                continue;
+            }
+            // Deleted but still staged files have nothing to search
+            if let Ok(data) = fs::read(self.dir.join(&path)) {
+                report(&path, &data);
+            }
+        }
+        Ok(())
+    }
+
+    /// Greps the staged blobs of the files in the index
+    fn grep_index(
+        &self,
+        pathspecs: &[String],
+        report: &mut dyn FnMut(&str, &[u8]),
+    ) -> Result<(), String> {
+        let index = self.load_index_or_empty()?;
+        for (path, sha) in index.collect_entries() {
+            if !matches_pathspec(&path, pathspecs) {
+                continue;
+            }
+            let data = self.obj_db.retrieve(&sha).map_err(|why| why.to_string())?;
+            report(&path, &Blob::deserialize(&data)?.data);
+        }
+        Ok(())
+    }
+
+    /// Greps the blobs reachable from the tree of `rev`.
+    /// Trees are walked lazily and each blob is dropped once searched.
+    fn grep_revision(
+        &self,
+        rev: &str,
+        pathspecs: &[String],
+        report: &mut dyn FnMut(&str, &[u8]),
+    ) -> Result<(), String> {
+        let commit_sha = self.resolve_commit(rev)?;
+        let commit_data = self
+            .obj_db
+            .retrieve(&commit_sha)
+            .map_err(|why| why.to_string())?;
+        let commit = Commit::deserialize(&commit_data)?;
+
+        let mut error = None;
+        self.walk_tree(&commit.get_tree_sha(), |entry| match entry.object_type {
+            ObjectType::Tree if may_contain_matches(&entry.path, pathspecs) => {
+                TreeWalkStep::Continue
+            }
+            ObjectType::Blob if matches_pathspec(&entry.path, pathspecs) => {
+                let blob = self
+                    .obj_db
+                    .retrieve(&entry.sha)
+                    .map_err(|why| why.to_string())
+                    .and_then(|data| Blob::deserialize(&data));
+                match blob {
+                    Ok(blob) => {
+                        report(&format!("{rev}:{}", entry.path), &blob.data);
+                        TreeWalkStep::Continue
+                    }
+                    Err(why) => {
+                        error = Some(why);
+                        TreeWalkStep::Stop
+                    }
+                }
+            }
+            // Submodule contents live in another repository
+            _ => TreeWalkStep::Skip,
+        })
+        .map_err(|why| why.to_string())?;
+        error.map_or(Ok(()), Err)
+    }
+}
+
+/// Renders the matching lines of `data` as `grep` prints them, prefixed
+/// with `name` and, if `line_number`, the line number. Binary content
+/// (containing a NUL byte) is only reported as a whole.
+fn grep_buffer(regex: &Regex, name: &str, data: &[u8], line_number: bool) -> Vec<String> {
+    let text = String::from_utf8_lossy(data);
+    if memchr(0, data).is_some() {
+        if regex.is_match(&text) {
+            return vec![format!("Binary file {name} matches")];
+        }
+        return Vec::new();
+    }
+
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| regex.is_match(line))
+        .map(|(i, line)| match line_number {
+            true => format!("{name}:{}:{line}", i + 1),
+            false => format!("{name}:{line}"),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::init_test_repo;
+    use tempfile::TempDir;
+
+    fn write(repo: &Repository, path: &str, content: &str) -> String {
+        let full_path = repo.dir.join(path);
+        if let Some(parent) = full_path.parent() {
+            fs::create_dir_all(parent).unwrap();
+        }
+        fs::write(&full_path, content).unwrap();
+        full_path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn grep_searches_worktree_and_history() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        let a = write(&repo, "a.txt", "hello\nold line\n");
+        let b = write(&repo, "src/b.txt", "another old line\n");
+        repo.add(&vec![a.clone(), b]).unwrap();
+        repo.commit("first").unwrap();
+
+        write(&repo, "a.txt", "hello\nnew line\n");
+        repo.add(&vec![a]).unwrap();
+
+        let none: [&str; 0] = [];
+        let options = GrepOptions::default();
+        let head = Snapshot::Commit("HEAD");
+        assert!(
+            repo.grep("new", Snapshot::Worktree, options, &none)
+                .unwrap()
+        );
+        assert!(!repo.grep("new", head, options, &none).unwrap());
+        assert!(repo.grep("old", head, options, &none).unwrap());
+        let master = Snapshot::Commit("master");
+        assert!(repo.grep("old", master, options, &["src"]).unwrap());
+        assert!(!repo.grep("hello", head, options, &["src/"]).unwrap());
+
+        // The index holds the staged content, not the working tree's
+        write(
+            &repo,
+            "a.txt",
+            "hello
+newer line
+",
+        );
+        assert!(
+            repo.grep("newer", Snapshot::Worktree, options, &none)
+                .unwrap()
+        );
+        assert!(!repo.grep("newer", Snapshot::Index, options, &none).unwrap());
+        assert!(
+            repo.grep("new line", Snapshot::Index, options, &none)
+                .unwrap()
+        );
+
+        let ignore_case = GrepOptions {
+            ignore_case: true,
+            ..options
+        };
+        assert!(!repo.grep("HELLO", head, options, &none).unwrap());
+        assert!(repo.grep("HELLO", head, ignore_case, &none).unwrap());
+    }
+
+    #[test]
+    fn grep_buffer_numbers_lines() {
+        let regex = Regex::new("b").unwrap();
+        assert_eq!(
+            grep_buffer(&regex, "f", b"a\nb\nab\n", true),
+            ["f:2:b", "f:3:ab"]
+        );
+        assert_eq!(grep_buffer(&regex, "f", b"b", false), ["f:b"]);
+        assert_eq!(
+            grep_buffer(&regex, "f", b"b\0", false),
+            ["Binary file f matches"]
+        );
+    }
+}
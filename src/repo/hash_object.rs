@@ -0,0 +1,48 @@
+use super::Repository;
+use crate::object::{Blob, Object};
+use crate::{EncodedSha, Error};
+
+/// The SHA1 `data` has as a blob, like `git hash-object` outside of a
+/// repository
+pub fn hash_blob(data: &[u8]) -> EncodedSha {
+    EncodedSha(
+        Blob {
+            data: data.to_vec(),
+        }
+        .encoded_sha1(),
+    )
+}
+
+impl Repository {
+    /// Computes the SHA1 `data` has as a blob, like `git hash-object`, and
+    /// with `write` stores the blob in the object database
+    pub fn hash_object(&self, data: &[u8], write: bool) -> Result<EncodedSha, Error> {
+        if !write {
+            return Ok(hash_blob(data));
+        }
+        Ok(self.obj_db.store(&Blob {
+            data: data.to_vec(),
+        })?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::init_test_repo;
+    use tempfile::TempDir;
+
+    #[test]
+    fn hash_object_matches_git_and_writes_on_request() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        // `echo hello | git hash-object --stdin`
+        let sha = hash_blob(b"hello\n");
+        assert_eq!(sha.0, "ce013625030ba8dba906f756967f9e9ca394464a");
+
+        assert_eq!(repo.hash_object(b"hello\n", false).unwrap(), sha);
+        assert!(repo.obj_db.retrieve(&sha).is_err());
+        assert_eq!(repo.hash_object(b"hello\n", true).unwrap(), sha);
+        assert_eq!(repo.blob_content(&sha).unwrap(), b"hello\n");
+    }
+}
@@ -0,0 +1,267 @@
+use crate::diff::diff_slices;
+use similar::DiffOp;
+use std::collections::HashMap;
+
+/// Lines occurring more often than this on the old side are not used to
+/// anchor a match; a range without rarer lines falls back to Myers
+const MAX_CHAIN: usize = 64;
+
+/// Diffs two sequences of lines with the histogram algorithm of
+/// `git diff --histogram`. The region of common lines whose rarest line
+/// occurs least often on the old side anchors the diff, and the lines
+/// before and after it are diffed the same way. Unlike patience diff,
+/// lines need not be unique to anchor a region.
+pub(super) fn histogram_diff(old: &[&str], new: &[&str]) -> Vec<DiffOp> {
+    let mut ops = Ops(Vec::new());
+    // Ranges still to diff, and the regions found between them, in
+    // reverse order of output
+    let mut pending = vec![Task::Diff(0, old.len(), 0, new.len())];
+    while let Some(task) = pending.pop() {
+        let (a_start, a_end, b_start, b_end) = match task {
+            Task::Equal(old_index, new_index, len) => {
+                ops.equal(old_index, new_index, len);
+                continue;
+            }
+            Task::Diff(a_start, a_end, b_start, b_end) => (a_start, a_end, b_start, b_end),
+        };
+        // Common lines at both ends always match
+        let prefix = (0..(a_end - a_start).min(b_end - b_start))
+            .take_while(|&i| old[a_start + i] == new[b_start + i])
+            .count();
+        let (a_start, b_start) = (a_start + prefix, b_start + prefix);
+        let suffix = (0..(a_end - a_start).min(b_end - b_start))
+            .take_while(|&i| old[a_end - 1 - i] == new[b_end - 1 - i])
+            .count();
+        let (a_end, b_end) = (a_end - suffix, b_end - suffix);
+        ops.equal(a_start - prefix, b_start - prefix, prefix);
+        pending.push(Task::Equal(a_end, b_end, suffix));
+
+        if a_start == a_end || b_start == b_end {
+            ops.delete(a_start, a_end - a_start, b_start);
+            ops.insert(a_end, b_start, b_end - b_start);
+            continue;
+        }
+        match best_region(old, new, a_start, a_end, b_start, b_end) {
+            Some(Region::Found(a, b, len)) => {
+                pending.push(Task::Diff(a + len, a_end, b + len, b_end));
+                pending.push(Task::Equal(a, b, len));
+                pending.push(Task::Diff(a_start, a, b_start, b));
+            }
+            Some(Region::TooCommon) => {
+                let (mut old_at, mut new_at) = (a_start, b_start);
+                for hunk in diff_slices(&old[a_start..a_end], &new[b_start..b_end]) {
+                    let (old_index, new_index) =
+                        (a_start + hunk.old.start, b_start + hunk.new.start);
+                    ops.equal(old_at, new_at, old_index - old_at);
+                    ops.delete(old_index, hunk.old.len(), new_index);
+                    ops.insert(old_index + hunk.old.len(), new_index, hunk.new.len());
+                    (old_at, new_at) = (a_start + hunk.old.end, b_start + hunk.new.end);
+                }
+                ops.equal(old_at, new_at, a_end - old_at);
+            }
+            None => {
+                ops.delete(a_start, a_end - a_start, b_start);
+                ops.insert(a_end, b_start, b_end - b_start);
+            }
+        }
+    }
+    ops.0
+}
+
+/// A step of [`histogram_diff`]
+enum Task {
+    /// Diff the old lines from `.0` to `.1` against the new lines from
+    /// `.2` to `.3`
+    Diff(usize, usize, usize, usize),
+    /// Lines matched between the old and new index, and how many
+    Equal(usize, usize, usize),
+}
+
+/// The anchor [`best_region`] picked
+enum Region {
+    /// Matching lines at the old and new index, and how many
+    Found(usize, usize, usize),
+    /// The ranges have common lines, but all of them are too common
+    TooCommon,
+}
+
+/// Finds the run of matching lines between `old[a_start..a_end]` and
+/// `new[b_start..b_end]` whose rarest old line occurs least often, the
+/// longest one among equally rare runs
+fn best_region(
+    old: &[&str],
+    new: &[&str],
+    a_start: usize,
+    a_end: usize,
+    b_start: usize,
+    b_end: usize,
+) -> Option<Region> {
+    let mut histogram: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (i, line) in old.iter().enumerate().take(a_end).skip(a_start) {
+        histogram.entry(line).or_default().push(i);
+    }
+    let count = |line: &str| histogram.get(line).map_or(0, Vec::len);
+
+    // (occurrences of the rarest line, old index, new index, length)
+    let mut best: Option<(usize, usize, usize, usize)> = None;
+    let mut too_common = false;
+    let mut b = b_start;
+    while b < b_end {
+        let mut next = b + 1;
+        let Some(positions) = histogram.get(new[b]) else {
+            b = next;
+            continue;
+        };
+        if positions.len() > MAX_CHAIN {
+            too_common = true;
+            b = next;
+            continue;
+        }
+        for &a in positions {
+            let (mut start_a, mut start_b) = (a, b);
+            while start_a > a_start && start_b > b_start && old[start_a - 1] == new[start_b - 1] {
+                start_a -= 1;
+                start_b -= 1;
+            }
+            let (mut end_a, mut end_b) = (a + 1, b + 1);
+            while end_a < a_end && end_b < b_end && old[end_a] == new[end_b] {
+                end_a += 1;
+                end_b += 1;
+            }
+            let rarest = old[start_a..end_a]
+                .iter()
+                .map(|line| count(line))
+                .min()
+                .unwrap_or_default();
+            let len = end_a - start_a;
+            let better = best.is_none_or(|(best_rarest, _, _, best_len)| {
+                rarest < best_rarest || (rarest == best_rarest && len > best_len)
+            });
+            if better {
+                best = Some((rarest, start_a, start_b, len));
+            }
+            next = next.max(end_b);
+        }
+        b = next;
+    }
+    match best {
+        Some((_, a, b, len)) => Some(Region::Found(a, b, len)),
+        None if too_common => Some(Region::TooCommon),
+        None => None,
+    }
+}
+
+/// Diff operations, merging each one into the previous one of its kind
+struct Ops(Vec<DiffOp>);
+
+impl Ops {
+    fn equal(&mut self, old_index: usize, new_index: usize, len: usize) {
+        if len == 0 {
+            return;
+        }
+        if let Some(DiffOp::Equal {
+            old_index: last_old,
+            len: last_len,
+            ..
+        }) = self.0.last_mut()
+            && *last_old + *last_len == old_index
+        {
+            *last_len += len;
+            return;
+        }
+        self.0.push(DiffOp::Equal {
+            old_index,
+            new_index,
+            len,
+        });
+    }
+
+    fn delete(&mut self, old_index: usize, old_len: usize, new_index: usize) {
+        if old_len == 0 {
+            return;
+        }
+        if let Some(DiffOp::Delete {
+            old_index: last_old,
+            old_len: last_len,
+            ..
+        }) = self.0.last_mut()
+            && *last_old + *last_len == old_index
+        {
+            *last_len += old_len;
+            return;
+        }
+        self.0.push(DiffOp::Delete {
+            old_index,
+            old_len,
+            new_index,
+        });
+    }
+
+    fn insert(&mut self, old_index: usize, new_index: usize, new_len: usize) {
+        if new_len == 0 {
+            return;
+        }
+        if let Some(DiffOp::Insert {
+            new_index: last_new,
+            new_len: last_len,
+            ..
+        }) = self.0.last_mut()
+            && *last_new + *last_len == new_index
+        {
+            *last_len += new_len;
+            return;
+        }
+        self.0.push(DiffOp::Insert {
+            old_index,
+            new_index,
+            new_len,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use similar::ChangeTag;
+
+    /// The lines of a diff prefixed with ` `, `-` or `+`
+    fn render(old: &[&str], new: &[&str]) -> Vec<String> {
+        histogram_diff(old, new)
+            .iter()
+            .flat_map(|op| op.iter_changes(old, new))
+            .map(|change| {
+                let sign = match change.tag() {
+                    ChangeTag::Equal => ' ',
+                    ChangeTag::Delete => '-',
+                    ChangeTag::Insert => '+',
+                };
+                format!("{sign}{}", change.value())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn rare_lines_anchor_the_diff() {
+        // The unique `b` anchors, not the `}` coming first
+        let old = ["a", "}", "b", "}"];
+        let new = ["b", "}", "c", "}"];
+        assert_eq!(render(&old, &new), ["-a", "-}", " b", "+}", "+c", " }"]);
+        assert_eq!(render(&old, &old), [" a", " }", " b", " }"]);
+        assert_eq!(render(&old, &[]), ["-a", "-}", "-b", "-}"]);
+        assert_eq!(render(&["x"], &["y"]), ["-x", "+y"]);
+    }
+
+    #[test]
+    fn too_common_lines_fall_back_to_myers() {
+        let mut old = vec!["x"; MAX_CHAIN + 1];
+        old.insert(0, "a");
+        old.push("b");
+        let mut new = vec!["x"; MAX_CHAIN + 2];
+        new.insert(0, "c");
+        new.push("d");
+        let changes = render(&old, &new);
+        let count = |line: &str| changes.iter().filter(|change| *change == line).count();
+        assert_eq!(count(" x"), MAX_CHAIN + 1);
+        assert_eq!(count("+x"), 1);
+    }
+}
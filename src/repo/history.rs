@@ -0,0 +1,269 @@
+use super::Repository;
+use crate::EncodedSha;
+use crate::diff::line_similarity;
+use crate::index::Index;
+use crate::object::Commit;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashSet};
+
+/// Renamed files must share at least this fraction of their lines
+pub(super) const RENAME_SIMILARITY: f32 = 0.5;
+
+/// A commit that changed a file, as yielded by `file_history`
+#[derive(Debug)]
+pub struct FileRevision {
+    pub commit_sha: EncodedSha,
+    pub commit: Commit,
+    /// Path of the file in this commit, which differs from the requested
+    /// path once a rename has been followed
+    pub path: String,
+    /// Content of the file in this commit; `None` if the commit deleted it
+    pub blob: Option<EncodedSha>,
+}
+
+impl Repository {
+    /// Lists the commits reachable from HEAD that changed `path`, newest
+    /// first, together with the blob the file had in each of them.
+    ///
+    /// Merges are only listed when the file differs from every parent, and
+    /// history is then only followed through a parent that has the same
+    /// file. With `follow`, a commit that adds the file is checked for a
+    /// file it was renamed from, and older history is followed under that
+    /// name.
+    pub fn file_history(
+        &self,
+        path: &str,
+        follow: bool,
+    ) -> impl Iterator<Item = FileRevision> + '_ {
+        self.file_history_from(self.get_current_commit(), path, follow)
+    }
+
+    /// Like [`Self::file_history`], but from the commit `tip` instead of
+    /// HEAD
+    pub(super) fn file_history_from(
+        &self,
+        tip: Option<EncodedSha>,
+        path: &str,
+        follow: bool,
+    ) -> impl Iterator<Item = FileRevision> + '_ {
+        let mut history = FileHistory {
+            repo: self,
+            pending: BinaryHeap::new(),
+            seen: HashSet::new(),
+            follow,
+        };
+        if let Some(tip) = tip {
+            history.push(tip, path.to_string());
+        }
+        history
+    }
+
+    /// Finds the file in `parent` that `blob` was renamed from:
+    /// an identical file if there is one, otherwise the most similar file
+    /// among those that no longer exist in `index`
+    fn find_rename_source(
+        &self,
+        index: &Index,
+        parent: &Index,
+        blob: &EncodedSha,
+    ) -> Option<String> {
+        let removed: Vec<(String, EncodedSha)> = parent
+            .collect_entries()
+            .into_iter()
+            .filter(|(name, _)| index.get_sha1(name).is_none())
+            .collect();
+        if let Some((name, _)) = removed.iter().find(|(_, sha)| sha == blob) {
+            return Some(name.clone());
+        }
+        let text = self.blob_text(Some(blob)).ok()?;
+        removed
+            .into_iter()
+            .filter_map(|(name, sha)| {
+                let old_text = self.blob_text(Some(&sha)).ok()?;
+                let ratio = line_similarity(&old_text, &text);
+                (ratio >= RENAME_SIMILARITY).then_some((ratio, name))
+            })
+            .max_by(|a, b| a.0.total_cmp(&b.0))
+            .map(|(_, name)| name)
+    }
+}
+
+/// A commit waiting to be visited, ordered by committer date
+struct Pending {
+    time: i64,
+    sha: EncodedSha,
+    path: String,
+    commit: Commit,
+}
+
+impl PartialEq for Pending {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Pending {}
+
+impl PartialOrd for Pending {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Pending {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.time
+            .cmp(&other.time)
+            .then_with(|| self.sha.0.cmp(&other.sha.0))
+    }
+}
+
+/// Walk over the history of one file, newest commit first
+struct FileHistory<'r> {
+    repo: &'r Repository,
+    pending: BinaryHeap<Pending>,
+    seen: HashSet<EncodedSha>,
+    follow: bool,
+}
+
+impl FileHistory<'_> {
+    /// Queues a commit to be checked for changes to `path`. Commits that
+    /// cannot be read end the walk along that line of history.
+    fn push(&mut self, sha: EncodedSha, path: String) {
+        if self.seen.contains(&sha) {
+            return;
+        }
+        if let Ok(commit) = self.repo.read_commit(&sha) {
+            self.pending.push(Pending {
+                time: commit.get_committer().get_timestamp().timestamp(),
+                sha,
+                path,
+                commit,
+            });
+        }
+    }
+}
+
+impl Iterator for FileHistory<'_> {
+    type Item = FileRevision;
+
+    fn next(&mut self) -> Option<FileRevision> {
+        while let Some(Pending {
+            sha, path, commit, ..
+        }) = self.pending.pop()
+        {
+            if !self.seen.insert(sha.clone()) {
+                continue;
+            }
+            let Ok(index) = self.repo.read_tree(&commit.get_tree_sha()) else {
+                continue;
+            };
+            let blob = index.get_sha1(&path).cloned();
+
+            let mut parents = Vec::new();
+            for parent_sha in commit.get_parents() {
+                let parent_index = self
+                    .repo
+                    .read_commit(parent_sha)
+                    .and_then(|parent| self.repo.read_tree(&parent.get_tree_sha()))
+                    .unwrap_or_else(|_| Index::new());
+                let parent_blob = parent_index.get_sha1(&path).cloned();
+                parents.push((parent_sha.clone(), parent_index, parent_blob));
+            }
+
+            // Unchanged relative to a parent: the file's history continues there
+            if let Some((parent_sha, _, _)) = parents.iter().find(|(_, _, b)| *b == blob) {
+                self.push(parent_sha.clone(), path);
+                continue;
+            }
+            for (parent_sha, parent_index, parent_blob) in &parents {
+                let parent_path = match (&blob, parent_blob) {
+                    (Some(blob), None) if self.follow => self
+                        .repo
+                        .find_rename_source(&index, parent_index, blob)
+                        .unwrap_or_else(|| path.clone()),
+                    _ => path.clone(),
+                };
+                self.push(parent_sha.clone(), parent_path);
+            }
+            // A root commit without the file did not touch it
+            if blob.is_none() && parents.is_empty() {
+                continue;
+            }
+            return Some(FileRevision {
+                commit_sha: sha,
+                commit,
+                path,
+                blob,
+            });
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{AUTHOR_EMAIL, AUTHOR_NAME};
+    use super::*;
+    use crate::repo::init_test_repo;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn commit_all(repo: &Repository, message: &str) -> EncodedSha {
+        let tree = repo.write_tree().unwrap();
+        let parent = repo.get_current_commit();
+        let parents = parent.clone().into_iter().collect();
+        let commit = repo
+            .commit_tree(tree, parents, message, AUTHOR_NAME, AUTHOR_EMAIL)
+            .unwrap();
+        repo.update_head(parent.as_ref(), &commit, message).unwrap();
+        commit
+    }
+
+    #[test]
+    fn lists_changes_and_follows_renames() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        let write = |path: &str, content: &str| {
+            let file = repo.dir.join(path);
+            fs::write(&file, content).unwrap();
+            repo.update_index(&file).unwrap();
+        };
+
+        write("old.txt", "one\ntwo\nthree\n");
+        let added = commit_all(&repo, "add");
+        write("other.txt", "unrelated\n");
+        commit_all(&repo, "other");
+        write("old.txt", "one\ntwo\nthree\nfour\n");
+        let edited = commit_all(&repo, "edit");
+
+        let mut index = Index::load(&repo.get_index_path()).unwrap();
+        index.remove_entry("old.txt");
+        index.save(&repo.get_index_path()).unwrap();
+        fs::remove_file(repo.dir.join("old.txt")).unwrap();
+        write("new.txt", "one\ntwo\nthree\nfour\nfive\n");
+        let renamed = commit_all(&repo, "rename");
+
+        let history: Vec<_> = repo.file_history("new.txt", false).collect();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].commit_sha, renamed);
+
+        let history: Vec<_> = repo
+            .file_history("new.txt", true)
+            .map(|rev| (rev.commit_sha, rev.path))
+            .collect();
+        assert_eq!(
+            history,
+            vec![
+                (renamed, "new.txt".to_string()),
+                (edited, "old.txt".to_string()),
+                (added.clone(), "old.txt".to_string()),
+            ]
+        );
+
+        let deleted: Vec<_> = repo.file_history("old.txt", false).collect();
+        assert_eq!(deleted.len(), 3);
+        assert!(deleted[0].blob.is_none());
+        assert_eq!(deleted[2].commit_sha, added);
+    }
+}
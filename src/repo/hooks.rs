@@ -0,0 +1,170 @@
+use super::Repository;
+use crate::{EncodedSha, Error};
+
+/// What is about to be committed
+#[derive(Debug)]
+pub struct CommitInfo<'a> {
+    pub tree: &'a EncodedSha,
+    pub parents: &'a [EncodedSha],
+    pub message: &'a str,
+}
+
+/// A switch of HEAD from one commit to another
+#[derive(Debug)]
+pub struct CheckoutInfo<'a> {
+    /// Branch being checked out
+    pub branch: &'a str,
+    /// Commit HEAD pointed at before, `None` on an unborn branch
+    pub old: Option<&'a EncodedSha>,
+    /// Commit HEAD points at afterwards, `None` on an unborn branch
+    pub new: Option<&'a EncodedSha>,
+}
+
+type CommitValidator = Box<dyn Fn(&Repository, &CommitInfo) -> Result<(), Error>>;
+type CommitObserver = Box<dyn Fn(&Repository, &EncodedSha)>;
+type CheckoutValidator = Box<dyn Fn(&Repository, &CheckoutInfo) -> Result<(), Error>>;
+type CheckoutObserver = Box<dyn Fn(&Repository, &CheckoutInfo)>;
+
+/// Callbacks registered on a `Repository`, for embedders that want to
+/// validate or react to commits and checkouts without hook scripts
+#[derive(Default)]
+pub(super) struct Hooks {
+    pre_commit: Vec<CommitValidator>,
+    post_commit: Vec<CommitObserver>,
+    pre_checkout: Vec<CheckoutValidator>,
+    post_checkout: Vec<CheckoutObserver>,
+}
+
+impl Repository {
+    /// Registers a callback run before a commit is written. Returning an
+    /// error aborts the commit with that message.
+    pub fn on_pre_commit<F>(&mut self, hook: F)
+    where
+        F: Fn(&Repository, &CommitInfo) -> Result<(), Error> + 'static,
+    {
+        self.hooks.pre_commit.push(Box::new(hook));
+    }
+
+    /// Registers a callback run after a commit has been written and HEAD
+    /// moved to it
+    pub fn on_post_commit<F>(&mut self, hook: F)
+    where
+        F: Fn(&Repository, &EncodedSha) + 'static,
+    {
+        self.hooks.post_commit.push(Box::new(hook));
+    }
+
+    /// Registers a callback run before the working tree is switched to
+    /// another branch. Returning an error aborts the checkout untouched.
+    pub fn on_pre_checkout<F>(&mut self, hook: F)
+    where
+        F: Fn(&Repository, &CheckoutInfo) -> Result<(), Error> + 'static,
+    {
+        self.hooks.pre_checkout.push(Box::new(hook));
+    }
+
+    /// Registers a callback run once a checkout has updated HEAD, the index
+    /// and the working tree
+    pub fn on_post_checkout<F>(&mut self, hook: F)
+    where
+        F: Fn(&Repository, &CheckoutInfo) + 'static,
+    {
+        self.hooks.post_checkout.push(Box::new(hook));
+    }
+
+    /// Runs the pre-commit callbacks, stopping at the first rejection
+    pub(super) fn run_pre_commit(&self, info: &CommitInfo) -> Result<(), Error> {
+        self.hooks
+            .pre_commit
+            .iter()
+            .try_for_each(|hook| hook(self, info))
+    }
+
+    pub(super) fn run_post_commit(&self, commit_sha: &EncodedSha) {
+        for hook in &self.hooks.post_commit {
+            hook(self, commit_sha);
+        }
+    }
+
+    /// Runs the pre-checkout callbacks, stopping at the first rejection
+    pub(super) fn run_pre_checkout(&self, info: &CheckoutInfo) -> Result<(), Error> {
+        self.hooks
+            .pre_checkout
+            .iter()
+            .try_for_each(|hook| hook(self, info))
+    }
+
+    pub(super) fn run_post_checkout(&self, info: &CheckoutInfo) {
+        for hook in &self.hooks.post_checkout {
+            hook(self, info);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::init_test_repo;
+    use std::cell::RefCell;
+    use std::fs;
+    use std::rc::Rc;
+    use tempfile::TempDir;
+
+    #[test]
+    fn hooks_run_around_commit_and_checkout() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut repo = init_test_repo(temp_dir.path());
+        let events = Rc::new(RefCell::new(Vec::new()));
+
+        repo.on_pre_commit(|_, info| {
+            if info.message.starts_with("wip") {
+                Err("no wip commits".into())
+            } else {
+                Ok(())
+            }
+        });
+        let log = events.clone();
+        repo.on_post_commit(move |_, sha| log.borrow_mut().push(format!("commit {sha}")));
+        repo.on_pre_checkout(|_, info| {
+            if info.branch == "frozen" {
+                Err("frozen is read-only".into())
+            } else {
+                Ok(())
+            }
+        });
+        let log = events.clone();
+        repo.on_post_checkout(move |_, info| {
+            log.borrow_mut()
+                .push(format!("checkout {} {}", info.branch, info.new.is_some()))
+        });
+
+        let file = repo.dir.join("a.txt");
+        fs::write(&file, "a").unwrap();
+        repo.add(&vec![file.to_str().unwrap()]).unwrap();
+        let info = CommitInfo {
+            tree: &repo.write_tree().unwrap(),
+            parents: &[],
+            message: "wip: later",
+        };
+        assert_eq!(
+            repo.run_pre_commit(&info).unwrap_err().to_string(),
+            "no wip commits"
+        );
+
+        repo.commit("first").unwrap();
+        let head = repo.get_current_commit().unwrap();
+        repo.branch("topic").unwrap();
+        repo.checkout("topic", false).unwrap();
+        assert_eq!(
+            *events.borrow(),
+            vec![format!("commit {head}"), "checkout topic true".to_string()]
+        );
+
+        let info = CheckoutInfo {
+            branch: "frozen",
+            old: Some(&head),
+            new: Some(&head),
+        };
+        assert!(repo.run_pre_checkout(&info).is_err());
+    }
+}
@@ -0,0 +1,297 @@
+use super::{Branch, HEADS_DIR, Head, REFS_DIR, Repository};
+use crate::git_source::{GitHead, GitKind, GitSource};
+use crate::index::Index;
+use crate::object::{Blob, Commit, FileMode, ObjectDB, ObjectType, Tree};
+use crate::{EncodedSha, Error};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// File in the git dir recording `<git commit> <imported commit>` pairs
+const IMPORT_MAP_FILE: &str = "import-git-map";
+
+impl Repository {
+    /// Imports the history of an existing git repository into this
+    /// repository, which must not have any commits yet.
+    ///
+    /// Every branch under `refs/heads` is converted together with the
+    /// commits, trees and blobs it reaches, HEAD is pointed at the same
+    /// branch, and the working tree is populated from it. Trees and commits
+    /// are rewritten into this tool's formats, so their SHA1s change; the
+    /// mapping of commit ids is saved to `.git/import-git-map`.
+    pub fn import_git(&self, path: &str) -> Result<(), Error> {
+        Ok(self.import_git_impl(Path::new(path))?)
+    }
+
+    fn import_git_impl(&self, path: &Path) -> Result<(), String> {
+        if self.get_current_commit().is_some() {
+            return Err("import-git needs a repository without commits".to_string());
+        }
+        let source = GitSource::open(path)?;
+        let quarantine = self.obj_db.quarantine().map_err(|why| why.to_string())?;
+        let mut converter = Converter {
+            source: &source,
+            db: quarantine.db(),
+            commits: HashMap::new(),
+            trees: HashMap::new(),
+        };
+
+        let mut branches = Vec::new();
+        let mut skipped_refs = Vec::new();
+        for (name, sha) in source.refs()? {
+            match name.strip_prefix("refs/heads/") {
+                Some(branch) => branches.push((branch.to_string(), converter.commit(&sha)?)),
+                None => skipped_refs.push(name),
+            }
+        }
+        let head = match source.head()? {
+            GitHead::Symbolic(name) => Head::Symbolic(name.into()),
+            GitHead::Detached(sha) => Head::Detached(converter.commit(&sha)?),
+        };
+        let imported_commits = converter.commits.len();
+        let mut mapping: Vec<String> = converter
+            .commits
+            .iter()
+            .map(|(git_sha, sha)| format!("{git_sha} {sha}\n"))
+            .collect();
+        mapping.sort();
+
+        // Objects must be in place before any ref points at them
+        quarantine.migrate().map_err(|why| why.to_string())?;
+
+        // Drop the unborn initial branch created by init
+        if let Some(Head::Symbolic(unborn)) = self.get_head()
+            && let Ok(unborn) = unborn.strip_prefix(Path::new(REFS_DIR).join(HEADS_DIR))
+            && !branches.iter().any(|(name, _)| Path::new(name) == unborn)
+        {
+            let _ = self.remove_branch(&unborn.to_string_lossy(), "import-git");
+        }
+        for (name, sha) in &branches {
+            let branch = Branch {
+                name: name.clone(),
+                commit_sha: Some(sha.clone()),
+            };
+            self.save_branch(&branch, "import-git")
+                .map_err(|why| why.to_string())?;
+        }
+        self.save_head(&head, "import-git")
+            .map_err(|why| why.to_string())?;
+        fs::write(self.git_dir.join(IMPORT_MAP_FILE), mapping.concat())
+            .map_err(|why| why.to_string())?;
+
+        if let Some(commit_sha) = self.get_current_commit() {
+            let index = self.read_tree(&self.read_commit(&commit_sha)?.get_tree_sha())?;
+            for (path, _) in index.collect_entries() {
+                if self.dir.join(&path).exists() {
+                    return Err(format!(
+                        "untracked file {path} is in the way; history was imported but not checked out"
+                    ));
+                }
+            }
+            self.update_worktree(&Index::new(), &index)?;
+            index.save(&self.get_index_path())?;
+        }
+
+        println!(
+            "Imported {imported_commits} commit(s) on {} branch(es)",
+            branches.len()
+        );
+        for name in skipped_refs {
+            println!("warning: skipped {name}: only branches can be imported");
+        }
+        Ok(())
+    }
+}
+
+/// Rewrites git objects into this tool's formats, remembering the new SHA1
+/// of every converted commit and tree
+struct Converter<'a> {
+    source: &'a GitSource,
+    db: &'a ObjectDB,
+    commits: HashMap<String, EncodedSha>,
+    trees: HashMap<String, EncodedSha>,
+}
+
+impl Converter<'_> {
+    /// Converts a commit and all of its ancestors, parents first
+    fn commit(&mut self, git_sha: &str) -> Result<EncodedSha, String> {
+        // Walk iteratively: long histories would overflow the stack
+        let mut stack = vec![(git_sha.to_string(), false)];
+        while let Some((sha, parents_done)) = stack.pop() {
+            if self.commits.contains_key(&sha) {
+                continue;
+            }
+            let (kind, data) = self.source.read(&sha)?;
+            if kind != GitKind::Commit {
+                return Err(format!("{sha} is not a commit"));
+            }
+            let text = String::from_utf8_lossy(&data).into_owned();
+            let (headers, message) = text.split_once("\n\n").unwrap_or((&text, ""));
+
+            let mut tree = None;
+            let mut parents = Vec::new();
+            let mut people = Vec::new();
+            for line in headers.lines() {
+                if let Some(value) = line.strip_prefix("tree ") {
+                    tree = Some(value.to_string());
+                } else if let Some(value) = line.strip_prefix("parent ") {
+                    parents.push(value.to_string());
+                } else if line.starts_with("author ") || line.starts_with("committer ") {
+                    people.push(line.to_string());
+                }
+                // gpgsig, encoding, mergetag and their continuation lines are dropped
+            }
+
+            if !parents_done {
+                stack.push((sha.clone(), true));
+                for parent in parents.iter().rev() {
+                    if !self.commits.contains_key(parent) {
+                        stack.push((parent.clone(), false));
+                    }
+                }
+                continue;
+            }
+
+            let tree = tree.ok_or_else(|| format!("commit {sha} has no tree"))?;
+            let mut content = format!("tree {}\n", self.tree(&tree)?);
+            for parent in &parents {
+                content += &format!("parent {}\n", self.commits[parent]);
+            }
+            for person in &people {
+                content += &format!("{person}\n");
+            }
+            content += &format!("\n{message}");
+            let raw = format!("commit {}\0{content}", content.len());
+            let commit = Commit::deserialize(raw.as_bytes())
+                .map_err(|why| format!("cannot convert commit {sha}: {why}"))?;
+            let new_sha = self.db.store(&commit).map_err(|why| why.to_string())?;
+            self.commits.insert(sha, new_sha);
+        }
+        Ok(self.commits[git_sha].clone())
+    }
+
+    /// Converts a binary git tree and everything below it
+    fn tree(&mut self, git_sha: &str) -> Result<EncodedSha, String> {
+        if let Some(sha) = self.trees.get(git_sha) {
+            return Ok(sha.clone());
+        }
+        let (kind, data) = self.source.read(git_sha)?;
+        if kind != GitKind::Tree {
+            return Err(format!("{git_sha} is not a tree"));
+        }
+
+        let mut tree = Tree::new();
+        let mut rest = data.as_slice();
+        while !rest.is_empty() {
+            let corrupt = || format!("corrupt tree {git_sha}");
+            let space = rest.iter().position(|&b| b == b' ').ok_or_else(corrupt)?;
+            let nul = rest.iter().position(|&b| b == 0).ok_or_else(corrupt)?;
+            let mode = String::from_utf8_lossy(&rest[..space]).into_owned();
+            let name = String::from_utf8_lossy(&rest[space + 1..nul]).into_owned();
+            let sha = hex::encode(rest.get(nul + 1..nul + 21).ok_or_else(corrupt)?);
+            rest = &rest[nul + 21..];
+
+            match mode.as_str() {
+                "40000" => {
+                    let sub_tree = self.tree(&sha)?;
+                    tree.add_entry(ObjectType::Tree, &sub_tree, &name);
+                }
+                // Submodules are commits of another repository and keep
+                // their id
+                "160000" => {
+                    tree.add_entry_with_mode(FileMode::Gitlink, &EncodedSha(sha), &name);
+                }
+                _ => {
+                    let mode = FileMode::parse(&mode).ok_or_else(corrupt)?;
+                    let (_, content) = self.source.read(&sha)?;
+                    let blob_sha = self
+                        .db
+                        .store(&Blob { data: content })
+                        .map_err(|why| why.to_string())?;
+                    tree.add_entry_with_mode(mode, &blob_sha, &name);
+                }
+            }
+        }
+        let sha = self.db.store(&tree).map_err(|why| why.to_string())?;
+        self.trees.insert(git_sha.to_string(), sha.clone());
+        Ok(sha)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::Compression;
+    use flate2::write::ZlibEncoder;
+    use sha1::{Digest, Sha1};
+    use std::io::Write;
+    use tempfile::TempDir;
+
+    /// Writes a loose object the way git does and returns its id
+    fn write_git_object(git_dir: &Path, kind: &str, content: &[u8]) -> String {
+        let mut data = format!("{kind} {}\0", content.len()).into_bytes();
+        data.extend_from_slice(content);
+        let sha = hex::encode(Sha1::digest(&data));
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&data).unwrap();
+        let dir = git_dir.join("objects").join(&sha[..2]);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(&sha[2..]), encoder.finish().unwrap()).unwrap();
+        sha
+    }
+
+    fn tree_entry(mode: &str, name: &str, sha: &str) -> Vec<u8> {
+        let mut entry = format!("{mode} {name}\0").into_bytes();
+        entry.extend(hex::decode(sha).unwrap());
+        entry
+    }
+
+    #[test]
+    fn imports_branches_and_checks_out_head() {
+        let source_dir = TempDir::new().unwrap();
+        let git_dir = source_dir.path().join(".git");
+        fs::create_dir_all(git_dir.join("refs/heads")).unwrap();
+        fs::write(git_dir.join("HEAD"), "ref: refs/heads/main\n").unwrap();
+
+        let blob = write_git_object(&git_dir, "blob", b"hello\n");
+        let sub = write_git_object(&git_dir, "tree", &tree_entry("100644", "b.txt", &blob));
+        let mut root = tree_entry("100644", "a.txt", &blob);
+        root.extend(tree_entry("40000", "dir", &sub));
+        let root = write_git_object(&git_dir, "tree", &root);
+        let person = "Bob <bob@example.com> 1700000000 +0100";
+        let first = write_git_object(
+            &git_dir,
+            "commit",
+            format!("tree {root}\nauthor {person}\ncommitter {person}\n\nfirst\n").as_bytes(),
+        );
+        let second = write_git_object(
+            &git_dir,
+            "commit",
+            format!("tree {root}\nparent {first}\nauthor {person}\ncommitter {person}\n\nsecond\n")
+                .as_bytes(),
+        );
+        fs::write(git_dir.join("refs/heads/main"), format!("{second}\n")).unwrap();
+        fs::write(
+            git_dir.join("packed-refs"),
+            format!("# pack-refs with: peeled\n{first} refs/heads/old\n"),
+        )
+        .unwrap();
+
+        let temp_dir = TempDir::new().unwrap();
+        let repo = Repository::init_with_branch(temp_dir.path(), "trunk").unwrap();
+        repo.import_git_impl(source_dir.path()).unwrap();
+        assert!(repo.load_branch("trunk").is_none());
+
+        let head = repo.get_current_commit().unwrap();
+        let commit = repo.read_commit(&head).unwrap();
+        assert_eq!(commit.get_message(), "second");
+        let parent = repo.load_branch("old").unwrap().commit_sha.unwrap();
+        assert_eq!(commit.get_parents(), &vec![parent]);
+        assert_eq!(
+            fs::read_to_string(repo.dir.join("dir/b.txt")).unwrap(),
+            "hello\n"
+        );
+        let map = fs::read_to_string(repo.git_dir.join(IMPORT_MAP_FILE)).unwrap();
+        assert!(map.contains(&format!("{second} {head}")));
+    }
+}
@@ -0,0 +1,418 @@
+use super::diff::diff_files;
+use super::pathspec::{matches_pathspec, normalize_pathspecs};
+use super::{HEAD_FILE, PrettyFormat, Repository};
+use crate::diff;
+use crate::object::Commit;
+use crate::{EncodedSha, Error};
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// How `log` and `show` print dates
+pub(super) const DATE_FORMAT: &str = "%a %b %e %H:%M:%S %Y %z";
+
+/// Options of [`Repository::log`]
+#[derive(Debug, Clone, Default)]
+pub struct LogOptions {
+    /// How to print each commit, like `--format` or `--oneline`
+    pub format: PrettyFormat,
+    /// Check the signatures of signed commits, like `--show-signature`;
+    /// `log.showSignature` turns this on by default
+    pub show_signature: bool,
+    /// Only list commits whose author, as `Name <email>`, matches this
+    /// regex, like `--author`
+    pub author: Option<String>,
+    /// Only list commits whose message matches this regex, like `--grep`
+    pub grep: Option<String>,
+    /// Only list commits committed at or after this time, like `--since`
+    pub since: Option<SystemTime>,
+    /// Only list commits committed at or before this time, like `--until`
+    pub until: Option<SystemTime>,
+    /// Only list commits changing files matching these pathspecs, like
+    /// `log -- <path>...`
+    pub paths: Vec<String>,
+    /// Go on listing the commits of a single file under the name it had
+    /// before a rename, like `--follow`
+    pub follow: bool,
+    /// Only list commits changing how often this string occurs in a file,
+    /// like `-S`
+    pub pickaxe: Option<String>,
+    /// Only list commits adding or removing lines that match this regex,
+    /// like `-G`
+    pub pickaxe_regex: Option<String>,
+}
+
+/// The commits `log` lists, compiled from [`LogOptions`]
+struct CommitFilter {
+    author: Option<Regex>,
+    grep: Option<Regex>,
+    since: Option<i64>,
+    until: Option<i64>,
+    pickaxe: Option<String>,
+    pickaxe_regex: Option<Regex>,
+}
+
+impl CommitFilter {
+    fn new(options: &LogOptions) -> Result<Self, Error> {
+        let regex = |pattern: &Option<String>| {
+            pattern
+                .as_deref()
+                .map(|pattern| {
+                    Regex::new(pattern).map_err(|why| format!("invalid regex '{pattern}': {why}"))
+                })
+                .transpose()
+        };
+        let seconds = |time: Option<SystemTime>| {
+            time.map(|time| match time.duration_since(UNIX_EPOCH) {
+                Ok(after) => after.as_secs() as i64,
+                Err(before) => -(before.duration().as_secs() as i64),
+            })
+        };
+        Ok(CommitFilter {
+            author: regex(&options.author)?,
+            grep: regex(&options.grep)?,
+            since: seconds(options.since),
+            until: seconds(options.until),
+            pickaxe: options.pickaxe.clone(),
+            pickaxe_regex: regex(&options.pickaxe_regex)?,
+        })
+    }
+
+    fn matches(&self, commit: &Commit) -> bool {
+        let author = commit.get_author();
+        let time = commit.get_committer().get_timestamp().timestamp();
+        self.author.as_ref().is_none_or(|regex| {
+            regex.is_match(&format!("{} <{}>", author.get_name(), author.get_email()))
+        }) && self
+            .grep
+            .as_ref()
+            .is_none_or(|regex| regex.is_match(commit.get_message()))
+            && self.since.is_none_or(|since| time >= since)
+            && self.until.is_none_or(|until| time <= until)
+    }
+}
+
+impl Repository {
+    /// Prints the history of `rev` (default `HEAD`), newest commit first,
+    /// as [`Self::log_commits`] lists it. By default each commit prints
+    /// its SHA1, the parents of merges, the author, the date and the
+    /// message.
+    pub fn log(&self, rev: Option<&str>, options: LogOptions) -> Result<(), Error> {
+        let show_signature = options.show_signature || self.show_signature()?;
+        let decorations = self.decorations();
+        for (sha, commit) in self.log_commits(rev, &options)? {
+            print!(
+                "{}",
+                self.format_header(&sha, &commit, &options.format, show_signature, &decorations)?
+            );
+        }
+        Ok(())
+    }
+
+    /// The commits `log` lists: those reachable from `rev` (default
+    /// `HEAD`) that pass the filters of `options`, newest first. `rev` can
+    /// be a range such as `A..B` or `A...B`, see [`Self::parse_rev_range`].
+    ///
+    /// With pathspecs, only the commits changing matching files are
+    /// listed; merges only when they differ from every parent. `follow`
+    /// takes the history of a single file as [`Self::file_history`] does.
+    pub fn log_commits(
+        &self,
+        rev: Option<&str>,
+        options: &LogOptions,
+    ) -> Result<impl Iterator<Item = (EncodedSha, Commit)> + '_, Error> {
+        let (include, exclude) = self.parse_rev_range(&[rev.unwrap_or(HEAD_FILE)])?;
+        let filter = CommitFilter::new(options)?;
+        let pathspecs = normalize_pathspecs(&options.paths);
+        let commits: Box<dyn Iterator<Item = (EncodedSha, Commit)>> = if options.follow {
+            let [path] = &pathspecs[..] else {
+                return Err("--follow requires exactly one pathspec".into());
+            };
+            let [tip] = &include[..] else {
+                return Err("--follow requires a single starting commit".into());
+            };
+            let excluded: HashSet<EncodedSha> = self.reachable_from(exclude).collect();
+            Box::new(
+                self.file_history_from(Some(tip.clone()), path, true)
+                    .map(|revision| (revision.commit_sha, revision.commit))
+                    .filter(move |(sha, _)| !excluded.contains(sha)),
+            )
+        } else if pathspecs.is_empty() {
+            Box::new(self.walk_range(include, exclude))
+        } else {
+            Box::new(
+                self.walk_range(include, exclude)
+                    .filter(move |(_, commit)| self.changes_paths(commit, &pathspecs)),
+            )
+        };
+        Ok(commits.filter(move |(_, commit)| {
+            filter.matches(commit) && self.pickaxe_matches(commit, &filter)
+        }))
+    }
+
+    /// Whether the patch of `commit` against its first parent passes the
+    /// pickaxe of `filter`: a file whose count of the `-S` string changed,
+    /// or an added or removed line matching the `-G` regex. Merges have no
+    /// single patch and never pass.
+    fn pickaxe_matches(&self, commit: &Commit, filter: &CommitFilter) -> bool {
+        if filter.pickaxe.is_none() && filter.pickaxe_regex.is_none() {
+            return true;
+        }
+        if commit.get_parents().len() > 1 {
+            return false;
+        }
+        let Ok((old, new)) = self.commit_sides(commit) else {
+            return false;
+        };
+        diff_files(&old, &new).into_iter().any(|pair| {
+            let (Ok(old_text), Ok(new_text)) = (
+                self.snapshot_text(&old, &pair.path, pair.old.as_ref()),
+                self.snapshot_text(&new, &pair.path, pair.new.as_ref()),
+            ) else {
+                return false;
+            };
+            filter.pickaxe.as_deref().is_none_or(|needle| {
+                old_text.matches(needle).count() != new_text.matches(needle).count()
+            }) && filter.pickaxe_regex.as_ref().is_none_or(|regex| {
+                let (old_lines, new_lines) = (diff::lines(&old_text), diff::lines(&new_text));
+                diff::diff_slices(&old_lines, &new_lines)
+                    .into_iter()
+                    .flat_map(|hunk| old_lines[hunk.old].iter().chain(&new_lines[hunk.new]))
+                    .any(|line| regex.is_match(line))
+            })
+        })
+    }
+
+    /// Whether `commit` changes files matching `pathspecs` relative to
+    /// every parent, or adds any for a root commit
+    fn changes_paths(&self, commit: &Commit, pathspecs: &[String]) -> bool {
+        let matching_files = |tree: &EncodedSha| -> HashMap<String, EncodedSha> {
+            let Ok(index) = self.read_tree(tree) else {
+                return HashMap::new();
+            };
+            index
+                .collect_entries()
+                .into_iter()
+                .filter(|(path, _)| matches_pathspec(path, pathspecs))
+                .collect()
+        };
+        let files = matching_files(&commit.get_tree_sha());
+        let parents = commit.get_parents();
+        if parents.is_empty() {
+            return !files.is_empty();
+        }
+        parents.iter().all(|parent| {
+            self.read_commit(parent).map_or(true, |parent| {
+                matching_files(&parent.get_tree_sha()) != files
+            })
+        })
+    }
+
+    /// Renders a commit for `log` or `show` in `format`. Only the default
+    /// format is followed by a blank line; `decorations` are the refs by
+    /// commit for `%d`.
+    pub(super) fn format_header(
+        &self,
+        sha: &EncodedSha,
+        commit: &Commit,
+        format: &PrettyFormat,
+        show_signature: bool,
+        decorations: &HashMap<EncodedSha, Vec<String>>,
+    ) -> Result<String, Error> {
+        let format = match format {
+            PrettyFormat::Medium => return self.commit_header(sha, commit, show_signature),
+            PrettyFormat::Oneline => "%h%d %s",
+            PrettyFormat::Format(format) => format,
+        };
+        Ok(format!(
+            "{}\n",
+            self.format_commit(sha, commit, format, decorations)
+        ))
+    }
+
+    /// Whether `log.showSignature` asks for signatures to be checked
+    pub(super) fn show_signature(&self) -> Result<bool, Error> {
+        Ok(self
+            .config()?
+            .get_bool("log.showSignature")?
+            .unwrap_or(false))
+    }
+
+    /// [`format_commit_header`], with the outcome of checking the signature
+    /// of the commit under its SHA1 when `show_signature` is set
+    pub(super) fn commit_header(
+        &self,
+        sha: &EncodedSha,
+        commit: &Commit,
+        show_signature: bool,
+    ) -> Result<String, Error> {
+        let mut header = format_commit_header(sha, commit);
+        if show_signature && let Some(status) = self.commit_signature(sha)? {
+            let end = header.find('\n').map_or(0, |end| end + 1);
+            header.insert_str(end, &format!("{status}\n"));
+        }
+        Ok(header)
+    }
+}
+
+/// Renders a commit the way `log` lists it: the SHA1, the parents of
+/// merges, the author, the date and the indented message
+pub(super) fn format_commit_header(sha: &EncodedSha, commit: &Commit) -> String {
+    let mut out = format!("commit {sha}\n");
+    let parents = commit.get_parents();
+    if parents.len() > 1 {
+        let short: Vec<&str> = parents.iter().map(|p| &p.0[..7]).collect();
+        out.push_str(&format!("Merge: {}\n", short.join(" ")));
+    }
+    let author = commit.get_author();
+    out.push_str(&format!(
+        "Author: {} <{}>\nDate:   {}\n\n",
+        author.get_name(),
+        author.get_email(),
+        author.get_timestamp().format(DATE_FORMAT)
+    ));
+    for line in commit.get_message().lines() {
+        out.push_str(&format!("    {line}\n"));
+    }
+    out.push('\n');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::init_test_repo;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn log_filters_by_author_message_and_date() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        let file = repo.dir.join("a.txt");
+        let commit = |message: &str| {
+            fs::write(&file, message).unwrap();
+            repo.add(&vec![file.to_str().unwrap()]).unwrap();
+            repo.commit(message).unwrap()
+        };
+        let fix = commit("Fix the parser");
+        repo.set_config("user.name", "Bob").unwrap();
+        let feature = commit("Add a feature");
+        let listed = |options: LogOptions| -> Vec<EncodedSha> {
+            repo.log_commits(None, &options)
+                .unwrap()
+                .map(|(sha, _)| sha)
+                .collect()
+        };
+
+        let by_bob = LogOptions {
+            author: Some("^Bob <".to_string()),
+            ..LogOptions::default()
+        };
+        assert_eq!(listed(by_bob), vec![feature.clone()]);
+        let fixes = LogOptions {
+            grep: Some("(?i)^fix".to_string()),
+            ..LogOptions::default()
+        };
+        assert_eq!(listed(fixes), vec![fix.clone()]);
+        let recent = LogOptions {
+            since: Some(SystemTime::now() - std::time::Duration::from_secs(3600)),
+            until: Some(SystemTime::now() + std::time::Duration::from_secs(3600)),
+            ..LogOptions::default()
+        };
+        assert_eq!(listed(recent), [feature, fix]);
+        let old = LogOptions {
+            until: Some(UNIX_EPOCH),
+            ..LogOptions::default()
+        };
+        assert!(listed(old).is_empty());
+        let invalid = LogOptions {
+            grep: Some("(".to_string()),
+            ..LogOptions::default()
+        };
+        assert!(repo.log_commits(None, &invalid).is_err());
+    }
+
+    #[test]
+    fn log_limits_to_paths_and_follows_renames() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        fs::create_dir(repo.dir.join("src")).unwrap();
+        let commit = |name: &str, content: &str| {
+            let file = repo.dir.join(name);
+            fs::write(&file, content).unwrap();
+            repo.add(&vec![file.to_str().unwrap()]).unwrap();
+            repo.commit(name).unwrap()
+        };
+        let lib = commit("src/lib.rs", "fn lib() {}\n");
+        let readme = commit("README", "read me\n");
+        let moved = {
+            let path = |name: &str| repo.dir.join(name).to_str().unwrap().to_string();
+            repo.mv(&path("src/lib.rs"), &path("src/main.rs")).unwrap();
+            repo.commit("move").unwrap()
+        };
+        let listed = |paths: &[&str], follow: bool| -> Vec<EncodedSha> {
+            let options = LogOptions {
+                paths: paths.iter().map(|path| path.to_string()).collect(),
+                follow,
+                ..LogOptions::default()
+            };
+            repo.log_commits(None, &options)
+                .unwrap()
+                .map(|(sha, _)| sha)
+                .collect()
+        };
+
+        assert_eq!(listed(&["src"], false), [moved.clone(), lib.clone()]);
+        assert_eq!(listed(&["README"], false), vec![readme]);
+        assert_eq!(listed(&["src/main.rs"], false), vec![moved.clone()]);
+        assert_eq!(listed(&["src/main.rs"], true), [moved, lib]);
+        let options = LogOptions {
+            follow: true,
+            ..LogOptions::default()
+        };
+        assert!(repo.log_commits(None, &options).is_err());
+    }
+
+    #[test]
+    fn pickaxe_finds_commits_changing_a_string() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        let file = repo.dir.join("a.rs");
+        let commit = |content: &str, message: &str| {
+            fs::write(&file, content).unwrap();
+            repo.add(&vec![file.to_str().unwrap()]).unwrap();
+            repo.commit(message).unwrap()
+        };
+        let add = commit("fn parse() {}\n", "add");
+        let call = commit("fn parse() {}\nfn main() { parse() }\n", "call");
+        // Moving the lines around keeps the counts
+        let reorder = commit("fn main() { parse() }\nfn parse() {}\n", "reorder");
+        let remove = commit("fn main() {}\n", "remove");
+        let listed = |pickaxe: Option<&str>, pickaxe_regex: Option<&str>| -> Vec<EncodedSha> {
+            let options = LogOptions {
+                pickaxe: pickaxe.map(str::to_string),
+                pickaxe_regex: pickaxe_regex.map(str::to_string),
+                ..LogOptions::default()
+            };
+            repo.log_commits(None, &options)
+                .unwrap()
+                .map(|(sha, _)| sha)
+                .collect()
+        };
+
+        assert_eq!(
+            listed(Some("fn parse"), None),
+            [remove.clone(), add.clone()]
+        );
+        assert_eq!(
+            listed(Some("parse()"), None),
+            [remove.clone(), call.clone(), add.clone()]
+        );
+        // but the moved line is in the patch
+        assert_eq!(
+            listed(None, Some(r"main\(\) \{ parse")),
+            [remove, reorder, call]
+        );
+    }
+}
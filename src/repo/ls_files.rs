@@ -0,0 +1,146 @@
+use super::Repository;
+use crate::Error;
+
+/// Options of [`Repository::ls_files`]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LsFilesOptions {
+    /// Show the mode, SHA1 and stage of every index entry, like `--stage`
+    pub stage: bool,
+    /// List untracked files that are not ignored, like `--others`
+    pub others: bool,
+    /// List tracked files whose content changed or that were deleted, like
+    /// `--modified`
+    pub modified: bool,
+    /// List tracked files that were deleted, like `--deleted`
+    pub deleted: bool,
+}
+
+impl Repository {
+    /// Lists files of the index and the working tree, like `git ls-files`,
+    /// one line per file with its path relative to the top of the working
+    /// tree. Index entries are listed unless only `others`, `modified` or
+    /// `deleted` are asked for; `stage` lists them as
+    /// `<mode> <sha1> <stage>\t<path>`, an unmerged path once for each of
+    /// its stages 1 to 3. Untracked files come first, then
+    /// every index entry, then the deleted and modified ones.
+    pub fn ls_files(&self, options: LsFilesOptions) -> Result<Vec<String>, Error> {
+        let index = self.load_index_or_empty()?;
+        let mut lines = Vec::new();
+        if options.others {
+            lines.extend(self.untracked_files(false)?);
+        }
+        if options.stage {
+            for (path, mode, stage, sha) in index.collect_stage_entries() {
+                lines.push(format!("{} {sha} {stage}\t{path}", mode.as_str()));
+            }
+        } else if !(options.others || options.modified || options.deleted) {
+            lines.extend(index.collect_entries().into_iter().map(|(path, _)| path));
+        }
+        if options.deleted {
+            lines.extend(index.deleted_entries(&self.dir));
+        }
+        if options.modified {
+            lines.extend(index.modified_entries(&self.dir)?);
+        }
+        Ok(lines)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::{Blob, Object};
+    use crate::repo::{FastForward, commit_file, init_test_repo};
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn ls_files_lists_staged_untracked_modified_and_deleted_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        fs::create_dir(repo.dir.join("src")).unwrap();
+        let files = ["a.txt", "b.txt", "src/lib.rs"].map(|name| repo.dir.join(name));
+        for file in &files {
+            fs::write(file, "content\n").unwrap();
+        }
+        let paths: Vec<&str> = files.iter().map(|f| f.to_str().unwrap()).collect();
+        repo.add(&paths).unwrap();
+        fs::write(repo.dir.join("new.txt"), "new\n").unwrap();
+        fs::write(repo.dir.join(".gitignore"), "*.log\n").unwrap();
+        fs::write(repo.dir.join("debug.log"), "noise\n").unwrap();
+
+        let ls = |options| repo.ls_files(options).unwrap();
+        assert_eq!(
+            ls(LsFilesOptions::default()),
+            ["a.txt", "b.txt", "src/lib.rs"]
+        );
+        let staged = ls(LsFilesOptions {
+            stage: true,
+            ..Default::default()
+        });
+        let sha = Blob {
+            data: b"content\n".to_vec(),
+        }
+        .encoded_sha1();
+        assert_eq!(staged[2], format!("100644 {sha} 0\tsrc/lib.rs"));
+
+        fs::write(&files[0], "changed\n").unwrap();
+        fs::remove_file(&files[1]).unwrap();
+        assert_eq!(
+            ls(LsFilesOptions {
+                others: true,
+                ..Default::default()
+            }),
+            [".gitignore", "new.txt"]
+        );
+        assert_eq!(
+            ls(LsFilesOptions {
+                deleted: true,
+                ..Default::default()
+            }),
+            ["b.txt"]
+        );
+        assert_eq!(
+            ls(LsFilesOptions {
+                modified: true,
+                deleted: true,
+                ..Default::default()
+            }),
+            ["b.txt", "a.txt", "b.txt"]
+        );
+    }
+
+    #[test]
+    fn ls_files_stage_lists_the_stages_of_unmerged_paths() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        commit_file(&repo, "a.txt", "base\n");
+        commit_file(&repo, "b.txt", "other\n");
+        repo.branch("topic").unwrap();
+        commit_file(&repo, "a.txt", "ours\n");
+        repo.checkout("topic", false).unwrap();
+        commit_file(&repo, "a.txt", "theirs\n");
+        repo.checkout("master", false).unwrap();
+        assert!(!repo.merge("topic", false, FastForward::Allow).unwrap());
+
+        let sha = |content: &str| {
+            Blob {
+                data: content.as_bytes().to_vec(),
+            }
+            .encoded_sha1()
+        };
+        assert_eq!(
+            repo.ls_files(LsFilesOptions {
+                stage: true,
+                ..Default::default()
+            })
+            .unwrap(),
+            [
+                format!("100644 {} 1\ta.txt", sha("base\n")),
+                format!("100644 {} 2\ta.txt", sha("ours\n")),
+                format!("100644 {} 3\ta.txt", sha("theirs\n")),
+                format!("100644 {} 0\tb.txt", sha("other\n")),
+            ]
+        );
+    }
+}
@@ -0,0 +1,154 @@
+use super::{Repository, TreeWalkStep};
+use crate::object::{FileMode, Tree};
+use crate::{EncodedSha, Error};
+use std::str::FromStr;
+
+/// Options of [`Repository::ls_tree`]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LsTreeOptions {
+    /// List the files of subtrees instead of the subtrees, like `-r`
+    pub recursive: bool,
+    /// Only print the paths, like `--name-only`
+    pub name_only: bool,
+}
+
+impl Repository {
+    /// Lists the entries of a tree like `git ls-tree`, one line per entry
+    /// as `<mode> <type> <sha1>\t<path>`.
+    ///
+    /// # Arguments
+    /// * `tree_ish` - A commit, whose tree is listed, or the SHA1 of a tree
+    /// * `path` - Only list this entry; a directory given with a trailing
+    ///   slash lists its content instead
+    pub fn ls_tree(
+        &self,
+        tree_ish: &str,
+        path: Option<&str>,
+        options: LsTreeOptions,
+    ) -> Result<Vec<String>, Error> {
+        let tree_sha = match self.resolve_commit(tree_ish) {
+            Ok(sha) => self.read_commit(&sha)?.get_tree_sha(),
+            Err(_) => EncodedSha::from_str(tree_ish)
+                .ok()
+                .filter(|sha| self.read_tree_object(sha).is_ok())
+                .ok_or_else(|| Error::BadRevision(tree_ish.to_string()))?,
+        };
+        let (dir, name) = match path.map(|path| path.trim_start_matches("./")) {
+            None | Some("") => ("", None),
+            Some(path) if path.ends_with('/') => (path.trim_end_matches('/'), None),
+            Some(path) => match path.rsplit_once('/') {
+                Some((dir, name)) => (dir, Some(name)),
+                None => ("", Some(path)),
+            },
+        };
+        // Walk down to the directory holding the entries to list
+        let mut tree = self.read_tree_object(&tree_sha)?;
+        for component in dir.split('/').filter(|component| !component.is_empty()) {
+            let Some((_, entry)) = tree.get_entries().find(|(entry_name, entry)| {
+                *entry_name == component && entry.mode == FileMode::Directory
+            }) else {
+                return Ok(Vec::new());
+            };
+            tree = self.read_tree_object(&entry.sha1.clone())?;
+        }
+        let prefix = match dir {
+            "" => String::new(),
+            dir => format!("{dir}/"),
+        };
+
+        let mut entries = Vec::new();
+        for (entry_name, entry) in tree.get_entries() {
+            if name.is_some_and(|name| name != entry_name) {
+                continue;
+            }
+            let entry_path = format!("{prefix}{entry_name}");
+            if options.recursive && entry.mode == FileMode::Directory {
+                self.walk_tree(&entry.sha1, |file| {
+                    if file.mode != FileMode::Directory {
+                        let path = format!("{entry_path}/{}", file.path);
+                        entries.push((file.mode, file.sha.clone(), path));
+                    }
+                    TreeWalkStep::Continue
+                })?;
+            } else {
+                entries.push((entry.mode, entry.sha1.clone(), entry_path));
+            }
+        }
+        Ok(entries
+            .into_iter()
+            .map(|(mode, sha, path)| match options.name_only {
+                true => path,
+                false => format_tree_entry(mode, &sha, &path),
+            })
+            .collect())
+    }
+
+    pub(super) fn read_tree_object(&self, sha: &EncodedSha) -> Result<Tree, Error> {
+        let data = self.obj_db.retrieve(sha)?;
+        Tree::deserialize(&data).map_err(|why| Error::Other(why.to_string()))
+    }
+}
+
+/// Renders a tree entry the way `ls-tree` lists it
+pub(super) fn format_tree_entry(mode: FileMode, sha: &EncodedSha, path: &str) -> String {
+    format!("{:0>6} {} {sha}\t{path}", mode.as_str(), mode.object_type())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::init_test_repo;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn ls_tree_lists_entries_of_commits_and_trees() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        fs::create_dir_all(repo.dir.join("src/bin")).unwrap();
+        let files = ["a.txt", "src/lib.rs", "src/bin/main.rs"].map(|name| repo.dir.join(name));
+        for file in &files {
+            fs::write(file, "content\n").unwrap();
+        }
+        let paths: Vec<&str> = files.iter().map(|f| f.to_str().unwrap()).collect();
+        repo.add(&paths).unwrap();
+        let commit = repo.commit("first").unwrap();
+        let tree = repo.read_commit(&commit).unwrap().get_tree_sha();
+
+        let ls = |tree_ish: &str, path, recursive| {
+            let options = LsTreeOptions {
+                recursive,
+                name_only: true,
+            };
+            repo.ls_tree(tree_ish, path, options).unwrap()
+        };
+        assert_eq!(ls("HEAD", None, false), ["a.txt", "src"]);
+        assert_eq!(
+            ls(&tree.0, None, true),
+            ["a.txt", "src/bin/main.rs", "src/lib.rs"]
+        );
+        assert_eq!(ls("HEAD", Some("src"), false), ["src"]);
+        assert_eq!(ls("HEAD", Some("src/"), false), ["src/bin", "src/lib.rs"]);
+        assert_eq!(
+            ls("HEAD", Some("src/"), true),
+            ["src/bin/main.rs", "src/lib.rs"]
+        );
+        assert!(ls("HEAD", Some("missing/"), false).is_empty());
+
+        let long = repo
+            .ls_tree("master", None, LsTreeOptions::default())
+            .unwrap();
+        let blob = repo
+            .read_tree(&tree)
+            .unwrap()
+            .get_sha1("a.txt")
+            .unwrap()
+            .clone();
+        assert_eq!(long[0], format!("100644 blob {blob}\ta.txt"));
+        assert!(long[1].starts_with("040000 tree "));
+        assert!(
+            repo.ls_tree("nope", None, LsTreeOptions::default())
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,328 @@
+use super::revert::MERGE_MSG;
+use super::{HEAD_FILE, INDEX_FILE, IndexDiffType, Repository, ResetMode};
+use crate::index::Index;
+use crate::{EncodedSha, Error};
+use std::fs;
+use std::path::Path;
+
+/// Names the commit being merged while the conflicts of a merge are
+/// resolved
+pub(super) const MERGE_HEAD: &str = "MERGE_HEAD";
+
+/// Whether `merge` may just move the branch when HEAD is an ancestor of
+/// the merged commit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FastForward {
+    /// Fast-forward when possible, otherwise create a merge commit
+    #[default]
+    Allow,
+    /// Refuse to merge unless it is a fast-forward
+    Only,
+    /// Always create a merge commit
+    Never,
+}
+
+impl Repository {
+    /// Merges the given branch into the current branch
+    ///
+    /// On conflicts the index and working tree receive the files with
+    /// conflict markers, `MERGE_HEAD` names the merged commit and the merge
+    /// message waits in `MERGE_MSG`; the next `commit` concludes the merge
+    /// and [`Self::merge_abort`] gives it up.
+    ///
+    /// # Arguments
+    /// * `branch_name` - Name of the branch to merge, or a remote-tracking
+    ///   ref or commit
+    /// * `autostash` - Stash uncommitted changes before merging and reapply
+    ///   them afterwards instead of refusing to merge
+    /// * `fast_forward` - Whether the merge may, or must, be a fast-forward
+    ///
+    /// # Returns
+    /// - `Ok(true)`: The branch was merged or there was nothing to merge
+    /// - `Ok(false)`: The merge stopped at conflicts
+    pub fn merge(
+        &self,
+        branch_name: &str,
+        autostash: bool,
+        fast_forward: FastForward,
+    ) -> Result<bool, Error> {
+        self.check_no_operation_in_progress()?;
+        let current_commit_sha = self
+            .get_current_commit()
+            .ok_or("HEAD does not point to a commit yet")?;
+        let index = Index::load(&self.get_index_path())?;
+        let current_commit = self.read_commit(&current_commit_sha)?;
+        let current_commit_index = self.read_tree(&current_commit.get_tree_sha())?;
+        let diff = self.diff_index(&current_commit_index, &index);
+        if !autostash
+            && diff
+                .values()
+                .any(|status| *status != IndexDiffType::Unmodified)
+        {
+            return Err(Error::UncommittedChanges);
+        }
+        let branch_commit_sha = match self.load_branch(branch_name) {
+            Some(branch) => branch
+                .commit_sha
+                .ok_or_else(|| format!("There is no commit in branch {}", &branch.name))?,
+            // Remote-tracking refs and commits can be merged as well
+            None => self
+                .resolve_commit(branch_name)
+                .map_err(|_| Error::NoSuchBranch(branch_name.to_string()))?,
+        };
+        if branch_commit_sha == current_commit_sha {
+            return Err("Cannot merge a branch with itself.".into());
+        }
+        let lca = self
+            .find_lca(&current_commit_sha, &branch_commit_sha)
+            .ok_or_else(|| {
+                format!("Cannot find lca of {current_commit_sha} and {branch_commit_sha}")
+            })?;
+        if lca.eq(&branch_commit_sha) {
+            return Ok(true);
+        }
+        let can_fast_forward = lca.eq(&current_commit_sha);
+        if fast_forward == FastForward::Only && !can_fast_forward {
+            return Err("Not possible to fast-forward, aborting.".into());
+        }
+        let stash = if autostash {
+            self.create_autostash()?
+        } else {
+            None
+        };
+        self.save_orig_head()?;
+        let merged = if can_fast_forward && fast_forward != FastForward::Never {
+            self.fast_forward(branch_name)?;
+            true
+        } else {
+            self.merge_commit(branch_name, &current_commit_sha, &branch_commit_sha, &lca)?
+        };
+        match stash {
+            Some(stash) if merged => self.apply_autostash(&stash)?,
+            // Reapplying onto conflict markers would only add more
+            Some(stash) => println!("Your local changes are safe in the stash commit {stash}."),
+            None => {}
+        }
+        Ok(merged)
+    }
+
+    /// Gives up the merge stopped at conflicts, resetting the index and
+    /// working tree to HEAD as they were before the merge
+    pub fn merge_abort(&self) -> Result<(), Error> {
+        if self.merge_head().is_none() {
+            return Err("There is no merge to abort (MERGE_HEAD missing).".into());
+        }
+        // Resetting also forgets MERGE_HEAD and MERGE_MSG
+        self.reset(Some(HEAD_FILE), ResetMode::Hard)
+    }
+
+    /// The commit being merged while a merge waits for its conflicts to be
+    /// resolved
+    pub(super) fn merge_head(&self) -> Option<EncodedSha> {
+        self.state_head(MERGE_HEAD)
+    }
+
+    /// Creates the merge commit for a non fast-forward merge, updating the
+    /// index and working tree along the way
+    ///
+    /// # Returns
+    /// Whether the merge was committed; on conflicts it is left for
+    /// `commit` to conclude
+    fn merge_commit(
+        &self,
+        branch_name: &str,
+        current_commit_sha: &EncodedSha,
+        branch_commit_sha: &EncodedSha,
+        lca: &EncodedSha,
+    ) -> Result<bool, Error> {
+        // Check the identity before touching the index and working tree
+        let (author_name, author_email) = self.identity()?;
+        let current_commit = self.read_commit(current_commit_sha)?;
+        let current_commit_index = self.read_tree(&current_commit.get_tree_sha())?;
+
+        let branch_commit = self.read_commit(branch_commit_sha)?;
+        let lca_commit = self.read_commit(lca)?;
+
+        let branch_index = self.read_tree(&branch_commit.get_tree_sha())?;
+        let lca_index = self.read_tree(&lca_commit.get_tree_sha())?;
+
+        let (mut index, conflicts) = self.merge_indexes(
+            &lca_index,
+            &current_commit_index,
+            &branch_index,
+            branch_name,
+        )?;
+
+        /* Example:
+        Merge conflict in test.txt: 1
+        Merge conflict in test.txt: [3, 5]
+        Merge conflict in test.txt: [7, 9]  */
+        for conflict in &conflicts {
+            let file_name = Path::new(&conflict.path).file_name().unwrap();
+            for (start, end) in &conflict.lines {
+                print!("Merge conflict in {}: ", file_name.to_str().unwrap());
+                if start == end {
+                    println!("{}", start);
+                } else {
+                    println!("[{}, {}]", start, end);
+                }
+            }
+        }
+
+        // Update work dir, conflicted files with their markers
+        self.checkout_index(&index)?;
+        // Write merged index, leaving conflicted files unmerged
+        Self::stage_conflicts(
+            &mut index,
+            &conflicts,
+            &lca_index,
+            &current_commit_index,
+            &branch_index,
+        );
+        self.rerere_conflicts(&mut index)?;
+        index.save(&self.get_index_path())?;
+        let message = format!("Merge {}", branch_name);
+        if !conflicts.is_empty() {
+            fs::write(
+                self.git_dir.join(MERGE_HEAD),
+                format!("{branch_commit_sha}\n"),
+            )?;
+            fs::write(self.git_dir.join(MERGE_MSG), format!("{message}\n"))?;
+            println!("Automatic merge failed; fix conflicts and then commit the result.");
+            return Ok(false);
+        }
+        let tree_sha = self.write_tree()?;
+        let parents = vec![current_commit_sha.clone(), branch_commit_sha.clone()];
+        let commit_sha = self.commit_tree_with_author(
+            tree_sha,
+            parents,
+            &message,
+            None,
+            &author_name,
+            &author_email,
+            self.gpg_sign(None)?,
+        )?;
+        self.update_head(
+            Some(current_commit_sha),
+            &commit_sha,
+            &format!("commit (merge): {message}"),
+        )?;
+        Ok(true)
+    }
+
+    fn fast_forward(&self, target_branch_name: &str) -> Result<(), Error> {
+        let old = self.get_current_commit();
+        let target_sha = self.resolve_commit(target_branch_name)?;
+        let index = self.read_tree(&self.read_commit(&target_sha)?.get_tree_sha())?;
+        // Update working directory
+        self.checkout_index(&index)?;
+        index.save(&self.git_dir.join(INDEX_FILE))?;
+        self.update_head(
+            old.as_ref(),
+            &target_sha,
+            &format!("merge {target_branch_name}: Fast-forward"),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::{ConflictKind, init_test_repo};
+    use tempfile::TempDir;
+
+    #[test]
+    fn conflicted_merge_is_concluded_by_commit_or_aborted() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        let file = repo.dir.join("a.txt");
+        let commit_file = |content: &str, message: &str| {
+            fs::write(&file, content).unwrap();
+            repo.add(&vec![file.to_str().unwrap()]).unwrap();
+            repo.commit(message).unwrap()
+        };
+        commit_file("1\n2\n3\n", "base");
+        repo.branch("topic").unwrap();
+        let ours = commit_file("1\ntwo\n3\n", "ours");
+        repo.checkout("topic", false).unwrap();
+        let theirs = commit_file("1\nTWO\n3\n", "theirs");
+        repo.checkout("master", false).unwrap();
+
+        // The conflicted merge is left for commit to conclude
+        assert!(!repo.merge("topic", false, FastForward::Allow).unwrap());
+        assert_eq!(repo.merge_head(), Some(theirs.clone()));
+        assert_eq!(repo.get_current_commit(), Some(ours.clone()));
+        assert!(fs::read_to_string(&file).unwrap().contains("<<<<<<<"));
+        assert!(repo.merge("topic", false, FastForward::Allow).is_err());
+        // The index holds all three versions of the unmerged file
+        let index = repo.load_index_or_empty().unwrap();
+        let stages = index.get_conflict("a.txt").unwrap();
+        let version = |commit: &EncodedSha| {
+            let tree = repo.read_commit(commit).unwrap().get_tree_sha();
+            repo.read_tree(&tree).unwrap().get_sha1("a.txt").cloned()
+        };
+        assert_eq!(stages.kind(), ConflictKind::Content);
+        assert_eq!(stages.ours, version(&ours));
+        assert_eq!(stages.theirs, version(&theirs));
+        assert!(
+            matches!(repo.commit("early"), Err(Error::UnmergedFiles(paths)) if paths == ["a.txt"])
+        );
+        assert!(repo.untracked_files(false).unwrap().is_empty());
+
+        // Aborting restores the state before the merge
+        repo.merge_abort().unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "1\ntwo\n3\n");
+        assert!(repo.merge_head().is_none());
+        assert!(!repo.git_dir.join(MERGE_MSG).exists());
+        assert!(repo.merge_abort().is_err());
+
+        assert!(!repo.merge("topic", false, FastForward::Allow).unwrap());
+        assert!(repo.commit_amend(None, &[] as &[&str]).is_err());
+        fs::write(&file, "1\ntwo\n3\n").unwrap();
+        repo.add(&vec![file.to_str().unwrap()]).unwrap();
+        // The merge message is taken up, even though the tree is unchanged
+        let merge = repo.commit("").unwrap();
+        let commit = repo.read_commit(&merge).unwrap();
+        assert_eq!(*commit.get_parents(), vec![ours, theirs]);
+        assert_eq!(commit.get_message(), "Merge topic");
+        assert!(repo.merge_head().is_none());
+        assert!(repo.merge("topic", false, FastForward::Allow).unwrap());
+    }
+
+    #[test]
+    fn fast_forward_modes() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        let file = repo.dir.join("a.txt");
+        let commit_file = |content: &str, message: &str| {
+            fs::write(&file, content).unwrap();
+            repo.add(&vec![file.to_str().unwrap()]).unwrap();
+            repo.commit(message).unwrap()
+        };
+        let base = commit_file("1\n", "base");
+        repo.branch("topic").unwrap();
+        repo.checkout("topic", false).unwrap();
+        let ahead = commit_file("1\n2\n", "ahead");
+        repo.checkout("master", false).unwrap();
+
+        // Without a fast-forward a merge commit is created
+        assert!(repo.merge("topic", false, FastForward::Never).unwrap());
+        let merge = repo.get_current_commit().unwrap();
+        let commit = repo.read_commit(&merge).unwrap();
+        assert_eq!(*commit.get_parents(), vec![base.clone(), ahead.clone()]);
+        assert_eq!(fs::read_to_string(&file).unwrap(), "1\n2\n");
+
+        repo.reset(Some(base.to_string().as_str()), ResetMode::Hard)
+            .unwrap();
+        assert!(repo.merge("topic", false, FastForward::Only).unwrap());
+        assert_eq!(repo.get_current_commit(), Some(ahead));
+
+        // Diverged branches cannot be fast-forwarded
+        repo.reset(Some(base.to_string().as_str()), ResetMode::Hard)
+            .unwrap();
+        let ours = commit_file("0\n1\n", "ours");
+        assert!(repo.merge("topic", false, FastForward::Only).is_err());
+        assert_eq!(repo.get_current_commit(), Some(ours));
+        assert_eq!(fs::read_to_string(&file).unwrap(), "0\n1\n");
+    }
+}
@@ -0,0 +1,133 @@
+use crate::diff;
+
+/// Result of [`merge_text`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileMerge {
+    /// The merged text, with conflict markers around the hunks both sides
+    /// changed in different ways
+    pub content: String,
+    /// 1-based, inclusive line ranges of our version taking part in
+    /// conflicts
+    pub conflicts: Vec<(usize, usize)>,
+}
+
+impl FileMerge {
+    /// Whether both sides merged without conflicts
+    pub fn is_clean(&self) -> bool {
+        self.conflicts.is_empty()
+    }
+}
+
+/// Three-way merges the changes from `base` to `ours` and from `base` to
+/// `theirs`, like `git merge-file`. Hunks changed on one side only take
+/// that side; hunks changed on both sides in different ways are kept
+/// between `<<<<<<< ours_label` and `>>>>>>> theirs_label` markers.
+pub fn merge_text(
+    base: &str,
+    ours: &str,
+    theirs: &str,
+    ours_label: &str,
+    theirs_label: &str,
+) -> FileMerge {
+    let base_lines: Vec<&str> = base.split_inclusive('\n').collect();
+    let our_lines: Vec<&str> = ours.split_inclusive('\n').collect();
+    let their_lines: Vec<&str> = theirs.split_inclusive('\n').collect();
+    let to_ours = matching_lines(base, ours, base_lines.len());
+    let to_theirs = matching_lines(base, theirs, base_lines.len());
+
+    let mut merge = FileMerge {
+        content: String::new(),
+        conflicts: Vec::new(),
+    };
+    let (mut b, mut o, mut t) = (0, 0, 0);
+    loop {
+        if b < base_lines.len() && to_ours[b] == Some(o) && to_theirs[b] == Some(t) {
+            // A line all three versions share
+            merge.content.push_str(base_lines[b]);
+            (b, o, t) = (b + 1, o + 1, t + 1);
+            continue;
+        }
+        // The changed hunk reaches up to the next line all three share
+        let next = (b..base_lines.len())
+            .find_map(|line| Some((line, to_ours[line]?, to_theirs[line]?)))
+            .unwrap_or((base_lines.len(), our_lines.len(), their_lines.len()));
+        if next == (b, o, t) {
+            break;
+        }
+        let (base_hunk, our_hunk, their_hunk) = (
+            &base_lines[b..next.0],
+            &our_lines[o..next.1],
+            &their_lines[t..next.2],
+        );
+        if our_hunk == their_hunk || their_hunk == base_hunk {
+            merge.content.extend(our_hunk.iter().copied());
+        } else if our_hunk == base_hunk {
+            merge.content.extend(their_hunk.iter().copied());
+        } else {
+            merge.conflicts.push((o + 1, next.1.max(o + 1)));
+            merge.content.push_str(&format!("<<<<<<< {ours_label}\n"));
+            push_hunk(&mut merge.content, our_hunk);
+            merge.content.push_str("=======\n");
+            push_hunk(&mut merge.content, their_hunk);
+            merge.content.push_str(&format!(">>>>>>> {theirs_label}\n"));
+        }
+        (b, o, t) = next;
+    }
+    merge
+}
+
+/// For every line of `base`, the line of `other` it is kept as, if any
+fn matching_lines(base: &str, other: &str, base_len: usize) -> Vec<Option<usize>> {
+    let mut matches = vec![None; base_len];
+    let hunks = diff::diff_lines(base, other);
+    for (old, new) in diff::unchanged(&hunks, base_len, diff::lines(other).len()) {
+        matches[old] = Some(new);
+    }
+    matches
+}
+
+/// Appends the lines of one side of a conflict, ending the last line so
+/// that the next marker starts a line of its own
+fn push_hunk(content: &mut String, lines: &[&str]) {
+    content.extend(lines.iter().copied());
+    if !content.is_empty() && !content.ends_with('\n') {
+        content.push('\n');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changes_on_different_lines_merge_cleanly() {
+        let merge = merge_text(
+            "1\n2\n3\n4\n",
+            "one\n2\n3\n4\n",
+            "1\n2\n3\nfour\n",
+            "a",
+            "b",
+        );
+        assert!(merge.is_clean());
+        assert_eq!(merge.content, "one\n2\n3\nfour\n");
+
+        // Lines added at either end
+        let merge = merge_text("x\n", "top\nx\n", "x\nend\n", "a", "b");
+        assert_eq!(merge.content, "top\nx\nend\n");
+    }
+
+    #[test]
+    fn overlapping_changes_conflict() {
+        let merge = merge_text("1\n2\n3", "1\nours\n3", "1\ntheirs\n3", "HEAD", "topic");
+        assert_eq!(merge.conflicts, [(2, 2)]);
+        assert_eq!(
+            merge.content,
+            "1\n<<<<<<< HEAD\nours\n=======\ntheirs\n>>>>>>> topic\n3"
+        );
+
+        // Deleting a file that the other side changed
+        let merge = merge_text("a\n", "", "b\n", "HEAD", "topic");
+        assert!(!merge.is_clean());
+        assert_eq!(merge.content, "<<<<<<< HEAD\n=======\nb\n>>>>>>> topic\n");
+    }
+}
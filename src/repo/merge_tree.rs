@@ -0,0 +1,415 @@
+use super::merge_file::merge_text;
+use super::{IndexDiffType, Repository};
+use crate::index::{ConflictStages, Index};
+use crate::object::Blob;
+use crate::{EncodedSha, Error};
+use std::collections::HashSet;
+
+/// How a path ended up in conflict during a three-way merge
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictKind {
+    /// Both sides changed the file in different ways
+    Content,
+    /// The file was absent at the merge base and both sides added different contents
+    AddAdd,
+    /// Our side deleted the file while their side modified it
+    DeletedByUs,
+    /// Their side deleted the file while our side modified it
+    DeletedByThem,
+}
+
+impl ConflictKind {
+    /// The name Git reports the conflict under
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConflictKind::Content => "content",
+            ConflictKind::AddAdd => "add/add",
+            ConflictKind::DeletedByUs | ConflictKind::DeletedByThem => "modify/delete",
+        }
+    }
+
+    /// How `status` describes the conflict, e.g. "both modified"
+    pub fn describe(&self) -> &'static str {
+        match self {
+            ConflictKind::Content => "both modified",
+            ConflictKind::AddAdd => "both added",
+            ConflictKind::DeletedByUs => "deleted by us",
+            ConflictKind::DeletedByThem => "deleted by them",
+        }
+    }
+}
+
+/// A path the three-way merge could not resolve on its own.
+/// The merged tree holds a blob with conflict markers at this path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MergeConflict {
+    pub path: String,
+    pub kind: ConflictKind,
+    /// 1-based, inclusive line ranges of our version that differ from theirs
+    pub lines: Vec<(usize, usize)>,
+}
+
+/// Result of [`Repository::merge_trees`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeMerge {
+    /// Tree holding the merged snapshot, including conflicted files
+    pub tree: EncodedSha,
+    /// Conflicts sorted by path; empty when the merge is clean
+    pub conflicts: Vec<MergeConflict>,
+}
+
+impl Repository {
+    /// Merges `ours` and `theirs` relative to `base` without touching the
+    /// index or the working tree. All three arguments are tree SHA1s; the
+    /// merged tree and conflicted blobs are written to the object database.
+    ///
+    /// # Returns
+    /// - `Ok(TreeMerge)`: The merged tree and the list of conflicts
+    /// - `Err(Error)`: One of the trees or blobs cannot be read
+    pub fn merge_trees(
+        &self,
+        base: &EncodedSha,
+        ours: &EncodedSha,
+        theirs: &EncodedSha,
+    ) -> Result<TreeMerge, Error> {
+        let base_index = self.read_tree(base)?;
+        let our_index = self.read_tree(ours)?;
+        let their_index = self.read_tree(theirs)?;
+        let (merged, conflicts) =
+            self.merge_indexes(&base_index, &our_index, &their_index, "theirs")?;
+        let tree = self.write_tree_impl(merged.get_root())?;
+        Ok(TreeMerge { tree, conflicts })
+    }
+
+    /// Performs `merge-tree` for two revisions and prints the merged tree
+    /// followed by one line per conflict.
+    ///
+    /// # Arguments
+    /// * `ours` - Branch or commit whose side is kept on conflicts
+    /// * `theirs` - Branch or commit being merged in
+    /// * `merge_base` - Revision to use as the merge base; defaults to the
+    ///   common ancestor of both sides
+    ///
+    /// # Returns
+    /// - `Ok(bool)`: Whether the merge is free of conflicts
+    /// - `Err(Error)`: A revision cannot be resolved or read
+    pub fn merge_tree(
+        &self,
+        ours: &str,
+        theirs: &str,
+        merge_base: Option<&str>,
+    ) -> Result<bool, Error> {
+        let merge = self.merge_revisions(ours, theirs, merge_base)?;
+        println!("{}", merge.tree);
+        if !merge.conflicts.is_empty() {
+            println!();
+        }
+        for conflict in &merge.conflicts {
+            println!(
+                "CONFLICT ({}): Merge conflict in {}",
+                conflict.kind.as_str(),
+                conflict.path
+            );
+        }
+        Ok(merge.conflicts.is_empty())
+    }
+
+    fn merge_revisions(
+        &self,
+        ours: &str,
+        theirs: &str,
+        merge_base: Option<&str>,
+    ) -> Result<TreeMerge, Error> {
+        let our_sha = self.resolve_commit(ours)?;
+        let their_sha = self.resolve_commit(theirs)?;
+        let base_sha = match merge_base {
+            Some(rev) => self.resolve_commit(rev)?,
+            None => self
+                .find_lca(&our_sha, &their_sha)
+                .ok_or_else(|| format!("no merge base between {ours} and {theirs}"))?,
+        };
+        let base_tree = self.read_commit(&base_sha)?.get_tree_sha();
+        let our_tree = self.read_commit(&our_sha)?.get_tree_sha();
+        let their_tree = self.read_commit(&their_sha)?.get_tree_sha();
+        self.merge_trees(&base_tree, &our_tree, &their_tree)
+    }
+
+    /// Applies the per-file merge rules to three snapshots. The result starts
+    /// from `ours` and takes over the changes made on `theirs`; files changed
+    /// on both sides are merged line by line with [`merge_text`], and
+    /// conflicting files are replaced by blobs with conflict markers, the
+    /// side of `theirs` labeled `theirs_label`.
+    pub(super) fn merge_indexes(
+        &self,
+        base: &Index,
+        ours: &Index,
+        theirs: &Index,
+        theirs_label: &str,
+    ) -> Result<(Index, Vec<MergeConflict>), String> {
+        let mut index = ours.clone();
+        let mut conflicts = Vec::new();
+
+        let diff_base_ours = self.diff_index(base, ours);
+        let diff_base_theirs = self.diff_index(base, theirs);
+
+        // Collect all unique files from both diffs
+        let all_files: HashSet<_> = diff_base_ours
+            .keys()
+            .chain(diff_base_theirs.keys())
+            .cloned()
+            .collect();
+
+        // Merge logic for each file
+        for file_path in all_files {
+            let cur_status = diff_base_ours.get(&file_path);
+            let branch_status = diff_base_theirs.get(&file_path);
+
+            if let (Some(cur_status), Some(branch_status)) = (cur_status, branch_status) {
+                match (cur_status, branch_status) {
+                    // Both modified differently - Conflict
+                    // 8 Any files modified in different ways in the current and given branches are in conflict.
+                    // 8.1 the contents of both are changed and different from other.
+                    // 8.3 the file was absent at the split point and has different
+                    // contents in the given and current branches.
+                    (IndexDiffType::Modified, IndexDiffType::Modified)
+                    | (IndexDiffType::RightOnly, IndexDiffType::RightOnly) => {
+                        let cur_sha = ours.get_sha1(&file_path).unwrap();
+                        let branch_sha = theirs.get_sha1(&file_path).unwrap();
+                        // 3. Any files that have been modified in both the current and given branch in the same way
+                        // are left unchanged by the merge.
+                        // 3.1. Both files now have the same content
+                        if cur_sha != branch_sha {
+                            let kind = if *cur_status == IndexDiffType::Modified {
+                                ConflictKind::Content
+                            } else {
+                                ConflictKind::AddAdd
+                            };
+                            // Changes to different lines merge cleanly
+                            let lines = self.merge_file(
+                                &file_path,
+                                base,
+                                self.blob_text(Some(cur_sha))?,
+                                self.blob_text(Some(branch_sha))?,
+                                theirs_label,
+                                &mut index,
+                            )?;
+                            if !lines.is_empty() {
+                                conflicts.push(MergeConflict {
+                                    path: file_path.clone(),
+                                    kind,
+                                    lines,
+                                });
+                            }
+                        }
+                    }
+
+                    // Current deleted, branch modified (or vice versa) - Conflict
+                    // 8.2 the contents of one are changed and the other file is deleted
+                    (IndexDiffType::LeftOnly, IndexDiffType::Modified)
+                    | (IndexDiffType::Modified, IndexDiffType::LeftOnly) => {
+                        let (cur_content, branch_content, kind) =
+                            if let IndexDiffType::LeftOnly = cur_status {
+                                let sha = theirs.get_sha1(&file_path).unwrap();
+                                let content = self.blob_text(Some(sha))?;
+                                (String::new(), content, ConflictKind::DeletedByUs)
+                            } else {
+                                let sha = ours.get_sha1(&file_path).unwrap();
+                                let content = self.blob_text(Some(sha))?;
+                                (content, String::new(), ConflictKind::DeletedByThem)
+                            };
+                        let lines = self.merge_file(
+                            &file_path,
+                            base,
+                            cur_content,
+                            branch_content,
+                            theirs_label,
+                            &mut index,
+                        )?;
+                        conflicts.push(MergeConflict {
+                            path: file_path.clone(),
+                            kind,
+                            lines,
+                        });
+                    }
+
+                    // 1. Any files that have been modified in the given branch since the split point,
+                    // but not modified in the current branch since the split point should be changed to their versions in the given branch
+                    (IndexDiffType::Unmodified, IndexDiffType::Modified) => {
+                        let sha = theirs.get_sha1(&file_path).unwrap();
+                        index.update_entry(&file_path, sha.clone());
+                    }
+
+                    // 2. Any files that have been modified in the current branch but not in the given branch
+                    // since the split point should stay as they are.
+                    (IndexDiffType::Modified, IndexDiffType::Unmodified) => (),
+
+                    // 3.2 Both files were both removed are left unchanged by the merge.
+                    (IndexDiffType::LeftOnly, IndexDiffType::LeftOnly) => (),
+
+                    // 6. Any files present at the split point, unmodified in the current branch,
+                    // and absent in the given branch should be removed (and untracked).
+                    (IndexDiffType::Unmodified, IndexDiffType::LeftOnly) => {
+                        index.remove_entry(&file_path);
+                    }
+
+                    // 7. Any files present at the split point, unmodified in the given branch,
+                    // and absent in the current branch should remain absent.
+                    (IndexDiffType::LeftOnly, IndexDiffType::Unmodified) => (),
+
+                    // Other cases
+                    _ => (),
+                }
+            }
+            if branch_status.is_none() {
+                let cur_status = cur_status.unwrap();
+                match cur_status {
+                    // 4. Any files that were not present at the split point and are present only in the current branch
+                    // should remain as they are.
+                    IndexDiffType::RightOnly => (),
+                    _ => unreachable!(),
+                }
+            }
+            if cur_status.is_none() {
+                let branch_status = branch_status.unwrap();
+                // 5. Any files that were not present at the split point
+                // and are present only in the given branch should be checked out and staged.
+                match branch_status {
+                    IndexDiffType::RightOnly => {
+                        let sha = theirs.get_sha1(&file_path).unwrap();
+                        index.update_entry(&file_path, sha.clone());
+                    }
+                    _ => unreachable!(),
+                }
+            }
+        }
+
+        conflicts.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok((index, conflicts))
+    }
+
+    /// Stages the three-way merge of our and their content at `path`,
+    /// conflict markers included, and returns the line ranges of our
+    /// version in conflict
+    fn merge_file(
+        &self,
+        path: &str,
+        base: &Index,
+        cur_content: String,
+        branch_content: String,
+        theirs_label: &str,
+        index: &mut Index,
+    ) -> Result<Vec<(usize, usize)>, String> {
+        let base_content = self.blob_text(base.get_sha1(path))?;
+        let merge = merge_text(
+            &base_content,
+            &cur_content,
+            &branch_content,
+            "HEAD",
+            theirs_label,
+        );
+        let blob = Blob {
+            data: merge.content.into(),
+        };
+        let blob_sha = self.obj_db.store(&blob).map_err(|why| why.to_string())?;
+        index.update_entry(path, blob_sha);
+        Ok(merge.conflicts)
+    }
+
+    /// Replaces the conflict-marker entries [`Self::merge_indexes`] left in
+    /// `merged` by the base, ours and theirs stages of each conflicted path,
+    /// once the markers have been written to the working tree
+    pub(super) fn stage_conflicts(
+        merged: &mut Index,
+        conflicts: &[MergeConflict],
+        base: &Index,
+        ours: &Index,
+        theirs: &Index,
+    ) {
+        for conflict in conflicts {
+            let path = &conflict.path;
+            let stages = ConflictStages {
+                base: base.get_sha1(path).cloned(),
+                ours: ours.get_sha1(path).cloned(),
+                theirs: theirs.get_sha1(path).cloned(),
+                mode: [ours, theirs, base]
+                    .iter()
+                    .find_map(|index| index.get_mode(path))
+                    .unwrap_or_default(),
+            };
+            merged.add_conflict(path, stages);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::init_test_repo;
+    use tempfile::TempDir;
+
+    fn snapshot(repo: &Repository, files: &[(&str, &str)]) -> EncodedSha {
+        let mut index = Index::new();
+        for (path, content) in files {
+            let blob = Blob {
+                data: content.as_bytes().to_vec(),
+            };
+            index.update_entry(path, repo.obj_db.store(&blob).unwrap());
+        }
+        repo.write_tree_impl(index.get_root()).unwrap()
+    }
+
+    #[test]
+    fn clean_merge_takes_both_sides() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        let base = snapshot(&repo, &[("a.txt", "a"), ("b.txt", "b"), ("c.txt", "c")]);
+        let ours = snapshot(&repo, &[("a.txt", "a2"), ("b.txt", "b"), ("c.txt", "c")]);
+        let theirs = snapshot(&repo, &[("a.txt", "a"), ("b.txt", "b2"), ("d/e.txt", "e")]);
+
+        let merge = repo.merge_trees(&base, &ours, &theirs).unwrap();
+        assert!(merge.conflicts.is_empty());
+        let expected = snapshot(&repo, &[("a.txt", "a2"), ("b.txt", "b2"), ("d/e.txt", "e")]);
+        assert_eq!(merge.tree, expected);
+        // Nothing outside the object database is touched
+        assert!(!repo.dir.join("d").exists());
+    }
+
+    #[test]
+    fn conflicts_are_reported() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        let base = snapshot(&repo, &[("a.txt", "1\n2\n3"), ("gone.txt", "x")]);
+        let ours = snapshot(&repo, &[("a.txt", "1\nours\n3"), ("new.txt", "ours")]);
+        let theirs = snapshot(
+            &repo,
+            &[
+                ("a.txt", "1\ntheirs\n3"),
+                ("gone.txt", "y"),
+                ("new.txt", "theirs"),
+            ],
+        );
+
+        let merge = repo.merge_trees(&base, &ours, &theirs).unwrap();
+        let summary: Vec<_> = merge
+            .conflicts
+            .iter()
+            .map(|c| (c.path.as_str(), c.kind))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("a.txt", ConflictKind::Content),
+                ("gone.txt", ConflictKind::DeletedByUs),
+                ("new.txt", ConflictKind::AddAdd),
+            ]
+        );
+        assert_eq!(merge.conflicts[0].lines, vec![(2, 2)]);
+
+        let merged = repo.read_tree(&merge.tree).unwrap();
+        let text = repo.blob_text(merged.get_sha1("a.txt")).unwrap();
+        assert_eq!(
+            text,
+            "1\n<<<<<<< HEAD\nours\n=======\ntheirs\n>>>>>>> theirs\n3"
+        );
+    }
+}
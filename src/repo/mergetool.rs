@@ -0,0 +1,173 @@
+use super::Repository;
+use super::pathspec::{matches_pathspec, normalize_pathspecs};
+use crate::Error;
+use std::fs;
+use std::path::Path;
+use std::process;
+
+/// Command lines of the merge tools known without `mergetool.<tool>.cmd`
+const BUILTIN_TOOLS: &[(&str, &str)] = &[
+    ("meld", r#"meld "$LOCAL" "$MERGED" "$REMOTE""#),
+    ("vimdiff", r#"vimdiff -d "$LOCAL" "$MERGED" "$REMOTE""#),
+    (
+        "kdiff3",
+        r#"kdiff3 --auto "$BASE" "$LOCAL" "$REMOTE" -o "$MERGED""#,
+    ),
+];
+
+impl Repository {
+    /// Runs a merge tool on every unmerged path matching `paths`, or all of
+    /// them, like `git mergetool`. The base, our and their versions are
+    /// written next to the file as `<name>_BASE_<pid>` and so on, and the
+    /// tool's command line finds them in `$BASE`, `$LOCAL`, `$REMOTE` and
+    /// the file to resolve in `$MERGED`. A path is staged, and so
+    /// resolved, when the tool exits successfully.
+    ///
+    /// # Arguments
+    /// * `tool` - Tool to run; defaults to `merge.tool`. Its command line
+    ///   is `mergetool.<tool>.cmd` unless it is one of the built-in tools.
+    /// * `paths` - Limit the paths to resolve
+    ///
+    /// # Returns
+    /// The paths left unmerged
+    pub fn mergetool<S: AsRef<str>>(
+        &self,
+        tool: Option<&str>,
+        paths: &[S],
+    ) -> Result<Vec<String>, Error> {
+        let pathspecs = normalize_pathspecs(paths);
+        let config = self.config()?;
+        let tool = tool
+            .or(config.get("merge.tool"))
+            .ok_or("No merge tool configured; set merge.tool or use --tool")?;
+        let cmd = config
+            .get(&format!("mergetool.{tool}.cmd"))
+            .or_else(|| {
+                BUILTIN_TOOLS
+                    .iter()
+                    .find(|(name, _)| *name == tool)
+                    .map(|(_, cmd)| *cmd)
+            })
+            .ok_or_else(|| format!("unknown merge tool '{tool}'"))?
+            .to_string();
+
+        let index = self.load_index_or_empty()?;
+        let conflicts: Vec<_> = index
+            .conflicts()
+            .iter()
+            .filter(|(path, _)| matches_pathspec(path, &pathspecs))
+            .collect();
+        if conflicts.is_empty() {
+            println!("No files need merging");
+            return Ok(Vec::new());
+        }
+        let mut unmerged = Vec::new();
+        for (path, stages) in conflicts {
+            println!("Merging: {path}");
+            let versions = [
+                ("BASE", &stages.base),
+                ("LOCAL", &stages.ours),
+                ("REMOTE", &stages.theirs),
+            ];
+            let mut command = shell(&cmd);
+            command
+                .current_dir(&self.dir)
+                .env("MERGED", path)
+                .stdin(process::Stdio::inherit());
+            let mut temp_files = Vec::new();
+            for (name, sha) in versions {
+                let temp = stage_file_name(path, name);
+                fs::write(self.dir.join(&temp), self.blob_bytes(sha.as_ref())?)?;
+                command.env(name, &temp);
+                temp_files.push(temp);
+            }
+            let status = command.status();
+            for temp in temp_files {
+                let _ = fs::remove_file(self.dir.join(temp));
+            }
+            let status = status.map_err(|why| format!("cannot run merge tool '{tool}': {why}"))?;
+            if status.success() {
+                self.update_index(&self.dir.join(path))?;
+            } else {
+                println!("merge of {path} failed");
+                unmerged.push(path.clone());
+            }
+        }
+        Ok(unmerged)
+    }
+}
+
+/// `dir/name_BASE_<pid>.ext` for `dir/name.ext`, keeping the extension so
+/// that tools recognize the file type
+fn stage_file_name(path: &str, stage: &str) -> String {
+    let file = Path::new(path);
+    let stem = file.file_stem().unwrap_or_default().to_string_lossy();
+    let mut name = format!("{stem}_{stage}_{}", process::id());
+    if let Some(ext) = file.extension() {
+        name.push('.');
+        name.push_str(&ext.to_string_lossy());
+    }
+    match path.rfind('/') {
+        Some(end) => format!("{}{name}", &path[..=end]),
+        None => name,
+    }
+}
+
+/// A command running `cmd` through the shell
+pub(super) fn shell(cmd: &str) -> process::Command {
+    let mut command;
+    if cfg!(windows) {
+        command = process::Command::new("cmd");
+        command.arg("/C");
+    } else {
+        command = process::Command::new("sh");
+        command.arg("-c");
+    }
+    command.arg(cmd);
+    command
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::repo::{FastForward, init_test_repo};
+    use tempfile::TempDir;
+
+    #[test]
+    fn mergetool_resolves_paths_the_tool_merged() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        let file = repo.dir.join("a.txt");
+        let commit_file = |content: &str, message: &str| {
+            fs::write(&file, content).unwrap();
+            repo.add(&vec![file.to_str().unwrap()]).unwrap();
+            repo.commit(message).unwrap()
+        };
+        commit_file("base\n", "base");
+        repo.branch("topic").unwrap();
+        commit_file("ours\n", "ours");
+        repo.checkout("topic", false).unwrap();
+        commit_file("theirs\n", "theirs");
+        repo.checkout("master", false).unwrap();
+        assert!(!repo.merge("topic", false, FastForward::Allow).unwrap());
+
+        assert!(repo.mergetool(None, &[] as &[&str]).is_err());
+        repo.set_config("mergetool.fail.cmd", "false").unwrap();
+        assert_eq!(
+            repo.mergetool(Some("fail"), &[] as &[&str]).unwrap(),
+            ["a.txt"]
+        );
+        repo.set_config("merge.tool", "cat").unwrap();
+        repo.set_config(
+            "mergetool.cat.cmd",
+            r#"cat "$BASE" "$LOCAL" "$REMOTE" > "$MERGED""#,
+        )
+        .unwrap();
+        assert!(repo.mergetool(None, &[] as &[&str]).unwrap().is_empty());
+        assert_eq!(fs::read_to_string(&file).unwrap(), "base\nours\ntheirs\n");
+        let index = repo.load_index_or_empty().unwrap();
+        assert!(index.conflicts().is_empty());
+        // The stage files are cleaned up
+        assert_eq!(fs::read_dir(&repo.dir).unwrap().count(), 2);
+    }
+}
@@ -0,0 +1,207 @@
+use super::Repository;
+use super::am::AM_DIR;
+use super::cherry_pick::{CHERRY_PICK_HEAD, SEQUENCER_DIR};
+use super::merge::MERGE_HEAD;
+use super::revert::REVERT_HEAD;
+use crate::{EncodedSha, Error};
+use std::fs;
+use std::str::FromStr;
+
+/// Where HEAD was before the last reset, merge or `am` moved it
+pub(super) const ORIG_HEAD: &str = "ORIG_HEAD";
+/// The state files naming a commit, which resolve as revisions
+pub(super) const STATE_HEADS: [&str; 4] = [ORIG_HEAD, MERGE_HEAD, CHERRY_PICK_HEAD, REVERT_HEAD];
+/// Why a revert or cherry-pick cannot start
+const PICK_IN_PROGRESS: &str =
+    "a revert or cherry-pick is already in progress; conclude it or reset first";
+
+/// An operation that stopped half-way and waits for the user
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Operation {
+    /// A merge of the given commit stopped at conflicts
+    Merge(EncodedSha),
+    /// A revert of the given commit stopped at conflicts
+    Revert(EncodedSha),
+    /// A cherry-pick of the given commit stopped at conflicts
+    CherryPick(EncodedSha),
+    /// `am` stopped at a patch that does not apply
+    Am,
+    /// A bisection started from the given branch or commit
+    Bisect(String),
+}
+
+/// The state the operations leave in the git dir, see
+/// [`Repository::operation_state`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OperationState {
+    /// Where HEAD was before the last reset, merge or `am` moved it
+    pub orig_head: Option<EncodedSha>,
+    /// The operations in progress, e.g. a merge stopped at conflicts. Only
+    /// a bisection can be in progress together with another operation.
+    pub in_progress: Vec<Operation>,
+}
+
+impl Repository {
+    /// What the operations recorded in the git dir: `ORIG_HEAD` and the
+    /// operations waiting to be continued or aborted
+    pub fn operation_state(&self) -> OperationState {
+        let mut in_progress = Vec::new();
+        if let Some(sha) = self.state_head(MERGE_HEAD) {
+            in_progress.push(Operation::Merge(sha));
+        }
+        if let Some(sha) = self.state_head(REVERT_HEAD) {
+            in_progress.push(Operation::Revert(sha));
+        }
+        if let Some(sha) = self.state_head(CHERRY_PICK_HEAD) {
+            in_progress.push(Operation::CherryPick(sha));
+        }
+        if self.git_dir.join(AM_DIR).is_dir() {
+            in_progress.push(Operation::Am);
+        }
+        if let Some(start) = self.bisect_start_point() {
+            in_progress.push(Operation::Bisect(start));
+        }
+        OperationState {
+            orig_head: self.state_head(ORIG_HEAD),
+            in_progress,
+        }
+    }
+
+    /// The commit a state file such as `ORIG_HEAD` or `MERGE_HEAD` names
+    pub(super) fn state_head(&self, name: &str) -> Option<EncodedSha> {
+        let content = fs::read_to_string(self.git_dir.join(name)).ok()?;
+        EncodedSha::from_str(content.trim()).ok()
+    }
+
+    /// Records the current commit in `ORIG_HEAD` before HEAD is moved, so
+    /// that the move can be undone with `reset --hard ORIG_HEAD`
+    pub(super) fn save_orig_head(&self) -> Result<(), Error> {
+        if let Some(head) = self.get_current_commit() {
+            fs::write(self.git_dir.join(ORIG_HEAD), format!("{head}\n"))?;
+        }
+        Ok(())
+    }
+
+    /// Refuses to start an operation while another one waits to be
+    /// continued or aborted. A bisection does not get in the way.
+    pub(super) fn check_no_operation_in_progress(&self) -> Result<(), Error> {
+        let state = self.operation_state();
+        let blocking = state
+            .in_progress
+            .iter()
+            .find(|operation| !matches!(operation, Operation::Bisect(_)));
+        match blocking {
+            Some(Operation::Merge(_)) => Err("You have not concluded your merge \
+                                               (MERGE_HEAD exists); commit or abort it first"
+                .into()),
+            Some(Operation::Am) => {
+                Err("an am session is already in progress; use --continue or --abort".into())
+            }
+            Some(_) => Err(PICK_IN_PROGRESS.into()),
+            // A cherry-pick sequence goes on after its conflicts are committed
+            None if self.git_dir.join(SEQUENCER_DIR).exists() => Err(PICK_IN_PROGRESS.into()),
+            None => Ok(()),
+        }
+    }
+
+    /// Prints what `status` says about the operations in progress, with the
+    /// commands to go on or to give up, depending on whether `unmerged`
+    /// paths are left
+    pub(super) fn print_operations(&self, unmerged: bool) {
+        for operation in self.operation_state().in_progress {
+            match operation {
+                Operation::Merge(_) => {
+                    println!("You are in the middle of a merge.");
+                    if unmerged {
+                        println!("You have unmerged paths.");
+                        println!("  (fix conflicts and run \"rust-git commit\")");
+                    } else {
+                        println!("All conflicts fixed but you are still merging.");
+                        println!("  (use \"rust-git commit\" to conclude merge)");
+                    }
+                    println!("  (use \"rust-git merge --abort\" to abort the merge)");
+                }
+                Operation::Revert(sha) => {
+                    println!("You are currently reverting commit {}.", &sha.0[..7]);
+                    if unmerged {
+                        println!("  (fix conflicts and run \"rust-git commit\")");
+                    } else {
+                        println!("  (all conflicts fixed: run \"rust-git commit\")");
+                    }
+                    println!("  (use \"rust-git reset --hard\" to abort the revert)");
+                }
+                Operation::CherryPick(sha) => {
+                    println!("You are currently cherry-picking commit {}.", &sha.0[..7]);
+                    if unmerged {
+                        println!("  (fix conflicts and run \"rust-git cherry-pick --continue\")");
+                    } else {
+                        println!(
+                            "  (all conflicts fixed: run \"rust-git cherry-pick --continue\")"
+                        );
+                    }
+                    println!(
+                        "  (use \"rust-git cherry-pick --abort\" to cancel the cherry-pick operation)"
+                    );
+                }
+                Operation::Am => {
+                    println!("You are in the middle of an am session.");
+                    println!("  (fix the patch and run \"rust-git am --continue\")");
+                    println!("  (use \"rust-git am --abort\" to restore the original branch)");
+                }
+                Operation::Bisect(start) => {
+                    println!("You are currently bisecting, started from branch '{start}'.");
+                    println!(
+                        "  (use \"rust-git bisect reset\" to get back to the original branch)"
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::{FastForward, ResetMode, init_test_repo};
+    use tempfile::TempDir;
+
+    #[test]
+    fn stopped_merge_is_in_progress_until_committed() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        let file = repo.dir.join("a.txt");
+        let commit_file = |content: &str, message: &str| {
+            fs::write(&file, content).unwrap();
+            repo.add(&vec![file.to_str().unwrap()]).unwrap();
+            repo.commit(message).unwrap()
+        };
+        commit_file("base\n", "base");
+        repo.branch("topic").unwrap();
+        let ours = commit_file("ours\n", "ours");
+        repo.checkout("topic", false).unwrap();
+        let theirs = commit_file("theirs\n", "theirs");
+        repo.checkout("master", false).unwrap();
+        assert_eq!(
+            repo.operation_state(),
+            OperationState {
+                orig_head: None,
+                in_progress: Vec::new()
+            }
+        );
+
+        assert!(!repo.merge("topic", false, FastForward::Allow).unwrap());
+        assert!(repo.revert("HEAD").is_err());
+        let state = repo.operation_state();
+        assert_eq!(state.orig_head, Some(ours.clone()));
+        assert_eq!(state.in_progress, [Operation::Merge(theirs.clone())]);
+        assert_eq!(repo.resolve_commit("MERGE_HEAD").unwrap(), theirs);
+        commit_file("both\n", "");
+        assert!(repo.operation_state().in_progress.is_empty());
+        assert!(repo.resolve_commit("MERGE_HEAD").is_err());
+
+        // ORIG_HEAD undoes the merge
+        repo.reset(Some("ORIG_HEAD"), ResetMode::Hard).unwrap();
+        assert_eq!(repo.get_current_commit(), Some(ours));
+        assert_eq!(fs::read_to_string(&file).unwrap(), "ours\n");
+    }
+}
@@ -0,0 +1,178 @@
+use walkdir::WalkDir;
+
+use super::tag::TAGS_DIR;
+use super::{Branch, HEADS_DIR, REFS_DIR, Repository};
+use crate::lock::{self, LOCK_SUFFIX};
+use crate::{EncodedSha, Error};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+/// File in the git dir holding the refs that have no loose file
+pub(super) const PACKED_REFS_FILE: &str = "packed-refs";
+
+/// First line of packed-refs as Git writes it. Every annotated tag is
+/// followed by a `^<sha>` line naming the object it peels to.
+const PACKED_REFS_HEADER: &str = "# pack-refs with: peeled fully-peeled sorted ";
+
+/// Reads the refs in `<git_dir>/packed-refs` by their full name, e.g.
+/// `refs/heads/master`. A missing file holds no refs.
+pub(super) fn read_packed_refs(git_dir: &Path) -> BTreeMap<String, EncodedSha> {
+    let mut refs = BTreeMap::new();
+    let Ok(content) = fs::read_to_string(git_dir.join(PACKED_REFS_FILE)) else {
+        return refs;
+    };
+    for line in content.lines() {
+        // Skip the header and peeled tag lines
+        if line.starts_with('#') || line.starts_with('^') {
+            continue;
+        }
+        if let Some((sha, name)) = line.split_once(' ')
+            && let Ok(sha) = EncodedSha::from_str(sha)
+        {
+            refs.insert(name.to_string(), sha);
+        }
+    }
+    refs
+}
+
+impl Repository {
+    /// Looks up the full ref name `name` in packed-refs
+    pub(super) fn packed_ref(&self, name: &str) -> Option<EncodedSha> {
+        read_packed_refs(&self.git_dir).remove(name)
+    }
+
+    /// The branches recorded only in packed-refs
+    pub(super) fn packed_branches(&self) -> Vec<Branch> {
+        let prefix = format!("{REFS_DIR}/{HEADS_DIR}/");
+        read_packed_refs(&self.git_dir)
+            .into_iter()
+            .filter_map(|(name, sha)| {
+                let name = name.strip_prefix(&prefix)?.to_string();
+                Some(Branch {
+                    name,
+                    commit_sha: Some(sha),
+                })
+            })
+            .collect()
+    }
+
+    /// Renames or drops packed refs: `update` maps each ref name to its new
+    /// name, or to `None` to drop it. Returns whether anything changed.
+    pub(super) fn update_packed_refs<F>(&self, mut update: F) -> io::Result<bool>
+    where
+        F: FnMut(&str) -> Option<String>,
+    {
+        let refs = read_packed_refs(&self.git_dir);
+        let mut changed = false;
+        let mut updated = BTreeMap::new();
+        for (name, sha) in refs {
+            match update(&name) {
+                Some(new_name) => {
+                    changed |= new_name != name;
+                    updated.insert(new_name, sha);
+                }
+                None => changed = true,
+            }
+        }
+        if changed {
+            self.write_packed_refs(&updated)?;
+        }
+        Ok(changed)
+    }
+
+    /// Moves every loose ref below refs/ into packed-refs, like
+    /// `git pack-refs --all`, and returns how many were packed. Unborn
+    /// branches have no commit to record and stay loose.
+    pub fn pack_refs(&self) -> Result<usize, Error> {
+        let refs_dir = self.git_dir.join(REFS_DIR);
+        let mut refs = read_packed_refs(&self.git_dir);
+        let mut loose = Vec::new();
+        for entry in WalkDir::new(&refs_dir)
+            .sort_by_file_name()
+            .into_iter()
+            .filter_map(|e| e.ok())
+        {
+            let file_name = entry.file_name().to_string_lossy();
+            if !entry.file_type().is_file() || file_name.ends_with(LOCK_SUFFIX) {
+                continue;
+            }
+            let content = fs::read_to_string(entry.path())?;
+            let Ok(sha) = EncodedSha::from_str(content.trim()) else {
+                continue;
+            };
+            let name = entry.path().strip_prefix(&self.git_dir).unwrap();
+            refs.insert(name.to_string_lossy().replace('\\', "/"), sha);
+            loose.push(entry.path().to_path_buf());
+        }
+        self.write_packed_refs(&refs)?;
+
+        for path in &loose {
+            fs::remove_file(path)?;
+            // Prune the directories left empty, keeping refs/<kind> itself
+            let mut dir = path.parent();
+            while let Some(parent) = dir
+                && parent.parent() != Some(refs_dir.as_path())
+                && parent != refs_dir
+                && fs::remove_dir(parent).is_ok()
+            {
+                dir = parent.parent();
+            }
+        }
+        Ok(loose.len())
+    }
+
+    fn write_packed_refs(&self, refs: &BTreeMap<String, EncodedSha>) -> io::Result<()> {
+        let mut content = format!("{PACKED_REFS_HEADER}\n");
+        for (name, sha) in refs {
+            content.push_str(&format!("{sha} {name}\n"));
+            if name.starts_with(&format!("{REFS_DIR}/{TAGS_DIR}/")) && self.read_tag(sha).is_some()
+            {
+                content.push_str(&format!("^{}\n", self.peel_tag(sha).0));
+            }
+        }
+        lock::write_locked(&self.git_dir.join(PACKED_REFS_FILE), content)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::init_test_repo;
+    use tempfile::TempDir;
+
+    #[test]
+    fn packed_refs_resolve_and_update() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        let file = repo.dir.join("a.txt");
+        fs::write(&file, "a").unwrap();
+        repo.add(&vec![file.to_str().unwrap()]).unwrap();
+        let first = repo.commit("first").unwrap();
+        repo.branch("feature/x").unwrap();
+
+        assert_eq!(repo.pack_refs().unwrap(), 2);
+        assert!(!repo.get_branch_dir().join("master").exists());
+        assert!(!repo.get_branch_dir().join("feature").exists());
+        assert!(repo.get_branch_dir().is_dir());
+        assert_eq!(repo.get_current_commit(), Some(first.clone()));
+        assert_eq!(repo.resolve_commit("feature/x"), Ok(first.clone()));
+        let names: Vec<_> = repo.list_branches().into_iter().map(|b| b.name).collect();
+        assert_eq!(names, ["feature/x", "master"]);
+        assert!(repo.branch("feature/x").is_err());
+
+        // A loose ref written later takes precedence over the packed one
+        fs::write(&file, "b").unwrap();
+        repo.add(&vec![file.to_str().unwrap()]).unwrap();
+        let second = repo.commit("second").unwrap();
+        assert_eq!(repo.resolve_commit("master"), Ok(second));
+        assert_eq!(repo.packed_ref("refs/heads/master"), Some(first));
+
+        // Deleting a packed branch drops it from packed-refs
+        repo.rm_branch("feature/x").unwrap();
+        assert!(repo.load_branch("feature/x").is_none());
+        assert!(repo.packed_ref("refs/heads/feature/x").is_none());
+    }
+}
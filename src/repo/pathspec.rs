@@ -0,0 +1,74 @@
+use crate::ignore::glob_to_regex;
+use regex::Regex;
+
+/// Normalizes pathspecs given on the command line
+pub(super) fn normalize_pathspecs<S: AsRef<str>>(paths: &[S]) -> Vec<String> {
+    paths
+        .iter()
+        .map(|p| normalize_pathspec(p.as_ref()))
+        .collect()
+}
+
+/// Strips `./` prefixes and trailing slashes so pathspecs compare against index paths
+fn normalize_pathspec(spec: &str) -> String {
+    let spec = spec.replace('\\', "/");
+    let mut spec = spec.as_str();
+    while let Some(stripped) = spec.strip_prefix("./") {
+        spec = stripped;
+    }
+    spec.trim_end_matches('/').to_string()
+}
+
+/// A path matches if it equals a pathspec or lives below a pathspec directory
+pub(super) fn matches_pathspec(path: &str, pathspecs: &[String]) -> bool {
+    pathspecs.is_empty()
+        || pathspecs.iter().any(|spec| {
+            spec.is_empty() || spec == "." || path == spec || path.starts_with(&format!("{spec}/"))
+        })
+}
+
+/// Whether a directory may hold paths matched by the pathspecs
+pub(super) fn may_contain_matches(dir: &str, pathspecs: &[String]) -> bool {
+    matches_pathspec(dir, pathspecs)
+        || pathspecs
+            .iter()
+            .any(|spec| spec.starts_with(&format!("{dir}/")))
+}
+
+/// Whether a pathspec is a glob pattern like `src/**/*.rs`
+pub(super) fn is_glob(spec: &str) -> bool {
+    spec.contains(['*', '?', '['])
+}
+
+/// Compiles a glob pathspec into a regex matching whole repository-relative
+/// paths, with the wildcards of `.gitignore` patterns
+pub(super) fn glob_pathspec(spec: &str) -> Result<Regex, String> {
+    let pattern = glob_to_regex(&normalize_pathspec(spec));
+    Regex::new(&format!("^{pattern}$")).map_err(|why| format!("invalid pathspec '{spec}': {why}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pathspec_matching() {
+        let specs = vec![normalize_pathspec("./src/")];
+        assert!(matches_pathspec("src/main.rs", &specs));
+        assert!(!matches_pathspec("srcs/main.rs", &specs));
+        assert!(may_contain_matches("src", &specs));
+        assert!(!may_contain_matches("docs", &specs));
+
+        let specs = vec![normalize_pathspec("src/bin/tool.rs")];
+        assert!(may_contain_matches("src", &specs));
+        assert!(may_contain_matches("src/bin", &specs));
+        assert!(!may_contain_matches("src/lib", &specs));
+
+        assert!(is_glob("src/**/*.rs") && !is_glob("src/main.rs"));
+        let glob = glob_pathspec("./src/**/*.rs").unwrap();
+        assert!(glob.is_match("src/main.rs"));
+        assert!(glob.is_match("src/repo/log.rs"));
+        assert!(!glob.is_match("src/main.rsx"));
+        assert!(!glob.is_match("tests/cli.rs"));
+    }
+}
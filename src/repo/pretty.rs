@@ -0,0 +1,224 @@
+use super::events::snapshot_refs;
+use super::log::DATE_FORMAT;
+use super::tag::TAGS_DIR;
+use super::{HEAD_FILE, HEADS_DIR, REFS_DIR, REMOTES_DIR, Repository};
+use crate::EncodedSha;
+use crate::object::{Author, Commit};
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// How `log` and `show` print a commit
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum PrettyFormat {
+    /// The SHA1, the author, the date and the indented message
+    #[default]
+    Medium,
+    /// The abbreviated SHA1, the refs pointing at the commit and the
+    /// subject, on one line
+    Oneline,
+    /// A line of text with placeholders, see [`Repository::format_commit`]
+    Format(String),
+}
+
+impl FromStr for PrettyFormat {
+    type Err = String;
+
+    /// Parses `medium`, `oneline`, `format:<fmt>` or `tformat:<fmt>`, or
+    /// a `<fmt>` with placeholders, as `--format` takes them
+    fn from_str(format: &str) -> Result<Self, String> {
+        match format {
+            "medium" => Ok(PrettyFormat::Medium),
+            "oneline" => Ok(PrettyFormat::Oneline),
+            format => match format
+                .strip_prefix("format:")
+                .or_else(|| format.strip_prefix("tformat:"))
+            {
+                Some(format) => Ok(PrettyFormat::Format(format.to_string())),
+                None if format.contains('%') => Ok(PrettyFormat::Format(format.to_string())),
+                None => Err(format!("invalid --pretty format: {format}")),
+            },
+        }
+    }
+}
+
+impl Repository {
+    /// Renders a commit by the placeholders of `format`, like
+    /// `git log --format`:
+    /// - `%H`, `%h`: the SHA1 and its first 7 digits; `%T`, `%t` the same
+    ///   for the tree and `%P`, `%p` for the parents
+    /// - `%an`, `%ae`, `%ad`, `%at`: the author's name, email, date and
+    ///   UNIX timestamp; `%cn`, `%ce`, `%cd`, `%ct` the same for the
+    ///   committer
+    /// - `%s`: the subject; `%b`: the body; `%B`: the whole message
+    /// - `%d`: the refs pointing at the commit, like ` (HEAD -> master)`;
+    ///   `%D` the same without the parentheses
+    /// - `%n`: a newline; `%%`: a `%`
+    ///
+    /// Other placeholders are kept as they are. `decorations` are the refs
+    /// by commit, as [`Self::decorations`] finds them.
+    pub fn format_commit(
+        &self,
+        sha: &EncodedSha,
+        commit: &Commit,
+        format: &str,
+        decorations: &HashMap<EncodedSha, Vec<String>>,
+    ) -> String {
+        let tree = commit.get_tree_sha();
+        let parents = commit.get_parents();
+        let join_parents = |len: usize| {
+            let parents: Vec<&str> = parents.iter().map(|parent| &parent.0[..len]).collect();
+            parents.join(" ")
+        };
+        let message = commit.get_message();
+        let (subject, body) = message.split_once("\n\n").unwrap_or((message, ""));
+        let refs = decorations.get(sha).map(|refs| refs.join(", "));
+        let person = |author: &Author, field: char| match field {
+            'n' => Some(author.get_name().to_string()),
+            'e' => Some(author.get_email().to_string()),
+            'd' => Some(author.get_timestamp().format(DATE_FORMAT).to_string()),
+            't' => Some(author.get_timestamp().timestamp().to_string()),
+            _ => None,
+        };
+
+        let mut out = String::new();
+        let mut rest = format;
+        while let Some(start) = rest.find('%') {
+            out.push_str(&rest[..start]);
+            rest = &rest[start + 1..];
+            let mut chars = rest.chars();
+            let (expansion, len) = match (chars.next(), chars.next()) {
+                (Some('a'), Some(field)) => (person(commit.get_author(), field), 2),
+                (Some('c'), Some(field)) => (person(commit.get_committer(), field), 2),
+                (Some('H'), _) => (Some(sha.0.clone()), 1),
+                (Some('h'), _) => (Some(sha.0[..7].to_string()), 1),
+                (Some('T'), _) => (Some(tree.0.clone()), 1),
+                (Some('t'), _) => (Some(tree.0[..7].to_string()), 1),
+                (Some('P'), _) => (Some(join_parents(40)), 1),
+                (Some('p'), _) => (Some(join_parents(7)), 1),
+                (Some('s'), _) => (Some(subject.lines().collect::<Vec<_>>().join(" ")), 1),
+                (Some('b'), _) if body.is_empty() => (Some(String::new()), 1),
+                (Some('b'), _) => (Some(format!("{body}\n")), 1),
+                (Some('B'), _) => (Some(format!("{message}\n")), 1),
+                (Some('d'), _) => {
+                    let refs = refs.as_ref().map(|refs| format!(" ({refs})"));
+                    (Some(refs.unwrap_or_default()), 1)
+                }
+                (Some('D'), _) => (Some(refs.clone().unwrap_or_default()), 1),
+                (Some('n'), _) => (Some("\n".to_string()), 1),
+                (Some('%'), _) => (Some("%".to_string()), 1),
+                _ => (None, 0),
+            };
+            match expansion {
+                Some(expansion) => {
+                    out.push_str(&expansion);
+                    rest = &rest[len..];
+                }
+                None => out.push('%'),
+            }
+        }
+        out.push_str(rest);
+        out
+    }
+
+    /// The refs pointing at each commit, the way `%d` lists them: `HEAD`,
+    /// or `HEAD -> <branch>` for the branch checked out, then tags as
+    /// `tag: <name>`, branches and remote-tracking branches
+    pub fn decorations(&self) -> HashMap<EncodedSha, Vec<String>> {
+        let head_ref = self.head_ref_name();
+        let mut decorations: HashMap<EncodedSha, Vec<(u8, String)>> = HashMap::new();
+        for (name, sha) in snapshot_refs(&self.git_dir) {
+            let Some(sha) = sha else {
+                continue;
+            };
+            let (rank, label) = if name == HEAD_FILE {
+                let label = match head_ref
+                    .as_deref()
+                    .and_then(|head| head.strip_prefix(&format!("{REFS_DIR}/{HEADS_DIR}/")))
+                {
+                    Some(branch) => format!("{HEAD_FILE} -> {branch}"),
+                    None => HEAD_FILE.to_string(),
+                };
+                (0, label)
+            } else if head_ref.as_deref() == Some(name.as_str()) {
+                // Shown with HEAD already
+                continue;
+            } else if let Some(tag) = name.strip_prefix(&format!("{REFS_DIR}/{TAGS_DIR}/")) {
+                (1, format!("tag: {tag}"))
+            } else if let Some(branch) = name.strip_prefix(&format!("{REFS_DIR}/{HEADS_DIR}/")) {
+                (2, branch.to_string())
+            } else if let Some(branch) = name.strip_prefix(&format!("{REFS_DIR}/{REMOTES_DIR}/")) {
+                (3, branch.to_string())
+            } else {
+                (4, name)
+            };
+            let (sha, _) = self.peel_tag(&sha);
+            decorations.entry(sha).or_default().push((rank, label));
+        }
+        decorations
+            .into_iter()
+            .map(|(sha, mut refs)| {
+                refs.sort();
+                (sha, refs.into_iter().map(|(_, label)| label).collect())
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::init_test_repo;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn placeholders_expand_to_commit_fields() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        let file = repo.dir.join("a.txt");
+        fs::write(&file, "a").unwrap();
+        repo.add(&vec![file.to_str().unwrap()]).unwrap();
+        let first = repo.commit("first").unwrap();
+        repo.tag_create("v1", None, Some("Release")).unwrap();
+        repo.branch("topic").unwrap();
+        fs::write(&file, "b").unwrap();
+        repo.add(&vec![file.to_str().unwrap()]).unwrap();
+        let second = repo.commit("Second\nsubject\n\nBody").unwrap();
+
+        let decorations = repo.decorations();
+        let format = |sha: &EncodedSha, format: &str| {
+            let commit = repo.read_commit(sha).unwrap();
+            repo.format_commit(sha, &commit, format, &decorations)
+        };
+        assert_eq!(
+            format(&second, "%h %p %an <%ae>%d"),
+            format!(
+                "{} {} Alice <alice@wonderland.edu> (HEAD -> master)",
+                &second.0[..7],
+                &first.0[..7]
+            )
+        );
+        assert_eq!(format(&second, "%s|%b|%%|%x"), "Second subject|Body\n|%|%x");
+        assert_eq!(format(&first, "%H%n%D"), format!("{first}\ntag: v1, topic"));
+        assert_eq!(format(&first, "%d"), " (tag: v1, topic)");
+        let commit = repo.read_commit(&first).unwrap();
+        assert_eq!(
+            repo.format_commit(&first, &commit, "%d%D", &HashMap::new()),
+            ""
+        );
+    }
+
+    #[test]
+    fn formats_parse_like_git() {
+        assert_eq!("oneline".parse(), Ok(PrettyFormat::Oneline));
+        assert_eq!(
+            "format:%h".parse(),
+            Ok(PrettyFormat::Format("%h".to_string()))
+        );
+        assert_eq!(
+            "%H %s".parse(),
+            Ok(PrettyFormat::Format("%H %s".to_string()))
+        );
+        assert!("fancy".parse::<PrettyFormat>().is_err());
+    }
+}
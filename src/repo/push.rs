@@ -0,0 +1,413 @@
+use super::events::branch_ref_name;
+use super::{Branch, HEADS_DIR, REFS_DIR, Repository};
+use crate::smart_http::{self, RefUpdate};
+use crate::{EncodedSha, Error};
+use std::str::FromStr;
+
+/// How the update of one remote branch turned out
+enum PushStatus {
+    New,
+    FastForward(EncodedSha),
+    Forced(EncodedSha),
+    UpToDate,
+    Rejected(String),
+}
+
+impl PushStatus {
+    /// Whether the remote branch is to be moved
+    fn moves_branch(&self) -> bool {
+        matches!(
+            self,
+            PushStatus::New | PushStatus::FastForward(_) | PushStatus::Forced(_)
+        )
+    }
+}
+
+impl Repository {
+    /// Sends local branches to `remote` together with the objects it is
+    /// missing, like `git push`, and prints one line per branch. The remote
+    /// is either a local repository or an `http://` URL served by Git's
+    /// smart HTTP protocol.
+    ///
+    /// Each refspec is `[+]<src>[:<dst>]`, naming a local branch and the
+    /// remote branch to update, which defaults to the same name. Without
+    /// refspecs the current branch is pushed. An update that is not a
+    /// fast-forward is rejected unless forced with `+`, and so is one of the
+    /// branch checked out in the remote's working tree, or any the remote
+    /// refuses.
+    ///
+    /// # Returns
+    /// - `Ok(bool)`: Whether every branch was updated or already up to date
+    /// - `Err(Error)`: The remote or a local branch cannot be read
+    pub fn push<S: AsRef<str>>(&self, remote: &str, refspecs: &[S]) -> Result<bool, Error> {
+        let url = self.remote_url(remote)?;
+        let heads_prefix = format!("{REFS_DIR}/{HEADS_DIR}/");
+        let mut updates = Vec::new();
+        if refspecs.is_empty() {
+            let branch = self
+                .head_ref_name()
+                .and_then(|name| name.strip_prefix(&heads_prefix).map(str::to_string))
+                .ok_or("HEAD is detached; name the branch to push")?;
+            updates.push((false, branch.clone(), branch));
+        }
+        for spec in refspecs {
+            let spec = spec.as_ref();
+            let (force, spec) = match spec.strip_prefix('+') {
+                Some(spec) => (true, spec),
+                None => (false, spec),
+            };
+            let (src, dst) = spec.split_once(':').unwrap_or((spec, spec));
+            let src = src.strip_prefix(&heads_prefix).unwrap_or(src);
+            let dst = dst.strip_prefix(&heads_prefix).unwrap_or(dst);
+            if src.is_empty() || dst.is_empty() {
+                return Err(format!("invalid refspec '{spec}'").into());
+            }
+            updates.push((force, src.to_string(), dst.to_string()));
+        }
+
+        let updates = updates
+            .into_iter()
+            .map(|(force, src, dst)| {
+                let new = self
+                    .load_branch(&src)
+                    .and_then(|branch| branch.commit_sha)
+                    .ok_or_else(|| format!("src refspec {src} does not match any"))?;
+                Ok((force, src, dst, new))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        let statuses = match url.contains("://") {
+            true => self.push_http(&url, &updates)?,
+            false => {
+                let (target, _) = self.open_remote(remote)?;
+                updates
+                    .iter()
+                    .map(|(force, _, dst, new)| self.push_branch(&target, new, dst, *force))
+                    .collect::<Result<Vec<_>, Error>>()?
+            }
+        };
+
+        println!("To {url}");
+        let mut all_pushed = true;
+        for ((_, src, dst, new), status) in updates.into_iter().zip(statuses) {
+            let short = |sha: &EncodedSha| sha.0[..7].to_string();
+            let (flag, summary, note) = match &status {
+                PushStatus::New => ('*', "[new branch]".to_string(), String::new()),
+                PushStatus::FastForward(old) => (
+                    ' ',
+                    format!("{}..{}", short(old), short(&new)),
+                    String::new(),
+                ),
+                PushStatus::Forced(old) => (
+                    '+',
+                    format!("{}...{}", short(old), short(&new)),
+                    " (forced update)".to_string(),
+                ),
+                PushStatus::UpToDate => ('=', "[up to date]".to_string(), String::new()),
+                PushStatus::Rejected(why) => ('!', "[rejected]".to_string(), format!(" ({why})")),
+            };
+            println!(" {flag} {summary:<17} {src} -> {dst}{note}");
+            match status {
+                PushStatus::Rejected(_) => all_pushed = false,
+                _ => {
+                    let branch = Branch {
+                        name: dst,
+                        commit_sha: Some(new),
+                    };
+                    self.save_remote_ref(remote, &branch, "update by push")?;
+                }
+            }
+        }
+        Ok(all_pushed)
+    }
+
+    /// Points the branch `dst` of `target` at `new`, copying the objects
+    /// it needs first
+    fn push_branch(
+        &self,
+        target: &Repository,
+        new: &EncodedSha,
+        dst: &str,
+        force: bool,
+    ) -> Result<PushStatus, Error> {
+        let old = target.load_branch(dst).and_then(|branch| branch.commit_sha);
+        let status = self.push_status(old.clone(), new, force);
+        if !status.moves_branch() {
+            return Ok(status);
+        }
+        // Moving the branch would leave the remote's index and working
+        // tree behind
+        if target.head_ref_name() == Some(format!("{REFS_DIR}/{HEADS_DIR}/{dst}")) {
+            return Ok(PushStatus::Rejected(
+                "branch is currently checked out".to_string(),
+            ));
+        }
+        Self::copy_missing_objects(self, target, [new.clone()])?;
+        // Another push may have moved the branch meanwhile
+        target.write_ref(&branch_ref_name(dst), Some(new), Some(old.as_ref()), "push")?;
+        Ok(status)
+    }
+
+    /// Pushes `updates` to the smart HTTP remote at `url`: sends the
+    /// updates it would take together with a pack of the objects it lacks
+    /// to its `git-receive-pack`
+    fn push_http(
+        &self,
+        url: &str,
+        updates: &[(bool, String, String, EncodedSha)],
+    ) -> Result<Vec<PushStatus>, Error> {
+        let remote_refs = smart_http::receive_pack_refs(url)?;
+        let remote_ref = |name: &str| {
+            remote_refs
+                .iter()
+                .find(|(remote_name, _)| remote_name == name)
+                .and_then(|(_, sha)| EncodedSha::from_str(sha).ok())
+        };
+        let mut statuses = Vec::new();
+        let mut commands = Vec::new();
+        for (force, _, dst, new) in updates {
+            let name = format!("{REFS_DIR}/{HEADS_DIR}/{dst}");
+            let old = remote_ref(&name);
+            let status = self.push_status(old.clone(), new, *force);
+            if status.moves_branch() {
+                commands.push(RefUpdate {
+                    name,
+                    old: old.map(|sha| sha.0),
+                    new: new.0.clone(),
+                });
+            }
+            statuses.push(status);
+        }
+        if commands.is_empty() {
+            return Ok(statuses);
+        }
+
+        // The remote has everything its refs reach that is here too
+        let has = remote_refs
+            .iter()
+            .filter_map(|(_, sha)| EncodedSha::from_str(sha).ok())
+            .filter(|sha| self.read_commit(sha).is_ok());
+        let tips = commands
+            .iter()
+            .map(|command| EncodedSha(command.new.clone()));
+        let objects = self.pack_objects(tips, has)?;
+        let pack = crate::git_source::write_pack(&objects);
+        let results = smart_http::receive_pack(url, &commands, &pack)?;
+
+        for (status, (_, _, dst, _)) in statuses.iter_mut().zip(updates) {
+            let name = format!("{REFS_DIR}/{HEADS_DIR}/{dst}");
+            let result = results.iter().find(|(result_name, _)| *result_name == name);
+            match result {
+                Some((_, Some(why))) => *status = PushStatus::Rejected(why.clone()),
+                Some((_, None)) => {}
+                None if !status.moves_branch() => {}
+                None => *status = PushStatus::Rejected("no status from the remote".to_string()),
+            }
+        }
+        Ok(statuses)
+    }
+
+    /// How moving a remote branch from `old` to `new` turns out, before the
+    /// remote has its say
+    fn push_status(&self, old: Option<EncodedSha>, new: &EncodedSha, force: bool) -> PushStatus {
+        match old {
+            None => PushStatus::New,
+            Some(old) if &old == new => PushStatus::UpToDate,
+            // A remote commit this repository lacks has to be fetched first
+            Some(old) if self.read_commit(&old).is_err() && !force => {
+                PushStatus::Rejected("fetch first".to_string())
+            }
+            Some(old) if self.is_ancestor(&old, new).unwrap_or(false) => {
+                PushStatus::FastForward(old)
+            }
+            Some(_) if !force => PushStatus::Rejected("non-fast-forward".to_string()),
+            Some(old) => PushStatus::Forced(old),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git_source;
+    use crate::repo::ResetMode;
+    use crate::repo::{commit_file, init_test_repo};
+    use crate::smart_http::{FLUSH_PKT, pkt_line};
+    use std::fs;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::path::PathBuf;
+    use std::thread::{self, JoinHandle};
+    use tempfile::TempDir;
+
+    /// Serves the repository in `dir` for one push like `git http-backend`
+    /// does: answers the ref advertisement, then the push itself, refusing
+    /// to move the checked out branch
+    fn serve_receive_pack(dir: PathBuf, listener: TcpListener) -> JoinHandle<()> {
+        thread::spawn(move || {
+            let origin = Repository::open(&dir).unwrap();
+            for _ in 0..2 {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = Vec::new();
+                let mut buf = [0; 4096];
+                let head_end = loop {
+                    let n = stream.read(&mut buf).unwrap();
+                    request.extend_from_slice(&buf[..n]);
+                    if let Some(end) = memchr::memmem::find(&request, b"\r\n\r\n") {
+                        break end + 4;
+                    }
+                };
+                let head = String::from_utf8_lossy(&request[..head_end]).to_string();
+                let length = head
+                    .lines()
+                    .find_map(|line| line.strip_prefix("Content-Length: "))
+                    .map_or(0, |len| len.parse().unwrap());
+                while request.len() < head_end + length {
+                    let n = stream.read(&mut buf).unwrap();
+                    request.extend_from_slice(&buf[..n]);
+                }
+                let body = &request[head_end..];
+
+                let response = if head.starts_with("GET /repo/info/refs?service=git-receive-pack ")
+                {
+                    let mut refs = pkt_line(b"# service=git-receive-pack\n");
+                    refs.extend(FLUSH_PKT);
+                    for (i, branch) in origin.list_branches().iter().enumerate() {
+                        let sha = branch.commit_sha.as_ref().unwrap();
+                        let caps = if i == 0 { "\0report-status" } else { "" };
+                        let line = format!("{sha} refs/heads/{}{caps}\n", branch.name);
+                        refs.extend(pkt_line(line.as_bytes()));
+                    }
+                    refs.extend(FLUSH_PKT);
+                    let mut response = format!(
+                        "HTTP/1.1 200 OK\r\n\
+                         Content-Type: application/x-git-receive-pack-advertisement\r\n\
+                         Content-Length: {}\r\n\r\n",
+                        refs.len()
+                    )
+                    .into_bytes();
+                    response.extend(refs);
+                    response
+                } else {
+                    assert!(head.starts_with("POST /repo/git-receive-pack "));
+                    let mut commands = Vec::new();
+                    let mut rest = body;
+                    while &rest[..4] != FLUSH_PKT {
+                        let len =
+                            usize::from_str_radix(std::str::from_utf8(&rest[..4]).unwrap(), 16)
+                                .unwrap();
+                        let line = String::from_utf8_lossy(&rest[4..len]).to_string();
+                        let line = line.split('\0').next().unwrap().trim_end().to_string();
+                        commands.push(line);
+                        rest = &rest[len..];
+                    }
+                    for (sha, kind, content) in
+                        git_source::parse_pack(&rest[4..], |_| None).unwrap()
+                    {
+                        let mut data = format!("{} {}\0", kind.name(), content.len()).into_bytes();
+                        data.extend(content);
+                        assert_eq!(origin.obj_db.store_serialized(data).unwrap().0, sha);
+                    }
+                    let mut report = pkt_line(b"unpack ok\n");
+                    for command in commands {
+                        let mut parts = command.split(' ');
+                        let (_, new, name) = (
+                            parts.next().unwrap(),
+                            parts.next().unwrap(),
+                            parts.next().unwrap(),
+                        );
+                        let status = if origin.head_ref_name().as_deref() == Some(name) {
+                            format!("ng {name} branch is currently checked out\n")
+                        } else {
+                            let branch = Branch {
+                                name: name.strip_prefix("refs/heads/").unwrap().to_string(),
+                                commit_sha: Some(EncodedSha::from_str(new).unwrap()),
+                            };
+                            origin.save_branch(&branch, "push").unwrap();
+                            format!("ok {name}\n")
+                        };
+                        report.extend(pkt_line(status.as_bytes()));
+                    }
+                    report.extend(FLUSH_PKT);
+                    // Sent in chunks, as http-backend does
+                    let mut response = format!(
+                        "HTTP/1.1 200 OK\r\n\
+                         Content-Type: application/x-git-receive-pack-result\r\n\
+                         Transfer-Encoding: chunked\r\n\r\n{:x}\r\n",
+                        report.len()
+                    )
+                    .into_bytes();
+                    response.extend(report);
+                    response.extend(b"\r\n0\r\n\r\n");
+                    response
+                };
+                stream.write_all(&response).unwrap();
+            }
+        })
+    }
+
+    #[test]
+    fn push_updates_remote_branches() {
+        let origin_dir = TempDir::new().unwrap();
+        let origin = init_test_repo(origin_dir.path());
+        let file = origin.dir.join("a.txt");
+        fs::write(&file, "a").unwrap();
+        origin.add(&vec![file.to_str().unwrap()]).unwrap();
+        let first = origin.commit("first").unwrap();
+
+        let clone_dir = TempDir::new().unwrap();
+        let repo = Repository::clone(origin_dir.path(), clone_dir.path()).unwrap();
+        repo.set_config("user.name", "Bob").unwrap();
+        repo.set_config("user.email", "bob@example.com").unwrap();
+        repo.branch("topic").unwrap();
+        repo.checkout("topic", false).unwrap();
+        let file = repo.dir.join("b.txt");
+        fs::write(&file, "b").unwrap();
+        repo.add(&vec![file.to_str().unwrap()]).unwrap();
+        let second = repo.commit("second").unwrap();
+
+        assert!(repo.push("origin", &["topic"]).unwrap());
+        assert_eq!(origin.resolve_commit("topic"), Ok(second.clone()));
+        assert!(origin.read_commit(&second).is_ok());
+        assert_eq!(repo.resolve_commit("origin/topic"), Ok(second.clone()));
+        // The checked out branch of the remote is left alone
+        assert!(!repo.push("origin", &["topic:master"]).unwrap());
+        assert_eq!(origin.get_current_commit(), Some(first));
+
+        repo.reset(Some("master"), ResetMode::Hard).unwrap();
+        assert!(!repo.push::<&str>("origin", &[]).unwrap());
+        assert_eq!(origin.resolve_commit("topic"), Ok(second));
+        assert!(repo.push("origin", &["+topic"]).unwrap());
+        assert_eq!(
+            origin.resolve_commit("topic"),
+            repo.resolve_commit("master")
+        );
+    }
+
+    #[test]
+    fn push_over_smart_http() {
+        let origin_dir = TempDir::new().unwrap();
+        let origin = init_test_repo(origin_dir.path());
+        let first = commit_file(&origin, "a.txt", "a");
+        let clone_dir = TempDir::new().unwrap();
+        let repo = Repository::clone(origin_dir.path(), clone_dir.path()).unwrap();
+        repo.set_config("user.name", "Bob").unwrap();
+        repo.set_config("user.email", "bob@example.com").unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/repo", listener.local_addr().unwrap());
+        repo.set_config("remote.origin.url", &url).unwrap();
+        let server = serve_receive_pack(origin_dir.path().to_path_buf(), listener);
+
+        repo.branch("topic").unwrap();
+        repo.checkout("topic", false).unwrap();
+        let second = commit_file(&repo, "b.txt", "b");
+        // The remote takes the new branch but not its checked out one
+        assert!(!repo.push("origin", &["topic", "topic:master"]).unwrap());
+        server.join().unwrap();
+        assert_eq!(origin.resolve_commit("topic"), Ok(second.clone()));
+        assert!(origin.fsck_report().unwrap().is_ok());
+        assert_eq!(origin.get_current_commit(), Some(first.clone()));
+        assert_eq!(repo.resolve_commit("origin/topic"), Ok(second));
+        assert_eq!(repo.resolve_commit("origin/master"), Ok(first));
+    }
+}
@@ -0,0 +1,251 @@
+use super::Repository;
+use crate::git_source::GitKind;
+use crate::object::{ObjectType, Tree, determine_object_type};
+use crate::{EncodedSha, Error};
+use memchr::memchr;
+use std::collections::HashSet;
+
+impl Repository {
+    /// Checks whether `ancestor` can be reached from `descendant` by following
+    /// parent links. A commit counts as its own ancestor.
+    ///
+    /// # Returns
+    /// - `Ok(bool)`: Whether `ancestor` is an ancestor of `descendant`
+    /// - `Err(Error)`: Either commit cannot be read from the object database
+    pub fn is_ancestor(
+        &self,
+        ancestor: &EncodedSha,
+        descendant: &EncodedSha,
+    ) -> Result<bool, Error> {
+        self.read_commit(ancestor)?;
+        self.read_commit(descendant)?;
+        Ok(self
+            .reachable_from([descendant.clone()])
+            .any(|sha| &sha == ancestor))
+    }
+
+    /// Lazily enumerates every commit reachable from the given tips,
+    /// each commit exactly once. Tips themselves are included.
+    ///
+    /// Commits that cannot be read are still yielded, but their history
+    /// is not followed any further.
+    pub fn reachable_from<I>(&self, tips: I) -> impl Iterator<Item = EncodedSha> + '_
+    where
+        I: IntoIterator<Item = EncodedSha>,
+    {
+        ReachableCommits {
+            repo: self,
+            pending: tips.into_iter().collect(),
+            seen: HashSet::new(),
+        }
+    }
+
+    /// Enumerates every object reachable from the given tips in the spirit of
+    /// `rev-list --objects`: each commit is followed by the trees and blobs of
+    /// its snapshot that have not been emitted before.
+    pub fn reachable_objects<I>(
+        &self,
+        tips: I,
+    ) -> impl Iterator<Item = (EncodedSha, ObjectType)> + '_
+    where
+        I: IntoIterator<Item = EncodedSha>,
+    {
+        ReachableObjects {
+            repo: self,
+            commits: ReachableCommits {
+                repo: self,
+                pending: tips.into_iter().collect(),
+                seen: HashSet::new(),
+            },
+            pending: Vec::new(),
+            seen: HashSet::new(),
+        }
+    }
+
+    /// Collects the objects reachable from `tips` but not from `excluded`,
+    /// in the form [`crate::git_source::write_pack`] takes
+    pub(super) fn pack_objects<I, J>(
+        &self,
+        tips: I,
+        excluded: J,
+    ) -> Result<Vec<(GitKind, Vec<u8>)>, Error>
+    where
+        I: IntoIterator<Item = EncodedSha>,
+        J: IntoIterator<Item = EncodedSha>,
+    {
+        let excluded: HashSet<EncodedSha> = self
+            .reachable_objects(excluded)
+            .map(|(sha, _)| sha)
+            .collect();
+        let mut objects = Vec::new();
+        for (sha, _) in self.reachable_objects(tips) {
+            if excluded.contains(&sha) {
+                continue;
+            }
+            let data = self.obj_db.retrieve(&sha)?;
+            let kind = match determine_object_type(&data)? {
+                ObjectType::Blob => GitKind::Blob,
+                ObjectType::Tree => GitKind::Tree,
+                ObjectType::Commit => GitKind::Commit,
+                ObjectType::Tag => GitKind::Tag,
+            };
+            let nul = memchr(0, &data).ok_or_else(|| format!("corrupt object {sha}"))?;
+            objects.push((kind, data[nul + 1..].to_vec()));
+        }
+        Ok(objects)
+    }
+}
+
+/// Depth-first walk over the commit graph
+struct ReachableCommits<'r> {
+    repo: &'r Repository,
+    pending: Vec<EncodedSha>,
+    seen: HashSet<EncodedSha>,
+}
+
+impl Iterator for ReachableCommits<'_> {
+    type Item = EncodedSha;
+
+    fn next(&mut self) -> Option<EncodedSha> {
+        while let Some(sha) = self.pending.pop() {
+            if !self.seen.insert(sha.clone()) {
+                continue;
+            }
+            if let Ok(commit) = self.repo.read_commit(&sha) {
+                // Push in reverse so the first parent is visited first
+                for parent in commit.get_parents().iter().rev() {
+                    if !self.seen.contains(parent) {
+                        self.pending.push(parent.clone());
+                    }
+                }
+            }
+            return Some(sha);
+        }
+        None
+    }
+}
+
+/// Walks commits and expands each snapshot into its trees and blobs
+struct ReachableObjects<'r> {
+    repo: &'r Repository,
+    commits: ReachableCommits<'r>,
+    /// Trees and blobs discovered but not yet emitted
+    pending: Vec<(EncodedSha, ObjectType)>,
+    seen: HashSet<EncodedSha>,
+}
+
+impl Iterator for ReachableObjects<'_> {
+    type Item = (EncodedSha, ObjectType);
+
+    fn next(&mut self) -> Option<(EncodedSha, ObjectType)> {
+        if let Some((sha, object_type)) = self.pending.pop() {
+            if object_type == ObjectType::Tree
+                && let Ok(data) = self.repo.obj_db.retrieve(&sha)
+                && let Ok(tree) = Tree::deserialize(&data)
+            {
+                // Submodule commits belong to other repositories
+                for (_, entry) in tree.get_entries().rev() {
+                    if entry.object_type != ObjectType::Commit
+                        && self.seen.insert(entry.sha1.clone())
+                    {
+                        self.pending.push((entry.sha1.clone(), entry.object_type));
+                    }
+                }
+            }
+            return Some((sha, object_type));
+        }
+
+        let commit_sha = self.commits.next()?;
+        if let Ok(commit) = self.repo.read_commit(&commit_sha) {
+            let tree_sha = commit.get_tree_sha();
+            if self.seen.insert(tree_sha.clone()) {
+                self.pending.push((tree_sha, ObjectType::Tree));
+            }
+        }
+        Some((commit_sha, ObjectType::Commit))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::init_test_repo;
+    use std::fs;
+    use tempfile::TempDir;
+
+    /// Builds `root <- left`, `root <- right` and returns (repo, root, left, right)
+    fn forked_history(temp_dir: &TempDir) -> (Repository, EncodedSha, EncodedSha, EncodedSha) {
+        let repo = init_test_repo(temp_dir.path());
+        let file = repo.dir.join("a.txt");
+        fs::write(&file, "base").unwrap();
+        repo.update_index(&file).unwrap();
+        let tree = repo.write_tree().unwrap();
+        let root = repo
+            .commit_tree(tree, vec![], "root", "Alice", "alice@example.com")
+            .unwrap();
+
+        fs::write(&file, "left").unwrap();
+        repo.update_index(&file).unwrap();
+        let tree = repo.write_tree().unwrap();
+        let left = repo
+            .commit_tree(
+                tree,
+                vec![root.clone()],
+                "left",
+                "Alice",
+                "alice@example.com",
+            )
+            .unwrap();
+
+        fs::write(&file, "right").unwrap();
+        repo.update_index(&file).unwrap();
+        let tree = repo.write_tree().unwrap();
+        let right = repo
+            .commit_tree(
+                tree,
+                vec![root.clone()],
+                "right",
+                "Alice",
+                "alice@example.com",
+            )
+            .unwrap();
+        (repo, root, left, right)
+    }
+
+    #[test]
+    fn ancestry_queries() {
+        let temp_dir = TempDir::new().unwrap();
+        let (repo, root, left, right) = forked_history(&temp_dir);
+
+        assert!(repo.is_ancestor(&root, &left).unwrap());
+        assert!(repo.is_ancestor(&left, &left).unwrap());
+        assert!(!repo.is_ancestor(&left, &root).unwrap());
+        assert!(!repo.is_ancestor(&left, &right).unwrap());
+        assert!(
+            repo.is_ancestor(&EncodedSha("0".repeat(40)), &left)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn reachable_commits_are_unique() {
+        let temp_dir = TempDir::new().unwrap();
+        let (repo, root, left, right) = forked_history(&temp_dir);
+
+        let commits: Vec<_> = repo.reachable_from([left.clone(), right.clone()]).collect();
+        assert_eq!(commits.len(), 3);
+        assert!(commits.contains(&root));
+    }
+
+    #[test]
+    fn reachable_objects_include_trees_and_blobs() {
+        let temp_dir = TempDir::new().unwrap();
+        let (repo, _, left, right) = forked_history(&temp_dir);
+
+        let objects: Vec<_> = repo.reachable_objects([left, right]).collect();
+        let count = |wanted: ObjectType| objects.iter().filter(|(_, t)| *t == wanted).count();
+        assert_eq!(count(ObjectType::Commit), 3);
+        assert_eq!(count(ObjectType::Tree), 3);
+        assert_eq!(count(ObjectType::Blob), 3);
+    }
+}
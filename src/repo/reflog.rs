@@ -0,0 +1,303 @@
+use chrono::Local;
+
+use super::{HEAD_FILE, HEADS_DIR, REFS_DIR, REMOTES_DIR, Repository};
+use crate::{EncodedSha, Error};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// Directory in the git dir holding one reflog per ref, e.g. `logs/HEAD`
+pub const LOGS_DIR: &str = "logs";
+
+/// Names the ref whose reflog was written last. File modification times
+/// are too coarse to tell which of several logs changed last.
+const LAST_REFLOG_FILE: &str = "LAST_REFLOG";
+
+/// Stands for "no commit" on either side of a reflog entry
+const NULL_SHA: &str = "0000000000000000000000000000000000000000";
+
+/// One line of a reflog: a ref moved from `old` to `new`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReflogEntry {
+    /// `None` if the ref did not exist or was unborn
+    pub old: Option<EncodedSha>,
+    /// `None` if the ref was deleted
+    pub new: Option<EncodedSha>,
+    /// `Name <email>` of whoever moved the ref
+    pub identity: String,
+    /// Seconds since the Unix epoch
+    pub timestamp: i64,
+    /// Timezone offset, e.g. `+0800`
+    pub offset: String,
+    /// What moved the ref, e.g. `commit: <message>`
+    pub reason: String,
+}
+
+impl ReflogEntry {
+    fn parse(line: &str) -> Option<ReflogEntry> {
+        let (header, reason) = line.split_once('\t').unwrap_or((line, ""));
+        let sha = |s: &str| {
+            (s != NULL_SHA)
+                .then(|| EncodedSha::from_str(s).ok())
+                .flatten()
+        };
+        let (old, rest) = header.split_once(' ')?;
+        let (new, rest) = rest.split_once(' ')?;
+        let (rest, offset) = rest.rsplit_once(' ')?;
+        let (identity, timestamp) = rest.rsplit_once(' ')?;
+        Some(ReflogEntry {
+            old: sha(old),
+            new: sha(new),
+            identity: identity.to_string(),
+            timestamp: timestamp.parse().ok()?,
+            offset: offset.to_string(),
+            reason: reason.to_string(),
+        })
+    }
+}
+
+impl std::fmt::Display for ReflogEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let sha = |sha: &Option<EncodedSha>| match sha {
+            Some(sha) => sha.0.clone(),
+            None => NULL_SHA.to_string(),
+        };
+        write!(
+            f,
+            "{} {} {} {} {}\t{}",
+            sha(&self.old),
+            sha(&self.new),
+            self.identity,
+            self.timestamp,
+            self.offset,
+            self.reason
+        )
+    }
+}
+
+impl Repository {
+    /// Reads the reflog of `ref_name` (`HEAD` or `refs/heads/<branch>`),
+    /// oldest entry first. A ref without a log has an empty one.
+    pub fn reflog(&self, ref_name: &str) -> Result<Vec<ReflogEntry>, Error> {
+        let content = match fs::read_to_string(self.reflog_path(ref_name)) {
+            Ok(content) => content,
+            Err(why) if why.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(why) => return Err(why.into()),
+        };
+        content
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(|line| {
+                ReflogEntry::parse(line).ok_or_else(|| {
+                    Error::Other(format!("corrupt reflog entry for {ref_name}: {line}"))
+                })
+            })
+            .collect()
+    }
+
+    /// Prints the reflog of `name`, HEAD by default, newest entry first as
+    /// `<sha> <name>@{n}: <reason>`, like `git reflog`
+    pub fn print_reflog(&self, name: Option<&str>) -> Result<(), Error> {
+        let name = name.unwrap_or(HEAD_FILE);
+        let ref_name = self
+            .reflog_ref_name(name)
+            .ok_or_else(|| format!("no reflog for '{name}'"))?;
+        for (n, entry) in self.reflog(&ref_name)?.iter().rev().enumerate() {
+            let sha = entry.new.as_ref().map_or(NULL_SHA, |sha| &sha.0);
+            println!("{} {name}@{{{n}}}: {}", &sha[..7], entry.reason);
+        }
+        Ok(())
+    }
+
+    /// Resolves `<ref>@{n}`, the commit `ref` pointed at `n` moves ago, from
+    /// its reflog. A bare `@{n}` stands for the checked out branch, or
+    /// HEAD when it is detached. Deleted branches keep their reflog, so
+    /// they can be recovered this way.
+    ///
+    /// # Returns
+    /// `None` if `rev` is not of that form
+    pub(super) fn resolve_reflog_selector(&self, rev: &str) -> Option<Result<EncodedSha, String>> {
+        let (name, n) = rev.strip_suffix('}')?.split_once("@{")?;
+        let n: usize = n.parse().ok()?;
+        let ref_name = match name {
+            "" => Some(
+                self.head_ref_name()
+                    .unwrap_or_else(|| HEAD_FILE.to_string()),
+            ),
+            name => self.reflog_ref_name(name),
+        };
+        let Some(ref_name) = ref_name else {
+            return Some(Err(format!("no reflog for '{name}'")));
+        };
+        let entries = match self.reflog(&ref_name) {
+            Ok(entries) => entries,
+            Err(why) => return Some(Err(why.to_string())),
+        };
+        Some(match entries.iter().rev().nth(n) {
+            Some(entry) => entry
+                .new
+                .clone()
+                .ok_or_else(|| format!("'{rev}' is a deletion of the ref")),
+            None => Err(format!(
+                "log for '{ref_name}' only has {} entries",
+                entries.len()
+            )),
+        })
+    }
+
+    /// Full name of the ref whose reflog `name` refers to: HEAD, a full
+    /// `refs/...` name, a branch, a remote-tracking branch or another ref
+    /// under refs, such as `stash`
+    fn reflog_ref_name(&self, name: &str) -> Option<String> {
+        if name == HEAD_FILE || name.starts_with(&format!("{REFS_DIR}/")) {
+            return Some(name.to_string());
+        }
+        [
+            format!("{REFS_DIR}/{HEADS_DIR}/{name}"),
+            format!("{REFS_DIR}/{REMOTES_DIR}/{name}"),
+            format!("{REFS_DIR}/{name}"),
+        ]
+        .into_iter()
+        .find(|ref_name| self.reflog_path(ref_name).is_file())
+    }
+
+    /// Appends an entry to the reflog of `ref_name`
+    pub(super) fn append_reflog(
+        &self,
+        ref_name: &str,
+        old: Option<&EncodedSha>,
+        new: Option<&EncodedSha>,
+        reason: &str,
+    ) -> io::Result<()> {
+        let now = Local::now();
+        // Ref moves that make no commit go through without an identity
+        let identity = self
+            .identity()
+            .map(|(name, email)| format!("{name} <{email}>"))
+            .unwrap_or_else(|_| "unknown <unknown>".to_string());
+        let entry = ReflogEntry {
+            old: old.cloned(),
+            new: new.cloned(),
+            identity,
+            timestamp: now.timestamp(),
+            offset: now.format("%z").to_string(),
+            // Keep each entry on one line
+            reason: reason.lines().next().unwrap_or_default().to_string(),
+        };
+        let path = self.reflog_path(ref_name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        writeln!(file, "{entry}")?;
+        // Only branch and HEAD moves are candidates for undo
+        if ref_name == HEAD_FILE || ref_name.starts_with(&format!("{REFS_DIR}/{HEADS_DIR}/")) {
+            fs::write(self.git_dir.join(LAST_REFLOG_FILE), ref_name)?;
+        }
+        Ok(())
+    }
+
+    /// Replaces the reflog of `ref_name`, e.g. to drop an entry; an empty
+    /// list removes the log
+    pub(super) fn rewrite_reflog(&self, ref_name: &str, entries: &[ReflogEntry]) -> io::Result<()> {
+        let path = self.reflog_path(ref_name);
+        if entries.is_empty() {
+            return match fs::remove_file(path) {
+                Err(why) if why.kind() != io::ErrorKind::NotFound => Err(why),
+                _ => Ok(()),
+            };
+        }
+        let content: String = entries.iter().map(|entry| format!("{entry}\n")).collect();
+        fs::write(path, content)
+    }
+
+    /// Name of the ref whose reflog was appended to last, if any
+    pub(super) fn last_logged_ref(&self) -> Option<String> {
+        fs::read_to_string(self.git_dir.join(LAST_REFLOG_FILE)).ok()
+    }
+
+    pub(super) fn reflog_path(&self, ref_name: &str) -> PathBuf {
+        self.git_dir.join(LOGS_DIR).join(ref_name)
+    }
+
+    /// Name of the branch ref HEAD points at, if it is not detached
+    pub(super) fn head_ref_name(&self) -> Option<String> {
+        match self.get_head()? {
+            super::Head::Symbolic(path) => Some(path.to_string_lossy().replace('\\', "/")),
+            super::Head::Detached(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::init_test_repo;
+    use tempfile::TempDir;
+
+    #[test]
+    fn branch_and_head_moves_are_logged() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        let file = repo.dir.join("a.txt");
+        fs::write(&file, "a").unwrap();
+        repo.add(&vec![file.to_str().unwrap()]).unwrap();
+        repo.commit("first\nwith a body").unwrap();
+        let first = repo.get_current_commit().unwrap();
+        repo.branch("topic").unwrap();
+        repo.checkout("topic", false).unwrap();
+
+        let master = repo.reflog("refs/heads/master").unwrap();
+        assert_eq!(master.len(), 1);
+        assert_eq!(master[0].old, None);
+        assert_eq!(master[0].new.as_ref(), Some(&first));
+        assert_eq!(master[0].reason, "commit (initial): first");
+
+        let head = repo.reflog(HEAD_FILE).unwrap();
+        let reasons: Vec<_> = head.iter().map(|e| e.reason.as_str()).collect();
+        assert_eq!(
+            reasons,
+            vec![
+                "commit (initial): first",
+                "checkout: moving from master to topic"
+            ]
+        );
+        assert_eq!(
+            ReflogEntry::parse(&head[1].to_string()),
+            Some(head[1].clone())
+        );
+        assert!(repo.reflog("refs/heads/none").unwrap().is_empty());
+    }
+
+    #[test]
+    fn reflog_selectors_recover_old_commits() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        let file = repo.dir.join("a.txt");
+        let commit = |content: &str| {
+            fs::write(&file, content).unwrap();
+            repo.add(&vec![file.to_str().unwrap()]).unwrap();
+            repo.commit(content).unwrap()
+        };
+        let first = commit("first");
+        let second = commit("second");
+        repo.reset(Some(&first.0), crate::repo::ResetMode::Hard)
+            .unwrap();
+
+        // A bad reset is recovered from the branch's or HEAD's reflog
+        let resolve = |rev: &str| repo.resolve_commit(rev);
+        assert_eq!(resolve("master@{0}"), Ok(first.clone()));
+        assert_eq!(resolve("master@{1}"), Ok(second.clone()));
+        assert_eq!(resolve("HEAD@{1}"), Ok(second.clone()));
+        assert_eq!(resolve("@{2}"), Ok(first.clone()));
+        assert!(resolve("HEAD@{3}").is_err());
+
+        // So is a deleted branch
+        repo.branch("topic").unwrap();
+        repo.rm_branch("topic").unwrap();
+        assert!(resolve("topic@{0}").is_err());
+        assert_eq!(resolve("topic@{1}"), Ok(first));
+        assert!(resolve("none@{0}").is_err());
+    }
+}
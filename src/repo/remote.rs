@@ -0,0 +1,279 @@
+use super::{Branch, REFS_DIR, REMOTES_DIR, Repository, check_branch_name};
+use crate::config::Config;
+use crate::{EncodedSha, Error};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+impl Repository {
+    /// The configured remotes and their URLs, in config order
+    pub fn remotes(&self) -> Result<Vec<(String, String)>, Error> {
+        let config = self.config()?;
+        Ok(config
+            .subsections("remote")
+            .into_iter()
+            .map(|name| {
+                let url = config
+                    .get(&format!("remote.{name}.url"))
+                    .unwrap_or_default();
+                (name.to_string(), url.to_string())
+            })
+            .collect())
+    }
+
+    /// Records a remote whose branches are tracked under
+    /// `refs/remotes/<name>`
+    pub fn remote_add(&self, name: &str, url: &str) -> Result<(), Error> {
+        check_remote_name(name)?;
+        let path = self.config_path();
+        let mut config = Config::load(&path)?;
+        if config.get(&format!("remote.{name}.url")).is_some() {
+            return Err(format!("remote {name} already exists").into());
+        }
+        config.set(&format!("remote.{name}.url"), url)?;
+        config.set(&format!("remote.{name}.fetch"), fetch_refspec(name))?;
+        Ok(config.save(&path)?)
+    }
+
+    /// Forgets a remote together with its remote-tracking refs and the
+    /// upstream settings of the branches tracking it
+    pub fn remote_remove(&self, name: &str) -> Result<(), Error> {
+        let path = self.config_path();
+        let mut config = Config::load(&path)?;
+        if !config.remove_section(&format!("remote.{name}")) {
+            return Err(format!("No such remote: '{name}'").into());
+        }
+        for branch in tracking_branches(&config, name) {
+            config.remove_section(&format!("branch.{branch}"));
+        }
+        config.save(&path)?;
+        let prefix = format!("{REFS_DIR}/{REMOTES_DIR}/{name}/");
+        self.update_packed_refs(|packed| {
+            (!packed.starts_with(&prefix)).then(|| packed.to_string())
+        })?;
+        for dir in [
+            self.get_remotes_dir(),
+            self.reflog_path(REFS_DIR).join(REMOTES_DIR),
+        ] {
+            match fs::remove_dir_all(dir.join(name)) {
+                Err(why) if why.kind() != io::ErrorKind::NotFound => return Err(why.into()),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Renames a remote, moving its remote-tracking refs along
+    pub fn remote_rename(&self, old: &str, new: &str) -> Result<(), Error> {
+        check_remote_name(new)?;
+        let path = self.config_path();
+        let mut config = Config::load(&path)?;
+        if config.get(&format!("remote.{old}.url")).is_none() {
+            return Err(format!("No such remote: '{old}'").into());
+        }
+        if config.get(&format!("remote.{new}.url")).is_some() {
+            return Err(format!("remote {new} already exists").into());
+        }
+        config.rename_section(&format!("remote.{old}"), &format!("remote.{new}"));
+        if config.get(&format!("remote.{new}.fetch")) == Some(&fetch_refspec(old)) {
+            config.set(&format!("remote.{new}.fetch"), fetch_refspec(new))?;
+        }
+        for branch in tracking_branches(&config, old) {
+            config.set(&format!("branch.{branch}.remote"), new)?;
+        }
+        config.save(&path)?;
+        let old_prefix = format!("{REFS_DIR}/{REMOTES_DIR}/{old}/");
+        let new_prefix = format!("{REFS_DIR}/{REMOTES_DIR}/{new}/");
+        self.update_packed_refs(|packed| match packed.strip_prefix(&old_prefix) {
+            Some(branch) => Some(format!("{new_prefix}{branch}")),
+            None => Some(packed.to_string()),
+        })?;
+        for dir in [
+            self.get_remotes_dir(),
+            self.reflog_path(REFS_DIR).join(REMOTES_DIR),
+        ] {
+            if dir.join(old).exists() {
+                fs::rename(dir.join(old), dir.join(new))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Opens the repository `remote` points at. Only repositories on a
+    /// local path can be reached.
+    ///
+    /// # Returns
+    /// - `Ok((Repository, String))`: The remote repository and its URL
+    /// - `Err(Error)`: The remote is unknown or cannot be opened
+    pub(super) fn open_remote(&self, remote: &str) -> Result<(Repository, String), Error> {
+        let url = self.remote_url(remote)?;
+        if url.contains("://") {
+            return Err(
+                format!("cannot reach {url}: only local repositories are supported").into(),
+            );
+        }
+        Ok((Repository::open(Path::new(&url))?, url))
+    }
+
+    /// The `remote.<remote>.url` setting
+    pub(super) fn remote_url(&self, remote: &str) -> Result<String, Error> {
+        Ok(self
+            .config()?
+            .get(&format!("remote.{remote}.url"))
+            .map(str::to_string)
+            .ok_or_else(|| format!("No such remote: '{remote}'"))?)
+    }
+
+    /// Copies the objects reachable from `tips` that `to` lacks from the
+    /// object store of `from`, returning how many were copied. Object files
+    /// are copied as is, so both stores must use the same encryption key.
+    /// Objects Git has packed are stored as loose objects.
+    ///
+    /// The objects are gathered in a quarantine and only move into the
+    /// store of `to` once all of them are there, so a failed copy leaves
+    /// no partial history behind.
+    pub(super) fn copy_missing_objects<I>(
+        from: &Repository,
+        to: &Repository,
+        tips: I,
+    ) -> Result<usize, Error>
+    where
+        I: IntoIterator<Item = EncodedSha>,
+    {
+        let quarantine = to.obj_db.quarantine()?;
+        let mut copied = 0;
+        for (sha, _) in from.reachable_objects(tips) {
+            if to.obj_db.object_path(&sha).exists() {
+                continue;
+            }
+            let source = from.obj_db.object_path(&sha);
+            if source.exists() {
+                let target = quarantine.db().object_path(&sha);
+                fs::create_dir_all(target.parent().unwrap())?;
+                fs::copy(source, &target)?;
+            } else {
+                // Objects Git has packed are unpacked into loose ones
+                quarantine
+                    .db()
+                    .store_serialized(from.obj_db.retrieve(&sha)?)?;
+            }
+            copied += 1;
+        }
+        quarantine.migrate()?;
+        Ok(copied)
+    }
+
+    /// Makes `branch` track the branch of the same name on `remote`
+    pub(super) fn set_upstream(&self, branch: &str, remote: &str) -> Result<(), Error> {
+        let path = self.config_path();
+        let mut config = Config::load(&path)?;
+        config.set(&format!("branch.{branch}.remote"), remote)?;
+        config.set(
+            &format!("branch.{branch}.merge"),
+            format!("refs/heads/{branch}"),
+        )?;
+        Ok(config.save(&path)?)
+    }
+
+    /// Creates the local branch `name` from `<remote>/<name>` when exactly
+    /// one remote has such a branch, and sets it up to track that branch
+    pub(super) fn branch_from_remote(&self, name: &str) -> Result<Option<Branch>, Error> {
+        if check_branch_name(name).is_err() {
+            return Ok(None);
+        }
+        let config = self.config()?;
+        let mut candidates = config
+            .subsections("remote")
+            .into_iter()
+            .filter_map(|remote| {
+                self.load_remote_ref(&format!("{remote}/{name}"))
+                    .map(|sha| (remote, sha))
+            });
+        let (Some((remote, sha)), None) = (candidates.next(), candidates.next()) else {
+            return Ok(None);
+        };
+        let branch = Branch {
+            name: name.to_string(),
+            commit_sha: Some(sha),
+        };
+        self.save_branch(&branch, &format!("branch: Created from {remote}/{name}"))?;
+        self.set_upstream(name, remote)?;
+        println!("branch '{name}' set up to track '{remote}/{name}'.");
+        Ok(Some(branch))
+    }
+}
+
+/// The refspec mapping the branches of `remote` to its remote-tracking refs
+fn fetch_refspec(remote: &str) -> String {
+    format!("+refs/heads/*:{REFS_DIR}/{REMOTES_DIR}/{remote}/*")
+}
+
+/// Branches whose `branch.<name>.remote` is `remote`
+fn tracking_branches(config: &Config, remote: &str) -> Vec<String> {
+    config
+        .subsections("branch")
+        .into_iter()
+        .filter(|branch| config.get(&format!("branch.{branch}.remote")) == Some(remote))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Remote names become a directory under refs/remotes, so they follow the
+/// branch name rules and cannot contain a slash
+fn check_remote_name(name: &str) -> Result<(), String> {
+    if name.contains('/') || check_branch_name(name).is_err() {
+        return Err(format!("'{name}' is not a valid remote name"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::init_test_repo;
+    use tempfile::TempDir;
+
+    #[test]
+    fn remotes_track_branches() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        let file = repo.dir.join("a.txt");
+        fs::write(&file, "a").unwrap();
+        repo.add(&vec![file.to_str().unwrap()]).unwrap();
+        let first = repo.commit("first").unwrap();
+
+        repo.remote_add("origin", "/srv/a").unwrap();
+        assert!(repo.remote_add("origin", "/srv/b").is_err());
+        assert!(repo.remote_add("a/b", "/srv/b").is_err());
+        let topic = Branch {
+            name: "topic".to_string(),
+            commit_sha: Some(first.clone()),
+        };
+        repo.save_remote_ref("origin", &topic, "fetch").unwrap();
+        assert_eq!(repo.resolve_commit("origin/topic"), Ok(first.clone()));
+
+        repo.checkout("topic", false).unwrap();
+        assert_eq!(repo.head_ref_name().as_deref(), Some("refs/heads/topic"));
+        let config = repo.config().unwrap();
+        assert_eq!(config.get("branch.topic.remote"), Some("origin"));
+
+        repo.remote_rename("origin", "upstream").unwrap();
+        assert_eq!(
+            repo.remotes().unwrap(),
+            [("upstream".to_string(), "/srv/a".to_string())]
+        );
+        assert_eq!(repo.resolve_commit("upstream/topic"), Ok(first));
+        let config = repo.config().unwrap();
+        assert_eq!(config.get("branch.topic.remote"), Some("upstream"));
+        assert_eq!(
+            config.get("remote.upstream.fetch"),
+            Some("+refs/heads/*:refs/remotes/upstream/*")
+        );
+
+        repo.remote_remove("upstream").unwrap();
+        assert!(repo.remotes().unwrap().is_empty());
+        assert!(repo.resolve_commit("upstream/topic").is_err());
+        assert_eq!(repo.config().unwrap().get("branch.topic.remote"), None);
+        assert!(repo.remote_remove("upstream").is_err());
+    }
+}
@@ -0,0 +1,328 @@
+use sha1::{Digest, Sha1};
+use walkdir::WalkDir;
+
+use super::events::branch_ref_name;
+use super::{HEAD_FILE, Head, OBJECTS_DIR, Repository};
+use crate::git_source::PackStore;
+use crate::lock::{LOCK_SUFFIX, LockOwner};
+use crate::object::{Blob, Commit, ObjectType, Tag, Tree, determine_object_type};
+use crate::{EncodedSha, Error};
+use std::fs;
+use std::path::PathBuf;
+
+/// Directory inside the git dir where corrupt objects are moved aside
+const CORRUPT_DIR: &str = "corrupt-objects";
+
+/// Reflog reason of a ref moved back by `repair_objects`
+const REPAIR_REASON: &str = "repair-objects: restored from reflog";
+
+impl Repository {
+    /// Checks every loose object and moves corrupt ones out of the object
+    /// store, writing back the sound copy a pack holds where there is one.
+    /// Refs that point at commits which are still missing are moved back
+    /// to the newest commit of their reflog that can be read.
+    ///
+    /// # Returns
+    /// - `Ok(bool)`: Whether every ref points at a commit again
+    /// - `Err(Error)`: The object store, refs or reflogs cannot be read
+    pub fn repair_objects(&self) -> Result<bool, Error> {
+        let quarantined = self.quarantine_corrupt_objects()?;
+        for (sha, why, restored) in &quarantined {
+            println!("quarantined corrupt object {sha}: {why}");
+            if *restored {
+                println!("restored {sha} from a pack");
+            }
+        }
+        if !quarantined.is_empty() {
+            println!(
+                "moved {} object(s) to {}",
+                quarantined.len(),
+                self.git_dir.join(CORRUPT_DIR).display()
+            );
+        }
+
+        let dangling = self.dangling_refs()?;
+        let mut lost = 0;
+        for (name, ref_name, sha) in &dangling {
+            match self.reflog_fallback(ref_name, sha)? {
+                Some(restored) => {
+                    match ref_name.as_str() {
+                        HEAD_FILE => {
+                            self.save_head(&Head::Detached(restored.clone()), REPAIR_REASON)?
+                        }
+                        _ => self.write_ref(ref_name, Some(&restored), None, REPAIR_REASON)?,
+                    }
+                    println!(
+                        "restored {name} from missing commit {sha} to {restored}, found in its reflog"
+                    );
+                }
+                None => {
+                    println!(
+                        "error: {name} points at missing commit {sha}; no copy available to restore it"
+                    );
+                    lost += 1;
+                }
+            }
+        }
+        if quarantined.is_empty() && dangling.is_empty() {
+            println!("no corrupt objects found");
+        }
+        Ok(lost == 0)
+    }
+
+    /// Moves every loose object that fails verification into
+    /// `<git dir>/corrupt-objects`, returning each with the reason and
+    /// whether a sound copy from a pack took its place
+    fn quarantine_corrupt_objects(&self) -> Result<Vec<(EncodedSha, String, bool)>, String> {
+        let corrupt_dir = self.git_dir.join(CORRUPT_DIR);
+        let packs = PackStore::open(&self.git_dir.join(OBJECTS_DIR))?;
+        let mut quarantined = Vec::new();
+        for sha in self.obj_db.loose_objects().map_err(|why| why.to_string())? {
+            let Err(why) = self.verify_object(&sha) else {
+                continue;
+            };
+            fs::create_dir_all(&corrupt_dir).map_err(|why| why.to_string())?;
+            fs::rename(self.obj_db.object_path(&sha), corrupt_dir.join(&sha.0))
+                .map_err(|why| why.to_string())?;
+            let restored = self.restore_from_packs(&packs, &sha)?;
+            quarantined.push((sha, why, restored));
+        }
+        Ok(quarantined)
+    }
+
+    /// Writes the copy of `sha` a pack holds back as a loose object, if
+    /// there is one and it hashes to its name
+    ///
+    /// # Returns
+    /// Whether the object was restored
+    fn restore_from_packs(&self, packs: &PackStore, sha: &EncodedSha) -> Result<bool, String> {
+        let Some(Ok((kind, content))) = packs.read(&sha.0) else {
+            return Ok(false);
+        };
+        let mut data = format!("{} {}\0", kind.name(), content.len()).into_bytes();
+        data.extend(content);
+        if hex::encode(Sha1::digest(&data)) != sha.0 {
+            return Ok(false);
+        }
+        self.obj_db
+            .store_serialized(data)
+            .map_err(|why| why.to_string())?;
+        Ok(true)
+    }
+
+    /// The newest commit the reflog of `ref_name` names, other than
+    /// `missing`, that can still be read, going back from where the ref
+    /// moved last
+    fn reflog_fallback(
+        &self,
+        ref_name: &str,
+        missing: &EncodedSha,
+    ) -> Result<Option<EncodedSha>, Error> {
+        Ok(self
+            .reflog(ref_name)?
+            .into_iter()
+            .rev()
+            .flat_map(|entry| [entry.new, entry.old])
+            .flatten()
+            .find(|sha| sha != missing && self.read_commit(sha).is_ok()))
+    }
+
+    /// Removes lock files left behind by processes that are no longer
+    /// running. Locks held by live processes, and locks that do not say who
+    /// holds them, are reported and left alone.
+    pub fn repair_stale_locks(&self) -> Result<(), Error> {
+        let locks = self.find_locks()?;
+        let mut removed = 0;
+        for (lock_path, owner) in locks {
+            let name = lock_path.strip_prefix(&self.git_dir).unwrap().display();
+            match owner {
+                Some(owner) if !owner.is_alive() => {
+                    if let Err(why) = fs::remove_file(&lock_path) {
+                        println!("error: cannot remove {name}: {why}");
+                        continue;
+                    }
+                    println!(
+                        "removed stale lock {name} (process {} gone, taken {})",
+                        owner.pid,
+                        owner.taken_at()
+                    );
+                    removed += 1;
+                }
+                Some(owner) => println!(
+                    "kept {name}: held by running process {} since {}",
+                    owner.pid,
+                    owner.taken_at()
+                ),
+                None => println!(
+                    "warning: cannot tell who holds {name}; \
+                     remove it by hand if no other process is running"
+                ),
+            }
+        }
+        if removed == 0 {
+            println!("no stale locks found");
+        }
+        Ok(())
+    }
+
+    /// Deflates the loose objects written before object files were
+    /// compressed, so the object store only holds Git's loose format
+    pub fn repair_compress_objects(&self) -> Result<(), Error> {
+        let converted = self.obj_db.compress_legacy_objects()?;
+        println!("compressed {converted} loose objects");
+        Ok(())
+    }
+
+    /// Lists every lock file in the git dir with its recorded owner
+    fn find_locks(&self) -> Result<Vec<(PathBuf, Option<LockOwner>)>, String> {
+        let mut locks = Vec::new();
+        for entry in WalkDir::new(&self.git_dir).sort_by_file_name() {
+            let entry = entry.map_err(|why| why.to_string())?;
+            let name = entry.file_name().to_string_lossy();
+            if entry.file_type().is_file() && name.ends_with(LOCK_SUFFIX) {
+                locks.push((entry.path().to_path_buf(), LockOwner::read(entry.path())));
+            }
+        }
+        Ok(locks)
+    }
+
+    /// Checks that an object hashes to its name and parses as its type
+    pub(super) fn verify_object(&self, sha: &EncodedSha) -> Result<(), String> {
+        let data = self.obj_db.retrieve(sha).map_err(|why| why.to_string())?;
+        let actual = hex::encode(Sha1::digest(&data));
+        if actual != sha.0 {
+            return Err(format!("hash mismatch, content hashes to {actual}"));
+        }
+        match determine_object_type(&data)? {
+            ObjectType::Blob => Blob::deserialize(&data).map(|_| ()),
+            ObjectType::Tree => Tree::deserialize(&data)
+                .map(|_| ())
+                .map_err(|why| why.to_string()),
+            ObjectType::Commit => Commit::deserialize(&data).map(|_| ()),
+            ObjectType::Tag => Tag::deserialize(&data).map(|_| ()),
+        }
+    }
+
+    /// Lists the branches and detached HEAD whose commit cannot be read, as
+    /// (description, ref name, commit)
+    fn dangling_refs(&self) -> Result<Vec<(String, String, EncodedSha)>, String> {
+        let mut dangling = Vec::new();
+        for branch in self.list_branches() {
+            // Unborn branches hold no commit at all
+            if let Some(sha) = branch.commit_sha
+                && self.read_commit(&sha).is_err()
+            {
+                let name = format!("branch '{}'", branch.name);
+                dangling.push((name, branch_ref_name(&branch.name), sha));
+            }
+        }
+        if let Some(Head::Detached(sha)) = self.get_head()
+            && self.read_commit(&sha).is_err()
+        {
+            dangling.push((HEAD_FILE.to_string(), HEAD_FILE.to_string(), sha));
+        }
+        Ok(dangling)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git_source::{GitKind, write_pack};
+    use crate::repo::{commit_file, init_test_repo};
+    use tempfile::TempDir;
+
+    #[test]
+    fn corrupt_objects_are_moved_aside() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        let file = repo.dir.join("a.txt");
+        fs::write(&file, "hello").unwrap();
+        repo.add(&vec![file.to_str().unwrap()]).unwrap();
+        repo.commit("first").unwrap();
+        let commit = repo.get_current_commit().unwrap();
+        let tree = repo.read_commit(&commit).unwrap().get_tree_sha();
+
+        assert!(repo.quarantine_corrupt_objects().unwrap().is_empty());
+        assert!(repo.dangling_refs().unwrap().is_empty());
+
+        // Flip the commit's content so it no longer matches its name
+        fs::write(repo.obj_db.object_path(&commit), "commit 3\0bad").unwrap();
+        let quarantined = repo.quarantine_corrupt_objects().unwrap();
+        assert_eq!(quarantined.len(), 1);
+        assert_eq!(quarantined[0].0, commit);
+        assert!(repo.git_dir.join(CORRUPT_DIR).join(&commit.0).exists());
+        assert!(repo.verify_object(&tree).is_ok());
+
+        let dangling = repo.dangling_refs().unwrap();
+        assert_eq!(
+            dangling,
+            vec![(
+                "branch 'master'".to_string(),
+                "refs/heads/master".to_string(),
+                commit
+            )]
+        );
+        // Nothing older to go back to
+        assert!(!repo.repair_objects().unwrap());
+    }
+
+    #[test]
+    fn objects_come_back_from_packs_and_refs_from_the_reflog() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        let first = commit_file(&repo, "a.txt", "one\n");
+        let second = commit_file(&repo, "a.txt", "two\n");
+
+        // A pack holds the first commit, with its version 2 index
+        let data = repo.obj_db.retrieve(&first).unwrap();
+        let content = data[data.iter().position(|&b| b == 0).unwrap() + 1..].to_vec();
+        let pack = write_pack(&[(GitKind::Commit, content)]);
+        let mut idx = b"\xfftOc\0\0\0\x02".to_vec();
+        let fanout = u32::from_str_radix(&first.0[..2], 16).unwrap();
+        for i in 0..256u32 {
+            idx.extend(u32::from(i >= fanout).to_be_bytes());
+        }
+        idx.extend(hex::decode(&first.0).unwrap());
+        idx.extend([0; 4]);
+        idx.extend(12u32.to_be_bytes());
+        let pack_dir = repo.git_dir.join(OBJECTS_DIR).join("pack");
+        fs::create_dir_all(&pack_dir).unwrap();
+        fs::write(pack_dir.join("pack-1.pack"), pack).unwrap();
+        fs::write(pack_dir.join("pack-1.idx"), idx).unwrap();
+
+        for sha in [&first, &second] {
+            fs::write(repo.obj_db.object_path(sha), "commit 3\0bad").unwrap();
+        }
+        assert!(repo.repair_objects().unwrap());
+        assert!(repo.verify_object(&first).is_ok());
+        assert!(!repo.obj_db.object_path(&second).exists());
+        assert_eq!(repo.get_current_commit(), Some(first.clone()));
+        let last = repo.reflog("refs/heads/master").unwrap().pop().unwrap();
+        assert_eq!((last.old, last.new), (Some(second), Some(first)));
+        assert_eq!(last.reason, REPAIR_REASON);
+    }
+
+    #[test]
+    fn only_dead_owners_locks_are_stale() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        let dead = LockOwner {
+            pid: u32::MAX,
+            timestamp: 0,
+        };
+        let stale = repo.get_branch_dir().join("master.lock");
+        fs::write(&stale, dead.to_string()).unwrap();
+        let unknown = repo.git_dir.join("index.lock");
+        fs::write(&unknown, "").unwrap();
+        let held = crate::lock::LockFile::acquire(&repo.git_dir.join(HEAD_FILE)).unwrap();
+
+        let locks = repo.find_locks().unwrap();
+        assert_eq!(locks.len(), 3);
+        repo.repair_stale_locks().unwrap();
+        assert!(!stale.exists());
+        assert!(unknown.exists());
+        assert!(repo.git_dir.join("HEAD.lock").exists());
+        drop(held);
+    }
+}
@@ -0,0 +1,245 @@
+use super::Repository;
+use crate::Error;
+use crate::index::{ConflictStages, Index};
+use crate::object::Blob;
+use sha1::{Digest, Sha1};
+use std::fs;
+use std::io;
+
+/// Directory of the recorded conflicts, one `<id>/preimage` per conflict
+/// and a `<id>/postimage` once it was resolved
+pub(super) const RR_CACHE_DIR: &str = "rr-cache";
+/// The conflicts of the operation in progress whose resolution is yet to
+/// be recorded, one `<id>\t<path>` line each
+pub(super) const MERGE_RR: &str = "MERGE_RR";
+/// The conflicted file with its markers
+const PREIMAGE: &str = "preimage";
+/// The file as the conflict was resolved
+const POSTIMAGE: &str = "postimage";
+
+impl Repository {
+    /// Whether conflict resolutions are recorded and replayed: as
+    /// `rerere.enabled` says, or else if resolutions were recorded before
+    fn rerere_enabled(&self) -> Result<bool, Error> {
+        Ok(self
+            .config()?
+            .get_bool("rerere.enabled")?
+            .unwrap_or_else(|| self.git_dir.join(RR_CACHE_DIR).is_dir()))
+    }
+
+    /// Records the conflicts of `index`, whose markers are in the working
+    /// tree, and replays the resolutions recorded for identical conflicts.
+    /// With `rerere.autoUpdate` a replayed file is staged as well.
+    pub(super) fn rerere_conflicts(&self, index: &mut Index) -> Result<(), Error> {
+        if !self.rerere_enabled()? {
+            return Ok(());
+        }
+        let auto_update = self
+            .config()?
+            .get_bool("rerere.autoUpdate")?
+            .unwrap_or(false);
+        let mut merge_rr = String::new();
+        let mut resolved = Vec::new();
+        for (path, stages) in index.conflicts() {
+            let id = self.conflict_id(stages)?;
+            let dir = self.git_dir.join(RR_CACHE_DIR).join(&id);
+            fs::create_dir_all(&dir)?;
+            let file = self.dir.join(path);
+            match fs::read(dir.join(POSTIMAGE)) {
+                Ok(postimage) => {
+                    fs::write(&file, postimage)?;
+                    println!("Resolved '{path}' using previous resolution.");
+                    if auto_update {
+                        resolved.push(path.clone());
+                    }
+                }
+                Err(why) if why.kind() == io::ErrorKind::NotFound => {
+                    fs::write(dir.join(PREIMAGE), fs::read(&file)?)?;
+                    println!("Recorded preimage for '{path}'");
+                }
+                Err(why) => return Err(why.into()),
+            }
+            merge_rr.push_str(&format!("{id}\t{path}\n"));
+        }
+        fs::write(self.git_dir.join(MERGE_RR), merge_rr)?;
+        for path in resolved {
+            let blob = Blob::new(self.dir.join(&path))?;
+            let sha = self.obj_db.store(&blob).map_err(|why| why.to_string())?;
+            index.update_entry(&path, sha);
+            println!("Staged '{path}' using previous resolution.");
+        }
+        Ok(())
+    }
+
+    /// Records the resolution of every conflict of the operation in
+    /// progress whose file no longer has conflict markers, for
+    /// [`Self::rerere_conflicts`] to replay. `commit` does this on its own.
+    pub fn rerere(&self) -> Result<(), Error> {
+        for (id, path) in self.merge_rr()? {
+            let dir = self.git_dir.join(RR_CACHE_DIR).join(&id);
+            if dir.join(POSTIMAGE).exists() {
+                continue;
+            }
+            let Ok(content) = fs::read(self.dir.join(&path)) else {
+                continue;
+            };
+            if has_conflict_markers(&content) {
+                continue;
+            }
+            fs::create_dir_all(&dir)?;
+            fs::write(dir.join(POSTIMAGE), content)?;
+            println!("Recorded resolution for '{path}'.");
+        }
+        Ok(())
+    }
+
+    /// The paths whose conflicts `rerere` keeps track of
+    pub fn rerere_status(&self) -> Result<Vec<String>, Error> {
+        Ok(self.merge_rr()?.into_iter().map(|(_, path)| path).collect())
+    }
+
+    /// Forgets the recorded resolution of the conflict at `path`, so that
+    /// it is not replayed and a new resolution is recorded
+    pub fn rerere_forget(&self, path: &str) -> Result<(), Error> {
+        let (id, _) = self
+            .merge_rr()?
+            .into_iter()
+            .find(|(_, conflicted)| conflicted == path)
+            .ok_or_else(|| Error::PathspecNoMatch(path.to_string()))?;
+        let postimage = self.git_dir.join(RR_CACHE_DIR).join(id).join(POSTIMAGE);
+        match fs::remove_file(postimage) {
+            Ok(()) => {
+                println!("Forgot resolution for '{path}'");
+                Ok(())
+            }
+            Err(why) if why.kind() == io::ErrorKind::NotFound => {
+                Err(format!("no remembered resolution for '{path}'").into())
+            }
+            Err(why) => Err(why.into()),
+        }
+    }
+
+    /// Drops the conflicts of the operation in progress that were not
+    /// resolved yet, e.g. when the operation is given up
+    pub fn rerere_clear(&self) -> Result<(), Error> {
+        for (id, _) in self.merge_rr()? {
+            let dir = self.git_dir.join(RR_CACHE_DIR).join(id);
+            if !dir.join(POSTIMAGE).exists() {
+                match fs::remove_dir_all(dir) {
+                    Err(why) if why.kind() != io::ErrorKind::NotFound => return Err(why.into()),
+                    _ => {}
+                }
+            }
+        }
+        match fs::remove_file(self.git_dir.join(MERGE_RR)) {
+            Err(why) if why.kind() != io::ErrorKind::NotFound => Err(why.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// The `(id, path)` conflicts listed in `MERGE_RR`
+    fn merge_rr(&self) -> Result<Vec<(String, String)>, Error> {
+        let content = match fs::read_to_string(self.git_dir.join(MERGE_RR)) {
+            Ok(content) => content,
+            Err(why) if why.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(why) => return Err(why.into()),
+        };
+        Ok(content
+            .lines()
+            .filter_map(|line| line.split_once('\t'))
+            .map(|(id, path)| (id.to_string(), path.to_string()))
+            .collect())
+    }
+
+    /// Names a conflict by both sides of it. The sides are ordered, so that
+    /// merging in the other direction meets the same conflict.
+    fn conflict_id(&self, stages: &ConflictStages) -> Result<String, String> {
+        let mut sides = [
+            self.blob_bytes(stages.ours.as_ref())?,
+            self.blob_bytes(stages.theirs.as_ref())?,
+        ];
+        sides.sort();
+        let mut hasher = Sha1::new();
+        hasher.update(&sides[0]);
+        hasher.update([0]);
+        hasher.update(&sides[1]);
+        Ok(hex::encode(hasher.finalize()))
+    }
+}
+
+/// Whether a file still has a line opening a conflict
+fn has_conflict_markers(content: &[u8]) -> bool {
+    content
+        .split(|&byte| byte == b'\n')
+        .any(|line| line.starts_with(b"<<<<<<<"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::{FastForward, ResetMode, init_test_repo};
+    use tempfile::TempDir;
+
+    #[test]
+    fn recorded_resolution_is_replayed() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        repo.set_config("rerere.enabled", true).unwrap();
+        let file = repo.dir.join("a.txt");
+        let commit_file = |content: &str, message: &str| {
+            fs::write(&file, content).unwrap();
+            repo.add(&vec![file.to_str().unwrap()]).unwrap();
+            repo.commit(message).unwrap()
+        };
+        commit_file("1\n", "base");
+        repo.branch("topic").unwrap();
+        let ours = commit_file("ours\n", "ours");
+        repo.checkout("topic", false).unwrap();
+        commit_file("theirs\n", "theirs");
+        repo.checkout("master", false).unwrap();
+
+        assert!(!repo.merge("topic", false, FastForward::Allow).unwrap());
+        assert_eq!(repo.rerere_status().unwrap(), ["a.txt"]);
+        // Nothing is recorded while the markers are there
+        repo.rerere().unwrap();
+        assert!(repo.rerere_forget("a.txt").is_err());
+        commit_file("both\n", "");
+        assert!(repo.rerere_status().unwrap().is_empty());
+
+        // The same conflict again is resolved the same way
+        repo.reset(Some(ours.to_string().as_str()), ResetMode::Hard)
+            .unwrap();
+        assert!(!repo.merge("topic", false, FastForward::Allow).unwrap());
+        assert_eq!(fs::read_to_string(&file).unwrap(), "both\n");
+        assert!(
+            repo.load_index_or_empty()
+                .unwrap()
+                .get_conflict("a.txt")
+                .is_some()
+        );
+
+        // and staged with rerere.autoUpdate
+        repo.merge_abort().unwrap();
+        repo.set_config("rerere.autoUpdate", true).unwrap();
+        assert!(!repo.merge("topic", false, FastForward::Allow).unwrap());
+        let index = repo.load_index_or_empty().unwrap();
+        assert!(index.conflicts().is_empty());
+        assert!(index.get_sha1("a.txt").is_some());
+
+        // A forgotten resolution is not replayed
+        repo.rerere_forget("a.txt").unwrap();
+        repo.merge_abort().unwrap();
+        assert!(!repo.merge("topic", false, FastForward::Allow).unwrap());
+        assert!(has_conflict_markers(&fs::read(&file).unwrap()));
+        repo.rerere_clear().unwrap();
+        assert!(repo.rerere_status().unwrap().is_empty());
+        assert!(
+            !repo
+                .git_dir
+                .join(RR_CACHE_DIR)
+                .read_dir()
+                .unwrap()
+                .any(|_| true)
+        );
+    }
+}
@@ -0,0 +1,100 @@
+use super::diff::Snapshot;
+use super::{HEAD_FILE, Repository};
+use crate::Error;
+use crate::index::Index;
+
+/// How much of the repository `reset` moves to the target commit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResetMode {
+    /// Only the current branch, or a detached HEAD
+    Soft,
+    /// The branch and the index
+    Mixed,
+    /// The branch, the index and the tracked files in the working tree
+    Hard,
+}
+
+impl Repository {
+    /// Points the current branch, or a detached HEAD, at `target`, a branch
+    /// name or commit SHA1 (default `HEAD`), updating the index and working
+    /// tree as `mode` asks. Local changes are discarded by `Hard`.
+    pub fn reset(&self, target: Option<&str>, mode: ResetMode) -> Result<(), Error> {
+        let target = target.unwrap_or(HEAD_FILE);
+        let old = self.get_current_commit();
+        let commit_sha = self.resolve_commit(target)?;
+        let tree = self.read_tree(&self.read_commit(&commit_sha)?.get_tree_sha())?;
+        let index = match mode {
+            ResetMode::Soft => None,
+            ResetMode::Mixed | ResetMode::Hard => Some(self.lock_index()?),
+        };
+        if mode == ResetMode::Hard {
+            // Whatever the tracked files hold now is replaced
+            let worktree = self.snapshot_files(Snapshot::Worktree)?;
+            let mut current = Index::new();
+            for (path, sha) in worktree.entries {
+                current.update_entry(&path, sha);
+            }
+            self.update_worktree(&current, &tree)?;
+        }
+        if let Some(mut index) = index {
+            *index = tree;
+            index.commit()?;
+        }
+        self.save_orig_head()?;
+        self.update_head(
+            old.as_ref(),
+            &commit_sha,
+            &format!("reset: moving to {target}"),
+        )?;
+        // Resetting abandons a revert or cherry-pick stopped at conflicts
+        self.clear_sequencer()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::init_test_repo;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn reset_modes() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        let a = repo.dir.join("a.txt");
+        let b = repo.dir.join("b.txt");
+        fs::write(&a, "one").unwrap();
+        repo.add(&vec![a.to_str().unwrap()]).unwrap();
+        repo.commit("first").unwrap();
+        let first = repo.get_current_commit().unwrap();
+        fs::write(&a, "two").unwrap();
+        fs::write(&b, "b").unwrap();
+        repo.add(&vec![a.to_str().unwrap(), b.to_str().unwrap()])
+            .unwrap();
+        repo.commit("second").unwrap();
+        let second = repo.get_current_commit().unwrap();
+        let staged = || Index::load(&repo.get_index_path()).unwrap();
+
+        repo.reset(Some(&first.0), ResetMode::Soft).unwrap();
+        assert_eq!(repo.get_current_commit().as_ref(), Some(&first));
+        assert!(staged().get_sha1("b.txt").is_some());
+
+        repo.reset(Some("HEAD"), ResetMode::Mixed).unwrap();
+        assert!(staged().get_sha1("b.txt").is_none());
+        assert_eq!(fs::read_to_string(&a).unwrap(), "two");
+
+        repo.reset(Some(&second.0), ResetMode::Mixed).unwrap();
+        fs::write(&a, "local change").unwrap();
+        repo.reset(Some("master"), ResetMode::Hard).unwrap();
+        assert_eq!(fs::read_to_string(&a).unwrap(), "two");
+        repo.reset(Some(&first.0), ResetMode::Hard).unwrap();
+        assert_eq!(fs::read_to_string(&a).unwrap(), "one");
+        assert!(!b.exists());
+        assert_eq!(
+            repo.reflog(HEAD_FILE).unwrap().last().unwrap().reason,
+            format!("reset: moving to {}", first.0)
+        );
+    }
+}
@@ -0,0 +1,130 @@
+use super::pathspec::{matches_pathspec, normalize_pathspecs};
+use super::{HEAD_FILE, Repository};
+use crate::index::Index;
+use crate::{EncodedSha, Error};
+
+impl Repository {
+    /// Restores files matching `paths`, like `git restore`.
+    ///
+    /// # Arguments
+    /// * `source` - Commit to take the files from; defaults to HEAD when
+    ///   restoring the index and to the index when restoring only the
+    ///   working tree
+    /// * `staged` - Restore the index entries, dropping those the source
+    ///   does not have, so staged changes to the paths are undone
+    /// * `worktree` - Restore the working tree files, discarding their
+    ///   unstaged changes; implied when `staged` is not given
+    ///
+    /// Every pathspec must match a file on the restored side.
+    pub fn restore<S: AsRef<str>>(
+        &self,
+        paths: &[S],
+        source: Option<&str>,
+        staged: bool,
+        worktree: bool,
+    ) -> Result<(), Error> {
+        let pathspecs = normalize_pathspecs(paths);
+        let source_index = match source {
+            Some(source) => Some(self.read_commit_index(source)?),
+            None if staged => Some(match self.get_current_commit() {
+                Some(_) => self.read_commit_index(HEAD_FILE)?,
+                // Nothing is committed yet: unstaging drops the entries
+                None => Index::new(),
+            }),
+            None => None,
+        };
+
+        if staged {
+            let source_index = source_index.as_ref().unwrap();
+            let mut index = self.load_index_or_empty()?;
+            for spec in &pathspecs {
+                let spec = std::slice::from_ref(spec);
+                let matching = |index: &Index| -> Vec<(String, EncodedSha)> {
+                    index
+                        .collect_entries()
+                        .into_iter()
+                        .filter(|(path, _)| matches_pathspec(path, spec))
+                        .collect()
+                };
+                let staged_entries = matching(&index);
+                let source_entries = matching(source_index);
+                if staged_entries.is_empty() && source_entries.is_empty() {
+                    return Err(Error::PathspecNoMatch(spec[0].clone()));
+                }
+                for (path, _) in staged_entries {
+                    index.remove_entry(&path);
+                }
+                for (path, sha) in source_entries {
+                    let mode = source_index.get_mode(&path).unwrap_or_default();
+                    index.update_entry_with_mode(&path, sha, mode);
+                }
+            }
+            index.save(&self.get_index_path())?;
+        }
+
+        if worktree || !staged {
+            let index = match source_index {
+                Some(index) => index,
+                None => self.load_index_or_empty()?,
+            };
+            for spec in pathspecs {
+                if self.checkout_index_paths(&index, std::slice::from_ref(&spec))? == 0 {
+                    return Err(Error::PathspecNoMatch(spec));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The snapshot of the commit `rev`
+    fn read_commit_index(&self, rev: &str) -> Result<Index, String> {
+        let commit = self.read_commit(&self.resolve_commit(rev)?)?;
+        self.read_tree(&commit.get_tree_sha())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::index::Index;
+    use crate::repo::init_test_repo;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn restore_worktree_and_staged() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        let file = repo.dir.join("a.txt");
+        let name = file.to_str().unwrap();
+        fs::write(&file, "one\n").unwrap();
+        repo.add(&vec![name]).unwrap();
+        let first = repo.commit("first").unwrap();
+        let committed = Index::load(&repo.get_index_path()).unwrap();
+
+        // Unstaged changes are discarded from the index
+        fs::write(&file, "two\n").unwrap();
+        repo.add(&vec![name]).unwrap();
+        fs::write(&file, "three\n").unwrap();
+        repo.restore(&["a.txt"], None, false, false).unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "two\n");
+
+        // --staged takes HEAD's version back into the index only
+        repo.restore(&["a.txt"], None, true, false).unwrap();
+        let index = Index::load(&repo.get_index_path()).unwrap();
+        assert_eq!(index.get_sha1("a.txt"), committed.get_sha1("a.txt"));
+        assert_eq!(fs::read_to_string(&file).unwrap(), "two\n");
+
+        // A newly added file is unstaged but kept
+        let new = repo.dir.join("new.txt");
+        fs::write(&new, "new\n").unwrap();
+        repo.add(&vec![new.to_str().unwrap()]).unwrap();
+        repo.restore(&["new.txt"], None, true, false).unwrap();
+        let index = Index::load(&repo.get_index_path()).unwrap();
+        assert!(index.get_sha1("new.txt").is_none());
+        assert!(new.exists());
+
+        repo.restore(&["."], Some(&first.0), true, true).unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "one\n");
+        assert!(repo.restore(&["missing"], None, false, false).is_err());
+    }
+}
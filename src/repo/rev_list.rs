@@ -0,0 +1,164 @@
+use super::{HEAD_FILE, Repository, RevWalk};
+use crate::{EncodedSha, Error};
+
+impl Repository {
+    /// Lists the commits reachable from the included revisions but not
+    /// from the excluded ones, like `git rev-list`, newest committer date
+    /// first.
+    ///
+    /// # Arguments
+    /// * `revs` - Revisions to include, as [`Self::parse_rev_range`]
+    ///   takes them
+    /// * `max_count` - Stop after this many commits
+    pub fn rev_list<S: AsRef<str>>(
+        &self,
+        revs: &[S],
+        max_count: Option<usize>,
+    ) -> Result<Vec<EncodedSha>, Error> {
+        let (include, exclude) = self.parse_rev_range(revs)?;
+        Ok(self
+            .walk_range(include, exclude)
+            .map(|(sha, _)| sha)
+            .take(max_count.unwrap_or(usize::MAX))
+            .collect())
+    }
+
+    /// Splits revisions into the commits to include and the commits to
+    /// exclude, like `git rev-parse` prints them:
+    /// - `^<rev>` excludes a revision and what it reaches
+    /// - `<a>..<b>` stands for `^<a> <b>`: what `b` reaches but `a` does not
+    /// - `<a>...<b>` stands for `<a> <b>` minus their merge bases: what
+    ///   either reaches but not both
+    ///
+    /// An empty side of a range means HEAD.
+    pub fn parse_rev_range<S: AsRef<str>>(
+        &self,
+        revs: &[S],
+    ) -> Result<(Vec<EncodedSha>, Vec<EncodedSha>), Error> {
+        let mut include = Vec::new();
+        let mut exclude = Vec::new();
+        for rev in revs {
+            let rev = rev.as_ref();
+            if let Some(excluded) = rev.strip_prefix('^') {
+                exclude.push(self.resolve(excluded)?);
+            } else if let Some((lhs, rhs)) = rev.split_once("...") {
+                let lhs = self.resolve(or_head(lhs))?;
+                let rhs = self.resolve(or_head(rhs))?;
+                exclude.extend(self.find_lcas(&lhs, &rhs));
+                include.extend([lhs, rhs]);
+            } else if let Some((from, to)) = rev.split_once("..") {
+                exclude.push(self.resolve(or_head(from))?);
+                include.push(self.resolve(or_head(to))?);
+            } else {
+                include.push(self.resolve(rev)?);
+            }
+        }
+        Ok((include, exclude))
+    }
+
+    /// Walks the commits reachable from `include` but not from `exclude`,
+    /// newest committer date first
+    pub(super) fn walk_range(
+        &self,
+        include: Vec<EncodedSha>,
+        exclude: Vec<EncodedSha>,
+    ) -> RevWalk<'_> {
+        let mut walk = self.revwalk();
+        for sha in include {
+            walk.push(sha);
+        }
+        for sha in exclude {
+            walk.hide(sha);
+        }
+        walk
+    }
+}
+
+/// One side of a range, an empty one meaning HEAD
+pub(super) fn or_head(side: &str) -> &str {
+    match side {
+        "" => HEAD_FILE,
+        side => side,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::repo::init_test_repo;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn rev_list_walks_history_minus_exclusions() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        let file = repo.dir.join("a.txt");
+        let commit_file = |content: &str| {
+            fs::write(&file, content).unwrap();
+            repo.add(&vec![file.to_str().unwrap()]).unwrap();
+            repo.commit(content).unwrap()
+        };
+        let first = commit_file("one\n");
+        let second = commit_file("two\n");
+        repo.branch("topic").unwrap();
+        let third = commit_file("three\n");
+
+        assert_eq!(
+            repo.rev_list(&["HEAD"], None).unwrap(),
+            [third.clone(), second.clone(), first.clone()]
+        );
+        assert_eq!(repo.rev_list(&["HEAD"], Some(2)).unwrap().len(), 2);
+        assert_eq!(
+            repo.rev_list(&["topic..master"], None).unwrap(),
+            std::slice::from_ref(&third)
+        );
+        assert_eq!(
+            repo.rev_list(&["topic.."], None).unwrap(),
+            std::slice::from_ref(&third)
+        );
+        assert_eq!(
+            repo.rev_list(&["master", "^HEAD~2"], None).unwrap(),
+            [third, second]
+        );
+        assert!(repo.rev_list(&["master..topic"], None).unwrap().is_empty());
+        assert!(repo.rev_list(&["^nope"], None).is_err());
+    }
+
+    #[test]
+    fn symmetric_range_excludes_merge_bases() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        let commit_file = |name: &str| {
+            let file = repo.dir.join(name);
+            fs::write(&file, name).unwrap();
+            repo.add(&vec![file.to_str().unwrap()]).unwrap();
+            repo.commit(name).unwrap()
+        };
+        let base = commit_file("base");
+        repo.branch("topic").unwrap();
+        let ours = commit_file("ours");
+        repo.checkout("topic", false).unwrap();
+        let theirs = commit_file("theirs");
+
+        let mut listed = repo.rev_list(&["master...topic"], None).unwrap();
+        listed.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut expected = vec![ours.clone(), theirs.clone()];
+        expected.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(listed, expected);
+        assert_eq!(
+            repo.parse_rev_range(&["master..."]).unwrap(),
+            (vec![ours.clone(), theirs.clone()], vec![base.clone()])
+        );
+
+        // diff compares the ends of `..` and the merge base with `...`
+        assert_eq!(
+            repo.diff_range("master..topic").unwrap(),
+            Some((ours.clone(), theirs.clone()))
+        );
+        assert_eq!(
+            repo.diff_range("master...topic").unwrap(),
+            Some((base, theirs))
+        );
+        assert_eq!(repo.diff_range("master").unwrap(), None);
+    }
+}
@@ -0,0 +1,198 @@
+use super::undo::UNDO_PREFIX;
+use super::{HEAD_FILE, HEADS_DIR, REFS_DIR, REMOTES_DIR, Repository};
+use crate::{EncodedSha, Error};
+
+impl Repository {
+    /// Resolves a revision to the SHA1 of a commit, like `git rev-parse`.
+    /// See [`Self::resolve_commit`] for the syntax.
+    pub fn resolve(&self, rev: &str) -> Result<EncodedSha, Error> {
+        self.resolve_commit(rev)
+            .map_err(|why| match why.starts_with("bad revision") {
+                true => Error::BadRevision(rev.to_string()),
+                false => Error::Other(why),
+            })
+    }
+
+    /// Resolves a revision to a commit SHA1. A revision is a name followed
+    /// by any number of suffixes:
+    /// - the name is `HEAD`, a branch, a remote-tracking branch, a tag, a
+    ///   full or abbreviated SHA1, `<ref>@{n}` for the n-th prior value of
+    ///   a ref, `@{-n}` for the branch or commit checked out n checkouts
+    ///   ago, or `<branch>@{upstream}` (`@{u}`) for the branch it tracks
+    /// - `~n` goes back n generations along first parents, `~` being `~1`
+    /// - `^n` takes the n-th parent, `^` being `^1` and `^0` the commit
+    ///   itself
+    pub(super) fn resolve_commit(&self, rev: &str) -> Result<EncodedSha, String> {
+        // Names that merely contain `~` or `^` are not revisions with suffixes
+        if let Ok(sha) = self.resolve_base(rev) {
+            return Ok(sha);
+        }
+        let Some(split) = rev.find(['~', '^']).filter(|&split| split > 0) else {
+            return self.resolve_base(rev);
+        };
+        let mut sha = self.resolve_base(&rev[..split])?;
+        let mut suffixes = &rev[split..];
+        while let Some(operator) = suffixes.chars().next() {
+            let digits = suffixes[1..]
+                .find(|c: char| !c.is_ascii_digit())
+                .map_or(suffixes.len(), |end| end + 1);
+            let n = match &suffixes[1..digits] {
+                "" => 1,
+                n => n
+                    .parse::<usize>()
+                    .map_err(|_| format!("bad revision '{rev}'"))?,
+            };
+            suffixes = &suffixes[digits..];
+            match operator {
+                '~' => {
+                    for _ in 0..n {
+                        sha = self.nth_parent(&sha, 1, rev)?;
+                    }
+                }
+                '^' if n == 0 => {}
+                '^' => sha = self.nth_parent(&sha, n, rev)?,
+                _ => return Err(format!("bad revision '{rev}'")),
+            }
+        }
+        Ok(sha)
+    }
+
+    /// Resolves a revision without suffixes: `@{-n}`, `@{upstream}` or any
+    /// name [`Self::resolve_name`] takes
+    fn resolve_base(&self, rev: &str) -> Result<EncodedSha, String> {
+        if let Some(name) = self.previous_checkout(rev)? {
+            return self.resolve_name(&name);
+        }
+        for suffix in ["@{upstream}", "@{u}"] {
+            if let Some(branch) = rev.strip_suffix(suffix) {
+                return self.resolve_upstream(branch);
+            }
+        }
+        self.resolve_name(rev)
+    }
+
+    /// The branch or commit `@{-n}` names: what HEAD was moved away from
+    /// by the n-th most recent checkout. `-` stands for `@{-1}`.
+    ///
+    /// # Returns
+    /// `None` if `rev` is not of that form
+    pub(super) fn previous_checkout(&self, rev: &str) -> Result<Option<String>, String> {
+        let n = match rev {
+            "-" => 1,
+            rev => match rev
+                .strip_prefix("@{-")
+                .and_then(|rest| rest.strip_suffix('}'))
+                .and_then(|n| n.parse::<usize>().ok())
+            {
+                Some(n) if n > 0 => n,
+                _ => return Ok(None),
+            },
+        };
+        let entries = self.reflog(HEAD_FILE).map_err(|why| why.to_string())?;
+        entries
+            .iter()
+            .rev()
+            .filter_map(|entry| {
+                let (from, _) = entry
+                    .reason
+                    .trim_start_matches(UNDO_PREFIX)
+                    .strip_prefix("checkout: moving from ")?
+                    .rsplit_once(" to ")?;
+                Some(from.to_string())
+            })
+            .nth(n - 1)
+            .map(Some)
+            .ok_or_else(|| format!("'{rev}': only {n} checkouts needed, fewer were logged"))
+    }
+
+    /// The commit of the branch `branch` tracks, by its
+    /// `branch.<name>.remote` and `branch.<name>.merge` settings. An empty
+    /// name stands for the current branch.
+    fn resolve_upstream(&self, branch: &str) -> Result<EncodedSha, String> {
+        let heads_prefix = format!("{REFS_DIR}/{HEADS_DIR}/");
+        let branch = match branch {
+            "" | "HEAD" => self
+                .head_ref_name()
+                .and_then(|name| name.strip_prefix(&heads_prefix).map(str::to_string))
+                .ok_or("HEAD does not point to a branch")?,
+            branch => branch.to_string(),
+        };
+        let config = self.config().map_err(|why| why.to_string())?;
+        let remote = config.get(&format!("branch.{branch}.remote"));
+        let merge = config.get(&format!("branch.{branch}.merge"));
+        let (Some(remote), Some(merge)) = (remote, merge) else {
+            return Err(format!("no upstream configured for branch '{branch}'"));
+        };
+        let merge = merge.strip_prefix(&heads_prefix).unwrap_or(merge);
+        // `.` tracks a local branch
+        let tracking = match remote {
+            "." => merge.to_string(),
+            remote => format!("{REFS_DIR}/{REMOTES_DIR}/{remote}/{merge}"),
+        };
+        self.resolve_name(&tracking).map_err(|_| {
+            format!(
+                "upstream branch '{merge}' of '{branch}' is not stored as a remote-tracking branch"
+            )
+        })
+    }
+
+    /// The `n`-th parent of `sha`, counting from 1
+    fn nth_parent(&self, sha: &EncodedSha, n: usize, rev: &str) -> Result<EncodedSha, String> {
+        self.read_commit(sha)?
+            .get_parents()
+            .get(n - 1)
+            .cloned()
+            .ok_or_else(|| format!("bad revision '{rev}': {sha} has no parent {n}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::{FastForward, commit_file, init_test_repo};
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn revisions_take_ancestry_suffixes_and_checkout_history() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        let file = repo.dir.join("a.txt");
+        let first = commit_file(&repo, "a.txt", "one\n");
+        let second = commit_file(&repo, "a.txt", "two\n");
+        repo.branch("topic").unwrap();
+        repo.checkout("topic", false).unwrap();
+        let side = commit_file(&repo, "b.txt", "side\n");
+        repo.checkout("master", false).unwrap();
+        let third = commit_file(&repo, "a.txt", "three\n");
+        repo.merge("topic", false, FastForward::Allow).unwrap();
+        let merge = repo.get_current_commit().unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "three\n");
+
+        let resolve = |rev: &str| repo.resolve(rev).unwrap();
+        assert_eq!(resolve("HEAD^0"), merge);
+        assert_eq!(resolve("HEAD^"), third);
+        assert_eq!(resolve("HEAD^2"), side);
+        assert_eq!(resolve("master~2"), second);
+        assert_eq!(resolve("HEAD~~"), second);
+        assert_eq!(resolve("HEAD^2~1"), second);
+        assert_eq!(resolve("HEAD~3"), first);
+        assert_eq!(resolve(&format!("{}~1", &third.0[..7])), second);
+        assert!(matches!(repo.resolve("HEAD~4"), Err(Error::BadRevision(_))));
+        assert!(matches!(repo.resolve("HEAD^3"), Err(Error::BadRevision(_))));
+        assert!(matches!(repo.resolve("nope~1"), Err(Error::BadRevision(_))));
+
+        // The previous checkout, and where a branch is tracking
+        assert_eq!(resolve("@{-1}"), side);
+        assert_eq!(resolve("@{-2}"), merge);
+        assert!(repo.resolve("@{-9}").is_err());
+        assert!(repo.resolve("@{u}").is_err());
+        repo.set_config("branch.master.remote", ".").unwrap();
+        repo.set_config("branch.master.merge", "refs/heads/topic")
+            .unwrap();
+        assert_eq!(resolve("@{upstream}~1"), second);
+        assert_eq!(resolve("master@{u}"), side);
+        repo.checkout("@{-1}", false).unwrap();
+        assert_eq!(repo.head_ref_name().as_deref(), Some("refs/heads/topic"));
+    }
+}
@@ -0,0 +1,205 @@
+use super::cherry_pick::CHERRY_PICK_HEAD;
+use super::merge::MERGE_HEAD;
+use super::rerere::MERGE_RR;
+use super::{IndexDiffType, Repository};
+use crate::index::Index;
+use crate::object::Commit;
+use crate::{EncodedSha, Error};
+use std::fs;
+use std::io;
+
+/// Names the commit being reverted while its conflicts are resolved
+pub(super) const REVERT_HEAD: &str = "REVERT_HEAD";
+/// Message offered to the commit that concludes a conflicted revert,
+/// cherry-pick or merge
+pub(super) const MERGE_MSG: &str = "MERGE_MSG";
+
+impl Repository {
+    /// Creates a commit undoing the change `rev` introduced relative to its
+    /// parent, like `git revert`. The inverse change is merged into HEAD,
+    /// so later changes to the same files are kept.
+    ///
+    /// On conflicts the index and working tree receive the files with
+    /// conflict markers, `REVERT_HEAD` names the reverted commit and the
+    /// revert message waits in `MERGE_MSG` for the next `commit`.
+    ///
+    /// # Returns
+    /// - `Ok(Some(EncodedSha))`: The revert commit
+    /// - `Ok(None)`: The revert stopped at conflicts
+    /// - `Err(Error)`: `rev` cannot be reverted or there are staged changes
+    pub fn revert(&self, rev: &str) -> Result<Option<EncodedSha>, Error> {
+        self.check_no_operation_in_progress()?;
+        let (head_sha, head_index) = self.clean_head()?;
+        let target_sha = self.resolve_commit(rev)?;
+        let target = self.read_commit(&target_sha)?;
+        let parent_index = self.parent_index(&target_sha, &target)?;
+        let target_index = self.read_tree(&target.get_tree_sha())?;
+        let subject = target.get_message().lines().next().unwrap_or_default();
+        let message = format!("Revert \"{subject}\"\n\nThis reverts commit {target_sha}.");
+
+        // Check the identity before touching the index and working tree
+        let (author_name, author_email) = self.identity()?;
+        let label = format!("parent of {} ({subject})", &target_sha.0[..7]);
+        if !self.apply_change(&head_index, &target_index, &parent_index, &label)? {
+            fs::write(self.git_dir.join(REVERT_HEAD), format!("{target_sha}\n"))?;
+            fs::write(self.git_dir.join(MERGE_MSG), format!("{message}\n"))?;
+            println!(
+                "error: could not revert {}... {subject}",
+                &target_sha.0[..7]
+            );
+            println!(
+                "hint: after resolving the conflicts, mark the corrected paths\n\
+                 hint: with 'rust-git add <paths>' and run 'rust-git commit'"
+            );
+            return Ok(None);
+        }
+
+        let tree = self.write_tree()?;
+        if tree == self.read_commit(&head_sha)?.get_tree_sha() {
+            return Err(Error::NothingToCommit);
+        }
+        let commit_sha = self.commit_tree_with_author(
+            tree,
+            vec![head_sha.clone()],
+            &message,
+            None,
+            &author_name,
+            &author_email,
+            self.gpg_sign(None)?,
+        )?;
+        self.update_head(Some(&head_sha), &commit_sha, &format!("revert: {message}"))?;
+        Ok(Some(commit_sha))
+    }
+
+    /// The HEAD commit and its snapshot, provided the index has no changes
+    /// staged on top of it
+    pub(super) fn clean_head(&self) -> Result<(EncodedSha, Index), Error> {
+        let head_sha = self
+            .get_current_commit()
+            .ok_or("HEAD does not point to a commit yet")?;
+        let head_index = self.read_tree(&self.read_commit(&head_sha)?.get_tree_sha())?;
+        let index = self.load_index_or_empty()?;
+        if self
+            .diff_index(&head_index, &index)
+            .values()
+            .any(|status| *status != IndexDiffType::Unmodified)
+        {
+            return Err(Error::UncommittedChanges);
+        }
+        Ok((head_sha, head_index))
+    }
+
+    /// The snapshot `commit` changed, empty for a root commit. Merges
+    /// have no single change to replay and are refused.
+    pub(super) fn parent_index(&self, sha: &EncodedSha, commit: &Commit) -> Result<Index, Error> {
+        match commit.get_parents().as_slice() {
+            [] => Ok(Index::new()),
+            [parent] => Ok(self.read_tree(&self.read_commit(parent)?.get_tree_sha())?),
+            _ => Err(format!("commit {sha} is a merge; its change cannot be replayed").into()),
+        }
+    }
+
+    /// Three-way merges the change from `base` to `theirs` into the HEAD
+    /// snapshot `head_index`, writing the result to the index and working
+    /// tree. Conflicted files get conflict markers, the side of `theirs`
+    /// labeled `theirs_label`, and are reported.
+    ///
+    /// # Returns
+    /// Whether the change applied without conflicts
+    pub(super) fn apply_change(
+        &self,
+        head_index: &Index,
+        base: &Index,
+        theirs: &Index,
+        theirs_label: &str,
+    ) -> Result<bool, Error> {
+        let (mut merged, conflicts) = self.merge_indexes(base, head_index, theirs, theirs_label)?;
+        self.checkout_index(&merged)?;
+        Self::stage_conflicts(&mut merged, &conflicts, base, head_index, theirs);
+        self.rerere_conflicts(&mut merged)?;
+        merged.save(&self.get_index_path())?;
+        for conflict in &conflicts {
+            println!(
+                "CONFLICT ({}): Merge conflict in {}",
+                conflict.kind.as_str(),
+                conflict.path
+            );
+        }
+        Ok(conflicts.is_empty())
+    }
+
+    /// The message a conflicted revert or cherry-pick left for the
+    /// concluding commit
+    pub(super) fn pending_message(&self) -> Option<String> {
+        let message = fs::read_to_string(self.git_dir.join(MERGE_MSG)).ok()?;
+        Some(message.trim_end().to_string())
+    }
+
+    /// Forgets the revert, cherry-pick or merge stopped at conflicts, once
+    /// it was committed. A cherry-pick sequence keeps the commits still to
+    /// pick.
+    pub(super) fn clear_pick_state(&self) -> io::Result<()> {
+        for file in [
+            REVERT_HEAD,
+            CHERRY_PICK_HEAD,
+            MERGE_HEAD,
+            MERGE_MSG,
+            MERGE_RR,
+        ] {
+            match fs::remove_file(self.git_dir.join(file)) {
+                Err(why) if why.kind() != io::ErrorKind::NotFound => return Err(why),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::{commit_file, init_test_repo};
+    use tempfile::TempDir;
+
+    #[test]
+    fn revert_undoes_a_commit() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        commit_file(&repo, "a.txt", "1\n2\n3\n");
+        let added = commit_file(&repo, "b.txt", "b\n");
+        commit_file(&repo, "a.txt", "1\n2\n3\n4\n");
+
+        let revert = repo.revert(&added.0).unwrap().unwrap();
+        assert!(!repo.dir.join("b.txt").exists());
+        let commit = repo.read_commit(&revert).unwrap();
+        assert_eq!(
+            commit.get_message(),
+            format!("Revert \"change b.txt\"\n\nThis reverts commit {added}.")
+        );
+
+        // Reverting a change later commits built on conflicts
+        let changed = commit_file(&repo, "a.txt", "1\ntwo\n3\n4\n");
+        commit_file(&repo, "a.txt", "1\nTWO\n3\n4\n");
+        assert_eq!(repo.revert(&changed.0).unwrap(), None);
+        assert!(repo.git_dir.join(REVERT_HEAD).exists());
+        assert!(
+            fs::read_to_string(repo.dir.join("a.txt"))
+                .unwrap()
+                .contains("<<<<<<<")
+        );
+        assert!(repo.revert("HEAD").is_err());
+
+        fs::write(repo.dir.join("a.txt"), "1\n2\n3\n4\n").unwrap();
+        let file = repo.dir.join("a.txt");
+        repo.add(&vec![file.to_str().unwrap()]).unwrap();
+        // The revert message is taken up when no other is given
+        let concluded = repo.commit("").unwrap();
+        assert!(
+            repo.read_commit(&concluded)
+                .unwrap()
+                .get_message()
+                .starts_with("Revert \"change a.txt\"")
+        );
+        assert!(!repo.git_dir.join(REVERT_HEAD).exists());
+    }
+}
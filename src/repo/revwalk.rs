@@ -0,0 +1,259 @@
+use super::Repository;
+use crate::EncodedSha;
+use crate::object::Commit;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+/// The order a [`RevWalk`] yields commits in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RevSort {
+    /// Newest committer date first
+    #[default]
+    Date,
+    /// No commit before all of its children, keeping the commits of each
+    /// line of history together, like `--topo-order`
+    Topological,
+}
+
+/// A walk over the history, yielding each commit once together with its
+/// SHA1, see [`Repository::revwalk`]. It starts from the commits pushed,
+/// follows all parents of merges and leaves out whatever the hidden
+/// commits reach. Commits that cannot be read end the walk along that
+/// line of history.
+pub struct RevWalk<'r> {
+    repo: &'r Repository,
+    /// `(committer timestamp, order of discovery, SHA1)` of the commits
+    /// waiting to be yielded. Commits from the same second come out in the
+    /// order they were found, so children precede their parents.
+    queue: BinaryHeap<(i64, Reverse<usize>, String)>,
+    commits: HashMap<String, Commit>,
+    seen: HashSet<EncodedSha>,
+    hidden: Vec<EncodedSha>,
+    /// Everything the hidden commits reach, found once the walk starts
+    excluded: Option<HashSet<EncodedSha>>,
+    sorting: RevSort,
+    reverse: bool,
+    /// The whole walk, for the orders that need to see all of it first
+    sorted: Option<std::vec::IntoIter<(EncodedSha, Commit)>>,
+}
+
+impl Repository {
+    /// Starts a walk over the history, with no commits to start from yet;
+    /// they are added with [`RevWalk::push`]
+    pub fn revwalk(&self) -> RevWalk<'_> {
+        RevWalk {
+            repo: self,
+            queue: BinaryHeap::new(),
+            commits: HashMap::new(),
+            seen: HashSet::new(),
+            hidden: Vec::new(),
+            excluded: None,
+            sorting: RevSort::Date,
+            reverse: false,
+            sorted: None,
+        }
+    }
+}
+
+impl RevWalk<'_> {
+    /// Starts the walk from a commit as well
+    pub fn push(&mut self, sha: EncodedSha) -> &mut Self {
+        if !self.seen.insert(sha.clone()) {
+            return self;
+        }
+        if let Ok(commit) = self.repo.read_commit(&sha) {
+            let time = commit.get_committer().get_timestamp().timestamp();
+            let order = Reverse(self.seen.len());
+            self.queue.push((time, order, sha.0.clone()));
+            self.commits.insert(sha.0, commit);
+        }
+        self
+    }
+
+    /// Leaves out a commit and all of its ancestors, like `^<commit>`.
+    /// Takes effect if called before the walk starts.
+    pub fn hide(&mut self, sha: EncodedSha) -> &mut Self {
+        self.hidden.push(sha);
+        self
+    }
+
+    /// Sets the order of the walk; takes effect if called before the walk
+    /// starts
+    pub fn sorting(&mut self, sorting: RevSort) -> &mut Self {
+        self.sorting = sorting;
+        self
+    }
+
+    /// Yields the commits in the opposite order, oldest first by default;
+    /// takes effect if called before the walk starts
+    pub fn reverse(&mut self, reverse: bool) -> &mut Self {
+        self.reverse = reverse;
+        self
+    }
+
+    /// The next commit by committer date that is not hidden
+    fn next_by_date(&mut self) -> Option<(EncodedSha, Commit)> {
+        if self.excluded.is_none() {
+            self.excluded = Some(self.repo.reachable_from(self.hidden.clone()).collect());
+        }
+        loop {
+            let (_, _, sha) = self.queue.pop()?;
+            let commit = self.commits.remove(&sha)?;
+            let sha = EncodedSha(sha);
+            // What a hidden commit reaches is hidden as well
+            if self
+                .excluded
+                .as_ref()
+                .is_some_and(|hidden| hidden.contains(&sha))
+            {
+                continue;
+            }
+            for parent in commit.get_parents() {
+                self.push(parent.clone());
+            }
+            return Some((sha, commit));
+        }
+    }
+}
+
+impl Iterator for RevWalk<'_> {
+    type Item = (EncodedSha, Commit);
+
+    fn next(&mut self) -> Option<(EncodedSha, Commit)> {
+        if self.sorting == RevSort::Date && !self.reverse {
+            return self.next_by_date();
+        }
+        if self.sorted.is_none() {
+            let mut commits: Vec<(EncodedSha, Commit)> =
+                std::iter::from_fn(|| self.next_by_date()).collect();
+            if self.sorting == RevSort::Topological {
+                commits = topological(commits);
+            }
+            if self.reverse {
+                commits.reverse();
+            }
+            self.sorted = Some(commits.into_iter());
+        }
+        self.sorted.as_mut()?.next()
+    }
+}
+
+/// Orders commits given newest first so that each comes after all of its
+/// children, following a line of history down to where another line
+/// joins it before going on with that one. First parents come first.
+fn topological(commits: Vec<(EncodedSha, Commit)>) -> Vec<(EncodedSha, Commit)> {
+    let mut children: HashMap<EncodedSha, usize> =
+        commits.iter().map(|(sha, _)| (sha.clone(), 0)).collect();
+    for (_, commit) in &commits {
+        for parent in commit.get_parents() {
+            if let Some(count) = children.get_mut(parent) {
+                *count += 1;
+            }
+        }
+    }
+    let mut ready: Vec<EncodedSha> = commits
+        .iter()
+        .rev()
+        .filter(|(sha, _)| children[sha] == 0)
+        .map(|(sha, _)| sha.clone())
+        .collect();
+    let mut commits: HashMap<EncodedSha, Commit> = commits.into_iter().collect();
+    let mut sorted = Vec::with_capacity(commits.len());
+    while let Some(sha) = ready.pop() {
+        let Some(commit) = commits.remove(&sha) else {
+            continue;
+        };
+        for parent in commit.get_parents().iter().rev() {
+            if let Some(count) = children.get_mut(parent) {
+                *count -= 1;
+                if *count == 0 {
+                    ready.push(parent.clone());
+                }
+            }
+        }
+        sorted.push((sha, commit));
+    }
+    sorted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::{FastForward, init_test_repo};
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn revwalk_follows_all_parents_once() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        let commit = |name: &str| {
+            let file = repo.dir.join(name);
+            fs::write(&file, name).unwrap();
+            repo.add(&vec![file.to_str().unwrap()]).unwrap();
+            repo.commit(name).unwrap();
+            repo.get_current_commit().unwrap()
+        };
+        let base = commit("base");
+        repo.branch("topic").unwrap();
+        let main = commit("main");
+        repo.checkout("topic", false).unwrap();
+        let topic = commit("topic");
+        repo.checkout("master", false).unwrap();
+        repo.merge("topic", false, FastForward::Allow).unwrap();
+        let merge = repo.get_current_commit().unwrap();
+
+        let mut walk = repo.revwalk();
+        walk.push(merge.clone());
+        let walked: Vec<(EncodedSha, Commit)> = walk.collect();
+        let shas: Vec<&EncodedSha> = walked.iter().map(|(sha, _)| sha).collect();
+        assert_eq!(shas.len(), 4);
+        assert_eq!(shas[0], &merge);
+        assert_eq!(shas[3], &base);
+        assert!(shas.contains(&&main) && shas.contains(&&topic));
+        assert_eq!(walked[0].1.get_parents().len(), 2);
+    }
+
+    #[test]
+    fn sorting_hiding_and_reversing() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        let commit = |name: &str| {
+            let file = repo.dir.join(name);
+            fs::write(&file, name).unwrap();
+            repo.add(&vec![file.to_str().unwrap()]).unwrap();
+            repo.commit(name).unwrap()
+        };
+        let base = commit("base");
+        repo.branch("topic").unwrap();
+        repo.checkout("topic", false).unwrap();
+        let topic = commit("topic");
+        repo.checkout("master", false).unwrap();
+        let main = commit("main");
+        repo.merge("topic", false, FastForward::Allow).unwrap();
+        let merge = repo.get_current_commit().unwrap();
+        let walk = |sorting, reverse, hide: Option<&EncodedSha>| -> Vec<EncodedSha> {
+            let mut walk = repo.revwalk();
+            walk.push(merge.clone()).sorting(sorting).reverse(reverse);
+            if let Some(hide) = hide {
+                walk.hide(hide.clone());
+            }
+            walk.map(|(sha, _)| sha).collect()
+        };
+
+        // The first parent's line comes before the merged one
+        let topological = walk(RevSort::Topological, false, None);
+        assert_eq!(
+            topological,
+            [merge.clone(), main.clone(), topic.clone(), base.clone()]
+        );
+        let mut reversed = topological.clone();
+        reversed.reverse();
+        assert_eq!(walk(RevSort::Topological, true, None), reversed);
+        assert_eq!(walk(RevSort::Date, true, None).first(), Some(&base));
+        assert_eq!(
+            walk(RevSort::Topological, false, Some(&topic)),
+            [merge, main]
+        );
+    }
+}
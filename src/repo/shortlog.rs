@@ -0,0 +1,95 @@
+use super::{HEAD_FILE, Repository};
+use crate::Error;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+impl Repository {
+    /// Prints the history of `rev` grouped by author, like `git shortlog`,
+    /// see [`Self::shortlog_output`]
+    pub fn shortlog(&self, rev: Option<&str>, summary: bool, numbered: bool) -> Result<(), Error> {
+        let out = self.shortlog_output(rev, summary, numbered)?;
+        io::stdout().write_all(out.as_bytes())?;
+        Ok(())
+    }
+
+    /// Renders the history of `rev` (default `HEAD`) grouped by author
+    /// name: every author with their commit count and the subjects of
+    /// their commits, oldest first.
+    ///
+    /// # Arguments
+    /// * `summary` - Only print the count of every author
+    /// * `numbered` - Sort the authors by their count instead of by name
+    pub fn shortlog_output(
+        &self,
+        rev: Option<&str>,
+        summary: bool,
+        numbered: bool,
+    ) -> Result<String, Error> {
+        let tip = self.resolve_commit(rev.unwrap_or(HEAD_FILE))?;
+        let mut authors: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        let mut walk = self.revwalk();
+        walk.push(tip);
+        for (_, commit) in walk {
+            let subject = commit.get_message().lines().next().unwrap_or_default();
+            authors
+                .entry(commit.get_author().get_name().to_string())
+                .or_default()
+                .push(subject.to_string());
+        }
+        let mut authors: Vec<(String, Vec<String>)> = authors.into_iter().collect();
+        if numbered {
+            // Stable, so authors with the same count stay sorted by name
+            authors.sort_by_key(|(_, subjects)| std::cmp::Reverse(subjects.len()));
+        }
+
+        let mut out = String::new();
+        for (author, subjects) in authors {
+            if summary {
+                out.push_str(&format!("{:>6}\t{author}\n", subjects.len()));
+                continue;
+            }
+            out.push_str(&format!("{author} ({}):\n", subjects.len()));
+            for subject in subjects.iter().rev() {
+                out.push_str(&format!("      {subject}\n"));
+            }
+            out.push('\n');
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::repo::init_test_repo;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn shortlog_groups_commits_by_author() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        let file = repo.dir.join("a.txt");
+        let commit_as = |name: &str, content: &str| {
+            repo.set_config("user.name", name).unwrap();
+            fs::write(&file, content).unwrap();
+            repo.add(&vec![file.to_str().unwrap()]).unwrap();
+            repo.commit(format!("{content}\n\nbody")).unwrap();
+        };
+        commit_as("Bob", "one");
+        commit_as("Alice", "two");
+        commit_as("Bob", "three");
+
+        assert_eq!(
+            repo.shortlog_output(None, false, false).unwrap(),
+            "Alice (1):\n      two\n\nBob (2):\n      one\n      three\n\n"
+        );
+        assert_eq!(
+            repo.shortlog_output(None, true, true).unwrap(),
+            "     2\tBob\n     1\tAlice\n"
+        );
+        assert_eq!(
+            repo.shortlog_output(Some("HEAD@{1}"), true, false).unwrap(),
+            "     1\tAlice\n     1\tBob\n"
+        );
+    }
+}
@@ -0,0 +1,180 @@
+use super::pathspec::normalize_pathspecs;
+use super::{HEAD_FILE, PrettyFormat, Repository};
+use crate::index::Index;
+use crate::object::{Blob, Tag};
+use crate::{EncodedSha, Error};
+use std::collections::BTreeSet;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+impl Repository {
+    /// Prints an object the way `git show` does, with commits in `format`,
+    /// see [`Self::show_output_with`]
+    pub fn show(&self, object: &str, format: &PrettyFormat) -> Result<(), Error> {
+        let out = self.show_output_with(object, format)?;
+        io::stdout().write_all(&out)?;
+        Ok(())
+    }
+
+    /// [`Self::show_output_with`] in the default format
+    pub fn show_output(&self, object: &str) -> Result<Vec<u8>, Error> {
+        self.show_output_with(object, &PrettyFormat::Medium)
+    }
+
+    /// Renders an object for `show`:
+    /// - a commit prints like in `log`, in `format`, with its signature
+    ///   checked if `log.showSignature` is set, followed by its patch
+    ///   against the first parent; an annotated tag prints its tagger and
+    ///   message first
+    /// - `<rev>:<path>` prints the content of that file in `rev`, or lists
+    ///   the directory; an empty `rev` stands for HEAD
+    /// - the SHA1 of a tree lists it, the SHA1 of a blob prints its content
+    pub fn show_output_with(&self, object: &str, format: &PrettyFormat) -> Result<Vec<u8>, Error> {
+        if let Some((rev, path)) = object.split_once(':') {
+            let rev = if rev.is_empty() { HEAD_FILE } else { rev };
+            let commit = self.read_commit(&self.resolve_commit(rev)?)?;
+            let tree = self.read_tree(&commit.get_tree_sha())?;
+            let path = normalize_pathspecs(&[path]).remove(0);
+            if let Some(blob) = tree.get_sha1(&path) {
+                return self.blob_content(blob);
+            }
+            return list_directory(&tree, &path, object)
+                .ok_or_else(|| format!("path '{path}' does not exist in '{rev}'").into());
+        }
+
+        if let Ok(sha) = self.resolve_commit(object) {
+            let commit = self.read_commit(&sha)?;
+            // Branches take precedence over tags of the same name
+            let tag = self
+                .load_branch(object)
+                .is_none()
+                .then(|| self.load_tag(object));
+            let mut out = tag
+                .flatten()
+                .and_then(|tag| self.read_tag(&tag))
+                .map(|tag| format_tag_header(&tag))
+                .unwrap_or_default();
+            out.push_str(&self.format_header(
+                &sha,
+                &commit,
+                format,
+                self.show_signature()?,
+                &self.decorations(),
+            )?);
+            out.push_str(&self.commit_patch(&commit)?);
+            return Ok(out.into_bytes());
+        }
+
+        // Trees and blobs can only be named by their full SHA1
+        let sha = EncodedSha::from_str(object).map_err(|_| Error::BadRevision(object.into()))?;
+        match self.read_tree(&sha) {
+            Ok(tree) => Ok(list_directory(&tree, "", object).unwrap_or_default()),
+            Err(_) => self.blob_content(&sha),
+        }
+    }
+
+    pub(super) fn blob_content(&self, sha: &EncodedSha) -> Result<Vec<u8>, Error> {
+        let data = self.obj_db.retrieve(sha)?;
+        Ok(Blob::deserialize(&data)?.data)
+    }
+}
+
+/// Header `show` prints above the commit an annotated tag points at
+fn format_tag_header(tag: &Tag) -> String {
+    let tagger = tag.get_tagger();
+    format!(
+        "tag {}\nTagger: {} <{}>\nDate:   {}\n\n{}\n\n",
+        tag.get_name(),
+        tagger.get_name(),
+        tagger.get_email(),
+        tagger.get_timestamp().format("%a %b %e %H:%M:%S %Y %z"),
+        tag.get_message()
+    )
+}
+
+/// Lists the entries directly below `dir` in the flattened `tree` like
+/// `git show` lists a tree, marking directories with a trailing slash.
+/// Returns `None` if `tree` has nothing below `dir`.
+fn list_directory(tree: &Index, dir: &str, name: &str) -> Option<Vec<u8>> {
+    let prefix = match dir {
+        "" => String::new(),
+        dir => format!("{dir}/"),
+    };
+    let children: BTreeSet<String> = tree
+        .collect_entries()
+        .into_iter()
+        .filter_map(|(path, _)| {
+            let rest = path.strip_prefix(&prefix)?;
+            Some(match rest.split_once('/') {
+                Some((child, _)) => format!("{child}/"),
+                None => rest.to_string(),
+            })
+        })
+        .collect();
+    if children.is_empty() && !dir.is_empty() {
+        return None;
+    }
+    let mut out = format!("tree {name}\n\n");
+    for child in children {
+        out.push_str(&child);
+        out.push('\n');
+    }
+    Some(out.into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::repo::{PrettyFormat, init_test_repo};
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn show_commits_files_and_trees() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        fs::create_dir(repo.dir.join("src")).unwrap();
+        let files = [repo.dir.join("a.txt"), repo.dir.join("src/lib.rs")];
+        fs::write(&files[0], "one\n").unwrap();
+        fs::write(&files[1], "fn main() {}\n").unwrap();
+        let paths: Vec<&str> = files.iter().map(|f| f.to_str().unwrap()).collect();
+        repo.add(&paths).unwrap();
+        repo.commit("first").unwrap();
+        fs::write(&files[0], "one\ntwo\n").unwrap();
+        repo.add(&vec![paths[0]]).unwrap();
+        let second = repo.commit("second").unwrap();
+
+        let show = String::from_utf8(repo.show_output(&second.0).unwrap()).unwrap();
+        assert!(show.starts_with(&format!("commit {second}\n")));
+        assert!(show.contains("    second\n\ndiff --git a/a.txt b/a.txt\n"));
+        assert!(show.ends_with(" one\n+two\n"));
+        repo.tag_create("v2", None, Some("Second release")).unwrap();
+        let tagged = String::from_utf8(repo.show_output("v2").unwrap()).unwrap();
+        assert!(tagged.starts_with("tag v2\nTagger: Alice <"));
+        assert!(tagged.ends_with(&show));
+        assert!(tagged.contains(&format!("\n\nSecond release\n\ncommit {second}\n")));
+
+        assert_eq!(repo.show_output("HEAD:a.txt").unwrap(), b"one\ntwo\n");
+        assert_eq!(repo.show_output(":src/lib.rs").unwrap(), b"fn main() {}\n");
+        assert_eq!(
+            repo.show_output("HEAD:").unwrap(),
+            b"tree HEAD:\n\na.txt\nsrc/\n"
+        );
+        assert_eq!(
+            repo.show_output("HEAD:src").unwrap(),
+            b"tree HEAD:src\n\nlib.rs\n"
+        );
+        let tree = repo.read_commit(&second).unwrap().get_tree_sha();
+        let listing = String::from_utf8(repo.show_output(&tree.0).unwrap()).unwrap();
+        assert!(listing.ends_with("\n\na.txt\nsrc/\n"));
+        assert!(repo.show_output("HEAD:missing").is_err());
+
+        let oneline = repo
+            .show_output_with("HEAD", &PrettyFormat::Oneline)
+            .unwrap();
+        let oneline = String::from_utf8(oneline).unwrap();
+        assert!(oneline.starts_with(&format!(
+            "{} (HEAD -> master, tag: v2) second\ndiff --git",
+            &second.0[..7]
+        )));
+    }
+}
@@ -0,0 +1,441 @@
+use super::Repository;
+use crate::{EncodedSha, Error};
+use std::fmt::{self, Display, Formatter};
+use std::fs;
+use std::io::Write;
+use std::process::{self, Command, Output, Stdio};
+
+/// First line of an ssh signature; any other signature is an OpenPGP one
+const SSH_SIGNATURE: &str = "-----BEGIN SSH SIGNATURE-----";
+const PGP_SIGNATURE: &str = "-----BEGIN PGP SIGNATURE-----";
+
+/// Outcome of checking a signature
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SignatureStatus {
+    /// The signature is valid, made by the given signer
+    Good(String),
+    /// The signature claims to be by the given signer but does not match
+    /// the signed data
+    Bad(String),
+    /// The signature cannot be checked, e.g. for lack of the key, for the
+    /// given reason
+    Unknown(String),
+}
+
+impl Display for SignatureStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            SignatureStatus::Good(signer) => write!(f, "Good signature from \"{signer}\""),
+            SignatureStatus::Bad(signer) => write!(f, "BAD signature from \"{signer}\""),
+            SignatureStatus::Unknown(why) => write!(f, "Can't check signature: {why}"),
+        }
+    }
+}
+
+impl Repository {
+    /// Whether new commits are signed: as `sign` asks, or else as
+    /// `commit.gpgSign` says
+    pub(super) fn gpg_sign(&self, sign: Option<bool>) -> Result<bool, Error> {
+        match sign {
+            Some(sign) => Ok(sign),
+            None => Ok(self.config()?.get_bool("commit.gpgSign")?.unwrap_or(false)),
+        }
+    }
+
+    /// Makes a detached, armored signature of `payload` in the format
+    /// `gpg.format` names:
+    /// - `openpgp`, the default: with `gpg.program`, by default `gpg`, and
+    ///   the key `user.signingKey` or else the key of `signer`, given as
+    ///   `Name <email>`
+    /// - `ssh`: with `gpg.ssh.program`, by default `ssh-keygen`, and the
+    ///   private key file `user.signingKey`
+    pub(super) fn sign_payload(&self, payload: &str, signer: &str) -> Result<String, Error> {
+        let config = self.config()?;
+        let key = config.get("user.signingKey");
+        let command = match config.get("gpg.format").unwrap_or("openpgp") {
+            "openpgp" => {
+                let mut command = Command::new(config.get("gpg.program").unwrap_or("gpg"));
+                command.args(["--status-fd=2", "-bsau", key.unwrap_or(signer)]);
+                command
+            }
+            "ssh" => {
+                let key = key.ok_or("user.signingKey needs to be set for ssh signing")?;
+                let mut command =
+                    Command::new(config.get("gpg.ssh.program").unwrap_or("ssh-keygen"));
+                command.args(["-Y", "sign", "-n", "git", "-f", key]);
+                command
+            }
+            format => return Err(format!("unsupported value for gpg.format: {format}").into()),
+        };
+        let program = command.get_program().to_string_lossy().into_owned();
+        let output = run_with_input(command, payload)?;
+        let signature = String::from_utf8_lossy(&output.stdout);
+        if !output.status.success() || signature.trim().is_empty() {
+            return Err(format!(
+                "{program} failed to sign the data\n{}",
+                String::from_utf8_lossy(&output.stderr).trim_end()
+            )
+            .into());
+        }
+        Ok(signature.into_owned())
+    }
+
+    /// Checks the signature of the commit `rev`, like `git verify-commit`
+    ///
+    /// # Returns
+    /// `None` if the commit is not signed
+    pub fn verify_commit(&self, rev: &str) -> Result<Option<SignatureStatus>, Error> {
+        let sha = self.resolve_commit(rev)?;
+        self.commit_signature(&sha)
+    }
+
+    /// Checks the signature of the annotated tag `name`, like
+    /// `git verify-tag`
+    ///
+    /// # Returns
+    /// `None` if the tag is not signed
+    pub fn verify_tag(&self, name: &str) -> Result<Option<SignatureStatus>, Error> {
+        let sha = self
+            .load_tag(name)
+            .ok_or_else(|| format!("tag '{name}' not found."))?;
+        if self.read_tag(&sha).is_none() {
+            return Err(format!("{name}: cannot verify a non-tag object").into());
+        }
+        match split_tag_signature(&self.raw_content(&sha)?) {
+            Some((payload, signature)) => Ok(Some(self.verify_signature(&payload, &signature)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Checks the signature of the commit `sha`, `None` if it has none
+    pub(super) fn commit_signature(
+        &self,
+        sha: &EncodedSha,
+    ) -> Result<Option<SignatureStatus>, Error> {
+        match split_commit_signature(&self.raw_content(sha)?) {
+            Some((payload, signature)) => Ok(Some(self.verify_signature(&payload, &signature)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// The content of the object `sha`, exactly as it was signed
+    fn raw_content(&self, sha: &EncodedSha) -> Result<String, Error> {
+        let data = self.obj_db.retrieve(sha)?;
+        let start = data
+            .iter()
+            .position(|&byte| byte == 0)
+            .map_or(0, |nul| nul + 1);
+        Ok(String::from_utf8_lossy(&data[start..]).into_owned())
+    }
+
+    /// Checks that `signature` signs `payload`. OpenPGP signatures are
+    /// checked with `gpg.program` against the keyring; ssh signatures with
+    /// `gpg.ssh.program` against the keys of `gpg.ssh.allowedSignersFile`.
+    fn verify_signature(&self, payload: &str, signature: &str) -> Result<SignatureStatus, Error> {
+        let config = self.config()?;
+        let ssh = signature.starts_with(SSH_SIGNATURE);
+        let allowed_signers = config.get("gpg.ssh.allowedSignersFile");
+        if ssh && allowed_signers.is_none() {
+            return Ok(SignatureStatus::Unknown(
+                "gpg.ssh.allowedSignersFile needs to be configured for ssh signature verification"
+                    .into(),
+            ));
+        }
+        let signature_file = self.git_dir.join(format!("verify-{}.sig", process::id()));
+        fs::write(&signature_file, signature)?;
+        let status = if let Some(allowed_signers) = allowed_signers.filter(|_| ssh) {
+            let program = config.get("gpg.ssh.program").unwrap_or("ssh-keygen");
+            let mut find = Command::new(program);
+            find.args(["-Y", "find-principals", "-f", allowed_signers, "-s"])
+                .arg(&signature_file);
+            let found = run_with_input(find, "");
+            let principal = found.as_ref().ok().and_then(|output| {
+                let stdout = String::from_utf8_lossy(&output.stdout);
+                let principal = stdout.lines().next()?.trim().to_string();
+                (output.status.success() && !principal.is_empty()).then_some(principal)
+            });
+            match principal {
+                Some(principal) => {
+                    let mut verify = Command::new(program);
+                    verify
+                        .args(["-Y", "verify", "-f", allowed_signers, "-I", &principal])
+                        .args(["-n", "git", "-s"])
+                        .arg(&signature_file);
+                    run_with_input(verify, payload).map(|output| {
+                        if output.status.success() {
+                            SignatureStatus::Good(principal)
+                        } else {
+                            SignatureStatus::Bad(principal)
+                        }
+                    })
+                }
+                None => found.map(|_| {
+                    SignatureStatus::Unknown("no principal matched the signing key".into())
+                }),
+            }
+        } else {
+            let mut verify = Command::new(config.get("gpg.program").unwrap_or("gpg"));
+            verify
+                .args(["--status-fd=1", "--verify"])
+                .arg(&signature_file)
+                .arg("-");
+            run_with_input(verify, payload).map(|output| gpg_status(&output))
+        };
+        let _ = fs::remove_file(&signature_file);
+        status
+    }
+}
+
+/// Runs `command` with `input` on its standard input, collecting what it
+/// prints
+fn run_with_input(mut command: Command, input: &str) -> Result<Output, Error> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|why| format!("cannot run {program}: {why}"))?;
+    // A program that stops reading early is told by its exit status
+    let _ = child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(input.as_bytes());
+    Ok(child.wait_with_output()?)
+}
+
+/// Reads the outcome of `gpg --verify` from its `--status-fd` lines, like
+/// `[GNUPG:] GOODSIG <key id> <user id>`
+fn gpg_status(output: &Output) -> SignatureStatus {
+    let status = String::from_utf8_lossy(&output.stdout);
+    for line in status.lines() {
+        let Some((keyword, args)) = line
+            .strip_prefix("[GNUPG:] ")
+            .and_then(|rest| rest.split_once(' '))
+        else {
+            continue;
+        };
+        let signer = args
+            .split_once(' ')
+            .map_or(args, |(_, user)| user)
+            .to_string();
+        match keyword {
+            "GOODSIG" => return SignatureStatus::Good(signer),
+            "BADSIG" => return SignatureStatus::Bad(signer),
+            _ => {}
+        }
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let why = stderr.lines().last().unwrap_or("no public key");
+    SignatureStatus::Unknown(why.to_string())
+}
+
+/// Splits a raw commit into the data its `gpgsig` header signs, which is
+/// the commit without that header, and the signature
+fn split_commit_signature(content: &str) -> Option<(String, String)> {
+    let (headers, message) = content.split_once("\n\n").unwrap_or((content, ""));
+    let mut payload = String::new();
+    let mut signature: Option<String> = None;
+    let mut in_signature = false;
+    for line in headers.split('\n') {
+        if in_signature
+            && let Some(signature) = &mut signature
+            && let Some(continuation) = line.strip_prefix(' ')
+        {
+            signature.push_str(continuation);
+            signature.push('\n');
+            continue;
+        }
+        in_signature = false;
+        if let Some(first) = line.strip_prefix("gpgsig ") {
+            signature = Some(format!("{first}\n"));
+            in_signature = true;
+        } else {
+            payload.push_str(line);
+            payload.push('\n');
+        }
+    }
+    payload.push('\n');
+    payload.push_str(message);
+    Some((payload, signature?))
+}
+
+/// Splits a raw tag at the signature appended to its message
+fn split_tag_signature(content: &str) -> Option<(String, String)> {
+    let start = [PGP_SIGNATURE, SSH_SIGNATURE]
+        .iter()
+        .filter_map(|marker| content.find(&format!("\n{marker}")))
+        .min()?
+        + 1;
+    Some((content[..start].to_string(), content[start..].to_string()))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::repo::{CommitOptions, init_test_repo};
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use tempfile::TempDir;
+
+    #[test]
+    fn signed_commit_carries_the_signature() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        // A stand-in for gpg that signs everything the same way
+        let gpg = repo.git_dir.join("fake-gpg");
+        fs::write(
+            &gpg,
+            "#!/bin/sh\ncat > /dev/null\n\
+             printf '%s\\n' '-----BEGIN PGP SIGNATURE-----' '' sig '-----END PGP SIGNATURE-----'\n",
+        )
+        .unwrap();
+        fs::set_permissions(&gpg, fs::Permissions::from_mode(0o755)).unwrap();
+        repo.set_config("gpg.program", gpg.to_str().unwrap())
+            .unwrap();
+        let file = repo.dir.join("a.txt");
+        let stage = |content: &str| {
+            fs::write(&file, content).unwrap();
+            repo.add(&vec![file.to_str().unwrap()]).unwrap();
+        };
+
+        stage("a");
+        let options = CommitOptions {
+            sign: Some(true),
+            ..CommitOptions::default()
+        };
+        let sha = repo
+            .commit_with(Some("signed"), &[] as &[&str], options)
+            .unwrap();
+        let commit = repo.read_commit(&sha).unwrap();
+        assert_eq!(
+            commit.get_signature(),
+            Some("-----BEGIN PGP SIGNATURE-----\n\nsig\n-----END PGP SIGNATURE-----")
+        );
+        assert_eq!(commit.get_message(), "signed");
+
+        // commit.gpgSign signs by default, so a failing gpg fails the commit
+        repo.set_config("commit.gpgSign", true).unwrap();
+        repo.set_config("gpg.program", "false").unwrap();
+        stage("b");
+        assert!(repo.commit("unsigned").is_err());
+        let options = CommitOptions {
+            sign: Some(false),
+            ..CommitOptions::default()
+        };
+        let sha = repo
+            .commit_with(Some("unsigned"), &[] as &[&str], options)
+            .unwrap();
+        assert!(repo.read_commit(&sha).unwrap().get_signature().is_none());
+    }
+
+    #[test]
+    fn ssh_signature_uses_the_signing_key() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        // A stand-in for ssh-keygen that signs with its arguments
+        let ssh_keygen = repo.git_dir.join("fake-ssh-keygen");
+        fs::write(
+            &ssh_keygen,
+            "#!/bin/sh\ncat > /dev/null\n\
+             printf '%s\\n' '-----BEGIN SSH SIGNATURE-----' \"$*\" '-----END SSH SIGNATURE-----'\n",
+        )
+        .unwrap();
+        fs::set_permissions(&ssh_keygen, fs::Permissions::from_mode(0o755)).unwrap();
+        repo.set_config("gpg.format", "ssh").unwrap();
+        repo.set_config("gpg.ssh.program", ssh_keygen.to_str().unwrap())
+            .unwrap();
+        repo.set_config("commit.gpgSign", true).unwrap();
+        let file = repo.dir.join("a.txt");
+        fs::write(&file, "a").unwrap();
+        repo.add(&vec![file.to_str().unwrap()]).unwrap();
+
+        // The key is not guessed from the identity
+        assert!(repo.commit("signed").is_err());
+        repo.set_config("user.signingKey", "/keys/id_ed25519")
+            .unwrap();
+        let sha = repo.commit("signed").unwrap();
+        assert_eq!(
+            repo.read_commit(&sha).unwrap().get_signature(),
+            Some(
+                "-----BEGIN SSH SIGNATURE-----\n\
+                 -Y sign -n git -f /keys/id_ed25519\n\
+                 -----END SSH SIGNATURE-----"
+            )
+        );
+    }
+
+    #[test]
+    fn signatures_are_checked_against_the_unsigned_commit() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        // A stand-in for gpg and ssh-keygen whose signatures only fail for
+        // tampered commits, and for commits still holding their signature
+        let gpg = repo.git_dir.join("fake-gpg");
+        fs::write(
+            &gpg,
+            "#!/bin/sh\n\
+             if [ \"$2\" != --verify ]; then\n\
+             cat > /dev/null\n\
+             if [ \"$1\" = -Y ]; then echo '-----BEGIN SSH SIGNATURE-----'\n\
+             else echo '-----BEGIN PGP SIGNATURE-----'; fi\n\
+             exit\n\
+             fi\n\
+             if grep -q -e gpgsig -e tampered; then\n\
+             echo '[GNUPG:] BADSIG 1234 Alice <alice@example.com>'\n\
+             else\n\
+             echo '[GNUPG:] GOODSIG 1234 Alice <alice@example.com>'\n\
+             fi\n",
+        )
+        .unwrap();
+        fs::set_permissions(&gpg, fs::Permissions::from_mode(0o755)).unwrap();
+        repo.set_config("gpg.program", gpg.to_str().unwrap())
+            .unwrap();
+        let file = repo.dir.join("a.txt");
+        let commit = |content: &str, message: &str| {
+            fs::write(&file, content).unwrap();
+            repo.add(&vec![file.to_str().unwrap()]).unwrap();
+            repo.commit(message).unwrap()
+        };
+
+        commit("a", "unsigned");
+        assert_eq!(repo.verify_commit("HEAD").unwrap(), None);
+        repo.set_config("commit.gpgSign", true).unwrap();
+        commit("b", "signed");
+        assert_eq!(
+            repo.verify_commit("HEAD").unwrap(),
+            Some(SignatureStatus::Good("Alice <alice@example.com>".into()))
+        );
+        commit("c", "tampered");
+        assert_eq!(
+            repo.verify_commit("HEAD").unwrap(),
+            Some(SignatureStatus::Bad("Alice <alice@example.com>".into()))
+        );
+
+        // ssh signatures need the allowed signers
+        repo.set_config("gpg.format", "ssh").unwrap();
+        repo.set_config("gpg.ssh.program", gpg.to_str().unwrap())
+            .unwrap();
+        repo.set_config("user.signingKey", "/keys/id_ed25519")
+            .unwrap();
+        commit("d", "ssh");
+        assert!(matches!(
+            repo.verify_commit("HEAD").unwrap(),
+            Some(SignatureStatus::Unknown(_))
+        ));
+    }
+
+    #[test]
+    fn tag_signature_follows_the_message() {
+        let content = "object 1234\ntype commit\ntag v1\n\nRelease\n\
+                       -----BEGIN PGP SIGNATURE-----\nsig\n-----END PGP SIGNATURE-----\n";
+        let (payload, signature) = split_tag_signature(content).unwrap();
+        assert_eq!(payload, "object 1234\ntype commit\ntag v1\n\nRelease\n");
+        assert_eq!(
+            signature,
+            "-----BEGIN PGP SIGNATURE-----\nsig\n-----END PGP SIGNATURE-----\n"
+        );
+        assert!(split_tag_signature("object 1234\n\nRelease\n").is_none());
+    }
+}
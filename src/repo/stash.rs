@@ -0,0 +1,156 @@
+use super::Repository;
+use super::reflog::ReflogEntry;
+use crate::{EncodedSha, Error};
+use std::fs;
+use std::io;
+
+/// Ref holding the most recent stash; older ones live in its reflog
+pub const STASH_REF: &str = "refs/stash";
+
+impl Repository {
+    /// Stashes the changes of the index and the tracked files, then resets
+    /// both to HEAD. The stash is titled `On <branch>: <message>`, or
+    /// `WIP on <branch>: <HEAD>` without a message.
+    pub fn stash_push(&self, message: Option<&str>) -> Result<(), Error> {
+        let Some(stash) = self.write_stash(message)? else {
+            println!("No local changes to save");
+            return Ok(());
+        };
+        let reason = self.read_commit(&stash)?.get_message().to_string();
+        self.save_stash_ref(&stash, &reason)
+            .map_err(|why| format!("cannot save stash: {why}"))?;
+        println!("Saved working directory and index state {reason}");
+        Ok(())
+    }
+
+    /// Lists the stashes, most recent first, as `stash@{n}: <title>`
+    pub fn stash_list(&self) -> Result<(), Error> {
+        for (n, entry) in self.stashes().iter().enumerate() {
+            println!("stash@{{{n}}}: {}", entry.reason);
+        }
+        Ok(())
+    }
+
+    /// Applies a stash (default the most recent) on top of HEAD. With `pop`,
+    /// it is dropped afterwards unless applying it caused conflicts.
+    pub fn stash_apply(&self, stash: Option<&str>, pop: bool) -> Result<(), Error> {
+        let (n, entry) = self.find_stash(stash)?;
+        let head = self.get_current_commit();
+        let head_index = self.commit_index(head.as_ref())?;
+        if !self.worktree_is_clean(&head_index)? {
+            return Err(Error::UncommittedChanges);
+        }
+        let stash_sha = entry.new.clone().ok_or("corrupt stash log")?;
+        let conflicts = self.merge_stash(&stash_sha)?;
+        if !conflicts.is_empty() {
+            println!("The stash entry is kept in case you need it again.");
+        } else if pop {
+            self.drop_stash(n)?;
+            println!("Dropped stash@{{{n}}} ({stash_sha})");
+        }
+        Ok(())
+    }
+
+    /// Deletes a stash (default the most recent)
+    pub fn stash_drop(&self, stash: Option<&str>) -> Result<(), Error> {
+        let (n, entry) = self.find_stash(stash)?;
+        self.drop_stash(n)?;
+        println!(
+            "Dropped stash@{{{n}}} ({})",
+            entry.new.map(|sha| sha.0).unwrap_or_default()
+        );
+        Ok(())
+    }
+
+    /// The stash log, most recent first
+    fn stashes(&self) -> Vec<ReflogEntry> {
+        let mut stashes = self.reflog(STASH_REF).unwrap_or_default();
+        stashes.reverse();
+        stashes
+    }
+
+    /// Resolves `stash@{n}` or `n` to its position and log entry
+    fn find_stash(&self, stash: Option<&str>) -> Result<(usize, ReflogEntry), String> {
+        let stash = stash.unwrap_or("stash@{0}");
+        let n = stash
+            .strip_prefix("stash@{")
+            .and_then(|rest| rest.strip_suffix('}'))
+            .unwrap_or(stash)
+            .parse::<usize>()
+            .map_err(|_| format!("'{stash}' is not a stash reference"))?;
+        let stashes = self.stashes();
+        if stashes.is_empty() {
+            return Err("no stash entries found".to_string());
+        }
+        stashes
+            .into_iter()
+            .nth(n)
+            .map(|entry| (n, entry))
+            .ok_or_else(|| format!("stash@{{{n}}} does not exist"))
+    }
+
+    /// Removes the `n`th most recent stash, pointing `refs/stash` at the
+    /// next one or deleting it when none is left
+    fn drop_stash(&self, n: usize) -> io::Result<()> {
+        let mut stashes = self.stashes();
+        stashes.remove(n);
+        stashes.reverse();
+        self.rewrite_reflog(STASH_REF, &stashes)?;
+        let path = self.git_dir.join(STASH_REF);
+        match stashes.last().and_then(|entry| entry.new.as_ref()) {
+            Some(top) => fs::write(path, format!("{top}\n")),
+            None => fs::remove_file(path),
+        }
+    }
+
+    /// Points `refs/stash` at a new stash and logs it
+    fn save_stash_ref(&self, stash: &EncodedSha, reason: &str) -> io::Result<()> {
+        let old = self.stashes().first().and_then(|entry| entry.new.clone());
+        fs::write(self.git_dir.join(STASH_REF), format!("{stash}\n"))?;
+        self.append_reflog(STASH_REF, old.as_ref(), Some(stash), reason)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::Index;
+    use crate::repo::init_test_repo;
+    use tempfile::TempDir;
+
+    #[test]
+    fn push_list_pop_and_drop() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        let a = repo.dir.join("a.txt");
+        fs::write(&a, "a\n").unwrap();
+        repo.add(&vec![a.to_str().unwrap()]).unwrap();
+        repo.commit("first").unwrap();
+
+        fs::write(&a, "one\n").unwrap();
+        repo.stash_push(None).unwrap();
+        assert_eq!(fs::read_to_string(&a).unwrap(), "a\n");
+        fs::write(&a, "two\n").unwrap();
+        repo.add(&vec![a.to_str().unwrap()]).unwrap();
+        repo.stash_push(Some("second")).unwrap();
+
+        let stashes = repo.stashes();
+        assert_eq!(stashes.len(), 2);
+        assert_eq!(stashes[0].reason, "On master: second");
+        assert!(stashes[1].reason.starts_with("WIP on master: "));
+
+        // The staged change comes back staged
+        repo.stash_apply(None, true).unwrap();
+        assert_eq!(fs::read_to_string(&a).unwrap(), "two\n");
+        let index = Index::load(&repo.get_index_path()).unwrap();
+        let head = repo
+            .commit_index(repo.get_current_commit().as_ref())
+            .unwrap();
+        assert_ne!(index.get_sha1("a.txt"), head.get_sha1("a.txt"));
+        assert_eq!(repo.stashes().len(), 1);
+
+        repo.stash_drop(Some("stash@{0}")).unwrap();
+        assert!(repo.stashes().is_empty());
+        assert!(!repo.git_dir.join(STASH_REF).exists());
+    }
+}
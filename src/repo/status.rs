@@ -0,0 +1,262 @@
+use super::diff::{Snapshot, diff_files};
+use super::merge_tree::ConflictKind;
+use super::pathspec::{matches_pathspec, normalize_pathspecs};
+use super::{HEADS_DIR, Head, IndexDiffType, REFS_DIR, Repository};
+use crate::index::Index;
+use crate::object::FileMode;
+use crate::{EncodedSha, Error};
+use std::path::Path;
+
+/// How a path changed from one of HEAD, the index and the working tree to
+/// the next
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    /// The path is new
+    Added,
+    /// The path is gone
+    Deleted,
+    /// The path is on both sides with different content
+    Modified,
+}
+
+/// A changed path of a [`StatusReport`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatusEntry {
+    pub path: String,
+    pub kind: ChangeKind,
+    /// Whether the path is a submodule, which changes by the commit
+    /// checked out in it
+    pub submodule: bool,
+}
+
+/// The state of the working tree and the index, see
+/// [`Repository::status_report`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StatusReport {
+    /// The branch checked out; `None` if HEAD is detached
+    pub branch: Option<String>,
+    /// The commit checked out; `None` before the first commit
+    pub head: Option<EncodedSha>,
+    /// Changes of the index from HEAD, sorted by path
+    pub staged: Vec<StatusEntry>,
+    /// Changes of the working tree from the index, sorted by path
+    pub unstaged: Vec<StatusEntry>,
+    /// Files the index does not track and that are not ignored, sorted by
+    /// path
+    pub untracked: Vec<String>,
+    /// Paths a merge left unmerged, with how they conflict
+    pub conflicted: Vec<(String, ConflictKind)>,
+}
+
+impl Repository {
+    /// Prints the result of [`Self::status_report`] like `git status`,
+    /// limited to the paths matching `paths` if any are given
+    pub fn status<S: AsRef<str>>(&self, paths: &[S]) -> Result<(), Error> {
+        let pathspecs = normalize_pathspecs(paths);
+        let report = self.status_report()?;
+        match (&report.branch, &report.head) {
+            (Some(branch), _) => println!("On branch {branch}"),
+            (None, Some(head)) => println!("HEAD detached at {head}"),
+            (None, None) => (),
+        }
+        self.print_operations(!report.conflicted.is_empty());
+        if report.head.is_none() {
+            println!("No commits yet");
+        }
+        let selected = |path: &String| matches_pathspec(path, &pathspecs);
+        for (path, kind) in report.conflicted.iter().filter(|(path, _)| selected(path)) {
+            println!("Unmerged: {}: {path}", kind.describe());
+        }
+        for entry in report.staged.iter().filter(|entry| selected(&entry.path)) {
+            match entry.kind {
+                ChangeKind::Added => println!("New: {}", entry.path),
+                ChangeKind::Deleted => println!("Deleted: {}", entry.path),
+                ChangeKind::Modified => println!("Modified: {}", entry.path),
+            }
+        }
+        for entry in report.unstaged.iter().filter(|entry| selected(&entry.path)) {
+            match entry.kind {
+                ChangeKind::Modified if entry.submodule => {
+                    println!("Modified: {} (new commits)", entry.path)
+                }
+                ChangeKind::Added => println!("Not staged: new: {}", entry.path),
+                ChangeKind::Deleted => println!("Not staged: deleted: {}", entry.path),
+                ChangeKind::Modified => println!("Not staged: modified: {}", entry.path),
+            }
+        }
+        for path in report.untracked.iter().filter(|path| selected(path)) {
+            println!("Untracked: {path}");
+        }
+        Ok(())
+    }
+
+    /// Compares HEAD, the index and the working tree: what is staged to be
+    /// committed, what changed in the working tree and is not staged, the
+    /// untracked files and the unmerged paths. Unmerged paths are not
+    /// reported as staged or unstaged changes.
+    pub fn status_report(&self) -> Result<StatusReport, Error> {
+        let (branch, head) = match self.get_head().ok_or("Failed to fetch head")? {
+            Head::Symbolic(path) => {
+                let branch = path
+                    .strip_prefix(Path::new(REFS_DIR).join(HEADS_DIR))
+                    .unwrap_or(&path)
+                    .to_string_lossy()
+                    .replace('\\', "/");
+                // The initial branch has no file until it is first saved
+                let head = self
+                    .load_branch(&branch)
+                    .and_then(|branch| branch.commit_sha);
+                (Some(branch), head)
+            }
+            Head::Detached(sha) => (None, Some(sha)),
+        };
+        let index = self
+            .load_index_or_empty()
+            .map_err(|why| format!("cannot read index: {why}"))?;
+        let head_index = match &head {
+            Some(sha) => self.read_tree(&self.read_commit(sha)?.get_tree_sha())?,
+            None => Index::new(),
+        };
+        let is_submodule = |path: &str| {
+            [&index, &head_index]
+                .iter()
+                .any(|index| index.get_mode(path) == Some(FileMode::Gitlink))
+        };
+
+        let conflicted = index
+            .conflicts()
+            .iter()
+            .map(|(path, stages)| (path.clone(), stages.kind()))
+            .collect();
+        let mut staged: Vec<StatusEntry> = self
+            .diff_index(&head_index, &index)
+            .into_iter()
+            .filter(|(path, _)| index.get_conflict(path).is_none())
+            .filter_map(|(path, status)| {
+                let kind = match status {
+                    IndexDiffType::LeftOnly => ChangeKind::Deleted,
+                    IndexDiffType::RightOnly => ChangeKind::Added,
+                    IndexDiffType::Modified => ChangeKind::Modified,
+                    IndexDiffType::Unmodified => return None,
+                };
+                Some(StatusEntry {
+                    submodule: is_submodule(&path),
+                    path,
+                    kind,
+                })
+            })
+            .collect();
+        staged.sort_by(|a, b| a.path.cmp(&b.path));
+
+        let mut unstaged = Vec::new();
+        let staged_files = self.snapshot_files(Snapshot::Index)?;
+        let worktree_files = self.snapshot_files(Snapshot::Worktree)?;
+        for pair in diff_files(&staged_files, &worktree_files) {
+            let submodule = index.get_mode(&pair.path) == Some(FileMode::Gitlink);
+            let kind = match (&pair.old, &pair.new) {
+                (None, _) => ChangeKind::Added,
+                // A submodule that is not cloned is not a change
+                (_, None) if submodule => continue,
+                (_, None) => ChangeKind::Deleted,
+                _ => ChangeKind::Modified,
+            };
+            unstaged.push(StatusEntry {
+                path: pair.path,
+                kind,
+                submodule,
+            });
+        }
+
+        Ok(StatusReport {
+            branch,
+            head,
+            staged,
+            unstaged,
+            untracked: self.untracked_files(false)?,
+            conflicted,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::{FastForward, init_test_repo};
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn report_separates_staged_unstaged_and_untracked_changes() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        let path = |name: &str| repo.dir.join(name).to_str().unwrap().to_string();
+        let entry = |path: &str, kind| StatusEntry {
+            path: path.to_string(),
+            kind,
+            submodule: false,
+        };
+        for name in ["a.txt", "b.txt", "c.txt"] {
+            fs::write(path(name), "base\n").unwrap();
+        }
+        repo.add(&vec![path("a.txt"), path("b.txt"), path("c.txt")])
+            .unwrap();
+        let report = repo.status_report().unwrap();
+        assert_eq!(report.branch.as_deref(), Some("master"));
+        assert_eq!(report.head, None);
+        assert_eq!(report.staged.len(), 3);
+        let base = repo.commit("base").unwrap();
+
+        fs::write(path("a.txt"), "staged\n").unwrap();
+        fs::write(path("new.txt"), "new\n").unwrap();
+        repo.add(&vec![path("a.txt"), path("new.txt")]).unwrap();
+        fs::write(path("a.txt"), "staged, then changed\n").unwrap();
+        fs::remove_file(path("b.txt")).unwrap();
+        fs::write(path("untracked.txt"), "?\n").unwrap();
+        let report = repo.status_report().unwrap();
+        assert_eq!(report.head, Some(base));
+        assert_eq!(
+            report.staged,
+            [
+                entry("a.txt", ChangeKind::Modified),
+                entry("new.txt", ChangeKind::Added)
+            ]
+        );
+        assert_eq!(
+            report.unstaged,
+            [
+                entry("a.txt", ChangeKind::Modified),
+                entry("b.txt", ChangeKind::Deleted)
+            ]
+        );
+        assert_eq!(report.untracked, ["untracked.txt"]);
+        assert!(report.conflicted.is_empty());
+    }
+
+    #[test]
+    fn unmerged_paths_are_only_reported_as_conflicts() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        let file = repo.dir.join("a.txt");
+        let commit_file = |content: &str, message: &str| {
+            fs::write(&file, content).unwrap();
+            repo.add(&vec![file.to_str().unwrap()]).unwrap();
+            repo.commit(message).unwrap()
+        };
+        commit_file("base\n", "base");
+        repo.branch("topic").unwrap();
+        commit_file("ours\n", "ours");
+        repo.checkout("topic", false).unwrap();
+        commit_file("theirs\n", "theirs");
+        repo.checkout("master", false).unwrap();
+        assert!(!repo.merge("topic", false, FastForward::Allow).unwrap());
+
+        let report = repo.status_report().unwrap();
+        assert_eq!(
+            report.conflicted,
+            [("a.txt".to_string(), ConflictKind::Content)]
+        );
+        assert!(report.staged.is_empty());
+        assert!(report.unstaged.is_empty());
+        assert!(report.untracked.is_empty());
+    }
+}
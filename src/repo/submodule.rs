@@ -0,0 +1,315 @@
+use super::{GIT_DIR, Head, Repository};
+use crate::config::Config;
+use crate::index::Index;
+use crate::object::FileMode;
+use crate::{EncodedSha, Error};
+use std::path::{self, Path, PathBuf};
+
+/// Tracked file listing the submodules, in config syntax:
+///
+/// ```text
+/// [submodule "lib"]
+///     path = lib
+///     url = ../lib
+/// ```
+const GITMODULES_FILE: &str = ".gitmodules";
+
+/// A submodule: a repository checked out inside the working tree, which
+/// the superproject records by commit
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Submodule {
+    /// Name in `.gitmodules`, the path it was added at by default
+    pub name: String,
+    /// Path in the working tree
+    pub path: String,
+    /// `submodule.<name>.url` of the repository config, set once the
+    /// submodule is initialized
+    pub url: Option<String>,
+    /// Commit the index records
+    pub commit: EncodedSha,
+    /// Commit checked out in the submodule, if it was cloned
+    pub head: Option<EncodedSha>,
+}
+
+impl Repository {
+    /// Clones the repository at `url` into `path` and records it as a
+    /// submodule, like `git submodule add`: `.gitmodules` gets its path and
+    /// URL, and the index the commit checked out in it. The submodule is
+    /// initialized right away.
+    ///
+    /// # Arguments
+    /// * `url` - A local repository or bundle; relative URLs are taken
+    ///   relative to the top of the working tree
+    /// * `path` - Where to put the submodule, named after `url` by default
+    pub fn submodule_add(&self, url: &str, path: Option<&str>) -> Result<Submodule, Error> {
+        let path = match path {
+            Some(path) => path.trim_end_matches('/').to_string(),
+            None => {
+                let name = Path::new(url.trim_end_matches('/'))
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                let name = name.strip_suffix(".git").unwrap_or(&name);
+                name.strip_suffix(".bundle").unwrap_or(name).to_string()
+            }
+        };
+        if path.is_empty() {
+            return Err(format!("cannot tell a submodule path from '{url}'").into());
+        }
+        let gitmodules_path = self.dir.join(GITMODULES_FILE);
+        let mut gitmodules = Config::load(&gitmodules_path)?;
+        if self.load_index_or_empty()?.get_sha1(&path).is_some() {
+            return Err(format!("'{path}' already exists in the index").into());
+        }
+
+        Repository::clone(&self.submodule_source(url)?, &self.dir.join(&path))?;
+        gitmodules.set(&format!("submodule.{path}.path"), &path)?;
+        gitmodules.set(&format!("submodule.{path}.url"), url)?;
+        gitmodules.save(&gitmodules_path)?;
+        self.set_config(&format!("submodule.{path}.url"), url)?;
+        self.update_index(&gitmodules_path)?;
+        self.update_gitlink(&self.dir.join(&path))?;
+        self.submodules()?
+            .into_iter()
+            .find(|submodule| submodule.path == path)
+            .ok_or_else(|| format!("submodule '{path}' was not recorded").into())
+    }
+
+    /// Copies the URL of every submodule in `.gitmodules` into the
+    /// repository config, like `git submodule init`, so that
+    /// [`Self::submodule_update`] clones it. URLs already set are kept.
+    ///
+    /// # Returns
+    /// The names of the submodules initialized
+    pub fn submodule_init(&self) -> Result<Vec<String>, Error> {
+        let gitmodules = Config::load(&self.dir.join(GITMODULES_FILE))?;
+        let config = self.config()?;
+        let mut initialized = Vec::new();
+        for name in gitmodules.subsections("submodule") {
+            let key = format!("submodule.{name}.url");
+            let Some(url) = gitmodules.get(&key) else {
+                continue;
+            };
+            if config.get(&key).is_none() {
+                self.set_config(&key, url)?;
+                let path = gitmodules
+                    .get(&format!("submodule.{name}.path"))
+                    .unwrap_or(name);
+                println!("Submodule '{name}' ({url}) registered for path '{path}'");
+                initialized.push(name.to_string());
+            }
+        }
+        Ok(initialized)
+    }
+
+    /// Checks out the commit the index records in every initialized
+    /// submodule, cloning those not cloned yet and fetching those that
+    /// lack the commit, like `git submodule update`. HEAD of the
+    /// submodules is detached at the commit.
+    ///
+    /// # Returns
+    /// The paths of the submodules that were moved
+    pub fn submodule_update(&self) -> Result<Vec<String>, Error> {
+        let mut updated = Vec::new();
+        for submodule in self.submodules()? {
+            let Some(url) = &submodule.url else {
+                continue;
+            };
+            if submodule.head.as_ref() == Some(&submodule.commit) {
+                continue;
+            }
+            let dir = self.dir.join(&submodule.path);
+            let repo = match submodule.head {
+                Some(_) => Repository::open(&dir)?,
+                None => Repository::clone(&self.submodule_source(url)?, &dir)?,
+            };
+            if repo.read_commit(&submodule.commit).is_err() {
+                repo.fetch("origin")?;
+            }
+            let commit = &submodule.commit;
+            repo.read_commit(commit).map_err(|_| {
+                format!(
+                    "commit {commit} of submodule '{}' was not found in {url}",
+                    submodule.path
+                )
+            })?;
+            repo.move_head(
+                Head::Detached(commit.clone()),
+                Some(commit.clone()),
+                &commit.0,
+                false,
+            )?;
+            println!(
+                "Submodule path '{}': checked out '{commit}'",
+                submodule.path
+            );
+            updated.push(submodule.path);
+        }
+        Ok(updated)
+    }
+
+    /// Prints the state of every submodule like `git submodule status`: the
+    /// recorded commit and path, prefixed with `-` if the submodule is not
+    /// cloned and `+` if another commit is checked out in it
+    pub fn submodule_status(&self) -> Result<(), Error> {
+        for submodule in self.submodules()? {
+            let (prefix, commit) = match &submodule.head {
+                None => ('-', &submodule.commit),
+                Some(head) if *head != submodule.commit => ('+', head),
+                Some(head) => (' ', head),
+            };
+            println!("{prefix}{commit} {}", submodule.path);
+        }
+        Ok(())
+    }
+
+    /// The submodules the index records, sorted by path, matched up with
+    /// their `.gitmodules` entries
+    pub fn submodules(&self) -> Result<Vec<Submodule>, Error> {
+        let index = self.load_index_or_empty()?;
+        let gitmodules = Config::load(&self.dir.join(GITMODULES_FILE))?;
+        let config = self.config()?;
+        let names = gitmodules.subsections("submodule");
+        let mut submodules = Vec::new();
+        for (path, commit) in index.collect_entries() {
+            if index.get_mode(&path) != Some(FileMode::Gitlink) {
+                continue;
+            }
+            let name = names
+                .iter()
+                .find(|name| gitmodules.get(&format!("submodule.{name}.path")) == Some(&path))
+                .ok_or_else(|| format!("no submodule mapping found in .gitmodules for '{path}'"))?
+                .to_string();
+            submodules.push(Submodule {
+                url: config
+                    .get(&format!("submodule.{name}.url"))
+                    .map(str::to_string),
+                head: self.submodule_head(&path),
+                name,
+                path,
+                commit,
+            });
+        }
+        Ok(submodules)
+    }
+
+    /// Whether `dir` is a repository nested in the working tree
+    pub(super) fn is_submodule_dir(&self, dir: &Path) -> bool {
+        // A relative `dir` like `.` may well be the working tree itself
+        path::absolute(dir).is_ok_and(|abs_dir| abs_dir != self.dir) && dir.join(GIT_DIR).exists()
+    }
+
+    /// The commit checked out in the submodule at `path`, if it was cloned
+    pub(super) fn submodule_head(&self, path: &str) -> Option<EncodedSha> {
+        let dir = self.dir.join(path);
+        if !self.is_submodule_dir(&dir) {
+            return None;
+        }
+        Repository::open(&dir).ok()?.get_current_commit()
+    }
+
+    /// Stages the nested repository at `dir` as a submodule at the commit
+    /// checked out in it
+    pub(super) fn update_gitlink(&self, dir: &Path) -> Result<(), Error> {
+        let mut index = self.lock_index()?;
+        self.stage_gitlink(&mut index, dir)?;
+        Ok(index.commit()?)
+    }
+
+    /// Stages the nested repository at `dir` in `index`, see
+    /// [`Self::update_gitlink`]
+    pub(super) fn stage_gitlink(&self, index: &mut Index, dir: &Path) -> Result<(), Error> {
+        let path = self
+            .turn_relative_path_to_repo_dir(dir)?
+            .to_string_lossy()
+            .replace('\\', "/");
+        let commit = self
+            .submodule_head(&path)
+            .ok_or_else(|| format!("'{path}' does not have a commit checked out"))?;
+        index.update_entry_with_mode(&path, commit, FileMode::Gitlink);
+        Ok(())
+    }
+
+    /// Where to clone the submodule with `url` from
+    fn submodule_source(&self, url: &str) -> Result<PathBuf, Error> {
+        if url.contains("://") {
+            return Err(
+                format!("cannot reach {url}: only local repositories are supported").into(),
+            );
+        }
+        Ok(path::absolute(self.dir.join(url))?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::Tree;
+    use crate::repo::{FastForward, Snapshot, commit_file, init_test_repo};
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn submodules_are_recorded_by_commit_and_cloned_on_update() {
+        let temp_dir = TempDir::new().unwrap();
+        let lib_dir = temp_dir.path().join("lib");
+        fs::create_dir(&lib_dir).unwrap();
+        let lib = init_test_repo(&lib_dir);
+        let first = commit_file(&lib, "lib.rs", "one\n");
+
+        let app_dir = temp_dir.path().join("app");
+        fs::create_dir(&app_dir).unwrap();
+        let app = init_test_repo(&app_dir);
+        let submodule = app.submodule_add("../lib", Some("vendor/lib")).unwrap();
+        assert_eq!(submodule.commit, first);
+        assert_eq!(submodule.head, Some(first.clone()));
+        let recorded = app.commit("add lib").unwrap();
+        let tree = app.read_commit(&recorded).unwrap().get_tree_sha();
+        let vendor = app.read_tree(&tree).unwrap();
+        assert_eq!(vendor.get_mode("vendor/lib"), Some(FileMode::Gitlink));
+        let data = app.obj_db.retrieve(&tree).unwrap();
+        assert!(
+            Tree::deserialize(&data)
+                .unwrap()
+                .get_mode(".gitmodules")
+                .is_some()
+        );
+        assert!(app.fsck_report().unwrap().is_ok());
+        assert!(app.untracked_files(false).unwrap().is_empty());
+
+        // New commits in the submodule show up as one changed line
+        let second = commit_file(&lib, "lib.rs", "two\n");
+        let checkout = submodule_repo(&app);
+        checkout.fetch("origin").unwrap();
+        checkout
+            .merge("origin/master", false, FastForward::Allow)
+            .unwrap();
+        let diff = app
+            .unified_diff::<&str>(Snapshot::Index, Snapshot::Worktree, &[])
+            .unwrap();
+        assert!(diff.contains(&format!("-Subproject commit {first}")));
+        assert!(diff.contains(&format!("+Subproject commit {second}")));
+        app.add(&vec![app_dir.join("vendor/lib").to_str().unwrap()])
+            .unwrap();
+        app.commit("bump lib").unwrap();
+
+        // A clone gets an empty directory until the submodule is updated
+        let clone_dir = temp_dir.path().join("clone");
+        let clone = Repository::clone(&app_dir, &clone_dir).unwrap();
+        assert!(clone_dir.join("vendor/lib").is_dir());
+        assert!(clone.submodule_update().unwrap().is_empty());
+        assert_eq!(clone.submodules().unwrap()[0].head, None);
+        assert_eq!(clone.submodule_init().unwrap(), ["vendor/lib"]);
+        assert_eq!(clone.submodule_update().unwrap(), ["vendor/lib"]);
+        let submodule = &clone.submodules().unwrap()[0];
+        assert_eq!(submodule.head, Some(second.clone()));
+        assert_eq!(
+            fs::read_to_string(clone_dir.join("vendor/lib/lib.rs")).unwrap(),
+            "two\n"
+        );
+    }
+
+    fn submodule_repo(repo: &Repository) -> Repository {
+        Repository::open(&repo.dir.join("vendor/lib")).unwrap()
+    }
+}
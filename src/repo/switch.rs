@@ -0,0 +1,108 @@
+use super::{Branch, HEAD_FILE, HEADS_DIR, Head, REFS_DIR, Repository, check_branch_name};
+use crate::Error;
+use std::path::Path;
+
+impl Repository {
+    /// Switches to the branch `target`, like `git switch`. A branch only
+    /// one remote has is created to track it. Unlike `checkout`, a commit
+    /// is only accepted with `detach`, which detaches HEAD at it.
+    ///
+    /// Local changes are carried over, and the switch is refused when they
+    /// touch files that differ between the two commits.
+    pub fn switch(&self, target: &str, detach: bool) -> Result<(), Error> {
+        if detach {
+            let sha = self.resolve_commit(target)?;
+            return self.move_head(Head::Detached(sha.clone()), Some(sha), target, false);
+        }
+        let branch = match self.load_branch(target) {
+            Some(branch) => branch,
+            None => match self.branch_from_remote(target)? {
+                Some(branch) => branch,
+                None if self.resolve_commit(target).is_ok() => {
+                    return Err(format!(
+                        "a branch is expected, got commit '{target}'\n\
+                         hint: If you want to detach HEAD at the commit, try again with the --detach option."
+                    )
+                    .into());
+                }
+                None => return Err(Error::NoSuchBranch(target.to_string())),
+            },
+        };
+        let path = Path::new(REFS_DIR).join(HEADS_DIR).join(&branch.name);
+        if self.get_head() == Some(Head::Symbolic(path.clone())) {
+            println!("Already on '{target}'");
+            return Ok(());
+        }
+        self.move_head(Head::Symbolic(path), branch.commit_sha, target, false)
+    }
+
+    /// Creates the branch `name` at `start_point`, HEAD by default, and
+    /// switches to it, like `git switch -c`. The branch is not left behind
+    /// if the switch is refused.
+    pub fn switch_create(&self, name: &str, start_point: Option<&str>) -> Result<(), Error> {
+        check_branch_name(name)?;
+        if self.load_branch(name).is_some() {
+            return Err(Error::BranchExists(name.to_string()));
+        }
+        let commit_sha = match start_point {
+            Some(start_point) => Some(self.resolve_commit(start_point)?),
+            None => self.get_current_commit(),
+        };
+        let branch = Branch {
+            name: name.to_string(),
+            commit_sha: commit_sha.clone(),
+        };
+        let from = start_point.unwrap_or(HEAD_FILE);
+        self.save_branch(&branch, &format!("branch: Created from {from}"))?;
+        let head = Head::Symbolic(Path::new(REFS_DIR).join(HEADS_DIR).join(name));
+        if let Err(why) = self.move_head(head, commit_sha, name, false) {
+            self.remove_branch(name, "branch: deleted")?;
+            return Err(why);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::{commit_file, init_test_repo};
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn switch_carries_local_changes_and_refuses_conflicting_ones() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        let first = commit_file(&repo, "a.txt", "a\n");
+        commit_file(&repo, "b.txt", "b\n");
+        repo.switch_create("topic", Some(&first.0)).unwrap();
+        assert!(!repo.dir.join("b.txt").exists());
+        assert!(repo.switch_create("topic", None).is_err());
+
+        // Changes to files both branches share are carried over
+        fs::write(repo.dir.join("a.txt"), "local\n").unwrap();
+        repo.switch("master", false).unwrap();
+        assert_eq!(
+            fs::read_to_string(repo.dir.join("a.txt")).unwrap(),
+            "local\n"
+        );
+        assert!(repo.dir.join("b.txt").exists());
+
+        // Changes to files that differ between them are refused
+        fs::write(repo.dir.join("a.txt"), "a\n").unwrap();
+        fs::write(repo.dir.join("b.txt"), "local\n").unwrap();
+        assert!(matches!(
+            repo.switch("topic", false),
+            Err(Error::LocalChangesOverwritten(files)) if files == ["b.txt"]
+        ));
+        assert!(repo.switch_create("other", Some(&first.0)).is_err());
+        assert!(repo.load_branch("other").is_none());
+        fs::write(repo.dir.join("b.txt"), "b\n").unwrap();
+
+        // Commits need --detach
+        assert!(repo.switch(&first.0, false).is_err());
+        repo.switch(&first.0, true).unwrap();
+        assert_eq!(repo.get_head(), Some(Head::Detached(first)));
+    }
+}
@@ -0,0 +1,115 @@
+use super::update_ref::check_ref_name;
+use super::{HEAD_FILE, Head, Repository};
+use crate::Error;
+
+/// How many symbolic refs are followed before giving up on a loop
+const MAX_SYMREF_DEPTH: usize = 5;
+
+impl Repository {
+    /// The ref the symbolic ref `name` points to, like `git symbolic-ref
+    /// <name>`: `refs/heads/<branch>` for `HEAD` while a branch is
+    /// checked out
+    pub fn symbolic_ref(&self, name: &str) -> Result<String, Error> {
+        check_ref_name(name)?;
+        self.read_symbolic_ref(name)
+            .ok_or_else(|| format!("ref {name} is not a symbolic ref").into())
+    }
+
+    /// Points the symbolic ref `name` at the ref `target`, like
+    /// `git symbolic-ref <name> <target>`. `name` is `HEAD` or a full name
+    /// below refs/, such as `refs/remotes/origin/HEAD`; `target` is a full
+    /// name below refs/ that need not exist yet.
+    pub fn set_symbolic_ref(
+        &self,
+        name: &str,
+        target: &str,
+        reason: Option<&str>,
+    ) -> Result<(), Error> {
+        check_ref_name(name)?;
+        if target == HEAD_FILE {
+            return Err(format!("refusing to point {name} outside of refs/").into());
+        }
+        check_ref_name(target)?;
+        if name == target {
+            return Err(format!("refusing to point {name} at itself").into());
+        }
+        let reason = reason.unwrap_or("symbolic-ref");
+        Ok(self.save_symbolic_ref(name, target, reason)?)
+    }
+
+    /// The ref the symbolic ref `name` points to, or `None` if `name` is
+    /// not symbolic
+    pub(super) fn read_symbolic_ref(&self, name: &str) -> Option<String> {
+        match Head::load(&self.git_dir.join(name)).ok()? {
+            Head::Symbolic(target) => Some(target.to_string_lossy().replace('\\', "/")),
+            Head::Detached(_) => None,
+        }
+    }
+
+    /// Follows symbolic refs from `name` to the ref holding a value, which
+    /// is `name` itself unless it is symbolic
+    ///
+    /// # Returns
+    /// `None` if the symbolic refs form a loop
+    pub(super) fn follow_symbolic_ref(&self, name: &str) -> Option<String> {
+        let mut name = name.to_string();
+        for _ in 0..MAX_SYMREF_DEPTH {
+            match self.read_symbolic_ref(&name) {
+                Some(target) => name = target,
+                None => return Some(name),
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::repo::init_test_repo;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn symbolic_refs_point_at_other_refs() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        let file = repo.dir.join("a.txt");
+        fs::write(&file, "one\n").unwrap();
+        repo.add(&vec![file.to_str().unwrap()]).unwrap();
+        let first = repo.commit("first").unwrap();
+        assert_eq!(repo.symbolic_ref("HEAD").unwrap(), "refs/heads/master");
+
+        // A remote's default branch, followed when resolving and updating
+        repo.update_ref("refs/remotes/origin/main", "HEAD", None)
+            .unwrap();
+        repo.set_symbolic_ref("refs/remotes/origin/HEAD", "refs/remotes/origin/main", None)
+            .unwrap();
+        assert_eq!(
+            repo.symbolic_ref("refs/remotes/origin/HEAD").unwrap(),
+            "refs/remotes/origin/main"
+        );
+        assert_eq!(repo.resolve("origin/HEAD").unwrap(), first);
+        assert!(repo.symbolic_ref("refs/remotes/origin/main").is_err());
+        assert_eq!(repo.pack_refs().unwrap(), 2);
+        assert_eq!(repo.resolve("refs/remotes/origin/HEAD").unwrap(), first);
+
+        // Pointing HEAD at an unborn branch is logged like a checkout
+        repo.set_symbolic_ref("HEAD", "refs/heads/orphan", Some("orphan"))
+            .unwrap();
+        assert_eq!(repo.head_ref_name().as_deref(), Some("refs/heads/orphan"));
+        assert!(repo.resolve("HEAD").is_err());
+        assert_eq!(
+            repo.reflog("HEAD").unwrap().last().unwrap().reason,
+            "orphan"
+        );
+        assert!(repo.set_symbolic_ref("HEAD", "master", None).is_err());
+        assert!(repo.set_symbolic_ref("HEAD", "HEAD", None).is_err());
+
+        // Symbolic refs pointing at each other go nowhere
+        repo.set_symbolic_ref("refs/heads/a", "refs/heads/b", None)
+            .unwrap();
+        repo.set_symbolic_ref("refs/heads/b", "refs/heads/a", None)
+            .unwrap();
+        assert!(repo.update_ref("refs/heads/a", &first.0, None).is_err());
+    }
+}
@@ -0,0 +1,175 @@
+use chrono::{FixedOffset, Utc};
+use walkdir::WalkDir;
+
+use super::packed_refs::read_packed_refs;
+use super::{HEAD_FILE, REFS_DIR, Repository, check_branch_name};
+use crate::lock;
+use crate::object::{Author, ObjectType, Tag, determine_object_type};
+use crate::{EncodedSha, Error};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// Directory under refs/ holding the tags
+pub(super) const TAGS_DIR: &str = "tags";
+
+impl Repository {
+    /// Creates the tag `name` at `target`, HEAD by default, like `git tag`.
+    /// With a message the tag is annotated: it points at a tag object
+    /// recording the tagger and the message. Otherwise it is a lightweight
+    /// tag pointing straight at the commit.
+    ///
+    /// # Returns
+    /// The SHA1 the tag ref holds
+    pub fn tag_create(
+        &self,
+        name: &str,
+        target: Option<&str>,
+        message: Option<&str>,
+    ) -> Result<EncodedSha, Error> {
+        check_branch_name(name).map_err(|_| format!("'{name}' is not a valid tag name"))?;
+        if self.load_tag(name).is_some() {
+            return Err(format!("tag '{name}' already exists").into());
+        }
+        let rev = target.unwrap_or(HEAD_FILE);
+        let commit = self
+            .resolve_commit(rev)
+            .map_err(|_| Error::BadRevision(rev.to_string()))?;
+        let sha = match message {
+            Some(message) => {
+                let (tagger_name, tagger_email) = self.identity()?;
+                let offset = FixedOffset::east_opt(8 * 3600).unwrap();
+                let tagger = Author::new(
+                    &tagger_name,
+                    &tagger_email,
+                    Utc::now().with_timezone(&offset),
+                );
+                let tag = Tag::new(commit, ObjectType::Commit, name, tagger, message);
+                self.obj_db.store(&tag)?
+            }
+            None => commit,
+        };
+        self.save_tag(name, &sha)?;
+        Ok(sha)
+    }
+
+    /// Deletes the tag `name`, loose or packed, like `git tag -d`
+    pub fn tag_delete(&self, name: &str) -> Result<(), Error> {
+        if self.load_tag(name).is_none() {
+            return Err(format!("tag '{name}' not found.").into());
+        }
+        Ok(self.remove_tag(name)?)
+    }
+
+    /// Every tag by name with the SHA1 its ref holds, loose tags taking
+    /// precedence over packed ones
+    pub fn list_tags(&self) -> Result<BTreeMap<String, EncodedSha>, Error> {
+        let prefix = format!("{REFS_DIR}/{TAGS_DIR}/");
+        let mut tags: BTreeMap<String, EncodedSha> = read_packed_refs(&self.git_dir)
+            .into_iter()
+            .filter_map(|(name, sha)| Some((name.strip_prefix(&prefix)?.to_string(), sha)))
+            .collect();
+        let tags_dir = self.get_tags_dir();
+        for entry in WalkDir::new(&tags_dir).into_iter().filter_map(|e| e.ok()) {
+            let file_name = entry.file_name().to_string_lossy();
+            if !entry.file_type().is_file() || file_name.ends_with(lock::LOCK_SUFFIX) {
+                continue;
+            }
+            if let Ok(sha) = EncodedSha::from_str(fs::read_to_string(entry.path())?.trim()) {
+                let name = entry.path().strip_prefix(&tags_dir).unwrap();
+                tags.insert(name.to_string_lossy().replace('\\', "/"), sha);
+            }
+        }
+        Ok(tags)
+    }
+
+    /// Reads the tag given as `<name>` or `refs/tags/<name>`
+    pub(super) fn load_tag(&self, name: &str) -> Option<EncodedSha> {
+        let prefix = format!("{REFS_DIR}/{TAGS_DIR}/");
+        let name = name.strip_prefix(&prefix).unwrap_or(name);
+        match fs::read_to_string(self.get_tags_dir().join(name)) {
+            Ok(content) => EncodedSha::from_str(content.trim()).ok(),
+            Err(_) => self.packed_ref(&format!("{prefix}{name}")),
+        }
+    }
+
+    /// Reads the annotated tag object `sha`, or `None` if it is another
+    /// kind of object
+    pub(super) fn read_tag(&self, sha: &EncodedSha) -> Option<Tag> {
+        let data = self.obj_db.retrieve(sha).ok()?;
+        Tag::deserialize(&data).ok()
+    }
+
+    /// Follows annotated tags from `sha` down to the object they name
+    pub(super) fn peel_tag(&self, sha: &EncodedSha) -> (EncodedSha, ObjectType) {
+        let mut sha = sha.clone();
+        while let Some(tag) = self.read_tag(&sha) {
+            if tag.get_object_type() != ObjectType::Tag {
+                return (tag.get_object().clone(), tag.get_object_type());
+            }
+            sha = tag.get_object().clone();
+        }
+        let object_type = self
+            .obj_db
+            .retrieve(&sha)
+            .ok()
+            .and_then(|data| determine_object_type(&data).ok())
+            .unwrap_or(ObjectType::Commit);
+        (sha, object_type)
+    }
+
+    pub(super) fn get_tags_dir(&self) -> PathBuf {
+        self.git_dir.join(REFS_DIR).join(TAGS_DIR)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::init_test_repo;
+    use tempfile::TempDir;
+
+    #[test]
+    fn tags_resolve_to_commits_and_survive_pack_refs() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        let file = repo.dir.join("a.txt");
+        fs::write(&file, "one\n").unwrap();
+        repo.add(&vec![file.to_str().unwrap()]).unwrap();
+        let first = repo.commit("first").unwrap();
+
+        assert_eq!(repo.tag_create("light", None, None).unwrap(), first);
+        let annotated = repo
+            .tag_create("v1.0", Some("master"), Some("Release 1.0"))
+            .unwrap();
+        assert_ne!(annotated, first);
+        let tag = repo.read_tag(&annotated).unwrap();
+        assert_eq!(tag.get_name(), "v1.0");
+        assert_eq!(tag.get_message(), "Release 1.0");
+        assert_eq!(tag.get_object(), &first);
+        assert_eq!(repo.resolve_commit("v1.0"), Ok(first.clone()));
+        assert_eq!(repo.resolve_commit("refs/tags/light"), Ok(first.clone()));
+        assert!(repo.tag_create("v1.0", None, None).is_err());
+        assert!(repo.tag_create("bad..name", None, None).is_err());
+        assert!(repo.fsck_report().unwrap().is_ok());
+
+        // Packed annotated tags record the commit they peel to
+        repo.pack_refs().unwrap();
+        let packed = fs::read_to_string(repo.git_dir.join("packed-refs")).unwrap();
+        assert!(packed.contains(&format!("{annotated} refs/tags/v1.0\n^{first}\n")));
+        assert_eq!(
+            repo.list_tags().unwrap().into_iter().collect::<Vec<_>>(),
+            [
+                ("light".to_string(), first.clone()),
+                ("v1.0".to_string(), annotated)
+            ]
+        );
+        assert!(repo.fsck_report().unwrap().is_ok());
+
+        repo.tag_delete("v1.0").unwrap();
+        assert!(repo.resolve_commit("v1.0").is_err());
+        assert!(repo.tag_delete("v1.0").is_err());
+        assert_eq!(repo.list_tags().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,148 @@
+use super::Repository;
+use crate::object::{FileMode, ObjectType};
+use crate::{EncodedSha, Error};
+
+/// An entry of a tree met by [`Repository::walk_tree`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeWalkEntry {
+    /// The path from the tree walked, with `/` between directories
+    pub path: String,
+    pub mode: FileMode,
+    /// A blob for a file, a tree for a directory, a commit for a submodule
+    pub object_type: ObjectType,
+    pub sha: EncodedSha,
+}
+
+/// How [`Repository::walk_tree`] goes on after an entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TreeWalkStep {
+    /// Go on, into the entry if it is a subtree
+    Continue,
+    /// Go on, but not into the entry if it is a subtree
+    Skip,
+    /// End the walk
+    Stop,
+}
+
+impl Repository {
+    /// Walks a tree depth-first, calling `visit` for each entry: a
+    /// directory before its contents, the entries of a directory by name.
+    /// What `visit` returns decides whether a subtree is entered and
+    /// whether the walk goes on at all.
+    pub fn walk_tree<F>(&self, tree_sha: &EncodedSha, mut visit: F) -> Result<(), Error>
+    where
+        F: FnMut(&TreeWalkEntry) -> TreeWalkStep,
+    {
+        self.walk_subtree(tree_sha, "", &mut visit)?;
+        Ok(())
+    }
+
+    /// Walks the tree at `prefix` for [`Self::walk_tree`]
+    ///
+    /// # Returns
+    /// Whether `visit` stopped the walk
+    fn walk_subtree(
+        &self,
+        tree_sha: &EncodedSha,
+        prefix: &str,
+        visit: &mut dyn FnMut(&TreeWalkEntry) -> TreeWalkStep,
+    ) -> Result<bool, Error> {
+        let tree = self.read_tree_object(tree_sha)?;
+        for (name, entry) in tree.get_entries() {
+            let walk_entry = TreeWalkEntry {
+                path: match prefix {
+                    "" => name.clone(),
+                    prefix => format!("{prefix}/{name}"),
+                },
+                mode: entry.mode,
+                object_type: entry.object_type,
+                sha: entry.sha1.clone(),
+            };
+            match visit(&walk_entry) {
+                TreeWalkStep::Stop => return Ok(true),
+                TreeWalkStep::Continue if entry.object_type == ObjectType::Tree => {
+                    if self.walk_subtree(&entry.sha1, &walk_entry.path, visit)? {
+                        return Ok(true);
+                    }
+                }
+                TreeWalkStep::Continue | TreeWalkStep::Skip => (),
+            }
+        }
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::init_test_repo;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn walk_visits_directories_before_their_contents() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        fs::create_dir_all(repo.dir.join("src/bin")).unwrap();
+        let files = ["a.txt", "src/bin/main.rs", "src/lib.rs", "z.txt"]
+            .map(|name| repo.dir.join(name).to_str().unwrap().to_string());
+        for file in &files {
+            fs::write(file, "content\n").unwrap();
+        }
+        repo.add(&files.to_vec()).unwrap();
+        let commit = repo.commit("first").unwrap();
+        let tree = repo.read_commit(&commit).unwrap().get_tree_sha();
+        let walk = |step: &dyn Fn(&TreeWalkEntry) -> TreeWalkStep| {
+            let mut paths = Vec::new();
+            repo.walk_tree(&tree, |entry| {
+                paths.push(entry.path.clone());
+                step(entry)
+            })
+            .unwrap();
+            paths
+        };
+
+        assert_eq!(
+            walk(&|_| TreeWalkStep::Continue),
+            [
+                "a.txt",
+                "src",
+                "src/bin",
+                "src/bin/main.rs",
+                "src/lib.rs",
+                "z.txt"
+            ]
+        );
+        let skip_bin = |entry: &TreeWalkEntry| match entry.path.as_str() {
+            "src/bin" => TreeWalkStep::Skip,
+            _ => TreeWalkStep::Continue,
+        };
+        assert_eq!(
+            walk(&skip_bin),
+            ["a.txt", "src", "src/bin", "src/lib.rs", "z.txt"]
+        );
+        let stop_at_lib = |entry: &TreeWalkEntry| match entry.path.as_str() {
+            "src/lib.rs" => TreeWalkStep::Stop,
+            _ => TreeWalkStep::Continue,
+        };
+        assert_eq!(
+            walk(&stop_at_lib),
+            ["a.txt", "src", "src/bin", "src/bin/main.rs", "src/lib.rs"]
+        );
+
+        let mut entries = Vec::new();
+        repo.walk_tree(&tree, |entry| {
+            entries.push((entry.mode, entry.object_type));
+            TreeWalkStep::Skip
+        })
+        .unwrap();
+        assert_eq!(
+            entries,
+            [
+                (FileMode::Regular, ObjectType::Blob),
+                (FileMode::Directory, ObjectType::Tree),
+                (FileMode::Regular, ObjectType::Blob)
+            ]
+        );
+    }
+}
@@ -0,0 +1,279 @@
+use walkdir::WalkDir;
+
+use super::diff::{Snapshot, diff_files};
+use super::reflog::{LOGS_DIR, ReflogEntry};
+use super::{Branch, HEAD_FILE, HEADS_DIR, Head, REFS_DIR, Repository};
+use crate::index::Index;
+use crate::{EncodedSha, Error};
+use std::path::Path;
+
+/// Prefix of the reflog reason recorded by `undo`
+pub(super) const UNDO_PREFIX: &str = "undo: ";
+
+/// The ref updates that take the repository back to before an operation
+struct UndoPlan {
+    /// Reflog reason of the operation being undone
+    reason: String,
+    /// `(branch, current commit, commit to restore)`
+    branches: Vec<(String, Option<EncodedSha>, Option<EncodedSha>)>,
+    /// Where HEAD goes back to, if the operation moved HEAD itself
+    head: Option<Head>,
+    /// Whether the index and working tree follow HEAD back. Plain commits
+    /// only move a ref: their changes stay staged, as before committing.
+    restore_worktree: bool,
+}
+
+impl Repository {
+    /// Reverts the most recent operation recorded in the reflogs, e.g. a
+    /// commit, merge, checkout or branch deletion, by moving every ref it
+    /// touched back to where it was. With `dry_run`, only prints which
+    /// refs would move.
+    ///
+    /// Running `undo` again reverts the undo.
+    pub fn undo(&self, dry_run: bool) -> Result<(), Error> {
+        let plan = self.plan_undo()?;
+        println!("Undoing '{}'", plan.reason);
+        self.print_undo_plan(&plan, dry_run);
+        if dry_run {
+            Ok(())
+        } else {
+            self.apply_undo(&plan)
+        }
+    }
+
+    /// Finds the newest reflog entry and every ref that operation moved
+    fn plan_undo(&self) -> Result<UndoPlan, Error> {
+        let newest_ref = self
+            .last_logged_ref()
+            .ok_or("nothing to undo: the reflog is empty")?;
+        let newest = self
+            .reflog(&newest_ref)?
+            .pop()
+            .ok_or("nothing to undo: the reflog is empty")?;
+        // Every ref moved by that operation got an entry just like it
+        let logs_dir = self.git_dir.join(LOGS_DIR);
+        let mut operation: Vec<(String, ReflogEntry)> = Vec::new();
+        for entry in WalkDir::new(&logs_dir).into_iter().filter_map(|e| e.ok()) {
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            let ref_name = entry
+                .path()
+                .strip_prefix(&logs_dir)
+                .unwrap()
+                .to_string_lossy()
+                .replace('\\', "/");
+            if let Some(last) = self.reflog(&ref_name)?.pop()
+                && last.reason == newest.reason
+                && last.timestamp == newest.timestamp
+            {
+                operation.push((ref_name, last));
+            }
+        }
+
+        let heads_prefix = format!("{REFS_DIR}/{HEADS_DIR}/");
+        let mut branches = Vec::new();
+        let mut head_entry = None;
+        for (name, entry) in &operation {
+            if name == HEAD_FILE {
+                head_entry = Some(entry);
+                continue;
+            }
+            let Some(branch) = name.strip_prefix(&heads_prefix) else {
+                continue;
+            };
+            let current = self.load_branch(branch).and_then(|b| b.commit_sha);
+            let exists = self.load_branch(branch).is_some();
+            if current != entry.new || (entry.new.is_none() && exists) {
+                return Err(
+                    format!("{name} has moved since '{}'; not undoing", entry.reason).into(),
+                );
+            }
+            branches.push((branch.to_string(), entry.new.clone(), entry.old.clone()));
+        }
+
+        // HEAD follows a branch that moved; it only has to be restored when
+        // the operation repointed HEAD itself
+        let head_branch = self.head_ref_name();
+        let moved_head_branch = operation
+            .iter()
+            .any(|(name, _)| Some(name) == head_branch.as_ref());
+        let head = match head_entry {
+            Some(entry) if !moved_head_branch => {
+                if self.get_current_commit() != entry.new {
+                    return Err(
+                        format!("HEAD has moved since '{}'; not undoing", entry.reason).into(),
+                    );
+                }
+                Some(previous_head(self, entry)?)
+            }
+            _ => None,
+        };
+
+        let base_reason = newest.reason.trim_start_matches(UNDO_PREFIX);
+        let restore_worktree = !["commit:", "commit (initial)", "commit (amend)"]
+            .iter()
+            .any(|prefix| base_reason.starts_with(prefix));
+        Ok(UndoPlan {
+            reason: newest.reason,
+            branches,
+            head,
+            restore_worktree,
+        })
+    }
+
+    fn print_undo_plan(&self, plan: &UndoPlan, dry_run: bool) {
+        let verb = if dry_run { "Would move" } else { "Moving" };
+        let short = |sha: &Option<EncodedSha>| match sha {
+            Some(sha) => sha.0[..7].to_string(),
+            None => "(none)".to_string(),
+        };
+        for (branch, from, to) in &plan.branches {
+            println!(
+                "{verb} {REFS_DIR}/{HEADS_DIR}/{branch} from {} to {}",
+                short(from),
+                short(to)
+            );
+        }
+        match &plan.head {
+            Some(Head::Symbolic(path)) => {
+                println!(
+                    "{verb} HEAD to {}",
+                    path.to_string_lossy().replace('\\', "/")
+                )
+            }
+            Some(Head::Detached(sha)) => println!("{verb} HEAD to {sha} (detached)"),
+            None => {}
+        }
+    }
+
+    fn apply_undo(&self, plan: &UndoPlan) -> Result<(), Error> {
+        let reason = format!("{UNDO_PREFIX}{}", plan.reason);
+        let old_commit = self.get_current_commit();
+        let old_index = self.commit_index(old_commit.as_ref())?;
+        if plan.restore_worktree && !self.worktree_is_clean(&old_index)? {
+            return Err(Error::UncommittedChanges);
+        }
+
+        for (branch, from, to) in &plan.branches {
+            let is_head_branch =
+                self.head_ref_name() == Some(format!("{REFS_DIR}/{HEADS_DIR}/{branch}"));
+            let written = match (from, to) {
+                // Undoing a branch creation deletes it again, unless HEAD
+                // is on it, which leaves it unborn
+                (Some(_), None) if !is_head_branch => self.remove_branch(branch, &reason),
+                _ => self.save_branch(
+                    &Branch {
+                        name: branch.clone(),
+                        commit_sha: to.clone(),
+                    },
+                    &reason,
+                ),
+            };
+            written?;
+        }
+        if let Some(head) = &plan.head {
+            self.save_head(head, &reason)?;
+        }
+
+        let new_commit = self.get_current_commit();
+        if plan.restore_worktree && new_commit != old_commit {
+            let new_index = self.commit_index(new_commit.as_ref())?;
+            for (path, _) in new_index.collect_entries() {
+                if old_index.get_sha1(&path).is_none() && self.dir.join(&path).exists() {
+                    return Err(Error::UntrackedInTheWay(path));
+                }
+            }
+            self.update_worktree(&old_index, &new_index)?;
+            new_index.save(&self.get_index_path())?;
+        }
+        Ok(())
+    }
+
+    /// Snapshot of a commit's tree; no commit is an empty snapshot
+    pub(super) fn commit_index(&self, commit: Option<&EncodedSha>) -> Result<Index, String> {
+        match commit {
+            Some(sha) => self.read_tree(&self.read_commit(sha)?.get_tree_sha()),
+            None => Ok(Index::new()),
+        }
+    }
+
+    /// Whether the index and the tracked files match `head_index`
+    pub(super) fn worktree_is_clean(&self, head_index: &Index) -> Result<bool, String> {
+        let staged = self.snapshot_files(Snapshot::Index)?;
+        let worktree = self.snapshot_files(Snapshot::Worktree)?;
+        let head_entries = head_index.collect_entries();
+        let staged_entries = &staged.entries;
+        Ok(head_entries.len() == staged_entries.len()
+            && head_entries
+                .iter()
+                .all(|(path, sha)| staged_entries.get(path) == Some(sha))
+            && diff_files(&staged, &worktree).is_empty())
+    }
+}
+
+/// Where HEAD pointed before the operation logged in `entry`
+fn previous_head(repo: &Repository, entry: &ReflogEntry) -> Result<Head, String> {
+    // `checkout: moving from <branch or commit> to <target>`
+    let from = entry
+        .reason
+        .trim_start_matches(UNDO_PREFIX)
+        .strip_prefix("checkout: moving from ")
+        .and_then(|rest| rest.rsplit_once(" to "))
+        .map(|(from, _)| from);
+    if let Some(from) = from
+        && repo.load_branch(from).is_some()
+    {
+        return Ok(Head::Symbolic(
+            Path::new(REFS_DIR).join(HEADS_DIR).join(from),
+        ));
+    }
+    entry
+        .old
+        .clone()
+        .map(Head::Detached)
+        .ok_or_else(|| format!("cannot tell where HEAD was before '{}'", entry.reason))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::{commit_file, init_test_repo};
+    use tempfile::TempDir;
+
+    #[test]
+    fn undo_commit_checkout_and_branch_deletion() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        commit_file(&repo, "a.txt", "a");
+        let first = repo.get_current_commit();
+        commit_file(&repo, "b.txt", "b");
+
+        // Undoing a commit keeps its changes staged
+        repo.apply_undo(&repo.plan_undo().unwrap()).unwrap();
+        assert_eq!(repo.get_current_commit(), first);
+        assert!(repo.dir.join("b.txt").exists());
+        let index = Index::load(&repo.get_index_path()).unwrap();
+        assert!(index.get_sha1("b.txt").is_some());
+        // ...and undoing the undo brings the commit back
+        repo.apply_undo(&repo.plan_undo().unwrap()).unwrap();
+        assert_ne!(repo.get_current_commit(), first);
+
+        repo.branch("topic").unwrap();
+        repo.checkout("topic", false).unwrap();
+        commit_file(&repo, "c.txt", "c");
+        repo.checkout("master", false).unwrap();
+        assert!(!repo.dir.join("c.txt").exists());
+        let plan = repo.plan_undo().unwrap();
+        assert!(matches!(&plan.head, Some(Head::Symbolic(p)) if p.ends_with("topic")));
+        repo.apply_undo(&plan).unwrap();
+        assert_eq!(repo.head_ref_name().unwrap(), "refs/heads/topic");
+        assert!(repo.dir.join("c.txt").exists());
+
+        repo.checkout("master", false).unwrap();
+        let topic = repo.load_branch("topic").unwrap().commit_sha;
+        repo.rm_branch("topic").unwrap();
+        repo.apply_undo(&repo.plan_undo().unwrap()).unwrap();
+        assert_eq!(repo.load_branch("topic").unwrap().commit_sha, topic);
+    }
+}
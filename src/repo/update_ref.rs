@@ -0,0 +1,148 @@
+use super::{HEAD_FILE, Head, REFS_DIR, Repository, check_branch_name};
+use crate::{EncodedSha, Error};
+use std::str::FromStr;
+
+/// The value `update-ref` takes as the old value of a ref that must not
+/// exist yet
+const NULL_SHA: &str = "0000000000000000000000000000000000000000";
+
+impl Repository {
+    /// Points the ref `name` at `new`, like `git update-ref`. `name` is
+    /// `HEAD`, which updates the branch it points to, or a full name below
+    /// refs/. With `old` the update is a compare-and-swap: it fails unless
+    /// the ref currently holds `old`, all zeros meaning that it must not
+    /// exist.
+    pub fn update_ref(&self, name: &str, new: &str, old: Option<&str>) -> Result<(), Error> {
+        let new = self.resolve_ref_value(new)?;
+        let expected = old.map(|old| self.resolve_expected(old)).transpose()?;
+        let reason = "update-ref";
+        match self.ref_to_update(name)? {
+            Some(name) => self.write_ref(
+                &name,
+                Some(&new),
+                expected.as_ref().map(Option::as_ref),
+                reason,
+            )?,
+            None => {
+                // HEAD is detached, so it is the ref to update
+                let current = self.get_current_commit();
+                if let Some(expected) = expected.filter(|expected| *expected != current) {
+                    return Err(Error::Other(format!(
+                        "cannot lock ref 'HEAD': is at {} but expected {}",
+                        current.map_or(NULL_SHA.to_string(), |sha| sha.0),
+                        expected.map_or(NULL_SHA.to_string(), |sha| sha.0),
+                    )));
+                }
+                self.save_head(&Head::Detached(new), reason)?
+            }
+        }
+        Ok(())
+    }
+
+    /// Deletes the ref `name`, like `git update-ref -d`, failing unless it
+    /// holds `old` if given
+    pub fn delete_ref(&self, name: &str, old: Option<&str>) -> Result<(), Error> {
+        let Some(name) = self.ref_to_update(name)? else {
+            return Err("cannot delete a detached HEAD".into());
+        };
+        let expected = old.map(|old| self.resolve_ref_value(old)).transpose()?;
+        if self.read_ref(&name).is_none() {
+            return Err(format!("ref '{name}' not found").into());
+        }
+        Ok(self.remove_ref(&name, expected.as_ref(), "update-ref: deleted")?)
+    }
+
+    /// The full name of the ref to update for `name`: the ref it points
+    /// to if symbolic, such as the branch HEAD is on, or `None` if `name`
+    /// is a detached HEAD
+    fn ref_to_update(&self, name: &str) -> Result<Option<String>, Error> {
+        check_ref_name(name)?;
+        let target = self
+            .follow_symbolic_ref(name)
+            .ok_or_else(|| format!("'{name}' is part of a loop of symbolic refs"))?;
+        Ok((target != HEAD_FILE).then_some(target))
+    }
+
+    /// Resolves a value for a ref: the SHA1 of a stored object, such as an
+    /// annotated tag, or any revision
+    fn resolve_ref_value(&self, value: &str) -> Result<EncodedSha, Error> {
+        match EncodedSha::from_str(value) {
+            Ok(sha) if self.obj_db.retrieve(&sha).is_ok() => Ok(sha),
+            _ => self.resolve(value),
+        }
+    }
+
+    /// Resolves the value a ref is expected to hold, `None` for all zeros
+    fn resolve_expected(&self, value: &str) -> Result<Option<EncodedSha>, Error> {
+        match value {
+            NULL_SHA => Ok(None),
+            value => self.resolve_ref_value(value).map(Some),
+        }
+    }
+}
+
+/// Fails unless `name` is `HEAD` or a valid full name below refs/
+pub(super) fn check_ref_name(name: &str) -> Result<(), Error> {
+    let valid = name == HEAD_FILE
+        || name
+            .strip_prefix(&format!("{REFS_DIR}/"))
+            .is_some_and(|rest| rest.contains('/') && check_branch_name(rest).is_ok());
+    match valid {
+        true => Ok(()),
+        false => Err(format!("'{name}' is not a valid ref name").into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::repo::init_test_repo;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn update_ref_compares_before_swapping() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_test_repo(temp_dir.path());
+        let file = repo.dir.join("a.txt");
+        let commit_file = |content: &str| {
+            fs::write(&file, content).unwrap();
+            repo.add(&vec![file.to_str().unwrap()]).unwrap();
+            repo.commit(content).unwrap()
+        };
+        let first = commit_file("one\n");
+        let second = commit_file("two\n");
+
+        // Creating a ref only works while it does not exist
+        let null = "0".repeat(40);
+        repo.update_ref("refs/heads/topic", &first.0, Some(&null))
+            .unwrap();
+        assert!(
+            repo.update_ref("refs/heads/topic", "HEAD", Some(&null))
+                .is_err()
+        );
+        assert_eq!(repo.resolve("topic").unwrap(), first);
+
+        // A stale old value leaves the ref alone
+        assert!(
+            repo.update_ref("refs/heads/topic", "HEAD", Some("HEAD"))
+                .is_err()
+        );
+        repo.update_ref("refs/heads/topic", "HEAD", Some(&first.0))
+            .unwrap();
+        assert_eq!(repo.resolve("topic").unwrap(), second);
+        let log = repo.reflog("refs/heads/topic").unwrap();
+        assert_eq!(log.last().unwrap().reason, "update-ref");
+
+        // HEAD updates the branch it points to
+        repo.update_ref("HEAD", "HEAD~1", None).unwrap();
+        assert_eq!(repo.resolve("master").unwrap(), first);
+        assert!(repo.update_ref("master", "HEAD", None).is_err());
+        assert!(repo.update_ref("refs/bad..name", "HEAD", None).is_err());
+
+        assert!(repo.delete_ref("refs/heads/topic", Some(&first.0)).is_err());
+        repo.delete_ref("refs/heads/topic", Some(&second.0))
+            .unwrap();
+        assert!(repo.resolve("topic").is_err());
+        assert!(repo.delete_ref("refs/heads/topic", None).is_err());
+    }
+}
@@ -0,0 +1,265 @@
+use std::io::{Read, Write};
+use std::net::TcpStream;
+
+/// The service that takes pushes
+const RECEIVE_PACK: &str = "git-receive-pack";
+
+/// Old or new value of a ref that does not exist
+const ZERO_SHA: &str = "0000000000000000000000000000000000000000";
+
+/// One ref update a push asks `git-receive-pack` for; `None` stands for a
+/// ref the remote does not have
+pub struct RefUpdate {
+    pub name: String,
+    pub old: Option<String>,
+    pub new: String,
+}
+
+/// Lists the refs of the repository at `url` as `git-receive-pack`
+/// advertises them
+///
+/// # Returns
+/// The full name and hex id of every ref
+pub fn receive_pack_refs(url: &str) -> Result<Vec<(String, String)>, String> {
+    let (content_type, body) = request(url, &format!("info/refs?service={RECEIVE_PACK}"), None)?;
+    if content_type != format!("application/x-{RECEIVE_PACK}-advertisement") {
+        return Err(format!("{url} is not a smart HTTP Git server"));
+    }
+    let lines = parse_pkt_lines(&body)?;
+    // The service announcement comes first, in a section of its own
+    let announcement = format!("# service={RECEIVE_PACK}\n");
+    if lines.first() != Some(&Some(announcement.into_bytes())) {
+        return Err(format!("{url} did not announce {RECEIVE_PACK}"));
+    }
+    let mut refs = Vec::new();
+    let mut capabilities = None;
+    for line in lines.into_iter().skip(2).map_while(|line| line) {
+        let line = String::from_utf8_lossy(&line);
+        let line = line.trim_end_matches('\n');
+        // The first line carries the capabilities after a NUL
+        let line = match line.split_once('\0') {
+            Some((line, caps)) => {
+                capabilities = Some(caps.split(' ').map(str::to_string).collect::<Vec<_>>());
+                line
+            }
+            None => line,
+        };
+        let (sha, name) = line
+            .split_once(' ')
+            .ok_or_else(|| format!("bad ref advertisement '{line}'"))?;
+        // An empty repository advertises only its capabilities
+        if name != "capabilities^{}" {
+            refs.push((name.to_string(), sha.to_string()));
+        }
+    }
+    if !capabilities.is_some_and(|caps| caps.iter().any(|cap| cap == "report-status")) {
+        return Err(format!("{url} does not report the status of pushes"));
+    }
+    Ok(refs)
+}
+
+/// Sends `updates` and `pack` to `git-receive-pack` at `url`
+///
+/// # Returns
+/// - `Ok(Vec)`: The name of every ref, with the reason if the remote
+///   refused to update it
+/// - `Err(String)`: The request failed or the remote could not unpack
+pub fn receive_pack(
+    url: &str,
+    updates: &[RefUpdate],
+    pack: &[u8],
+) -> Result<Vec<(String, Option<String>)>, String> {
+    let mut body = Vec::new();
+    for (i, update) in updates.iter().enumerate() {
+        let old = update.old.as_deref().unwrap_or(ZERO_SHA);
+        let mut command = format!("{old} {} {}", update.new, update.name);
+        if i == 0 {
+            command.push_str("\0report-status agent=rust-git");
+        }
+        command.push('\n');
+        body.extend(pkt_line(command.as_bytes()));
+    }
+    body.extend(FLUSH_PKT);
+    body.extend(pack);
+
+    let (_, response) = request(url, RECEIVE_PACK, Some(&body))?;
+    let lines = parse_pkt_lines(&response)?;
+    let mut lines = lines
+        .into_iter()
+        .map_while(|line| line)
+        .map(|line| String::from_utf8_lossy(&line).trim_end().to_string());
+    match lines.next() {
+        Some(line) if line == "unpack ok" => {}
+        Some(line) => {
+            let why = line.strip_prefix("unpack ").unwrap_or(&line);
+            return Err(format!("the remote failed to unpack: {why}"));
+        }
+        None => return Err(format!("{url} sent no push status")),
+    }
+    lines
+        .map(|line| match line.split_once(' ') {
+            Some(("ok", name)) => Ok((name.to_string(), None)),
+            Some(("ng", rest)) => {
+                let (name, why) = rest.split_once(' ').unwrap_or((rest, "rejected"));
+                Ok((name.to_string(), Some(why.to_string())))
+            }
+            _ => Err(format!("bad push status '{line}'")),
+        })
+        .collect()
+}
+
+/// Marks the end of a section of pkt-lines
+pub(crate) const FLUSH_PKT: &[u8] = b"0000";
+
+/// Frames `data` as a pkt-line: four hex digits of length, which counts
+/// themselves too, then the data
+pub(crate) fn pkt_line(data: &[u8]) -> Vec<u8> {
+    let mut line = format!("{:04x}", data.len() + 4).into_bytes();
+    line.extend_from_slice(data);
+    line
+}
+
+/// Splits `data` into pkt-lines, with `None` for every flush
+pub(crate) fn parse_pkt_lines(mut data: &[u8]) -> Result<Vec<Option<Vec<u8>>>, String> {
+    let mut lines = Vec::new();
+    while data.len() >= 4 {
+        let len = std::str::from_utf8(&data[..4])
+            .ok()
+            .and_then(|len| usize::from_str_radix(len, 16).ok())
+            .ok_or("bad pkt-line length")?;
+        match len {
+            0 => lines.push(None),
+            4.. if len <= data.len() => lines.push(Some(data[4..len].to_vec())),
+            _ => return Err("truncated pkt-line".to_string()),
+        }
+        data = &data[len.max(4)..];
+    }
+    Ok(lines)
+}
+
+/// Sends a GET, or a POST of `body`, for `service` below the repository
+/// at `url`
+///
+/// # Returns
+/// The content type and body of the response
+fn request(url: &str, service: &str, body: Option<&[u8]>) -> Result<(String, Vec<u8>), String> {
+    let rest = match url.split_once("://") {
+        Some(("http", rest)) => rest,
+        Some((scheme, _)) => {
+            return Err(format!(
+                "cannot reach {url}: {scheme} is not supported, use http"
+            ));
+        }
+        None => return Err(format!("'{url}' is not a URL")),
+    };
+    let (authority, path) = rest.split_once('/').unwrap_or((rest, ""));
+    let (credentials, host) = match authority.rsplit_once('@') {
+        Some((credentials, host)) => (Some(credentials), host),
+        None => (None, authority),
+    };
+    let address = match host.contains(':') {
+        true => host.to_string(),
+        false => format!("{host}:80"),
+    };
+    let path = match path.trim_end_matches('/') {
+        "" => format!("/{service}"),
+        path => format!("/{path}/{service}"),
+    };
+
+    let mut head = match body {
+        Some(body) => format!(
+            "POST {path} HTTP/1.1\r\n\
+             Content-Type: application/x-{service}-request\r\n\
+             Accept: application/x-{service}-result\r\n\
+             Content-Length: {}\r\n",
+            body.len()
+        ),
+        None => format!("GET {path} HTTP/1.1\r\n"),
+    };
+    head.push_str(&format!(
+        "Host: {host}\r\nUser-Agent: git/rust-git\r\nConnection: close\r\n"
+    ));
+    if let Some(credentials) = credentials {
+        head.push_str(&format!(
+            "Authorization: Basic {}\r\n",
+            base64(credentials.as_bytes())
+        ));
+    }
+    head.push_str("\r\n");
+
+    let failed = |why: std::io::Error| format!("cannot reach {url}: {why}");
+    let mut stream = TcpStream::connect(&address).map_err(failed)?;
+    stream.write_all(head.as_bytes()).map_err(failed)?;
+    stream.write_all(body.unwrap_or_default()).map_err(failed)?;
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).map_err(failed)?;
+
+    let end = memchr::memmem::find(&response, b"\r\n\r\n")
+        .ok_or_else(|| format!("{url} sent a malformed response"))?;
+    let head = String::from_utf8_lossy(&response[..end]);
+    let mut head = head.split("\r\n");
+    let status = head.next().unwrap_or_default();
+    match status.split(' ').nth(1) {
+        Some("200") => {}
+        Some("401") => return Err(format!("authentication failed for {url}")),
+        _ => return Err(format!("{url} answered '{status}'")),
+    }
+    let mut content_type = String::new();
+    let mut chunked = false;
+    for header in head {
+        let Some((name, value)) = header.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        match name.to_ascii_lowercase().as_str() {
+            "content-type" => content_type = value.to_string(),
+            "transfer-encoding" => chunked = value.eq_ignore_ascii_case("chunked"),
+            _ => {}
+        }
+    }
+    let body = &response[end + 4..];
+    let body = match chunked {
+        true => dechunk(body).ok_or_else(|| format!("{url} sent a malformed response"))?,
+        false => body.to_vec(),
+    };
+    Ok((content_type, body))
+}
+
+/// Joins the chunks of a body sent with `Transfer-Encoding: chunked`
+fn dechunk(mut data: &[u8]) -> Option<Vec<u8>> {
+    let mut body = Vec::new();
+    loop {
+        let line_end = memchr::memmem::find(data, b"\r\n")?;
+        let size = std::str::from_utf8(&data[..line_end]).ok()?;
+        // Chunk extensions follow a semicolon
+        let size = size.split(';').next()?.trim();
+        let size = usize::from_str_radix(size, 16).ok()?;
+        data = &data[line_end + 2..];
+        if size == 0 {
+            return Some(body);
+        }
+        body.extend_from_slice(data.get(..size)?);
+        data = data.get(size + 2..)?;
+    }
+}
+
+/// Encodes `data` as standard Base64, for Basic authentication
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::new();
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for i in 0..4 {
+            match i <= chunk.len() {
+                true => encoded.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char),
+                false => encoded.push('='),
+            }
+        }
+    }
+    encoded
+}